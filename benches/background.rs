@@ -0,0 +1,99 @@
+//! The background's per-frame work: converting a decoded frame to cells,
+//! with each ASCII mode and dither, and compositing the cells under the
+//! chat
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::Color;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use mega_cli::background::{
+    self, AsciiMode, BackgroundOptions, ColorMode, Conversion, Dither, LoopRange, Palette, VideoBackground,
+};
+
+/// A full-screen background on a large terminal
+const WIDTH: usize = 200;
+const HEIGHT: usize = 60;
+
+fn options(ascii_mode: AsciiMode, dither: Dither) -> BackgroundOptions {
+    BackgroundOptions {
+        opacity: 0.3,
+        panel_opacity: 0.1,
+        dither,
+        palette: Palette::default(),
+        ascii_mode,
+        color_mode: ColorMode::Full,
+        loop_range: LoopRange::default(),
+        adaptive: false,
+        crossfade: Duration::ZERO,
+        cache: false,
+    }
+}
+
+/// An RGB24 frame with gradients and hard edges, rows padded as ffmpeg
+/// pads them
+fn frame(stride: usize) -> Vec<u8> {
+    let mut data = vec![0; stride * HEIGHT];
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let at = y * stride + x * 3;
+            let stripe = if (x / 16 + y / 8) % 2 == 0 { 255 } else { 0 };
+            data[at] = (x * 255 / WIDTH) as u8;
+            data[at + 1] = (y * 255 / HEIGHT) as u8;
+            data[at + 2] = stripe;
+        }
+    }
+    data
+}
+
+fn to_ascii_frame(c: &mut Criterion) {
+    let stride = (WIDTH * 3).next_multiple_of(64);
+    let data = frame(stride);
+    let mut group = c.benchmark_group("to_ascii_frame");
+    for (name, mode, dither) in [
+        ("density", AsciiMode::Density, Dither::None),
+        ("ordered", AsciiMode::Density, Dither::Ordered),
+        ("floyd", AsciiMode::Density, Dither::FloydSteinberg),
+        ("edges", AsciiMode::Edges, Dither::None),
+        ("solid", AsciiMode::Solid, Dither::None),
+    ] {
+        let conv = Conversion::new(&options(mode, dither), false);
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| background::to_ascii_frame(black_box(&data), stride, WIDTH, HEIGHT, &conv))
+        });
+    }
+    group.finish();
+}
+
+/// The built-in animation, once its first frame is in
+fn playing() -> VideoBackground {
+    let options = options(AsciiMode::Density, Dither::None);
+    let mut video = VideoBackground::new(background::BUILTIN_SOURCE, WIDTH as u16, HEIGHT as u16, options).unwrap();
+    let started = Instant::now();
+    while !video.update() {
+        assert!(started.elapsed() < Duration::from_secs(5), "no frame from the built-in animation");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    video
+}
+
+fn compositing(c: &mut Criterion) {
+    let video = playing();
+    let area = Rect::new(0, 0, WIDTH as u16, HEIGHT as u16);
+    // The messages and input boxes, as the chat lays them out
+    let panels = [Rect::new(1, 4, WIDTH as u16 - 2, HEIGHT as u16 - 9), Rect::new(1, HEIGHT as u16 - 4, WIDTH as u16 - 2, 1)];
+    let mut buffer = Buffer::empty(area);
+    let mut group = c.benchmark_group("compositing");
+    group.bench_function("full screen", |b| {
+        b.iter(|| video.render_background(&mut buffer, area, Color::Cyan, &[]))
+    });
+    group.bench_function("under panels", |b| {
+        b.iter(|| video.render_background(&mut buffer, area, Color::Cyan, black_box(&panels)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, to_ascii_frame, compositing);
+criterion_main!(benches);
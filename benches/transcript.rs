@@ -0,0 +1,60 @@
+//! Laying out the transcript: a frame of the chat screen over a long
+//! conversation, scrolled to its end as it is while chatting, and to its
+//! start
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{backend::TestBackend, Terminal};
+
+use mega_cli::chat::ChatInterface;
+use mega_cli::config::Config;
+use mega_cli::providers::AIProvider;
+use mega_cli::store::Database;
+
+/// A chat with the mock provider over a conversation of `messages`
+/// messages, replies several paragraphs long
+fn chat(messages: usize) -> ChatInterface {
+    let db = Database::open_in_memory().unwrap();
+    let id = db.create_conversation(AIProvider::Mock.db_name(), "Benchmark").unwrap();
+    let reply = "Here's how that works, one step at a time. ".repeat(12);
+    for n in 0..messages {
+        let (role, content) = if n % 2 == 0 {
+            ("user", format!("Question {} about the same topic as before?", n / 2))
+        } else {
+            ("assistant", format!("{}\n\n{}\n\n- one\n- two\n- three", reply, reply))
+        };
+        db.save_message(id, role, &content, None).unwrap();
+    }
+    let conversation = db.get_conversation(id).unwrap().unwrap();
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(db), &Config::default());
+    chat.resume(&conversation);
+    chat
+}
+
+fn transcript(c: &mut Criterion) {
+    let mut terminal = Terminal::new(TestBackend::new(160, 50)).unwrap();
+    let mut group = c.benchmark_group("transcript");
+    for messages in [100, 1_000, 10_000] {
+        let mut at_end = chat(messages);
+        group.bench_function(BenchmarkId::new("at end", messages), |b| {
+            b.iter(|| {
+                terminal.draw(|f| at_end.render(f, f.area()).unwrap()).unwrap();
+            })
+        });
+
+        let mut at_start = chat(messages);
+        // Ten messages a page
+        for _ in 0..=messages / 10 {
+            at_start.handle_key(KeyEvent::new(KeyCode::PageUp, KeyModifiers::NONE)).unwrap();
+        }
+        group.bench_function(BenchmarkId::new("at start", messages), |b| {
+            b.iter(|| {
+                terminal.draw(|f| at_start.render(f, f.area()).unwrap()).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, transcript);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Accessible mode (`display.accessible` or `--accessible`): no background
+//! or screensaver, high-contrast colors, replays shown a message at a time
+//! rather than typed out, and what happens said in words in the footer,
+//! where it stays until the next thing happens, for screen readers and
+//! low-vision setups
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier};
+
+/// Recolor `area` for contrast: text bright on black, and anything drawn
+/// on a color of its own (badges, selections) black on white
+pub fn high_contrast(buf: &mut Buffer, area: Rect) {
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            if let Some(cell) = buf.cell_mut((x, y)) {
+                if cell.bg == Color::Reset || cell.bg == Color::Black {
+                    cell.fg = bright(cell.fg);
+                    cell.bg = Color::Black;
+                } else {
+                    cell.fg = Color::Black;
+                    cell.bg = Color::White;
+                }
+                cell.modifier.remove(Modifier::DIM | Modifier::SLOW_BLINK | Modifier::RAPID_BLINK);
+            }
+        }
+    }
+}
+
+/// The color as it reads best on black: dark and muted ones brightened,
+/// RGB and palette colors white
+fn bright(color: Color) -> Color {
+    match color {
+        Color::Red | Color::LightRed => Color::LightRed,
+        Color::Green | Color::LightGreen => Color::LightGreen,
+        Color::Yellow | Color::LightYellow => Color::LightYellow,
+        // Blue is hard to read on black at any brightness
+        Color::Blue | Color::LightBlue | Color::Cyan | Color::LightCyan => Color::LightCyan,
+        Color::Magenta | Color::LightMagenta => Color::LightMagenta,
+        _ => Color::White,
+    }
+}
+
+/// "Response received from Claude, 14 lines"
+pub fn reply_received(from: &str, content: &str) -> String {
+    let lines = content.lines().count().max(1);
+    format!("Response received from {}, {} line{}", from, lines, if lines == 1 { "" } else { "s" })
+}
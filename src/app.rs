@@ -0,0 +1,622 @@
+//! The chat screen: the chat interface over the animated background, with
+//! the keys, audio, screensaver and config reloading around it
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::prelude::*;
+use serde::Serialize;
+#[cfg(feature = "video")]
+use std::time::Duration;
+
+#[cfg(feature = "audio")]
+use crate::audio;
+#[cfg(feature = "video")]
+use crate::background::{self, setup::BackgroundSetup, BackgroundRegion, VideoBackground};
+use crate::chat::ChatInterface;
+use crate::config::Config;
+#[cfg(feature = "video")]
+use crate::overlay::DebugOverlay;
+use crate::pacing::Pacer;
+use crate::problems::{Problem, Source};
+use crate::providers::AIProvider;
+use crate::reload::{self, ConfigWatcher};
+#[cfg(feature = "video")]
+use crate::screensaver::{self, Phase, Screensaver};
+use crate::store::Database;
+
+/// Step used by the runtime opacity keys
+#[cfg(feature = "video")]
+const OPACITY_STEP: f32 = 0.05;
+
+/// Seconds skipped per Shift+←/→ press
+#[cfg(feature = "video")]
+const SEEK_STEP: f64 = 5.0;
+
+pub struct App {
+    /// None when the video couldn't be opened; the chat runs on a plain background
+    #[cfg(feature = "video")]
+    video_bg: Option<VideoBackground>,
+    #[cfg(feature = "video")]
+    region: BackgroundRegion,
+    #[cfg(feature = "audio")]
+    audio: Option<audio::BackgroundAudio>,
+    chat: ChatInterface,
+    #[cfg(feature = "video")]
+    debug: DebugOverlay,
+    #[cfg(feature = "video")]
+    screensaver: Screensaver,
+    /// The screensaver's phase when last checked, to redraw when it moves on
+    #[cfg(feature = "video")]
+    screensaver_phase: Phase,
+    pacer: Pacer,
+    /// None if the config directory couldn't be watched
+    watcher: Option<ConfigWatcher>,
+    config: Config,
+    /// `display.accessible` or `--accessible`, for the whole run. Kept out
+    /// of `config` so the flag isn't saved with it. Only the screensaver
+    /// asks after it.
+    #[cfg(feature = "video")]
+    accessible: bool,
+    /// Drawing to a console with only the 16 named colors
+    sixteen_colors: bool,
+    /// Ctrl+Z was pressed; the loop stops the chat once it's handled
+    #[cfg(unix)]
+    suspend_requested: bool,
+    should_quit: bool,
+}
+
+impl App {
+    pub fn new(
+        provider: AIProvider,
+        db: Option<Database>,
+        #[cfg(feature = "video")] background: BackgroundSetup,
+        pacer: Pacer,
+        config: Config,
+        accessible: bool,
+    ) -> Result<Self> {
+        let accessible = accessible || config.display.accessible;
+        let mut chat = ChatInterface::new(provider, db, &config);
+        chat.set_accessible(accessible);
+
+        // The soundtrack loops over the same part as the picture
+        #[cfg(feature = "audio")]
+        let loop_range = background.options.loop_range;
+
+        // Accessible mode has a plain background and no screensaver
+        #[cfg(feature = "video")]
+        let video_bg = if accessible {
+            None
+        } else {
+            // Get terminal size for video scaling; without a terminal (the
+            // tests) any size will do
+            let size = background.size.unwrap_or_else(|| crossterm::terminal::size().unwrap_or((80, 24)));
+            let (video_area, _) = Self::split(background.region, Rect::new(0, 0, size.0, size.1));
+            match VideoBackground::new(&background.video, video_area.width, video_area.height, background.options) {
+                Ok(bg) => Some(bg),
+                Err(e) => {
+                    chat.show_problem(Problem::new(Source::Background, "Background video unavailable", e));
+                    None
+                }
+            }
+        };
+
+        // Only real media can carry a soundtrack
+        #[cfg(feature = "audio")]
+        let audio = {
+            let video = background.video.as_str();
+            (config.audio.enabled
+                && video_bg.is_some()
+                && video != background::BUILTIN_SOURCE
+                && background::webcam_device(video).is_none())
+            .then(|| audio::BackgroundAudio::new(video, config.audio.volume, config.audio.muted, loop_range))
+        };
+
+        Ok(Self {
+            #[cfg(feature = "video")]
+            video_bg,
+            #[cfg(feature = "video")]
+            region: background.region,
+            #[cfg(feature = "audio")]
+            audio,
+            chat,
+            #[cfg(feature = "video")]
+            debug: DebugOverlay::new(),
+            #[cfg(feature = "video")]
+            screensaver: if accessible { Screensaver::new(None) } else { background.screensaver },
+            #[cfg(feature = "video")]
+            screensaver_phase: Phase::Off,
+            pacer,
+            watcher: Self::watch(&config),
+            config,
+            #[cfg(feature = "video")]
+            accessible,
+            sixteen_colors: false,
+            #[cfg(unix)]
+            suspend_requested: false,
+            should_quit: false,
+        })
+    }
+
+    /// The chat, for setting it up before `run`
+    pub fn chat_mut(&mut self) -> &mut ChatInterface {
+        &mut self.chat
+    }
+
+    /// Draw whatever changed, wait for input and update until quit
+    pub fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        self.sixteen_colors = crate::terminal::sixteen_colors();
+        #[cfg(unix)]
+        let signals = crate::terminal::StopSignals::watch()
+            .map_err(|e| tracing::warn!("Stop signals won't restore the terminal: {:#}", e))
+            .ok();
+        // Logged against "starting", to see how long the chat takes to show
+        let mut first_frame = true;
+        loop {
+            if self.pacer.take_redraw() {
+                self.draw(terminal)?;
+                if std::mem::take(&mut first_frame) {
+                    tracing::info!("first frame drawn");
+                }
+            }
+            self.pacer.tick();
+
+            if event::poll(self.pacer.timeout())? {
+                self.handle_event(event::read()?)?;
+            }
+
+            if self.should_quit() {
+                return Ok(());
+            }
+
+            #[cfg(unix)]
+            if let Some(ref signals) = signals {
+                if std::mem::take(&mut self.suspend_requested) || signals.stop_requested() {
+                    self.suspend(terminal)?;
+                }
+                // Also after a SIGSTOP, which nothing can catch beforehand
+                if signals.continued() {
+                    crate::terminal::take_over()?;
+                    self.redraw_all(terminal)?;
+                }
+            }
+
+            self.update()?;
+        }
+    }
+
+    /// Ctrl+Z: give the shell its terminal back and stop, with the video
+    /// held where it is, then carry on as if no time had passed
+    #[cfg(unix)]
+    fn suspend<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        #[cfg(feature = "video")]
+        if let Some(ref video_bg) = self.video_bg {
+            video_bg.pause();
+        }
+        let suspended = crate::terminal::suspend();
+        #[cfg(feature = "video")]
+        {
+            if let Some(ref video_bg) = self.video_bg {
+                video_bg.resume();
+            }
+            // Time away isn't idle time at the chat
+            self.screensaver.wake();
+        }
+        suspended?;
+        self.redraw_all(terminal)
+    }
+
+    /// Draw the whole screen on the next pass, not only what changed:
+    /// whatever ran in the meantime has drawn over it
+    #[cfg(unix)]
+    fn redraw_all<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        terminal.clear()?;
+        self.pacer.changed();
+        Ok(())
+    }
+
+    /// Screen areas for the video and the chat
+    #[cfg(feature = "video")]
+    fn split(region: BackgroundRegion, area: Rect) -> (Rect, Rect) {
+        region.split(area, ChatInterface::header_area(area))
+    }
+
+    #[cfg(feature = "audio")]
+    fn toggle_mute(&mut self) {
+        let Some(ref audio) = self.audio else {
+            self.chat.flash("No background audio playing");
+            return;
+        };
+        let muted = audio.toggle_mute();
+        self.chat.flash(if muted { "🔇 Audio muted" } else { "🔊 Audio unmuted" });
+
+        self.config.audio.muted = muted;
+        self.save_setting("audio.muted", &muted);
+    }
+
+    #[cfg(not(feature = "audio"))]
+    fn toggle_mute(&mut self) {
+        self.chat.flash("Built without audio support");
+    }
+
+    /// Turn reading replies aloud on or off, and remember it
+    fn toggle_speech(&mut self) {
+        let enabled = self.chat.toggle_speech();
+        self.config.speech.enabled = enabled;
+        self.save_setting("speech.enabled", &enabled);
+    }
+
+    /// Turn sound cues on or off, and remember it
+    fn toggle_sounds(&mut self) {
+        let enabled = self.chat.toggle_sounds();
+        self.config.sounds.enabled = enabled;
+        self.save_setting("sounds.enabled", &enabled);
+    }
+
+    /// Write a setting changed here into config.toml, saying so if it
+    /// can't be
+    fn save_setting<T: Serialize>(&mut self, key: &str, value: &T) {
+        if let Err(e) = self.config.save_setting(key, value) {
+            self.chat.show_problem(Problem::new(Source::Config, format!("Couldn't save {} to config.toml", key), e));
+        }
+    }
+
+    fn watch(config: &Config) -> Option<ConfigWatcher> {
+        ConfigWatcher::new(config.profile.as_deref())
+            .map_err(|e| tracing::warn!("Config changes won't be picked up: {:#}", e))
+            .ok()
+    }
+
+    /// Apply an edited config file: what can change on the fly does, and
+    /// the rest is listed as needing a restart
+    fn reload_config(&mut self) {
+        let config = match Config::load(self.config.profile.as_deref()) {
+            Ok(config) => config,
+            Err(e) => {
+                self.chat.add_system_message(&format!("Config not reloaded: {:#}", e));
+                return;
+            }
+        };
+        let changed = reload::changed_keys(&self.config, &config);
+        // Includes the app's own saves (opacity, mute), which change nothing
+        if changed.is_empty() {
+            return;
+        }
+        let is_changed = |key: &str| changed.iter().any(|c| c == key || c.starts_with(&format!("{}.", key)));
+
+        #[cfg(feature = "video")]
+        if let Some(ref mut video_bg) = self.video_bg {
+            if is_changed("background.opacity") {
+                video_bg.set_opacity(config.background.opacity);
+            }
+            if is_changed("background.panel_opacity") {
+                video_bg.set_panel_opacity(config.background.panel_opacity);
+            }
+            if is_changed("background.color") {
+                match background::ColorMode::parse(&config.background.color) {
+                    Some(mode) => video_bg.set_color_mode(mode),
+                    None => self.chat.add_system_message(&format!(
+                        "Unknown background color mode: {}; keeping the current one",
+                        config.background.color
+                    )),
+                }
+            }
+        }
+        #[cfg(feature = "video")]
+        if is_changed("screensaver.idle_minutes") && !self.accessible {
+            let minutes = config.screensaver.idle_minutes;
+            self.screensaver.set_timeout((minutes > 0).then(|| Duration::from_secs(minutes * 60)));
+        }
+        #[cfg(feature = "audio")]
+        if let Some(audio) = self.audio.as_ref().filter(|_| is_changed("audio.volume")) {
+            audio.set_volume(config.audio.volume);
+        }
+        if is_changed("display.fps") || is_changed("display.idle_fps") {
+            self.pacer = Pacer::new(config.display.fps, config.display.idle_fps);
+        }
+        if is_changed("personas")
+            || is_changed("templates")
+            || is_changed("macros")
+            || is_changed("api_keys")
+            || is_changed("rate_limits")
+            || is_changed("paste")
+        {
+            self.chat.apply_config(&config);
+        }
+        if is_changed("speech") {
+            self.chat.apply_speech(&config.speech);
+        }
+        if is_changed("sounds") {
+            self.chat.apply_sounds(&config.sounds);
+        }
+        if is_changed("events") {
+            self.chat.apply_events(&config.events);
+        }
+        if is_changed("keys") {
+            self.chat.apply_keys(&config.keys);
+        }
+        if is_changed("redact") {
+            self.chat.apply_redact(&config.redact);
+        }
+        self.config = config;
+
+        let (live, restart): (Vec<String>, Vec<String>) = changed.into_iter().partition(|key| reload::is_live(key));
+        let mut message = String::from("Config reloaded");
+        if !live.is_empty() {
+            message.push_str(&format!(": {}", live.join(", ")));
+        }
+        if !restart.is_empty() {
+            message.push_str(&format!(". Takes a restart: {}", restart.join(", ")));
+        }
+        self.chat.add_system_message(&message);
+    }
+
+    /// Load another profile's config and database and hand them to the
+    /// chat. The background keeps playing as it is until the next launch.
+    fn switch_profile(&mut self, profile: Option<String>) {
+        let loaded = Config::load(profile.as_deref()).and_then(|config| {
+            let db_path = Database::resolve_path(None, &config)?;
+            let db = Database::open(&db_path)?;
+            let problems = db.integrity_problems(true)?;
+            if !problems.is_empty() {
+                anyhow::bail!("{} is damaged; run `repair` with this --profile to fix it", db_path.display());
+            }
+            Ok((config, db))
+        });
+        match loaded {
+            Ok((config, db)) => {
+                let provider = config
+                    .provider
+                    .as_deref()
+                    .and_then(AIProvider::parse)
+                    .unwrap_or_else(|| self.chat.provider().clone());
+                self.chat.change_profile(&config, Some(db), provider);
+                self.watcher = Self::watch(&config);
+                self.config = config;
+            }
+            Err(e) => self.chat.show_problem(Problem::new(Source::Profile, "Couldn't switch profile", e)),
+        }
+    }
+
+    #[cfg(feature = "video")]
+    fn adjust_opacity(&mut self, delta: f32) {
+        let Some(ref mut video_bg) = self.video_bg else {
+            return;
+        };
+        video_bg.set_opacity(video_bg.opacity() + delta);
+
+        // Persist so the next launch starts at the same level. Saved as an
+        // f64, as an f32 would widen to 0.30000001192092896.
+        let opacity = (f64::from(video_bg.opacity()) * 100.0).round() / 100.0;
+        self.config.background.opacity = opacity as f32;
+        self.save_setting("background.opacity", &opacity);
+        self.chat.flash(format!(
+            "Background opacity {:.0}%",
+            self.config.background.opacity * 100.0
+        ));
+    }
+
+    /// Draw a frame
+    pub fn draw<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        terminal.draw(|f| {
+            if let Err(e) = self.render(f) {
+                tracing::error!("Render error: {:#}", e);
+            }
+            if self.sixteen_colors {
+                let area = f.area();
+                crate::terminal::limit_to_sixteen_colors(f.buffer_mut(), area);
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Handle a key, resize or other terminal event
+    pub fn handle_event(&mut self, event: Event) -> Result<()> {
+        // Resizes and the like need a redraw too
+        self.pacer.changed();
+        let Event::Key(key) = event else {
+            return Ok(());
+        };
+        // Windows reports letting go of a key too, which would type
+        // everything twice
+        if key.kind == KeyEventKind::Release {
+            return Ok(());
+        }
+
+        // The key that ends the screensaver only wakes the chat
+        #[cfg(feature = "video")]
+        if self.screensaver.wake() {
+            return Ok(());
+        }
+
+        // Any key stops a macro that's waiting on a reply to play on
+        if self.chat.stop_macro() {
+            return Ok(());
+        }
+
+        // Macros, anywhere in the chat
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        if control && key.code == KeyCode::Char('r') {
+            self.chat.toggle_macro_recording();
+            return Ok(());
+        }
+        if control && key.code == KeyCode::Char('p') {
+            self.chat.play_last_macro();
+            return Ok(());
+        }
+        if self.chat.play_bound_macro(&key) {
+            return Ok(());
+        }
+
+        self.press(key, false)
+    }
+
+    /// Act on a key, typed or `played` by a macro. A macro's keys don't
+    /// quit or suspend the chat.
+    fn press(&mut self, key: KeyEvent, played: bool) -> Result<()> {
+        // Esc closes an open popup before it quits
+        if key.code == KeyCode::Esc && self.chat.close_popup() {
+            self.chat.record_macro_key(key);
+            return Ok(());
+        }
+
+        // Global quit handlers
+        if key.code == KeyCode::Esc
+            || (key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c'))
+        {
+            self.should_quit |= !played;
+            return Ok(());
+        }
+
+        // Raw mode turns Ctrl+Z into a key instead of a stop
+        #[cfg(unix)]
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('z') {
+            self.suspend_requested |= !played;
+            return Ok(());
+        }
+
+        self.chat.record_macro_key(key);
+
+        // The leader and what follows stand for another key, or a command
+        let Some(key) = self.chat.lead(key) else {
+            return Ok(());
+        };
+
+        // Background controls, otherwise pass to chat interface
+        match key.code {
+            #[cfg(feature = "video")]
+            KeyCode::F(3) => self.adjust_opacity(-OPACITY_STEP),
+            #[cfg(feature = "video")]
+            KeyCode::F(4) => self.adjust_opacity(OPACITY_STEP),
+            KeyCode::F(5) => self.toggle_mute(),
+            KeyCode::F(8) => self.toggle_speech(),
+            KeyCode::F(9) => self.toggle_sounds(),
+            #[cfg(feature = "video")]
+            KeyCode::F(12) => self.debug.toggle(),
+            #[cfg(feature = "video")]
+            KeyCode::Left | KeyCode::Right if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                let delta = if key.code == KeyCode::Left { -SEEK_STEP } else { SEEK_STEP };
+                if let Some(ref video_bg) = self.video_bg {
+                    video_bg.seek(delta);
+                }
+                #[cfg(feature = "audio")]
+                if let Some(ref audio) = self.audio {
+                    audio.seek(delta);
+                }
+            }
+            _ => self.chat.handle_key(key)?,
+        }
+        if let Some(profile) = self.chat.take_profile_request() {
+            self.switch_profile(profile);
+        }
+        if let Some(macros) = self.chat.take_changed_macros() {
+            self.save_setting("macros", &macros);
+            self.config.macros = macros;
+        }
+        Ok(())
+    }
+
+    /// Check everything that can change the screen without a key, and
+    /// have the next pass redraw if any of it did
+    pub fn update(&mut self) -> Result<()> {
+        let mut changed = false;
+        if self.chat.is_waiting() || self.chat.is_replaying() {
+            self.pacer.busy();
+        }
+        #[cfg(feature = "video")]
+        {
+            // Its numbers change every frame
+            changed |= self.debug.is_visible();
+            let phase = self.screensaver.phase();
+            changed |= phase != self.screensaver_phase;
+            self.screensaver_phase = phase;
+        }
+
+        // Update video background (gets next frame)
+        #[cfg(feature = "video")]
+        if let Some(ref mut video_bg) = self.video_bg {
+            changed |= video_bg.update();
+
+            // Decode thread died (missing/corrupt file, dropped stream):
+            // fall back to a plain background and say why
+            if let Some(e) = video_bg.take_error() {
+                self.chat.show_problem(Problem::new(Source::Background, "Background video stopped", e));
+                self.video_bg = None;
+                changed = true;
+            }
+        }
+
+        #[cfg(feature = "audio")]
+        if let Some(e) = self.audio.as_ref().and_then(|a| a.take_error()) {
+            self.chat.show_problem(Problem::new(Source::Audio, "Background audio stopped", e));
+            self.audio = None;
+            changed = true;
+        }
+
+        // Update chat (polls AI responses)
+        changed |= self.chat.update()?;
+
+        // A macro plays on, holding while a reply it asked for comes in
+        while let Some(key) = self.chat.next_macro_key() {
+            self.press(key, true)?;
+            changed = true;
+        }
+
+        if self.watcher.as_mut().is_some_and(ConfigWatcher::poll) {
+            self.reload_config();
+            changed = true;
+        }
+
+        if changed {
+            self.pacer.changed();
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "video"))]
+    fn render(&mut self, frame: &mut Frame) -> Result<()> {
+        let area = frame.area();
+        self.chat.render(frame, area)
+    }
+
+    #[cfg(feature = "video")]
+    fn render(&mut self, frame: &mut Frame) -> Result<()> {
+        let area = frame.area();
+
+        // Idle: the video takes over the whole screen, no text
+        if let Phase::Showing(brightness) = self.screensaver.phase() {
+            if let Some(ref video_bg) = self.video_bg {
+                let accent = self.chat.provider().color();
+                let full_color = self.config.screensaver.full_color;
+                video_bg.render_screensaver(frame.buffer_mut(), area, accent, brightness, full_color);
+            }
+            self.debug.record_render(self.video_bg.as_ref());
+            self.debug.render(frame);
+            return Ok(());
+        }
+
+        let (video_area, chat_area) = Self::split(self.region, area);
+
+        // First render video background with opacity
+        if let Some(ref video_bg) = self.video_bg {
+            let accent = self.chat.provider().color();
+            let panels = self.chat.text_panels(chat_area);
+            video_bg.render_background(frame.buffer_mut(), video_area, accent, &panels);
+        }
+
+        // Then render chat interface on top
+        self.chat.render(frame, chat_area)?;
+
+        if let Phase::FadingOut(brightness) = self.screensaver.phase() {
+            screensaver::dim(frame.buffer_mut(), area, brightness);
+        }
+
+        self.debug.record_render(self.video_bg.as_ref());
+        self.debug.render(frame);
+
+        Ok(())
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.should_quit
+    }
+}
@@ -0,0 +1,345 @@
+//! `animated-cli ask`: one question, one answer on stdout, no TUI. The
+//! exchange is saved as a conversation like any other.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use serde::Serialize;
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+
+use crate::providers::{AIClient, AIProvider, ChatSettings, Message};
+use crate::conversation::auto_title;
+use crate::config::Config;
+#[cfg(unix)]
+use crate::daemon::{self, SaveAs};
+use crate::paste;
+use crate::redact::{self, Redactor};
+use crate::store::Database;
+
+#[derive(Args, Debug)]
+pub struct AskArgs {
+    /// What to ask
+    question: String,
+
+    /// AI provider to ask (claude, grok, gpt, gemini) [config: provider, default: claude]
+    #[arg(long)]
+    provider: Option<String>,
+
+    /// Model to use instead of the provider's default
+    #[arg(long)]
+    model: Option<String>,
+
+    /// System prompt
+    #[arg(long)]
+    system: Option<String>,
+
+    /// Sampling temperature, 0 to 2
+    #[arg(long)]
+    temperature: Option<f64>,
+
+    /// Persona from `[personas]` in the config
+    #[arg(long)]
+    persona: Option<String>,
+
+    /// Output format: raw (the answer exactly as returned) or markdown
+    /// (question and answer under headings, for notes)
+    #[arg(long, default_value = "raw")]
+    format: String,
+
+    /// Print one JSON object with the answer, model, token usage, cost and
+    /// latency (or the error), for scripts
+    #[arg(long, conflicts_with = "format")]
+    json: bool,
+
+    /// Most of piped stdin to send, in KB; the middle of anything longer
+    /// is cut out
+    #[arg(long, default_value_t = 100)]
+    max_stdin_kb: usize,
+
+    /// Don't read stdin even when it isn't a terminal
+    #[arg(long)]
+    no_stdin: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AskFormat {
+    Raw,
+    Markdown,
+    Json,
+}
+
+/// What `--json` prints on success
+#[derive(Serialize)]
+struct JsonAnswer<'a> {
+    ok: bool,
+    provider: &'a str,
+    model: &'a str,
+    content: &'a str,
+    prompt_tokens: Option<i64>,
+    completion_tokens: Option<i64>,
+    /// US dollars, when the model's price is known
+    cost: Option<f64>,
+    latency_ms: i64,
+    /// Where the exchange was saved; None if it couldn't be
+    conversation_id: Option<i64>,
+}
+
+/// What `--json` prints on failure
+#[derive(Serialize)]
+struct JsonError {
+    ok: bool,
+    error: String,
+}
+
+/// Run `animated-cli ask`. Fails, and so exits non-zero, when the provider
+/// doesn't answer; not being able to save the answer is only a warning.
+/// With `--json` a failure is printed as JSON too.
+pub async fn run(args: AskArgs, db_path: &Path, config: &Config) -> Result<()> {
+    let json = args.json;
+    let result = ask(args, db_path, config).await;
+    if let (true, Err(e)) = (json, &result) {
+        let error = JsonError { ok: false, error: format!("{:#}", e) };
+        println!("{}", serde_json::to_string(&error)?);
+    }
+    result
+}
+
+async fn ask(args: AskArgs, db_path: &Path, config: &Config) -> Result<()> {
+    let format = match args.format.to_lowercase().as_str() {
+        _ if args.json => AskFormat::Json,
+        "raw" => AskFormat::Raw,
+        "markdown" | "md" => AskFormat::Markdown,
+        other => bail!("Unknown output format: {} (expected raw or markdown)", other),
+    };
+    let name = args.provider.as_deref().or(config.provider.as_deref()).unwrap_or("claude");
+    let provider = AIProvider::parse(name).ok_or_else(|| anyhow!("Unknown provider: {}", name))?;
+    if args.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+        bail!("Temperature must be a number from 0 to 2");
+    }
+    if let Some(ref persona) = args.persona {
+        if !config.personas.contains_key(persona) {
+            bail!("Unknown persona: {} (add it under [personas] in config.toml)", persona);
+        }
+    }
+
+    let question = match read_stdin(args.no_stdin, args.max_stdin_kb * 1024)? {
+        Some(context) => format!("{}\n\n{}", args.question, fenced(&context)),
+        None => args.question.clone(),
+    };
+    let question = screen_secrets(question, config)?;
+
+    let settings = ChatSettings {
+        model: args.model,
+        system_prompt: args.system,
+        temperature: args.temperature,
+        persona: args.persona,
+    };
+    let client = AIClient::new(provider.clone(), config.api_keys.get(provider.api_key_env()).cloned());
+    let messages = vec![Message {
+        role: "user".to_string(),
+        content: question.clone(),
+    }];
+    let sent = settings.clone().resolve_persona(&config.personas);
+    let title = auto_title(&args.question);
+
+    // A running daemon answers and saves; otherwise do both here
+    #[cfg(unix)]
+    let forwarded = match daemon::socket_path(config.profile.as_deref()).ok().as_deref().and_then(daemon::connect) {
+        Some(connection) => {
+            let save = SaveAs {
+                db: db_path.to_path_buf(),
+                title: title.clone(),
+                settings: settings.clone(),
+            };
+            let forwarded = daemon::forward(connection, &provider, client.api_key().ok(), messages.clone(), &sent, Some(save))
+                .await
+                .with_context(|| format!("{} didn't answer", provider.name()))?;
+            let saved = match (forwarded.conversation_id, forwarded.save_error) {
+                (Some(id), _) => Some(Ok(id)),
+                (None, Some(e)) => Some(Err(anyhow!(e))),
+                (None, None) => None,
+            };
+            Some((forwarded.reply, saved))
+        }
+        None => None,
+    };
+    #[cfg(not(unix))]
+    let forwarded = None;
+
+    let (reply, saved) = match forwarded {
+        Some(forwarded) => forwarded,
+        None => {
+            let reply = client
+                .send_message(messages, &sent)
+                .await
+                .with_context(|| format!("{} didn't answer", provider.name()))?;
+            (reply, None)
+        }
+    };
+    let saved = saved.unwrap_or_else(|| {
+        Database::open(db_path).and_then(|db| {
+            let id = db.create_conversation(provider.db_name(), &title)?;
+            if settings != ChatSettings::default() {
+                db.set_conversation_settings(id, &settings)?;
+            }
+            db.save_message(id, "user", &question, None)?;
+            db.save_message(id, "assistant", &reply.content, Some(&reply.usage))?;
+            Ok(id)
+        })
+    });
+    let conversation_id = match saved {
+        Ok(id) => Some(id),
+        Err(e) => {
+            eprintln!("Couldn't save the answer to history: {:#}", e);
+            None
+        }
+    };
+
+    let mut out = std::io::stdout().lock();
+    match format {
+        AskFormat::Raw => write!(out, "{}", reply.content)?,
+        AskFormat::Markdown => write!(
+            out,
+            "## Question\n\n{}\n\n## {}\n\n{}",
+            args.question,
+            provider.name(),
+            reply.content
+        )?,
+        AskFormat::Json => {
+            let answer = JsonAnswer {
+                ok: true,
+                provider: provider.db_name(),
+                model: &reply.usage.model,
+                content: &reply.content,
+                prompt_tokens: reply.usage.prompt_tokens,
+                completion_tokens: reply.usage.completion_tokens,
+                cost: reply.usage.cost(),
+                latency_ms: reply.usage.latency_ms,
+                conversation_id,
+            };
+            serde_json::to_writer(&mut out, &answer)?;
+        }
+    }
+    if format == AskFormat::Json || !reply.content.ends_with('\n') {
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The chat's check for secrets, with no one to ask: what `[redact]`
+/// would mask is masked, and what it would warn about stops the question
+/// from being sent at all
+fn screen_secrets(question: String, config: &Config) -> Result<String> {
+    let redactor = Redactor::new(&config.redact)?;
+    let findings = redactor.find(&question, &paste::secrets(&config.api_keys, &config.paste));
+    if findings.is_empty() {
+        return Ok(question);
+    }
+    match redactor.action() {
+        redact::Action::Off => Ok(question),
+        redact::Action::Mask => {
+            let count = findings.len();
+            eprintln!("Masked {} secret{} before sending", count, if count == 1 { "" } else { "s" });
+            Ok(redact::mask(&question, &findings))
+        }
+        redact::Action::Warn => bail!(
+            "Not sent: it has {} in it. action = \"mask\" under [redact] blanks them out, and \"off\" sends them",
+            redact::describe(&question, &findings)
+        ),
+    }
+}
+
+/// Whatever was piped in, cut down to `limit` bytes by dropping the
+/// middle (the start and the end of a log are usually what matter). None
+/// when stdin is a terminal or empty.
+fn read_stdin(skip: bool, limit: usize) -> Result<Option<String>> {
+    if skip || std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    std::io::stdin().lock().read_to_end(&mut bytes).context("Failed to read stdin")?;
+    let text = String::from_utf8_lossy(&bytes);
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    if text.len() <= limit {
+        return Ok(Some(text.into_owned()));
+    }
+
+    let head = floor_char_boundary(&text, limit / 2);
+    let tail = ceil_char_boundary(&text, text.len() - limit / 2);
+    let cut = tail - head;
+    eprintln!(
+        "stdin is {} KB; sending the first and last {} KB and leaving out {} KB in the middle (--max-stdin-kb raises the limit)",
+        text.len() / 1024,
+        limit / 2048,
+        cut / 1024
+    );
+    Ok(Some(format!("{}\n[… {} bytes left out …]\n{}", &text[..head], cut, &text[tail..])))
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// A code block around `text`, with a fence longer than any run of
+/// backticks inside it
+fn fenced(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{}\n{}\n{}", fence, text.trim_end(), fence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "sk-ant-REDACTED";
+
+    fn config(action: &str) -> Config {
+        let mut config = Config::default();
+        config.redact.action = action.to_string();
+        config
+    }
+
+    #[test]
+    fn secrets_are_masked_before_sending() {
+        let question = format!("why?\n\n```\nKEY={}\n```", KEY);
+        let sent = screen_secrets(question, &config("mask")).unwrap();
+        assert_eq!(sent, "why?\n\n```\nKEY=[redacted]\n```");
+    }
+
+    #[test]
+    fn secrets_stop_the_question_when_warning() {
+        let error = screen_secrets(format!("KEY={}", KEY), &config("warn")).unwrap_err();
+        assert!(error.to_string().starts_with("Not sent: it has an Anthropic key (sk-an"), "{}", error);
+    }
+
+    #[test]
+    fn a_question_without_secrets_goes_as_it_is() {
+        assert_eq!(screen_secrets("why?".to_string(), &config("warn")).unwrap(), "why?");
+        let question = format!("KEY={}", KEY);
+        assert_eq!(screen_secrets(question.clone(), &config("off")).unwrap(), question);
+    }
+
+    #[test]
+    fn piped_text_is_fenced_past_its_backticks() {
+        assert_eq!(fenced("a ``` b\n"), "````\na ``` b\n````");
+        assert_eq!(fenced("plain"), "```\nplain\n```");
+    }
+}
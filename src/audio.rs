@@ -0,0 +1,222 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use ffmpeg_next as ff;
+use ff::format::sample::Type as SampleType;
+use ff::format::Sample;
+use ff::software::resampling::context::Context as Resampler;
+use ff::util::frame::audio::Audio;
+use ff::ChannelLayout;
+use rodio::{buffer::SamplesBuffer, OutputStream, Sink};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::background::{self, LoopRange};
+
+/// Decoded chunks queued in the sink before the decoder waits. Keeps
+/// latency (and memory) low while still riding out a slow tick.
+const MAX_QUEUED_CHUNKS: usize = 16;
+
+/// Volume and mute state shared between the UI and the audio thread
+struct AudioControl {
+    /// f32 volume stored as bits so it can live in an atomic
+    volume: AtomicU32,
+    muted: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl AudioControl {
+    fn effective_volume(&self) -> f32 {
+        if self.muted.load(Ordering::Relaxed) {
+            0.0
+        } else {
+            f32::from_bits(self.volume.load(Ordering::Relaxed))
+        }
+    }
+}
+
+/// Plays the background video's audio track on a loop, over the same
+/// range as the picture
+pub struct BackgroundAudio {
+    control: Arc<AudioControl>,
+    /// Seconds to jump by, as the video is sent them
+    seeks: Sender<f64>,
+    errors: Receiver<String>,
+}
+
+impl BackgroundAudio {
+    pub fn new(path: &str, volume: f32, muted: bool, range: LoopRange) -> Self {
+        let control = Arc::new(AudioControl {
+            volume: AtomicU32::new(volume.clamp(0.0, 1.0).to_bits()),
+            muted: AtomicBool::new(muted),
+            stop: AtomicBool::new(false),
+        });
+        let (err_tx, errors) = bounded::<String>(1);
+        let (seeks, seek_rx) = bounded::<f64>(4);
+
+        let path = path.to_string();
+        let thread_control = control.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = play_loop(&path, range, &thread_control, &seek_rx) {
+                tracing::error!(path, "Background audio failed: {:#}", e);
+                let _ = err_tx.send(format!("{:#}", e));
+            }
+        });
+
+        Self { control, seeks, errors }
+    }
+
+    /// Jump by `delta` seconds, wrapping within the loop range like the
+    /// video
+    pub fn seek(&self, delta: f64) {
+        let _ = self.seeks.try_send(delta);
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.control.volume.store(volume.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn toggle_mute(&self) -> bool {
+        let muted = !self.control.muted.load(Ordering::Relaxed);
+        self.control.muted.store(muted, Ordering::Relaxed);
+        muted
+    }
+
+    /// Error that stopped playback, if any (reported once)
+    pub fn take_error(&self) -> Option<String> {
+        self.errors.try_recv().ok()
+    }
+}
+
+impl Drop for BackgroundAudio {
+    fn drop(&mut self) {
+        self.control.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Opens the audio track, with its time base in seconds, or None if the
+/// source is silent
+fn open_audio(
+    path: &str,
+) -> Result<Option<(ff::format::context::Input, usize, ff::codec::decoder::Audio, f64)>> {
+    ff::init().context("init ffmpeg")?;
+    let ictx = background::open_input(path)?;
+
+    let Some(stream) = ictx.streams().best(ff::media::Type::Audio) else {
+        return Ok(None);
+    };
+    let idx = stream.index();
+    let time_base = f64::from(stream.time_base());
+
+    let dec_ctx = ff::codec::context::Context::from_parameters(stream.parameters())?;
+    let decoder = dec_ctx.decoder().audio()?;
+
+    Ok(Some((ictx, idx, decoder, time_base)))
+}
+
+fn play_loop(path: &str, range: LoopRange, control: &AudioControl, seeks: &Receiver<f64>) -> Result<()> {
+    let Some((mut ictx, a_idx, mut dec, time_base)) = open_audio(path)? else {
+        return Ok(()); // nothing to play
+    };
+
+    // The output stream must stay on this thread for as long as we play
+    let (_stream, handle) = OutputStream::try_default().context("open audio output")?;
+    let sink = Sink::try_new(&handle).context("create audio sink")?;
+
+    let rate = dec.rate();
+    let mut resampler = Resampler::get(
+        dec.format(),
+        dec.channel_layout(),
+        rate,
+        Sample::F32(SampleType::Packed),
+        ChannelLayout::STEREO,
+        rate,
+    )
+    .context("create resampler")?;
+
+    let mut frame = Audio::empty();
+    let mut out = Audio::empty();
+
+    // Seeks are bounded the way the video's are: by the loop-out point,
+    // or the end of the file
+    let duration = (ictx.duration() > 0).then(|| ictx.duration() as f64 / 1_000_000.0);
+    let end = range.end.or(duration);
+
+    // Timestamp of the last chunk decoded, how long a chunk plays, a
+    // pending seek, and the timestamp below which frames are discarded
+    // after a seek lands early
+    let mut position = range.start;
+    let mut chunk_secs = 0.0;
+    let mut seek_target = (range.start > 0.0).then_some(range.start);
+    let mut skip_until: Option<f64> = None;
+
+    loop {
+        if let Some(target) = seek_target.take() {
+            background::seek_input(&mut ictx, target)?;
+            dec.flush();
+            skip_until = Some(target);
+            position = target;
+        }
+
+        'packets: for (stream, packet) in ictx.packets() {
+            if let Ok(delta) = seeks.try_recv() {
+                // The queued chunks haven't been heard yet, so the jump
+                // is from where the sound is, and they're dropped
+                let target = position - sink.len() as f64 * chunk_secs + delta;
+                seek_target = Some(match end {
+                    Some(end) if target >= end => range.start,
+                    _ => target.max(range.start),
+                });
+                sink.clear();
+                sink.play();
+                break;
+            }
+
+            if stream.index() != a_idx {
+                continue;
+            }
+            dec.send_packet(&packet)?;
+
+            while dec.receive_frame(&mut frame).is_ok() {
+                if let Some(t) = frame.timestamp().map(|ts| ts as f64 * time_base) {
+                    if skip_until.is_some_and(|skip| t < skip) {
+                        continue;
+                    }
+                    if range.end.is_some_and(|loop_out| t >= loop_out) {
+                        break 'packets;
+                    }
+                    position = t;
+                }
+                skip_until = None;
+
+                resampler.run(&frame, &mut out)?;
+                chunk_secs = out.samples() as f64 / rate as f64;
+
+                // Packed f32 stereo: interleaved L/R samples in plane 0
+                let len = out.samples() * 2 * std::mem::size_of::<f32>();
+                let samples: Vec<f32> = out.data(0)[..len]
+                    .chunks_exact(4)
+                    .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+
+                loop {
+                    if control.stop.load(Ordering::Relaxed) {
+                        return Ok(());
+                    }
+                    sink.set_volume(control.effective_volume());
+                    if sink.len() < MAX_QUEUED_CHUNKS {
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                sink.append(SamplesBuffer::new(2, rate, samples));
+            }
+        }
+
+        // Loop with the video: back to the loop-in point at the loop-out
+        // point or the end of the file
+        if seek_target.is_none() {
+            seek_target = Some(range.start);
+        }
+    }
+}
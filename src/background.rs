@@ -0,0 +1,1011 @@
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use std::cell::Cell;
+use std::sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}};
+use std::time::{Duration, Instant};
+use ratatui::{
+    prelude::*,
+    buffer::Buffer,
+};
+use std::cmp::min;
+
+#[cfg(any(feature = "ffmpeg", feature = "gif"))]
+mod cache;
+#[cfg(feature = "ffmpeg")]
+mod ffmpeg;
+#[cfg(feature = "gif")]
+mod gif;
+pub mod setup;
+
+#[cfg(feature = "audio")]
+pub use ffmpeg::{open_input, seek_input};
+
+/// ASCII palette from light→dark
+const CLASSIC_PALETTE: &str = " .'`^\",:;Il!i><~+_-?][}{1)(|\\tfjrxnuvczXYUJCLQ0OZmwqpdbkhao*#MW&8%B@$";
+const BLOCKS_PALETTE: &str = " ░▒▓█";
+const DOTS_PALETTE: &str = " ⠁⠃⠇⠏⠟⠿⡿⣿";
+const SIMPLE_PALETTE: &str = " .:-=+*#%@";
+
+/// 4x4 Bayer threshold matrix used for ordered dithering
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+/// How luminance is quantized onto the palette ramp
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dither {
+    /// Plain truncation (fastest, bands on smooth gradients)
+    None,
+    /// Bayer matrix thresholding (stable between frames)
+    Ordered,
+    /// Floyd–Steinberg error diffusion (smoothest, can shimmer)
+    FloydSteinberg,
+}
+
+impl Dither {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "none" | "off" => Some(Dither::None),
+            "ordered" | "bayer" => Some(Dither::Ordered),
+            "floyd" | "floyd-steinberg" | "fs" => Some(Dither::FloydSteinberg),
+            _ => None,
+        }
+    }
+}
+
+/// How pixels are turned into characters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AsciiMode {
+    /// Brightness picks a character from the palette ramp
+    Density,
+    /// Like density, but strong edges are drawn with `/ \ | -` along their direction
+    Edges,
+    /// No characters: each cell is filled with its color as a background
+    Solid,
+}
+
+impl AsciiMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "density" | "ramp" => Some(AsciiMode::Density),
+            "edges" | "edge" | "sobel" => Some(AsciiMode::Edges),
+            "solid" | "fill" => Some(AsciiMode::Solid),
+            _ => None,
+        }
+    }
+}
+
+/// Sobel gradient magnitude above which a pixel is drawn as an edge
+const EDGE_THRESHOLD: f32 = 160.0;
+
+/// Brightest a solid-mode cell may be under a text panel, so chat text
+/// drawn over it stays readable whatever the panel opacity
+const SOLID_PANEL_MAX_LUMA: f32 = 72.0;
+
+/// How background colors are transformed before opacity is applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    /// Original video colors
+    Full,
+    /// Grayscale
+    Mono,
+    /// Luminance tinted toward a fixed hue
+    Tint(u8, u8, u8),
+    /// Luminance tinted toward the active provider's color
+    Provider,
+}
+
+impl ColorMode {
+    /// Accepts full, mono, provider, or a `#rrggbb` tint
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "full" | "color" => Some(ColorMode::Full),
+            "mono" | "gray" | "grayscale" => Some(ColorMode::Mono),
+            "provider" => Some(ColorMode::Provider),
+            hex => {
+                let hex = hex.strip_prefix('#')?;
+                if hex.len() != 6 {
+                    return None;
+                }
+                let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+                Some(ColorMode::Tint(channel(0)?, channel(2)?, channel(4)?))
+            }
+        }
+    }
+
+    fn apply(&self, r: u8, g: u8, b: u8, accent: Color) -> (u8, u8, u8) {
+        let tint = |(tr, tg, tb): (u8, u8, u8)| {
+            let y = luminance(r, g, b) as u16;
+            (
+                (tr as u16 * y / 255) as u8,
+                (tg as u16 * y / 255) as u8,
+                (tb as u16 * y / 255) as u8,
+            )
+        };
+
+        match *self {
+            ColorMode::Full => (r, g, b),
+            ColorMode::Mono => tint((255, 255, 255)),
+            ColorMode::Tint(tr, tg, tb) => tint((tr, tg, tb)),
+            ColorMode::Provider => match accent {
+                Color::Rgb(tr, tg, tb) => tint((tr, tg, tb)),
+                _ => tint((255, 255, 255)),
+            },
+        }
+    }
+}
+
+/// Part of the screen the background video occupies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundRegion {
+    /// Behind the whole UI
+    Full,
+    /// A banner across the top third, with the chat below it
+    Top,
+    /// The left third, with the chat to its right
+    Left,
+    /// Only behind the chat header
+    Header,
+}
+
+impl BackgroundRegion {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "full" | "fullscreen" => Some(BackgroundRegion::Full),
+            "top" | "banner" => Some(BackgroundRegion::Top),
+            "left" => Some(BackgroundRegion::Left),
+            "header" => Some(BackgroundRegion::Header),
+            _ => None,
+        }
+    }
+
+    /// Split the screen into (video area, chat area). `header` is where the
+    /// chat would draw its header when given the whole screen.
+    pub fn split(self, area: Rect, header: Rect) -> (Rect, Rect) {
+        match self {
+            BackgroundRegion::Full => (area, area),
+            BackgroundRegion::Header => (header, area),
+            BackgroundRegion::Top | BackgroundRegion::Left => {
+                let direction = if self == BackgroundRegion::Top {
+                    Direction::Vertical
+                } else {
+                    Direction::Horizontal
+                };
+                let chunks = Layout::default()
+                    .direction(direction)
+                    .constraints([Constraint::Ratio(1, 3), Constraint::Ratio(2, 3)])
+                    .split(area);
+                (chunks[0], chunks[1])
+            }
+        }
+    }
+}
+
+/// Character ramp ordered from light→dark
+#[derive(Debug, Clone)]
+pub struct Palette(Vec<char>);
+
+impl Palette {
+    /// Resolve a preset name or treat the string as a custom ramp
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let ramp = match spec.to_lowercase().as_str() {
+            "classic" | "ascii" => CLASSIC_PALETTE,
+            "blocks" => BLOCKS_PALETTE,
+            "dots" | "braille" => DOTS_PALETTE,
+            "simple" => SIMPLE_PALETTE,
+            _ => spec,
+        };
+
+        let chars: Vec<char> = ramp.chars().collect();
+        if chars.len() < 2 {
+            return Err(anyhow!("palette needs at least 2 characters, got {:?}", spec));
+        }
+        Ok(Self(chars))
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self(CLASSIC_PALETTE.chars().collect())
+    }
+}
+
+/// Conversion and compositing settings for the background
+#[derive(Debug, Clone)]
+pub struct BackgroundOptions {
+    pub opacity: f32,
+    /// Opacity used underneath text panels
+    pub panel_opacity: f32,
+    pub dither: Dither,
+    pub palette: Palette,
+    pub ascii_mode: AsciiMode,
+    pub color_mode: ColorMode,
+    pub loop_range: LoopRange,
+    /// Lower resolution/frame rate automatically when the machine struggles
+    pub adaptive: bool,
+    /// Blend between the last and first frames when the video wraps (zero cuts)
+    pub crossfade: Duration,
+    /// Replay converted frames from memory once a looping video has played through
+    pub cache: bool,
+}
+
+/// Looping behaviour handed to the decode thread
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(feature = "ffmpeg"), allow(dead_code))]
+struct Playback {
+    range: LoopRange,
+    adaptive: bool,
+    cache: bool,
+}
+
+/// Portion of the video that plays on repeat, in seconds
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(not(any(feature = "ffmpeg", feature = "gif")), allow(dead_code))]
+pub struct LoopRange {
+    pub start: f64,
+    /// None plays to the end of the file
+    pub end: Option<f64>,
+}
+
+/// Parse `90`, `12.5`, `1:30` or `1:02:03` into seconds
+pub fn parse_timestamp(s: &str) -> Option<f64> {
+    s.trim().split(':').try_fold(0.0, |acc, part| {
+        let value: f64 = part.parse().ok()?;
+        (value >= 0.0).then_some(acc * 60.0 + value)
+    })
+}
+
+/// Per-frame conversion settings owned by the decode thread
+pub struct Conversion {
+    palette: Palette,
+    dither: Dither,
+    mode: AsciiMode,
+    /// Flip horizontally (webcams read more naturally as a mirror)
+    mirror: bool,
+}
+
+impl Conversion {
+    /// `mirror` flips frames left to right
+    pub fn new(options: &BackgroundOptions, mirror: bool) -> Self {
+        Self {
+            palette: options.palette.clone(),
+            dither: options.dither,
+            mode: options.ascii_mode,
+            mirror,
+        }
+    }
+}
+
+pub struct AsciiFrame {
+    w: u16,
+    h: u16,
+    /// Each cell is drawn as a `scale`×`scale` block (reduced-quality mode)
+    scale: u16,
+    /// Packed cells: (ch, r, g, b) row-major
+    cells: Vec<(char, u8, u8, u8)>,
+}
+
+impl AsciiFrame {
+    /// Screen area covered by the frame when centered in `area`
+    fn content_rect(&self, area: Rect) -> Rect {
+        let w = min(self.w * self.scale, area.width);
+        let h = min(self.h * self.scale, area.height);
+        Rect::new(area.x + (area.width - w) / 2, area.y + (area.height - h) / 2, w, h)
+    }
+
+    /// Cell drawn at `pos` when the frame is centered in `area`
+    fn cell_at(&self, area: Rect, pos: Position) -> Option<(char, u8, u8, u8)> {
+        let rect = self.content_rect(area);
+        if !rect.contains(pos) {
+            return None;
+        }
+        let x = (pos.x - rect.x) / self.scale;
+        let y = (pos.y - rect.y) / self.scale;
+        self.cells.get(y as usize * self.w as usize + x as usize).copied()
+    }
+}
+
+fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    let y = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    y as u8
+}
+
+/// Map every pixel to a palette index, spreading quantization error per `dither`
+fn palette_indices(
+    pixels: &[(u8, u8, u8)],
+    w: usize,
+    h: usize,
+    levels_count: usize,
+    dither: Dither,
+) -> Vec<usize> {
+    let max = (levels_count - 1) as f32;
+    let level = |&(r, g, b): &(u8, u8, u8)| luminance(r, g, b) as f32 * max / 255.0;
+
+    match dither {
+        Dither::None => pixels
+            .iter()
+            .map(|&(r, g, b)| luminance(r, g, b) as usize * (levels_count - 1) / 255)
+            .collect(),
+        Dither::Ordered => pixels
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let threshold = (BAYER_4X4[(i / w) % 4][(i % w) % 4] as f32 + 0.5) / 16.0;
+                (level(p) + threshold).floor().min(max) as usize
+            })
+            .collect(),
+        Dither::FloydSteinberg => {
+            let mut levels: Vec<f32> = pixels.iter().map(level).collect();
+            let mut out = Vec::with_capacity(levels.len());
+            for y in 0..h {
+                for x in 0..w {
+                    let i = y * w + x;
+                    let q = levels[i].round().clamp(0.0, max);
+                    let err = levels[i] - q;
+                    out.push(q as usize);
+
+                    if x + 1 < w {
+                        levels[i + 1] += err * 7.0 / 16.0;
+                    }
+                    if y + 1 < h {
+                        if x > 0 {
+                            levels[i + w - 1] += err * 3.0 / 16.0;
+                        }
+                        levels[i + w] += err * 5.0 / 16.0;
+                        if x + 1 < w {
+                            levels[i + w + 1] += err / 16.0;
+                        }
+                    }
+                }
+            }
+            out
+        }
+    }
+}
+
+/// Directional character for each pixel that sits on a strong edge
+fn edge_chars(pixels: &[(u8, u8, u8)], w: usize, h: usize) -> Vec<Option<char>> {
+    let lum: Vec<f32> = pixels.iter().map(|&(r, g, b)| luminance(r, g, b) as f32).collect();
+    let mut out = vec![None; w * h];
+
+    for y in 1..h.saturating_sub(1) {
+        for x in 1..w.saturating_sub(1) {
+            let p = |dx: usize, dy: usize| lum[(y + dy - 1) * w + (x + dx - 1)];
+            let gx = (p(2, 0) + 2.0 * p(2, 1) + p(2, 2)) - (p(0, 0) + 2.0 * p(0, 1) + p(0, 2));
+            let gy = (p(0, 2) + 2.0 * p(1, 2) + p(2, 2)) - (p(0, 0) + 2.0 * p(1, 0) + p(2, 0));
+            if (gx * gx + gy * gy).sqrt() < EDGE_THRESHOLD {
+                continue;
+            }
+
+            // Cells are about twice as tall as wide, so a one-row step covers
+            // twice the distance of a one-column step. The edge runs
+            // perpendicular to the gradient; fold the angle into 0..180°.
+            let mut angle = (gy / 2.0).atan2(gx).to_degrees();
+            if angle < 0.0 {
+                angle += 180.0;
+            }
+            out[y * w + x] = Some(match angle {
+                a if !(22.5..157.5).contains(&a) => '|',
+                a if a < 67.5 => '/',
+                a if a < 112.5 => '-',
+                _ => '\\',
+            });
+        }
+    }
+
+    out
+}
+
+/// A decoded frame of packed RGB24 rows, `stride` bytes apart, as cells
+pub fn to_ascii_frame(data: &[u8], stride: usize, w: usize, h: usize, conv: &Conversion) -> AsciiFrame {
+    let mut pixels = Vec::with_capacity(w * h);
+    for y in 0..h {
+        let row = &data[y * stride..(y * stride + w * 3)];
+        for x in 0..w {
+            let i = if conv.mirror { (w - 1 - x) * 3 } else { x * 3 };
+            pixels.push((row[i], row[i + 1], row[i + 2]));
+        }
+    }
+
+    pixels_to_ascii(&pixels, w, h, conv)
+}
+
+fn pixels_to_ascii(pixels: &[(u8, u8, u8)], w: usize, h: usize, conv: &Conversion) -> AsciiFrame {
+    if conv.mode == AsciiMode::Solid {
+        return AsciiFrame {
+            w: w as u16,
+            h: h as u16,
+            scale: 1,
+            cells: pixels.iter().map(|&(r, g, b)| (' ', r, g, b)).collect(),
+        };
+    }
+
+    let palette = &conv.palette;
+    let mut cells: Vec<(char, u8, u8, u8)> = pixels
+        .iter()
+        .zip(palette_indices(pixels, w, h, palette.len(), conv.dither))
+        .map(|(&(r, g, b), idx)| (palette.0[idx], r, g, b))
+        .collect();
+
+    if conv.mode == AsciiMode::Edges {
+        for (cell, edge) in cells.iter_mut().zip(edge_chars(pixels, w, h)) {
+            if let Some(ch) = edge {
+                cell.0 = ch;
+            }
+        }
+    }
+
+    AsciiFrame {
+        w: w as u16,
+        h: h as u16,
+        scale: 1,
+        cells,
+    }
+}
+
+/// Source name for the built-in procedural animation (no files or ffmpeg input needed)
+pub const BUILTIN_SOURCE: &str = "builtin";
+
+/// Frame interval for the procedural animation (~30 FPS)
+const BUILTIN_FRAME_TIME: Duration = Duration::from_millis(33);
+
+/// One frame of a slowly drifting plasma, sized to the terminal. Rows are
+/// stretched because terminal cells are roughly twice as tall as wide.
+fn plasma_frame(w: usize, h: usize, t: f32) -> Vec<(u8, u8, u8)> {
+    let mut pixels = Vec::with_capacity(w * h);
+    let (cx, cy) = (w as f32 / 2.0, h as f32);
+
+    for y in 0..h {
+        for x in 0..w {
+            let (fx, fy) = (x as f32, y as f32 * 2.0);
+            let dist = ((fx - cx).powi(2) + (fy - cy).powi(2)).sqrt();
+            let v = (fx / 9.0 + t).sin()
+                + (fy / 7.0 + t * 0.7).sin()
+                + ((fx + fy) / 13.0 + t * 1.3).sin()
+                + (dist / 8.0 - t).sin();
+
+            // v is in -4..4; map to a phase and run it through shifted sines
+            // for a smooth purple/teal/amber cycle
+            let phase = v * std::f32::consts::PI / 4.0;
+            let channel = |shift: f32| (((phase + shift).sin() * 0.5 + 0.5) * 255.0) as u8;
+            pixels.push((channel(0.0), channel(2.1), channel(4.2)));
+        }
+    }
+
+    pixels
+}
+
+fn procedural_loop(
+    (target_w, target_h): (u16, u16),
+    conv: Conversion,
+    commands: &Receiver<VideoCommand>,
+    out: Delivery,
+    finished_flag: &AtomicBool,
+) -> Result<()> {
+    let (w, h) = (target_w as usize, target_h as usize);
+    let mut start = Instant::now();
+
+    loop {
+        // Seeking means nothing to an endless animation
+        if let Ok(VideoCommand::Pause) = commands.try_recv() {
+            start += hold(commands);
+        }
+        let started = Instant::now();
+        let pixels = plasma_frame(w, h, start.elapsed().as_secs_f32() * 0.8);
+        let ascii = pixels_to_ascii(&pixels, w, h, &conv);
+        if !out.send(Arc::new(ascii), started.elapsed()) {
+            finished_flag.store(true, Ordering::Relaxed);
+            return Ok(()); // UI gone
+        }
+        std::thread::sleep(BUILTIN_FRAME_TIME);
+    }
+}
+
+/// Default camera for `--video webcam`
+#[cfg(target_os = "macos")]
+const WEBCAM_DEFAULT_DEVICE: &str = "0";
+#[cfg(target_os = "windows")]
+const WEBCAM_DEFAULT_DEVICE: &str = "video=Integrated Camera";
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const WEBCAM_DEFAULT_DEVICE: &str = "/dev/video0";
+
+/// `webcam` or `webcam:<device>` captures from a camera instead of a file
+pub fn webcam_device(path: &str) -> Option<&str> {
+    if path == "webcam" {
+        Some(WEBCAM_DEFAULT_DEVICE)
+    } else {
+        path.strip_prefix("webcam:")
+    }
+}
+
+/// Counters updated by the decode thread for the debug overlay
+#[derive(Default)]
+struct DecodeStats {
+    decoded: AtomicU64,
+    dropped: AtomicU64,
+    /// Scale + ASCII conversion time of the last frame, in microseconds
+    convert_us: AtomicU64,
+    /// Time the UI spent compositing the last frame, in microseconds
+    render_us: Arc<AtomicU64>,
+    /// Frames are being replayed from the frame cache
+    cached: AtomicBool,
+}
+
+/// Point-in-time copy of the decode counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VideoStats {
+    pub decoded: u64,
+    pub dropped: u64,
+    pub convert_time: Duration,
+    /// Frames waiting in the channel for the UI
+    pub queued: usize,
+    /// Current resolution divisor (1 = full quality)
+    pub scale: u16,
+    /// Playing from the frame cache instead of decoding
+    pub cached: bool,
+}
+
+/// A converted frame on its way to the UI
+struct FrameUpdate {
+    frame: Arc<AsciiFrame>,
+    /// First frame after the video wrapped back to its loop-in point
+    wrapped: bool,
+}
+
+/// Sending half of the frame channel, with bookkeeping
+struct Delivery {
+    tx: Sender<FrameUpdate>,
+    stats: Arc<DecodeStats>,
+    /// A wrap happened since the last frame got through
+    wrap_pending: Cell<bool>,
+}
+
+impl Delivery {
+    /// Flag the next frame as the start of a new pass over the loop
+    #[cfg_attr(not(any(feature = "ffmpeg", feature = "gif")), allow(dead_code))]
+    fn mark_wrap(&self) {
+        self.wrap_pending.set(true);
+    }
+
+    #[cfg_attr(not(feature = "ffmpeg"), allow(dead_code))]
+    fn drop_frame(&self) {
+        self.stats.decoded.fetch_add(1, Ordering::Relaxed);
+        self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns false once the UI side has gone away
+    fn send(&self, ascii: Arc<AsciiFrame>, convert_time: Duration) -> bool {
+        self.stats.decoded.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .convert_us
+            .store(convert_time.as_micros() as u64, Ordering::Relaxed);
+
+        // Never block on a slow UI: it always shows the newest frame it has,
+        // and the pacer keeps us on the wall clock while it catches up
+        let update = FrameUpdate {
+            frame: ascii,
+            wrapped: self.wrap_pending.take(),
+        };
+        match self.tx.try_send(update) {
+            Ok(()) => true,
+            Err(TrySendError::Full(update)) => {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                self.wrap_pending.set(update.wrapped);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+fn spawn_decode(
+    path: String,
+    target: (u16, u16),
+    conv: Conversion,
+    playback: Playback,
+    commands: Receiver<VideoCommand>,
+    stats: Arc<DecodeStats>,
+    finished_flag: Arc<AtomicBool>,
+) -> Result<(Receiver<FrameUpdate>, Receiver<String>)> {
+    let (tx, rx) = bounded::<FrameUpdate>(2);
+    let (err_tx, err_rx) = bounded::<String>(1);
+    let out = Delivery {
+        tx,
+        stats,
+        wrap_pending: Cell::new(false),
+    };
+
+    std::thread::spawn(move || {
+        // Report open/decode failures to the UI instead of dying silently
+        let result = if path == BUILTIN_SOURCE {
+            procedural_loop(target, conv, &commands, out, &finished_flag)
+        } else {
+            decode_source(&path, target, conv, playback, &commands, out, &finished_flag)
+        };
+        if let Err(e) = result {
+            tracing::error!(path, "Background decode failed: {:#}", e);
+            let _ = err_tx.send(format!("{:#}", e));
+        }
+    });
+
+    Ok((rx, err_rx))
+}
+
+/// Hand `path` to the first compiled-in backend that can play it
+fn decode_source(
+    path: &str,
+    target: (u16, u16),
+    conv: Conversion,
+    playback: Playback,
+    commands: &Receiver<VideoCommand>,
+    out: Delivery,
+    finished_flag: &AtomicBool,
+) -> Result<()> {
+    #[cfg(feature = "gif")]
+    if gif::is_gif(path) {
+        return gif::gif_loop(path, target, &conv, playback.range, commands, out, finished_flag);
+    }
+
+    decode_media(path, target, conv, playback, commands, out, finished_flag)
+}
+
+#[cfg(feature = "ffmpeg")]
+fn decode_media(
+    path: &str,
+    target: (u16, u16),
+    conv: Conversion,
+    playback: Playback,
+    commands: &Receiver<VideoCommand>,
+    out: Delivery,
+    finished_flag: &AtomicBool,
+) -> Result<()> {
+    ffmpeg::decode_loop(path, target, conv, playback, commands, out, finished_flag)
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn decode_media(
+    path: &str,
+    _target: (u16, u16),
+    _conv: Conversion,
+    _playback: Playback,
+    _commands: &Receiver<VideoCommand>,
+    _out: Delivery,
+    _finished_flag: &AtomicBool,
+) -> Result<()> {
+    Err(anyhow!(
+        "can't play {path}: built without the ffmpeg feature (only GIF files and `builtin` are supported)"
+    ))
+}
+
+/// Requests from the UI to the decode thread
+#[cfg_attr(not(any(feature = "ffmpeg", feature = "gif")), allow(dead_code))]
+pub enum VideoCommand {
+    /// Jump forward/backward by this many seconds
+    Seek(f64),
+    /// Stop where it is until `Resume`, as if no time had passed
+    Pause,
+    Resume,
+}
+
+/// Wait out a `Pause`: block until the `Resume`, or until the UI has gone
+/// away. Returns how long that was, to move the playback clock on by.
+fn hold(commands: &Receiver<VideoCommand>) -> Duration {
+    let paused = Instant::now();
+    while let Ok(command) = commands.recv() {
+        if let VideoCommand::Resume = command {
+            break;
+        }
+    }
+    paused.elapsed()
+}
+
+pub struct VideoBackground {
+    rx: Receiver<FrameUpdate>,
+    commands: Sender<VideoCommand>,
+    stats: Arc<DecodeStats>,
+    errors: Receiver<String>,
+    latest: Option<Arc<AsciiFrame>>,
+    /// Last frame before the video wrapped, blended out over `crossfade`
+    fading_from: Option<(Arc<AsciiFrame>, Instant)>,
+    crossfade: Duration,
+    opacity: f32,
+    panel_opacity: f32,
+    color_mode: ColorMode,
+    /// Cells are filled with background color instead of colored glyphs
+    solid: bool,
+}
+
+impl VideoBackground {
+    pub fn new(path: &str, width: u16, height: u16, options: BackgroundOptions) -> Result<Self> {
+        let conv = Conversion::new(&options, webcam_device(path).is_some());
+        let (commands, command_rx) = bounded::<VideoCommand>(4);
+
+        let stats = Arc::new(DecodeStats::default());
+        let finished_flag = Arc::new(AtomicBool::new(false));
+        let (rx, errors) = spawn_decode(
+            path.to_string(),
+            (width, height),
+            conv,
+            Playback {
+                range: options.loop_range,
+                adaptive: options.adaptive,
+                cache: options.cache,
+            },
+            command_rx,
+            stats.clone(),
+            finished_flag,
+        )?;
+
+        Ok(Self {
+            rx,
+            commands,
+            stats,
+            errors,
+            latest: None,
+            fading_from: None,
+            crossfade: options.crossfade,
+            opacity: options.opacity.clamp(0.0, 1.0),
+            panel_opacity: options.panel_opacity.clamp(0.0, 1.0),
+            color_mode: options.color_mode,
+            solid: options.ascii_mode == AsciiMode::Solid,
+        })
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    pub fn set_panel_opacity(&mut self, opacity: f32) {
+        self.panel_opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    pub fn set_color_mode(&mut self, color_mode: ColorMode) {
+        self.color_mode = color_mode;
+    }
+
+    pub fn stats(&self) -> VideoStats {
+        VideoStats {
+            decoded: self.stats.decoded.load(Ordering::Relaxed),
+            dropped: self.stats.dropped.load(Ordering::Relaxed),
+            convert_time: Duration::from_micros(self.stats.convert_us.load(Ordering::Relaxed)),
+            queued: self.rx.len(),
+            scale: self.latest.as_ref().map(|af| af.scale).unwrap_or(1),
+            cached: self.stats.cached.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Seek the background by `delta` seconds, wrapping within the loop range
+    pub fn seek(&self, delta: f64) {
+        let _ = self.commands.try_send(VideoCommand::Seek(delta));
+    }
+
+    /// Hold the decode thread where it is, so a stopped chat costs nothing
+    /// and doesn't come back to a backlog of late frames
+    pub fn pause(&self) {
+        let _ = self.commands.send(VideoCommand::Pause);
+    }
+
+    /// Carry on from where `pause` left off
+    pub fn resume(&self) {
+        let _ = self.commands.send(VideoCommand::Resume);
+    }
+
+    /// Error that stopped the decode thread, if any (reported once)
+    pub fn take_error(&self) -> Option<String> {
+        self.errors.try_recv().ok()
+    }
+
+    /// Take the newest decoded frame. Returns whether the picture changed
+    /// (a new frame, or a crossfade still going).
+    pub fn update(&mut self) -> bool {
+        let mut changed = false;
+        // Latest frame wins: drain whatever queued up during a slow tick
+        while let Ok(update) = self.rx.try_recv() {
+            if update.wrapped && !self.crossfade.is_zero() {
+                self.fading_from = self.latest.take().map(|af| (af, Instant::now()));
+            }
+            self.latest = Some(update.frame);
+            changed = true;
+        }
+
+        if self
+            .fading_from
+            .as_ref()
+            .is_some_and(|(_, since)| since.elapsed() >= self.crossfade)
+        {
+            self.fading_from = None;
+        }
+        changed || self.fading_from.is_some()
+    }
+
+    /// Render video as background with opacity applied.
+    /// `accent` is the active provider's color, used by `ColorMode::Provider`.
+    /// Cells inside `panels` use the panel opacity instead of the global one.
+    pub fn render_background(&self, buf: &mut Buffer, area: Rect, accent: Color, panels: &[Rect]) {
+        let started = Instant::now();
+        let opacity = (self.opacity, self.panel_opacity);
+        self.draw(buf, area, accent, panels, opacity, self.color_mode);
+
+        // Feed the adaptive quality controller in the decode thread
+        self.stats
+            .render_us
+            .store(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Render the video on its own at `brightness` (screensaver mode),
+    /// optionally ignoring the configured color mode
+    pub fn render_screensaver(&self, buf: &mut Buffer, area: Rect, accent: Color, brightness: f32, full_color: bool) {
+        let color_mode = if full_color { ColorMode::Full } else { self.color_mode };
+        self.draw(buf, area, accent, &[], (brightness, brightness), color_mode);
+    }
+
+    fn draw(
+        &self,
+        buf: &mut Buffer,
+        area: Rect,
+        accent: Color,
+        panels: &[Rect],
+        (opacity, panel_opacity): (f32, f32),
+        color_mode: ColorMode,
+    ) {
+        let Some(ref af) = self.latest else {
+            return;
+        };
+
+        // Weight of the outgoing frame while crossfading across a loop wrap
+        let fade = self.fading_from.as_ref().map(|(old, since)| {
+            let t = since.elapsed().as_secs_f32() / self.crossfade.as_secs_f32();
+            (old, (1.0 - t).clamp(0.0, 1.0))
+        });
+
+        for pos in af.content_rect(area).positions() {
+            let Some((mut ch, r, g, b)) = af.cell_at(area, pos) else {
+                continue;
+            };
+            let (mut r, mut g, mut b) = (r, g, b);
+            if let Some((old, weight)) = fade {
+                if let Some((old_ch, old_r, old_g, old_b)) = old.cell_at(area, pos) {
+                    let mix = |new: u8, old: u8| (new as f32 * (1.0 - weight) + old as f32 * weight) as u8;
+                    (r, g, b) = (mix(r, old_r), mix(g, old_g), mix(b, old_b));
+                    if weight > 0.5 {
+                        ch = old_ch;
+                    }
+                }
+            }
+            let (r, g, b) = color_mode.apply(r, g, b, accent);
+
+            let in_panel = panels.iter().any(|p| p.contains(pos));
+            let mut opacity = if in_panel { panel_opacity } else { opacity };
+            if opacity <= 0.0 {
+                continue;
+            }
+
+            // Solid cells sit behind the chat text rather than beside
+            // it, so keep them dark enough under the panels
+            if self.solid && in_panel {
+                let luma = luminance(r, g, b) as f32 * opacity;
+                if luma > SOLID_PANEL_MAX_LUMA {
+                    opacity *= SOLID_PANEL_MAX_LUMA / luma;
+                }
+            }
+
+            // Apply opacity by blending with black
+            let r_dim = (r as f32 * opacity) as u8;
+            let g_dim = (g as f32 * opacity) as u8;
+            let b_dim = (b as f32 * opacity) as u8;
+
+            if let Some(cell) = buf.cell_mut(pos) {
+                cell.set_char(ch);
+                if self.solid {
+                    cell.set_bg(Color::Rgb(r_dim, g_dim, b_dim));
+                } else {
+                    cell.set_fg(Color::Rgb(r_dim, g_dim, b_dim));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: u8, n: usize) -> Vec<(u8, u8, u8)> {
+        vec![(v, v, v); n]
+    }
+
+    #[test]
+    fn plain_quantizing_truncates_onto_the_ramp() {
+        let pixels = [(0, 0, 0), (128, 128, 128), (255, 255, 255)];
+        assert_eq!(palette_indices(&pixels, 3, 1, 10, Dither::None), [0, 4, 9]);
+    }
+
+    #[test]
+    fn ordered_dithering_splits_a_half_tone_evenly_over_the_bayer_tile() {
+        let indices = palette_indices(&gray(128, 16), 4, 4, 2, Dither::Ordered);
+        assert_eq!(indices.iter().filter(|&&i| i == 1).count(), 8);
+        // Thresholds 8 and up tip over, which is the same cells every frame
+        assert_eq!(&indices[..4], [0, 1, 0, 1]);
+        assert_eq!(&indices[4..8], [1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn error_diffusion_keeps_the_average_level_of_a_flat_field() {
+        let indices = palette_indices(&gray(128, 64), 8, 8, 2, Dither::FloydSteinberg);
+        let ones = indices.iter().filter(|&&i| i == 1).count();
+        assert!((30..=34).contains(&ones), "{ones} of 64 cells lit");
+
+        for (v, level) in [(0, 0), (255, 9)] {
+            let flat = palette_indices(&gray(v, 16), 4, 4, 10, Dither::FloydSteinberg);
+            assert!(flat.iter().all(|&i| i == level), "{flat:?}");
+        }
+    }
+
+    #[test]
+    fn color_modes_parse_by_name_or_hex_tint() {
+        assert_eq!(ColorMode::parse("Mono"), Some(ColorMode::Mono));
+        assert_eq!(ColorMode::parse("color"), Some(ColorMode::Full));
+        assert_eq!(ColorMode::parse("provider"), Some(ColorMode::Provider));
+        assert_eq!(ColorMode::parse("#FF8000"), Some(ColorMode::Tint(255, 128, 0)));
+        for bad in ["ff8000", "#ff80", "#gg0000", "#ff80000", "sepia"] {
+            assert_eq!(ColorMode::parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn tints_scale_the_hue_by_luminance() {
+        assert_eq!(ColorMode::Mono.apply(255, 0, 0, Color::Reset), (76, 76, 76));
+        assert_eq!(ColorMode::Tint(0, 255, 0).apply(255, 0, 0, Color::Reset), (0, 76, 0));
+        assert_eq!(ColorMode::Provider.apply(255, 0, 0, Color::Rgb(0, 0, 200)), (0, 0, 59));
+        assert_eq!(ColorMode::Full.apply(1, 2, 3, Color::Reset), (1, 2, 3));
+    }
+
+    #[test]
+    fn timestamps_read_as_seconds_minutes_or_hours() {
+        assert_eq!(parse_timestamp("90"), Some(90.0));
+        assert_eq!(parse_timestamp(" 12.5 "), Some(12.5));
+        assert_eq!(parse_timestamp("1:30"), Some(90.0));
+        assert_eq!(parse_timestamp("1:02:03"), Some(3723.0));
+        assert_eq!(parse_timestamp("0:07.5"), Some(7.5));
+        for bad in ["", "-5", "1:-3", "1:xx", "1::2"] {
+            assert_eq!(parse_timestamp(bad), None, "{bad:?}");
+        }
+    }
+
+    /// 5×5 image, black where `dark` says so and white elsewhere
+    fn split_image(dark: impl Fn(usize, usize) -> bool) -> Vec<(u8, u8, u8)> {
+        (0..25).map(|i| if dark(i % 5, i / 5) { (0, 0, 0) } else { (255, 255, 255) }).collect()
+    }
+
+    #[test]
+    fn edges_are_drawn_along_their_direction() {
+        let row = |edges: &[Option<char>], y: usize| edges[y * 5..y * 5 + 5].to_vec();
+
+        let vertical = edge_chars(&split_image(|x, _| x < 2), 5, 5);
+        assert_eq!(row(&vertical, 2), [None, Some('|'), Some('|'), None, None]);
+
+        let horizontal = edge_chars(&split_image(|_, y| y < 2), 5, 5);
+        assert_eq!(row(&horizontal, 1), [None, Some('-'), Some('-'), Some('-'), None]);
+        assert_eq!(row(&horizontal, 3), [None; 5]);
+
+        // Border pixels have no full neighbourhood and are never edges
+        assert_eq!(row(&vertical, 0), [None; 5]);
+    }
+
+    #[test]
+    fn flat_images_have_no_edges() {
+        assert!(edge_chars(&gray(200, 25), 5, 5).iter().all(Option::is_none));
+        assert!(edge_chars(&gray(200, 4), 2, 2).iter().all(Option::is_none));
+    }
+}
@@ -0,0 +1,141 @@
+//! Frame cache shared by the backends: converted frames from one pass over
+//! the loop, replayed on every later pass without decoding anything
+
+use anyhow::Result;
+use crossbeam_channel::Receiver;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{hold, AsciiFrame, Delivery, VideoCommand};
+
+/// Memory the frame cache may use before caching is abandoned
+const MAX_CACHE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Converted frames from one uninterrupted pass over the loop, indexed by
+/// frame number. The decode thread owns it and its target size never
+/// changes, so the frames always match the terminal they were made for.
+pub(super) struct FrameCache {
+    /// Loop-in point; frame offsets are measured from here
+    origin: f64,
+    /// Length of one pass over the loop
+    pub(super) length: Duration,
+    /// (offset into the loop, frame) in playback order
+    pub(super) frames: Vec<(Duration, Arc<AsciiFrame>)>,
+    bytes: usize,
+    /// False after a seek or quality change, until the next pass starts
+    recording: bool,
+}
+
+impl FrameCache {
+    pub(super) fn new(origin: f64, length: Duration) -> Self {
+        Self {
+            origin,
+            length,
+            frames: Vec::new(),
+            bytes: 0,
+            recording: true,
+        }
+    }
+
+    /// Begin recording a fresh pass from the loop-in point
+    #[cfg_attr(not(feature = "ffmpeg"), allow(dead_code))]
+    pub(super) fn restart(&mut self) {
+        self.frames.clear();
+        self.bytes = 0;
+        self.recording = true;
+    }
+
+    /// Discard the current pass; it no longer plays straight through
+    pub(super) fn interrupt(&mut self) {
+        self.frames.clear();
+        self.bytes = 0;
+        self.recording = false;
+    }
+
+    /// Whether a whole pass was recorded and can be replayed
+    #[cfg_attr(not(feature = "ffmpeg"), allow(dead_code))]
+    pub(super) fn is_complete(&self) -> bool {
+        self.recording && !self.frames.is_empty()
+    }
+
+    /// Store a frame shown at `secs`. Returns false once the cache outgrows
+    /// its memory budget.
+    pub(super) fn record(&mut self, secs: Option<f64>, frame: &Arc<AsciiFrame>) -> bool {
+        if !self.recording {
+            return true;
+        }
+        // Without a timestamp the replay can't be paced
+        let Some(secs) = secs else {
+            self.interrupt();
+            return true;
+        };
+
+        self.bytes += frame.cells.len() * std::mem::size_of::<(char, u8, u8, u8)>();
+        if self.bytes > MAX_CACHE_BYTES {
+            return false;
+        }
+        let offset = Duration::from_secs_f64((secs - self.origin).max(0.0));
+        self.frames.push((offset, frame.clone()));
+        true
+    }
+
+    /// Index of the first frame at or after `offset`
+    fn position(&self, offset: Duration) -> usize {
+        self.frames.partition_point(|(t, _)| *t < offset)
+    }
+}
+
+/// Play a recorded pass on repeat without decoding anything. Seeks move
+/// within the recording. Returns once the UI has gone away.
+pub(super) fn replay_cache(
+    cache: &FrameCache,
+    commands: &Receiver<VideoCommand>,
+    out: &Delivery,
+    finished_flag: &AtomicBool,
+) -> Result<()> {
+    out.stats.cached.store(true, Ordering::Relaxed);
+    let mut start = Instant::now();
+    let mut index = 0;
+
+    loop {
+        match commands.try_recv() {
+            Ok(VideoCommand::Seek(delta)) => {
+                let target = start.elapsed().as_secs_f64() + delta;
+                let target = if target >= cache.length.as_secs_f64() {
+                    Duration::ZERO
+                } else {
+                    Duration::from_secs_f64(target.max(0.0))
+                };
+                index = cache.position(target);
+                start = Instant::now().checked_sub(target).unwrap_or_else(Instant::now);
+            }
+            Ok(VideoCommand::Pause) => start += hold(commands),
+            _ => {}
+        }
+
+        // Hold the last frame until the pass is over, then go around
+        let Some((offset, frame)) = cache.frames.get(index) else {
+            let pass_end = start + cache.length;
+            let now = Instant::now();
+            if pass_end > now {
+                std::thread::sleep(pass_end - now);
+            }
+            start = pass_end.max(now);
+            index = 0;
+            out.mark_wrap();
+            continue;
+        };
+
+        let due = start + *offset;
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+        if !out.send(frame.clone(), Duration::ZERO) {
+            finished_flag.store(true, Ordering::Relaxed);
+            return Ok(()); // UI gone
+        }
+        index += 1;
+    }
+}
@@ -0,0 +1,550 @@
+//! Decoding through ffmpeg: files, network streams and webcams, with
+//! wall-clock pacing, loop ranges, seeking and adaptive quality
+
+use anyhow::{Context, Result};
+use crossbeam_channel::Receiver;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use ffmpeg_next as ff;
+use ff::format::context::Input;
+use ff::format::Pixel;
+use ff::software::scaling::{context::Context as Scaler, flag::Flags};
+use ff::util::frame::video::Video;
+
+use super::cache::{replay_cache, FrameCache};
+use super::{hold, webcam_device, AsciiFrame, Conversion, Delivery, Playback, VideoCommand};
+
+/// Platform capture backend for `--video webcam`
+#[cfg(target_os = "macos")]
+const WEBCAM_FORMAT: &str = "avfoundation";
+#[cfg(target_os = "windows")]
+const WEBCAM_FORMAT: &str = "dshow";
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const WEBCAM_FORMAT: &str = "v4l2";
+
+/// Network read timeout handed to ffmpeg, in microseconds
+const NETWORK_TIMEOUT_US: &str = "10000000";
+
+/// Frames further behind schedule than this are dropped without converting
+const MAX_LATENESS: Duration = Duration::from_millis(50);
+
+/// Pacing used when a stream carries no usable timestamps (~30 FPS)
+const FALLBACK_FRAME_TIME: Duration = Duration::from_millis(33);
+
+fn to_ascii_frame(rgb: &Video, conv: &Conversion) -> AsciiFrame {
+    super::to_ascii_frame(rgb.data(0), rgb.stride(0), rgb.width() as usize, rgb.height() as usize, conv)
+}
+
+/// Anything with a scheme (http://, rtsp://, ...) is opened as a network input
+fn is_network_source(path: &str) -> bool {
+    path.contains("://") && !path.starts_with("file://")
+}
+
+fn open_webcam(device: &str) -> Result<Input> {
+    ff::device::register_all();
+
+    let format = ff::device::input::video()
+        .find(|f| f.name() == WEBCAM_FORMAT)
+        .with_context(|| format!("ffmpeg was built without {WEBCAM_FORMAT} capture support"))?;
+
+    let mut opts = ff::Dictionary::new();
+    opts.set("framerate", "30");
+
+    ff::format::open_with(&device, &format, opts)
+        .map(|ctx| ctx.input())
+        .with_context(|| format!("open webcam {device} via {WEBCAM_FORMAT}"))
+}
+
+pub fn open_input(path: &str) -> Result<Input> {
+    if let Some(device) = webcam_device(path) {
+        return open_webcam(device);
+    }
+
+    if !is_network_source(path) {
+        return ff::format::input(&path).with_context(|| format!("open input {path}"));
+    }
+
+    ff::format::network::init();
+
+    // Fail instead of hanging forever on a dead server, reconnect on
+    // dropped HTTP connections, and read in larger chunks to ride out jitter
+    let mut opts = ff::Dictionary::new();
+    opts.set("rw_timeout", NETWORK_TIMEOUT_US);
+    opts.set("timeout", NETWORK_TIMEOUT_US);
+    opts.set("reconnect", "1");
+    opts.set("reconnect_streamed", "1");
+    opts.set("reconnect_delay_max", "5");
+    opts.set("buffer_size", "4194304");
+
+    ff::format::input_with_dictionary(&path, opts).with_context(|| format!("open stream {path}"))
+}
+
+fn open_decoder(
+    path: &str,
+) -> Result<(
+    Input,
+    usize,
+    ff::codec::decoder::Video,
+    f64,
+)> {
+    ff::init().context("init ffmpeg")?;
+    let ictx = open_input(path)?;
+
+    let stream = ictx
+        .streams()
+        .best(ff::media::Type::Video)
+        .context("no video stream")?;
+    let idx = stream.index();
+    let time_base = f64::from(stream.time_base());
+
+    let dec_ctx = ff::codec::context::Context::from_parameters(stream.parameters())?;
+    let decoder = dec_ctx.decoder().video()?;
+
+    Ok((ictx, idx, decoder, time_base))
+}
+
+/// Schedules decoded frames against the wall clock so playback runs at the
+/// video's own speed instead of however fast the UI happens to drain frames
+struct Pacer {
+    time_base: f64,
+    start: Instant,
+    first_pts: Option<i64>,
+}
+
+impl Pacer {
+    fn new(time_base: f64) -> Self {
+        Self {
+            time_base,
+            start: Instant::now(),
+            first_pts: None,
+        }
+    }
+
+    /// Start a new timeline (after looping, seeking or reopening the input)
+    fn restart(&mut self, time_base: f64) {
+        *self = Self::new(time_base);
+    }
+
+    /// Push the timeline back by the time spent paused
+    fn delay(&mut self, by: Duration) {
+        self.start += by;
+    }
+
+    /// Sleep until the frame is due. Returns false if the frame is already
+    /// too far behind schedule to be worth converting.
+    fn wait_for(&mut self, pts: Option<i64>) -> bool {
+        let Some(pts) = pts else {
+            std::thread::sleep(FALLBACK_FRAME_TIME);
+            return true;
+        };
+
+        let first = *self.first_pts.get_or_insert(pts);
+        let offset = ((pts - first) as f64 * self.time_base).max(0.0);
+        let due = self.start + Duration::from_secs_f64(offset);
+
+        let now = Instant::now();
+        if now > due + MAX_LATENESS {
+            return false;
+        }
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+        true
+    }
+}
+
+/// Background quality steps, cheapest last
+const QUALITY_LEVELS: [Quality; 3] = [
+    Quality { scale: 1, frame_stride: 1 },
+    Quality { scale: 2, frame_stride: 1 },
+    Quality { scale: 2, frame_stride: 2 },
+];
+
+/// Fraction of the frame budget above which quality is reduced
+const DEGRADE_LOAD: f32 = 0.6;
+/// Fraction below which the next better level would still fit comfortably
+const RESTORE_LOAD: f32 = 0.12;
+/// Minimum time between quality changes, so we don't flap
+const QUALITY_HOLD: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy)]
+struct Quality {
+    /// Decode at terminal size / scale, upscale by cell duplication
+    scale: u16,
+    /// Convert only every Nth frame
+    frame_stride: u64,
+}
+
+/// Tracks how much of each frame's time budget is spent converting and
+/// rendering, and steps quality down under load / back up when idle
+struct Adaptive {
+    enabled: bool,
+    level: usize,
+    /// Exponential moving average of (convert + render time) / frame budget
+    load: f32,
+    budget: Duration,
+    last_change: Instant,
+}
+
+impl Adaptive {
+    fn new(enabled: bool, budget: Duration) -> Self {
+        Self {
+            enabled,
+            level: 0,
+            load: 0.0,
+            budget,
+            last_change: Instant::now(),
+        }
+    }
+
+    fn quality(&self) -> Quality {
+        QUALITY_LEVELS[self.level]
+    }
+
+    /// Feed one frame's cost; returns true when the level changed
+    fn record(&mut self, cost: Duration) -> bool {
+        let sample = cost.as_secs_f32() / self.budget.as_secs_f32().max(f32::EPSILON);
+        self.load = self.load * 0.9 + sample * 0.1;
+
+        if !self.enabled || self.last_change.elapsed() < QUALITY_HOLD {
+            return false;
+        }
+
+        let new_level = if self.load > DEGRADE_LOAD && self.level + 1 < QUALITY_LEVELS.len() {
+            self.level + 1
+        } else if self.load < RESTORE_LOAD && self.level > 0 {
+            self.level - 1
+        } else {
+            return false;
+        };
+
+        self.level = new_level;
+        self.load = 0.0;
+        self.last_change = Instant::now();
+        true
+    }
+}
+
+/// Scale → convert → deliver, shared by the normal and EOF-flush paths
+struct FramePipeline {
+    scaler: Scaler,
+    rgb: Video,
+    /// Decoder output format and size, needed to rebuild the scaler
+    source: (Pixel, u32, u32),
+    /// Full-quality output size (the terminal)
+    target: (u16, u16),
+    conv: Conversion,
+    pacer: Pacer,
+    adaptive: Adaptive,
+    frames_seen: u64,
+    /// None when caching is off, impossible (live input) or over budget
+    cache: Option<FrameCache>,
+    out: Delivery,
+}
+
+impl FramePipeline {
+    fn new(
+        source: (Pixel, u32, u32),
+        target: (u16, u16),
+        conv: Conversion,
+        pacer: Pacer,
+        adaptive: Adaptive,
+        out: Delivery,
+    ) -> Result<Self> {
+        let (fmt, src_w, src_h) = source;
+        Ok(Self {
+            scaler: build_scaler(fmt, src_w, src_h, target.0 as u32, target.1 as u32)?,
+            rgb: Video::new(Pixel::RGB24, target.0 as u32, target.1 as u32),
+            source,
+            target,
+            conv,
+            pacer,
+            adaptive,
+            frames_seen: 0,
+            cache: None,
+            out,
+        })
+    }
+
+    /// Rebuild the scaler for the current quality level
+    fn rescale(&mut self) -> Result<()> {
+        let scale = self.adaptive.quality().scale;
+        let (w, h) = (self.target.0.div_ceil(scale), self.target.1.div_ceil(scale));
+        let (fmt, src_w, src_h) = self.source;
+
+        self.scaler = build_scaler(fmt, src_w, src_h, w as u32, h as u32)?;
+        self.rgb = Video::new(Pixel::RGB24, w as u32, h as u32);
+        Ok(())
+    }
+
+    /// Returns false once the UI side has gone away
+    fn push(&mut self, frame: &Video, render_time: Duration) -> Result<bool> {
+        self.frames_seen += 1;
+        let quality = self.adaptive.quality();
+        if !self.pacer.wait_for(frame.timestamp())
+            || !self.frames_seen.is_multiple_of(quality.frame_stride)
+        {
+            self.out.drop_frame();
+            return Ok(true);
+        }
+
+        let started = Instant::now();
+        self.scaler.run(frame, &mut self.rgb)?;
+        let mut ascii = to_ascii_frame(&self.rgb, &self.conv);
+        ascii.scale = quality.scale;
+        let ascii = Arc::new(ascii);
+        let convert_time = started.elapsed();
+
+        if let Some(ref mut cache) = self.cache {
+            let secs = frame.timestamp().map(|ts| ts as f64 * self.pacer.time_base);
+            if !cache.record(secs, &ascii) {
+                self.cache = None;
+            }
+        }
+
+        if self.adaptive.record(convert_time + render_time) {
+            self.rescale()?;
+            // Don't replay a pass recorded at mixed quality
+            if let Some(ref mut cache) = self.cache {
+                cache.interrupt();
+            }
+        }
+
+        Ok(self.out.send(ascii, convert_time))
+    }
+}
+
+fn build_scaler(
+    src_fmt: Pixel,
+    src_w: u32,
+    src_h: u32,
+    dst_w: u32,
+    dst_h: u32,
+) -> Result<Scaler> {
+    Scaler::get(
+        src_fmt,
+        src_w,
+        src_h,
+        Pixel::RGB24,
+        dst_w,
+        dst_h,
+        Flags::BILINEAR,
+    )
+    .context("create scaler")
+}
+
+/// Seek the input to `secs` (in AV_TIME_BASE units under the hood)
+pub fn seek_input(ictx: &mut Input, secs: f64) -> Result<()> {
+    let ts = (secs.max(0.0) * 1_000_000.0) as i64;
+    ictx.seek(ts, ..ts).context("seek")?;
+    Ok(())
+}
+
+/// What becomes of a decoded frame
+#[derive(Debug, PartialEq)]
+enum Placed {
+    /// Before where a seek was headed, having landed on an earlier keyframe
+    Skip,
+    /// At or past the loop-out point
+    LoopEnd,
+    Show,
+}
+
+/// Place a frame at `secs` against the seek still landing and the
+/// loop-out point, moving `position` to it if it's shown
+fn place_frame(secs: Option<f64>, skip_until: &mut Option<f64>, loop_out: Option<f64>, position: &mut f64) -> Placed {
+    if let Some(t) = secs {
+        if skip_until.is_some_and(|skip| t < skip) {
+            return Placed::Skip;
+        }
+        if loop_out.is_some_and(|out| t >= out) {
+            return Placed::LoopEnd;
+        }
+        *position = t;
+    }
+    *skip_until = None;
+    Placed::Show
+}
+
+pub(super) fn decode_loop(
+    path: &str,
+    (target_w, target_h): (u16, u16),
+    conv: Conversion,
+    playback: Playback,
+    commands: &Receiver<VideoCommand>,
+    out: Delivery,
+    finished_flag: &AtomicBool,
+) -> Result<()> {
+    let range = playback.range;
+    let (mut ictx, mut v_idx, mut dec, mut time_base) = open_decoder(path)?;
+
+    // Per-frame time budget for the adaptive quality controller
+    let frame_rate = ictx
+        .stream(v_idx)
+        .map(|s| f64::from(s.avg_frame_rate()))
+        .filter(|fps| fps.is_finite() && *fps > 0.0)
+        .unwrap_or(30.0);
+    let budget = Duration::from_secs_f64(1.0 / frame_rate);
+
+    let render_us = out.stats.render_us.clone();
+    let mut pipeline = FramePipeline::new(
+        (dec.format(), dec.width(), dec.height()),
+        (target_w, target_h),
+        conv,
+        Pacer::new(time_base),
+        Adaptive::new(playback.adaptive, budget),
+        out,
+    )?;
+    let mut frame = Video::empty();
+    let render_time = || Duration::from_micros(render_us.load(Ordering::Relaxed));
+
+    // Loop-out point (or the end of the file) bounds forward seeks
+    let duration = (ictx.duration() > 0).then(|| ictx.duration() as f64 / 1_000_000.0);
+    let end = range.end.or(duration);
+
+    // Only a clip with a known end loops the same frames every pass
+    let pass_end = match (range.end, duration) {
+        (Some(out), Some(duration)) => Some(out.min(duration)),
+        (out, duration) => out.or(duration),
+    };
+    pipeline.cache = pass_end
+        .filter(|end| playback.cache && *end > range.start)
+        .map(|end| FrameCache::new(range.start, Duration::from_secs_f64(end - range.start)));
+
+    // Current playback position, a pending seek, and the PTS below which
+    // frames are discarded after a seek lands on an earlier keyframe
+    let mut position = range.start;
+    let mut seek_target = (range.start > 0.0).then_some(range.start);
+    let mut skip_until: Option<f64> = None;
+
+    loop {
+        if let Some(target) = seek_target.take() {
+            seek_input(&mut ictx, target)?;
+            dec.flush();
+            pipeline.pacer.restart(time_base);
+            skip_until = Some(target);
+            position = target;
+        }
+
+        let mut reached_eof = true;
+        let mut reached_loop_end = false;
+        'packets: for (stream, packet) in ictx.packets() {
+            match commands.try_recv() {
+                Ok(VideoCommand::Seek(delta)) => {
+                    if let Some(ref mut cache) = pipeline.cache {
+                        cache.interrupt();
+                    }
+                    let target = position + delta;
+                    seek_target = Some(match end {
+                        Some(end) if target >= end => range.start,
+                        _ => target.max(range.start),
+                    });
+                    reached_eof = false;
+                    break;
+                }
+                Ok(VideoCommand::Pause) => pipeline.pacer.delay(hold(commands)),
+                _ => {}
+            }
+
+            if stream.index() != v_idx {
+                continue;
+            }
+            dec.send_packet(&packet)?;
+
+            while dec.receive_frame(&mut frame).is_ok() {
+                let secs = frame.timestamp().map(|ts| ts as f64 * time_base);
+                match place_frame(secs, &mut skip_until, range.end, &mut position) {
+                    Placed::Skip => continue,
+                    Placed::LoopEnd => {
+                        seek_target = Some(range.start);
+                        reached_eof = false;
+                        reached_loop_end = true;
+                        break 'packets;
+                    }
+                    Placed::Show => {}
+                }
+                if !pipeline.push(&frame, render_time())? {
+                    finished_flag.store(true, Ordering::Relaxed);
+                    return Ok(()); // UI gone
+                }
+            }
+        }
+
+        if reached_eof {
+            // Flush the decoder's last frames, held to the same checks;
+            // past the loop-out point, the pass is over anyway
+            dec.send_eof()?;
+            while dec.receive_frame(&mut frame).is_ok() {
+                let secs = frame.timestamp().map(|ts| ts as f64 * time_base);
+                match place_frame(secs, &mut skip_until, range.end, &mut position) {
+                    Placed::Skip => continue,
+                    Placed::LoopEnd => break,
+                    Placed::Show => {}
+                }
+                if !pipeline.push(&frame, render_time())? {
+                    finished_flag.store(true, Ordering::Relaxed);
+                    return Ok(()); // UI gone
+                }
+            }
+        }
+
+        // A full pass went by: replay it from memory from now on if it was
+        // recorded whole, otherwise record the next one
+        if reached_eof || reached_loop_end {
+            pipeline.out.mark_wrap();
+            if let Some(cache) = pipeline.cache.take_if(|c| c.is_complete()) {
+                return replay_cache(&cache, commands, &pipeline.out, finished_flag);
+            }
+            if let Some(ref mut cache) = pipeline.cache {
+                cache.restart();
+            }
+        }
+
+        if !reached_eof {
+            continue;
+        }
+
+        // Loop the video - seek back to the loop-in point. Streams that
+        // can't seek (live or chunked HTTP) are reopened from scratch instead.
+        if seek_input(&mut ictx, range.start).is_ok() {
+            dec = ff::codec::context::Context::from_parameters(
+                ictx.streams().best(ff::media::Type::Video).unwrap().parameters()
+            )?.decoder().video()?;
+            skip_until = (range.start > 0.0).then_some(range.start);
+        } else {
+            (ictx, v_idx, dec, time_base) = open_decoder(path)?;
+        }
+        pipeline.pacer.restart(time_base);
+        position = range.start;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_short_of_a_seek_are_skipped_until_one_lands() {
+        let (mut skip_until, mut position) = (Some(10.0), 0.0);
+        assert_eq!(place_frame(Some(9.5), &mut skip_until, None, &mut position), Placed::Skip);
+        assert_eq!((skip_until, position), (Some(10.0), 0.0));
+        assert_eq!(place_frame(Some(10.0), &mut skip_until, None, &mut position), Placed::Show);
+        assert_eq!((skip_until, position), (None, 10.0));
+    }
+
+    #[test]
+    fn frames_from_the_loop_out_point_end_the_pass() {
+        let (mut skip_until, mut position) = (None, 4.0);
+        assert_eq!(place_frame(Some(4.9), &mut skip_until, Some(5.0), &mut position), Placed::Show);
+        assert_eq!(place_frame(Some(5.0), &mut skip_until, Some(5.0), &mut position), Placed::LoopEnd);
+        assert_eq!(position, 4.9);
+    }
+
+    #[test]
+    fn a_frame_without_a_timestamp_is_shown_where_it_is() {
+        let (mut skip_until, mut position) = (Some(3.0), 1.0);
+        assert_eq!(place_frame(None, &mut skip_until, Some(5.0), &mut position), Placed::Show);
+        assert_eq!((skip_until, position), (None, 1.0));
+    }
+}
@@ -0,0 +1,118 @@
+//! Pure-Rust GIF playback, so animated backgrounds work in builds without
+//! ffmpeg. Every frame is decoded and converted up front, then played back
+//! from a frame cache.
+
+use anyhow::{anyhow, Context, Result};
+use crossbeam_channel::Receiver;
+use std::fs::File;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::cache::{replay_cache, FrameCache};
+use super::{pixels_to_ascii, Conversion, Delivery, LoopRange, VideoCommand};
+
+/// Delay used for frames that don't specify one (browsers do the same)
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+pub(super) fn is_gif(path: &str) -> bool {
+    path.to_lowercase().ends_with(".gif")
+}
+
+pub(super) fn gif_loop(
+    path: &str,
+    target: (u16, u16),
+    conv: &Conversion,
+    range: LoopRange,
+    commands: &Receiver<VideoCommand>,
+    out: Delivery,
+    finished_flag: &AtomicBool,
+) -> Result<()> {
+    let cache = load(path, target, conv, range)?;
+    replay_cache(&cache, commands, &out, finished_flag)
+}
+
+/// Decode, composite and convert every frame inside the loop range
+fn load(path: &str, (target_w, target_h): (u16, u16), conv: &Conversion, range: LoopRange) -> Result<FrameCache> {
+    let file = File::open(path).with_context(|| format!("open input {path}"))?;
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(file).with_context(|| format!("read GIF {path}"))?;
+
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    let (w, h) = (target_w as usize, target_h as usize);
+    let mut canvas = vec![0u8; width * height * 4];
+    let mut cache = FrameCache::new(range.start, Duration::ZERO);
+    let mut elapsed = 0.0;
+
+    while let Some(frame) = decoder.read_next_frame().context("decode GIF frame")? {
+        let before = (frame.dispose == gif::DisposalMethod::Previous).then(|| canvas.clone());
+        draw_frame(&mut canvas, width, frame, |dst, src| {
+            // Transparent pixels let the previous frame show through
+            if src[3] > 0 {
+                dst.copy_from_slice(src);
+            }
+        });
+
+        let shown_at = elapsed;
+        elapsed += match frame.delay {
+            0 => DEFAULT_DELAY.as_secs_f64(),
+            delay => delay as f64 / 100.0,
+        };
+
+        if shown_at >= range.start && range.end.is_none_or(|end| shown_at < end) {
+            let pixels = resample(&canvas, (width, height), (w, h));
+            let ascii = Arc::new(pixels_to_ascii(&pixels, w, h, conv));
+            if !cache.record(Some(shown_at), &ascii) {
+                return Err(anyhow!("{path} is too large to play at this terminal size"));
+            }
+        }
+
+        match (frame.dispose, before) {
+            (gif::DisposalMethod::Background, _) => {
+                draw_frame(&mut canvas, width, frame, |dst, _| dst.fill(0));
+            }
+            (gif::DisposalMethod::Previous, Some(before)) => canvas = before,
+            _ => {}
+        }
+    }
+
+    if cache.frames.is_empty() {
+        return Err(anyhow!("{path} has no frames inside the loop range"));
+    }
+    let end = range.end.map_or(elapsed, |end| end.min(elapsed));
+    cache.length = Duration::from_secs_f64((end - range.start).max(0.0));
+    Ok(cache)
+}
+
+/// Apply `op` to each canvas pixel covered by `frame`, paired with the
+/// frame's own RGBA pixel
+fn draw_frame(canvas: &mut [u8], width: usize, frame: &gif::Frame, op: impl Fn(&mut [u8], &[u8])) {
+    let (left, top) = (frame.left as usize, frame.top as usize);
+    let frame_w = frame.width as usize;
+    let height = canvas.len() / 4 / width.max(1);
+
+    for (i, src) in frame.buffer.chunks_exact(4).enumerate() {
+        let (x, y) = (left + i % frame_w, top + i / frame_w);
+        if x < width && y < height {
+            let at = (y * width + x) * 4;
+            op(&mut canvas[at..at + 4], src);
+        }
+    }
+}
+
+/// Nearest-neighbour resize of the RGBA canvas to the target size, with
+/// transparent areas shown as black
+fn resample(canvas: &[u8], (src_w, src_h): (usize, usize), (w, h): (usize, usize)) -> Vec<(u8, u8, u8)> {
+    let mut pixels = Vec::with_capacity(w * h);
+    for y in 0..h {
+        let sy = y * src_h / h.max(1);
+        for x in 0..w {
+            let sx = x * src_w / w.max(1);
+            let p = &canvas[(sy * src_w + sx) * 4..][..4];
+            let alpha = |c: u8| (c as u16 * p[3] as u16 / 255) as u8;
+            pixels.push((alpha(p[0]), alpha(p[1]), alpha(p[2])));
+        }
+    }
+    pixels
+}
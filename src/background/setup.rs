@@ -0,0 +1,168 @@
+use clap::Args;
+use std::time::Duration;
+
+use crate::background::{
+    self, AsciiMode, BackgroundOptions, BackgroundRegion, ColorMode, Dither, LoopRange, Palette,
+};
+use crate::config::{self, Config};
+use crate::screensaver::Screensaver;
+use crate::youtube;
+
+/// Background flags; each one overrides the matching config value
+#[derive(Args, Debug)]
+pub struct BackgroundArgs {
+    /// Background video file, URL (http, https, rtsp, YouTube via yt-dlp),
+    /// `webcam[:device]`, or `builtin` [config: background.video]
+    #[arg(long)]
+    video: Option<String>,
+
+    /// Video background opacity (0.0 - 1.0) [config: background.opacity]
+    #[arg(long)]
+    opacity: Option<f32>,
+
+    /// Background opacity behind text panels (0.0 - 1.0) [config: background.panel_opacity]
+    #[arg(long)]
+    panel_opacity: Option<f32>,
+
+    /// Background dithering (none, ordered, floyd) [config: background.dither]
+    #[arg(long)]
+    dither: Option<String>,
+
+    /// Background character ramp: classic, blocks, dots, simple, or custom light→dark chars
+    #[arg(long)]
+    palette: Option<String>,
+
+    /// Character selection: density, edges to trace outlines, or solid color cells [config: background.ascii_mode]
+    #[arg(long)]
+    ascii_mode: Option<String>,
+
+    /// Background color mode: full, mono, provider, or a #rrggbb tint [config: background.color]
+    #[arg(long)]
+    bg_color: Option<String>,
+
+    /// Loop-in point, e.g. 10 or 0:10 [config: background.loop_start]
+    #[arg(long)]
+    loop_start: Option<String>,
+
+    /// Loop-out point, e.g. 25 or 0:25 [config: background.loop_end]
+    #[arg(long)]
+    loop_end: Option<String>,
+
+    /// Where the background plays: full, top (banner), left (third), header [config: background.region]
+    #[arg(long)]
+    region: Option<String>,
+
+    /// Minutes idle before the screensaver starts, 0 to disable [config: screensaver.idle_minutes]
+    #[arg(long)]
+    screensaver: Option<u64>,
+}
+
+/// Everything the app needs to start the background
+pub struct BackgroundSetup {
+    /// Source handed to the decoder (YouTube links already resolved)
+    pub video: String,
+    pub options: BackgroundOptions,
+    pub region: BackgroundRegion,
+    pub screensaver: Screensaver,
+    /// Screen size in cells to scale the video for; the terminal's when None
+    pub size: Option<(u16, u16)>,
+}
+
+impl BackgroundArgs {
+    /// Play `video` unless --video names something else, whatever the
+    /// config says
+    pub fn default_video(&mut self, video: &str) {
+        self.video.get_or_insert_with(|| video.to_string());
+    }
+}
+
+impl BackgroundSetup {
+    /// Combine flags with the config file, warning about (and replacing)
+    /// any value that doesn't parse
+    pub fn resolve(args: BackgroundArgs, config: &Config) -> Self {
+        let dither_name = args.dither.unwrap_or(config.background.dither.clone());
+        let dither = Dither::parse(&dither_name).unwrap_or_else(|| {
+            eprintln!("Unknown dither mode: {}. Using none.", dither_name);
+            Dither::None
+        });
+
+        let palette_spec = args.palette.unwrap_or(config.background.palette.clone());
+        let palette = Palette::from_spec(&palette_spec).unwrap_or_else(|e| {
+            eprintln!("Invalid palette: {}. Using classic.", e);
+            Palette::default()
+        });
+
+        let mode_name = args.ascii_mode.unwrap_or(config.background.ascii_mode.clone());
+        let ascii_mode = AsciiMode::parse(&mode_name).unwrap_or_else(|| {
+            eprintln!("Unknown ASCII mode: {}. Using density.", mode_name);
+            AsciiMode::Density
+        });
+
+        let color_name = args.bg_color.unwrap_or(config.background.color.clone());
+        let color_mode = ColorMode::parse(&color_name).unwrap_or_else(|| {
+            eprintln!("Unknown background color mode: {}. Using full.", color_name);
+            ColorMode::Full
+        });
+
+        let mut video = args.video.unwrap_or(config.background.video.clone());
+
+        // A fresh install (e.g. `cargo install`) has no loading.mp4 next to it:
+        // use the built-in animation rather than greeting the user with an error
+        if video == config::DEFAULT_VIDEO && !std::path::Path::new(&video).exists() {
+            video = background::BUILTIN_SOURCE.to_string();
+        }
+        if youtube::is_youtube_url(&video) {
+            println!("Resolving YouTube stream with {}...", config.background.ytdlp);
+            match youtube::resolve_stream_url(&config.background.ytdlp, &video) {
+                Ok(url) => video = url,
+                Err(e) => eprintln!("{:#}", e),
+            }
+        }
+
+        let parse_point = |value: Option<String>| {
+            value.and_then(|v| {
+                let secs = background::parse_timestamp(&v);
+                if secs.is_none() {
+                    eprintln!("Invalid loop point: {}. Ignoring.", v);
+                }
+                secs
+            })
+        };
+        let loop_range = LoopRange {
+            start: parse_point(args.loop_start.or(config.background.loop_start.clone())).unwrap_or(0.0),
+            end: parse_point(args.loop_end.or(config.background.loop_end.clone())),
+        };
+
+        let options = BackgroundOptions {
+            opacity: args.opacity.unwrap_or(config.background.opacity),
+            panel_opacity: args.panel_opacity.unwrap_or(config.background.panel_opacity),
+            dither,
+            palette,
+            ascii_mode,
+            color_mode,
+            loop_range,
+            adaptive: config.background.adaptive,
+            crossfade: Duration::from_millis(config.background.crossfade_ms),
+            cache: config.background.cache_frames,
+        };
+
+        let region_name = args.region.unwrap_or(config.background.region.clone());
+        let region = BackgroundRegion::parse(&region_name).unwrap_or_else(|| {
+            eprintln!("Unknown background region: {}. Using full.", region_name);
+            BackgroundRegion::Full
+        });
+
+        let idle_minutes = args.screensaver.unwrap_or(config.screensaver.idle_minutes);
+        let screensaver = Screensaver::new(
+            (idle_minutes > 0).then(|| Duration::from_secs(idle_minutes * 60)),
+        );
+
+        Self {
+            video,
+            options,
+            region,
+            screensaver,
+            size: None,
+        }
+    }
+}
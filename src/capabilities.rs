@@ -0,0 +1,144 @@
+//! What each model can take: images, tool calls, streamed replies, a
+//! system prompt, a temperature, and how much context. Requests are
+//! shaped to fit (a system prompt a model has no place for goes ahead of
+//! the conversation, a temperature it won't take is left out) and the
+//! chat turns away what can't be sent, rather than having the provider
+//! refuse it.
+
+use crate::providers::{AIProvider, ChatSettings, Message};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Reads images along with the text
+    pub vision: bool,
+    /// Calls tools (functions) it's given
+    pub tools: bool,
+    /// Can stream its reply as it's written
+    pub streaming: bool,
+    /// Has a separate system prompt
+    pub system_prompt: bool,
+    /// Takes a temperature other than its own
+    pub temperature: bool,
+    /// Tokens it takes in at once, prompt and reply together; None if not
+    /// known
+    pub context_window: Option<u64>,
+}
+
+/// Every feature a provider's own model has, with its window
+const fn full(context_window: u64) -> Capabilities {
+    Capabilities {
+        vision: true,
+        tools: true,
+        streaming: true,
+        system_prompt: true,
+        temperature: true,
+        context_window: Some(context_window),
+    }
+}
+
+/// Reasoning models answer at their own temperature
+const fn reasoning(context_window: u64) -> Capabilities {
+    Capabilities {
+        temperature: false,
+        ..full(context_window)
+    }
+}
+
+const fn text_only(context_window: u64) -> Capabilities {
+    Capabilities {
+        vision: false,
+        ..full(context_window)
+    }
+}
+
+/// The models known to each provider, default first
+const KNOWN: [(&str, Capabilities); 14] = [
+    ("claude-sonnet-4-5-20250929", full(200_000)),
+    ("claude-opus-4-1-20250805", full(200_000)),
+    ("grok-4", full(256_000)),
+    ("grok-3", text_only(131_072)),
+    ("grok-3-mini", text_only(131_072)),
+    ("gpt-5", reasoning(400_000)),
+    ("gpt-5-mini", reasoning(400_000)),
+    ("gpt-5-nano", reasoning(400_000)),
+    ("gpt-4.1", full(1_047_576)),
+    ("o3", reasoning(200_000)),
+    ("gemini-2.5-pro", full(1_048_576)),
+    ("gemini-2.5-flash", full(1_048_576)),
+    // Gemma on the Gemini API has no system instruction and no tools
+    (
+        "gemma-3-27b-it",
+        Capabilities {
+            tools: false,
+            system_prompt: false,
+            ..full(131_072)
+        },
+    ),
+    (
+        crate::providers::mock::MODEL,
+        Capabilities {
+            vision: false,
+            tools: false,
+            streaming: false,
+            ..full(crate::providers::mock::CONTEXT_WINDOW)
+        },
+    ),
+];
+
+impl Capabilities {
+    /// A model's, as far as known. One not in the table is taken to
+    /// have what its provider's default model has, apart from the window.
+    pub fn of(provider: &AIProvider, model: &str) -> Self {
+        if let Some((_, known)) = KNOWN.iter().find(|(name, _)| *name == model) {
+            return *known;
+        }
+        let default = KNOWN
+            .iter()
+            .find(|(name, _)| *name == provider.model())
+            .map_or(full(0), |(_, known)| *known);
+        Self {
+            context_window: None,
+            ..default
+        }
+    }
+
+    /// Every model in the table, with its capabilities
+    pub fn known() -> &'static [(&'static str, Capabilities)] {
+        &KNOWN
+    }
+
+    /// The features by name, and whether the model has each
+    pub fn features(&self) -> [(&'static str, bool); 5] {
+        [
+            ("images", self.vision),
+            ("tools", self.tools),
+            ("streaming", self.streaming),
+            ("system prompt", self.system_prompt),
+            ("temperature", self.temperature),
+        ]
+    }
+
+    /// The features the model doesn't have
+    pub fn missing(&self) -> Vec<&'static str> {
+        self.features().into_iter().filter(|(_, has)| !has).map(|(name, _)| name).collect()
+    }
+
+    /// A request made to fit: without a system prompt of its own, the
+    /// model gets it ahead of the first message, and a temperature it
+    /// won't take is left out
+    pub fn adapt(&self, mut messages: Vec<Message>, settings: &ChatSettings) -> (Vec<Message>, ChatSettings) {
+        let mut settings = settings.clone();
+        if !self.system_prompt {
+            if let Some(system) = settings.system_prompt.take() {
+                match messages.iter_mut().find(|m| m.role == "user") {
+                    Some(first) => first.content = format!("{}\n\n{}", system, first.content),
+                    None => messages.insert(0, Message { role: "user".to_string(), content: system }),
+                }
+            }
+        }
+        if !self.temperature {
+            settings.temperature = None;
+        }
+        (messages, settings)
+    }
+}
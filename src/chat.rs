@@ -1,277 +1,2812 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::Local;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
-use std::collections::HashMap;
-use std::time::Instant;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 
-use crate::ai::{AIProvider, AIClient, Message};
-use crate::db::Database;
+use crate::providers::{AIProvider, ChatSettings, Message, Reply, Usage};
+use crate::accessibility;
+use crate::commands::{self, Command};
+use crate::capabilities::Capabilities;
+use crate::context::{Budget, ContextView};
+use crate::eval::{self, Contender, EvalView, Vote};
+use crate::config::{self, Config, EventsConfig, KeysConfig, MacroConfig, PasteConfig, RedactConfig, SoundsConfig, SpeechConfig};
+use crate::conversation::{auto_title, ChatMessage, Conversations, MessageRole, OpenConversation};
+#[cfg(unix)]
+use crate::control::{Control, Pending, Request, Response, Status};
+#[cfg(unix)]
+use crate::export::{self, ExportFormat};
+#[cfg(feature = "scripting")]
+use crate::hooks::{self, Hooks};
+use crate::store::{self, normalize_tag, Database, EvalSide, Rating};
+use crate::clipboard;
+use crate::events::{Event, EventKind, Events};
+use crate::git;
+use crate::leader::{Leader, Step};
+use crate::macros::Macros;
+use crate::mentions;
+use crate::paste;
+use crate::patch::{self, Decision, PatchView};
+use crate::problems::{ErrorLog, Problem, Source};
+use crate::outbox::{Outbox, Screened};
+use crate::ratelimit::Queued;
+use crate::record::Recorder;
+use crate::redact::Redactor;
+use crate::replay::Replay;
+use crate::search::{tag_list, SearchView};
+use crate::sessions::{self, Outcome, SessionManager};
+use crate::share::{FromHost, Guest, Host, Inbound, SharedMessage, MAX_WRONG_CODES};
+use crate::snippets::SnippetPicker;
+#[cfg(feature = "audio")]
+use crate::sounds::{Cue, Sounds};
+use crate::speech::Speaker;
+use crate::stats::StatsView;
+use crate::templates::Filling;
+use crate::transcript::Transcript;
+use crate::update::Version;
+use crate::variations::{self, VariationsView};
 
-#[derive(Debug, Clone)]
-pub enum MessageRole {
-    User,
-    Assistant,
-}
+/// How long a footer flash stays visible
+const FLASH_DURATION: Duration = Duration::from_secs(2);
+
+/// Longest note `/note` keeps on a reply
+const NOTE_LENGTH: usize = 200;
+
+/// Columns ←/→ scroll wide tables by
+const TABLE_STEP: usize = 8;
+
+/// What `/diff` asks when no question is given
+const REVIEW_PROMPT: &str = "Review this diff. Point out bugs, risky changes and anything unclear, most important first.";
 
-#[derive(Debug, Clone)]
-pub struct ChatMessage {
-    pub role: MessageRole,
-    pub content: String,
-    #[allow(dead_code)]
-    pub timestamp: Instant,
-    pub is_system: bool,
+/// What `/commitmsg` asks for
+const COMMIT_MESSAGE_PROMPT: &str = "Write a git commit message for this staged diff. Reply with the message \
+only: a summary line of at most 72 characters in the imperative mood, then, if the change needs \
+explaining, a blank line and a short body wrapped at 72 characters.";
+
+/// Flashed when a guest in a shared session tries what only the host can
+const HOST_ONLY: &str = "Only the host can do that in a shared session";
+
+/// Key hints shown in the footer
+#[cfg(feature = "video")]
+const FOOTER_HINTS: &str =
+    "F1 Help | F2 Switch AI | F3/F4 Opacity | F5 Mute | F6 Chats | Ctrl+L New | Ctrl+C Exit";
+#[cfg(not(feature = "video"))]
+const FOOTER_HINTS: &str = "F1 Help | F2 Switch AI | F6 Chats | Ctrl+L New | Ctrl+C Exit";
+
+/// Popup listing a provider's saved conversations
+struct Switcher {
+    conversations: Vec<store::Conversation>,
+    state: ListState,
 }
 
 pub struct ChatInterface {
     provider: AIProvider,
-    ai_client: AIClient,
-    // Store messages per provider
-    messages_per_provider: HashMap<String, Vec<ChatMessage>>,
+    /// The conversation open with each provider, and where they're saved
+    conversations: Conversations,
+    /// Sends them, and hands back the replies
+    outbox: Outbox,
     input_buffer: String,
+    /// Paths that could finish the `@mention` at the end of the input
+    completions: Vec<String>,
     scroll_offset: usize,
+    /// Columns wide tables are scrolled sideways
+    table_scroll: usize,
     is_streaming: bool,
     show_help: bool,
-    response_rx: mpsc::UnboundedReceiver<Result<String>>,
-    response_tx: mpsc::UnboundedSender<Result<String>>,
-    db: Option<Database>,
+    /// Dismissible banner for things worth knowing outside the
+    /// conversation itself
+    notice: Option<String>,
+    /// Errors the chat carried on after, with the overlay that shows them
+    errors: ErrorLog,
+    switcher: Option<Switcher>,
+    /// Full-screen session manager, drawn over everything else
+    sessions: Option<SessionManager>,
+    /// Full-screen search, drawn over everything else
+    search: Option<SearchView>,
+    /// Snippet picker, when open
+    snippets: Option<SnippetPicker>,
+    /// Usage dashboard, drawn over everything else
+    stats: Option<StatsView>,
+    /// The context gauge, when open
+    context: Option<ContextView>,
+    /// The two models `/ab` put up against each other, while it's on
+    ab: Option<[Contender; 2]>,
+    /// An A/B round, blinded until it's voted on
+    eval: Option<EvalView>,
+    /// A/B rounds started, so late answers to a skipped one are dropped
+    eval_rounds: u64,
+    /// Answers to the A/B round, by round and side
+    eval_rx: mpsc::UnboundedReceiver<(u64, usize, Result<Reply>)>,
+    eval_tx: mpsc::UnboundedSender<(u64, usize, Result<Reply>)>,
+    /// Answers to `/vary`, to pick one from
+    variations: Option<VariationsView>,
+    /// Times `/vary` was used, so late answers to a cancelled one are
+    /// dropped
+    variation_rounds: u64,
+    /// Answers to `/vary`, by round and temperature
+    variations_rx: mpsc::UnboundedReceiver<(u64, usize, Result<Reply>)>,
+    variations_tx: mpsc::UnboundedSender<(u64, usize, Result<Reply>)>,
+    /// A conversation being played back in place of the open one
+    replay: Option<Replay>,
+    /// The messages panel's text, laid out as the last frame drew it
+    transcript: RefCell<Transcript>,
+    /// Short-lived status text shown in place of the footer
+    flash: Option<(String, Instant)>,
+    /// The launch check for a newer release, until it answers
+    newer_release: Option<oneshot::Receiver<Version>>,
+    /// The launch's automatic backup, until it's taken
+    backup: Option<oneshot::Receiver<Result<Option<PathBuf>>>>,
+    /// `[paste]`, where `/share` uploads to
+    paste: PasteConfig,
+    /// A `/share` upload, until it answers with the link
+    sharing: Option<oneshot::Receiver<Result<String>>>,
+    /// `display.accessible`: high contrast, replays a message at a time,
+    /// and flashes that stay until the next, with more of them
+    accessible: bool,
+    /// `[personas]` from the config, by name
+    personas: BTreeMap<String, String>,
+    /// `[templates]` from the config, by name
+    templates: BTreeMap<String, String>,
+    /// The template `/t` is asking for placeholders of
+    filling: Option<Filling>,
+    /// `[macros]`, and any recording or playing
+    macros: Macros,
+    /// `/macro save` or `/macro delete` changed `[macros]`, to be saved
+    macros_changed: bool,
+    /// The leader key, and what's been typed after it
+    leader: Leader,
+    /// Guests' connections, when hosting a shared session
+    hosting: Option<Host>,
+    /// The provider and conversation guests were last sent, and how many
+    /// of its messages
+    shared: Option<((String, Option<i64>), usize)>,
+    /// Whether guests were last told a reply is on its way
+    shared_waiting: Option<bool>,
+    /// Guests' messages held until the reply before them is in, with who
+    /// sent them
+    guest_messages: VecDeque<(String, String)>,
+    /// The host's connection, when joined to a shared session
+    joined: Option<Guest>,
+    /// Requests from `animated-cli control`, when listening for them
+    #[cfg(unix)]
+    control: Option<Control>,
+    /// Control requests answered with the next reply
+    #[cfg(unix)]
+    control_waiting: Vec<oneshot::Sender<Response>>,
+    /// Named profile in use; None is the default one
+    profile: Option<String>,
+    /// The profile's API keys, by variable name
+    api_keys: BTreeMap<String, String>,
+    /// Profile asked for with `/profile`, for the app to load
+    profile_request: Option<Option<String>>,
+    /// Transcript file from `--record`
+    recorder: Option<Recorder>,
+    /// Where `[events]` go; None when nowhere
+    events: Option<Events>,
+    /// The profile's hooks script, if it has one
+    #[cfg(feature = "scripting")]
+    hooks: Option<Hooks>,
+    /// The reply on its way is a commit message for `/commitmsg`
+    awaiting_commit_message: bool,
+    /// A commit message from `/commitmsg`, offered to copy or commit with
+    commit_offer: Option<String>,
+    /// A reply's patch from `/apply`, waiting to be applied or not
+    patch: Option<PatchView>,
+    /// `[speech]` from the config
+    speech: SpeechConfig,
+    /// Reads replies aloud; None while speech is off
+    speaker: Option<Speaker>,
+    /// `[sounds]` from the config
+    #[cfg(feature = "audio")]
+    sound_config: SoundsConfig,
+    /// Plays event cues; None while they're off
+    #[cfg(feature = "audio")]
+    sounds: Option<Sounds>,
 }
 
 impl ChatInterface {
-    pub fn new(provider: AIProvider) -> Self {
-        let ai_client = AIClient::new(provider.clone());
-        let (response_tx, response_rx) = mpsc::unbounded_channel();
-
-        // Initialize database
-        let db = match Database::new() {
-            Ok(db) => Some(db),
-            Err(_e) => None,
-        };
+    /// `db` is None when the database couldn't be opened; the chat still
+    /// works but nothing is saved
+    pub fn new(provider: AIProvider, db: Option<Database>, config: &Config) -> Self {
+        let outbox = Outbox::new(provider.clone(), &config.rate_limits, &config.api_keys, config.profile.as_deref());
+        let (eval_tx, eval_rx) = mpsc::unbounded_channel();
+        let (variations_tx, variations_rx) = mpsc::unbounded_channel();
 
         let mut chat = Self {
-            provider: provider.clone(),
-            ai_client,
-            messages_per_provider: HashMap::new(),
+            provider,
+            conversations: Conversations::new(db),
+            outbox,
             input_buffer: String::new(),
+            completions: Vec::new(),
             scroll_offset: 0,
+            table_scroll: 0,
             is_streaming: false,
             show_help: false,
-            response_rx,
-            response_tx,
-            db,
+            notice: None,
+            errors: ErrorLog::default(),
+            switcher: None,
+            sessions: None,
+            search: None,
+            snippets: None,
+            stats: None,
+            context: None,
+            ab: None,
+            eval: None,
+            eval_rounds: 0,
+            eval_rx,
+            eval_tx,
+            variations: None,
+            variation_rounds: 0,
+            variations_rx,
+            variations_tx,
+            replay: None,
+            transcript: RefCell::default(),
+            flash: None,
+            newer_release: None,
+            backup: None,
+            paste: config.paste.clone(),
+            sharing: None,
+            accessible: config.display.accessible,
+            personas: config.personas.clone(),
+            templates: config.templates.clone(),
+            filling: None,
+            macros: Macros::new(&config.macros),
+            macros_changed: false,
+            leader: Leader::default(),
+            hosting: None,
+            shared: None,
+            shared_waiting: None,
+            guest_messages: VecDeque::new(),
+            joined: None,
+            #[cfg(unix)]
+            control: None,
+            #[cfg(unix)]
+            control_waiting: Vec::new(),
+            profile: config.profile.clone(),
+            api_keys: config.api_keys.clone(),
+            profile_request: None,
+            recorder: None,
+            events: None,
+            #[cfg(feature = "scripting")]
+            hooks: None,
+            awaiting_commit_message: false,
+            commit_offer: None,
+            patch: None,
+            speech: config.speech.clone(),
+            speaker: None,
+            #[cfg(feature = "audio")]
+            sound_config: config.sounds.clone(),
+            #[cfg(feature = "audio")]
+            sounds: None,
+        };
+
+        chat.conversations.load_latest(&chat.provider);
+        chat.apply_events(&config.events);
+        chat.apply_keys(&config.keys);
+        chat.apply_redact(&config.redact);
+        #[cfg(feature = "scripting")]
+        chat.load_hooks();
+        if config.speech.enabled {
+            chat.toggle_speech();
+        }
+        #[cfg(feature = "audio")]
+        if config.sounds.enabled {
+            chat.toggle_sounds();
+        }
+
+        chat
+    }
+
+    pub fn provider(&self) -> &AIProvider {
+        &self.provider
+    }
+
+    pub fn show_notice(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        tracing::warn!("{}", message);
+        self.notice = Some(message);
+    }
+
+    /// Show an error the chat can carry on after, with what might fix it
+    pub fn show_problem(&mut self, problem: Problem) {
+        #[cfg(feature = "audio")]
+        self.cue(Cue::Error);
+        self.announce(format!("Error: {}. Esc closes the details", problem.summary));
+        self.errors.push(problem);
+    }
+
+    /// Briefly show a status line in the footer (setting changes etc.)
+    pub fn flash(&mut self, message: impl Into<String>) {
+        self.flash = Some((message.into(), Instant::now()));
+    }
+
+    /// Say what just happened in the footer, in accessible mode only;
+    /// otherwise the screen shows it well enough
+    fn announce(&mut self, message: impl Into<String>) {
+        if self.accessible {
+            self.flash(message);
+        }
+    }
+
+    /// Accessible mode for this run whatever the config says, as
+    /// `--accessible` asks
+    pub fn set_accessible(&mut self, accessible: bool) {
+        self.accessible = accessible;
+    }
+
+    /// Close the leader's popup, the error overlay, stats, the context
+    /// gauge, an A/B round, variations, search, snippets, the session manager, conversation switcher,
+    /// commit message, patch, template being filled in or notice, whichever
+    /// is on top. Returns false if none was open.
+    pub fn close_popup(&mut self) -> bool {
+        if self.leader.close()
+            || self.errors.close()
+            || self.stats.take().is_some()
+            || self.context.take().is_some()
+            || self.eval.take().is_some()
+            || self.variations.take().is_some()
+            || self.search.take().is_some()
+            || self.snippets.take().is_some()
+            || self.replay.take().is_some()
+        {
+            return true;
+        }
+        if let (Some(sessions), Some(db)) = (self.sessions.as_mut(), self.conversations.db()) {
+            if !sessions.cancel(db) {
+                self.sessions = None;
+            }
+            return true;
+        }
+        self.switcher.take().is_some()
+            || self.commit_offer.take().is_some()
+            || self.patch.take().is_some()
+            || self.filling.take().is_some()
+            || self.notice.take().is_some()
+    }
+
+    /// The page before the first message shown, if there's one left to
+    /// read, keeping the same message in view
+    fn show_earlier(&mut self) {
+        match self.conversations.read_earlier(&self.provider) {
+            Ok(count) => self.scroll_offset += count,
+            Err(e) => self.show_problem(Problem::new(Source::Database, "Couldn't read the earlier messages", e)),
+        }
+    }
+
+    /// The conversation as it's sent, cut to fit the model's context
+    /// window. An error if its earlier messages couldn't be read.
+    fn request_history(&self) -> Result<Vec<Message>> {
+        let history = self.conversations.history(&self.provider)?;
+        Ok(self.outbox.fit(history, &self.request_settings()))
+    }
+
+    fn current_conversation_mut(&mut self) -> &mut OpenConversation {
+        self.conversations.open_mut(&self.provider)
+    }
+
+    fn get_current_messages(&self) -> &[ChatMessage] {
+        self.conversations
+            .get(&self.provider)
+            .map(|c| c.messages.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn get_current_messages_mut(&mut self) -> &mut Vec<ChatMessage> {
+        &mut self.current_conversation_mut().messages
+    }
+
+    /// Save a message to the current conversation, creating its database
+    /// row (and a title, if it has none) on the first one. Returns the
+    /// message's id, if it was saved, and when it was sent.
+    fn save_message(&mut self, role: &str, content: &str, usage: Option<&Usage>) -> (Option<i64>, i64) {
+        let (id, timestamp) = match self.conversations.save_message(&self.provider, role, content, usage) {
+            Ok(Some((id, timestamp))) => (Some(id), timestamp),
+            Ok(None) => (None, Local::now().timestamp()),
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Database, "Couldn't save the message", e));
+                (None, Local::now().timestamp())
+            }
+        };
+        self.record(role, content, usage, timestamp);
+        (id, timestamp)
+    }
+
+    /// Append every message from now on to the `--record` transcript
+    pub fn start_recording(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Mention a newer release in the footer if `check` finds one
+    pub fn watch_for_update(&mut self, check: oneshot::Receiver<Version>) {
+        self.newer_release = Some(check);
+    }
+
+    /// Say so if the automatic backup taken on another thread fails
+    pub fn watch_backup(&mut self, backup: oneshot::Receiver<Result<Option<PathBuf>>>) {
+        self.backup = Some(backup);
+    }
+
+    /// Share the conversation with whoever joins `host`, and say how
+    pub fn start_hosting(&mut self, host: Host) {
+        let addr = host.addr();
+        let reachable = if addr.ip().is_unspecified() {
+            format!("<this machine's address>:{}", addr.port())
+        } else {
+            addr.to_string()
+        };
+        self.add_system_message(&format!(
+            "Sharing this conversation on {}. Others join with: animated-cli --join {} --code {}",
+            addr,
+            reachable,
+            host.code()
+        ));
+        self.hosting = Some(host);
+    }
+
+    /// Follow a shared session through `guest`, starting from the
+    /// conversation the host sent on joining
+    pub fn join(&mut self, guest: Guest, conversation: FromHost) {
+        let message = format!("Joined the shared session at {} as {}", guest.addr(), guest.name());
+        self.joined = Some(guest);
+        if let FromHost::Conversation { provider, title, messages } = conversation {
+            self.show_shared(&provider, title, messages);
+        }
+        self.add_system_message(&message);
+    }
+
+    /// Take requests from `animated-cli control`
+    #[cfg(unix)]
+    pub fn start_control(&mut self, control: Control) {
+        self.control = Some(control);
+    }
+
+    /// A write that fails stops the recording rather than failing again on
+    /// every message
+    fn record(&mut self, role: &str, content: &str, usage: Option<&Usage>, timestamp: i64) {
+        let Some(ref mut recorder) = self.recorder else {
+            return;
+        };
+        let title = match self.conversations.get(&self.provider) {
+            Some(conversation) if !conversation.title.is_empty() => conversation.title.clone(),
+            _ => auto_title(content),
+        };
+        if let Err(e) = recorder.record(&self.provider, &title, role, content, usage, timestamp) {
+            self.recorder = None;
+            self.show_problem(Problem::new(Source::Recording, "Stopped recording", e));
+        }
+    }
+
+    /// Take up an edited `[events]`: its log file and webhooks
+    pub fn apply_events(&mut self, config: &EventsConfig) {
+        match Events::open(config) {
+            Ok(events) => self.events = events,
+            Err(e) => {
+                self.events = None;
+                self.show_problem(Problem::new(Source::Events, "Events aren't being sent", e));
+            }
+        }
+    }
+
+    /// Log and send an event about the open conversation
+    fn emit(&mut self, event: EventKind, content: &str, author: Option<&str>, usage: Option<&Usage>, timestamp: i64) {
+        let Some(ref mut events) = self.events else {
+            return;
+        };
+        let conversation = self.conversations.get(&self.provider);
+        let title = match conversation {
+            Some(conversation) if !conversation.title.is_empty() => conversation.title.clone(),
+            _ => auto_title(content),
+        };
+        let mut event = Event::new(event, &self.provider, conversation.and_then(|c| c.id), &title, content, timestamp)
+            .with_author(author);
+        if let Some(usage) = usage {
+            event = event.with_usage(usage);
+        }
+        if let Err(e) = events.emit(&event) {
+            self.show_problem(Problem::new(Source::Events, "Stopped writing the event log", e));
+        }
+    }
+
+    fn run_command(&mut self, command: Result<Command, String>) {
+        // A guest only adds to the host's conversation
+        let for_guests = matches!(
+            command,
+            Ok(Command::Errors
+                | Command::Template(_)
+                | Command::Diff(_)
+                | Command::Apply(_)
+                | Command::Snip(_)
+                | Command::Snippets(_)
+                | Command::Replay(_)
+                | Command::Context
+                | Command::Macro(_))
+                | Err(_)
+        );
+        if self.joined.is_some() && !for_guests {
+            self.flash(HOST_ONLY);
+            return;
+        }
+        match command {
+            Ok(Command::New(title)) => self.new_conversation(title),
+            Ok(Command::Chats) => self.open_switcher(),
+            Ok(Command::Sessions) => self.open_sessions(),
+            Ok(Command::Search(query)) => self.open_search(query.unwrap_or_default()),
+            Ok(Command::Stats) => self.open_stats(),
+            Ok(Command::Context) => self.open_context(),
+            Ok(Command::Replay(which)) => self.start_replay(which),
+            Ok(Command::Tag(tags)) => self.retag(tags, true),
+            Ok(Command::Untag(tags)) => self.retag(tags, false),
+            Ok(Command::Lock) => self.set_locked(true),
+            Ok(Command::Unlock) => self.set_locked(false),
+            Ok(Command::Star) => self.toggle_star(),
+            Ok(Command::Rate(arg)) => self.rate_reply(arg),
+            Ok(Command::Note(note)) => self.note_reply(note),
+            Ok(Command::Snip(arg)) => self.save_snippet(arg),
+            Ok(Command::Snippets(query)) => self.open_snippets(query.unwrap_or_default()),
+            Ok(Command::Set(setting)) => self.change_setting(setting),
+            Ok(Command::MoveTo(provider)) => self.move_to(provider),
+            Ok(Command::Ab(arg)) => self.ab_command(arg),
+            Ok(Command::Vary(arg)) => self.start_variations(arg),
+            Ok(Command::Share) => self.share_conversation(),
+            Ok(Command::Profile(name)) => self.request_profile(name),
+            Ok(Command::Errors) => {
+                if !self.errors.open_latest() {
+                    self.flash("No errors this session");
+                }
+            }
+            Ok(Command::Hooks) => self.reload_hooks(),
+            Ok(Command::Diff(question)) => self.send_diff(question),
+            Ok(Command::CommitMsg) => self.request_commit_message(),
+            Ok(Command::Apply(arg)) => self.open_patch(arg),
+            Ok(Command::Template(arg)) => self.start_template(arg),
+            Ok(Command::Macro(arg)) => self.run_macro_command(arg),
+            Err(e) => self.add_system_message(&e),
+        }
+    }
+
+    /// Add tags to the open conversation, or remove them. Without any,
+    /// show what it's tagged with.
+    fn retag(&mut self, tags: Option<String>, add: bool) {
+        let Some(tags) = tags else {
+            let current = self
+                .conversations
+                .get(&self.provider)
+                .map(|c| c.tags.as_slice())
+                .unwrap_or_default();
+            if current.is_empty() {
+                self.flash("No tags yet; /tag name adds one");
+            } else {
+                self.flash(format!("Tagged{}", tag_list(current)));
+            }
+            return;
+        };
+
+        let conversation = self.current_conversation_mut();
+        for tag in tags.split_whitespace().map(normalize_tag).filter(|tag| !tag.is_empty()) {
+            if !add {
+                conversation.tags.retain(|t| *t != tag);
+            } else if !conversation.tags.contains(&tag) {
+                conversation.tags.push(tag);
+            }
+        }
+        conversation.tags.sort();
+        let (id, tags) = (conversation.id, conversation.tags.clone());
+
+        if let (Some(id), Some(db)) = (id, self.conversations.db_mut()) {
+            if let Err(e) = db.set_tags(id, &tags) {
+                self.show_problem(Problem::new(Source::Database, "Couldn't save tags", e));
+                return;
+            }
+        }
+        if tags.is_empty() {
+            self.flash("No tags left");
+        } else {
+            self.flash(format!("Tagged{}", tag_list(&tags)));
+        }
+    }
+
+    /// Lock the open conversation so nothing more is sent to it and it
+    /// can't be deleted, or unlock it
+    fn set_locked(&mut self, locked: bool) {
+        let conversation = self.current_conversation_mut();
+        if conversation.locked == locked {
+            self.flash(if locked { "Already locked; /unlock to change it" } else { "It isn't locked" });
+            return;
+        }
+        let Some(id) = conversation.id else {
+            self.flash("Nothing to lock until the first message is saved");
+            return;
+        };
+        if locked && self.is_waiting() {
+            self.flash("Wait for the answer on its way first");
+            return;
+        }
+        if let Some(db) = self.conversations.db() {
+            if let Err(e) = db.set_locked(id, locked) {
+                self.show_problem(Problem::new(Source::Database, "Couldn't save the lock", e));
+                return;
+            }
+        }
+        self.current_conversation_mut().locked = locked;
+        self.flash(if locked {
+            "Locked: read-only, and kept from deletion, until /unlock"
+        } else {
+            "Unlocked"
+        });
+    }
+
+    /// Whether the open conversation is locked, saying so if it is
+    fn refuse_if_locked(&mut self) -> bool {
+        let locked = self.conversations.get(&self.provider).is_some_and(|c| c.locked);
+        if locked {
+            self.flash("This conversation is locked: /unlock it, or Ctrl+L for a new one");
+        }
+        locked
+    }
+
+    /// Star the last reply, or unstar it if it already is
+    fn toggle_star(&mut self) {
+        let last_reply = self
+            .get_current_messages()
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, m)| matches!(m.role, MessageRole::Assistant) && !m.is_system)
+            .and_then(|(index, m)| Some((index, m.id?, !m.starred)));
+        let (Some((index, id, starred)), Some(db)) = (last_reply, self.conversations.db()) else {
+            self.flash("No saved reply to star");
+            return;
+        };
+
+        if let Err(e) = db.set_starred(id, starred) {
+            self.show_problem(Problem::new(Source::Database, "Couldn't star the reply", e));
+            return;
+        }
+        self.get_current_messages_mut()[index].starred = starred;
+        self.flash(if starred { "Starred the last reply" } else { "Unstarred the last reply" });
+    }
+
+    /// The reply `back` from the last (1 being the last), as its place in
+    /// the open conversation and its database row, if it's saved
+    fn saved_reply(&self, back: usize) -> Option<(usize, i64)> {
+        self.get_current_messages()
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, m)| matches!(m.role, MessageRole::Assistant) && !m.is_system)
+            .nth(back - 1)
+            .and_then(|(index, m)| Some((index, m.id?)))
+    }
+
+    /// `/rate up|down|none [n]`: rate the last reply, or the nth from last,
+    /// or take its rating back
+    fn rate_reply(&mut self, arg: Option<String>) {
+        let usage = "Usage: /rate up|down|none [n], n counting replies back from the last";
+        let mut words = arg.as_deref().unwrap_or_default().split_whitespace();
+        let rating = match words.next() {
+            Some("none" | "clear") => None,
+            Some(word) if Rating::parse(word).is_some() => Rating::parse(word),
+            _ => {
+                self.add_system_message(usage);
+                return;
+            }
+        };
+        let back = match words.next().map(str::parse::<usize>) {
+            None => 1,
+            Some(Ok(n)) if n > 0 && words.next().is_none() => n,
+            _ => {
+                self.add_system_message(usage);
+                return;
+            }
+        };
+        let (Some((index, id)), Some(db)) = (self.saved_reply(back), self.conversations.db()) else {
+            self.flash(if back == 1 { "No saved reply to rate".to_string() } else { format!("There aren't {} saved replies", back) });
+            return;
+        };
+
+        if let Err(e) = db.set_rating(id, rating) {
+            self.show_problem(Problem::new(Source::Database, "Couldn't rate the reply", e));
+            return;
+        }
+        self.get_current_messages_mut()[index].rating = rating;
+        let which = if back == 1 { "the last reply".to_string() } else { format!("the reply {} back", back) };
+        self.flash(match rating {
+            Some(rating) => format!("{} Rated {}", rating.emoji(), which),
+            None => format!("Took back the rating on {}", which),
+        });
+    }
+
+    /// `/note [text]`: note something on the last reply, or remove its note
+    fn note_reply(&mut self, note: Option<String>) {
+        if note.as_ref().is_some_and(|note| note.chars().count() > NOTE_LENGTH) {
+            self.add_system_message(&format!("A note is {} characters at most", NOTE_LENGTH));
+            return;
+        }
+        let (Some((index, id)), Some(db)) = (self.saved_reply(1), self.conversations.db()) else {
+            self.flash("No saved reply to note");
+            return;
+        };
+
+        if let Err(e) = db.set_note(id, note.as_deref()) {
+            self.show_problem(Problem::new(Source::Database, "Couldn't save the note", e));
+            return;
+        }
+        let noted = note.is_some();
+        self.get_current_messages_mut()[index].note = note;
+        self.flash(if noted { "Noted on the last reply" } else { "Removed the last reply's note" });
+    }
+
+    /// `/snip name [n]`: keep the last reply, or the nth from last, in the
+    /// snippet library
+    fn save_snippet(&mut self, arg: Option<String>) {
+        let usage = "Usage: /snip name [n], n counting replies back from the last";
+        let Some(arg) = arg else {
+            self.add_system_message(usage);
+            return;
+        };
+        let mut words = arg.split_whitespace();
+        let name = words.next().unwrap_or_default().to_string();
+        let back = match words.next().map(str::parse::<usize>) {
+            None => 1,
+            Some(Ok(n)) if n > 0 && words.next().is_none() => n,
+            _ => {
+                self.add_system_message(usage);
+                return;
+            }
+        };
+
+        let reply = self
+            .get_current_messages()
+            .iter()
+            .rev()
+            .filter(|m| matches!(m.role, MessageRole::Assistant) && !m.is_system)
+            .nth(back - 1)
+            .map(|m| m.content.clone());
+        let Some(reply) = reply else {
+            self.flash(if back == 1 { "No reply to keep".to_string() } else { format!("There aren't {} replies", back) });
+            return;
+        };
+        let Some(db) = self.conversations.db() else {
+            self.flash("Conversation history is unavailable");
+            return;
+        };
+        match db.save_snippet(&name, &reply) {
+            Ok(true) => self.flash(format!("Replaced the snippet \"{}\"", name)),
+            Ok(false) => self.flash(format!("Kept as \"{}\"; Ctrl+S to use it", name)),
+            Err(e) => self.show_problem(Problem::new(Source::Database, "Couldn't save the snippet", e)),
+        }
+    }
+
+    /// Change one of the open conversation's settings (an empty value
+    /// goes back to the default), or list them all
+    fn change_setting(&mut self, setting: Option<String>) {
+        let Some(setting) = setting else {
+            let settings = self.current_conversation_mut().settings.clone();
+            let model = match settings.model {
+                Some(ref model) => model.clone(),
+                None => format!("{} (default)", self.provider.model()),
+            };
+            let mut parts = vec![format!("model {}", model)];
+            parts.extend(describe_settings(&ChatSettings { model: None, ..settings }));
+            let missing = self.model_capabilities().1.missing();
+            if !missing.is_empty() {
+                parts.push(format!("the model takes no {}", missing.join(", ")));
+            }
+            self.add_system_message(&format!("Settings: {}", parts.join(" · ")));
+            return;
+        };
+
+        let (name, value) = setting.split_once(char::is_whitespace).unwrap_or((&setting, ""));
+        let value = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        let mut settings = self.current_conversation_mut().settings.clone();
+        match name {
+            "model" => settings.model = value,
+            "system" => settings.system_prompt = value,
+            "temperature" | "temp" => {
+                let (model, capabilities) = self.model_capabilities();
+                settings.temperature = match value.as_deref().map(str::parse::<f64>) {
+                    None => None,
+                    Some(_) if !capabilities.temperature => {
+                        self.add_system_message(&format!("{} only answers at its own temperature", model));
+                        return;
+                    }
+                    Some(Ok(t)) if (0.0..=2.0).contains(&t) => Some(t),
+                    Some(_) => {
+                        self.add_system_message("Temperature must be a number from 0 to 2");
+                        return;
+                    }
+                }
+            }
+            "persona" => match value {
+                Some(ref persona) if !self.personas.contains_key(persona) => {
+                    let known: Vec<&str> = self.personas.keys().map(String::as_str).collect();
+                    self.add_system_message(&if known.is_empty() {
+                        "No personas yet; add them under [personas] in config.toml".to_string()
+                    } else {
+                        format!("Unknown persona: {} (have {})", persona, known.join(", "))
+                    });
+                    return;
+                }
+                _ => settings.persona = value,
+            },
+            _ => {
+                self.add_system_message(&format!(
+                    "Unknown setting: {} (expected model, system, temperature or persona)",
+                    name
+                ));
+                return;
+            }
+        }
+
+        let conversation = self.current_conversation_mut();
+        conversation.settings = settings.clone();
+        if let (Some(id), Some(db)) = (conversation.id, self.conversations.db()) {
+            if let Err(e) = db.set_conversation_settings(id, &settings) {
+                self.show_problem(Problem::new(Source::Database, "Couldn't save settings", e));
+                return;
+            }
+        }
+        // Said, not refused: they're kept for a model that takes them
+        let (model, capabilities) = self.model_capabilities();
+        let mut unused = vec![];
+        if settings.temperature.is_some() && !capabilities.temperature {
+            unused.push(format!("{} ignores the temperature", model));
+        }
+        if (settings.system_prompt.is_some() || settings.persona.is_some()) && !capabilities.system_prompt {
+            unused.push(format!("{} has no system prompt, so it goes ahead of the conversation", model));
+        }
+        if unused.is_empty() {
+            self.flash(format!("Set {} for this conversation", name));
+        } else {
+            self.add_system_message(&format!("Set {} for this conversation. {}.", name, unused.join("; ")));
+        }
+    }
+
+    /// The open conversation's model, and what it can take
+    fn model_capabilities(&self) -> (String, Capabilities) {
+        let model = self
+            .conversations
+            .get(&self.provider)
+            .and_then(|c| c.settings.model.clone())
+            .unwrap_or_else(|| self.provider.model().to_string());
+        let capabilities = Capabilities::of(&self.provider, &model);
+        (model, capabilities)
+    }
+
+    /// Take up `[keys]`. A leader key that can't be read leaves the
+    /// one there was.
+    pub fn apply_keys(&mut self, keys: &KeysConfig) {
+        match Leader::new(&keys.leader) {
+            Ok(leader) => self.leader = leader,
+            Err(e) => self.show_notice(format!("{:#}; the leader key stays as it was", e)),
+        }
+    }
+
+    /// Take up `[redact]`: what counts as a secret, and what's done with
+    /// a message that has one
+    pub fn apply_redact(&mut self, config: &RedactConfig) {
+        match Redactor::new(config) {
+            Ok(redactor) => self.outbox.set_redactor(redactor),
+            Err(e) => self.show_notice(format!("{:#}; secrets are looked for as before", e)),
+        }
+    }
+
+    /// Whether anything is open over the chat that takes keys itself
+    fn popup_is_open(&self) -> bool {
+        self.errors.is_open()
+            || self.stats.is_some()
+            || self.context.is_some()
+            || self.eval.is_some()
+            || self.variations.is_some()
+            || self.search.is_some()
+            || self.snippets.is_some()
+            || self.replay.is_some()
+            || self.sessions.is_some()
+            || self.switcher.is_some()
+            || self.commit_offer.is_some()
+            || self.patch.is_some()
+    }
+
+    /// Follow the leader key. Returns the key to handle as typed: `key`
+    /// itself if it's nothing to do with the leader, or the key its
+    /// sequence stands for; None if it's been taken care of.
+    pub fn lead(&mut self, key: KeyEvent) -> Option<KeyEvent> {
+        if !self.leader.is_open() {
+            if self.popup_is_open() || !self.leader.starts(&key, !self.input_buffer.is_empty()) {
+                return Some(key);
+            }
+            self.leader.open();
+            self.announce("Leader: a letter picks an action, Esc cancels");
+            return None;
+        }
+        match self.leader.follow(&key) {
+            Step::Wait => None,
+            Step::Key(key) => Some(key),
+            Step::Command(command) => {
+                if let Some(command) = Command::parse(command) {
+                    self.run_command(command);
+                }
+                None
+            }
+            Step::Unbound(keys) => {
+                self.flash(format!("{} does nothing", keys));
+                None
+            }
+        }
+    }
+
+    /// Settings as sent, with the persona's instructions filled in
+    fn request_settings(&self) -> ChatSettings {
+        self.conversations
+            .get(&self.provider)
+            .map(|c| c.settings.clone())
+            .unwrap_or_default()
+            .resolve_persona(&self.personas)
+    }
+
+    /// Start an empty conversation, keeping the current one's settings;
+    /// the previous one stays saved and can be reopened from the switcher
+    fn new_conversation(&mut self, title: Option<String>) {
+        let settings = self.current_conversation_mut().settings.clone();
+        *self.current_conversation_mut() = OpenConversation::new(title.unwrap_or_default(), settings, vec![]);
+        self.scroll_offset = 0;
+        self.flash("Started a new conversation");
+    }
+
+    fn open_switcher(&mut self) {
+        let Some(db) = self.conversations.db() else {
+            self.flash("Conversation history is unavailable");
+            return;
+        };
+        let conversations = db
+            .list_conversations(Some(self.provider.db_name()))
+            .map(|all| all.into_iter().filter(|c| !c.archived).collect::<Vec<_>>());
+        let conversations = match conversations {
+            Ok(conversations) if !conversations.is_empty() => conversations,
+            Ok(_) => {
+                self.flash(format!("No saved conversations with {}", self.provider.name()));
+                return;
+            }
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Database, "Couldn't list conversations", e));
+                return;
+            }
+        };
+
+        // Start on the conversation that's already open
+        let current = self.conversations.get(&self.provider).and_then(|c| c.id);
+        let selected = conversations.iter().position(|c| Some(c.id) == current).unwrap_or(0);
+        self.switcher = Some(Switcher {
+            conversations,
+            state: ListState::default().with_selected(Some(selected)),
+        });
+    }
+
+    /// Open the full-screen session manager
+    pub fn open_sessions(&mut self) {
+        let Some(db) = self.conversations.db() else {
+            self.flash("Conversation history is unavailable");
+            return;
+        };
+        match SessionManager::new(db) {
+            Ok(manager) => {
+                self.switcher = None;
+                self.sessions = Some(manager);
+            }
+            Err(e) => self.show_problem(Problem::new(Source::Database, "Couldn't list conversations", e)),
+        }
+    }
+
+    fn open_search(&mut self, query: String) {
+        let Some(db) = self.conversations.db() else {
+            self.flash("Conversation history is unavailable");
+            return;
+        };
+        self.search = Some(SearchView::new(query, db));
+    }
+
+    fn open_snippets(&mut self, query: String) {
+        let Some(db) = self.conversations.db() else {
+            self.flash("Conversation history is unavailable");
+            return;
+        };
+        self.snippets = Some(SnippetPicker::new(query, db));
+    }
+
+    fn open_stats(&mut self) {
+        let Some(db) = self.conversations.db() else {
+            self.flash("Conversation history is unavailable");
+            return;
+        };
+        self.stats = Some(StatsView::new(db));
+    }
+
+    /// Show what the next request would send, part by part, against the
+    /// model's context window
+    fn open_context(&mut self) {
+        let settings = self.request_settings();
+        let (model, capabilities) = self.model_capabilities();
+        let history = match self.request_history() {
+            Ok(history) => history,
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Database, "Couldn't measure the conversation", e));
+                return;
+            }
+        };
+        let history: Vec<&str> = history.iter().map(|m| m.content.as_str()).collect();
+        let dir = std::env::current_dir().unwrap_or_default();
+        let budget = Budget::measure(
+            &model,
+            capabilities.context_window,
+            settings.system_prompt.as_deref(),
+            &history,
+            &self.input_buffer,
+            &dir,
+        );
+        self.context = Some(ContextView::new(budget));
+    }
+
+    /// `/replay`: play back the open conversation, or a saved one
+    fn start_replay(&mut self, which: Option<String>) {
+        if self.is_streaming {
+            self.flash("Wait for the response before replaying");
+            return;
+        }
+        if let Some(which) = which {
+            if let Err(e) = self.replay_saved(&which, 1.0) {
+                self.add_system_message(&format!("{:#}", e));
+            }
+            return;
+        }
+        let conversation = match self.conversations.read_open(&self.provider) {
+            Ok(conversation) => conversation,
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Database, "Couldn't read the earlier messages to replay", e));
+                return;
+            }
+        };
+        match Replay::new(self.provider.clone(), conversation.title, conversation.messages) {
+            Some(replay) => self.play(replay),
+            None => self.flash("Nothing to replay yet"),
+        }
+    }
+
+    /// Play back a saved conversation, found as `--session` finds one, at
+    /// `speed` times normal
+    pub fn replay_saved(&mut self, which: &str, speed: f64) -> Result<()> {
+        let db = self.conversations.db().context("Conversation history is unavailable")?;
+        let chosen = sessions::find(db, Some(which), None)?;
+        let provider = AIProvider::parse(&chosen.provider).unwrap_or_else(|| self.provider.clone());
+        let conversation = self.conversations.read_whole(&chosen)?;
+        let mut replay = Replay::new(provider, conversation.title, conversation.messages)
+            .with_context(|| format!("Nothing to replay in {}", display_title(&chosen.title)))?;
+        replay.set_speed(speed);
+        self.play(replay);
+        Ok(())
+    }
+
+    fn play(&mut self, mut replay: Replay) {
+        if self.accessible {
+            replay.whole_messages();
+        }
+        let (_, count) = replay.progress();
+        self.announce(format!("Replaying {}, {} messages. Space pauses, Esc stops", display_title(replay.title()), count));
+        self.replay = Some(replay);
+    }
+
+    /// A replay that has typed out its last message
+    pub fn replay_finished(&self) -> bool {
+        self.replay.as_ref().is_some_and(Replay::is_finished)
+    }
+
+    fn handle_replay_key(&mut self, key: KeyEvent) {
+        let Some(ref mut replay) = self.replay else {
+            return;
+        };
+        let before = replay_position(replay);
+        match key.code {
+            KeyCode::Char(' ') => replay.toggle_pause(),
+            KeyCode::Right | KeyCode::Tab => replay.skip(),
+            KeyCode::Char('+') | KeyCode::Char('=') => replay.faster(true),
+            KeyCode::Char('-') => replay.faster(false),
+            _ => {}
+        }
+        self.announce_replay(before);
+    }
+
+    /// Say which message the replay is on, or that it's over, if it got
+    /// there since it was at `before`
+    fn announce_replay(&mut self, before: (usize, bool)) {
+        let Some(ref replay) = self.replay else {
+            return;
+        };
+        let (number, count) = replay.progress();
+        let announcement = if replay.is_finished() && !before.1 {
+            "Replay finished. Esc goes back to the chat".to_string()
+        } else if number != before.0 {
+            let message = &replay.visible().0[number - 1];
+            let from = match message.role {
+                MessageRole::User => message.author.as_deref().unwrap_or("you"),
+                MessageRole::Assistant => replay.provider().name(),
+            };
+            format!("Message {} of {}, from {}", number, count, from)
+        } else {
+            return;
+        };
+        self.announce(announcement);
+    }
+
+    /// A replay playing, which will change the screen as it goes
+    pub fn is_replaying(&self) -> bool {
+        self.replay.as_ref().is_some_and(|replay| !replay.is_paused() && !replay.is_finished())
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) {
+        let (Some(search), Some(db)) = (self.search.as_mut(), self.conversations.db()) else {
+            return;
+        };
+        if let Some(hit) = search.handle_key(key, db) {
+            self.search = None;
+            self.sessions = None;
+            self.open_conversation(&hit.conversation, Some(hit.message_id));
+        }
+    }
+
+    /// A picked snippet goes at the end of whatever's been typed
+    fn handle_snippets_key(&mut self, key: KeyEvent) {
+        let (Some(snippets), Some(db)) = (self.snippets.as_mut(), self.conversations.db()) else {
+            return;
+        };
+        if let Some(content) = snippets.handle_key(key, db) {
+            self.snippets = None;
+            if !self.input_buffer.is_empty() && !self.input_buffer.ends_with(char::is_whitespace) {
+                self.input_buffer.push(' ');
+            }
+            self.input_buffer.push_str(&content);
+            self.complete_mention(false);
+        }
+    }
+
+    fn handle_sessions_key(&mut self, key: KeyEvent) {
+        let (Some(sessions), Some(db)) = (self.sessions.as_mut(), self.conversations.db_mut()) else {
+            return;
+        };
+        if key.code == KeyCode::F(7) {
+            self.sessions = None;
+            return;
+        }
+
+        match sessions.handle_key(key, db) {
+            Some(Outcome::Open(chosen)) => {
+                self.sessions = None;
+                self.open_conversation(&chosen, None);
+            }
+            Some(Outcome::Renamed { id, title }) => {
+                for conversation in self.conversations.saved_as(id) {
+                    conversation.title = title.clone();
+                }
+            }
+            Some(Outcome::Tagged { id, tags }) => {
+                for conversation in self.conversations.saved_as(id) {
+                    conversation.tags = tags.clone();
+                }
+            }
+            Some(Outcome::Locked { id, locked }) => {
+                for conversation in self.conversations.saved_as(id) {
+                    conversation.locked = locked;
+                }
+            }
+            // A trashed conversation that's still open starts over as a new one
+            Some(Outcome::Deleted(id)) => {
+                for conversation in self.conversations.saved_as(id) {
+                    *conversation = OpenConversation::default();
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Pick up a saved conversation where it left off, for `--continue` and
+    /// `--session`
+    pub fn resume(&mut self, chosen: &store::Conversation) {
+        self.open_conversation(chosen, None);
+        let current = self.current_conversation_mut();
+        if current.id == Some(chosen.id) {
+            self.scroll_offset = current.messages.len().saturating_sub(1);
+        }
+    }
+
+    /// Show a saved conversation, switching to its provider if needed and
+    /// scrolling to `focus` (a message id) if given
+    fn open_conversation(&mut self, chosen: &store::Conversation, focus: Option<i64>) {
+        if let Some(provider) = AIProvider::parse(&chosen.provider) {
+            if provider != self.provider {
+                self.set_provider(provider);
+            }
+        }
+
+        match self.conversations.open_saved(chosen, focus) {
+            Some(conversation) => {
+                self.scroll_offset = focus
+                    .and_then(|id| conversation.messages.iter().position(|m| m.id == Some(id)))
+                    .unwrap_or(0);
+                self.flash(format!("Opened \"{}\"", display_title(&conversation.title)));
+                *self.current_conversation_mut() = conversation;
+            }
+            None => self.flash("Couldn't open that conversation"),
+        }
+    }
+
+    /// Copy the open conversation to `target` and carry on there; the
+    /// original stays where it was. Replies from the old provider go along
+    /// as assistant turns.
+    fn move_to(&mut self, target: AIProvider) {
+        if target == self.provider {
+            self.flash(format!("Already talking to {}", target.name()));
+            return;
+        }
+        if self.is_streaming {
+            self.flash("Wait for the response before moving the conversation");
+            return;
+        }
+        let source = self.provider.clone();
+        let current = self.conversations.get(&source).cloned().unwrap_or_default();
+        if current.messages.iter().all(|m| m.is_system) {
+            self.flash("Nothing to move yet");
+            return;
+        }
+        let suffix = format!(" (from {})", source.name());
+
+        let moved = match current.id.filter(|_| self.conversations.db().is_some()) {
+            Some(id) => match self.conversations.copy_to(id, &target, &suffix) {
+                Ok(moved) => moved,
+                Err(e) => {
+                    self.show_problem(Problem::new(Source::Database, "Couldn't copy the conversation", e));
+                    return;
+                }
+            },
+            // Never saved, so there's nothing to copy in the database
+            None => Some(current.unsaved_copy(&suffix)),
+        };
+        let Some(moved) = moved else {
+            self.flash("Couldn't open the copy");
+            return;
+        };
+
+        self.set_provider(target);
+        self.scroll_offset = moved.messages.len().saturating_sub(1);
+        *self.current_conversation_mut() = moved;
+        self.flash(format!("Copied from {}; carry on with {}", source.name(), self.provider.name()));
+    }
+
+    fn set_provider(&mut self, provider: AIProvider) {
+        #[cfg(feature = "scripting")]
+        if let Some(hooks) = self.hooks.as_ref().filter(|_| provider != self.provider) {
+            if let Err(e) = hooks.on_provider_switch(&self.provider, &provider) {
+                self.show_problem(Problem::new(Source::Hooks, "on_provider_switch failed", e));
+            }
+        }
+
+        #[cfg(unix)]
+        self.answer_control_waiting(|| Response::Error {
+            message: "The provider was switched before the reply came".to_string(),
+        });
+
+        self.is_streaming = false;
+        self.awaiting_commit_message = false;
+        // A reply still on its way from the old provider is dropped
+        self.outbox.connect(provider.clone(), &self.api_keys, self.profile.as_deref());
+        self.provider = provider;
+        self.conversations.load_latest(&self.provider);
+        self.announce(format!("Now talking to {}", self.provider.name()));
+
+        // Reset scroll when switching providers
+        self.scroll_offset = 0;
+    }
+
+    /// Take up edited personas, templates, API keys and rate limits from a
+    /// reloaded config
+    pub fn apply_config(&mut self, config: &Config) {
+        self.personas = config.personas.clone();
+        self.templates = config.templates.clone();
+        self.macros.configure(&config.macros);
+        self.api_keys = config.api_keys.clone();
+        self.paste = config.paste.clone();
+        self.outbox.configure(&config.rate_limits, &self.api_keys, self.profile.as_deref());
+    }
+
+    /// Take up an edited `[speech]`: its engine, voices and whether it's on
+    pub fn apply_speech(&mut self, speech: &SpeechConfig) {
+        self.speech = speech.clone();
+        self.speaker = None;
+        if speech.enabled {
+            self.toggle_speech();
+        }
+    }
+
+    /// Turn reading replies aloud on or off. Returns whether it's now on.
+    pub fn toggle_speech(&mut self) -> bool {
+        // Dropping the speaker stops it mid-sentence
+        if self.speaker.take().is_some() {
+            self.flash("🔈 Replies won't be read aloud");
+            return false;
+        }
+        match Speaker::new(&self.speech) {
+            Ok(speaker) => {
+                self.flash(format!("🗣 Replies will be read aloud with {}", speaker.engine_name()));
+                self.speaker = Some(speaker);
+                true
+            }
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Speech, "Can't read replies aloud", e));
+                false
+            }
+        }
+    }
+
+    /// Read a reply aloud, if speech is on
+    fn speak(&mut self, text: &str) {
+        let Some(ref mut speaker) = self.speaker else {
+            return;
+        };
+        if let Err(e) = speaker.speak(text, &self.provider) {
+            self.show_problem(Problem::new(Source::Speech, "Couldn't read the reply aloud", e));
+        }
+    }
+
+    /// Take up an edited `[sounds]`
+    #[cfg(feature = "audio")]
+    pub fn apply_sounds(&mut self, sounds: &SoundsConfig) {
+        self.sound_config = sounds.clone();
+        self.sounds = None;
+        if sounds.enabled {
+            self.toggle_sounds();
+        }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn apply_sounds(&mut self, _sounds: &SoundsConfig) {}
+
+    /// Turn event cues on or off. Returns whether they're now on.
+    #[cfg(feature = "audio")]
+    pub fn toggle_sounds(&mut self) -> bool {
+        if self.sounds.take().is_some() {
+            self.flash("🔕 Sound cues off");
+            return false;
+        }
+        match Sounds::new(&self.sound_config) {
+            Ok(sounds) => {
+                self.sounds = Some(sounds);
+                self.flash("🔔 Sound cues on");
+                true
+            }
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Audio, "Can't play sound cues", e));
+                false
+            }
+        }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn toggle_sounds(&mut self) -> bool {
+        self.flash("Built without audio support");
+        false
+    }
+
+    #[cfg(feature = "audio")]
+    fn cue(&self, cue: Cue) {
+        if let Some(ref sounds) = self.sounds {
+            sounds.play(cue);
+        }
+    }
+
+    /// Ask the app to switch to another profile, or list them
+    fn request_profile(&mut self, name: Option<String>) {
+        let current = self.profile.as_deref().unwrap_or(config::DEFAULT_PROFILE);
+        let Some(name) = name else {
+            match config::list_profiles() {
+                Ok(profiles) => {
+                    let list: Vec<String> = profiles
+                        .into_iter()
+                        .map(|p| if p == current { format!("{} (in use)", p) } else { p })
+                        .collect();
+                    self.add_system_message(&format!("Profiles: {}", list.join(", ")));
+                }
+                Err(e) => self.show_problem(Problem::new(Source::Profile, "Couldn't list profiles", e)),
+            }
+            return;
+        };
+
+        let profile = match config::parse_profile(&name) {
+            Ok(profile) => profile,
+            Err(e) => {
+                self.add_system_message(&e.to_string());
+                return;
+            }
+        };
+        if profile == self.profile {
+            self.flash(format!("Already using the {} profile", current));
+        } else if self.is_streaming {
+            self.flash("Wait for the response before switching profiles");
+        } else if self.conversations.db().is_some_and(Database::is_in_memory) {
+            self.flash("Profiles can't be switched in an incognito session");
+        } else {
+            self.profile_request = Some(profile);
+        }
+    }
+
+    /// The profile `/profile` asked for, once
+    pub fn take_profile_request(&mut self) -> Option<Option<String>> {
+        self.profile_request.take()
+    }
+
+    /// Start or stop recording a macro (Ctrl+R)
+    pub fn toggle_macro_recording(&mut self) {
+        if !self.macros.is_recording() {
+            self.macros.start_recording();
+            self.flash("Recording a macro; Ctrl+R again stops");
+            return;
+        }
+        match self.macros.stop_recording() {
+            0 => self.flash("Nothing recorded"),
+            count => self.flash(format!(
+                "Recorded {} key{}: Ctrl+P plays them, /macro save name [key] keeps them",
+                count,
+                if count == 1 { "" } else { "s" }
+            )),
+        }
+    }
+
+    /// Add a key the chat acted on to the macro being recorded, if any
+    pub fn record_macro_key(&mut self, key: KeyEvent) {
+        self.macros.record(key);
+    }
+
+    /// Play the last recording (Ctrl+P)
+    pub fn play_last_macro(&mut self) {
+        if let Err(e) = self.macros.play_last() {
+            self.flash(format!("{:#}", e));
+        }
+    }
+
+    /// Play the macro bound to `key`. Returns whether there was one.
+    pub fn play_bound_macro(&mut self, key: &KeyEvent) -> bool {
+        let Some(name) = self.macros.bound_to(key) else {
+            return false;
+        };
+        self.play_macro(&name);
+        true
+    }
+
+    fn play_macro(&mut self, name: &str) {
+        match self.macros.play(name) {
+            Ok(()) => self.announce(format!("Playing the macro {}", name)),
+            Err(e) => self.flash(format!("{:#}", e)),
+        }
+    }
+
+    /// The next key of the macro playing, unless it's waiting on a reply
+    pub fn next_macro_key(&mut self) -> Option<KeyEvent> {
+        if self.is_streaming && self.macros.keys_left() > 0 {
+            return None;
+        }
+        self.macros.next_key()
+    }
+
+    /// Stop a macro that still has keys to play. Returns whether there
+    /// was one.
+    pub fn stop_macro(&mut self) -> bool {
+        let stopped = self.macros.stop();
+        if stopped {
+            self.flash("Macro stopped");
+        }
+        stopped
+    }
+
+    /// `[macros]` as `/macro` last changed them, once, for saving
+    pub fn take_changed_macros(&mut self) -> Option<BTreeMap<String, MacroConfig>> {
+        std::mem::take(&mut self.macros_changed).then(|| self.macros.saved().clone())
+    }
+
+    /// `/macro`: list the macros, or save, delete or play one
+    fn run_macro_command(&mut self, arg: Option<String>) {
+        let Some(arg) = arg else {
+            let lines = self.macros.list();
+            let message = if lines.is_empty() {
+                "No macros yet; Ctrl+R starts recording one".to_string()
+            } else {
+                format!("Macros:\n{}", lines.join("\n"))
+            };
+            self.add_system_message(&message);
+            return;
+        };
+        let words: Vec<&str> = arg.split_whitespace().collect();
+        match words.as_slice() {
+            ["save", name] | ["save", name, _] => {
+                if self.macros.is_recording() {
+                    self.flash("Stop recording with Ctrl+R first");
+                    return;
+                }
+                match self.macros.save(name, words.get(2).copied()) {
+                    Ok(replaced) => {
+                        self.macros_changed = true;
+                        let key = self.macros.saved().get(*name).and_then(|saved| saved.key.clone());
+                        self.flash(match (replaced, key) {
+                            (true, _) => format!("Replaced the macro {}", name),
+                            (false, Some(key)) => format!("Kept as {}; {} plays it", name, key),
+                            (false, None) => format!("Kept as {}; /macro {} plays it", name, name),
+                        });
+                    }
+                    Err(e) => self.flash(format!("{:#}", e)),
+                }
+            }
+            ["delete", name] => {
+                if self.macros.delete(name) {
+                    self.macros_changed = true;
+                    self.flash(format!("Deleted the macro {}", name));
+                } else {
+                    self.flash(format!("No macro called {}", name));
+                }
+            }
+            [name] if !["save", "delete"].contains(name) => self.play_macro(name),
+            _ => self.add_system_message("Usage: /macro [name], /macro save name [key] or /macro delete name"),
+        }
+    }
+
+    /// Start over on another profile's history, keys, personas and
+    /// templates
+    pub fn change_profile(&mut self, config: &Config, db: Option<Database>, provider: AIProvider) {
+        self.switcher = None;
+        self.sessions = None;
+        self.search = None;
+        self.snippets = None;
+        self.stats = None;
+        self.context = None;
+        self.ab = None;
+        self.eval = None;
+        self.variations = None;
+        self.patch = None;
+        self.filling = None;
+        self.conversations.reset(db);
+        self.personas = config.personas.clone();
+        self.templates = config.templates.clone();
+        self.macros = Macros::new(&config.macros);
+        self.apply_keys(&config.keys);
+        self.apply_redact(&config.redact);
+        self.profile = config.profile.clone();
+        self.api_keys = config.api_keys.clone();
+        self.paste = config.paste.clone();
+        self.apply_speech(&config.speech);
+        self.apply_sounds(&config.sounds);
+        self.apply_events(&config.events);
+        #[cfg(feature = "scripting")]
+        self.load_hooks();
+        self.set_provider(provider);
+        self.flash(format!(
+            "Switched to the {} profile",
+            self.profile.as_deref().unwrap_or(config::DEFAULT_PROFILE)
+        ));
+    }
+
+    fn handle_switcher_key(&mut self, key: KeyEvent) {
+        let Some(ref mut switcher) = self.switcher else {
+            return;
+        };
+        match key.code {
+            KeyCode::Up => switcher.state.select_previous(),
+            KeyCode::Down => {
+                let last = switcher.conversations.len().saturating_sub(1);
+                let next = switcher.state.selected().map_or(0, |i| (i + 1).min(last));
+                switcher.state.select(Some(next));
+            }
+            KeyCode::Enter => {
+                let chosen = switcher
+                    .state
+                    .selected()
+                    .and_then(|i| switcher.conversations.get(i))
+                    .cloned();
+                self.switcher = None;
+                if let Some(chosen) = chosen {
+                    self.open_conversation(&chosen, None);
+                }
+            }
+            KeyCode::F(6) => self.switcher = None,
+            _ => {}
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
+        if self.errors.is_open() {
+            self.errors.handle_key(key);
+            return Ok(());
+        }
+        if let (Some(stats), Some(db)) = (self.stats.as_mut(), self.conversations.db()) {
+            stats.handle_key(key, db);
+            return Ok(());
+        }
+        // Only Esc, which closes it
+        if self.context.is_some() {
+            return Ok(());
+        }
+        if self.eval.is_some() {
+            self.handle_eval_key(key);
+            return Ok(());
+        }
+        if self.variations.is_some() {
+            self.handle_variations_key(key);
+            return Ok(());
+        }
+        if self.search.is_some() {
+            self.handle_search_key(key);
+            return Ok(());
+        }
+        if self.snippets.is_some() {
+            self.handle_snippets_key(key);
+            return Ok(());
+        }
+        if self.replay.is_some() {
+            self.handle_replay_key(key);
+            return Ok(());
+        }
+        if self.sessions.is_some() {
+            self.handle_sessions_key(key);
+            return Ok(());
+        }
+        if self.switcher.is_some() {
+            self.handle_switcher_key(key);
+            return Ok(());
+        }
+        if self.commit_offer.is_some() {
+            self.handle_commit_key(key);
+            return Ok(());
+        }
+        if self.patch.is_some() {
+            self.handle_patch_key(key);
+            return Ok(());
+        }
+
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        let host_only = matches!(key.code, KeyCode::F(2) | KeyCode::F(6) | KeyCode::F(7))
+            || (control && key.code == KeyCode::Char('l'));
+        if host_only && self.joined.is_some() {
+            self.flash(HOST_ONLY);
+            return Ok(());
+        }
+
+        if control {
+            match key.code {
+                KeyCode::Char('l') => {
+                    // Start over without losing the current conversation
+                    if self.is_streaming {
+                        self.flash("Wait for the response before starting a new conversation");
+                    } else {
+                        self.new_conversation(None);
+                    }
+                }
+                KeyCode::Char('f') => {
+                    if self.is_streaming {
+                        self.flash("Wait for the response before searching");
+                    } else {
+                        self.open_search(String::new());
+                    }
+                }
+                KeyCode::Char('s') => self.open_snippets(String::new()),
+                KeyCode::Char('g') => self.open_context(),
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        match key.code {
+            KeyCode::F(1) => {
+                self.show_help = !self.show_help;
+            }
+            KeyCode::F(2) => {
+                // Cycle through providers
+                let next = match self.provider {
+                    AIProvider::Claude => AIProvider::Grok,
+                    AIProvider::Grok => AIProvider::OpenAI,
+                    AIProvider::OpenAI => AIProvider::Gemini,
+                    AIProvider::Gemini | AIProvider::Mock => AIProvider::Claude,
+                };
+                self.set_provider(next);
+
+                self.add_system_message(&format!("Switched to {}", self.provider.name()));
+            }
+            KeyCode::F(6) => {
+                if self.is_streaming {
+                    self.flash("Wait for the response before switching conversations");
+                } else {
+                    self.open_switcher();
+                }
+            }
+            KeyCode::F(7) => {
+                if self.is_streaming {
+                    self.flash("Wait for the response before managing sessions");
+                } else {
+                    self.open_sessions();
+                }
+            }
+            KeyCode::Char(c) => {
+                self.input_buffer.push(c);
+                self.complete_mention(false);
+            }
+            KeyCode::Backspace => {
+                self.input_buffer.pop();
+                self.complete_mention(false);
+            }
+            KeyCode::Tab => self.complete_mention(true),
+            KeyCode::Enter if !self.input_buffer.is_empty() && !self.is_streaming => {
+                let user_input = self.input_buffer.clone();
+                self.input_buffer.clear();
+                self.completions.clear();
+
+                // An answer for a template, even if it starts with /
+                if let Some(ref mut filling) = self.filling {
+                    filling.answer(user_input);
+                    self.send_template_if_filled();
+                    return Ok(());
+                }
+                if let Some(command) = Command::parse(&user_input) {
+                    self.run_command(command);
+                    return Ok(());
+                }
+                let user_input = commands::unescape(user_input);
+                let Some(message) = self.attach_mentions(user_input.clone()) else {
+                    return Ok(());
+                };
+                if let Some(contenders) = self.ab.clone() {
+                    let Some(message) = self.screen_secrets(message, &user_input) else {
+                        return Ok(());
+                    };
+                    self.start_eval(message, contenders);
+                    return Ok(());
+                }
+                self.submit(message, user_input);
+            }
+            KeyCode::Up => {
+                if self.scroll_offset == 0 {
+                    self.show_earlier();
+                }
+                self.scroll_offset = self.scroll_offset.saturating_sub(1);
+            }
+            KeyCode::Down => {
+                let msg_count = self.get_current_messages().len();
+                if self.scroll_offset < msg_count.saturating_sub(1) {
+                    self.scroll_offset += 1;
+                }
+            }
+            KeyCode::PageUp => {
+                if self.scroll_offset < 10 {
+                    self.show_earlier();
+                }
+                self.scroll_offset = self.scroll_offset.saturating_sub(10);
+            }
+            KeyCode::PageDown => {
+                let msg_count = self.get_current_messages().len();
+                self.scroll_offset = (self.scroll_offset + 10).min(msg_count.saturating_sub(1));
+            }
+            KeyCode::Left => self.table_scroll = self.table_scroll.saturating_sub(TABLE_STEP),
+            KeyCode::Right => {
+                let overflow = self.transcript.borrow().table_overflow();
+                self.table_scroll = (self.table_scroll + TABLE_STEP).min(overflow);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Send `message` as the user's: through the hooks, then saved, shown
+    /// and sent. What was `typed` goes back in the input box if a hook
+    /// fails. Returns whether it was sent. Joined to a shared session, it
+    /// goes to the host instead.
+    fn submit(&mut self, message: String, typed: String) -> bool {
+        if self.refuse_if_locked() {
+            self.input_buffer = typed;
+            return false;
+        }
+        let Some(message) = self.screen_secrets(message, &typed) else {
+            return false;
+        };
+        if let Some(ref guest) = self.joined {
+            // The host sends it back, with the reply, once it's sent it on
+            self.is_streaming = guest.send(message);
+            return self.is_streaming;
+        }
+        self.submit_as(None, message, typed)
+    }
+
+    /// Look for secrets in a message about to leave, and mask them or,
+    /// the first time it's sent, hold it back with a warning. None if it's
+    /// held back, with `typed` put back in the input box to send again.
+    fn screen_secrets(&mut self, message: String, typed: &str) -> Option<String> {
+        let known = paste::secrets(&self.api_keys, &self.paste);
+        match self.outbox.screen(message, &known) {
+            Screened::Send { message, masked: 0 } => Some(message),
+            Screened::Send { message, masked } => {
+                self.flash(format!("Masked {} secret{} before sending", masked, if masked == 1 { "" } else { "s" }));
+                Some(message)
+            }
+            Screened::Held { secrets } => {
+                self.add_system_message(&format!(
+                    "Not sent: it has {} in it. Enter sends it anyway; action = \"mask\" under [redact] blanks them out",
+                    secrets
+                ));
+                self.input_buffer = typed.to_string();
+                None
+            }
+        }
+    }
+
+    /// `submit` for a message from `author`, or the user when None
+    #[cfg_attr(not(feature = "scripting"), allow(unused_variables))]
+    fn submit_as(&mut self, author: Option<String>, message: String, typed: String) -> bool {
+        // The conversation it goes with, before anything is saved, so it
+        // isn't sent at all rather than sent without it
+        let mut history = match self.conversations.history(&self.provider) {
+            Ok(history) => history,
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Database, "Message not sent", e));
+                self.input_buffer = typed;
+                return false;
+            }
+        };
+        #[cfg(feature = "scripting")]
+        let Some(message) = self.hook_user_message(message, typed) else {
+            return false;
+        };
+
+        let (id, timestamp) = self.save_message("user", &message, None);
+        self.emit(EventKind::UserMessage, &message, author.as_deref(), None, timestamp);
+        let messages = self.get_current_messages_mut();
+        messages.push(ChatMessage {
+            id,
+            role: MessageRole::User,
+            content: message.clone(),
+            timestamp,
+            is_system: false,
+            starred: false,
+            rating: None,
+            note: None,
+            author,
+        });
+
+        self.is_streaming = true;
+        #[cfg(feature = "audio")]
+        self.cue(Cue::Sent);
+        self.announce(format!("Message sent to {}, waiting for the response", self.provider.name()));
+        history.push(Message { role: "user".to_string(), content: message });
+        self.outbox.send(history, self.request_settings());
+        true
+    }
+
+    /// `/share`: upload the open conversation as Markdown, cleaned of
+    /// attached files, notes and keys, where `[paste]` says
+    fn share_conversation(&mut self) {
+        if self.sharing.is_some() {
+            self.flash("Already sharing; wait for the link");
+            return;
+        }
+        let id = self.conversations.get(&self.provider).and_then(|c| c.id);
+        let (Some(id), Some(db)) = (id, self.conversations.db()) else {
+            self.flash("Nothing saved to share yet");
+            return;
+        };
+        let secrets = paste::secrets(&self.api_keys, &self.paste);
+        let (title, markdown) = match paste::document(db, id, &secrets) {
+            Ok(document) => document,
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Database, "Couldn't read the conversation to share", e));
+                return;
+            }
+        };
+        let (tx, rx) = oneshot::channel();
+        let config = self.paste.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(paste::upload(&config, &title, markdown).await);
+        });
+        self.sharing = Some(rx);
+        self.flash(match self.paste.service.as_str() {
+            "gist" => "Uploading a gist…".to_string(),
+            service => format!("Uploading to {}…", service),
+        });
+    }
+
+    /// Put a shared conversation's link on the clipboard, and say where
+    /// it is either way
+    fn shared(&mut self, result: Result<String>) {
+        let url = match result {
+            Ok(url) => url,
+            Err(e) => {
+                self.add_system_message(&format!("Not shared: {:#}", e));
+                return;
+            }
+        };
+        match clipboard::copy(&url) {
+            Ok(how) => {
+                self.add_system_message(&format!("Shared at {}", url));
+                self.flash(format!("Link copied with {}", how));
+            }
+            Err(e) => self.add_system_message(&format!("Shared at {} (couldn't copy the link: {:#})", url, e)),
+        }
+    }
+
+    /// `/ab model model` puts two models up against each other, `/ab off`
+    /// ends it and `/ab` alone shows the standings
+    fn ab_command(&mut self, arg: Option<String>) {
+        let Some(arg) = arg else {
+            let standings = match self.conversations.db().map(Database::eval_standings) {
+                Some(Ok(standings)) => standings,
+                Some(Err(e)) => {
+                    self.show_problem(Problem::new(Source::Database, "Couldn't read the A/B standings", e));
+                    return;
+                }
+                None => {
+                    self.flash("A/B standings need the database");
+                    return;
+                }
+            };
+            self.add_system_message(&eval::standings(&standings));
+            return;
+        };
+        let names: Vec<&str> = arg.split_whitespace().collect();
+        match names.as_slice() {
+            ["off"] => {
+                if self.ab.take().is_some() {
+                    self.flash("A/B evals ended; messages go to the conversation again");
+                } else {
+                    self.flash("A/B evals aren't on");
+                }
+            }
+            [first, second] => {
+                let contenders = [Contender::parse(first, &self.provider), Contender::parse(second, &self.provider)];
+                self.add_system_message(&format!(
+                    "A/B evals between {} and {}: each message goes to both, answered as Model A and Model B \
+                     in a random order, and a, b or t votes. /ab off ends it",
+                    contenders[0].label(),
+                    contenders[1].label()
+                ));
+                self.ab = Some(contenders);
+            }
+            _ => self.add_system_message("Usage: /ab model model, /ab off, or /ab for the standings"),
+        }
+    }
+
+    /// Send `prompt` on its own to both contenders, with the
+    /// conversation's other settings, and open the round blinded
+    fn start_eval(&mut self, prompt: String, contenders: [Contender; 2]) {
+        self.eval_rounds += 1;
+        let round = self.eval_rounds;
+        let settings = self.request_settings();
+        for (side, contender) in contenders.iter().enumerate() {
+            let client = self.outbox.client_for(&contender.provider, &self.api_keys, self.profile.as_deref());
+            let messages = vec![Message { role: "user".to_string(), content: prompt.clone() }];
+            let settings = ChatSettings { model: Some(contender.model.clone()), ..settings.clone() };
+            let tx = self.eval_tx.clone();
+            tokio::spawn(async move {
+                let result = client.send_message(messages, &settings).await;
+                let _ = tx.send((round, side, result));
+            });
+        }
+        self.eval = Some(EvalView::new(round, prompt, contenders));
+        self.announce("Sent to both models, waiting for their answers");
+    }
+
+    /// a, b or t votes on the A/B round once both answers are in, and
+    /// says which model was which
+    fn handle_eval_key(&mut self, key: KeyEvent) {
+        let Some(vote) = self.eval.as_mut().and_then(|eval| eval.handle_key(key)) else {
+            return;
+        };
+        let Some(eval) = self.eval.take() else {
+            return;
+        };
+        let Some(answers) = eval.answers() else {
+            return;
+        };
+        let winner = eval.winner(vote);
+        let sides = [0, 1].map(|side| EvalSide {
+            provider: eval.contenders()[side].provider.db_name().to_string(),
+            model: eval.contenders()[side].model.clone(),
+            answer: answers[side].to_string(),
+        });
+        if let Some(db) = self.conversations.db() {
+            if let Err(e) = db.record_eval(eval.prompt(), &sides, winner) {
+                self.show_problem(Problem::new(Source::Database, "Couldn't save the A/B vote", e));
+            }
+        }
+        let verdict = match vote {
+            Vote::A => "You picked Model A",
+            Vote::B => "You picked Model B",
+            Vote::Tie => "A tie",
+        };
+        self.add_system_message(&format!("{}. {}; /ab shows the standings", verdict, eval.reveal()));
+    }
+
+    /// `/vary [n]`: ask again for the last reply n times at once, each
+    /// at its own temperature, to pick one to add
+    fn start_variations(&mut self, arg: Option<String>) {
+        let count = match arg.as_deref().map(str::parse::<usize>) {
+            None => variations::DEFAULT_COUNT,
+            Some(Ok(n)) if (2..=variations::MAX_COUNT).contains(&n) => n,
+            _ => {
+                self.add_system_message(&format!("Usage: /vary [n], n from 2 to {} variations", variations::MAX_COUNT));
+                return;
+            }
+        };
+        if self.is_waiting() {
+            self.flash("Wait for the answer on its way first");
+            return;
+        }
+        if self.refuse_if_locked() {
+            return;
+        }
+        let (model, capabilities) = self.model_capabilities();
+        if !capabilities.temperature {
+            self.add_system_message(&format!("{} only answers at its own temperature", model));
+            return;
+        }
+        // Everything up to the question the last reply answered
+        let sent = match self.request_history() {
+            Ok(sent) => sent,
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Database, "Variations not asked for", e));
+                return;
+            }
+        };
+        let question = sent.iter().rposition(|m| m.role == "user");
+        let Some(question) = question.filter(|&question| question + 1 < sent.len()) else {
+            self.flash("No reply to vary yet");
+            return;
+        };
+        let messages = sent[..=question].to_vec();
+        let prompt = sent[question].content.clone();
+
+        self.variation_rounds += 1;
+        let round = self.variation_rounds;
+        let temperatures = variations::temperatures(count);
+        let settings = self.request_settings();
+        for (index, &temperature) in temperatures.iter().enumerate() {
+            let client = self.outbox.client();
+            let messages = messages.clone();
+            let settings = ChatSettings { temperature: Some(temperature), ..settings.clone() };
+            let tx = self.variations_tx.clone();
+            tokio::spawn(async move {
+                let result = client.send_message(messages, &settings).await;
+                let _ = tx.send((round, index, result));
+            });
+        }
+        self.variations = Some(VariationsView::new(round, prompt, &temperatures));
+        self.announce(format!("Asked for {} variations, waiting for them", count));
+    }
+
+    /// Enter adds the variation chosen to the conversation
+    fn handle_variations_key(&mut self, key: KeyEvent) {
+        let Some((temperature, reply)) = self.variations.as_mut().and_then(|v| v.handle_key(key)) else {
+            return;
+        };
+        self.variations = None;
+        #[cfg(feature = "scripting")]
+        let content = self.hook_response(reply.content);
+        #[cfg(not(feature = "scripting"))]
+        let content = reply.content;
+        self.add_reply(&content, &reply.usage);
+        self.flash(format!("Added the variation at temperature {}", temperature));
+    }
+
+    /// `/diff`: send what's staged, or else what's changed, with a
+    /// question about it
+    fn send_diff(&mut self, question: Option<String>) {
+        let diff = git::staged_diff().and_then(|staged| match staged.trim() {
+            "" => git::unstaged_diff(),
+            _ => Ok(staged),
+        });
+        let diff = match diff {
+            Ok(diff) if diff.trim().is_empty() => {
+                self.flash("No changes to send");
+                return;
+            }
+            Ok(diff) => diff,
+            Err(e) => {
+                self.add_system_message(&format!("{:#}", e));
+                return;
+            }
+        };
+        let typed = format!("/diff {}", question.as_deref().unwrap_or_default());
+        let question = question.unwrap_or_else(|| REVIEW_PROMPT.to_string());
+        self.submit(format!("{}\n\n```diff\n{}\n```", question, diff.trim_end()), typed.trim_end().to_string());
+    }
+
+    /// `/commitmsg`: ask for a commit message for what's staged, to offer
+    /// when the reply comes
+    fn request_commit_message(&mut self) {
+        let diff = match git::staged_diff() {
+            Ok(diff) if diff.trim().is_empty() => {
+                self.flash("Nothing is staged; git add the changes first");
+                return;
+            }
+            Ok(diff) => diff,
+            Err(e) => {
+                self.add_system_message(&format!("{:#}", e));
+                return;
+            }
+        };
+        let prompt = format!("{}\n\n```diff\n{}\n```", COMMIT_MESSAGE_PROMPT, diff.trim_end());
+        self.awaiting_commit_message = self.submit(prompt, "/commitmsg".to_string());
+    }
+
+    /// `/t name [text]`: start filling in a template, with `text` as its
+    /// first placeholder. Without a name, list them.
+    fn start_template(&mut self, arg: Option<String>) {
+        let known: Vec<&str> = self.templates.keys().map(String::as_str).collect();
+        let Some(arg) = arg else {
+            let message = if known.is_empty() {
+                "No templates yet; add them under [templates] in config.toml".to_string()
+            } else {
+                format!("Templates: {}", known.join(", "))
+            };
+            self.add_system_message(&message);
+            return;
+        };
+        let (name, text) = match arg.split_once(char::is_whitespace) {
+            Some((name, text)) => (name, Some(text.trim())),
+            None => (arg.as_str(), None),
+        };
+        let Some(template) = self.templates.get(name) else {
+            let message = if known.is_empty() {
+                format!("Unknown template: {} (add it under [templates] in config.toml)", name)
+            } else {
+                format!("Unknown template: {} (expected one of {})", name, known.join(", "))
+            };
+            self.add_system_message(&message);
+            return;
+        };
+
+        let mut filling = Filling::new(name, template);
+        if let Some(text) = text {
+            filling.answer(text.to_string());
+        }
+        self.filling = Some(filling);
+        self.send_template_if_filled();
+    }
+
+    /// Send the template being filled in once nothing's left to ask for
+    fn send_template_if_filled(&mut self) {
+        if self.filling.as_ref().is_some_and(|filling| filling.next().is_some()) {
+            return;
+        }
+        let Some(filling) = self.filling.take() else {
+            return;
+        };
+        // Answers can mention files too
+        let Some(message) = self.attach_mentions(filling.message()) else {
+            return;
+        };
+        self.submit(message, format!("/t {}", filling.name()));
+    }
+
+    /// c copies the offered commit message, a commits with it
+    fn handle_commit_key(&mut self, key: KeyEvent) {
+        let Some(message) = self.commit_offer.clone() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char('c') => {
+                self.commit_offer = None;
+                match clipboard::copy(&message) {
+                    Ok(how) => self.flash(format!("Commit message copied with {}", how)),
+                    Err(e) => self.add_system_message(&format!("Couldn't copy the commit message: {:#}", e)),
+                }
+            }
+            KeyCode::Char('a') => {
+                self.commit_offer = None;
+                match git::commit(&message) {
+                    Ok(summary) => self.add_system_message(&format!("Committed: {}", summary)),
+                    Err(e) => self.add_system_message(&format!("{:#}", e)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// `/apply [n]`: look over the diffs in the last reply, or the nth
+    /// from last, before `git apply` takes them
+    fn open_patch(&mut self, arg: Option<String>) {
+        let back = match arg.as_deref().map(str::parse::<usize>) {
+            None => 1,
+            Some(Ok(n)) if n > 0 => n,
+            _ => {
+                self.add_system_message("Usage: /apply [n], n counting replies back from the last");
+                return;
+            }
+        };
+        let reply = self
+            .get_current_messages()
+            .iter()
+            .rev()
+            .filter(|m| matches!(m.role, MessageRole::Assistant) && !m.is_system)
+            .nth(back - 1)
+            .map(|m| m.content.clone());
+        let Some(reply) = reply else {
+            self.flash(if back == 1 { "No reply to apply".to_string() } else { format!("There aren't {} replies", back) });
+            return;
+        };
+        let Some(patch) = patch::extract(&reply) else {
+            self.flash(if back == 1 { "No diff in the last reply".to_string() } else { format!("No diff in the reply {} back", back) });
+            return;
+        };
+        let check = git::check_patch(&patch);
+        self.patch = Some(PatchView::new(patch, check));
+    }
+
+    fn handle_patch_key(&mut self, key: KeyEvent) {
+        let Some(decision) = self.patch.as_mut().and_then(|patch| patch.handle_key(key)) else {
+            return;
+        };
+        let Some(patch) = self.patch.take() else {
+            return;
+        };
+        if decision == Decision::Cancel {
+            return;
+        }
+        match git::apply_patch(patch.patch()) {
+            Ok(()) => self.add_system_message(&format!("Applied the patch: {}", patch.summary())),
+            Err(e) => self.add_system_message(&format!("{:#}", e)),
+        }
+    }
+
+    /// Load the profile's hooks script, if it has one. Returns false, with
+    /// the error shown, if it didn't load.
+    #[cfg(feature = "scripting")]
+    fn load_hooks(&mut self) -> bool {
+        match Hooks::load(self.profile.as_deref()) {
+            Ok(hooks) => {
+                self.hooks = hooks;
+                true
+            }
+            Err(e) => {
+                self.hooks = None;
+                self.show_problem(Problem::new(Source::Hooks, "Hooks script not loaded", e));
+                false
+            }
+        }
+    }
+
+    /// `/hooks`: load the script again, after an edit, and say what it
+    /// hooks into
+    #[cfg(feature = "scripting")]
+    fn reload_hooks(&mut self) {
+        if !self.load_hooks() {
+            return;
+        }
+        let message = match self.hooks {
+            Some(ref hooks) if hooks.names().is_empty() => format!("{} defines no hooks", hooks.file().display()),
+            Some(ref hooks) => format!("Hooks from {}: {}", hooks.file().display(), hooks.names().join(", ")),
+            None => {
+                let path = Hooks::path(self.profile.as_deref())
+                    .map_or_else(|_| hooks::FILE_NAME.to_string(), |path| path.display().to_string());
+                format!("No hooks script; create {} to add one", path)
+            }
+        };
+        self.add_system_message(&message);
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn reload_hooks(&mut self) {
+        self.flash("Built without scripting support");
+    }
+
+    /// Pass an outgoing message, with any files attached, through
+    /// `on_user_message`. None if it shouldn't be sent: the hook emptied
+    /// it, or failed, in which case what was `typed` goes back in the
+    /// input box.
+    #[cfg(feature = "scripting")]
+    fn hook_user_message(&mut self, text: String, typed: String) -> Option<String> {
+        let Some(ref hooks) = self.hooks else {
+            return Some(text);
+        };
+        match hooks.on_user_message(&text, &self.provider) {
+            Ok(None) => Some(text),
+            Ok(Some(rewritten)) if rewritten.trim().is_empty() => {
+                self.flash("A hook held the message back");
+                None
+            }
+            Ok(Some(rewritten)) => Some(rewritten),
+            Err(e) => {
+                self.input_buffer = typed;
+                self.show_problem(Problem::new(Source::Hooks, "Message not sent", e));
+                None
+            }
+        }
+    }
+
+    /// Pass a reply through `on_response`. If the hook fails the reply is
+    /// kept as it came.
+    #[cfg(feature = "scripting")]
+    fn hook_response(&mut self, text: String) -> String {
+        let Some(ref hooks) = self.hooks else {
+            return text;
         };
+        match hooks.on_response(&text, &self.provider) {
+            Ok(rewritten) => rewritten.unwrap_or(text),
+            Err(e) => {
+                self.show_problem(Problem::new(Source::Hooks, "on_response failed; the reply is as it came", e));
+                text
+            }
+        }
+    }
 
-        // Load history from database for all providers
-        chat.load_all_histories();
+    /// Look up what could finish an `@mention` being typed, and with `fill`
+    /// (Tab) extend it as far as those agree
+    fn complete_mention(&mut self, fill: bool) {
+        let dir = std::env::current_dir().unwrap_or_default();
+        self.completions = mentions::completions(&self.input_buffer, &dir);
+        if !fill {
+            return;
+        }
+        if let Some(completed) = mentions::complete(&self.input_buffer, &self.completions) {
+            self.input_buffer = completed;
+            self.completions = mentions::completions(&self.input_buffer, &dir);
+        }
+    }
 
-        chat
+    /// The message with the files it `@mentions` attached. None if one
+    /// can't be, in which case the text goes back in the input box.
+    fn attach_mentions(&mut self, text: String) -> Option<String> {
+        let dir = std::env::current_dir().unwrap_or_default();
+        let (model, capabilities) = self.model_capabilities();
+        if let Some(image) = mentions::images(&text, &dir).first().filter(|_| !capabilities.vision) {
+            self.add_system_message(&format!(
+                "Not sent: {} can't read images like @{}; /set model picks one that can",
+                model, image
+            ));
+            self.input_buffer = text;
+            return None;
+        }
+        match mentions::attach(&text, &dir) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                self.add_system_message(&format!("Not sent: {:#}", e));
+                self.input_buffer = text;
+                None
+            }
+        }
     }
 
-    fn load_all_histories(&mut self) {
-        if let Some(ref db) = self.db {
-            let providers = [
-                AIProvider::Claude,
-                AIProvider::Grok,
-                AIProvider::OpenAI,
-                AIProvider::Gemini,
-            ];
-
-            for provider in &providers {
-                if let Ok(db_messages) = db.get_messages(provider.db_name()) {
-                    let mut messages = Vec::new();
-                    for db_msg in db_messages {
-                        let role = match db_msg.role.as_str() {
-                            "user" => MessageRole::User,
-                            _ => MessageRole::Assistant,
-                        };
-                        messages.push(ChatMessage {
-                            role,
-                            content: db_msg.content,
-                            timestamp: Instant::now(),
-                            is_system: false,
-                        });
+    /// Relay between a shared session's guests and the conversation: take
+    /// in who joined or left and what they sent, and send them what's
+    /// changed. Returns whether anything changed here.
+    fn serve_guests(&mut self) -> bool {
+        let events = match self.hosting {
+            Some(ref mut host) => host.events(),
+            None => return false,
+        };
+        let changed = !events.is_empty();
+        for event in events {
+            match event {
+                Inbound::Joined { id, name, outbox } => {
+                    let text = format!("{} joined", name);
+                    let conversation = self.shared_conversation();
+                    if let Some(ref mut host) = self.hosting {
+                        host.broadcast(FromHost::Notice { text: text.clone() });
+                        host.admit(id, name, outbox, conversation);
+                    }
+                    self.add_system_message(&text);
+                }
+                Inbound::Message { name, content } => self.guest_messages.push_back((name, content)),
+                Inbound::Left { id, name } => {
+                    let text = format!("{} left", name);
+                    if let Some(ref mut host) = self.hosting {
+                        host.remove(id);
+                        host.broadcast(FromHost::Notice { text: text.clone() });
                     }
-                    self.messages_per_provider.insert(provider.db_name().to_string(), messages);
+                    self.add_system_message(&text);
                 }
+                Inbound::Closed => self.add_system_message(&format!(
+                    "{} wrong join codes: nobody else can join this session. Those here stay; host again for a new code",
+                    MAX_WRONG_CODES
+                )),
             }
         }
-    }
 
-    fn get_current_messages(&self) -> Vec<ChatMessage> {
-        self.messages_per_provider
-            .get(self.provider.db_name())
-            .cloned()
-            .unwrap_or_default()
+        if !self.is_streaming {
+            if let Some((name, content)) = self.guest_messages.pop_front() {
+                if self.refuse_if_locked() {
+                    if let Some(ref mut host) = self.hosting {
+                        let text = format!("The conversation is locked, so {}'s message wasn't sent", name);
+                        host.broadcast(FromHost::Notice { text });
+                    }
+                } else {
+                    // A hook that fails leaves the host's input box as it is
+                    let typed = self.input_buffer.clone();
+                    self.submit_as(Some(name), content, typed);
+                }
+                // Even if it wasn't sent, so the guest stops waiting
+                self.shared_waiting = None;
+            }
+        }
+        self.sync_guests();
+        changed
     }
 
-    fn get_current_messages_mut(&mut self) -> &mut Vec<ChatMessage> {
-        self.messages_per_provider
-            .entry(self.provider.db_name().to_string())
-            .or_insert_with(Vec::new)
+    /// Send guests the conversation if it's another one than they have,
+    /// or else its new messages, and whether a reply is on its way
+    fn sync_guests(&mut self) {
+        let Some(ref mut host) = self.hosting else {
+            return;
+        };
+        let conversation = self.conversations.get(&self.provider);
+        let key = (self.provider.db_name().to_string(), conversation.and_then(|c| c.id));
+        let messages = conversation.map_or(&[][..], |c| c.messages.as_slice());
+        match self.shared {
+            Some((ref sent, count)) if *sent == key && count <= messages.len() => {
+                for message in messages[count..].iter().filter(|m| !m.is_system) {
+                    let message = shared_message(message, host.name());
+                    host.broadcast(FromHost::Message { message });
+                }
+            }
+            _ => host.broadcast(conversation_snapshot(&self.provider, conversation, host.name())),
+        }
+        self.shared = Some((key, messages.len()));
+        if self.shared_waiting != Some(self.is_streaming) {
+            host.broadcast(FromHost::Waiting { waiting: self.is_streaming });
+            self.shared_waiting = Some(self.is_streaming);
+        }
     }
 
-    pub fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
-        if key.modifiers.contains(KeyModifiers::CONTROL) {
-            match key.code {
-                KeyCode::Char('l') => {
-                    // Clear current provider's messages
-                    self.get_current_messages_mut().clear();
-                    self.scroll_offset = 0;
+    /// The open conversation as guests are sent it
+    fn shared_conversation(&self) -> FromHost {
+        let host = self.hosting.as_ref().map_or("host", |host| host.name());
+        conversation_snapshot(&self.provider, self.conversations.get(&self.provider), host)
+    }
 
-                    if let Some(ref db) = self.db {
-                        let _ = db.clear_history(self.provider.db_name());
+    /// Take in what the host of a joined session sent. Returns whether
+    /// anything changed.
+    fn follow_host(&mut self) -> bool {
+        let (events, closed) = match self.joined {
+            Some(ref mut guest) => guest.events(),
+            None => return false,
+        };
+        let changed = closed || !events.is_empty();
+        for event in events {
+            match event {
+                FromHost::Conversation { provider, title, messages } => self.show_shared(&provider, title, messages),
+                FromHost::Message { message } => {
+                    let reply = message.role == "assistant";
+                    let message = self.chat_message(message);
+                    let content = message.content.clone();
+                    let messages = self.get_current_messages_mut();
+                    messages.push(message);
+                    self.scroll_offset = messages.len().saturating_sub(1);
+                    if reply {
+                        #[cfg(unix)]
+                        self.answer_control_waiting(|| Response::Reply { content: content.clone() });
+                        self.announce(accessibility::reply_received(self.provider.name(), &content));
+                        self.speak(&content);
+                        #[cfg(feature = "audio")]
+                        self.cue(Cue::Reply);
                     }
                 }
-                _ => {}
+                FromHost::Waiting { waiting } => self.is_streaming = waiting,
+                FromHost::Notice { text } => self.add_system_message(&text),
+                FromHost::Rejected { .. } => {}
             }
-            return Ok(());
         }
+        if closed {
+            self.joined = None;
+            self.is_streaming = false;
+            self.add_system_message(&format!(
+                "The host ended the shared session; messages now go to {} directly",
+                self.provider.name()
+            ));
+        }
+        changed
+    }
 
-        match key.code {
-            KeyCode::F(1) => {
-                self.show_help = !self.show_help;
-            }
-            KeyCode::F(2) => {
-                // Drain any pending responses
-                while self.response_rx.try_recv().is_ok() {}
+    /// Show the conversation a host sent, switching to its provider
+    fn show_shared(&mut self, provider: &str, title: String, messages: Vec<SharedMessage>) {
+        if let Some(provider) = AIProvider::parse(provider).filter(|p| *p != self.provider) {
+            self.set_provider(provider);
+        }
+        let messages: Vec<ChatMessage> = messages.into_iter().map(|m| self.chat_message(m)).collect();
+        self.scroll_offset = messages.len().saturating_sub(1);
+        *self.current_conversation_mut() = OpenConversation::new(title, ChatSettings::default(), messages);
+    }
 
-                self.is_streaming = false;
+    /// A message from the host, with our own marked as ours
+    fn chat_message(&self, message: SharedMessage) -> ChatMessage {
+        let own = self.joined.as_ref().map(Guest::name);
+        ChatMessage {
+            id: None,
+            role: if message.role == "user" { MessageRole::User } else { MessageRole::Assistant },
+            content: message.content,
+            timestamp: message.timestamp,
+            is_system: false,
+            starred: false,
+            rating: None,
+            note: None,
+            author: message.author.filter(|author| Some(author.as_str()) != own),
+        }
+    }
 
-                // Cycle through providers
-                self.provider = match self.provider {
-                    AIProvider::Claude => AIProvider::Grok,
-                    AIProvider::Grok => AIProvider::OpenAI,
-                    AIProvider::OpenAI => AIProvider::Gemini,
-                    AIProvider::Gemini => AIProvider::Claude,
-                };
-                self.ai_client = AIClient::new(self.provider.clone());
+    /// Answer what `animated-cli control` asked since last time. Returns
+    /// whether anything changed.
+    #[cfg(unix)]
+    fn serve_control(&mut self) -> bool {
+        let requests = match self.control {
+            Some(ref mut control) => control.requests(),
+            None => return false,
+        };
+        let changed = !requests.is_empty();
+        for Pending { request, reply } in requests {
+            let response = match request {
+                Request::Send { text, wait } => match self.control_send(text) {
+                    Ok(()) if wait => {
+                        self.control_waiting.push(reply);
+                        continue;
+                    }
+                    Ok(()) => Response::Sent,
+                    Err(message) => Response::Error { message },
+                },
+                Request::Provider { name } => self.control_provider(&name),
+                Request::Export { path, format } => self.control_export(path, &format),
+                Request::Status => Response::Status(self.control_status()),
+            };
+            let _ = reply.send(response);
+        }
+        changed
+    }
+
+    /// Send `text` as if typed, with its `@mentions` attached
+    #[cfg(unix)]
+    fn control_send(&mut self, text: String) -> std::result::Result<(), String> {
+        if self.is_streaming {
+            return Err("Still waiting on the last reply".to_string());
+        }
+        if text.trim().is_empty() {
+            return Err("Nothing to send".to_string());
+        }
+        if self.conversations.get(&self.provider).is_some_and(|c| c.locked) {
+            return Err("The conversation is locked".to_string());
+        }
+        let dir = std::env::current_dir().unwrap_or_default();
+        let message = mentions::attach(&text, &dir).map_err(|e| format!("Not sent: {:#}", e))?;
+        // A hook that fails leaves the input box as it is
+        let typed = self.input_buffer.clone();
+        match self.submit(message, typed) {
+            true => Ok(()),
+            false if self.joined.is_some() => Err("The shared session has ended".to_string()),
+            false => Err("A hook, or secrets found in it, stopped the message".to_string()),
+        }
+    }
 
-                // Reset scroll when switching providers
-                self.scroll_offset = 0;
+    #[cfg(unix)]
+    fn control_provider(&mut self, name: &str) -> Response {
+        let Some(provider) = AIProvider::parse(name) else {
+            return Response::Error { message: format!("Unknown provider: {} (expected claude, grok, gpt or gemini)", name) };
+        };
+        if self.joined.is_some() {
+            return Response::Error { message: HOST_ONLY.to_string() };
+        }
+        if provider != self.provider {
+            self.set_provider(provider);
+            self.add_system_message(&format!("Switched to {}", self.provider.name()));
+        }
+        Response::Switched { provider: self.provider.name().to_string() }
+    }
 
-                self.add_system_message(&format!("Switched to {}", self.provider.name()));
-            }
-            KeyCode::Char(c) => {
-                self.input_buffer.push(c);
-            }
-            KeyCode::Backspace => {
-                self.input_buffer.pop();
-            }
-            KeyCode::Enter => {
-                if !self.input_buffer.is_empty() && !self.is_streaming {
-                    let user_input = self.input_buffer.clone();
-                    self.input_buffer.clear();
+    /// Write the open conversation, as saved, to `path`
+    #[cfg(unix)]
+    fn control_export(&self, path: PathBuf, format: &str) -> Response {
+        let Some(format) = ExportFormat::parse(format) else {
+            return Response::Error {
+                message: format!("Unknown export format: {} (expected json, jsonl, sharegpt, html, markdown, pdf or rated)", format),
+            };
+        };
+        let Some(db) = self.conversations.db() else {
+            return Response::Error { message: "Nothing is saved in this session to export".to_string() };
+        };
+        let Some(id) = self.conversations.get(&self.provider).and_then(|c| c.id) else {
+            return Response::Error { message: "Nothing is saved in this conversation yet".to_string() };
+        };
+        match export::to_file(db, &[id], format, &path) {
+            Ok(messages) => Response::Exported { path, messages },
+            Err(e) => Response::Error { message: format!("{:#}", e) },
+        }
+    }
 
-                    let messages = self.get_current_messages_mut();
-                    messages.push(ChatMessage {
-                        role: MessageRole::User,
-                        content: user_input.clone(),
-                        timestamp: Instant::now(),
-                        is_system: false,
-                    });
+    #[cfg(unix)]
+    fn control_status(&self) -> Status {
+        let conversation = self.conversations.get(&self.provider);
+        Status {
+            pid: std::process::id(),
+            profile: self.profile.as_deref().unwrap_or(config::DEFAULT_PROFILE).to_string(),
+            provider: self.provider.name().to_string(),
+            conversation_id: conversation.and_then(|c| c.id),
+            title: conversation.map(|c| c.title.clone()).unwrap_or_default(),
+            messages: conversation.map_or(0, |c| c.count_messages(self.conversations.db())),
+            waiting: self.is_streaming,
+        }
+    }
 
-                    if let Some(ref db) = self.db {
-                        let _ = db.save_message(self.provider.db_name(), "user", &user_input);
-                    }
+    /// Answer the control requests waiting on a reply
+    #[cfg(unix)]
+    fn answer_control_waiting(&mut self, response: impl Fn() -> Response) {
+        for reply in self.control_waiting.drain(..) {
+            let _ = reply.send(response());
+        }
+    }
+
+    /// Waiting on a reply, which will change the screen when it comes
+    pub fn is_waiting(&self) -> bool {
+        self.is_streaming
+            || self.eval.as_ref().is_some_and(EvalView::is_waiting)
+            || self.variations.as_ref().is_some_and(VariationsView::is_waiting)
+    }
 
-                    self.is_streaming = true;
-                    self.send_message(user_input);
+    /// Take in a reply if one came, and let a flash run out. Returns
+    /// whether anything on screen changed.
+    pub fn update(&mut self) -> Result<bool> {
+        let mut changed = false;
+        if !self.accessible && self.flash.as_ref().is_some_and(|(_, at)| at.elapsed() >= FLASH_DURATION) {
+            self.flash = None;
+            changed = true;
+        }
+        if let Some(ref mut check) = self.newer_release {
+            match check.try_recv() {
+                Ok(version) => {
+                    self.newer_release = None;
+                    self.flash(format!("MEGA-CLI {} is out; `animated-cli update` installs it", version));
+                    changed = true;
                 }
+                Err(oneshot::error::TryRecvError::Closed) => self.newer_release = None,
+                Err(oneshot::error::TryRecvError::Empty) => {}
             }
-            KeyCode::Up => {
-                self.scroll_offset = self.scroll_offset.saturating_sub(1);
-            }
-            KeyCode::Down => {
-                let msg_count = self.get_current_messages().len();
-                if self.scroll_offset < msg_count.saturating_sub(1) {
-                    self.scroll_offset += 1;
+        }
+        if let Some(ref mut backup) = self.backup {
+            match backup.try_recv() {
+                Ok(taken) => {
+                    self.backup = None;
+                    if let Err(e) = taken {
+                        self.show_problem(Problem::new(Source::Database, "Automatic backup failed", e));
+                        changed = true;
+                    }
                 }
+                Err(oneshot::error::TryRecvError::Closed) => self.backup = None,
+                Err(oneshot::error::TryRecvError::Empty) => {}
             }
-            KeyCode::PageUp => {
-                self.scroll_offset = self.scroll_offset.saturating_sub(10);
-            }
-            KeyCode::PageDown => {
-                let msg_count = self.get_current_messages().len();
-                self.scroll_offset = (self.scroll_offset + 10).min(msg_count.saturating_sub(1));
+        }
+        if let Some(ref mut sharing) = self.sharing {
+            match sharing.try_recv() {
+                Ok(result) => {
+                    self.sharing = None;
+                    self.shared(result);
+                    changed = true;
+                }
+                Err(oneshot::error::TryRecvError::Closed) => self.sharing = None,
+                Err(oneshot::error::TryRecvError::Empty) => {}
             }
-            _ => {}
+        }
+        if let Some(ref mut replay) = self.replay {
+            let before = replay_position(replay);
+            changed |= replay.advance();
+            self.announce_replay(before);
         }
 
-        Ok(())
-    }
-
-    fn send_message(&mut self, _content: String) {
-        let current_messages = self.get_current_messages();
-        let messages: Vec<Message> = current_messages
-            .iter()
-            .filter(|m| !m.is_system)
-            .map(|m| Message {
-                role: match m.role {
-                    MessageRole::User => "user".to_string(),
-                    MessageRole::Assistant => "assistant".to_string(),
-                },
-                content: m.content.clone(),
-            })
-            .collect();
-
-        let client = self.ai_client.clone();
-        let tx = self.response_tx.clone();
-        tokio::spawn(async move {
-            let result = client.send_message(messages).await;
-            let _ = tx.send(result);
-        });
-    }
+        if let Some(joined) = self.outbox.follow_queue() {
+            if let (true, Some(place)) = (joined, self.outbox.queued()) {
+                self.announce(format!("Queued for {}'s rate limit, {}", self.provider.name(), queue_status(place)));
+            }
+            changed = true;
+        }
 
-    pub fn update(&mut self) -> Result<()> {
-        if let Ok(result) = self.response_rx.try_recv() {
+        if let Some(result) = self.outbox.reply() {
+            changed = true;
             self.is_streaming = false;
             match result {
-                Ok(response) => {
-                    // Save to database first
-                    if let Some(ref db) = self.db {
-                        let _ = db.save_message(self.provider.db_name(), "assistant", &response);
+                Ok(Reply { content: response, usage }) => {
+                    #[cfg(feature = "scripting")]
+                    let response = self.hook_response(response);
+                    self.add_reply(&response, &usage);
+                    if std::mem::take(&mut self.awaiting_commit_message) {
+                        self.commit_offer = Some(commit_message(&response));
                     }
-
-                    // Then add to messages
-                    let messages = self.get_current_messages_mut();
-                    messages.push(ChatMessage {
-                        role: MessageRole::Assistant,
-                        content: response.clone(),
-                        timestamp: Instant::now(),
-                        is_system: false,
-                    });
-
-                    // Auto-scroll to bottom
-                    let msg_len = messages.len();
-                    self.scroll_offset = msg_len.saturating_sub(1);
+                    #[cfg(unix)]
+                    self.answer_control_waiting(|| Response::Reply { content: response.clone() });
+                    self.announce(accessibility::reply_received(self.provider.name(), &response));
+                    self.speak(&response);
+                    #[cfg(feature = "audio")]
+                    self.cue(Cue::Reply);
                 }
                 Err(e) => {
+                    self.awaiting_commit_message = false;
                     self.add_system_message(&format!("Error: {}", e));
+                    self.emit(EventKind::Error, &format!("{:#}", e), None, None, Local::now().timestamp());
+                    let summary = format!("{} didn't answer", self.provider.name());
+                    #[cfg(unix)]
+                    self.answer_control_waiting(|| Response::Error { message: format!("{}: {}", summary, e) });
+                    self.show_problem(Problem::new(Source::Provider(self.provider.clone()), summary, e));
                 }
             }
         }
 
-        Ok(())
+        while let Ok((round, side, result)) = self.eval_rx.try_recv() {
+            if let Some(eval) = self.eval.as_mut().filter(|eval| eval.id() == round) {
+                eval.answer(side, result.map(|reply| reply.content).map_err(|e| format!("{:#}", e)));
+                changed = true;
+            }
+        }
+        changed |= self.conversations.take_loaded();
+        while let Ok((round, index, result)) = self.variations_rx.try_recv() {
+            if let Some(variations) = self.variations.as_mut().filter(|v| v.id() == round) {
+                variations.answer(index, result.map_err(|e| format!("{:#}", e)));
+                changed = true;
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            changed |= self.serve_control();
+        }
+        // After the reply, so guests get it straight away
+        changed |= self.serve_guests();
+        changed |= self.follow_host();
+        Ok(changed)
+    }
+
+    /// Save a reply, tell the event log, and add it to the open
+    /// conversation, scrolled to
+    fn add_reply(&mut self, response: &str, usage: &Usage) {
+        // Save to database first
+        let (id, timestamp) = self.save_message("assistant", response, Some(usage));
+        self.emit(EventKind::Response, response, None, Some(usage), timestamp);
+
+        // Then add to messages
+        let messages = self.get_current_messages_mut();
+        messages.push(ChatMessage {
+            id,
+            role: MessageRole::Assistant,
+            content: response.to_string(),
+            timestamp,
+            is_system: false,
+            starred: false,
+            rating: None,
+            note: None,
+            author: None,
+        });
+
+        // Auto-scroll to bottom
+        let msg_len = messages.len();
+        self.scroll_offset = msg_len.saturating_sub(1);
     }
 
-    fn add_system_message(&mut self, content: &str) {
+    pub fn add_system_message(&mut self, content: &str) {
         let messages = self.get_current_messages_mut();
         messages.push(ChatMessage {
+            id: None,
             role: MessageRole::Assistant,
             content: format!("🔧 {}", content),
-            timestamp: Instant::now(),
+            timestamp: Local::now().timestamp(),
             is_system: true,
+            starred: false,
+            rating: None,
+            note: None,
+            author: None,
         });
     }
 
-    pub fn render(&mut self, frame: &mut Frame) -> Result<()> {
-        let area = frame.area();
-
+    fn layout(area: Rect) -> std::rc::Rc<[Rect]> {
         // Main layout with semi-transparent panels over video
-        let chunks = Layout::default()
+        Layout::default()
             .direction(Direction::Vertical)
             .constraints([
                 Constraint::Length(3),      // Header
@@ -279,11 +2814,69 @@ impl ChatInterface {
                 Constraint::Length(3),      // Input
                 Constraint::Length(1),      // Footer
             ])
-            .split(area);
+            .split(area)
+    }
+
+    /// Where the header is drawn when the chat fills `area`
+    #[cfg_attr(not(feature = "video"), allow(dead_code))]
+    pub fn header_area(area: Rect) -> Rect {
+        Self::layout(area)[0]
+    }
+
+    /// Inner areas of the message and input blocks, where the background
+    /// is dimmed harder so text stays readable
+    #[cfg_attr(not(feature = "video"), allow(dead_code))]
+    pub fn text_panels(&self, area: Rect) -> Vec<Rect> {
+        let chunks = Self::layout(area);
+        vec![
+            chunks[1].inner(Margin::new(1, 1)),
+            chunks[2].inner(Margin::new(1, 1)),
+        ]
+    }
+
+    pub fn render(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        let chunks = Self::layout(area);
 
         // Header - semi-transparent
-        let header_text = format!("🎬 MEGA-CLI // {} ", self.provider.name());
-        let header = Paragraph::new(header_text)
+        let mut header_text = format!("🎬 MEGA-CLI // {} ", self.provider.name());
+        if let Some(conversation) = self.conversations.get(&self.provider) {
+            if !conversation.title.is_empty() {
+                header_text.push_str(&format!("// {} ", conversation.title));
+            }
+            if conversation.locked {
+                header_text.push_str("🔒 ");
+            }
+            header_text.push_str(&tag_list(&conversation.tags));
+            let settings = describe_settings(&conversation.settings);
+            if !settings.is_empty() {
+                header_text.push_str(&format!("  ⚙ {}", settings.join(" · ")));
+            }
+        }
+        let mut header_spans = Vec::new();
+        if let Some(ref profile) = self.profile {
+            header_spans.push(Span::styled(
+                format!(" 👤 {} ", profile),
+                Style::default().fg(Color::Black).bg(self.provider.color()),
+            ));
+            header_spans.push(Span::raw(" "));
+        }
+        if let Some(ref recorder) = self.recorder {
+            let name = recorder.path().file_name().unwrap_or_default().to_string_lossy();
+            header_spans.push(Span::styled(
+                format!(" ● REC {} ", name),
+                Style::default().fg(Color::White).bg(Color::Red),
+            ));
+            header_spans.push(Span::raw(" "));
+        }
+        if self.conversations.db().is_some_and(Database::is_in_memory) {
+            header_spans.push(Span::styled(
+                " 🕶 INCOGNITO · nothing is saved ",
+                Style::default().fg(Color::Black).bg(Color::Magenta),
+            ));
+            header_spans.push(Span::raw(" "));
+        }
+        header_spans.push(Span::raw(header_text));
+        let header = Paragraph::new(Line::from(header_spans))
             .style(Style::default().fg(self.provider.color()).bold())
             .block(
                 Block::default()
@@ -300,35 +2893,128 @@ impl ChatInterface {
             self.render_messages(frame, chunks[1]);
         }
 
+        if let Some(ref notice) = self.notice {
+            self.render_notice(frame, chunks[1], notice);
+        }
+
+        self.render_switcher(frame, chunks[1]);
+        if let Some(ref message) = self.commit_offer {
+            render_commit_offer(frame, chunks[1], message);
+        }
+
+        if let Some(ref mut sessions) = self.sessions {
+            sessions.render(frame, area);
+        }
+        if let Some(ref mut search) = self.search {
+            search.render(frame, area);
+        }
+        if let Some(ref mut snippets) = self.snippets {
+            snippets.render(frame, area);
+        }
+        if let Some(ref stats) = self.stats {
+            stats.render(frame, area);
+        }
+        if let Some(ref context) = self.context {
+            context.render(frame, area);
+        }
+        if let Some(ref eval) = self.eval {
+            eval.render(frame, area);
+        }
+        if let Some(ref mut variations) = self.variations {
+            variations.render(frame, area);
+        }
+        if let Some(ref patch) = self.patch {
+            patch.render(frame, area);
+        }
+        self.leader.render(frame, chunks[1]);
+
         // Input area
         let input_text = if self.is_streaming {
             "⏳ Waiting for response...".to_string()
         } else {
             format!("> {}_", self.input_buffer)
         };
+        let input_title = match self.filling {
+            Some(ref filling) => {
+                let (number, count) = filling.progress();
+                format!(
+                    "Template {} · {} ({}/{}) · Esc cancels",
+                    filling.name(),
+                    filling.next().unwrap_or_default(),
+                    number,
+                    count
+                )
+            }
+            None => match (&self.hosting, &self.joined) {
+                (Some(host), _) if host.guests().is_empty() => format!("Input · sharing on {}", host.addr()),
+                (Some(host), _) => format!("Input · shared with {}", host.guests().join(", ")),
+                (None, Some(guest)) => format!("Input · joined {} as {}", guest.addr(), guest.name()),
+                (None, None) => match self.ab {
+                    Some(_) => "Input · A/B: each message goes to both models · /ab off ends it".to_string(),
+                    None => "Input".to_string(),
+                },
+            },
+        };
+        let mut input_block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(input_title)
+            .border_style(Style::default().fg(Color::Cyan));
+        if !self.completions.is_empty() && !self.is_streaming {
+            input_block = input_block.title_bottom(mention_hint(&self.completions, chunks[2].width));
+        }
         let input = Paragraph::new(input_text)
             .style(Style::default().fg(Color::Cyan))
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_type(ratatui::widgets::BorderType::Rounded)
-                    .title("Input")
-                    .border_style(Style::default().fg(Color::Cyan)),
-            );
+            .block(input_block);
         frame.render_widget(input, chunks[2]);
 
         // Footer
-        let footer_text = "F1 Help | F2 Switch AI | Ctrl+C Exit | Ctrl+L Clear";
+        let status;
+        let (footer_text, footer_color) = match (&self.flash, self.outbox.queued()) {
+            (Some((text, at)), _) if self.accessible || at.elapsed() < FLASH_DURATION => (text.as_str(), Color::Yellow),
+            (_, Some(place)) => {
+                status = format!("Queued for {}'s rate limit: {}", self.provider.name(), queue_status(place));
+                (status.as_str(), Color::Magenta)
+            }
+            _ if self.macros.is_recording() => {
+                status = format!("● Recording a macro: {} keys so far. Ctrl+R stops", self.macros.recorded());
+                (status.as_str(), Color::Red)
+            }
+            _ if self.macros.keys_left() > 0 => {
+                status = format!("▶ Playing a macro: {} keys to go. Any key stops it", self.macros.keys_left());
+                (status.as_str(), Color::Magenta)
+            }
+            _ => (FOOTER_HINTS, Color::DarkGray),
+        };
         let footer = Paragraph::new(footer_text)
-            .style(Style::default().fg(Color::DarkGray))
+            .style(Style::default().fg(footer_color))
             .alignment(Alignment::Center);
         frame.render_widget(footer, chunks[3]);
 
+        // Over everything, input included
+        self.errors.render(frame, area);
+
+        if self.accessible {
+            accessibility::high_contrast(frame.buffer_mut(), area);
+        }
         Ok(())
     }
 
     fn render_messages(&self, frame: &mut Frame, area: Rect) {
-        let messages = self.get_current_messages();
+        // A replay shows the messages so far, the last cut to what's been
+        // typed of it
+        let (messages, typed, provider, scroll_offset, title) = match self.replay {
+            Some(ref replay) => {
+                let (messages, shown) = replay.visible();
+                let start = replay_start(messages, shown, area);
+                (messages, Some(shown), replay.provider(), start, replay_title(replay))
+            }
+            None => {
+                let earlier = self.conversations.get(&self.provider).is_some_and(OpenConversation::has_earlier);
+                let title = if earlier { "Messages · ↑ earlier ones" } else { "Messages" };
+                (self.get_current_messages(), None, &self.provider, self.scroll_offset, title.to_string())
+            }
+        };
 
         if messages.is_empty() {
             let welcome = Paragraph::new(format!(
@@ -352,59 +3038,115 @@ impl ChatInterface {
             return;
         }
 
-        let mut lines = vec![];
-        for (idx, msg) in messages.iter().enumerate() {
-            if idx < self.scroll_offset {
-                continue;
-            }
-
-            let (prefix, color) = match msg.role {
-                MessageRole::User => ("You", Color::Green),
-                MessageRole::Assistant => (
-                    self.provider.name(),
-                    self.provider.color(),
-                ),
-            };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(title)
+            .border_style(Style::default().fg(Color::White));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
 
-            lines.push(Line::from(vec![
-                Span::styled(format!("{}: ", prefix), Style::default().fg(color).bold()),
-                Span::styled(&msg.content, Style::default().fg(color)),
-            ]));
+        // Messages are laid out once and kept, so a frame only copies the
+        // rows in view; a long conversation costs no more than a short one
+        let mut transcript = self.transcript.borrow_mut();
+        transcript.prepare((inner.width, Local::now().date_naive(), provider.name(), typed.is_some(), self.table_scroll));
+        transcript.render(frame.buffer_mut(), inner, messages, scroll_offset, provider, typed);
+    }
 
-            if idx < messages.len() - 1 {
-                lines.push(Line::from(""));
-            }
-        }
+    fn render_notice(&self, frame: &mut Frame, area: Rect, notice: &str) {
+        let height = 4.min(area.height);
+        let popup = Rect::new(area.x + 2, area.y + 1, area.width.saturating_sub(4), height);
 
-        let messages_text = Text::from(lines);
-        let messages_paragraph = Paragraph::new(messages_text)
-            .wrap(Wrap { trim: false })
+        let paragraph = Paragraph::new(format!("🔧 {}", notice))
+            .style(Style::default().fg(Color::Yellow))
+            .wrap(Wrap { trim: true })
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_type(ratatui::widgets::BorderType::Rounded)
-                    .title("Messages")
-                    .border_style(Style::default().fg(Color::White)),
+                    .title("Notice (Esc to dismiss)")
+                    .border_style(Style::default().fg(Color::Yellow)),
             );
+        frame.render_widget(Clear, popup);
+        frame.render_widget(paragraph, popup);
+    }
+
+    fn render_switcher(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(ref mut switcher) = self.switcher else {
+            return;
+        };
+        let current = self.conversations.get(&self.provider).and_then(|c| c.id);
+
+        let items: Vec<ListItem> = switcher
+            .conversations
+            .iter()
+            .map(|c| {
+                let marker = if Some(c.id) == current { "● " } else { "  " };
+                ListItem::new(Line::from(vec![
+                    Span::raw(marker),
+                    Span::styled(display_title(&c.title), Style::default().bold()),
+                    Span::styled(
+                        format!("  {} · {} messages", c.created_on, c.message_count),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::styled(if c.locked { "  🔒" } else { "" }, Style::default().fg(Color::Cyan)),
+                ]))
+            })
+            .collect();
 
-        frame.render_widget(messages_paragraph, area);
+        let height = (items.len() as u16 + 2).min(area.height.saturating_sub(2));
+        let popup = Rect::new(area.x + 2, area.y + 1, area.width.saturating_sub(4), height);
+
+        let list = List::new(items)
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().fg(self.provider.color()).reversed())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Rounded)
+                    .title(format!("{} conversations (↑/↓, Enter to open, Esc to close)", self.provider.name()))
+                    .border_style(Style::default().fg(self.provider.color())),
+            );
+        frame.render_widget(Clear, popup);
+        frame.render_stateful_widget(list, popup, &mut switcher.state);
     }
 
     fn render_help(&self, frame: &mut Frame, area: Rect) {
-        let help_text =
+        let help_text = format!(
 "🎬 MEGA-CLI Keyboard Shortcuts
 
 Navigation:
   ↑/↓         Scroll messages
   PgUp/PgDn   Scroll 10 messages
+  ←/→         Scroll wide tables sideways
 
 Commands:
   Enter       Send message
+  Tab         Complete an @file path
   F1          Toggle this help
   F2          Switch AI provider
-  Ctrl+L      Clear conversation
+  F3/F4       Decrease/increase background opacity
+  F5          Mute/unmute background audio
+  F8          Read replies aloud, or stop
+  F9          Sound cues on/off
+  Shift+←/→   Seek background video 5 seconds
+  F6          Browse saved conversations
+  F7          Session manager (rename, archive, delete...)
+  F12         Toggle performance overlay
+  Ctrl+F      Search every conversation
+  Ctrl+S      Put a saved snippet in the input box
+  Ctrl+G      What the next request sends, against the context window
+  Space       Leader, on an empty input: a letter then does any of these
+              and more; a popup shows which
+  Ctrl+R      Record a macro, or stop recording
+  Ctrl+P      Play the macro just recorded
+  Ctrl+L      Start a new conversation
+  Ctrl+Z      Suspend to the shell (fg comes back)
   Ctrl+C      Exit
 
+Slash commands:
+{}
+
 AI Providers:
   • Claude Sonnet 4
   • Grok 4
@@ -414,11 +3156,13 @@ AI Providers:
 The animated video background plays continuously
 while you chat, creating a cinematic experience!
 
-Your conversations are saved per AI provider.
-Switch between providers with F2 - your chat
-history will be preserved!
+Every conversation is saved. Switch between
+providers with F2, or reopen an older chat
+with F6 - nothing is lost!
 
-Press F1 to return to chat.";
+Press F1 to return to chat.",
+            commands::HELP
+        );
 
         let help = Paragraph::new(help_text)
             .alignment(Alignment::Left)
@@ -434,3 +3178,167 @@ Press F1 to return to chat.";
         frame.render_widget(help, area);
     }
 }
+
+/// A waiting message's place in line and how long until the line moves
+fn queue_status(queued: Queued) -> String {
+    match (queued.position, queued.ready_in.as_secs()) {
+        (1, 0) => "next in line".to_string(),
+        (1, secs) => format!("next in line, sending in ~{}s", secs),
+        (n, 0) => format!("{} in line", ordinal(n)),
+        (n, secs) => format!("{} in line, the next goes in ~{}s", ordinal(n), secs),
+    }
+}
+
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// Settings that differ from the provider's defaults, model first
+fn describe_settings(settings: &ChatSettings) -> Vec<String> {
+    let mut parts = Vec::new();
+    if let Some(ref model) = settings.model {
+        parts.push(model.clone());
+    }
+    if let Some(temperature) = settings.temperature {
+        parts.push(format!("temperature {}", temperature));
+    }
+    if let Some(ref persona) = settings.persona {
+        parts.push(format!("persona {}", persona));
+    }
+    if settings.system_prompt.is_some() {
+        parts.push("system prompt".to_string());
+    }
+    parts
+}
+
+fn display_title(title: &str) -> &str {
+    if title.is_empty() {
+        "Untitled"
+    } else {
+        title
+    }
+}
+
+/// A conversation as guests of a shared session are sent it, the host's
+/// own messages under `host`
+fn conversation_snapshot(provider: &AIProvider, conversation: Option<&OpenConversation>, host: &str) -> FromHost {
+    FromHost::Conversation {
+        provider: provider.db_name().to_string(),
+        title: conversation.map(|c| c.title.clone()).unwrap_or_default(),
+        messages: conversation
+            .map(|c| c.messages.iter().filter(|m| !m.is_system).map(|m| shared_message(m, host)).collect())
+            .unwrap_or_default(),
+    }
+}
+
+fn shared_message(message: &ChatMessage, host: &str) -> SharedMessage {
+    let user = matches!(message.role, MessageRole::User);
+    SharedMessage {
+        role: if user { "user" } else { "assistant" }.to_string(),
+        author: user.then(|| message.author.clone().unwrap_or_else(|| host.to_string())),
+        content: message.content.clone(),
+        timestamp: message.timestamp,
+    }
+}
+
+/// The commit message in a reply to `/commitmsg`, without any code fence
+/// around it
+fn commit_message(reply: &str) -> String {
+    let reply = reply.trim();
+    let unfenced = reply
+        .strip_prefix("```")
+        .and_then(|rest| rest.split_once('\n'))
+        .and_then(|(_, body)| body.trim_end().strip_suffix("```"));
+    unfenced.unwrap_or(reply).trim().to_string()
+}
+
+/// The `/commitmsg` offer, along the bottom of the messages
+fn render_commit_offer(frame: &mut Frame, area: Rect, message: &str) {
+    let height = (message.lines().count() as u16 + 2).min(area.height.saturating_sub(2)).max(3);
+    let popup = Rect::new(
+        area.x + 2,
+        area.y + area.height.saturating_sub(height + 1),
+        area.width.saturating_sub(4),
+        height,
+    );
+    let paragraph = Paragraph::new(message.to_string())
+        .style(Style::default().fg(Color::White))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(ratatui::widgets::BorderType::Rounded)
+                .title(" Commit message ")
+                .title_bottom(Line::from(" c copy · a commit · Esc dismiss ").right_aligned())
+                .border_style(Style::default().fg(Color::Green)),
+        );
+    frame.render_widget(Clear, popup);
+    frame.render_widget(paragraph, popup);
+}
+
+/// Completions for an `@mention`, as many as fit in the input box's border
+fn mention_hint(completions: &[String], width: u16) -> Line<'static> {
+    let room = (width as usize).saturating_sub(20);
+    let mut shown = Vec::new();
+    let mut used = 0;
+    for path in completions {
+        let len = path.chars().count() + 3;
+        if used + len > room {
+            break;
+        }
+        used += len;
+        shown.push(path.as_str());
+    }
+    let mut hint = format!(" Tab ⇥ {}", shown.join(" · "));
+    if shown.len() < completions.len() {
+        hint.push_str(&format!(" +{}", completions.len() - shown.len()));
+    }
+    hint.push(' ');
+    Line::styled(hint, Style::default().fg(Color::DarkGray))
+}
+
+/// Which message a replay is on and whether it's finished
+fn replay_position(replay: &Replay) -> (usize, bool) {
+    (replay.progress().0, replay.is_finished())
+}
+
+/// Messages panel title during a replay, with its keys
+fn replay_title(replay: &Replay) -> String {
+    let (at, total) = replay.progress();
+    let state = if replay.is_finished() {
+        "done · Esc closes".to_string()
+    } else if replay.is_paused() {
+        "paused · Space resumes · Esc stops".to_string()
+    } else {
+        format!("{}× · Space pauses · → skips · +/- speed · Esc stops", replay.speed())
+    };
+    format!("Replay · {} · {}/{} · {}", display_title(replay.title()), at, total, state)
+}
+
+/// The first message to show during a replay so the one being typed is
+/// in view with as many before it as fit, guessing at how lines wrap
+fn replay_start(messages: &[ChatMessage], shown: usize, area: Rect) -> usize {
+    let width = area.width.saturating_sub(2).max(1) as usize;
+    let mut room = area.height.saturating_sub(2) as usize;
+    for (idx, msg) in messages.iter().enumerate().rev() {
+        let content = if idx == messages.len() - 1 { &msg.content[..shown] } else { msg.content.as_str() };
+        // Time and name come first; a blank line follows all but the last
+        let mut rows = usize::from(idx < messages.len() - 1);
+        for (n, line) in content.split('\n').enumerate() {
+            let chars = line.chars().count() + if n == 0 { 16 } else { 0 };
+            rows += chars.div_ceil(width).max(1);
+        }
+        if rows > room {
+            return (idx + 1).min(messages.len() - 1);
+        }
+        room -= rows;
+    }
+    0
+}
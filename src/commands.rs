@@ -0,0 +1,179 @@
+//! Slash commands typed into the input box instead of a message
+
+use crate::providers::AIProvider;
+
+/// Help lines for every command, shown on F1
+pub const HELP: &str = "\
+  /new [title]  Start a new conversation
+  /chats        Browse this provider's conversations
+  /sessions     Manage conversations with every provider
+  /search [text]  Search every conversation
+  /stats        Usage, tokens and cost charts
+  /context      What the next request sends, against the model's context
+                window (Ctrl+G)
+  /replay [id or title]  Play this conversation (or a saved one) back,
+                typed out message by message
+  /tag [names]  Tag this conversation, or list its tags
+  /untag names  Remove tags from this conversation
+  /lock         Make this conversation read-only: nothing more is sent to
+                it and it can't be deleted or pruned
+  /unlock       Let this conversation be added to and deleted again
+  /star         Star or unstar the last reply
+  /rate up|down|none [n]  Rate the last reply (or the nth from last) 👍 or
+                👎, or take the rating back
+  /note [text]  Note something on the last reply; no text removes it
+  /snip name [n]  Keep the last reply (or the nth from last) as a snippet
+  /snippets [text]  Pick a snippet to put in the input box (Ctrl+S)
+  /set [setting value]  Set model, system, temperature or persona for this
+                conversation; no value resets it, no setting lists them
+  /moveto name  Carry this conversation on with claude, grok, gpt or gemini
+  /share        Upload this conversation as Markdown, without attached
+                files, notes or keys, to a gist or [paste] service, and
+                copy the link
+  /vary [n]     Ask again for the last reply n times (3 if not given) at
+                once, at temperatures from low to high, and pick one to
+                add as the next reply
+  /ab [model model | off]  Send each message to both models, blinded as
+                A and B, and vote for the better answer. Alone it shows
+                each provider's win rate; off ends it
+  /profile [name]  Switch to another profile, or list them
+  /errors       Show this session's errors again, with what might fix them
+  /hooks        Load the profile's hooks.rhai again and list its hooks
+  /diff [question]  Send git's staged (or else unstaged) diff, with a
+                question or asking for a review
+  /commitmsg    Have a commit message written for the staged changes,
+                then copy it or commit with it
+  /apply [n]    Look over the diffs in the last reply (or the nth from
+                last), unified or side by side, and git apply them
+  /t [name] [text]  Send a template from [templates], asking for its
+                {placeholders}; text fills the first. No name lists them
+  /macro [name]  Play a saved macro, or list them
+  /macro save name [key]  Keep the last recording (Ctrl+R), bound to a
+                key such as alt+1 if given
+  /macro delete name  Forget a saved macro
+  //text        Send /text as a message rather than a command";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Start a fresh conversation, optionally titled
+    New(Option<String>),
+    /// Open the conversation switcher
+    Chats,
+    /// Open the full-screen session manager
+    Sessions,
+    /// Open search, optionally with a query already typed
+    Search(Option<String>),
+    /// Open the usage dashboard
+    Stats,
+    /// Open the context gauge
+    Context,
+    /// Play back the open conversation, or a saved one by id or title
+    Replay(Option<String>),
+    /// Add space-separated tags to the open conversation, or list them
+    Tag(Option<String>),
+    /// Remove space-separated tags from the open conversation
+    Untag(Option<String>),
+    /// Make the open conversation read-only
+    Lock,
+    /// Let the open conversation be changed again
+    Unlock,
+    /// Toggle the star on the last reply
+    Star,
+    /// Rate a reply up or down, or clear its rating: the rating, then
+    /// optionally how many replies back
+    Rate(Option<String>),
+    /// Set the last reply's note, or remove it
+    Note(Option<String>),
+    /// Keep a reply in the snippet library: a name, then optionally how
+    /// many replies back
+    Snip(Option<String>),
+    /// Open the snippet picker, optionally with a filter already typed
+    Snippets(Option<String>),
+    /// Change one of the open conversation's settings, or list them
+    Set(Option<String>),
+    /// Copy the open conversation to another provider and switch to it
+    MoveTo(AIProvider),
+    /// Upload the open conversation to a gist or paste service
+    Share,
+    /// Ask again for the last reply at several temperatures, optionally
+    /// how many
+    Vary(Option<String>),
+    /// Start A/B evals between two models, end them, or show the
+    /// standings
+    Ab(Option<String>),
+    /// Switch to a named profile, or list them
+    Profile(Option<String>),
+    /// Reopen the error overlay on the newest error
+    Errors,
+    /// Reload the hooks script and say which hooks it defines
+    Hooks,
+    /// Send the git diff, with a question about it or asking for a review
+    Diff(Option<String>),
+    /// Ask for a commit message for the staged changes
+    CommitMsg,
+    /// Offer a reply's diffs to `git apply`, optionally how many replies
+    /// back
+    Apply(Option<String>),
+    /// Fill in and send a template, or list them
+    Template(Option<String>),
+    /// Play, save or delete a keyboard macro, or list them
+    Macro(Option<String>),
+}
+
+impl Command {
+    /// None when `input` isn't a command and should be sent as a message
+    /// (after `unescape`); Err for an unknown command
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let rest = input.trim().strip_prefix('/').filter(|rest| !rest.starts_with('/'))?;
+        let (name, arg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let arg = Some(arg.trim()).filter(|a| !a.is_empty()).map(str::to_string);
+
+        Some(match name {
+            "new" => Ok(Command::New(arg)),
+            "chats" => Ok(Command::Chats),
+            "sessions" => Ok(Command::Sessions),
+            "search" => Ok(Command::Search(arg)),
+            "stats" => Ok(Command::Stats),
+            "context" => Ok(Command::Context),
+            "replay" => Ok(Command::Replay(arg)),
+            "tag" => Ok(Command::Tag(arg)),
+            "untag" => Ok(Command::Untag(arg)),
+            "lock" => Ok(Command::Lock),
+            "unlock" => Ok(Command::Unlock),
+            "star" => Ok(Command::Star),
+            "rate" => Ok(Command::Rate(arg)),
+            "note" => Ok(Command::Note(arg)),
+            "snip" => Ok(Command::Snip(arg)),
+            "snippets" => Ok(Command::Snippets(arg)),
+            "set" => Ok(Command::Set(arg)),
+            "moveto" => match arg.as_deref().and_then(AIProvider::parse) {
+                Some(provider) => Ok(Command::MoveTo(provider)),
+                None => Err("Usage: /moveto claude|grok|gpt|gemini".to_string()),
+            },
+            "share" => Ok(Command::Share),
+            "vary" => Ok(Command::Vary(arg)),
+            "ab" => Ok(Command::Ab(arg)),
+            "profile" => Ok(Command::Profile(arg)),
+            "errors" => Ok(Command::Errors),
+            "hooks" => Ok(Command::Hooks),
+            "diff" => Ok(Command::Diff(arg)),
+            "commitmsg" => Ok(Command::CommitMsg),
+            "apply" => Ok(Command::Apply(arg)),
+            "t" => Ok(Command::Template(arg)),
+            "macro" => Ok(Command::Macro(arg)),
+            _ => Err(format!(
+                "Unknown command: /{} (F1 lists commands; //{} sends it as a message)",
+                name, name
+            )),
+        })
+    }
+}
+
+/// A message as it's sent: `//text`, typed so a path or the like isn't
+/// taken for a command, loses the first slash
+pub fn unescape(input: String) -> String {
+    match input.trim_start().strip_prefix("//") {
+        Some(rest) => format!("/{}", rest),
+        None => input,
+    }
+}
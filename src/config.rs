@@ -0,0 +1,584 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use toml_edit::{DocumentMut, Item, TableLike};
+
+mod env;
+pub use env::{misnamed_keys, startup_warning};
+mod show;
+pub use show::show;
+
+/// Background video used when nothing else is configured
+pub const DEFAULT_VIDEO: &str = "loading.mp4";
+
+/// User configuration loaded from `~/.config/mega-cli/config.toml`.
+/// Every field has a default so a partial (or missing) file is fine.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Provider the chat starts with unless `--provider` is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    pub background: BackgroundConfig,
+    pub audio: AudioConfig,
+    pub speech: SpeechConfig,
+    pub sounds: SoundsConfig,
+    pub screensaver: ScreensaverConfig,
+    pub display: DisplayConfig,
+    pub log: LogConfig,
+    pub database: DatabaseConfig,
+    pub retention: RetentionConfig,
+    pub backup: BackupConfig,
+    pub sync: SyncConfig,
+    pub events: EventsConfig,
+    pub updates: UpdatesConfig,
+    pub keys: KeysConfig,
+    pub paste: PasteConfig,
+    pub redact: RedactConfig,
+    /// Client-side limits per provider, by its short name (claude, grok,
+    /// gpt, gemini, mock)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub rate_limits: BTreeMap<String, RateLimitConfig>,
+    /// Named instructions picked per conversation with `/set persona`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub personas: BTreeMap<String, String>,
+    /// Named prompts sent with `/t`, their `{placeholders}` asked for first
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub templates: BTreeMap<String, String>,
+    /// Recorded keys kept with `/macro save`, played by name or their key
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub macros: BTreeMap<String, MacroConfig>,
+    /// Named profile this was loaded for; None is the default one
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// API keys from the profile's `.env`, by variable name. They win
+    /// over the environment, so each profile can use its own.
+    #[serde(skip)]
+    pub api_keys: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundConfig {
+    /// Video file path, network URL (http, https, rtsp, ...), `webcam[:device]`,
+    /// or `builtin` for the procedural animation
+    pub video: String,
+    /// yt-dlp executable used to resolve YouTube URLs
+    pub ytdlp: String,
+    /// Preset name (classic, blocks, dots, simple) or a custom light→dark ramp
+    pub palette: String,
+    /// Dithering mode (none, ordered, floyd)
+    pub dither: String,
+    /// Character selection (density, edges for Sobel edge lines, or solid color fill)
+    pub ascii_mode: String,
+    /// Video background opacity (0.0 - 1.0)
+    pub opacity: f32,
+    /// Opacity behind the message and input panels (0.0 clears it entirely)
+    pub panel_opacity: f32,
+    /// Color mode (full, mono, provider, or a #rrggbb tint)
+    pub color: String,
+    /// Screen region (full, top, left, or header) the video plays in
+    pub region: String,
+    /// Drop to half resolution / frame rate automatically under CPU pressure
+    pub adaptive: bool,
+    /// Replay converted frames from memory after the first pass of a looping video
+    pub cache_frames: bool,
+    /// Milliseconds to blend the last frame into the first when the video loops (0 cuts)
+    pub crossfade_ms: u64,
+    /// Loop-in point, e.g. "0:10"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_start: Option<String>,
+    /// Loop-out point, e.g. "0:25"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_end: Option<String>,
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        Self {
+            video: DEFAULT_VIDEO.to_string(),
+            ytdlp: "yt-dlp".to_string(),
+            palette: "classic".to_string(),
+            dither: "none".to_string(),
+            ascii_mode: "density".to_string(),
+            opacity: 0.3,
+            panel_opacity: 0.1,
+            color: "full".to_string(),
+            region: "full".to_string(),
+            adaptive: true,
+            cache_frames: true,
+            crossfade_ms: 500,
+            loop_start: None,
+            loop_end: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Play the background video's audio track
+    pub enabled: bool,
+    /// Playback volume (0.0 - 1.0)
+    pub volume: f32,
+    /// Start muted (toggled with F5 and remembered)
+    pub muted: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            volume: 0.5,
+            muted: false,
+        }
+    }
+}
+
+/// Reading replies aloud
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeechConfig {
+    /// Read each reply aloud (toggled with F8 and remembered)
+    pub enabled: bool,
+    /// auto, say, espeak-ng, espeak or powershell
+    pub engine: String,
+    /// Shell command used instead of the engine. It gets the text on stdin,
+    /// and the voice and rate in MEGA_CLI_VOICE and MEGA_CLI_RATE.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Words per minute; the engine's own default if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<u32>,
+    /// Voice per provider (claude, grok, gpt, gemini), in the engine's own names
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub voices: BTreeMap<String, String>,
+}
+
+impl Default for SpeechConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            engine: "auto".to_string(),
+            command: None,
+            rate: None,
+            voices: BTreeMap::new(),
+        }
+    }
+}
+
+/// Short sound cues on chat events (built with the `audio` feature)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundsConfig {
+    /// Play cues at all (toggled with F9 and remembered)
+    pub enabled: bool,
+    /// Cue volume (0.0 - 1.0)
+    pub volume: f32,
+    /// When a message is sent
+    pub sent: bool,
+    /// When a reply arrives
+    pub reply: bool,
+    /// When something goes wrong
+    pub error: bool,
+}
+
+impl Default for SoundsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            volume: 0.3,
+            sent: true,
+            reply: true,
+            error: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreensaverConfig {
+    /// Minutes without a keypress before the video takes over (0 disables)
+    pub idle_minutes: u64,
+    /// Show the video in its original colors, ignoring `background.color`
+    pub full_color: bool,
+}
+
+impl Default for ScreensaverConfig {
+    fn default() -> Self {
+        Self {
+            idle_minutes: 5,
+            full_color: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Most redraws per second while a reply, the background or the screen is changing
+    pub fps: u32,
+    /// Checks for changes per second once nothing has changed for a couple
+    /// of seconds
+    pub idle_fps: u32,
+    /// High contrast, no background or animation, and status changes
+    /// spelled out in the footer, for screen readers and low vision
+    pub accessible: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self { fps: 30, idle_fps: 2, accessible: false }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// off, error, warn, info, debug or trace
+    pub level: String,
+    /// Daily log files kept
+    pub keep_days: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            keep_days: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdatesConfig {
+    /// Look for a newer release on launch, at most once a day
+    pub check: bool,
+}
+
+impl Default for UpdatesConfig {
+    fn default() -> Self {
+        Self { check: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    /// Key that starts a leader sequence, such as space or ctrl+space;
+    /// "none" turns it off
+    pub leader: String,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self { leader: "space".to_string() }
+    }
+}
+
+/// Where `/share` uploads a conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasteConfig {
+    /// gist, or the URL of a paste service that takes the text as the
+    /// body of a POST and answers with its URL
+    pub service: String,
+    /// List gists publicly rather than as secret (unlisted) ones
+    pub public: bool,
+    /// GitHub token with the gist scope; GITHUB_TOKEN from the environment
+    /// when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github_token: Option<String>,
+}
+
+impl Default for PasteConfig {
+    fn default() -> Self {
+        Self {
+            service: "gist".to_string(),
+            public: false,
+            github_token: None,
+        }
+    }
+}
+
+/// Secrets looked for in outgoing messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactConfig {
+    /// warn holds a message back the first time it's sent, mask replaces
+    /// what's found with [redacted], off doesn't look
+    pub action: String,
+    /// Email addresses count as secrets
+    pub emails: bool,
+    /// So do long strings random enough to be keys
+    pub entropy: bool,
+    /// More regexes to look for, by what to call what they find
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub patterns: BTreeMap<String, String>,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            action: "warn".to_string(),
+            emails: true,
+            entropy: true,
+            patterns: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// Conversation database file; defaults to the platform data directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// Limits on how much history is kept, applied on every launch. Nothing is
+/// removed unless a limit is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Conversations with no messages for this many days are archived or moved to the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u64>,
+    /// What happens to old conversations: archive or delete
+    pub action: String,
+    /// Delete the least recently used conversations while the database is bigger than this
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size_mb: Option<u64>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            action: "archive".to_string(),
+            max_size_mb: None,
+        }
+    }
+}
+
+/// Automatic rotating backups, taken on launch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    /// Where backups go; defaults to `backups` next to the database
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// Backups kept; older ones are deleted
+    pub keep: usize,
+    /// Hours between backups
+    pub every_hours: u64,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            keep: 7,
+            every_hours: 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Folder shared between machines for `animated-cli sync`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+}
+
+/// How much may be sent to one provider in any minute; a request that
+/// would go over waits until it fits. Unset (or 0) is no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+    /// Prompt and reply tokens together
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens_per_minute: Option<u32>,
+}
+
+/// A saved keyboard macro (`[macros.<name>]`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MacroConfig {
+    /// Key that plays it, such as `alt+r`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// What it types, in `<enter>`-style notation
+    pub keys: String,
+}
+
+/// Where chat events (messages sent, replies and errors) go as they happen
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// File each event is appended to as a line of JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+/// A URL each event is POSTed to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Events to send (user_message, response, error); every one when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on: Vec<String>,
+    /// json (the event as logged) or slack (a `text` summary, as Slack
+    /// and Mattermost incoming webhooks take)
+    #[serde(default = "default_webhook_format")]
+    pub format: String,
+}
+
+fn default_webhook_format() -> String {
+    "json".to_string()
+}
+
+/// Directory holding the config file: `~/.config/mega-cli`, or on
+/// Windows (which seldom sets HOME) `%APPDATA%\mega-cli\config`
+pub fn config_dir() -> Result<PathBuf> {
+    #[cfg(windows)]
+    {
+        let dirs = directories::ProjectDirs::from("", "", "mega-cli")
+            .context("Couldn't find the AppData directory for the config")?;
+        Ok(dirs.config_dir().to_path_buf())
+    }
+    #[cfg(not(windows))]
+    {
+        let home = std::env::var("HOME")
+            .context("HOME environment variable not set")?;
+        Ok(PathBuf::from(home).join(".config/mega-cli"))
+    }
+}
+
+/// Name of the profile that lives directly in `config_dir`
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Directory holding a profile's config and `.env`: `config_dir` for the
+/// default profile, `profiles/<name>` under it for the others
+pub fn profile_dir(profile: Option<&str>) -> Result<PathBuf> {
+    match profile {
+        None => config_dir(),
+        Some(name) => Ok(config_dir()?.join("profiles").join(name)),
+    }
+}
+
+/// `--profile` and `/profile` names; `default` means the default profile
+pub fn parse_profile(name: &str) -> Result<Option<String>> {
+    if name == DEFAULT_PROFILE {
+        return Ok(None);
+    }
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!("Profile names can only have letters, digits, - and _: {:?}", name);
+    }
+    Ok(Some(name.to_string()))
+}
+
+/// Every profile, the default one first
+pub fn list_profiles() -> Result<Vec<String>> {
+    let mut names = vec![DEFAULT_PROFILE.to_string()];
+    let dir = config_dir()?.join("profiles");
+    if dir.exists() {
+        let mut others: Vec<String> = std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read {}", dir.display()))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        others.sort();
+        names.extend(others);
+    }
+    Ok(names)
+}
+
+impl Config {
+    pub fn path(profile: Option<&str>) -> Result<PathBuf> {
+        Ok(profile_dir(profile)?.join("config.toml"))
+    }
+
+    /// Load a profile's config and API keys; a profile without a config
+    /// file gets the defaults
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        let path = Self::path(profile)?;
+        let mut config = if path.exists() {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read config at {:?}", path))?;
+            toml::from_str(&text).with_context(|| format!("Failed to parse config at {:?}", path))?
+        } else {
+            Self::default()
+        };
+        config.profile = profile.map(str::to_string);
+
+        // The default profile's keys come from the environment as before
+        if profile.is_some() {
+            let env = profile_dir(profile)?.join(".env");
+            if env.exists() {
+                config.api_keys = dotenvy::from_path_iter(&env)
+                    .and_then(|vars| vars.collect::<Result<_, _>>())
+                    .with_context(|| format!("Failed to read {:?}", env))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Write one setting changed at runtime, such as `audio.muted` or the
+    /// whole `macros` table, into the config file. The rest of the file,
+    /// comments and layout included, stays as it is.
+    pub fn save_setting<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let path = Self::path(self.profile.as_deref())?;
+        let text = if path.exists() {
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read config at {:?}", path))?
+        } else {
+            String::new()
+        };
+        let mut document: DocumentMut =
+            text.parse().with_context(|| format!("Failed to parse config at {:?}", path))?;
+
+        let (tables, name) = key.rsplit_once('.').map_or((None, key), |(tables, name)| (Some(tables), name));
+        let mut table = document.as_table_mut() as &mut dyn TableLike;
+        for part in tables.into_iter().flat_map(|tables| tables.split('.')) {
+            table = table
+                .entry(part)
+                .or_insert_with(toml_edit::table)
+                .as_table_like_mut()
+                .with_context(|| format!("{} in {:?} isn't a table", part, path))?;
+        }
+
+        // Serialized on its own, then moved across
+        let mut serialized = toml_edit::ser::to_document(&BTreeMap::from([(name, value)]))
+            .with_context(|| format!("Failed to serialize {}", key))?;
+        let item = serialized.remove(name).unwrap_or_default();
+        match (table.get_mut(name), item) {
+            // In place, which keeps the comments around the old value
+            (Some(Item::Value(old)), Item::Value(mut new)) => {
+                *new.decor_mut() = old.decor().clone();
+                *old = new;
+            }
+            (_, item) if item.as_table_like().is_some_and(TableLike::is_empty) => {
+                table.remove(name);
+            }
+            (_, item) => {
+                table.insert(name, item);
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, document.to_string()).with_context(|| format!("Failed to write config at {:?}", path))
+    }
+}
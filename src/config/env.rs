@@ -0,0 +1,133 @@
+//! Which API keys are set, and which were set under a name nothing reads:
+//! another tool's name for the same key, or a misspelling
+
+use crate::providers::AIProvider;
+use crate::models::key_source;
+
+use super::Config;
+
+/// Names other tools use for the same keys
+const OTHER_NAMES: [(&str, AIProvider); 5] = [
+    ("ANTHROPIC_API_KEY", AIProvider::Claude),
+    ("XAI_API_KEY", AIProvider::Grok),
+    ("OPENAI_KEY", AIProvider::OpenAI),
+    ("GOOGLE_API_KEY", AIProvider::Gemini),
+    ("GOOGLE_GENERATIVE_AI_API_KEY", AIProvider::Gemini),
+];
+
+/// Most edits between a name and a known one for it to count as a typo
+const MAX_TYPO_EDITS: usize = 2;
+
+/// An API key set under a name that isn't read
+#[derive(Debug, Clone)]
+pub struct Misnamed {
+    /// The name it was set under
+    pub name: String,
+    /// The known name it's closest to; differs from `name` for a typo
+    pub meant: String,
+    pub provider: AIProvider,
+    /// "environment" or "profile .env"
+    pub source: &'static str,
+}
+
+impl Misnamed {
+    /// One line saying what to rename
+    pub fn hint(&self) -> String {
+        let wanted = self.provider.api_key_env();
+        if self.name == self.meant {
+            format!("{} is set, but the {} key is read from {}", self.name, self.provider.db_name(), wanted)
+        } else if self.meant == wanted {
+            format!("{} looks like a misspelling of {}", self.name, wanted)
+        } else {
+            format!(
+                "{} looks like a misspelling of {}; the {} key is read from {}",
+                self.name,
+                self.meant,
+                self.provider.db_name(),
+                wanted
+            )
+        }
+    }
+}
+
+/// Providers with a key set, in the usual order
+pub fn usable_providers(config: &Config) -> Vec<AIProvider> {
+    AIProvider::ALL.into_iter().filter(|p| key_source(p, config).is_some()).collect()
+}
+
+/// Keys set under a name that isn't read, for providers that have no key
+/// under the right name. A provider that already has its key isn't
+/// flagged, so an unrelated variable one letter off (GROQ_API_KEY) is left
+/// alone once GROK_API_KEY is set.
+pub fn misnamed_keys(config: &Config) -> Vec<Misnamed> {
+    let set = config
+        .api_keys
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone(), "profile .env"))
+        .chain(std::env::vars().map(|(name, value)| (name, value, "environment")));
+
+    let mut found: Vec<Misnamed> = Vec::new();
+    for (name, value, source) in set {
+        if value.is_empty() || found.iter().any(|m| m.name == name) {
+            continue;
+        }
+        let Some((meant, provider)) = closest_known(&name) else {
+            continue;
+        };
+        if key_source(&provider, config).is_none() {
+            found.push(Misnamed { name, meant, provider, source });
+        }
+    }
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    found
+}
+
+/// What to say on launch, if anything: a misnamed key, or that the
+/// provider the chat starts with has none
+pub fn startup_warning(config: &Config, provider: &AIProvider) -> Option<String> {
+    if let Some(misnamed) = misnamed_keys(config).first() {
+        return Some(format!("{}. `animated-cli config show` lists every key.", misnamed.hint()));
+    }
+    if !provider.needs_key() || key_source(provider, config).is_some() {
+        return None;
+    }
+    let usable: Vec<String> = usable_providers(config).iter().map(|p| p.db_name().to_string()).collect();
+    Some(if usable.is_empty() {
+        "No API keys found. Set CLAUDE_API_KEY, GROK_API_KEY, OPENAI_API_KEY or GEMINI_API_KEY.".to_string()
+    } else {
+        format!("{} isn't set. Keys found for {}.", provider.api_key_env(), usable.join(", "))
+    })
+}
+
+/// The known key name `name` is, or is a near miss of, and whose key it
+/// is. None for the names that are read, and for anything further off.
+fn closest_known(name: &str) -> Option<(String, AIProvider)> {
+    let upper = name.to_uppercase();
+    if AIProvider::ALL.iter().any(|p| p.api_key_env() == name) {
+        return None;
+    }
+    let read = AIProvider::ALL.map(|p| (p.api_key_env().to_string(), p));
+    let other = OTHER_NAMES.map(|(name, p)| (name.to_string(), p));
+    read.into_iter()
+        .chain(other)
+        .map(|(known, p)| (edit_distance(&upper, &known), known, p))
+        .filter(|(edits, _, _)| *edits <= MAX_TYPO_EDITS)
+        .min_by_key(|(edits, _, _)| *edits)
+        .map(|(_, known, p)| (known, p))
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != *cb)).min(above + 1).min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,87 @@
+//! `animated-cli config show`: the settings the chat would run with, where
+//! each API key comes from, and keys set under a name that isn't read
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use super::env::{misnamed_keys, usable_providers};
+use super::{Config, DEFAULT_PROFILE};
+use crate::providers::AIProvider;
+use crate::models::key_source;
+
+/// Run `animated-cli config show`
+pub fn show(config: &Config, db_path: &Path) -> Result<()> {
+    let profile = config.profile.as_deref();
+    let path = Config::path(profile)?;
+    println!("Profile      {}", profile.unwrap_or(DEFAULT_PROFILE));
+    if path.exists() {
+        println!("Config file  {}", path.display());
+    } else {
+        println!("Config file  {} (not found; using defaults)", path.display());
+    }
+    println!("Database     {}", db_path.display());
+    if let Ok(dir) = crate::logging::log_dir() {
+        println!("Logs         {}", dir.display());
+    }
+
+    println!("\nAPI keys");
+    for provider in AIProvider::ALL {
+        let var = provider.api_key_env();
+        let key = match key_source(&provider, config) {
+            Some(source) => format!("{} from {}", masked(&key_value(&provider, config)), source),
+            None => "not set".to_string(),
+        };
+        println!("  {:<7} {:<15} {}", provider.db_name(), var, key);
+    }
+    let usable: Vec<String> = usable_providers(config).iter().map(|p| p.db_name().to_string()).collect();
+    if usable.is_empty() {
+        println!("No provider has a key, so none can be used");
+    } else {
+        println!("Usable: {}", usable.join(", "));
+    }
+
+    let misnamed = misnamed_keys(config);
+    if !misnamed.is_empty() {
+        println!("\nSet under a name that isn't read");
+        for key in misnamed {
+            println!("  ! {} (from {})", key.hint(), key.source);
+        }
+    }
+
+    let changed: Vec<String> = crate::reload::changed_keys(&Config::default(), config)
+        .into_iter()
+        .filter(|key| key != "api_keys")
+        .collect();
+    println!("\nSettings");
+    if changed.is_empty() {
+        println!("  All defaults");
+    } else {
+        println!("  Changed from the defaults: {}", changed.join(", "));
+    }
+    let text = toml::to_string_pretty(config).context("Failed to serialize config")?;
+    println!("\n{}", text.trim_end());
+    Ok(())
+}
+
+/// The key a provider will use, whichever place it comes from
+fn key_value(provider: &AIProvider, config: &Config) -> String {
+    let var = provider.api_key_env();
+    config
+        .api_keys
+        .get(var)
+        .filter(|key| !key.is_empty())
+        .cloned()
+        .or_else(|| std::env::var(var).ok())
+        .unwrap_or_default()
+}
+
+/// Enough of a key to tell two apart without giving it away
+fn masked(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() < 16 {
+        return format!("{} characters", chars.len());
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
@@ -0,0 +1,245 @@
+//! The context gauge (Ctrl+G or `/context`): what the next request would
+//! send, split into the system prompt, the conversation so far, the files
+//! attached to it and the message being typed, each against the model's
+//! context window. Token counts are estimates, at about four characters
+//! a token.
+
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+use std::path::Path;
+
+use crate::mentions;
+use crate::ratelimit::estimate_text;
+
+/// Filled share of the window past which the gauge warns
+const NEARLY_FULL: f64 = 0.8;
+
+const HINTS: &str = "Esc Close";
+
+/// The parts a request is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    /// The persona's instructions and the conversation's system prompt
+    System,
+    /// Earlier messages and replies, as typed
+    History,
+    /// Files `@mentioned` in those messages or the one being typed
+    Files,
+    /// The input box
+    Typing,
+}
+
+impl Part {
+    pub const ALL: [Part; 4] = [Part::System, Part::History, Part::Files, Part::Typing];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Part::System => "System prompt",
+            Part::History => "Conversation",
+            Part::Files => "Attached files",
+            Part::Typing => "Being typed",
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Part::System => Color::Magenta,
+            Part::History => Color::Cyan,
+            Part::Files => Color::Yellow,
+            Part::Typing => Color::Green,
+        }
+    }
+}
+
+/// A request's size, part by part, against its model's window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    pub model: String,
+    /// None for a model whose window isn't known
+    pub window: Option<u64>,
+    /// Estimated tokens of each part, in `Part::ALL` order
+    pub tokens: [u64; 4],
+}
+
+impl Budget {
+    /// Measure a request to `model`, with its `window`, of `history`
+    /// (messages as sent, attachments and all) and what's being `typed`,
+    /// whose mentions are looked up in `dir`
+    pub fn measure(
+        model: &str,
+        window: Option<u64>,
+        system_prompt: Option<&str>,
+        history: &[&str],
+        typed: &str,
+        dir: &Path,
+    ) -> Self {
+        let (mut said, mut attached) = (0, 0);
+        for message in history {
+            let (text, files) = mentions::split_attached(message);
+            said += estimate_text(text);
+            attached += estimate_text(files);
+        }
+        // Files not yet attached count at the same four bytes a token
+        let typed_files = mentions::attached_size(typed, dir) / 4;
+        Self {
+            model: model.to_string(),
+            window,
+            tokens: [
+                system_prompt.map_or(0, estimate_text),
+                said,
+                attached + typed_files,
+                estimate_text(typed),
+            ],
+        }
+    }
+
+    pub fn get(&self, part: Part) -> u64 {
+        self.tokens[Part::ALL.iter().position(|&p| p == part).unwrap_or(0)]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.tokens.iter().sum()
+    }
+}
+
+pub struct ContextView {
+    budget: Budget,
+}
+
+impl ContextView {
+    pub fn new(budget: Budget) -> Self {
+        Self { budget }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4).min(76);
+        let height = area.height.saturating_sub(2).min(15);
+        let popup = Rect {
+            x: area.x + (area.width.saturating_sub(width)) / 2,
+            y: area.y + (area.height.saturating_sub(height)) / 2,
+            width,
+            height,
+        };
+        frame.render_widget(Clear, popup);
+
+        let budget = &self.budget;
+        let window = match budget.window {
+            Some(window) => format!("{} token window", grouped(window)),
+            None => "window not known".to_string(),
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(format!("Context · {} · {}", budget.model, window))
+            .title_bottom(Line::from(HINTS).centered())
+            .border_style(Style::default().fg(Color::Cyan));
+        let inner = block.inner(popup);
+        frame.render_widget(block, popup);
+
+        let mut lines = vec![self.gauge(inner.width as usize), Line::default()];
+        let scale = budget.window.unwrap_or(budget.total()).max(1) as f64;
+        for part in Part::ALL {
+            let tokens = budget.get(part);
+            lines.push(Line::from(vec![
+                Span::styled("■ ", Style::default().fg(part.color())),
+                Span::raw(format!("{:<16}", part.label())),
+                Span::raw(format!("{:>12} tokens", grouped(tokens))),
+                Span::styled(
+                    format!("{:>8}", percent(tokens as f64 / scale)),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]));
+        }
+        lines.push(Line::default());
+        lines.push(self.summary());
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            "Estimated at about four characters a token; the provider's own count can differ.",
+            Style::default().fg(Color::DarkGray),
+        ));
+        frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+    }
+
+    /// One bar across the popup, each part its share of the window and
+    /// what's left dotted
+    fn gauge(&self, width: usize) -> Line<'static> {
+        let budget = &self.budget;
+        let total = budget.total();
+        let scale = budget.window.unwrap_or(total).max(total).max(1) as f64;
+        let mut spans = vec![];
+        let mut used = 0;
+        for part in Part::ALL {
+            let tokens = budget.get(part);
+            // Anything there at all gets a cell, so it doesn't vanish
+            let cells = ((tokens as f64 / scale * width as f64).round() as usize).max((tokens > 0) as usize);
+            let cells = cells.min(width - used);
+            used += cells;
+            spans.push(Span::styled("█".repeat(cells), Style::default().fg(part.color())));
+        }
+        spans.push(Span::styled("·".repeat(width - used), Style::default().fg(Color::DarkGray)));
+        Line::from(spans)
+    }
+
+    /// How full the window is, and what that means for sending
+    fn summary(&self) -> Line<'static> {
+        let budget = &self.budget;
+        let total = budget.total();
+        let Some(window) = budget.window else {
+            return Line::from(format!(
+                "{} tokens in all. There's no window on record for {} to measure them against.",
+                grouped(total),
+                budget.model
+            ));
+        };
+        let share = total as f64 / window.max(1) as f64;
+        if total > window {
+            return Line::styled(
+                format!(
+                    "{} of {} tokens: over by {}. The provider will turn it away; /new starts a fresh conversation.",
+                    grouped(total),
+                    grouped(window),
+                    grouped(total - window)
+                ),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            );
+        }
+        let text = format!(
+            "{} of {} tokens ({}), {} left for more and the reply",
+            grouped(total),
+            grouped(window),
+            percent(share),
+            grouped(window - total)
+        );
+        if share >= NEARLY_FULL {
+            Line::styled(format!("{}. Nearly full.", text), Style::default().fg(Color::Yellow))
+        } else {
+            Line::from(text)
+        }
+    }
+}
+
+fn percent(share: f64) -> String {
+    let percent = share * 100.0;
+    if percent == 0.0 {
+        "0%".to_string()
+    } else if percent < 1.0 {
+        "<1%".to_string()
+    } else {
+        format!("{:.0}%", percent)
+    }
+}
+
+/// 12,345
+fn grouped(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
@@ -0,0 +1,263 @@
+//! Remote control: a running chat listens on a unix socket so scripts,
+//! tmux bindings and editors can send it messages, switch its provider,
+//! export the open conversation or ask what it's doing. `animated-cli control`
+//! is the client; anything that can write a line of JSON to a unix socket
+//! can be one too:
+//!
+//! ```sh
+//! echo '{"type":"send","text":"hello","wait":true}' | socat - UNIX-CONNECT:$SOCKET
+//! ```
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+use crate::config::Config;
+
+/// Longest a request line may be, so a stray client can't fill memory
+const MAX_REQUEST_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Args, Debug)]
+pub struct ControlArgs {
+    #[command(subcommand)]
+    action: ControlAction,
+
+    /// Socket of a chat started with --control, instead of the profile's
+    #[arg(long, global = true, value_name = "PATH")]
+    socket: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum ControlAction {
+    /// Show the running chat's provider, conversation and whether it's waiting on a reply
+    Status,
+    /// Send a message as if typed into the chat
+    Send {
+        /// What to send
+        text: String,
+        /// Wait for the reply and print it
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Switch the chat to another provider (claude, grok, gpt, gemini)
+    Provider { name: String },
+    /// Write the open conversation to a file
+    Export {
+        /// File to create
+        path: PathBuf,
+        /// json, jsonl, sharegpt, html, markdown, pdf or rated
+        #[arg(long, default_value = "json")]
+        format: String,
+    },
+}
+
+/// One request per connection, as a line of JSON
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Send {
+        text: String,
+        /// Answer with the reply instead of as soon as it's sent
+        #[serde(default)]
+        wait: bool,
+    },
+    Provider { name: String },
+    Export {
+        path: PathBuf,
+        #[serde(default = "default_format")]
+        format: String,
+    },
+    Status,
+}
+
+fn default_format() -> String {
+    "json".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Sent,
+    Reply { content: String },
+    Switched { provider: String },
+    Exported { path: PathBuf, messages: usize },
+    Status(Status),
+    Error { message: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    pub profile: String,
+    pub provider: String,
+    /// None until the conversation's first message is saved
+    pub conversation_id: Option<i64>,
+    pub title: String,
+    pub messages: usize,
+    pub waiting: bool,
+}
+
+/// A request waiting for the chat to answer it on `reply`
+pub struct Pending {
+    pub request: Request,
+    pub reply: oneshot::Sender<Response>,
+}
+
+/// The chat's end of the socket
+pub struct Control {
+    path: PathBuf,
+    pending: UnboundedReceiver<Pending>,
+}
+
+impl Control {
+    /// Listen on `path`, unless another chat already is
+    pub fn start(path: PathBuf) -> Result<Self> {
+        if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+            bail!("Another chat is already listening on {}", path.display());
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        // Left behind by a chat that didn't shut down cleanly
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).with_context(|| format!("Failed to listen on {}", path.display()))?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+        let (pending_tx, pending) = mpsc::unbounded_channel();
+        tokio::spawn(accept(listener, pending_tx));
+        tracing::info!(socket = %path.display(), "Listening for control requests");
+        Ok(Self { path, pending })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Requests that came in since last asked
+    pub fn requests(&mut self) -> Vec<Pending> {
+        let mut requests = Vec::new();
+        while let Ok(request) = self.pending.try_recv() {
+            requests.push(request);
+        }
+        requests
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The profile's control socket, beside its daemon socket
+pub fn socket_path(profile: Option<&str>) -> Result<PathBuf> {
+    Ok(crate::daemon::socket_path(profile)?.with_file_name("control.sock"))
+}
+
+async fn accept(listener: UnixListener, pending: UnboundedSender<Pending>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let pending = pending.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, pending).await {
+                        tracing::warn!("Control connection: {:#}", e);
+                    }
+                });
+            }
+            Err(e) => tracing::warn!("Failed to accept a control connection: {}", e),
+        }
+        if pending.is_closed() {
+            return;
+        }
+    }
+}
+
+/// Read the request, hand it to the chat and write back its answer
+async fn serve(stream: UnixStream, pending: UnboundedSender<Pending>) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut line = String::new();
+    let read = BufReader::new(read).take(MAX_REQUEST_BYTES).read_line(&mut line).await?;
+    if read == 0 {
+        return Ok(());
+    }
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => {
+            let (reply, answer) = oneshot::channel();
+            if pending.send(Pending { request, reply }).is_err() {
+                return Ok(());
+            }
+            match answer.await {
+                Ok(response) => response,
+                // The chat dropped it: quitting, or the reply never came
+                Err(_) => Response::Error { message: "The chat closed before answering".to_string() },
+            }
+        }
+        Err(e) => Response::Error { message: format!("The request didn't parse: {}", e) },
+    };
+    let mut line = serde_json::to_vec(&response)?;
+    line.push(b'\n');
+    write.write_all(&line).await?;
+    Ok(())
+}
+
+/// Send one request to the chat on `socket` and read its answer
+pub async fn request(socket: &Path, request: &Request) -> Result<Response> {
+    let mut connection = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("No chat is listening on {}", socket.display()))?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    connection.write_all(&line).await.context("Failed to send to the chat")?;
+    let mut answer = String::new();
+    BufReader::new(connection)
+        .read_line(&mut answer)
+        .await
+        .context("Failed to read the chat's answer")?;
+    if answer.is_empty() {
+        bail!("The chat closed the connection without answering");
+    }
+    serde_json::from_str(&answer).context("The chat's answer didn't parse")
+}
+
+/// Run `animated-cli control`
+pub async fn run(args: ControlArgs, config: &Config) -> Result<()> {
+    let socket = match args.socket {
+        Some(socket) => socket,
+        None => socket_path(config.profile.as_deref())?,
+    };
+    let sent = match args.action {
+        ControlAction::Status => Request::Status,
+        ControlAction::Send { text, wait } => Request::Send { text, wait },
+        ControlAction::Provider { name } => Request::Provider { name },
+        ControlAction::Export { path, format } => {
+            // The chat may be running somewhere else
+            let path = std::path::absolute(&path).with_context(|| format!("Bad path {}", path.display()))?;
+            Request::Export { path, format }
+        }
+    };
+    match request(&socket, &sent).await? {
+        Response::Sent => {}
+        Response::Reply { content } => println!("{}", content),
+        Response::Switched { provider } => println!("Switched to {}", provider),
+        Response::Exported { path, messages } => println!("Exported {} messages to {}", messages, path.display()),
+        Response::Status(status) => {
+            println!("Running as pid {} for the {} profile", status.pid, status.profile);
+            println!("Provider: {}", status.provider);
+            let id = status.conversation_id.map_or_else(|| "unsaved".to_string(), |id| format!("#{}", id));
+            let title = if status.title.is_empty() { "Untitled" } else { &status.title };
+            println!("Conversation: {} ({}, {} messages)", title, id, status.messages);
+            if status.waiting {
+                println!("Waiting on a reply");
+            }
+        }
+        Response::Error { message } => bail!(message),
+    }
+    Ok(())
+}
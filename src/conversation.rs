@@ -0,0 +1,587 @@
+//! The chat's conversations apart from the screen: the one open with each
+//! provider, read from the store a page at a time and saved to it as
+//! messages come, and the history each new message is sent with.
+//! `chat::ChatInterface` draws them and `outbox` sends them.
+
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+
+use crate::capabilities::Capabilities;
+use crate::providers::{AIProvider, ChatSettings, Message, Usage};
+use crate::ratelimit::estimate_text;
+use crate::store::{self, Database, Rating};
+
+/// Longest title taken from a conversation's first message
+const AUTO_TITLE_CHARS: usize = 40;
+
+/// Messages read when a conversation is opened, and again each time
+/// scrolling up reaches the first of them
+const HISTORY_PAGE: usize = 200;
+
+/// Tokens of history sent to a model whose context window isn't known
+const DEFAULT_WINDOW: u64 = 128_000;
+
+#[derive(Debug, Clone)]
+pub enum MessageRole {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    /// Database row; None for system messages and when history is unavailable
+    pub id: Option<i64>,
+    pub role: MessageRole,
+    pub content: String,
+    /// When it was sent, in Unix seconds
+    pub timestamp: i64,
+    pub is_system: bool,
+    pub starred: bool,
+    pub rating: Option<Rating>,
+    pub note: Option<String>,
+    /// Who wrote a message in a shared session, when it wasn't you
+    pub author: Option<String>,
+}
+
+/// The conversation currently shown for a provider
+#[derive(Debug, Clone, Default)]
+pub struct OpenConversation {
+    /// Database row, created when the first message is saved so that
+    /// unused `/new`s don't leave empty conversations behind
+    pub id: Option<i64>,
+    /// Empty until named by `/new` or the first message
+    pub title: String,
+    /// Saved with the conversation's row, so `/tag` works before the
+    /// first message
+    pub tags: Vec<String>,
+    pub settings: ChatSettings,
+    pub messages: Vec<ChatMessage>,
+    /// Read-only until `/unlock`: nothing more is sent to it
+    pub locked: bool,
+    /// The first message read's `(seq, id)`, while there are older ones
+    /// still in the database
+    earlier: Option<(i64, i64)>,
+    /// The messages before `earlier` as they're sent, read once when the
+    /// conversation is opened: as many of the newest as fit the model's
+    /// context window
+    backlog: Vec<Message>,
+    /// Why the backlog couldn't be read. Nothing is sent from the
+    /// conversation while it's set, rather than sending it cut short.
+    backlog_error: Option<String>,
+}
+
+impl OpenConversation {
+    /// A conversation that isn't saved yet; it's given its row when the
+    /// first message is
+    pub fn new(title: String, settings: ChatSettings, messages: Vec<ChatMessage>) -> Self {
+        Self { title, settings, messages, ..Self::default() }
+    }
+
+    /// A saved conversation as it's opened: its last page of messages,
+    /// the rest read as scrolling reaches them, and the backlog to send
+    pub fn load(db: &Database, conversation: &store::Conversation) -> Option<Self> {
+        let mut open = Self {
+            id: Some(conversation.id),
+            title: conversation.title.clone(),
+            tags: conversation.tags.clone(),
+            settings: db.conversation_settings(conversation.id).unwrap_or_default(),
+            locked: conversation.locked,
+            ..Self::default()
+        };
+        open.read_earlier(db, Some(HISTORY_PAGE)).ok()?;
+
+        let window = AIProvider::parse(&conversation.provider)
+            .map_or(DEFAULT_WINDOW, |provider| context_window(&provider, &open.settings));
+        match read_backlog(db, conversation.id, open.earlier, window) {
+            Ok(backlog) => open.backlog = backlog,
+            Err(e) => open.backlog_error = Some(format!("{:#}", e)),
+        }
+        Some(open)
+    }
+
+    /// Put up to `limit` (or all) of the messages before the first read
+    /// in front of it. Returns how many there were.
+    pub fn read_earlier(&mut self, db: &Database, limit: Option<usize>) -> Result<usize> {
+        let Some(id) = self.id else {
+            return Ok(0);
+        };
+        if !self.messages.is_empty() && self.earlier.is_none() {
+            return Ok(0);
+        }
+        // One more than asked for tells whether any are left after these
+        let mut read = db.get_messages_before(id, self.earlier, limit.map(|limit| limit + 1))?;
+        let more = limit.is_some_and(|limit| read.len() > limit);
+        if more {
+            read.remove(0);
+        }
+        self.earlier = read.first().filter(|_| more).map(|m| (m.seq, m.id));
+        let count = read.len();
+        self.messages.splice(0..0, read.into_iter().map(stored_message));
+        // Those are shown now, so sent from there
+        self.backlog.truncate(self.backlog.len().saturating_sub(count));
+        Ok(count)
+    }
+
+    /// Whether there are older messages in the database than the first
+    /// one read
+    pub fn has_earlier(&self) -> bool {
+        self.earlier.is_some()
+    }
+
+    /// Messages sent, saved ones not read into view yet included
+    pub fn count_messages(&self, db: Option<&Database>) -> usize {
+        let unread = match (db, self.id.zip(self.earlier)) {
+            (Some(db), Some((id, earlier))) => db.count_messages_before(id, earlier).unwrap_or(0),
+            _ => 0,
+        };
+        unread + self.messages.iter().filter(|m| !m.is_system).count()
+    }
+
+    /// The conversation as it's sent: the backlog not read into view yet,
+    /// then the messages shown, without notices. An error if the backlog
+    /// couldn't be read.
+    pub fn history(&self) -> Result<Vec<Message>> {
+        if let Some(ref e) = self.backlog_error {
+            bail!("Couldn't read the earlier messages to send: {}", e);
+        }
+        let shown = self.messages.iter().filter(|m| !m.is_system).map(|m| Message {
+            role: match m.role {
+                MessageRole::User => "user".to_string(),
+                MessageRole::Assistant => "assistant".to_string(),
+            },
+            content: m.content.clone(),
+        });
+        Ok(self.backlog.iter().cloned().chain(shown).collect())
+    }
+
+    /// A copy to carry on with elsewhere when it was never saved, so
+    /// there's nothing to copy in the database. Its title gets `suffix`.
+    pub fn unsaved_copy(&self, suffix: &str) -> Self {
+        let mut copy = Self::new(
+            format!("{}{}", self.title, suffix),
+            ChatSettings { model: None, ..self.settings.clone() },
+            self.messages.iter().filter(|m| !m.is_system).cloned().collect(),
+        );
+        copy.tags = self.tags.clone();
+        copy
+    }
+
+    /// Nothing's been started or opened in it, only notices shown
+    fn is_blank(&self) -> bool {
+        self.id.is_none() && self.title.is_empty() && self.messages.iter().all(|m| m.is_system)
+    }
+
+    /// Save a message, creating the conversation's row (and a title, if
+    /// it has none) on the first one. Returns the message's id and when
+    /// it was sent.
+    fn save(&mut self, db: &mut Database, provider: &str, role: &str, content: &str, usage: Option<&Usage>) -> Result<(i64, i64)> {
+        let id = match self.id {
+            Some(id) => id,
+            None => {
+                if self.title.is_empty() {
+                    self.title = auto_title(content);
+                }
+                let id = db.create_conversation(provider, &self.title)?;
+                self.id = Some(id);
+                if !self.tags.is_empty() {
+                    db.set_tags(id, &self.tags)?;
+                }
+                if self.settings != ChatSettings::default() {
+                    db.set_conversation_settings(id, &self.settings)?;
+                }
+                id
+            }
+        };
+        db.save_message(id, role, content, usage)
+    }
+}
+
+/// The conversation open with each provider, and the database they're
+/// saved in
+pub struct Conversations {
+    /// None when the database couldn't be opened; conversations still
+    /// work but nothing is saved
+    db: Option<Database>,
+    open: HashMap<String, OpenConversation>,
+    /// Providers whose last conversation has been looked for, so each is
+    /// only read from the database once, when it's first shown
+    loaded: HashSet<String>,
+    /// Last conversations read on another thread, by provider
+    loaded_rx: mpsc::UnboundedReceiver<(String, Option<OpenConversation>)>,
+    loaded_tx: mpsc::UnboundedSender<(String, Option<OpenConversation>)>,
+}
+
+impl Conversations {
+    pub fn new(db: Option<Database>) -> Self {
+        let (loaded_tx, loaded_rx) = mpsc::unbounded_channel();
+        Self {
+            db,
+            open: HashMap::new(),
+            loaded: HashSet::new(),
+            loaded_rx,
+            loaded_tx,
+        }
+    }
+
+    pub fn db(&self) -> Option<&Database> {
+        self.db.as_ref()
+    }
+
+    pub fn db_mut(&mut self) -> Option<&mut Database> {
+        self.db.as_mut()
+    }
+
+    /// Close every conversation and save to `db` from now on, as
+    /// switching profile does. Anything still being read is from the old
+    /// database and is dropped.
+    pub fn reset(&mut self, db: Option<Database>) {
+        *self = Self::new(db);
+    }
+
+    pub fn get(&self, provider: &AIProvider) -> Option<&OpenConversation> {
+        self.open.get(provider.db_name())
+    }
+
+    /// The provider's open conversation, a new one if there's none
+    pub fn open_mut(&mut self, provider: &AIProvider) -> &mut OpenConversation {
+        self.open.entry(provider.db_name().to_string()).or_default()
+    }
+
+    /// Every open conversation saved as `id`, to follow a change made to
+    /// it elsewhere
+    pub fn saved_as(&mut self, id: i64) -> impl Iterator<Item = &mut OpenConversation> {
+        self.open.values_mut().filter(move |c| c.id == Some(id))
+    }
+
+    /// Reopen the provider's most recently active conversation the first
+    /// time it's shown. It's read on another thread where the database
+    /// allows, and taken up by `take_loaded`.
+    pub fn load_latest(&mut self, provider: &AIProvider) {
+        let provider = provider.db_name().to_string();
+        if !self.loaded.insert(provider.clone()) {
+            return;
+        }
+        let Some(ref db) = self.db else {
+            return;
+        };
+        let Some(reader) = db.reader() else {
+            let latest = db.latest_conversation(&provider).ok().flatten();
+            if let Some(conversation) = latest.and_then(|latest| OpenConversation::load(db, &latest)) {
+                self.adopt(provider, conversation);
+            }
+            return;
+        };
+        let tx = self.loaded_tx.clone();
+        std::thread::spawn(move || {
+            let latest = reader.latest_conversation(&provider).ok().flatten();
+            let _ = tx.send((provider, latest.and_then(|latest| OpenConversation::load(&reader, &latest))));
+        });
+    }
+
+    /// Take up the conversations `load_latest` has read since last time.
+    /// Returns whether there were any.
+    pub fn take_loaded(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok((provider, conversation)) = self.loaded_rx.try_recv() {
+            if let Some(conversation) = conversation {
+                self.adopt(provider, conversation);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Show a provider's reopened conversation, unless one was started or
+    /// opened there while it was read. Notices shown in the meantime stay,
+    /// after it.
+    fn adopt(&mut self, provider: String, mut conversation: OpenConversation) {
+        let open = self.open.entry(provider).or_default();
+        if !open.is_blank() {
+            return;
+        }
+        conversation.messages.append(&mut open.messages);
+        *open = conversation;
+    }
+
+    /// A saved conversation as it's opened, read back as far as the
+    /// message `focus` if given. None if it couldn't be read.
+    pub fn open_saved(&self, chosen: &store::Conversation, focus: Option<i64>) -> Option<OpenConversation> {
+        let db = self.db.as_ref()?;
+        let mut conversation = OpenConversation::load(db, chosen)?;
+        while focus.is_some_and(|id| !conversation.messages.iter().any(|m| m.id == Some(id))) {
+            if !matches!(conversation.read_earlier(db, Some(HISTORY_PAGE)), Ok(1..)) {
+                break;
+            }
+        }
+        Some(conversation)
+    }
+
+    /// A saved conversation with every one of its messages read, to play
+    /// back
+    pub fn read_whole(&self, chosen: &store::Conversation) -> Result<OpenConversation> {
+        let Some(ref db) = self.db else {
+            bail!("Conversation history is unavailable");
+        };
+        let mut conversation = OpenConversation::load(db, chosen).unwrap_or_default();
+        conversation.read_earlier(db, None)?;
+        Ok(conversation)
+    }
+
+    /// The provider's conversation with every one of its messages read,
+    /// without changing the one shown
+    pub fn read_open(&self, provider: &AIProvider) -> Result<OpenConversation> {
+        let mut conversation = self.get(provider).cloned().unwrap_or_default();
+        if let Some(ref db) = self.db {
+            conversation.read_earlier(db, None)?;
+        }
+        Ok(conversation)
+    }
+
+    /// Read the page before the first message of the provider's
+    /// conversation, if there's one left. Returns how many messages it
+    /// had.
+    pub fn read_earlier(&mut self, provider: &AIProvider) -> Result<usize> {
+        let (Some(db), Some(open)) = (self.db.as_ref(), self.open.get_mut(provider.db_name())) else {
+            return Ok(0);
+        };
+        if !open.has_earlier() {
+            return Ok(0);
+        }
+        open.read_earlier(db, Some(HISTORY_PAGE))
+    }
+
+    /// Copy the saved conversation `id` to `target` with `suffix` on its
+    /// title, opened there. Ok(None) if the copy can't be read back.
+    pub fn copy_to(&mut self, id: i64, target: &AIProvider, suffix: &str) -> Result<Option<OpenConversation>> {
+        let Some(ref mut db) = self.db else {
+            return Ok(None);
+        };
+        let copy = db.copy_to_provider(id, target.db_name(), suffix)?;
+        Ok(db.get_conversation(copy)?.and_then(|conversation| OpenConversation::load(db, &conversation)))
+    }
+
+    /// The provider's conversation as it's sent; see `OpenConversation::history`
+    pub fn history(&self, provider: &AIProvider) -> Result<Vec<Message>> {
+        self.get(provider).map_or(Ok(vec![]), OpenConversation::history)
+    }
+
+    /// Save a message to the provider's conversation. Ok(None) when
+    /// there's no database to save to; otherwise the message's id and
+    /// when it was sent.
+    pub fn save_message(
+        &mut self,
+        provider: &AIProvider,
+        role: &str,
+        content: &str,
+        usage: Option<&Usage>,
+    ) -> Result<Option<(i64, i64)>> {
+        let Some(ref mut db) = self.db else {
+            return Ok(None);
+        };
+        let open = self.open.entry(provider.db_name().to_string()).or_default();
+        open.save(db, provider.db_name(), role, content, usage).map(Some)
+    }
+}
+
+/// Tokens of history the provider's model takes with `settings`, or a
+/// generous guess when it isn't known
+pub fn context_window(provider: &AIProvider, settings: &ChatSettings) -> u64 {
+    let model = settings.model.as_deref().unwrap_or(provider.model());
+    Capabilities::of(provider, model).context_window.unwrap_or(DEFAULT_WINDOW)
+}
+
+/// The newest of `messages` that fit in `window` tokens along with the
+/// system prompt, starting with a user message as providers expect. The
+/// last is kept whatever its size.
+pub fn fit_window(mut messages: Vec<Message>, window: u64, system_prompt: Option<&str>) -> Vec<Message> {
+    let mut tokens = system_prompt.map_or(0, estimate_text) + messages.iter().map(|m| estimate_text(&m.content)).sum::<u64>();
+    let mut start = 0;
+    while tokens > window && start + 1 < messages.len() {
+        tokens -= estimate_text(&messages[start].content);
+        start += 1;
+    }
+    while start > 0 && start + 1 < messages.len() && messages[start].role != "user" {
+        start += 1;
+    }
+    messages.drain(..start);
+    messages
+}
+
+/// Name a conversation after the start of its first message
+pub fn auto_title(content: &str) -> String {
+    let line = content.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+    let mut title: String = line.chars().take(AUTO_TITLE_CHARS).collect();
+    if line.chars().count() > AUTO_TITLE_CHARS {
+        title.push('…');
+    }
+    title
+}
+
+/// The messages before `before`, as they're sent: the newest that fit in
+/// `window` tokens, read a page at a time until they're full
+fn read_backlog(db: &Database, id: i64, mut before: Option<(i64, i64)>, window: u64) -> Result<Vec<Message>> {
+    let (mut backlog, mut tokens) = (vec![], 0);
+    'pages: while let Some(cursor) = before {
+        let page = db.get_messages_before(id, Some(cursor), Some(HISTORY_PAGE))?;
+        before = page.first().filter(|_| page.len() == HISTORY_PAGE).map(|m| (m.seq, m.id));
+        for message in page.into_iter().rev() {
+            tokens += estimate_text(&message.content);
+            if tokens > window {
+                break 'pages;
+            }
+            backlog.push(Message { role: message.role, content: message.content });
+        }
+    }
+    backlog.reverse();
+    Ok(backlog)
+}
+
+/// A saved message as it's shown
+fn stored_message(message: store::Message) -> ChatMessage {
+    ChatMessage {
+        id: Some(message.id),
+        role: match message.role.as_str() {
+            "user" => MessageRole::User,
+            _ => MessageRole::Assistant,
+        },
+        content: message.content,
+        timestamp: message.timestamp,
+        is_system: false,
+        starred: message.starred,
+        rating: message.rating,
+        note: message.note,
+        author: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> Message {
+        Message { role: role.to_string(), content: content.to_string() }
+    }
+
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn fitting_the_window_drops_the_oldest_back_to_a_question() {
+        // Two tokens each
+        let messages = vec![
+            message("user", "q1q1q1q1"),
+            message("assistant", "a1a1a1a1"),
+            message("user", "q2q2q2q2"),
+            message("assistant", "a2a2a2a2"),
+            message("user", "q3q3q3q3"),
+        ];
+        assert_eq!(fit_window(messages.clone(), 10, None).len(), 5);
+        // Dropping q1 would leave a1 first, so it goes too
+        assert_eq!(contents(&fit_window(messages.clone(), 8, None)), ["q2q2q2q2", "a2a2a2a2", "q3q3q3q3"]);
+        // The system prompt takes its share
+        assert_eq!(contents(&fit_window(messages.clone(), 8, Some("system.."))), ["q2q2q2q2", "a2a2a2a2", "q3q3q3q3"]);
+        assert_eq!(contents(&fit_window(messages.clone(), 6, Some("system.."))), ["q3q3q3q3"]);
+        // The question being sent goes however big it is
+        assert_eq!(contents(&fit_window(messages, 0, None)), ["q3q3q3q3"]);
+    }
+
+    #[test]
+    fn the_backlog_is_the_newest_messages_before_the_page_that_fit() {
+        let db = Database::open_in_memory().unwrap();
+        let id = db.create_conversation("mock", "").unwrap();
+        for n in 1..=500 {
+            db.save_message(id, "user", &format!("{:04}", n), None).unwrap();
+        }
+        let shown = db.get_messages_before(id, None, Some(HISTORY_PAGE)).unwrap();
+        let cursor = shown.first().map(|m| (m.seq, m.id));
+
+        let backlog = read_backlog(&db, id, cursor, DEFAULT_WINDOW).unwrap();
+        assert_eq!(backlog.len(), 300);
+        assert_eq!((backlog[0].content.as_str(), backlog[299].content.as_str()), ("0001", "0300"));
+
+        // A token each, so a window of 250 takes the newest 250
+        let backlog = read_backlog(&db, id, cursor, 250).unwrap();
+        assert_eq!(contents(&backlog)[..2], ["0051", "0052"]);
+        assert_eq!(backlog.len(), 250);
+        assert!(read_backlog(&db, id, None, DEFAULT_WINDOW).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_conversation_is_saved_with_its_first_message_and_reopened_a_page_at_a_time() {
+        let mut conversations = Conversations::new(Some(Database::open_in_memory().unwrap()));
+        let provider = AIProvider::Mock;
+        conversations.open_mut(&provider).tags = vec!["work".to_string()];
+        assert!(conversations.get(&provider).unwrap().id.is_none(), "nothing saved before a message");
+
+        for n in 0..250 {
+            let role = if n % 2 == 0 { "user" } else { "assistant" };
+            conversations.save_message(&provider, role, &format!("Message {}", n), None).unwrap();
+        }
+        let open = conversations.get(&provider).unwrap();
+        assert_eq!(open.title, "Message 0");
+        let id = open.id.unwrap();
+
+        let db = conversations.db().unwrap();
+        let saved = db.get_conversation(id).unwrap().unwrap();
+        assert_eq!(saved.tags, ["work"]);
+        let mut reopened = OpenConversation::load(db, &saved).unwrap();
+        assert_eq!(reopened.messages.len(), HISTORY_PAGE);
+        assert!(reopened.has_earlier());
+        assert_eq!(reopened.count_messages(Some(db)), 250);
+
+        // Everything goes with the next message, shown or not
+        let history = reopened.history().unwrap();
+        assert_eq!(history.len(), 250);
+        assert_eq!((history[0].role.as_str(), history[0].content.as_str()), ("user", "Message 0"));
+
+        assert_eq!(reopened.read_earlier(db, Some(HISTORY_PAGE)).unwrap(), 50);
+        assert!(!reopened.has_earlier());
+        assert_eq!(reopened.history().unwrap().len(), 250, "read into view, not sent twice");
+    }
+
+    #[test]
+    fn nothing_is_sent_when_the_backlog_couldnt_be_read() {
+        let conversation = OpenConversation {
+            backlog_error: Some("disk I/O error".to_string()),
+            ..OpenConversation::default()
+        };
+        let error = conversation.history().unwrap_err();
+        assert_eq!(error.to_string(), "Couldn't read the earlier messages to send: disk I/O error");
+    }
+
+    #[test]
+    fn a_reopened_conversation_only_replaces_a_blank_one() {
+        let mut conversations = Conversations::new(None);
+        let saved = OpenConversation { id: Some(7), title: "Saved".to_string(), ..OpenConversation::default() };
+        let notice = ChatMessage {
+            id: None,
+            role: MessageRole::Assistant,
+            content: "notice".to_string(),
+            timestamp: 0,
+            is_system: true,
+            starred: false,
+            rating: None,
+            note: None,
+            author: None,
+        };
+
+        conversations.open_mut(&AIProvider::Mock).messages.push(notice);
+        conversations.adopt("mock".to_string(), saved.clone());
+        let open = conversations.get(&AIProvider::Mock).unwrap();
+        assert_eq!((open.id, open.messages.len()), (Some(7), 1), "the notice stays after it");
+
+        conversations.open_mut(&AIProvider::Claude).title = "Started meanwhile".to_string();
+        conversations.adopt("claude".to_string(), saved);
+        assert_eq!(conversations.get(&AIProvider::Claude).unwrap().id, None);
+    }
+
+    #[test]
+    fn titles_come_from_the_first_line_that_says_anything() {
+        assert_eq!(auto_title("\n  Hello there \nmore"), "Hello there");
+        let long = "x".repeat(AUTO_TITLE_CHARS + 5);
+        assert_eq!(auto_title(&long), format!("{}…", "x".repeat(AUTO_TITLE_CHARS)));
+        assert_eq!(auto_title(""), "");
+    }
+}
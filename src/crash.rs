@@ -0,0 +1,279 @@
+//! Crash reports: when the main thread panics, after the terminal is put
+//! back and the panic message printed, the backtrace, the platform, the
+//! terminal and the config the chat started with are written to a file in
+//! the data directory, with keys, webhooks, personal text and the home
+//! directory left out (and anything secret-looking in the panic message
+//! masked), and its path printed. At a terminal it offers to open a GitHub
+//! issue with the details filled in.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::io::{BufRead, IsTerminal, Write as _};
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::config::{Config, RedactConfig};
+use crate::models::key_source;
+use crate::providers::AIProvider;
+use crate::redact::{self, Redactor};
+
+/// The config part of the report, made when the hook is installed
+static CONFIG_SUMMARY: OnceLock<String> = OnceLock::new();
+
+/// What the panic message is scrubbed with: the redaction patterns, and
+/// the keys and tokens `/share` blanks
+static SCRUBBER: OnceLock<(Redactor, Vec<String>)> = OnceLock::new();
+
+/// Config keys whose values are never written out, by any part of their name
+const SECRET_KEYS: [&str; 6] = ["key", "token", "secret", "password", "url", "webhook"];
+
+/// Config tables of the user's own text, written out only as its length
+const PRIVATE_TABLES: [&str; 3] = ["personas", "templates", "macros"];
+
+/// Write a crash report whenever the main thread panics. Call before the
+/// terminal is entered, so its hook restores the screen first.
+pub fn install(config: &Config) {
+    let _ = CONFIG_SUMMARY.set(config_summary(config));
+    // Looked for even where outgoing messages aren't
+    let patterns = RedactConfig { action: "mask".to_string(), ..config.redact.clone() };
+    let redactor = Redactor::new(&patterns).unwrap_or_default();
+    let _ = SCRUBBER.set((redactor, crate::paste::secrets(&config.api_keys, &config.paste)));
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        // Other threads' panics are logged and the chat carries on
+        if std::thread::current().name() != Some("main") {
+            return;
+        }
+        match write_report(info) {
+            Ok(path) => {
+                eprintln!("\nMEGA-CLI crashed. A report, without your keys or settings' text, is in");
+                eprintln!("  {}", path.display());
+                offer_issue(info);
+            }
+            Err(e) => eprintln!("\nMEGA-CLI crashed, and couldn't write a crash report: {:#}", e),
+        }
+    }));
+}
+
+fn crash_dir() -> Result<PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "mega-cli")
+        .ok_or_else(|| anyhow!("Couldn't find a data directory for crash reports"))?;
+    Ok(dirs.data_dir().join("crashes"))
+}
+
+fn write_report(info: &PanicHookInfo) -> Result<PathBuf> {
+    let dir = crash_dir()?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("crash-{}.txt", Local::now().format("%Y%m%d-%H%M%S")));
+    std::fs::write(&path, report(info)).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+fn report(info: &PanicHookInfo) -> String {
+    let mut report = String::from("MEGA-CLI crash report\n\n");
+    let _ = writeln!(report, "{}", about(info));
+    let _ = writeln!(report, "Time      {}", Local::now().format("%Y-%m-%d %H:%M:%S %z"));
+    let _ = writeln!(report, "Terminal  {}", terminal());
+    if let Ok(dir) = crate::logging::log_dir() {
+        let _ = writeln!(report, "Logs      {}", redact_home(&dir.display().to_string()));
+    }
+    let _ = writeln!(report, "\n{}", CONFIG_SUMMARY.get().map_or("Config    not loaded yet", String::as_str));
+    let _ = writeln!(report, "\nBacktrace\n{}", redact_home(&Backtrace::force_capture().to_string()));
+    report
+}
+
+/// What broke and where, and what it ran on: the report's opening lines,
+/// and the issue's
+fn about(info: &PanicHookInfo) -> String {
+    let mut features = vec![];
+    for (name, on) in [
+        ("video", cfg!(feature = "video")),
+        ("ffmpeg", cfg!(feature = "ffmpeg")),
+        ("gif", cfg!(feature = "gif")),
+        ("audio", cfg!(feature = "audio")),
+        ("encryption", cfg!(feature = "encryption")),
+        ("scripting", cfg!(feature = "scripting")),
+        ("pdf", cfg!(feature = "pdf")),
+    ] {
+        if on {
+            features.push(name);
+        }
+    }
+    let location = info.location().map_or("unknown".to_string(), |at| format!("{}:{}:{}", at.file(), at.line(), at.column()));
+    format!(
+        "Version   {} ({})\nOS        {} {}\nPanic     {}\nAt        {}",
+        env!("CARGO_PKG_VERSION"),
+        if features.is_empty() { "no optional features".to_string() } else { features.join(", ") },
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        scrub(info.payload_as_str().unwrap_or("(not a message)")),
+        location,
+    )
+}
+
+/// The terminal's name and size, as far as the environment says
+fn terminal() -> String {
+    let mut parts: Vec<String> = ["TERM", "TERM_PROGRAM", "COLORTERM"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok().map(|value| format!("{}={}", var, value)))
+        .collect();
+    if std::env::var_os("WT_SESSION").is_some() {
+        parts.push("Windows Terminal".to_string());
+    }
+    if let Ok((width, height)) = crossterm::terminal::size() {
+        parts.push(format!("{}x{}", width, height));
+    }
+    if parts.is_empty() {
+        "unknown".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// Which providers have a key, and the config as set, with anything
+/// secret or personal replaced
+fn config_summary(config: &Config) -> String {
+    let mut summary = format!("Profile   {}\n", if config.profile.is_some() { "named (not the default)" } else { "default" });
+    let keys: Vec<String> = AIProvider::ALL
+        .iter()
+        .map(|provider| {
+            let set = if key_source(provider, config).is_some() { "set" } else { "not set" };
+            format!("{} {}", provider.db_name(), set)
+        })
+        .collect();
+    let _ = writeln!(summary, "API keys  {}", keys.join(", "));
+    let changed: Vec<String> = crate::reload::changed_keys(&Config::default(), config)
+        .into_iter()
+        .filter(|key| key != "api_keys")
+        .collect();
+    let _ = writeln!(
+        summary,
+        "Changed   {}",
+        if changed.is_empty() { "nothing; all defaults".to_string() } else { changed.join(", ") }
+    );
+    let config = toml::Value::try_from(config)
+        .map(|mut value| {
+            redact(&mut value);
+            toml::to_string_pretty(&value).unwrap_or_default()
+        })
+        .unwrap_or_else(|e| format!("(couldn't be written out: {})", e));
+    let _ = write!(summary, "\nConfig\n{}", config.trim_end());
+    summary
+}
+
+/// Blank out secret values and the user's own text, and the home
+/// directory in paths
+fn redact(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                let key = key.to_lowercase();
+                if PRIVATE_TABLES.contains(&key.as_str()) {
+                    if let toml::Value::Table(entries) = value {
+                        for (_, text) in entries.iter_mut() {
+                            *text = toml::Value::String(format!("<{} characters>", text_length(text)));
+                        }
+                    }
+                } else if SECRET_KEYS.iter().any(|secret| key.contains(secret)) && !value.is_bool() {
+                    *value = toml::Value::String("<redacted>".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        toml::Value::Array(items) => items.iter_mut().for_each(redact),
+        toml::Value::String(text) => *text = redact_home(text),
+        // Settings are f32s, which widen to 0.30000001192092896
+        toml::Value::Float(number) => *number = (*number as f32).to_string().parse().unwrap_or(*number),
+        _ => {}
+    }
+}
+
+/// Characters of text in a value, a macro's table of them included
+fn text_length(value: &toml::Value) -> usize {
+    match value {
+        toml::Value::String(text) => text.chars().count(),
+        toml::Value::Table(table) => table.values().map(text_length).sum(),
+        _ => 0,
+    }
+}
+
+/// `~` for the home directory, which usually has the user's name in it
+pub fn redact_home(text: &str) -> String {
+    match directories::BaseDirs::new() {
+        Some(dirs) => {
+            let home = dirs.home_dir().display().to_string();
+            if home.len() > 1 { text.replace(&home, "~") } else { text.to_string() }
+        }
+        None => text.to_string(),
+    }
+}
+
+/// Ask whether to open a GitHub issue about the crash, filled in with
+/// what broke and where; the report itself is for attaching
+fn offer_issue(info: &PanicHookInfo) {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return;
+    }
+    eprint!("Open a GitHub issue about it, with the details filled in? [y/N] ");
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() || !answer.trim().eq_ignore_ascii_case("y") {
+        return;
+    }
+    let body = format!(
+        "**What I was doing:**\n\n\n**Crash:**\n```\n{}\n```\n\nThe crash report is attached.",
+        about(info)
+    );
+    let url = match reqwest::Url::parse_with_params(
+        &format!("{}/issues/new", crate::update::REPOSITORY),
+        &[("title", format!("Crash: {}", first_line(info))), ("body", body)],
+    ) {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    if open_in_browser(url.as_str()).is_err() {
+        eprintln!("Couldn't open a browser; the issue is at\n  {}", url);
+    } else {
+        eprintln!("Please attach the crash report to the issue.");
+    }
+}
+
+fn first_line(info: &PanicHookInfo) -> String {
+    let message = info.payload_as_str().unwrap_or("panic");
+    let line = scrub(message.lines().next().unwrap_or(message));
+    line.chars().take(80).collect()
+}
+
+/// A panic message as it's written out. Some quote what they were working
+/// on, which can be chat text or a pasted key, so anything that looks
+/// secret is masked, and the home directory.
+fn scrub(message: &str) -> String {
+    let findings = match SCRUBBER.get() {
+        Some((redactor, secrets)) => redactor.find(message, secrets),
+        None => Redactor::default().find(message, &[]),
+    };
+    redact_home(&redact::mask(message, &findings))
+}
+
+fn open_in_browser(url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        // Not `cmd /C start`, which would split the URL at each `&`
+        let mut command = std::process::Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    let status = command.arg(url).status()?;
+    if !status.success() {
+        anyhow::bail!("the browser opener exited with {}", status);
+    }
+    Ok(())
+}
@@ -0,0 +1,413 @@
+//! `animated-cli daemon`: a background process that keeps provider connections
+//! warm and the database open. The chat and `ask` send through its unix
+//! socket whenever it's running, skipping the TLS handshakes and database
+//! open a cold start pays for.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Notify;
+
+use crate::providers::{AIClient, AIProvider, ChatSettings, Message, Reply, Usage};
+use crate::config::{Config, DEFAULT_PROFILE};
+use crate::ratelimit::RateLimits;
+use crate::store::Database;
+
+/// How long `daemon start` waits for the new daemon to answer
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest a request line may be, so a stray client can't fill memory
+const MAX_REQUEST_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Args, Debug)]
+pub struct DaemonArgs {
+    #[command(subcommand)]
+    action: DaemonAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum DaemonAction {
+    /// Start the daemon in the background
+    Start,
+    /// Run the daemon in the foreground (for systemd, launchd and the like)
+    Run,
+    /// Say whether the daemon is running, and what it has served
+    Status,
+    /// Stop the daemon
+    Stop,
+}
+
+/// One request per connection, as a line of JSON
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Send {
+        provider: String,
+        /// The client's key, so the reply is the same as sending directly;
+        /// None uses the daemon's own
+        api_key: Option<String>,
+        messages: Vec<Message>,
+        /// As sent, with the persona already resolved
+        settings: Box<ChatSettings>,
+        save: Option<Box<SaveAs>>,
+    },
+    Status,
+    Stop,
+}
+
+/// Save the exchange as a new conversation, if the daemon holds this
+/// database open
+#[derive(Serialize, Deserialize)]
+pub struct SaveAs {
+    pub db: PathBuf,
+    pub title: String,
+    /// Saved with the conversation, persona unresolved
+    pub settings: ChatSettings,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response {
+    Reply {
+        content: String,
+        usage: Usage,
+        conversation_id: Option<i64>,
+        save_error: Option<String>,
+    },
+    Status(Status),
+    Stopping,
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Status {
+    pid: u32,
+    profile: String,
+    db: Option<PathBuf>,
+    uptime_secs: u64,
+    requests: u64,
+}
+
+/// What came back from a forwarded request
+pub struct Forwarded {
+    pub reply: Reply,
+    /// Where the daemon saved the exchange; None if it wasn't asked to, or
+    /// doesn't hold that database
+    pub conversation_id: Option<i64>,
+    /// Why saving failed, when it tried
+    pub save_error: Option<String>,
+}
+
+struct Daemon {
+    config: Config,
+    /// Shared by every provider, so connections stay pooled between requests
+    http: reqwest::Client,
+    /// `[rate_limits]`, over every client's requests
+    rate_limits: RateLimits,
+    db_path: PathBuf,
+    /// None if the database couldn't be opened; clients then save themselves
+    db: Option<Mutex<Database>>,
+    started: Instant,
+    requests: AtomicU64,
+    stop: Notify,
+}
+
+/// The profile's socket: in the runtime directory where there is one, the
+/// data directory otherwise
+pub fn socket_path(profile: Option<&str>) -> Result<PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "mega-cli")
+        .ok_or_else(|| anyhow!("Couldn't find a directory for the daemon socket"))?;
+    let dir = dirs.runtime_dir().unwrap_or(dirs.data_dir());
+    Ok(match profile {
+        Some(name) => dir.join("profiles").join(name).join("daemon.sock"),
+        None => dir.join("daemon.sock"),
+    })
+}
+
+/// A connection to the daemon, or None when none is listening
+pub fn connect(socket: &Path) -> Option<UnixStream> {
+    let stream = std::os::unix::net::UnixStream::connect(socket).ok()?;
+    stream.set_nonblocking(true).ok()?;
+    UnixStream::from_std(stream).ok()
+}
+
+/// Have the daemon send a request, and save the exchange if asked to
+pub async fn forward(
+    connection: UnixStream,
+    provider: &AIProvider,
+    api_key: Option<String>,
+    messages: Vec<Message>,
+    settings: &ChatSettings,
+    save: Option<SaveAs>,
+) -> Result<Forwarded> {
+    let request = Request::Send {
+        provider: provider.db_name().to_string(),
+        api_key,
+        messages,
+        settings: Box::new(settings.clone()),
+        save: save.map(Box::new),
+    };
+    match exchange(connection, &request).await? {
+        Response::Reply { content, usage, conversation_id, save_error } => Ok(Forwarded {
+            reply: Reply { content, usage },
+            conversation_id,
+            save_error,
+        }),
+        Response::Error { message } => Err(anyhow!(message)),
+        _ => bail!("The daemon answered with something other than a reply"),
+    }
+}
+
+async fn exchange(mut connection: UnixStream, request: &Request) -> Result<Response> {
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    connection.write_all(&line).await.context("Failed to send to the daemon")?;
+    let mut answer = String::new();
+    BufReader::new(connection)
+        .read_line(&mut answer)
+        .await
+        .context("Failed to read the daemon's answer")?;
+    if answer.is_empty() {
+        bail!("The daemon closed the connection without answering");
+    }
+    serde_json::from_str(&answer).context("The daemon's answer didn't parse")
+}
+
+/// Run `animated-cli daemon`
+pub async fn run(args: DaemonArgs, db_path: &Path, config: &Config, log_level: Option<&str>) -> Result<()> {
+    let socket = socket_path(config.profile.as_deref())?;
+    match args.action {
+        DaemonAction::Start => start(&socket, db_path, config, log_level).await,
+        DaemonAction::Run => serve(&socket, db_path, config).await,
+        DaemonAction::Status => {
+            let Some(Status { pid, profile, db, uptime_secs, requests }) = status(&socket).await? else {
+                bail!("Not running (no daemon on {})", socket.display());
+            };
+            println!("Running as pid {} for the {} profile, up {}", pid, profile, uptime(uptime_secs));
+            match db {
+                Some(db) => println!("Holding {} open", db.display()),
+                None => println!("Not holding the database open"),
+            }
+            println!("{} requests served; socket {}", requests, socket.display());
+            Ok(())
+        }
+        DaemonAction::Stop => {
+            let connection = connect(&socket).ok_or_else(|| anyhow!("Not running"))?;
+            exchange(connection, &Request::Stop).await?;
+            println!("Stopped");
+            Ok(())
+        }
+    }
+}
+
+async fn status(socket: &Path) -> Result<Option<Status>> {
+    let Some(connection) = connect(socket) else {
+        return Ok(None);
+    };
+    match exchange(connection, &Request::Status).await? {
+        Response::Status(status) => Ok(Some(status)),
+        _ => bail!("The daemon answered with something other than its status"),
+    }
+}
+
+/// Launch `daemon run` detached from this terminal and wait for it to answer
+async fn start(socket: &Path, db_path: &Path, config: &Config, log_level: Option<&str>) -> Result<()> {
+    if let Some(status) = status(socket).await? {
+        println!("Already running as pid {}", status.pid);
+        return Ok(());
+    }
+    let mut command = Command::new(std::env::current_exe().context("Couldn't find this program to start it")?);
+    if let Some(ref profile) = config.profile {
+        command.args(["--profile", profile]);
+    }
+    if let Some(level) = log_level {
+        command.args(["--log-level", level]);
+    }
+    command.arg("--db").arg(db_path).args(["daemon", "run"]);
+    // Its own process group, so Ctrl+C here doesn't reach it
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("Failed to start the daemon")?;
+
+    let waited = Instant::now();
+    while waited.elapsed() < START_TIMEOUT {
+        if let Some(exit) = child.try_wait()? {
+            bail!("The daemon exited straight away ({}); `animated-cli daemon run` shows why", exit);
+        }
+        if let Some(status) = status(socket).await? {
+            println!("Started as pid {}; `animated-cli daemon stop` stops it", status.pid);
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    bail!("The daemon didn't answer within {} seconds", START_TIMEOUT.as_secs())
+}
+
+/// Listen on the socket until stopped, interrupted or terminated
+async fn serve(socket: &Path, db_path: &Path, config: &Config) -> Result<()> {
+    if status(socket).await?.is_some() {
+        bail!("A daemon is already running on {}", socket.display());
+    }
+    if let Some(dir) = socket.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // Left behind by one that didn't shut down cleanly
+    let _ = std::fs::remove_file(socket);
+    let listener = UnixListener::bind(socket).with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+
+    let db = match Database::open(db_path) {
+        Ok(db) => Some(Mutex::new(db)),
+        Err(e) => {
+            tracing::warn!("Daemon running without the database: {:#}", e);
+            None
+        }
+    };
+    let http = reqwest::Client::builder()
+        .pool_idle_timeout(None)
+        .tcp_keepalive(Duration::from_secs(60))
+        .build()
+        .context("Failed to set up the HTTP client")?;
+    let daemon = Arc::new(Daemon {
+        config: config.clone(),
+        http,
+        rate_limits: RateLimits::new(&config.rate_limits),
+        db_path: db_path.to_path_buf(),
+        db,
+        started: Instant::now(),
+        requests: AtomicU64::new(0),
+        stop: Notify::new(),
+    });
+    tracing::info!(socket = %socket.display(), "Daemon listening");
+    println!("Listening on {}; Ctrl+C stops", socket.display());
+
+    for provider in AIProvider::ALL {
+        if crate::models::key_source(&provider, config).is_some() {
+            let client = AIClient::with_http(provider, None, daemon.http.clone());
+            tokio::spawn(async move { client.warm_up().await });
+        }
+    }
+
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((connection, _)) => {
+                    let daemon = daemon.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle(&daemon, connection).await {
+                            tracing::warn!("Daemon request failed: {:#}", e);
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Daemon couldn't accept a connection: {}", e),
+            },
+            _ = daemon.stop.notified() => break,
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+    let _ = std::fs::remove_file(socket);
+    tracing::info!("Daemon stopped");
+    Ok(())
+}
+
+async fn handle(daemon: &Daemon, connection: UnixStream) -> Result<()> {
+    let (reader, mut writer) = connection.into_split();
+    let mut line = String::new();
+    tokio::io::AsyncReadExt::take(BufReader::new(reader), MAX_REQUEST_BYTES)
+        .read_line(&mut line)
+        .await?;
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => answer(daemon, request).await,
+        Err(e) => Response::Error { message: format!("Unreadable request: {}", e) },
+    };
+    let mut out = serde_json::to_vec(&response)?;
+    out.push(b'\n');
+    writer.write_all(&out).await?;
+    if let Response::Stopping = response {
+        daemon.stop.notify_one();
+    }
+    Ok(())
+}
+
+async fn answer(daemon: &Daemon, request: Request) -> Response {
+    match request {
+        Request::Status => Response::Status(Status {
+            pid: std::process::id(),
+            profile: daemon.config.profile.clone().unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+            db: daemon.db.as_ref().map(|_| daemon.db_path.clone()),
+            uptime_secs: daemon.started.elapsed().as_secs(),
+            requests: daemon.requests.load(Ordering::Relaxed),
+        }),
+        Request::Stop => Response::Stopping,
+        Request::Send { provider, api_key, messages, settings, save } => {
+            daemon.requests.fetch_add(1, Ordering::Relaxed);
+            let Some(provider) = AIProvider::parse(&provider) else {
+                return Response::Error { message: format!("Unknown provider: {}", provider) };
+            };
+            let key = api_key.or_else(|| daemon.config.api_keys.get(provider.api_key_env()).cloned());
+            let client = AIClient::with_http(provider.clone(), key, daemon.http.clone())
+                .with_rate_limit(daemon.rate_limits.for_provider(&provider));
+            let reply = match client.send_message(messages.clone(), &settings).await {
+                Ok(reply) => reply,
+                Err(e) => return Response::Error { message: format!("{:#}", e) },
+            };
+            let saved = save.and_then(|save| daemon.save(&provider, *save, &messages, &reply));
+            let (conversation_id, save_error) = match saved {
+                Some(Ok(id)) => (Some(id), None),
+                Some(Err(e)) => (None, Some(format!("{:#}", e))),
+                None => (None, None),
+            };
+            Response::Reply {
+                content: reply.content,
+                usage: reply.usage,
+                conversation_id,
+                save_error,
+            }
+        }
+    }
+}
+
+impl Daemon {
+    /// Save the exchange as a new conversation. None when this isn't the
+    /// database the client uses, so it saves for itself.
+    fn save(&self, provider: &AIProvider, save: SaveAs, messages: &[Message], reply: &Reply) -> Option<Result<i64>> {
+        let db = self.db.as_ref().filter(|_| save.db == self.db_path)?;
+        let db = db.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Some((|| {
+            let id = db.create_conversation(provider.db_name(), &save.title)?;
+            if save.settings != ChatSettings::default() {
+                db.set_conversation_settings(id, &save.settings)?;
+            }
+            for message in messages {
+                db.save_message(id, &message.role, &message.content, None)?;
+            }
+            db.save_message(id, "assistant", &reply.content, Some(&reply.usage))?;
+            Ok(id)
+        })())
+    }
+}
+
+fn uptime(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
@@ -0,0 +1,235 @@
+//! `animated-cli doctor`: check everything the chat depends on and say what
+//! to fix
+
+use anyhow::{bail, Result};
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::providers::AIProvider;
+use crate::config::Config;
+use crate::store::Database;
+use crate::models::key_source;
+
+/// Tallies problems as the checks print them
+#[derive(Default)]
+struct Report {
+    problems: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn ok(&self, what: &str, detail: impl AsRef<str>) {
+        println!("  ✓ {}: {}", what, detail.as_ref());
+    }
+
+    /// Works, but maybe not how you'd want
+    fn warn(&mut self, what: &str, detail: impl AsRef<str>) {
+        self.warnings += 1;
+        println!("  ! {}: {}", what, detail.as_ref());
+    }
+
+    /// Something won't work until this is fixed
+    fn fail(&mut self, what: &str, detail: impl AsRef<str>) {
+        self.problems += 1;
+        println!("  ✗ {}: {}", what, detail.as_ref());
+    }
+}
+
+/// Run `animated-cli doctor`. Exits non-zero if anything would stop the chat
+/// working.
+pub fn run(config: &Config, db_path: &Path) -> Result<()> {
+    let mut report = Report::default();
+
+    println!("Config");
+    check_config(&mut report, config);
+    println!("\nAPI keys");
+    check_keys(&mut report, config);
+    println!("\nDatabase");
+    check_database(&mut report, config, db_path);
+    #[cfg(feature = "video")]
+    {
+        println!("\nBackground");
+        check_background(&mut report, config);
+    }
+    println!("\nTerminal");
+    check_terminal(&mut report);
+
+    println!();
+    match (report.problems, report.warnings) {
+        (0, 0) => println!("Everything looks fine"),
+        (0, 1) => println!("No problems; 1 warning"),
+        (0, warnings) => println!("No problems; {} warnings", warnings),
+        (1, _) => bail!("1 problem found"),
+        (problems, _) => bail!("{} problems found", problems),
+    }
+    Ok(())
+}
+
+fn check_config(report: &mut Report, config: &Config) {
+    let profile = config.profile.as_deref();
+    report.ok("Profile", profile.unwrap_or(crate::config::DEFAULT_PROFILE));
+    match Config::path(profile) {
+        Ok(path) if !path.exists() => report.ok("Config file", format!("{} not found; using defaults", path.display())),
+        Ok(path) => match Config::load(profile) {
+            Ok(_) => report.ok("Config file", path.display().to_string()),
+            Err(e) => report.fail("Config file", format!("{:#}", e)),
+        },
+        Err(e) => report.fail("Config file", format!("{:#}", e)),
+    }
+    if let Some(ref name) = config.provider {
+        if AIProvider::parse(name).is_none() {
+            report.fail("provider", format!("unknown provider {:?} (expected claude, grok, gpt or gemini)", name));
+        }
+    }
+    let features = [
+        ("video", cfg!(feature = "video")),
+        ("ffmpeg", cfg!(feature = "ffmpeg")),
+        ("gif", cfg!(feature = "gif")),
+        ("audio", cfg!(feature = "audio")),
+        ("encryption", cfg!(feature = "encryption")),
+        ("scripting", cfg!(feature = "scripting")),
+        ("pdf", cfg!(feature = "pdf")),
+    ];
+    let built: Vec<&str> = features.iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect();
+    report.ok(
+        "Built with",
+        if built.is_empty() { "no optional features".to_string() } else { built.join(", ") },
+    );
+    #[cfg(feature = "scripting")]
+    match crate::hooks::Hooks::load(profile) {
+        Ok(Some(hooks)) if hooks.names().is_empty() => {
+            report.warn("Hooks", format!("{} defines no hooks", hooks.file().display()))
+        }
+        Ok(Some(hooks)) => report.ok("Hooks", format!("{}: {}", hooks.file().display(), hooks.names().join(", "))),
+        Ok(None) => {}
+        Err(e) => report.fail("Hooks", format!("{:#}", e)),
+    }
+}
+
+/// A missing key is only a problem for the provider the chat starts with
+fn check_keys(report: &mut Report, config: &Config) {
+    let default = config.provider.as_deref().and_then(AIProvider::parse).unwrap_or(AIProvider::Claude);
+    for provider in AIProvider::ALL {
+        let var = provider.api_key_env();
+        match key_source(&provider, config) {
+            Some(source) => report.ok(provider.name(), format!("{} from {}", var, source)),
+            None if provider == default => {
+                report.fail(provider.name(), format!("{} isn't set, and the chat starts with it", var))
+            }
+            None => report.warn(provider.name(), format!("{} isn't set", var)),
+        }
+    }
+    for key in crate::config::misnamed_keys(config) {
+        report.warn(&key.name, format!("{} (from {})", key.hint(), key.source));
+    }
+}
+
+fn check_database(report: &mut Report, config: &Config, db_path: &Path) {
+    let db = match Database::open(db_path) {
+        Ok(db) => db,
+        Err(e) => {
+            report.fail(&db_path.display().to_string(), format!("{:#}", e));
+            return;
+        }
+    };
+    match db.integrity_problems(false) {
+        Ok(problems) if problems.is_empty() => {
+            let conversations = db.list_conversations(None).map(|all| all.len()).unwrap_or(0);
+            report.ok(&db_path.display().to_string(), format!("intact, {} conversations", conversations));
+        }
+        Ok(problems) => report.fail(
+            &db_path.display().to_string(),
+            format!("damaged ({}); run `animated-cli repair`", problems.join("; ")),
+        ),
+        Err(e) => report.fail(&db_path.display().to_string(), format!("{:#}", e)),
+    }
+    if config.backup.enabled {
+        report.ok("Backups", format!("automatic, keeping {}", config.backup.keep));
+    } else {
+        report.warn("Backups", "automatic backups are off; set enabled = true under [backup]");
+    }
+}
+
+#[cfg(feature = "video")]
+fn check_background(report: &mut Report, config: &Config) {
+    let video = config.background.video.as_str();
+    if video == crate::background::BUILTIN_SOURCE || crate::background::webcam_device(video).is_some() {
+        report.ok("Video", video);
+    } else if crate::youtube::is_youtube_url(video) {
+        let ytdlp = &config.background.ytdlp;
+        match std::process::Command::new(ytdlp).arg("--version").output() {
+            Ok(output) if output.status.success() => report.ok("Video", format!("{} via {}", video, ytdlp)),
+            _ => report.fail("Video", format!("{} needs {}, which didn't run", video, ytdlp)),
+        }
+    } else if video.contains("://") {
+        report.ok("Video", format!("{} (streamed; not checked)", video));
+    } else if Path::new(video).exists() {
+        report.ok("Video", video);
+    } else {
+        report.warn("Video", format!("{} not found here; the chat falls back to a plain background", video));
+    }
+    #[cfg(all(windows, feature = "ffmpeg"))]
+    check_ffmpeg_dlls(report);
+}
+
+/// Windows finds the ffmpeg DLLs next to the program or on PATH, taking
+/// the first it comes to; another program's older copy earlier on PATH
+/// stops the chat starting, or breaks it oddly
+#[cfg(all(windows, feature = "ffmpeg"))]
+fn check_ffmpeg_dlls(report: &mut Report) {
+    let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut found: Vec<(std::path::PathBuf, String)> = Vec::new();
+    for dir in exe_dir.into_iter().chain(std::env::split_paths(&path)) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let dll = entries.filter_map(|entry| entry.ok()?.file_name().into_string().ok()).find(|name| {
+            let name = name.to_lowercase();
+            name.starts_with("avformat-") && name.ends_with(".dll")
+        });
+        if let Some(dll) = dll {
+            if !found.iter().any(|(seen, _)| *seen == dir) {
+                found.push((dir, dll));
+            }
+        }
+    }
+    match found.as_slice() {
+        [] => report.warn("FFmpeg", "no avformat DLL next to the program or on PATH; it was found some other way"),
+        [(dir, dll)] => report.ok("FFmpeg", format!("{} in {}", dll, dir.display())),
+        [(dir, dll), others @ ..] if others.iter().all(|(_, other)| other == dll) => {
+            report.ok("FFmpeg", format!("{} in {}", dll, dir.display()))
+        }
+        [(dir, dll), others @ ..] => report.warn(
+            "FFmpeg",
+            format!(
+                "using {} from {}; other versions are on PATH too ({}), so put the one it was built with first",
+                dll,
+                dir.display(),
+                others.iter().map(|(dir, dll)| format!("{} in {}", dll, dir.display())).collect::<Vec<_>>().join(", ")
+            ),
+        ),
+    }
+}
+
+fn check_terminal(report: &mut Report) {
+    if !std::io::stdout().is_terminal() {
+        report.warn("Output", "not a terminal; run the chat from one");
+        return;
+    }
+    match crossterm::terminal::size() {
+        Ok((columns, rows)) if columns >= 60 && rows >= 15 => report.ok("Size", format!("{}×{}", columns, rows)),
+        Ok((columns, rows)) => report.warn("Size", format!("{}×{} is cramped; 60×15 or more is best", columns, rows)),
+        Err(e) => report.warn("Size", format!("unknown ({})", e)),
+    }
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    if crate::terminal::sixteen_colors() {
+        report.warn("Colors", "this console has only 16 colors; Windows Terminal shows the background in full");
+    } else if colorterm.contains("truecolor") || colorterm.contains("24bit") {
+        report.ok("Colors", "24-bit");
+    } else if cfg!(windows) && std::env::var_os("WT_SESSION").is_some() {
+        report.ok("Colors", "24-bit (Windows Terminal)");
+    } else {
+        report.warn("Colors", "COLORTERM doesn't advertise 24-bit color; the background may look banded");
+    }
+}
@@ -0,0 +1,224 @@
+//! A/B evals: `/ab claude gpt/gpt-4.1` puts two models up against each
+//! other. Each message then goes to both on its own, and their answers
+//! come back side by side as Model A and Model B, in an order drawn at
+//! random each time. a or b picks the better one and t calls it a tie;
+//! only then does the chat say which was which. Votes are kept in the
+//! database, and `/ab` alone shows each provider's win rate.
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+use std::hash::BuildHasher;
+
+use crate::providers::AIProvider;
+use crate::store::EvalStanding;
+
+const HINTS: &str = "a A is better  b B is better  t Tie  ↑↓ Scroll  Esc Skip";
+const WAITING_HINTS: &str = "Waiting for both answers  Esc Skip";
+const FAILED_HINTS: &str = "Nothing to compare  Esc Close";
+
+const LABELS: [&str; 2] = ["Model A", "Model B"];
+
+/// A model in the running
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contender {
+    pub provider: AIProvider,
+    pub model: String,
+}
+
+impl Contender {
+    /// A contender as `/ab` takes it: a provider, `provider/model` or a
+    /// model id, with the open provider for an id it can't place
+    pub fn parse(name: &str, default: &AIProvider) -> Self {
+        let (provider, model) = AIProvider::route(name, default);
+        let model = model.unwrap_or_else(|| provider.model().to_string());
+        Self { provider, model }
+    }
+
+    /// provider/model
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.provider.db_name(), self.model)
+    }
+}
+
+/// The verdict on a round, as the user saw it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Vote {
+    A,
+    B,
+    Tie,
+}
+
+/// One prompt to both contenders, shown blinded until it's voted on
+pub struct EvalView {
+    id: u64,
+    prompt: String,
+    /// In the order `/ab` was given them
+    contenders: [Contender; 2],
+    /// Whether Model A is the second contender
+    swapped: bool,
+    /// Each contender's answer, or what went wrong, once it's in
+    answers: [Option<Result<String, String>>; 2],
+    scroll: u16,
+}
+
+impl EvalView {
+    pub fn new(id: u64, prompt: String, contenders: [Contender; 2]) -> Self {
+        let swapped = std::collections::hash_map::RandomState::new().hash_one(id) & 1 == 1;
+        Self {
+            id,
+            prompt,
+            contenders,
+            swapped,
+            answers: [None, None],
+            scroll: 0,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn prompt(&self) -> &str {
+        &self.prompt
+    }
+
+    pub fn contenders(&self) -> &[Contender; 2] {
+        &self.contenders
+    }
+
+    /// Take in a contender's answer, by its place in the pair
+    pub fn answer(&mut self, side: usize, answer: Result<String, String>) {
+        if let Some(slot) = self.answers.get_mut(side) {
+            *slot = Some(answer);
+        }
+    }
+
+    /// Both answers, if both came back
+    pub fn answers(&self) -> Option<[&str; 2]> {
+        match self.answers {
+            [Some(Ok(ref first)), Some(Ok(ref second))] => Some([first, second]),
+            _ => None,
+        }
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.answers.iter().any(Option::is_none)
+    }
+
+    /// The contender, by its place in the pair, shown as `label`'s model
+    fn side(&self, label: usize) -> usize {
+        label ^ self.swapped as usize
+    }
+
+    /// The side of the pair a vote went to; None for a tie
+    pub fn winner(&self, vote: Vote) -> Option<usize> {
+        match vote {
+            Vote::A => Some(self.side(0)),
+            Vote::B => Some(self.side(1)),
+            Vote::Tie => None,
+        }
+    }
+
+    /// Which contender was Model A and which Model B
+    pub fn reveal(&self) -> String {
+        format!(
+            "Model A was {}, Model B was {}",
+            self.contenders[self.side(0)].label(),
+            self.contenders[self.side(1)].label()
+        )
+    }
+
+    /// Scroll the answers, or vote once both are in
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Vote> {
+        match key.code {
+            KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::Down => self.scroll = self.scroll.saturating_add(1),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::Char(c) if self.answers().is_some() => {
+                return match c.to_ascii_lowercase() {
+                    'a' => Some(Vote::A),
+                    'b' => Some(Vote::B),
+                    't' => Some(Vote::Tie),
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4);
+        let height = area.height.saturating_sub(2);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        frame.render_widget(Clear, popup);
+
+        let hints = if self.is_waiting() {
+            WAITING_HINTS
+        } else if self.answers().is_none() {
+            FAILED_HINTS
+        } else {
+            HINTS
+        };
+        let prompt: String = self.prompt.lines().next().unwrap_or_default().chars().take(60).collect();
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(format!("A/B · {}", prompt))
+            .title_bottom(Line::from(hints).centered())
+            .border_style(Style::default().fg(Color::Magenta));
+        let inner = block.inner(popup);
+        frame.render_widget(block, popup);
+
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(inner);
+        for (index, (label, column)) in LABELS.iter().zip(columns.iter()).enumerate() {
+            let (text, style) = match self.answers[self.side(index)] {
+                None => ("Waiting…".to_string(), Style::default().fg(Color::DarkGray)),
+                Some(Ok(ref answer)) => (answer.clone(), Style::default().fg(Color::White)),
+                Some(Err(ref e)) => (e.clone(), Style::default().fg(Color::Red)),
+            };
+            let answer = Paragraph::new(text)
+                .style(style)
+                .wrap(Wrap { trim: false })
+                .scroll((self.scroll, 0))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(*label)
+                        .border_style(Style::default().fg(Color::Cyan)),
+                );
+            frame.render_widget(answer, *column);
+        }
+    }
+}
+
+/// The standings as lines of text, best first
+pub fn standings(standings: &[EvalStanding]) -> String {
+    if standings.is_empty() {
+        return "No A/B rounds yet; /ab claude gpt puts two models up against each other".to_string();
+    }
+    let mut lines = vec!["A/B standings, ties counting half:".to_string()];
+    for standing in standings {
+        lines.push(format!(
+            "  {:<8} {:>4.0}%  {} won, {} tied of {}",
+            standing.provider,
+            standing.win_rate() * 100.0,
+            standing.wins,
+            standing.ties,
+            standing.rounds
+        ));
+    }
+    lines.join("\n")
+}
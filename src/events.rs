@@ -0,0 +1,243 @@
+//! Chat events for other programs: each message sent, reply and error is
+//! appended to a JSONL file and POSTed to webhooks, as `[events]` in the
+//! config says:
+//!
+//! ```toml
+//! [events]
+//! log = "~/mega-cli-events.jsonl"
+//!
+//! [[events.webhooks]]
+//! url = "https://hooks.slack.com/services/..."
+//! on = ["response"]
+//! format = "slack"
+//! ```
+//!
+//! Webhooks are sent in the background; one that fails is logged and
+//! not retried.
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::EventsConfig;
+use crate::providers::{AIProvider, Usage};
+use crate::store::expand_home;
+
+/// How long a webhook gets to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest text a `slack` webhook is sent, in characters
+const SUMMARY_CHARS: usize = 2_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    UserMessage,
+    Response,
+    Error,
+}
+
+impl EventKind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "user_message" => Some(EventKind::UserMessage),
+            "response" => Some(EventKind::Response),
+            "error" => Some(EventKind::Error),
+            _ => None,
+        }
+    }
+}
+
+/// One event, as logged and sent to `json` webhooks
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub event: EventKind,
+    /// Local time, RFC 3339
+    pub time: String,
+    pub provider: String,
+    /// None until the conversation's first message is saved, and in an
+    /// incognito session
+    pub conversation_id: Option<i64>,
+    pub conversation: String,
+    /// Who sent a message from a shared session's guest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// The message, the reply or the error
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<i64>,
+    /// US dollars
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+impl Event {
+    /// `timestamp` is Unix seconds
+    pub fn new(
+        event: EventKind,
+        provider: &AIProvider,
+        conversation_id: Option<i64>,
+        conversation: &str,
+        content: &str,
+        timestamp: i64,
+    ) -> Self {
+        let time = Local.timestamp_opt(timestamp, 0).single().unwrap_or_else(Local::now);
+        Self {
+            event,
+            time: time.to_rfc3339(),
+            provider: provider.db_name().to_string(),
+            conversation_id,
+            conversation: conversation.to_string(),
+            author: None,
+            content: content.to_string(),
+            model: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            latency_ms: None,
+            cost: None,
+        }
+    }
+
+    pub fn with_author(mut self, author: Option<&str>) -> Self {
+        self.author = author.map(str::to_string);
+        self
+    }
+
+    pub fn with_usage(mut self, usage: &Usage) -> Self {
+        self.model = Some(usage.model.clone());
+        self.prompt_tokens = usage.prompt_tokens;
+        self.completion_tokens = usage.completion_tokens;
+        self.latency_ms = Some(usage.latency_ms);
+        self.cost = usage.cost();
+        self
+    }
+
+    /// One message for a chat channel
+    fn summary(&self) -> String {
+        let provider = AIProvider::parse(&self.provider).map_or(self.provider.as_str(), |p| p.name());
+        let what = match self.event {
+            EventKind::UserMessage => format!("{} asked {}", self.author.as_deref().unwrap_or("You"), provider),
+            EventKind::Response => format!("{} replied", provider),
+            EventKind::Error => format!("{} didn't answer", provider),
+        };
+        let mut content: String = self.content.chars().take(SUMMARY_CHARS).collect();
+        if content.len() < self.content.len() {
+            content.push('…');
+        }
+        format!("*{}* in “{}”:\n{}", what, self.conversation, content)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WebhookFormat {
+    Json,
+    Slack,
+}
+
+struct Webhook {
+    url: String,
+    /// Every event when empty
+    on: Vec<EventKind>,
+    format: WebhookFormat,
+}
+
+/// Where events go
+pub struct Events {
+    /// The event log; None once writing to it has failed
+    log: Option<(PathBuf, File)>,
+    webhooks: Vec<Webhook>,
+    /// Built when the first webhook is sent, not at startup
+    http: Option<reqwest::Client>,
+}
+
+impl Events {
+    /// Open the log and check the webhooks. None when neither is set up.
+    pub fn open(config: &EventsConfig) -> Result<Option<Self>> {
+        if config.log.is_none() && config.webhooks.is_empty() {
+            return Ok(None);
+        }
+        let mut webhooks = Vec::new();
+        for webhook in &config.webhooks {
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                bail!("Webhook URL isn't http or https: {}", webhook.url);
+            }
+            let on = webhook
+                .on
+                .iter()
+                .map(|name| {
+                    EventKind::parse(name)
+                        .with_context(|| format!("Unknown event: {} (expected user_message, response or error)", name))
+                })
+                .collect::<Result<_>>()?;
+            let format = match webhook.format.as_str() {
+                "json" => WebhookFormat::Json,
+                "slack" => WebhookFormat::Slack,
+                other => bail!("Unknown webhook format: {} (expected json or slack)", other),
+            };
+            webhooks.push(Webhook { url: webhook.url.clone(), on, format });
+        }
+        let log = match config.log {
+            Some(ref path) => {
+                let path = expand_home(path);
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("Failed to open the event log {}", path.display()))?;
+                Some((path, file))
+            }
+            None => None,
+        };
+        Ok(Some(Self { log, webhooks, http: None }))
+    }
+
+    /// Log `event` and send it to the webhooks that want it. A failed
+    /// write closes the log rather than failing again on every event.
+    pub fn emit(&mut self, event: &Event) -> Result<()> {
+        for webhook in self.webhooks.iter().filter(|w| w.on.is_empty() || w.on.contains(&event.event)) {
+            let http = match self.http {
+                Some(ref http) => http,
+                None => self.http.insert(
+                    reqwest::Client::builder()
+                        .timeout(WEBHOOK_TIMEOUT)
+                        .build()
+                        .context("Failed to set up the HTTP client")?,
+                ),
+            };
+            let request = match webhook.format {
+                WebhookFormat::Json => http.post(&webhook.url).json(event),
+                WebhookFormat::Slack => http.post(&webhook.url).json(&serde_json::json!({ "text": event.summary() })),
+            };
+            let url = webhook.url.clone();
+            tokio::spawn(async move {
+                match request.send().await.and_then(|response| response.error_for_status()) {
+                    Ok(_) => tracing::debug!(url, "Webhook sent"),
+                    Err(e) => tracing::warn!("Webhook {} failed: {}", url, e),
+                }
+            });
+        }
+
+        let Some((ref path, ref mut file)) = self.log else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        let written = file
+            .write_all(&line)
+            .with_context(|| format!("Failed to write to {}", path.display()));
+        if written.is_err() {
+            self.log = None;
+        }
+        written
+    }
+}
@@ -0,0 +1,335 @@
+//! Machine-readable conversation export for fine-tuning and analysis,
+//! a browsable HTML archive, Markdown, a PDF to print and the rated
+//! replies for an eval set
+
+mod html;
+mod markdown;
+#[cfg(feature = "pdf")]
+mod pdf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::store::{self, Database};
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Conversation ids to export (shown in the session manager); all when omitted
+    ids: Vec<i64>,
+
+    /// Output format: json (an array of conversations, each with an
+    /// OpenAI-style `messages` array), jsonl (one message per line, with
+    /// metadata), sharegpt, html (a single page to read or share),
+    /// markdown, pdf (pages to print, which needs --output) or rated (one
+    /// line per rated or noted reply, with the message it answered)
+    #[arg(long, default_value = "json")]
+    format: String,
+
+    /// File to write; stdout when omitted
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Include archived conversations when exporting everything
+    #[arg(long)]
+    archived: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Jsonl,
+    ShareGpt,
+    Html,
+    Markdown,
+    Pdf,
+    Rated,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Some(ExportFormat::Json),
+            "jsonl" | "ndjson" => Some(ExportFormat::Jsonl),
+            "sharegpt" => Some(ExportFormat::ShareGpt),
+            "html" => Some(ExportFormat::Html),
+            "markdown" | "md" => Some(ExportFormat::Markdown),
+            "pdf" => Some(ExportFormat::Pdf),
+            "rated" => Some(ExportFormat::Rated),
+            _ => None,
+        }
+    }
+}
+
+/// Role/content pair as the OpenAI chat APIs take it
+#[derive(Serialize)]
+struct ExportMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct ExportConversation<'a> {
+    id: i64,
+    provider: &'a str,
+    title: &'a str,
+    created_on: &'a str,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    messages: Vec<ExportMessage<'a>>,
+}
+
+#[derive(Serialize)]
+struct ShareGptConversation<'a> {
+    id: String,
+    conversations: Vec<ShareGptTurn<'a>>,
+}
+
+#[derive(Serialize)]
+struct ShareGptTurn<'a> {
+    from: &'a str,
+    value: &'a str,
+}
+
+/// One JSONL line
+#[derive(Serialize)]
+struct ExportLine<'a> {
+    conversation_id: i64,
+    provider: &'a str,
+    title: &'a str,
+    message_id: i64,
+    seq: i64,
+    role: &'a str,
+    content: &'a str,
+    timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt_tokens: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completion_tokens: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<i64>,
+    /// US dollars
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    starred: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rating: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<&'a str>,
+}
+
+/// A rated or noted reply and the message it answered, a line of the
+/// rated export
+#[derive(Serialize)]
+struct RatedPair<'a> {
+    conversation_id: i64,
+    provider: &'a str,
+    title: &'a str,
+    message_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    prompt: &'a str,
+    response: &'a str,
+    /// up or down
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rating: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<&'a str>,
+    timestamp: i64,
+}
+
+/// Run `animated-cli export`
+pub fn run(args: ExportArgs, db_path: &Path) -> Result<()> {
+    let format = ExportFormat::parse(&args.format)
+        .ok_or_else(|| anyhow!("Unknown export format: {} (expected json, jsonl, sharegpt, html, markdown, pdf or rated)", args.format))?;
+    if format == ExportFormat::Pdf && args.output.is_none() && std::io::stdout().is_terminal() {
+        bail!("A PDF isn't for the terminal; write it to a file with --output");
+    }
+    let db = Database::open(db_path)?;
+
+    let conversations = select(&db, &args.ids, args.archived)?;
+    let mut loaded = Vec::new();
+    for conversation in conversations {
+        let messages = db.get_messages(conversation.id)?;
+        loaded.push((conversation, messages));
+    }
+
+    let mut out: Box<dyn Write> = match args.output {
+        Some(ref path) => Box::new(
+            std::fs::File::create(path).with_context(|| format!("Failed to create {:?}", path))?,
+        ),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    write(&mut out, &loaded, format)?;
+    out.flush()?;
+
+    if let Some(path) = args.output {
+        eprintln!("Exported {} conversations to {}", loaded.len(), path.display());
+    }
+    Ok(())
+}
+
+/// Write the conversations `ids` to a new file at `path`. Returns how many
+/// messages were written.
+pub fn to_file(db: &Database, ids: &[i64], format: ExportFormat, path: &Path) -> Result<usize> {
+    let mut loaded = Vec::new();
+    for conversation in select(db, ids, false)? {
+        let messages = db.get_messages(conversation.id)?;
+        loaded.push((conversation, messages));
+    }
+    let mut out = std::io::BufWriter::new(
+        std::fs::File::create(path).with_context(|| format!("Failed to create {:?}", path))?,
+    );
+    write(&mut out, &loaded, format)?;
+    out.flush()?;
+    if format == ExportFormat::Rated {
+        return Ok(rated_pairs(&loaded).len());
+    }
+    Ok(loaded.iter().map(|(_, messages)| messages.len()).sum())
+}
+
+/// Conversations as a Markdown document
+pub fn markdown(conversations: &[(store::Conversation, Vec<store::Message>)]) -> Result<String> {
+    let mut out = Vec::new();
+    markdown::write(&mut out, conversations)?;
+    Ok(String::from_utf8(out)?)
+}
+
+/// The requested conversations, in the order given, or every one
+fn select(db: &Database, ids: &[i64], archived: bool) -> Result<Vec<store::Conversation>> {
+    let all = db.list_conversations(None)?;
+    if ids.is_empty() {
+        return Ok(all.into_iter().filter(|c| archived || !c.archived).collect());
+    }
+
+    ids.iter()
+        .map(|id| {
+            all.iter()
+                .find(|c| c.id == *id)
+                .cloned()
+                .ok_or_else(|| anyhow!("No conversation with id {}", id))
+        })
+        .collect()
+}
+
+/// Each reply with a rating or a note, with the latest message before
+/// it that it answered
+fn rated_pairs(conversations: &[(store::Conversation, Vec<store::Message>)]) -> Vec<RatedPair<'_>> {
+    let mut pairs = Vec::new();
+    for (c, messages) in conversations {
+        let mut prompt = None;
+        for m in messages {
+            if m.role == "user" {
+                prompt = Some(m.content.as_str());
+                continue;
+            }
+            let (Some(prompt), true) = (prompt, m.rating.is_some() || m.note.is_some()) else {
+                continue;
+            };
+            pairs.push(RatedPair {
+                conversation_id: c.id,
+                provider: &c.provider,
+                title: &c.title,
+                message_id: m.id,
+                model: m.usage.as_ref().map(|u| u.model.as_str()),
+                prompt,
+                response: &m.content,
+                rating: m.rating.as_ref().map(store::Rating::name),
+                note: m.note.as_deref(),
+                timestamp: m.timestamp,
+            });
+        }
+    }
+    pairs
+}
+
+fn write(
+    out: &mut impl Write,
+    conversations: &[(store::Conversation, Vec<store::Message>)],
+    format: ExportFormat,
+) -> Result<()> {
+    match format {
+        ExportFormat::Json => {
+            let export: Vec<ExportConversation> = conversations
+                .iter()
+                .map(|(c, messages)| ExportConversation {
+                    id: c.id,
+                    provider: &c.provider,
+                    title: &c.title,
+                    created_on: &c.created_on,
+                    tags: &c.tags,
+                    messages: messages
+                        .iter()
+                        .map(|m| ExportMessage {
+                            role: &m.role,
+                            content: &m.content,
+                        })
+                        .collect(),
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &export)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Jsonl => {
+            for (c, messages) in conversations {
+                for m in messages {
+                    let line = ExportLine {
+                        conversation_id: c.id,
+                        provider: &c.provider,
+                        title: &c.title,
+                        message_id: m.id,
+                        seq: m.seq,
+                        role: &m.role,
+                        content: &m.content,
+                        timestamp: m.timestamp,
+                        model: m.usage.as_ref().map(|u| u.model.as_str()),
+                        prompt_tokens: m.usage.as_ref().and_then(|u| u.prompt_tokens),
+                        completion_tokens: m.usage.as_ref().and_then(|u| u.completion_tokens),
+                        latency_ms: m.usage.as_ref().and_then(|u| u.latency_ms),
+                        cost: m.usage.as_ref().and_then(|u| u.cost),
+                        starred: m.starred,
+                        rating: m.rating.as_ref().map(store::Rating::name),
+                        note: m.note.as_deref(),
+                    };
+                    serde_json::to_writer(&mut *out, &line)?;
+                    writeln!(out)?;
+                }
+            }
+        }
+        ExportFormat::ShareGpt => {
+            let export: Vec<ShareGptConversation> = conversations
+                .iter()
+                .map(|(c, messages)| ShareGptConversation {
+                    id: format!("mega-cli-{}", c.id),
+                    conversations: messages
+                        .iter()
+                        .map(|m| ShareGptTurn {
+                            from: if m.role == "user" { "human" } else { "gpt" },
+                            value: &m.content,
+                        })
+                        .collect(),
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &export)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Rated => {
+            for pair in rated_pairs(conversations) {
+                serde_json::to_writer(&mut *out, &pair)?;
+                writeln!(out)?;
+            }
+        }
+        ExportFormat::Html => html::write(out, conversations)?,
+        ExportFormat::Markdown => markdown::write(out, conversations)?,
+        #[cfg(feature = "pdf")]
+        ExportFormat::Pdf => pdf::write(out, conversations)?,
+        #[cfg(not(feature = "pdf"))]
+        ExportFormat::Pdf => bail!("This build can't write PDFs; it was built without the pdf feature"),
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! One self-contained HTML page holding every exported conversation: no
+//! external files, collapsible conversations and a search box that works
+//! offline
+
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use std::io::Write;
+
+use crate::providers::AIProvider;
+use crate::store;
+
+const STYLE: &str = r#"
+:root { color-scheme: light dark; --bg: #f6f6f8; --fg: #1d1d22; --muted: #6b6b76; --card: #fff; --user: #e8f4ea; --mark: #ffe58a; }
+@media (prefers-color-scheme: dark) { :root { --bg: #141418; --fg: #e6e6ea; --muted: #9090a0; --card: #1e1e24; --user: #1f2c22; --mark: #6b5a00; } }
+* { box-sizing: border-box; }
+body { margin: 0; background: var(--bg); color: var(--fg); font: 15px/1.5 system-ui, sans-serif; }
+header { position: sticky; top: 0; background: var(--bg); padding: 1rem 1.5rem; border-bottom: 1px solid #8884; display: flex; gap: .75rem; align-items: center; flex-wrap: wrap; }
+header h1 { font-size: 1.1rem; margin: 0 auto 0 0; }
+header input { font: inherit; padding: .35rem .6rem; min-width: 16rem; border-radius: 6px; border: 1px solid #8886; background: var(--card); color: inherit; }
+header button { font: inherit; padding: .35rem .7rem; border-radius: 6px; border: 1px solid #8886; background: var(--card); color: inherit; cursor: pointer; }
+main { max-width: 60rem; margin: 0 auto; padding: 1rem 1.5rem 3rem; }
+details { background: var(--card); border-radius: 8px; margin: .6rem 0; box-shadow: 0 1px 2px #0002; }
+summary { cursor: pointer; padding: .7rem 1rem; display: flex; gap: .6rem; align-items: baseline; flex-wrap: wrap; }
+summary .title { font-weight: 600; }
+.meta, .time { color: var(--muted); font-size: .85rem; }
+.tag { font-size: .8rem; color: #b04cc8; }
+.provider { font-size: .85rem; font-weight: 600; }
+.messages { padding: 0 1rem 1rem; }
+.message { padding: .5rem .75rem; margin: .4rem 0; border-radius: 6px; }
+.message.user { background: var(--user); }
+.message .who { font-weight: 600; margin-right: .4rem; }
+.message .star { color: #d4a000; }
+.message .note { color: var(--muted); font-style: italic; margin-top: .2rem; }
+.message .text { white-space: pre-wrap; overflow-wrap: anywhere; margin-top: .2rem; }
+.message.hit { outline: 2px solid var(--mark); }
+.hidden { display: none; }
+#count { color: var(--muted); font-size: .85rem; }
+"#;
+
+/// Filters conversations as you type; a conversation matches when its
+/// title, tags or any message contains every word
+const SCRIPT: &str = r#"
+const input = document.getElementById('search');
+const count = document.getElementById('count');
+const conversations = [...document.querySelectorAll('details')];
+function search() {
+  const words = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+  let shown = 0;
+  for (const c of conversations) {
+    const messages = [...c.querySelectorAll('.message')];
+    const hay = c.textContent.toLowerCase();
+    const match = words.every(w => hay.includes(w));
+    c.classList.toggle('hidden', !match);
+    for (const m of messages) {
+      const text = m.textContent.toLowerCase();
+      m.classList.toggle('hit', words.length > 0 && words.some(w => text.includes(w)));
+    }
+    if (words.length) c.open = match;
+    if (match) shown++;
+  }
+  count.textContent = words.length ? shown + ' of ' + conversations.length + ' conversations' : conversations.length + ' conversations';
+}
+input.addEventListener('input', search);
+document.getElementById('expand').onclick = () => conversations.forEach(c => c.open = !c.classList.contains('hidden'));
+document.getElementById('collapse').onclick = () => conversations.forEach(c => c.open = false);
+search();
+"#;
+
+pub fn write(out: &mut impl Write, conversations: &[(store::Conversation, Vec<store::Message>)]) -> Result<()> {
+    writeln!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">")?;
+    writeln!(out, "<title>MEGA-CLI conversations</title>\n<style>{}</style>\n</head>\n<body>", STYLE)?;
+    writeln!(
+        out,
+        "<header><h1>MEGA-CLI conversations</h1><span id=\"count\"></span>\
+         <input id=\"search\" type=\"search\" placeholder=\"Search\" autofocus>\
+         <button id=\"expand\">Expand all</button><button id=\"collapse\">Collapse all</button></header>\n<main>"
+    )?;
+
+    for (c, messages) in conversations {
+        let provider = AIProvider::parse(&c.provider);
+        let name = provider.as_ref().map_or(c.provider.as_str(), |p| p.name());
+        let color = provider.as_ref().map_or("inherit".to_string(), |p| css_color(p.color()));
+        let title = if c.title.is_empty() { "Untitled" } else { &c.title };
+
+        write!(
+            out,
+            "<details id=\"conversation-{}\">\n<summary><span class=\"title\">{}</span>\
+             <span class=\"provider\" style=\"color: {}\">{}</span>\
+             <span class=\"meta\">#{} · {} · {} messages</span>",
+            c.id,
+            escape(title),
+            color,
+            escape(name),
+            c.id,
+            escape(&c.created_on),
+            c.message_count
+        )?;
+        for tag in &c.tags {
+            write!(out, "<span class=\"tag\">#{}</span>", escape(tag))?;
+        }
+        writeln!(out, "</summary>\n<div class=\"messages\">")?;
+
+        for m in messages {
+            let (class, who) = if m.role == "user" { ("user", "You") } else { ("assistant", name) };
+            let sent = Local
+                .timestamp_opt(m.timestamp, 0)
+                .single()
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            write!(out, "<div class=\"message {}\">", class)?;
+            if m.starred {
+                write!(out, "<span class=\"star\" title=\"Starred\">★</span> ")?;
+            }
+            if let Some(rating) = m.rating {
+                write!(out, "<span class=\"rating\" title=\"Rated {}\">{}</span> ", rating.name(), rating.emoji())?;
+            }
+            let note = match m.note {
+                Some(ref note) => format!("<div class=\"note\">✎ {}</div>", escape(note)),
+                None => String::new(),
+            };
+            writeln!(
+                out,
+                "<span class=\"who\">{}</span><span class=\"time\">{}</span><div class=\"text\">{}</div>{}</div>",
+                escape(who),
+                sent,
+                escape(&m.content),
+                note
+            )?;
+        }
+        writeln!(out, "</div>\n</details>")?;
+    }
+
+    writeln!(out, "</main>\n<script>{}</script>\n</body>\n</html>", SCRIPT)?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn css_color(color: ratatui::style::Color) -> String {
+    match color {
+        ratatui::style::Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        _ => "inherit".to_string(),
+    }
+}
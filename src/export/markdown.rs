@@ -0,0 +1,48 @@
+//! Conversations as a Markdown document, to read anywhere or paste
+//! somewhere: a heading for each, then its messages under who sent them
+
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use std::io::Write;
+
+use crate::providers::AIProvider;
+use crate::store;
+
+pub fn write(out: &mut impl Write, conversations: &[(store::Conversation, Vec<store::Message>)]) -> Result<()> {
+    for (index, (c, messages)) in conversations.iter().enumerate() {
+        if index > 0 {
+            writeln!(out, "\n---\n")?;
+        }
+        let name = AIProvider::parse(&c.provider).map_or(c.provider.clone(), |p| p.name().to_string());
+        let title = if c.title.is_empty() { "Untitled" } else { &c.title };
+        writeln!(out, "# {}\n", title)?;
+        let mut about = format!("*{} · {}", name, c.created_on);
+        for tag in &c.tags {
+            about.push_str(&format!(" · #{}", tag));
+        }
+        writeln!(out, "{}*", about)?;
+
+        for m in messages {
+            let who = if m.role == "user" { "You" } else { name.as_str() };
+            let sent = Local
+                .timestamp_opt(m.timestamp, 0)
+                .single()
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            let mut marks = String::new();
+            if m.starred {
+                marks.push_str(" ★");
+            }
+            if let Some(rating) = m.rating {
+                marks.push(' ');
+                marks.push_str(rating.emoji());
+            }
+            writeln!(out, "\n**{}**{} · {}\n", who, marks, sent)?;
+            writeln!(out, "{}", m.content.trim_end())?;
+            if let Some(ref note) = m.note {
+                writeln!(out, "\n> ✎ {}", note)?;
+            }
+        }
+    }
+    Ok(())
+}
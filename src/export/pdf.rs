@@ -0,0 +1,493 @@
+//! A PDF to print or hand on: A4 pages, each conversation starting on a
+//! new one with a bookmark, your messages on a green tint, the provider's
+//! beside a bar in its color, and code (fenced blocks and `inline`) in
+//! Hack. Text is set in Ubuntu Light, with Noto Emoji for what it lacks
+//! and nothing for what none of them have (CJK, for one). The fonts are
+//! embedded whole, so even a short export is several hundred kilobytes.
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, TimeZone};
+use fontdue::{Font, FontSettings};
+use printpdf::{Color, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rect, Rgb, TextRenderingMode};
+use std::io::Write;
+
+use crate::providers::AIProvider;
+use crate::store;
+
+/// A4, in millimetres
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 18.0;
+/// Below the last row, for the page number
+const FOOTER: f32 = 8.0;
+/// Inside a message's tint, on each side
+const PADDING: f32 = 3.0;
+/// Between messages
+const GAP: f32 = 4.0;
+/// The assistant's colored bar
+const BAR_WIDTH: f32 = 1.2;
+
+/// Font sizes in points
+const TITLE_SIZE: f32 = 16.0;
+const TEXT_SIZE: f32 = 10.5;
+const CODE_SIZE: f32 = 9.0;
+const SMALL_SIZE: f32 = 8.5;
+/// Row height as a multiple of the font size
+const LEADING: f32 = 1.45;
+/// Outline that makes the text font pass for bold
+const BOLD_STROKE: f32 = 0.3;
+
+const INK: [u8; 3] = [0x1d, 0x1d, 0x22];
+const MUTED: [u8; 3] = [0x6b, 0x6b, 0x76];
+const USER_TINT: [u8; 3] = [0xe8, 0xf4, 0xea];
+const USER_NAME: [u8; 3] = [0x2e, 0x7d, 0x32];
+const CODE_TINT: [u8; 3] = [0xf0, 0xf0, 0xf3];
+const INLINE_CODE: [u8; 3] = [0x8a, 0x2b, 0x5c];
+const STAR: [u8; 3] = [0xd4, 0xa0, 0x00];
+
+/// Columns a tab takes up in code
+const TAB_WIDTH: usize = 4;
+
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+/// Which typeface a character is meant to be set in
+#[derive(Clone, Copy, PartialEq)]
+enum Face {
+    Text,
+    Code,
+}
+
+/// The embedded fonts, in the order they're tried for a character
+#[derive(Clone, Copy, PartialEq)]
+enum FontId {
+    Text,
+    Code,
+    Emoji,
+}
+
+const FONT_COUNT: usize = 3;
+
+struct Fonts {
+    faces: [Font; FONT_COUNT],
+    /// Which of them the pages use, so only those are embedded
+    used: [bool; FONT_COUNT],
+}
+
+impl Fonts {
+    fn load() -> Result<Self> {
+        let load = |bytes: &'static [u8]| Font::from_bytes(bytes, FontSettings::default()).map_err(|e| anyhow!(e));
+        Ok(Self {
+            faces: [
+                load(epaint_default_fonts::UBUNTU_LIGHT)?,
+                load(epaint_default_fonts::HACK_REGULAR)?,
+                load(epaint_default_fonts::NOTO_EMOJI_REGULAR)?,
+            ],
+            used: [false; FONT_COUNT],
+        })
+    }
+
+    fn bytes(id: FontId) -> &'static [u8] {
+        match id {
+            FontId::Text => epaint_default_fonts::UBUNTU_LIGHT,
+            FontId::Code => epaint_default_fonts::HACK_REGULAR,
+            FontId::Emoji => epaint_default_fonts::NOTO_EMOJI_REGULAR,
+        }
+    }
+
+    /// The font that draws `c` in `face`: its own, then emoji, then the
+    /// other; None if none of them can
+    fn pick(&self, c: char, face: Face) -> Option<FontId> {
+        let order = match face {
+            Face::Text => [FontId::Text, FontId::Emoji, FontId::Code],
+            Face::Code => [FontId::Code, FontId::Emoji, FontId::Text],
+        };
+        order.into_iter().find(|&id| self.faces[id as usize].lookup_glyph_index(c) != 0)
+    }
+
+    /// How far `c` moves the pen at `size` points, in millimetres
+    fn advance(&self, font: FontId, c: char, size: f32) -> f32 {
+        self.faces[font as usize].metrics(c, size).advance_width / POINTS_PER_MM
+    }
+}
+
+/// Something drawn on a page; `y` is millimetres from the top
+enum Item {
+    Fill { x: f32, y: f32, width: f32, height: f32, color: [u8; 3] },
+    /// Runs of text in one font each, from `x` along the baseline at `y`
+    Text { x: f32, y: f32, size: f32, color: [u8; 3], bold: bool, runs: Vec<(FontId, String)> },
+}
+
+/// A character set in a face and color
+type Styled = (char, Face, [u8; 3]);
+
+/// Rows of a message: text, or a row of a code block
+enum Row {
+    Text(Vec<Styled>),
+    Code(Vec<Styled>),
+}
+
+struct Layout {
+    fonts: Fonts,
+    pages: Vec<Vec<Item>>,
+    /// Where the next row goes, from the top of the page
+    y: f32,
+    /// Each conversation's title and the page it starts on
+    bookmarks: Vec<(String, usize)>,
+}
+
+impl Layout {
+    fn new_page(&mut self) {
+        self.pages.push(Vec::new());
+        self.y = MARGIN;
+    }
+
+    /// Start a new page unless `height` more fits on this one
+    fn make_room(&mut self, height: f32) {
+        if self.pages.is_empty() || self.y + height > PAGE_HEIGHT - MARGIN - FOOTER {
+            self.new_page();
+        }
+    }
+
+    fn push(&mut self, item: Item) {
+        if let Some(page) = self.pages.last_mut() {
+            page.push(item);
+        }
+    }
+
+    /// Styled text as runs of one font each, noting the fonts used;
+    /// characters no font has are left out
+    fn runs(&mut self, text: &[Styled]) -> Vec<(FontId, String)> {
+        let mut runs: Vec<(FontId, String)> = Vec::new();
+        for &(c, face, _) in text {
+            let Some(font) = self.fonts.pick(c, face) else {
+                continue;
+            };
+            self.fonts.used[font as usize] = true;
+            match runs.last_mut() {
+                Some((last, run)) if *last == font => run.push(c),
+                _ => runs.push((font, c.to_string())),
+            }
+        }
+        runs
+    }
+
+    /// A line of text at `x`, in pieces of one color
+    fn text(&mut self, x: f32, baseline: f32, size: f32, bold: bool, text: &[Styled]) {
+        let mut x = x;
+        for piece in text.chunk_by(|a, b| a.2 == b.2) {
+            let runs = self.runs(piece);
+            let width: f32 = runs
+                .iter()
+                .flat_map(|(font, run)| run.chars().map(move |c| (*font, c)))
+                .map(|(font, c)| self.fonts.advance(font, c, size))
+                .sum();
+            self.push(Item::Text { x, y: baseline, size, color: piece[0].2, bold, runs });
+            x += width;
+        }
+    }
+
+    fn width(&self, text: &[Styled], size: f32) -> f32 {
+        text.iter()
+            .filter_map(|&(c, face, _)| self.fonts.pick(c, face).map(|font| self.fonts.advance(font, c, size)))
+            .sum()
+    }
+
+    fn conversation(&mut self, conversation: &store::Conversation, messages: &[store::Message]) {
+        self.new_page();
+        let provider = AIProvider::parse(&conversation.provider);
+        let name = provider.as_ref().map_or(conversation.provider.as_str(), |p| p.name());
+        let title = if conversation.title.is_empty() { "Untitled" } else { &conversation.title };
+        self.bookmarks.push((title.to_string(), self.pages.len() - 1));
+
+        let width = PAGE_WIDTH - 2.0 * MARGIN;
+        for row in wrap(&styled(title, Face::Text, INK), width, |text| self.width(text, TITLE_SIZE)) {
+            let height = row_height(TITLE_SIZE);
+            self.make_room(height);
+            self.text(MARGIN, self.y + baseline(TITLE_SIZE), TITLE_SIZE, true, &row);
+            self.y += height;
+        }
+        let mut meta = format!(
+            "{} · #{} · {} · {} messages",
+            name, conversation.id, conversation.created_on, conversation.message_count
+        );
+        for tag in &conversation.tags {
+            meta.push_str(&format!(" #{}", tag));
+        }
+        for row in wrap(&styled(&meta, Face::Text, MUTED), width, |text| self.width(text, SMALL_SIZE)) {
+            self.text(MARGIN, self.y + baseline(SMALL_SIZE), SMALL_SIZE, false, &row);
+            self.y += row_height(SMALL_SIZE);
+        }
+        self.y += GAP * 1.5;
+
+        let accent = provider.as_ref().map_or(MUTED, |p| rgb(p.color()));
+        for message in messages {
+            self.message(message, name, accent);
+        }
+    }
+
+    /// A message as a tinted box for yours, or beside a bar in the
+    /// provider's color for theirs; it breaks across pages row by row
+    fn message(&mut self, message: &store::Message, provider: &str, accent: [u8; 3]) {
+        let user = message.role == "user";
+        let (who, name_color) = if user { ("You", USER_NAME) } else { (provider, darken(accent)) };
+        let left = MARGIN;
+        let width = PAGE_WIDTH - 2.0 * MARGIN;
+        let inner = width - 2.0 * PADDING;
+        // Behind each row, so the box carries on over a page break
+        let band = |layout: &mut Layout, y: f32, height: f32| {
+            if user {
+                layout.push(Item::Fill { x: left, y, width, height, color: USER_TINT });
+            } else {
+                layout.push(Item::Fill { x: left, y, width: BAR_WIDTH, height, color: accent });
+            }
+        };
+
+        let header_height = row_height(TEXT_SIZE);
+        // The name and at least one row together
+        self.make_room(PADDING + header_height * 2.0);
+        band(self, self.y, PADDING);
+        self.y += PADDING;
+        band(self, self.y, header_height);
+        let mut x = left + PADDING;
+        if message.starred {
+            let star = styled("★ ", Face::Text, STAR);
+            self.text(x, self.y + baseline(TEXT_SIZE), TEXT_SIZE, false, &star);
+            x += self.width(&star, TEXT_SIZE);
+        }
+        let name = styled(who, Face::Text, name_color);
+        self.text(x, self.y + baseline(TEXT_SIZE), TEXT_SIZE, true, &name);
+        x += self.width(&name, TEXT_SIZE) + 2.0;
+        let sent = Local
+            .timestamp_opt(message.timestamp, 0)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        self.text(x, self.y + baseline(TEXT_SIZE), SMALL_SIZE, false, &styled(&sent, Face::Text, MUTED));
+        self.y += header_height;
+
+        for row in rows(&message.content, inner, |text, size| self.width(text, size)) {
+            let (size, text, code) = match row {
+                Row::Text(text) => (TEXT_SIZE, text, false),
+                Row::Code(text) => (CODE_SIZE, text, true),
+            };
+            let height = row_height(size);
+            self.make_room(height);
+            band(self, self.y, height);
+            if code {
+                self.push(Item::Fill { x: left + PADDING, y: self.y, width: inner, height, color: CODE_TINT });
+            }
+            let indent = if code { 1.5 } else { 0.0 };
+            self.text(left + PADDING + indent, self.y + baseline(size), size, false, &text);
+            self.y += height;
+        }
+        if self.y + PADDING <= PAGE_HEIGHT - MARGIN - FOOTER {
+            band(self, self.y, PADDING);
+        }
+        self.y += PADDING + GAP;
+    }
+}
+
+fn row_height(size: f32) -> f32 {
+    size * LEADING / POINTS_PER_MM
+}
+
+/// From the top of a row to its text's baseline
+fn baseline(size: f32) -> f32 {
+    size * (LEADING - 1.0) / 2.0 / POINTS_PER_MM + size * 0.8 / POINTS_PER_MM
+}
+
+fn styled(text: &str, face: Face, color: [u8; 3]) -> Vec<Styled> {
+    text.chars().filter(|c| !c.is_control()).map(|c| (c, face, color)).collect()
+}
+
+/// A message's text as rows `width` wide: code fenced with ``` in Hack
+/// on grey, the rest in the text font with `inline code` picked out.
+/// `measure` gives the width of some text at a size.
+fn rows(content: &str, width: f32, measure: impl Fn(&[Styled], f32) -> f32) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut in_code = false;
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            let line = line.replace('\t', &" ".repeat(TAB_WIDTH));
+            // Short of the tint's right edge, as it's indented from its left
+            let code = styled(&line, Face::Code, INK);
+            rows.extend(wrap(&code, width - 3.0, |text| measure(text, CODE_SIZE)).into_iter().map(Row::Code));
+        } else {
+            let text = inline_code(line);
+            rows.extend(wrap(&text, width, |text| measure(text, TEXT_SIZE)).into_iter().map(Row::Text));
+        }
+    }
+    // Leave out blank rows at the end, as a reply often has
+    while matches!(rows.last(), Some(Row::Text(text)) if text.is_empty()) {
+        rows.pop();
+    }
+    rows
+}
+
+/// A line of prose, with what's between pairs of backticks as code
+fn inline_code(line: &str) -> Vec<Styled> {
+    let line = line.replace('\t', " ");
+    if !line.matches('`').count().is_multiple_of(2) {
+        return styled(&line, Face::Text, INK);
+    }
+    let mut text = Vec::new();
+    for (index, part) in line.split('`').enumerate() {
+        if index % 2 == 1 {
+            text.extend(styled(part, Face::Code, INLINE_CODE));
+        } else {
+            text.extend(styled(part, Face::Text, INK));
+        }
+    }
+    text
+}
+
+/// Text as rows no wider than `width` by `measure`: broken between words,
+/// or within a word too long for a row of its own. A space where a row
+/// breaks is left out. An empty line is one empty row.
+fn wrap(text: &[Styled], width: f32, measure: impl Fn(&[Styled]) -> f32) -> Vec<Vec<Styled>> {
+    let mut rows = Vec::new();
+    let mut row: Vec<Styled> = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        // The next word, with the spaces before it
+        let spaces = text[start..].iter().take_while(|(c, ..)| *c == ' ').count();
+        let letters = text[start + spaces..].iter().take_while(|(c, ..)| *c != ' ').count();
+        let word = &text[start..start + spaces + letters];
+        start += spaces + letters;
+
+        let mut candidate = row.clone();
+        candidate.extend_from_slice(word);
+        if measure(&candidate) <= width {
+            row = candidate;
+            continue;
+        }
+        // Onto a new row, without the spaces, if it fits there
+        let bare = &word[spaces..];
+        if !row.is_empty() {
+            rows.push(std::mem::take(&mut row));
+        }
+        if measure(bare) <= width {
+            row = bare.to_vec();
+            continue;
+        }
+        // Too long for any row: as much as fits on each
+        for &c in bare {
+            row.push(c);
+            if measure(&row) > width && row.len() > 1 {
+                let last = row.pop();
+                rows.push(std::mem::replace(&mut row, last.into_iter().collect()));
+            }
+        }
+    }
+    rows.push(row);
+    rows
+}
+
+fn rgb(color: ratatui::style::Color) -> [u8; 3] {
+    match color {
+        ratatui::style::Color::Rgb(r, g, b) => [r, g, b],
+        _ => MUTED,
+    }
+}
+
+/// The provider colors are chosen for a dark terminal; on paper, names in
+/// them need to be darker to read
+fn darken([r, g, b]: [u8; 3]) -> [u8; 3] {
+    [r, g, b].map(|channel| (channel as f32 * 0.6) as u8)
+}
+
+fn color([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb(Rgb::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, None))
+}
+
+pub fn write(out: &mut impl Write, conversations: &[(store::Conversation, Vec<store::Message>)]) -> Result<()> {
+    let mut layout = Layout { fonts: Fonts::load()?, pages: Vec::new(), y: MARGIN, bookmarks: Vec::new() };
+    for (conversation, messages) in conversations {
+        layout.conversation(conversation, messages);
+    }
+    if layout.pages.is_empty() {
+        layout.new_page();
+        layout.text(MARGIN, MARGIN, TEXT_SIZE, false, &styled("No conversations to export", Face::Text, MUTED));
+    }
+
+    let title = match conversations {
+        [(conversation, _)] if !conversation.title.is_empty() => conversation.title.clone(),
+        _ => "MEGA-CLI conversations".to_string(),
+    };
+    let (document, first_page, first_layer) = PdfDocument::new(&title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Page");
+    let document = document.with_creator("MEGA-CLI").with_producer("MEGA-CLI");
+    let mut fonts: [Option<IndirectFontRef>; FONT_COUNT] = [None, None, None];
+    for (index, id) in [FontId::Text, FontId::Code, FontId::Emoji].into_iter().enumerate() {
+        // The page numbers are in the text font
+        if layout.fonts.used[index] || id == FontId::Text {
+            fonts[index] = Some(
+                document
+                    .add_external_font(Fonts::bytes(id))
+                    .map_err(|e| anyhow!("Couldn't embed a font in the PDF: {}", e))?,
+            );
+        }
+    }
+
+    let count = layout.pages.len();
+    for (number, items) in layout.pages.iter().enumerate() {
+        let (page, layer) = match number {
+            0 => (first_page, first_layer),
+            _ => document.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Page"),
+        };
+        for (title, _) in layout.bookmarks.iter().filter(|(_, first)| *first == number) {
+            document.add_bookmark(title.as_str(), page);
+        }
+        let layer = document.get_page(page).get_layer(layer);
+        for item in items {
+            draw(&layer, item, &fonts);
+        }
+        let footer = format!("{} of {}", number + 1, count);
+        let footer_width = layout.width(&styled(&footer, Face::Text, MUTED), SMALL_SIZE);
+        let runs = vec![(FontId::Text, footer)];
+        let item = Item::Text {
+            x: (PAGE_WIDTH - footer_width) / 2.0,
+            y: PAGE_HEIGHT - MARGIN,
+            size: SMALL_SIZE,
+            color: MUTED,
+            bold: false,
+            runs,
+        };
+        draw(&layer, &item, &fonts);
+    }
+
+    let bytes = document.save_to_bytes().map_err(|e| anyhow!("Couldn't write the PDF: {}", e))?;
+    out.write_all(&bytes)?;
+    Ok(())
+}
+
+fn draw(layer: &PdfLayerReference, item: &Item, fonts: &[Option<IndirectFontRef>; FONT_COUNT]) {
+    match *item {
+        Item::Fill { x, y, width, height, color: fill } => {
+            layer.set_fill_color(color(fill));
+            layer.add_rect(Rect::new(Mm(x), Mm(PAGE_HEIGHT - y - height), Mm(x + width), Mm(PAGE_HEIGHT - y)));
+        }
+        Item::Text { x, y, size, color: ink, bold, ref runs } => {
+            layer.begin_text_section();
+            layer.set_fill_color(color(ink));
+            if bold {
+                layer.set_outline_color(color(ink));
+                layer.set_outline_thickness(BOLD_STROKE);
+                layer.set_text_rendering_mode(TextRenderingMode::FillStroke);
+            } else {
+                layer.set_text_rendering_mode(TextRenderingMode::Fill);
+            }
+            layer.set_text_cursor(Mm(x), Mm(PAGE_HEIGHT - y));
+            for (font, text) in runs {
+                if let Some(ref font) = fonts[*font as usize] {
+                    layer.set_font(font, size);
+                    layer.write_text(text.as_str(), font);
+                }
+            }
+            layer.end_text_section();
+        }
+    }
+}
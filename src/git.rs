@@ -0,0 +1,68 @@
+//! Git in the directory the chat was started in, for `/diff`,
+//! `/commitmsg` and `/apply`
+
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Largest diff that goes into a prompt
+const MAX_DIFF_KB: usize = 250;
+
+/// Changes staged for the next commit; empty if there are none
+pub fn staged_diff() -> Result<String> {
+    diff(&["diff", "--staged"])
+}
+
+/// Changes not yet staged; empty if there are none
+pub fn unstaged_diff() -> Result<String> {
+    diff(&["diff"])
+}
+
+/// Commit what's staged with `message`, and return git's summary of the
+/// commit, e.g. "[main 1a2b3c4] Fix the parser"
+pub fn commit(message: &str) -> Result<String> {
+    let output = git(&["commit", "--file", "-"], Some(message))?;
+    Ok(output.lines().next().unwrap_or_default().to_string())
+}
+
+/// Whether `patch` would apply to the working tree; git's complaint if
+/// not. Hunk line counts aren't trusted, as models often get them wrong.
+pub fn check_patch(patch: &str) -> Result<()> {
+    git(&["apply", "--check", "--recount", "-"], Some(patch)).map(drop)
+}
+
+/// Apply `patch` to the working tree
+pub fn apply_patch(patch: &str) -> Result<()> {
+    git(&["apply", "--recount", "-"], Some(patch)).map(drop)
+}
+
+fn diff(args: &[&str]) -> Result<String> {
+    let diff = git(args, None)?;
+    let kb = diff.len().div_ceil(1024);
+    if kb > MAX_DIFF_KB {
+        bail!("The diff is {} KB, over the {} KB that fits in a prompt; stage less of it", kb, MAX_DIFF_KB);
+    }
+    Ok(diff)
+}
+
+/// Run git and return its output; its error message if it fails
+fn git(args: &[&str], stdin: Option<&str>) -> Result<String> {
+    let mut child = Command::new("git")
+        .args(args)
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git; is it installed?")?;
+    if let (Some(text), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(text.as_bytes()).context("Failed to pass git its input")?;
+    }
+    let output = child.wait_with_output().context("Failed to run git")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let reason = if stderr.trim().is_empty() { stdout.trim() } else { stderr.trim() };
+        bail!("git {} failed: {}", args[0], reason);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
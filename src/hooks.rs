@@ -0,0 +1,164 @@
+//! User scripts run at points in the chat: `hooks.rhai` in the profile's
+//! directory can rewrite what's sent (`on_user_message`), rewrite what
+//! comes back (`on_response`) and hear about provider switches
+//! (`on_provider_switch`). Every hook is optional.
+//!
+//! ```rhai
+//! fn on_user_message(text, provider) {
+//!     text.replace(env("WORK_EMAIL"), "[email]");
+//!     text
+//! }
+//!
+//! fn on_response(text, provider) {
+//!     append("/tmp/replies.log", `${now()} ${provider}: ${text}`);
+//! }
+//! ```
+//!
+//! A hook returns the new text, or nothing to leave it as it was. Besides
+//! Rhai's own functions, scripts get `log(text)` (the log file),
+//! `append(path, line)`, `env(name)` and `now()`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::providers::AIProvider;
+
+/// Looked for in the profile's directory
+pub const FILE_NAME: &str = "hooks.rhai";
+
+/// Enough for any reasonable hook; a runaway loop fails instead of
+/// freezing the chat
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+const HOOKS: [(&str, usize); 3] = [("on_user_message", 2), ("on_response", 2), ("on_provider_switch", 2)];
+
+pub struct Hooks {
+    engine: Engine,
+    ast: AST,
+    path: PathBuf,
+}
+
+impl Hooks {
+    /// The profile's hooks script, whether or not it exists
+    pub fn path(profile: Option<&str>) -> Result<PathBuf> {
+        Ok(config::profile_dir(profile)?.join(FILE_NAME))
+    }
+
+    /// Compile the profile's script and run its top level. None if there
+    /// isn't one.
+    pub fn load(profile: Option<&str>) -> Result<Option<Self>> {
+        let path = Self::path(profile)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::from_file(&path).map(Some)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let script = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let engine = engine();
+        let ast = engine
+            .compile(&script)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        engine
+            .run_ast(&ast)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            engine,
+            ast,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.path
+    }
+
+    /// The hooks the script defines
+    pub fn names(&self) -> Vec<&'static str> {
+        HOOKS
+            .iter()
+            .filter(|(name, arity)| self.defines(name, *arity))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// The message to send in place of `text`, if the script changes it.
+    /// An empty string means don't send anything.
+    pub fn on_user_message(&self, text: &str, provider: &AIProvider) -> Result<Option<String>> {
+        self.rewrite("on_user_message", text, provider)
+    }
+
+    /// The reply to show and save in place of `text`, if the script
+    /// changes it
+    pub fn on_response(&self, text: &str, provider: &AIProvider) -> Result<Option<String>> {
+        self.rewrite("on_response", text, provider)
+    }
+
+    pub fn on_provider_switch(&self, from: &AIProvider, to: &AIProvider) -> Result<()> {
+        self.call("on_provider_switch", from.db_name(), to.db_name()).map(|_| ())
+    }
+
+    fn rewrite(&self, hook: &str, text: &str, provider: &AIProvider) -> Result<Option<String>> {
+        let Some(result) = self.call(hook, text, provider.db_name())? else {
+            return Ok(None);
+        };
+        if result.is_unit() {
+            return Ok(None);
+        }
+        match result.into_immutable_string() {
+            Ok(rewritten) => Ok(Some(rewritten.to_string())),
+            Err(kind) => bail!("{} returned a {}; it should return text, or nothing to keep it as it was", hook, kind),
+        }
+    }
+
+    /// None if the script doesn't define `hook`
+    fn call(&self, hook: &str, first: &str, second: &str) -> Result<Option<Dynamic>> {
+        if !self.defines(hook, 2) {
+            return Ok(None);
+        }
+        // The top level ran once when the script was loaded
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine
+            .call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                &self.ast,
+                hook,
+                (first.to_string(), second.to_string()),
+            )
+            .map(Some)
+            .map_err(|e| anyhow!("{} in {}: {}", hook, self.path.display(), e))
+    }
+
+    fn defines(&self, hook: &str, arity: usize) -> bool {
+        self.ast.iter_functions().any(|f| f.name == hook && f.params.len() == arity)
+    }
+}
+
+/// A Rhai engine with the functions scripts get on top of the language's
+/// own, and print and debug sent to the log
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| tracing::info!(target: "hooks", "{}", text));
+    engine.on_debug(|text, _, position| tracing::debug!(target: "hooks", "{} {}", position, text));
+    engine.register_fn("log", |text: &str| tracing::info!(target: "hooks", "{}", text));
+    engine.register_fn("env", |name: &str| std::env::var(name).unwrap_or_default());
+    engine.register_fn("now", || chrono::Local::now().to_rfc3339());
+    engine.register_fn("append", append);
+    engine
+}
+
+/// Add `line` to the end of `path`, creating it if need be
+fn append(path: &str, line: &str) -> Result<(), Box<EvalAltResult>> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(|e| format!("Failed to append to {}: {}", path, e).into())
+}
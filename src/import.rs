@@ -0,0 +1,427 @@
+//! Bring conversations in from other tools
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::providers::AIProvider;
+use crate::conversation;
+use crate::store::{Database, NewConversation, NewMessage};
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// ChatGPT data-export zip (or the conversations.json inside it), or a ShareGPT JSON file
+    path: PathBuf,
+
+    /// Input format: chatgpt or sharegpt; detected from the file when omitted
+    #[arg(long)]
+    format: Option<String>,
+
+    /// Provider to file ShareGPT conversations under (ChatGPT exports are always gpt)
+    #[arg(long, default_value = "gpt")]
+    provider: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    ChatGpt,
+    ShareGpt,
+}
+
+impl ImportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "chatgpt" => Some(ImportFormat::ChatGpt),
+            "sharegpt" => Some(ImportFormat::ShareGpt),
+            _ => None,
+        }
+    }
+
+    /// Tell the formats apart by the keys of the first conversation
+    fn detect(json: &serde_json::Value) -> Option<Self> {
+        let first = json.as_array()?.first()?;
+        if first.get("mapping").is_some() {
+            Some(ImportFormat::ChatGpt)
+        } else if first.get("conversations").is_some() {
+            Some(ImportFormat::ShareGpt)
+        } else {
+            None
+        }
+    }
+}
+
+/// Run `animated-cli import`
+pub fn run(args: ImportArgs, db_path: &Path) -> Result<()> {
+    let text = read_export(&args.path)?;
+    let json: serde_json::Value =
+        serde_json::from_str(&text).with_context(|| format!("{} is not valid JSON", args.path.display()))?;
+
+    let format = match args.format {
+        Some(ref name) => ImportFormat::parse(name)
+            .ok_or_else(|| anyhow!("Unknown import format: {} (expected chatgpt or sharegpt)", name))?,
+        None => ImportFormat::detect(&json)
+            .ok_or_else(|| anyhow!("Couldn't tell what format {} is in; pass --format", args.path.display()))?,
+    };
+    let conversations = match format {
+        ImportFormat::ChatGpt => parse_chatgpt(json)
+            .with_context(|| format!("{} is not a ChatGPT export", args.path.display()))?,
+        ImportFormat::ShareGpt => {
+            let provider = AIProvider::parse(&args.provider)
+                .ok_or_else(|| anyhow!("Unknown provider: {}", args.provider))?;
+            parse_sharegpt(json, &provider)
+                .with_context(|| format!("{} is not ShareGPT JSON", args.path.display()))?
+        }
+    };
+
+    let mut db = Database::open(db_path)?;
+    let (mut imported, mut skipped) = (0, 0);
+    for conversation in &conversations {
+        match db.import_conversation(conversation)? {
+            Some(_) => imported += 1,
+            None => skipped += 1,
+        }
+    }
+
+    println!("Imported {} conversations", imported);
+    if skipped > 0 {
+        println!("Skipped {} already imported", skipped);
+    }
+    Ok(())
+}
+
+/// The file's contents, or for a ChatGPT zip, its conversations.json
+fn read_export(path: &Path) -> Result<String> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let is_zip = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if !is_zip {
+        return std::io::read_to_string(file).with_context(|| format!("Failed to read {}", path.display()));
+    }
+
+    let mut archive = zip::ZipArchive::new(file).with_context(|| format!("{} is not a zip archive", path.display()))?;
+    // Exports have always had it at the top level, but don't depend on it
+    let name = archive
+        .file_names()
+        .find(|name| name.rsplit('/').next() == Some("conversations.json"))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("No conversations.json in {}", path.display()))?;
+
+    let mut json = String::new();
+    archive.by_name(&name)?.read_to_string(&mut json)?;
+    Ok(json)
+}
+
+#[derive(Deserialize)]
+struct ChatGptConversation {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    current_node: Option<String>,
+    mapping: HashMap<String, ChatGptNode>,
+}
+
+/// One node of the message tree; edits and regenerations branch it
+#[derive(Deserialize)]
+struct ChatGptNode {
+    #[serde(default)]
+    message: Option<ChatGptMessage>,
+    #[serde(default)]
+    parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    content: ChatGptContent,
+    #[serde(default)]
+    metadata: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+}
+
+#[derive(Deserialize, Default)]
+struct ChatGptContent {
+    #[serde(default)]
+    parts: Vec<serde_json::Value>,
+}
+
+/// Map ChatGPT threads onto conversations with the OpenAI provider. Only
+/// the branch that was last shown is kept, and only user and assistant
+/// text (no system prompts, tool calls or images).
+fn parse_chatgpt(json: serde_json::Value) -> Result<Vec<NewConversation>> {
+    let threads: Vec<ChatGptConversation> = serde_json::from_value(json)?;
+
+    Ok(threads
+        .into_iter()
+        .filter_map(|thread| {
+            let created_at = thread.create_time.unwrap_or(0.0) as i64;
+            let messages: Vec<NewMessage> = current_branch(&thread)
+                .into_iter()
+                .filter(|m| matches!(m.author.role.as_str(), "user" | "assistant"))
+                .filter(|m| m.metadata.get("is_visually_hidden_from_conversation") != Some(&serde_json::Value::Bool(true)))
+                .filter_map(|m| {
+                    let text: Vec<&str> = m.content.parts.iter().filter_map(|p| p.as_str()).collect();
+                    let content = text.join("\n").trim().to_string();
+                    (!content.is_empty()).then(|| NewMessage {
+                        role: m.author.role.clone(),
+                        content,
+                        timestamp: m.create_time.map_or(created_at, |t| t as i64),
+                    })
+                })
+                .collect();
+            if messages.is_empty() {
+                return None;
+            }
+
+            Some(NewConversation {
+                provider: "gpt".to_string(),
+                title: thread.title.clone().unwrap_or_default(),
+                created_at,
+                source: thread
+                    .conversation_id
+                    .as_ref()
+                    .or(thread.id.as_ref())
+                    .map(|id| format!("chatgpt:{}", id)),
+                messages,
+            })
+        })
+        .collect())
+}
+
+/// Messages from the root to `current_node`, oldest first
+fn current_branch(thread: &ChatGptConversation) -> Vec<&ChatGptMessage> {
+    let mut branch = Vec::new();
+    let mut node = thread.current_node.as_deref();
+    // Bounded so a malformed export with a parent cycle can't hang us
+    for _ in 0..thread.mapping.len() {
+        let Some(entry) = node.and_then(|id| thread.mapping.get(id)) else {
+            break;
+        };
+        branch.extend(entry.message.as_ref());
+        node = entry.parent.as_deref();
+    }
+    branch.reverse();
+    branch
+}
+
+#[derive(Deserialize)]
+struct ShareGptConversation {
+    #[serde(default)]
+    id: Option<String>,
+    conversations: Vec<ShareGptTurn>,
+}
+
+#[derive(Deserialize)]
+struct ShareGptTurn {
+    from: String,
+    value: String,
+}
+
+/// ShareGPT has no timestamps or titles: conversations are dated now and
+/// titled after their first message. System turns are dropped.
+fn parse_sharegpt(json: serde_json::Value, provider: &AIProvider) -> Result<Vec<NewConversation>> {
+    let entries: Vec<ShareGptConversation> = serde_json::from_value(json)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| {
+            let messages: Vec<NewMessage> = entry
+                .conversations
+                .into_iter()
+                .filter_map(|turn| {
+                    let role = match turn.from.to_lowercase().as_str() {
+                        "human" | "user" => "user",
+                        "gpt" | "assistant" | "chatgpt" | "bard" | "model" => "assistant",
+                        _ => return None,
+                    };
+                    Some(NewMessage {
+                        role: role.to_string(),
+                        content: turn.value,
+                        timestamp: now,
+                    })
+                })
+                .collect();
+            let title = conversation::auto_title(&messages.iter().find(|m| m.role == "user")?.content);
+
+            Some(NewConversation {
+                provider: provider.db_name().to_string(),
+                title,
+                created_at: now,
+                source: entry.id.map(|id| format!("sharegpt:{}", id)),
+                messages,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(role: &str, text: &str, parent: Option<&str>) -> serde_json::Value {
+        json!({
+            "message": {
+                "author": { "role": role },
+                "create_time": 1_700_000_100.0,
+                "content": { "content_type": "text", "parts": [text] },
+            },
+            "parent": parent,
+        })
+    }
+
+    #[test]
+    fn chatgpt_threads_keep_the_branch_last_shown() {
+        let export = json!([{
+            "conversation_id": "abc",
+            "title": "Rust lifetimes",
+            "create_time": 1_700_000_000.0,
+            "current_node": "answer-2",
+            "mapping": {
+                "root": { "message": null, "parent": null },
+                "system": node("system", "You are helpful", Some("root")),
+                "question": node("user", "What is 'a?", Some("system")),
+                "answer-1": node("assistant", "First try", Some("question")),
+                "answer-2": node("assistant", "A lifetime", Some("question")),
+            },
+        }]);
+        assert_eq!(ImportFormat::detect(&export), Some(ImportFormat::ChatGpt));
+
+        let conversations = parse_chatgpt(export).unwrap();
+        assert_eq!(conversations.len(), 1);
+        let c = &conversations[0];
+        assert_eq!((c.provider.as_str(), c.title.as_str()), ("gpt", "Rust lifetimes"));
+        assert_eq!(c.source.as_deref(), Some("chatgpt:abc"));
+        assert_eq!(c.created_at, 1_700_000_000);
+
+        let turns: Vec<_> = c.messages.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect();
+        assert_eq!(turns, [("user", "What is 'a?"), ("assistant", "A lifetime")]);
+        assert_eq!(c.messages[0].timestamp, 1_700_000_100);
+    }
+
+    #[test]
+    fn chatgpt_hidden_empty_and_non_text_parts_are_dropped() {
+        let mut hidden = node("user", "context", None);
+        hidden["message"]["metadata"] = json!({ "is_visually_hidden_from_conversation": true });
+        let mut image = node("user", "", Some("hidden"));
+        image["message"]["content"]["parts"] = json!([{ "asset_pointer": "file-1" }, " Describe this "]);
+
+        let export = json!([
+            {
+                "id": "with-image",
+                "current_node": "image",
+                "mapping": { "hidden": hidden, "image": image },
+            },
+            {
+                "id": "empty",
+                "current_node": "blank",
+                "mapping": { "blank": node("assistant", "  ", None) },
+            },
+        ]);
+
+        let conversations = parse_chatgpt(export).unwrap();
+        assert_eq!(conversations.len(), 1, "the thread with nothing to show is skipped");
+        let c = &conversations[0];
+        assert_eq!(c.source.as_deref(), Some("chatgpt:with-image"));
+        assert_eq!(c.title, "");
+        assert_eq!(c.messages.len(), 1);
+        assert_eq!(c.messages[0].content, "Describe this");
+    }
+
+    #[test]
+    fn a_parent_cycle_ends_the_branch() {
+        let export = json!([{
+            "current_node": "a",
+            "mapping": {
+                "a": node("assistant", "answer", Some("b")),
+                "b": node("user", "question", Some("a")),
+            },
+        }]);
+        let messages = &parse_chatgpt(export).unwrap()[0].messages;
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[test]
+    fn anything_else_is_not_a_chatgpt_export() {
+        assert!(parse_chatgpt(json!({ "conversations": [] })).is_err());
+        assert_eq!(ImportFormat::detect(&json!([{ "messages": [] }])), None);
+        assert_eq!(ImportFormat::detect(&json!([])), None);
+        assert_eq!(ImportFormat::parse("ChatGPT"), Some(ImportFormat::ChatGpt));
+    }
+
+    #[test]
+    fn sharegpt_exports_import_back_as_the_same_conversation() {
+        let source = Database::open_in_memory().unwrap();
+        let id = source.create_conversation("claude", "Ownership").unwrap();
+        let turns = [
+            ("user", "Explain borrowing\nin one line"),
+            ("assistant", "Many readers or one writer."),
+            ("user", "And moves?"),
+            ("assistant", "Ownership goes with the value."),
+        ];
+        for (role, content) in turns {
+            source.save_message(id, role, content, None).unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!("mega-cli-sharegpt-test-{}.json", std::process::id()));
+        crate::export::to_file(&source, &[id], crate::export::ExportFormat::ShareGpt, &path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(json[0]["conversations"][0]["from"], "human");
+        assert_eq!(json[0]["conversations"][1]["from"], "gpt");
+        assert_eq!(ImportFormat::detect(&json), Some(ImportFormat::ShareGpt));
+
+        let conversations = parse_sharegpt(json, &AIProvider::Claude).unwrap();
+        let mut target = Database::open_in_memory().unwrap();
+        let imported = target.import_conversation(&conversations[0]).unwrap().unwrap();
+        assert_eq!(target.import_conversation(&conversations[0]).unwrap(), None, "imported twice");
+
+        let c = &target.list_conversations(None).unwrap()[0];
+        assert_eq!((c.provider.as_str(), c.title.as_str()), ("claude", "Explain borrowing"));
+        let messages: Vec<_> = target
+            .get_messages(imported)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.role, m.content))
+            .collect();
+        let expected: Vec<_> = turns.iter().map(|&(r, c)| (r.to_string(), c.to_string())).collect();
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn sharegpt_roles_map_and_system_turns_drop() {
+        let json = json!([
+            {
+                "conversations": [
+                    { "from": "system", "value": "Be brief" },
+                    { "from": "Human", "value": "Hi" },
+                    { "from": "bard", "value": "Hello" },
+                ],
+            },
+            { "id": "no-question", "conversations": [{ "from": "gpt", "value": "Orphan" }] },
+        ]);
+        let conversations = parse_sharegpt(json, &AIProvider::Gemini).unwrap();
+        assert_eq!(conversations.len(), 1, "a conversation with no user turn has no title");
+        let c = &conversations[0];
+        assert_eq!((c.provider.as_str(), c.title.as_str(), c.source.as_deref()), ("gemini", "Hi", None));
+        let roles: Vec<_> = c.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant"]);
+    }
+}
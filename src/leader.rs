@@ -0,0 +1,271 @@
+//! The leader key: Space on an empty input box (or the `[keys] leader`),
+//! then a letter, does what a function or Ctrl key does or runs a slash
+//! command, so every action doesn't need a key of its own. Some letters
+//! open a group, with letters of its own. A popup shows what can follow.
+//!
+//! ```toml
+//! [keys]
+//! leader = "space"   # or "ctrl+space", "alt+x"; "none" turns it off
+//! ```
+
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use crate::macros::{format_key, parse_key, same_key};
+
+const HINTS: &str = "Esc Cancel  Backspace Back";
+
+/// Columns in the popup are this wide, letter and label
+const COLUMN_WIDTH: usize = 30;
+
+/// What a letter after the leader does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Whatever this key does
+    Key(KeyEvent),
+    /// Run this slash command
+    Command(&'static str),
+    /// Wait for one of these
+    Group(&'static [Binding]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Binding {
+    pub letter: char,
+    pub label: &'static str,
+    pub action: Action,
+}
+
+const fn bind(letter: char, label: &'static str, action: Action) -> Binding {
+    Binding { letter, label, action }
+}
+
+const fn ctrl(c: char) -> Action {
+    Action::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL))
+}
+
+const fn function(n: u8) -> Action {
+    Action::Key(KeyEvent::new(KeyCode::F(n), KeyModifiers::NONE))
+}
+
+const CONVERSATION: [Binding; 6] = [
+    bind('s', "Star the last reply", Action::Command("/star")),
+    bind('u', "Thumbs up the last reply", Action::Command("/rate up")),
+    bind('d', "Thumbs down the last reply", Action::Command("/rate down")),
+    bind('t', "List its tags", Action::Command("/tag")),
+    bind('v', "Variations of the last reply", Action::Command("/vary")),
+    bind('r', "Replay it", Action::Command("/replay")),
+];
+
+const AUDIO: [Binding; 3] = [
+    bind('m', "Mute background audio", function(5)),
+    bind('r', "Read replies aloud", function(8)),
+    bind('c', "Sound cues", function(9)),
+];
+
+const GIT: [Binding; 3] = [
+    bind('d', "Review the diff", Action::Command("/diff")),
+    bind('c', "Write a commit message", Action::Command("/commitmsg")),
+    bind('a', "Apply the reply's patch", Action::Command("/apply")),
+];
+
+const INFO: [Binding; 4] = [
+    bind('s', "Usage stats", Action::Command("/stats")),
+    bind('a', "A/B standings", Action::Command("/ab")),
+    bind('e', "Errors so far", Action::Command("/errors")),
+    bind('h', "Reload hooks", Action::Command("/hooks")),
+];
+
+/// What follows the leader
+pub const ROOT: [Binding; 12] = [
+    bind('n', "New conversation", ctrl('l')),
+    bind('f', "Search everything", ctrl('f')),
+    bind('s', "Snippets", ctrl('s')),
+    bind('g', "Context window", ctrl('g')),
+    bind('p', "Switch provider", function(2)),
+    bind('o', "Open a conversation", function(6)),
+    bind('m', "Manage sessions", function(7)),
+    bind('?', "Help", function(1)),
+    bind('c', "Conversation", Action::Group(&CONVERSATION)),
+    bind('a', "Audio", Action::Group(&AUDIO)),
+    bind('d', "Git", Action::Group(&GIT)),
+    bind('i', "Info", Action::Group(&INFO)),
+];
+
+/// What a key after the leader came to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// The leader, or a group, is waiting for another key
+    Wait,
+    Key(KeyEvent),
+    Command(&'static str),
+    /// Nothing's bound to the keys, written out
+    Unbound(String),
+}
+
+#[derive(Debug)]
+pub struct Leader {
+    /// None turns the leader off
+    key: Option<KeyEvent>,
+    /// Group letters typed since the leader, while it waits for more
+    typed: Option<String>,
+}
+
+impl Default for Leader {
+    fn default() -> Self {
+        Self {
+            key: Some(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE)),
+            typed: None,
+        }
+    }
+}
+
+impl Leader {
+    /// A leader on the key `name`, or none for "none" or nothing
+    pub fn new(name: &str) -> Result<Self> {
+        let name = name.trim();
+        if name.is_empty() || name.eq_ignore_ascii_case("none") {
+            return Ok(Self { key: None, typed: None });
+        }
+        let key = parse_key(name).ok_or_else(|| anyhow!("Unknown leader key: {} (try space or ctrl+space)", name))?;
+        Ok(Self { key: Some(key), typed: None })
+    }
+
+    /// The leader key's name, if there is one
+    pub fn name(&self) -> Option<String> {
+        self.key.as_ref().map(format_key)
+    }
+
+    /// Whether `key` starts a sequence. A leader without Ctrl or Alt
+    /// only does at an empty input box, so it can still be typed.
+    pub fn starts(&self, key: &KeyEvent, typing: bool) -> bool {
+        let Some(ref leader) = self.key else {
+            return false;
+        };
+        let plain = !leader.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        same_key(leader, key) && !(plain && typing)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.typed.is_some()
+    }
+
+    pub fn open(&mut self) {
+        self.typed = Some(String::new());
+    }
+
+    /// Returns whether it was open
+    pub fn close(&mut self) -> bool {
+        self.typed.take().is_some()
+    }
+
+    /// The group waiting for a key, and the groups on the way to it
+    fn group(&self) -> (&'static [Binding], Vec<&'static str>) {
+        let mut group: &'static [Binding] = &ROOT;
+        let mut path = vec![];
+        for letter in self.typed.as_deref().unwrap_or_default().chars() {
+            if let Some(binding) = group.iter().find(|b| b.letter == letter) {
+                if let Action::Group(inner) = binding.action {
+                    group = inner;
+                    path.push(binding.label);
+                }
+            }
+        }
+        (group, path)
+    }
+
+    /// Take the key after the leader or a group
+    pub fn follow(&mut self, key: &KeyEvent) -> Step {
+        let (group, _) = self.group();
+        let leader = self.name().unwrap_or_default();
+        let Some(ref mut typed) = self.typed else {
+            return Step::Unbound(format_key(key));
+        };
+        // Back out of a group, or out of the leader from the first
+        if key.code == KeyCode::Backspace {
+            if typed.pop().is_none() {
+                self.typed = None;
+            }
+            return Step::Wait;
+        }
+        let letter = match key.code {
+            KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => Some(c),
+            _ => None,
+        };
+        let step = match letter.and_then(|letter| group.iter().find(|b| b.letter == letter)) {
+            Some(binding) => match binding.action {
+                Action::Group(_) => {
+                    typed.push(binding.letter);
+                    return Step::Wait;
+                }
+                Action::Key(key) => Step::Key(key),
+                Action::Command(command) => Step::Command(command),
+            },
+            None => {
+                let mut keys = vec![leader];
+                keys.extend(typed.chars().map(String::from));
+                keys.push(format_key(key));
+                Step::Unbound(keys.join(" "))
+            }
+        };
+        self.typed = None;
+        step
+    }
+
+    /// What can follow, along the bottom of `area`
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        if !self.is_open() {
+            return;
+        }
+        let (group, path) = self.group();
+        let inner_width = area.width.saturating_sub(2) as usize;
+        let columns = (inner_width / COLUMN_WIDTH).max(1);
+        let rows = group.len().div_ceil(columns);
+        let height = (rows as u16 + 2).min(area.height);
+        let popup = Rect {
+            x: area.x,
+            y: area.y + area.height - height,
+            width: area.width,
+            height,
+        };
+        frame.render_widget(Clear, popup);
+
+        let mut title = self.name().unwrap_or_default();
+        for label in path {
+            title.push_str(" › ");
+            title.push_str(label);
+        }
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(title)
+            .title_bottom(Line::from(HINTS).centered())
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(popup);
+        frame.render_widget(block, popup);
+
+        // Down each column, then across
+        let mut lines = vec![Line::default(); rows];
+        for (index, binding) in group.iter().enumerate() {
+            let (label, color) = match binding.action {
+                Action::Group(_) => (format!("+{}", binding.label), Color::Magenta),
+                _ => (binding.label.to_string(), Color::White),
+            };
+            let label: String = label.chars().take(COLUMN_WIDTH - 4).collect();
+            let line = &mut lines[index % rows];
+            line.push_span(Span::styled(
+                format!("{} ", binding.letter),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ));
+            line.push_span(Span::styled(
+                format!("{:<width$}", label, width = COLUMN_WIDTH - 2),
+                Style::default().fg(color),
+            ));
+        }
+        frame.render_widget(Paragraph::new(lines), inner);
+    }
+}
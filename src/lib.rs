@@ -0,0 +1,74 @@
+//! MEGA-CLI as a library. The provider clients (`providers`), the
+//! conversation store (`store`), the open conversations (`conversation`),
+//! sending them (`outbox`) and the animated background (`background`)
+//! don't depend on the terminal UI, so other programs, integration tests
+//! and benchmarks can use them directly; `app` is the chat screen the
+//! `animated-cli` binary runs, and `chat` draws it.
+
+pub mod accessibility;
+pub mod app;
+pub mod ask;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "video")]
+pub mod background;
+pub mod capabilities;
+pub mod chat;
+pub mod clipboard;
+pub mod commands;
+pub mod config;
+pub mod context;
+#[cfg(unix)]
+pub mod control;
+pub mod conversation;
+pub mod crash;
+#[cfg(unix)]
+pub mod daemon;
+pub mod doctor;
+pub mod eval;
+pub mod events;
+pub mod export;
+pub mod git;
+#[cfg(feature = "scripting")]
+pub mod hooks;
+pub mod import;
+pub mod leader;
+pub mod logging;
+pub mod macros;
+pub mod mentions;
+pub mod models;
+pub mod outbox;
+#[cfg(feature = "video")]
+pub mod overlay;
+pub mod pacing;
+pub mod paste;
+pub mod patch;
+pub mod problems;
+pub mod providers;
+pub mod ratelimit;
+pub mod record;
+pub mod redact;
+pub mod reload;
+pub mod render;
+pub mod replay;
+#[cfg(feature = "video")]
+pub mod screensaver;
+pub mod search;
+pub mod serve;
+pub mod sessions;
+pub mod share;
+pub mod snippets;
+#[cfg(feature = "audio")]
+pub mod sounds;
+pub mod speech;
+pub mod stats;
+pub mod store;
+pub mod sync;
+pub mod tables;
+pub mod templates;
+pub mod terminal;
+pub mod transcript;
+pub mod update;
+pub mod variations;
+#[cfg(feature = "video")]
+pub mod youtube;
@@ -0,0 +1,53 @@
+//! Log file for what the alternate screen hides: API requests and replies,
+//! background decode errors, notices and panics. One file a day under the
+//! data directory's `logs`, oldest removed after `[log] keep_days`.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+use crate::config::LogConfig;
+
+/// Where log files go
+pub fn log_dir() -> Result<PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "mega-cli")
+        .ok_or_else(|| anyhow!("Couldn't find a data directory for logs"))?;
+    Ok(dirs.data_dir().join("logs"))
+}
+
+/// Start logging at `level` (the flag, else the config's). Returns the
+/// guard that flushes the file when dropped, or None when logging is off.
+/// Panics are logged too, before the usual message.
+pub fn init(level: Option<&str>, config: &LogConfig) -> Result<Option<WorkerGuard>> {
+    let name = level.unwrap_or(&config.level);
+    let level: LevelFilter = name
+        .parse()
+        .map_err(|_| anyhow!("Unknown log level: {} (expected off, error, warn, info, debug or trace)", name))?;
+    if level == LevelFilter::OFF {
+        return Ok(None);
+    }
+
+    let dir = log_dir()?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("mega-cli")
+        .filename_suffix("log")
+        .max_log_files(config.keep_days.max(1))
+        .build(&dir)
+        .with_context(|| format!("Failed to open a log file in {}", dir.display()))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_max_level(level)
+        .init();
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!("{}", info);
+        previous(info);
+    }));
+    Ok(Some(guard))
+}
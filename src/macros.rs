@@ -0,0 +1,319 @@
+//! Keyboard macros: Ctrl+R records keys until it's pressed again, Ctrl+P
+//! plays the recording back, and `/macro save name [key]` keeps it in the
+//! config, bound to a key if one is given:
+//!
+//! ```toml
+//! [macros.review]
+//! key = "alt+r"
+//! keys = "<f2><f2>/t review<enter>"
+//! ```
+//!
+//! In `keys`, text stands for itself and a key by name goes in angle
+//! brackets, written as a binding is: `<enter>`, `<ctrl+s>`, `<f2>`.
+//! `<lt>` is a `<`. A macro that sends a message waits for the reply
+//! before playing on.
+
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::config::MacroConfig;
+
+/// Keys a macro can't be bound to: quitting, suspending, and recording
+/// and playing macros themselves
+const RESERVED: [&str; 4] = ["ctrl+c", "ctrl+z", "ctrl+r", "ctrl+p"];
+
+/// Names that are `/macro` subcommands rather than macros
+const SUBCOMMANDS: [&str; 2] = ["save", "delete"];
+
+#[derive(Debug, Default)]
+pub struct Macros {
+    saved: BTreeMap<String, MacroConfig>,
+    /// Keys so far, while recording
+    recording: Option<Vec<KeyEvent>>,
+    /// The latest finished recording, for Ctrl+P and `/macro save`
+    last: Vec<KeyEvent>,
+    /// Keys still to play; Some until the last one has been handled
+    playing: Option<VecDeque<KeyEvent>>,
+}
+
+impl Macros {
+    pub fn new(config: &BTreeMap<String, MacroConfig>) -> Self {
+        Self {
+            saved: config.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Take up edited `[macros]`; a recording in progress carries on
+    pub fn configure(&mut self, config: &BTreeMap<String, MacroConfig>) {
+        self.saved = config.clone();
+    }
+
+    /// The saved macros, as they go in the config
+    pub fn saved(&self) -> &BTreeMap<String, MacroConfig> {
+        &self.saved
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// How many keys the recording has so far
+    pub fn recorded(&self) -> usize {
+        self.recording.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    /// Finish recording. Returns how many keys it took, keeping them as
+    /// the last recording unless there were none.
+    pub fn stop_recording(&mut self) -> usize {
+        let keys = self.recording.take().unwrap_or_default();
+        let count = keys.len();
+        if count > 0 {
+            self.last = keys;
+        }
+        count
+    }
+
+    /// Add a handled key to the recording, if there is one
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some(ref mut keys) = self.recording {
+            keys.push(KeyEvent::new(key.code, key.modifiers));
+        }
+    }
+
+    /// The saved macro bound to `key`, if any
+    pub fn bound_to(&self, key: &KeyEvent) -> Option<String> {
+        self.saved
+            .iter()
+            .find(|(_, saved)| saved.key.as_deref().and_then(parse_key).is_some_and(|bound| same_key(&bound, key)))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Start playing a saved macro
+    pub fn play(&mut self, name: &str) -> Result<()> {
+        let saved = self.saved.get(name).ok_or_else(|| anyhow!("No macro called {}", name))?;
+        let keys = parse_keys(&saved.keys).map_err(|e| anyhow!("The macro {} can't be played: {:#}", name, e))?;
+        self.start_playing(keys)
+    }
+
+    /// Start playing the last recording
+    pub fn play_last(&mut self) -> Result<()> {
+        if self.last.is_empty() {
+            bail!("Nothing recorded yet; Ctrl+R starts recording");
+        }
+        self.start_playing(self.last.clone())
+    }
+
+    fn start_playing(&mut self, keys: Vec<KeyEvent>) -> Result<()> {
+        // Also keeps a macro from playing itself forever
+        if self.playing.is_some() {
+            bail!("A macro is already playing");
+        }
+        self.playing = Some(keys.into());
+        Ok(())
+    }
+
+    /// How many keys are still to play
+    pub fn keys_left(&self) -> usize {
+        self.playing.as_ref().map_or(0, VecDeque::len)
+    }
+
+    /// The next key to play, or None once the macro's done
+    pub fn next_key(&mut self) -> Option<KeyEvent> {
+        let key = self.playing.as_mut()?.pop_front();
+        if key.is_none() {
+            self.playing = None;
+        }
+        key
+    }
+
+    /// Stop a macro partway. Returns whether one had keys left.
+    pub fn stop(&mut self) -> bool {
+        let stopped = self.keys_left() > 0;
+        if stopped {
+            self.playing = None;
+        }
+        stopped
+    }
+
+    /// Keep the last recording as `name`, bound to `key` if given.
+    /// Returns whether it replaced a macro of that name.
+    pub fn save(&mut self, name: &str, key: Option<&str>) -> Result<bool> {
+        if SUBCOMMANDS.contains(&name) {
+            bail!("A macro can't be called {}", name);
+        }
+        if self.last.is_empty() {
+            bail!("Nothing recorded yet; Ctrl+R starts recording");
+        }
+        let key = match key {
+            Some(key) => {
+                let parsed = parse_key(key).ok_or_else(|| anyhow!("Unknown key: {} (try alt+1 or ctrl+g)", key))?;
+                let key = format_key(&parsed);
+                let modified = parsed.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+                if RESERVED.contains(&key.to_ascii_lowercase().as_str()) || !(modified || matches!(parsed.code, KeyCode::F(_))) {
+                    bail!("{} can't play a macro; pick a key with Ctrl or Alt, or an F key", key);
+                }
+                if let Some(other) = self.bound_to(&parsed).filter(|other| other != name) {
+                    bail!("{} already plays the macro {}", key, other);
+                }
+                Some(key)
+            }
+            None => None,
+        };
+        let saved = MacroConfig {
+            key,
+            keys: format_keys(&self.last),
+        };
+        Ok(self.saved.insert(name.to_string(), saved).is_some())
+    }
+
+    /// Forget a saved macro. Returns whether there was one.
+    pub fn delete(&mut self, name: &str) -> bool {
+        self.saved.remove(name).is_some()
+    }
+
+    /// The saved macros and last recording, a line each
+    pub fn list(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .saved
+            .iter()
+            .map(|(name, saved)| match saved.key {
+                Some(ref key) => format!("{} ({}): {}", name, key, saved.keys),
+                None => format!("{}: {}", name, saved.keys),
+            })
+            .collect();
+        if !self.last.is_empty() {
+            lines.push(format!("Last recording (Ctrl+P): {}", format_keys(&self.last)));
+        }
+        lines
+    }
+}
+
+/// Whether a typed key is a bound one. Terminals differ on reporting
+/// Shift with a character, and on its case with Ctrl.
+pub fn same_key(bound: &KeyEvent, typed: &KeyEvent) -> bool {
+    let normal = |key: &KeyEvent| match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            (KeyCode::Char(c.to_ascii_lowercase()), key.modifiers - KeyModifiers::SHIFT)
+        }
+        KeyCode::Char(c) => (KeyCode::Char(c), key.modifiers - KeyModifiers::SHIFT),
+        code => (code, key.modifiers),
+    };
+    normal(bound) == normal(typed)
+}
+
+/// A key by name, as bindings and `<...>` in a macro's keys are written:
+/// modifiers joined with `+`, then a character or a key's name
+pub fn parse_key(name: &str) -> Option<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name.trim();
+    // "ctrl++" is Ctrl and the + key
+    while let Some((modifier, after)) = rest.split_once('+').filter(|(_, after)| !after.is_empty()) {
+        modifiers |= match modifier.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        rest = after;
+    }
+    let mut chars = rest.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyEvent::new(KeyCode::Char(c), modifiers));
+    }
+    let code = match rest.to_ascii_lowercase().as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "backspace" | "bs" => KeyCode::Backspace,
+        "esc" | "escape" => KeyCode::Esc,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pgup" | "pageup" => KeyCode::PageUp,
+        "pgdn" | "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "del" | "delete" => KeyCode::Delete,
+        "ins" | "insert" => KeyCode::Insert,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        other => KeyCode::F(other.strip_prefix('f')?.parse().ok().filter(|n| (1..=24).contains(n))?),
+    };
+    Some(KeyEvent::new(code, modifiers))
+}
+
+/// A key's name, as `parse_key` reads it
+pub fn format_key(key: &KeyEvent) -> String {
+    let mut name = String::new();
+    for (modifier, prefix) in [
+        (KeyModifiers::CONTROL, "ctrl+"),
+        (KeyModifiers::ALT, "alt+"),
+        (KeyModifiers::SHIFT, "shift+"),
+    ] {
+        // A character's case already says whether Shift was down
+        if key.modifiers.contains(modifier) && !(modifier == KeyModifiers::SHIFT && matches!(key.code, KeyCode::Char(_))) {
+            name.push_str(prefix);
+        }
+    }
+    match key.code {
+        KeyCode::Char(' ') => name.push_str("space"),
+        KeyCode::Char('<') => name.push_str("lt"),
+        KeyCode::Char(c) => name.push(c),
+        KeyCode::F(n) => name.push_str(&format!("f{}", n)),
+        code => name.push_str(match code {
+            KeyCode::Enter => "enter",
+            KeyCode::Tab => "tab",
+            KeyCode::BackTab => "backtab",
+            KeyCode::Backspace => "backspace",
+            KeyCode::Esc => "esc",
+            KeyCode::Up => "up",
+            KeyCode::Down => "down",
+            KeyCode::Left => "left",
+            KeyCode::Right => "right",
+            KeyCode::PageUp => "pgup",
+            KeyCode::PageDown => "pgdn",
+            KeyCode::Home => "home",
+            KeyCode::End => "end",
+            KeyCode::Delete => "del",
+            KeyCode::Insert => "ins",
+            _ => "unknown",
+        }),
+    }
+    name
+}
+
+/// A macro's keys from their written form
+pub fn parse_keys(text: &str) -> Result<Vec<KeyEvent>> {
+    let mut keys = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            let end = rest.find('>').ok_or_else(|| anyhow!("no > after {}", rest))?;
+            let name = &rest[1..end];
+            keys.push(parse_key(name).ok_or_else(|| anyhow!("unknown key <{}>", name))?);
+            rest = &rest[end + 1..];
+        } else {
+            keys.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Ok(keys)
+}
+
+/// Keys written out: typed text as itself, and everything else by name
+/// in angle brackets
+pub fn format_keys(keys: &[KeyEvent]) -> String {
+    keys.iter()
+        .map(|key| match key.code {
+            KeyCode::Char(c) if c != '<' && (key.modifiers - KeyModifiers::SHIFT).is_empty() => c.to_string(),
+            _ => format!("<{}>", format_key(key)),
+        })
+        .collect()
+}
@@ -1,152 +1,447 @@
+//! The `animated-cli` binary: command-line parsing, and setting up the
+//! terminal for the chat
+
 use anyhow::Result;
-use clap::Parser;
-use crossterm::{
-    event::{self, Event, KeyCode, KeyModifiers},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-use ratatui::{
-    prelude::*,
-};
-use std::time::Duration;
-
-mod video;
-mod chat;
-mod ai;
-mod db;
-
-use video::VideoBackground;
-use chat::ChatInterface;
-use ai::AIProvider;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[cfg(feature = "video")]
+use mega_cli::background::setup::{BackgroundArgs, BackgroundSetup};
+#[cfg(unix)]
+use mega_cli::{control, daemon};
+use mega_cli::app::App;
+use mega_cli::config::{self, Config};
+use mega_cli::pacing::{self, Pacer};
+use mega_cli::problems::{Problem, Source};
+use mega_cli::providers::AIProvider;
+use mega_cli::store::{self, Database};
+use mega_cli::terminal::Tui;
+use mega_cli::share::{Guest, Host};
+use mega_cli::{ask, crash, doctor, export, import, logging, models, record, render, search, serve, sessions, stats, sync, update};
 
 #[derive(Parser, Debug)]
 #[command(name = "MEGA-CLI", about = "Multi-AI terminal chatbot with animated background")]
 struct Args {
-    /// AI provider to use (claude, grok, gpt, gemini)
-    #[arg(long, default_value = "claude")]
-    provider: String,
+    /// Named profile with its own config, API keys and database
+    #[arg(long, global = true)]
+    profile: Option<String>,
 
-    /// Video background opacity (0.0 - 1.0)
-    #[arg(long, default_value = "0.3")]
-    opacity: f32,
-}
+    /// Conversation database file [config: database.path]
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
 
-struct App {
-    video_bg: VideoBackground,
-    chat: ChatInterface,
-    should_quit: bool,
-}
+    /// Log file detail: off, error, warn, info, debug or trace [config: log.level, default: info]
+    #[arg(long, global = true)]
+    log_level: Option<String>,
 
-impl App {
-    fn new(provider: AIProvider, opacity: f32) -> Result<Self> {
-        // Get terminal size for video scaling
-        let size = crossterm::terminal::size()?;
-        let video_bg = VideoBackground::new("loading.mp4", size.0, size.1, opacity)?;
+    /// Chat flags, for running without a subcommand
+    #[command(flatten)]
+    chat: ChatArgs,
 
-        Ok(Self {
-            video_bg,
-            chat: ChatInterface::new(provider),
-            should_quit: false,
-        })
-    }
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
 
-    fn handle_input(&mut self) -> Result<()> {
-        if event::poll(Duration::from_millis(16))? {
-            if let Event::Key(key) = event::read()? {
-                // Global quit handlers
-                if key.code == KeyCode::Esc
-                    || (key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c'))
-                {
-                    self.should_quit = true;
-                    return Ok(());
-                }
-
-                // Pass to chat interface
-                self.chat.handle_key(key)?;
+impl Args {
+    /// Parse the command line, refusing chat flags given before another
+    /// subcommand (they'd be ignored). Global flags go anywhere.
+    fn parse_checked() -> Self {
+        let mut command = Self::command();
+        let matches = command.get_matches_mut();
+        let args = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        if args.command.is_some() {
+            let global = ["profile", "db", "log_level"];
+            let misplaced = matches.ids().find(|id| {
+                !global.contains(&id.as_str()) && matches.value_source(id.as_str()) == Some(ValueSource::CommandLine)
+            });
+            if let Some(id) = misplaced {
+                let flag = command
+                    .get_arguments()
+                    .find(|arg| arg.get_id() == id)
+                    .and_then(|arg| arg.get_long())
+                    .unwrap_or(id.as_str())
+                    .to_string();
+                command
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        format!("--{} is a chat flag; put it after `chat` or `sessions`, or leave out the subcommand", flag),
+                    )
+                    .exit();
             }
         }
-        Ok(())
+        args
     }
+}
 
-    fn update(&mut self) -> Result<()> {
-        // Update video background (gets next frame)
-        self.video_bg.update();
+/// Flags for the chat UI
+#[derive(clap::Args, Debug)]
+struct ChatArgs {
+    /// AI provider to use (claude, grok, gpt, gemini) [config: provider, default: claude]
+    #[arg(long)]
+    provider: Option<String>,
 
-        // Update chat (polls AI responses)
-        self.chat.update()?;
+    /// Most redraws per second while anything is changing [config: display.fps, default: 30]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=pacing::MAX_FPS as i64))]
+    fps: Option<u32>,
 
-        Ok(())
-    }
+    /// Checks for changes per second once nothing has changed for a moment [config: display.idle_fps, default: 2]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=pacing::MAX_FPS as i64))]
+    idle_fps: Option<u32>,
 
-    fn render(&mut self, frame: &mut Frame) -> Result<()> {
-        let area = frame.area();
+    /// High contrast, no background or animation, and status changes
+    /// spelled out in the footer, for screen readers and low vision
+    /// [config: display.accessible]
+    #[arg(long)]
+    accessible: bool,
 
-        // First render video background with opacity
-        self.video_bg.render_background(frame.buffer_mut(), area);
+    /// Append every message to this transcript file as it's sent: JSON
+    /// lines for a .jsonl path, Markdown otherwise
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
 
-        // Then render chat interface on top
-        self.chat.render(frame)?;
+    /// Save nothing: conversations are kept in memory and gone on exit
+    #[arg(long, conflicts_with = "db")]
+    incognito: bool,
 
-        Ok(())
-    }
+    /// Reopen the most recently active conversation (with --provider's
+    /// provider, if given)
+    #[arg(long = "continue", short = 'c', conflicts_with = "incognito")]
+    continue_last: bool,
 
-    fn should_quit(&self) -> bool {
-        self.should_quit
-    }
+    /// Open a saved conversation by id or title
+    #[arg(long, conflicts_with_all = ["incognito", "continue_last"])]
+    session: Option<String>,
+
+    /// Try it offline: the mock provider answers, the built-in background
+    /// plays (unless --video says otherwise) and nothing is saved
+    #[arg(long, conflicts_with_all = ["provider", "db", "incognito", "continue_last", "session"])]
+    demo: bool,
+
+    /// Share the conversation: listen on ADDR (such as 0.0.0.0:7878) for
+    /// others to --join
+    #[arg(long, value_name = "ADDR", conflicts_with = "join")]
+    host: Option<String>,
+
+    /// Join a conversation shared with --host on another machine, at
+    /// ADDR (host:port). Nothing is saved here.
+    #[arg(
+        long,
+        value_name = "ADDR",
+        requires = "code",
+        conflicts_with_all = ["provider", "db", "incognito", "continue_last", "session", "demo", "record"]
+    )]
+    join: Option<String>,
+
+    /// The join code the host shows
+    #[arg(long, requires = "join")]
+    code: Option<String>,
+
+    /// Name on your messages in a shared session [default: your login name]
+    #[arg(long, requires = "join")]
+    name: Option<String>,
+
+    /// Take `control` requests on this socket instead of the profile's,
+    /// to drive more than one chat
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH")]
+    control: Option<PathBuf>,
+
+    #[cfg(feature = "video")]
+    #[command(flatten)]
+    background: BackgroundArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Chat (what runs when no subcommand is given)
+    Chat(ChatArgs),
+    /// Ask one question and print the answer, without the chat UI
+    Ask(ask::AskArgs),
+    /// Start in the session manager to browse, rename, archive or delete conversations
+    Sessions(ChatArgs),
+    /// Write saved conversations as JSON, JSONL or an HTML page
+    Export(export::ExportArgs),
+    /// Play a saved conversation back over the background into an asciinema cast, GIF or MP4
+    Render(render::RenderArgs),
+    /// Import conversations from a ChatGPT data export
+    Import(import::ImportArgs),
+    /// Search every saved conversation
+    Search(search::SearchArgs),
+    /// Chart messages, tokens, cost and reply times
+    Stats(stats::StatsArgs),
+    /// List providers, their default models, prices and whether a key is set
+    Models,
+    /// Check the config, API keys, database and terminal for problems
+    Doctor,
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Answer OpenAI-style /v1/chat/completions requests locally with any provider
+    Serve(serve::ServeArgs),
+    /// Keep provider connections warm and the database open in the background, for faster starts
+    #[cfg(unix)]
+    Daemon(daemon::DaemonArgs),
+    /// Drive a running chat: send it messages, switch its provider, export or show its status
+    #[cfg(unix)]
+    Control(control::ControlArgs),
+    /// Write a consistent snapshot of the conversation database
+    Backup {
+        /// File to create
+        path: PathBuf,
+    },
+    /// Replace the conversation database with a backup
+    Restore {
+        /// Backup to restore
+        path: PathBuf,
+    },
+    /// Check the conversation database and fix it if it's damaged
+    Repair {
+        /// Salvage what can be read instead of restoring the newest backup
+        #[arg(long)]
+        salvage: bool,
+    },
+    /// Merge history with other machines through a shared folder or bundle files
+    Sync(sync::SyncArgs),
+    /// Apply the [retention] policy from the config now
+    Prune {
+        /// List what would be archived or deleted without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Download and install the newest release, or just check for one
+    Update(update::UpdateArgs),
+    /// Encrypt the conversation database with a passphrase
+    #[cfg(feature = "encryption")]
+    Encrypt {
+        /// Save the passphrase in the OS keychain so it isn't asked for on launch
+        #[arg(long)]
+        keychain: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print the effective settings, where each API key comes from, and
+    /// keys set under a name that isn't read (ANTROPIC_API_KEY)
+    Show,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let args = Args::parse_checked();
 
     // Load environment variables
     let _ = dotenvy::dotenv();
 
+    let profile = args.profile.as_deref().map(config::parse_profile).transpose()?.flatten();
+    let config = Config::load(profile.as_deref()).unwrap_or_else(|e| {
+        eprintln!("{:#}. Using defaults.", e);
+        Config { profile: profile.clone(), ..Config::default() }
+    });
+    // Held to the end so the last lines reach the file
+    let _log = logging::init(args.log_level.as_deref(), &config.log).unwrap_or_else(|e| {
+        eprintln!("{:#}. Not logging.", e);
+        None
+    });
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), profile = ?config.profile, "starting");
+    crash::install(&config);
+    let db_path = Database::resolve_path(args.db, &config)?;
+
+    // Subcommands that don't start the chat UI
+    let (chat, start_in_sessions) = match args.command {
+        Some(CliCommand::Ask(ask)) => return ask::run(ask, &db_path, &config).await,
+        Some(CliCommand::Export(export)) => return export::run(export, &db_path),
+        Some(CliCommand::Render(render)) => return render::run(render, &db_path, &config).await,
+        Some(CliCommand::Import(import)) => return import::run(import, &db_path),
+        Some(CliCommand::Search(search)) => return search::run(search, &db_path),
+        Some(CliCommand::Stats(stats)) => return stats::run(stats, &db_path),
+        Some(CliCommand::Models) => return models::run(&config),
+        Some(CliCommand::Doctor) => return doctor::run(&config, &db_path),
+        Some(CliCommand::Config { action: ConfigAction::Show }) => return config::show(&config, &db_path),
+        Some(CliCommand::Serve(serve)) => return serve::run(serve, &db_path, &config).await,
+        #[cfg(unix)]
+        Some(CliCommand::Daemon(daemon)) => {
+            return daemon::run(daemon, &db_path, &config, args.log_level.as_deref()).await
+        }
+        #[cfg(unix)]
+        Some(CliCommand::Control(control)) => return control::run(control, &config).await,
+        Some(CliCommand::Prune { dry_run }) => return store::prune(&db_path, &config.retention, dry_run),
+        Some(CliCommand::Backup { path }) => return store::backup(&db_path, &path),
+        Some(CliCommand::Sync(sync)) => return sync::run(sync, &db_path, &config.sync),
+        Some(CliCommand::Restore { path }) => return store::restore(&db_path, &path),
+        Some(CliCommand::Repair { salvage }) => return store::repair(&db_path, &config.backup, salvage),
+        Some(CliCommand::Update(update)) => return update::run(update).await,
+        #[cfg(feature = "encryption")]
+        Some(CliCommand::Encrypt { keychain }) => return store::encrypt(&db_path, keychain),
+        Some(CliCommand::Chat(chat)) => (chat, false),
+        Some(CliCommand::Sessions(chat)) => (chat, true),
+        None => (args.chat, false),
+    };
+
     // Parse AI provider
-    let provider = match args.provider.to_lowercase().as_str() {
-        "claude" => AIProvider::Claude,
-        "grok" => AIProvider::Grok,
-        "gpt" | "openai" => AIProvider::OpenAI,
-        "gemini" => AIProvider::Gemini,
-        _ => {
-            eprintln!("Unknown provider: {}. Using Claude.", args.provider);
+    let provider_given = chat.provider.is_some();
+    let provider_name = chat.provider.or_else(|| config.provider.clone()).unwrap_or_else(|| "claude".to_string());
+    let provider = if chat.demo {
+        AIProvider::Mock
+    } else {
+        AIProvider::parse(&provider_name).unwrap_or_else(|| {
+            eprintln!("Unknown provider: {}. Using Claude.", provider_name);
             AIProvider::Claude
-        }
+        })
     };
+    // A demo keeps nothing, like an incognito session, and neither does
+    // a guest: the host saves the shared conversation
+    let incognito = chat.incognito || chat.demo || chat.join.is_some();
 
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = std::io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    // The chat still runs without history if the database won't open, or
+    // is damaged and not repaired
+    let opened = if incognito {
+        Database::open_in_memory()
+    } else {
+        store::open_checked(&db_path, &config.backup)
+    };
+    let (mut db, db_error) = match opened {
+        Ok(db) => (Some(db), None),
+        Err(e) => (None, Some(e)),
+    };
+    // Before history loads, so pruned conversations don't reopen. Neither
+    // applies to an incognito session.
+    let pruned = db
+        .as_mut()
+        .filter(|_| !incognito)
+        .map(|db| db.apply_retention(&config.retention, false));
+    // Taken from a second connection on another thread, as it copies the
+    // whole file. An encrypted database has none to give, so it waits.
+    let backed_up = db.as_ref().filter(|_| !incognito).map(|db| {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        match db.reader() {
+            Some(reader) => {
+                let (db_path, backups) = (db_path.clone(), config.backup.clone());
+                std::thread::spawn(move || {
+                    let _ = tx.send(reader.auto_backup(&db_path, &backups));
+                });
+            }
+            None => {
+                let _ = tx.send(db.auto_backup(&db_path, &config.backup));
+            }
+        }
+        rx
+    });
 
-    // Run app
-    let mut app = App::new(provider, args.opacity)?;
+    // Asked for by name, so not finding it is worth stopping for
+    let resume = match (&db, &db_error) {
+        _ if !chat.continue_last && chat.session.is_none() => None,
+        (Some(db), _) => {
+            let filter = Some(provider.db_name()).filter(|_| provider_given);
+            Some(sessions::find(db, chat.session.as_deref(), filter)?)
+        }
+        (None, Some(e)) => anyhow::bail!("Conversation history unavailable: {:#}", e),
+        (None, None) => None,
+    };
 
-    loop {
-        terminal.draw(|f| {
-            if let Err(e) = app.render(f) {
-                eprintln!("Render error: {}", e);
-            }
-        })?;
+    let recorder = chat.record.as_deref().map(record::Recorder::open).transpose()?;
+    let host = match chat.host {
+        Some(ref addr) => Some(Host::start(addr).await?),
+        None => None,
+    };
+    let joined = match (&chat.join, &chat.code) {
+        (Some(addr), Some(code)) => Some(Guest::join(addr, code, chat.name.clone()).await?),
+        _ => None,
+    };
+    // Asked for by path, so not getting it is worth stopping for. Another
+    // chat may have the profile's.
+    #[cfg(unix)]
+    let control = match chat.control {
+        Some(ref path) => Some(control::Control::start(path.clone())?),
+        None => control::socket_path(config.profile.as_deref())
+            .and_then(control::Control::start)
+            .inspect_err(|e| tracing::warn!("Not taking control requests: {:#}", e))
+            .ok(),
+    };
 
-        app.handle_input()?;
+    // Answered while the chat runs; a demo stays offline
+    let newer_release = (config.updates.check && !chat.demo).then(|| {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            if let Some(version) = update::newer_release().await {
+                let _ = tx.send(version);
+            }
+        });
+        rx
+    });
 
-        if app.should_quit() {
-            break;
+    #[cfg(feature = "video")]
+    let background = {
+        let mut background = chat.background;
+        if chat.demo {
+            background.default_video(mega_cli::background::BUILTIN_SOURCE);
         }
+        BackgroundSetup::resolve(background, &config)
+    };
 
-        app.update()?;
+    // Restored when this goes out of scope, however that happens
+    let mut tui = Tui::enter()?;
 
-        // Small sleep to prevent CPU spinning (60 FPS)
-        tokio::time::sleep(Duration::from_millis(16)).await;
+    // Run app
+    let pacer = Pacer::new(
+        chat.fps.unwrap_or(config.display.fps),
+        chat.idle_fps.unwrap_or(config.display.idle_fps),
+    );
+    // A guest uses the host's keys
+    let key_warning = config::startup_warning(&config, &provider).filter(|_| joined.is_none());
+    let mut app = App::new(
+        provider,
+        db,
+        #[cfg(feature = "video")]
+        background,
+        pacer,
+        config,
+        chat.accessible,
+    )?;
+    let chat = app.chat_mut();
+    // Errors below open over it; Esc closes them first
+    if let Some(warning) = key_warning {
+        chat.show_notice(warning);
+    }
+    if let Some(e) = db_error {
+        chat.show_problem(Problem::new(Source::Database, "Conversation history unavailable", e));
+    }
+    match pruned {
+        Some(Ok(report)) if !report.is_empty() => chat.flash(report.summary()),
+        Some(Err(e)) => chat.show_problem(Problem::new(Source::Database, "Couldn't apply the retention policy", e)),
+        _ => {}
+    }
+    if let Some(backed_up) = backed_up {
+        chat.watch_backup(backed_up);
+    }
+    if let Some(recorder) = recorder {
+        chat.start_recording(recorder);
+    }
+    if let Some(host) = host {
+        chat.start_hosting(host);
+    }
+    if let Some((guest, conversation)) = joined {
+        chat.join(guest, conversation);
+    }
+    #[cfg(unix)]
+    if let Some(control) = control {
+        chat.start_control(control);
+    }
+    if let Some(ref conversation) = resume {
+        chat.resume(conversation);
+    }
+    if start_in_sessions {
+        chat.open_sessions();
+    }
+    if let Some(newer_release) = newer_release {
+        chat.watch_for_update(newer_release);
     }
 
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+    app.run(&mut tui)?;
+    drop(tui);
 
     println!("Thanks for using MEGA-CLI! 👋");
 
@@ -0,0 +1,237 @@
+//! `@path` mentions in a message: each names a local file whose contents
+//! are sent along with it, fenced and labelled with the file's language.
+//! Paths are relative to the directory the chat was started in; `~/`
+//! means the home directory.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::store::expand_home;
+
+/// Largest file that can be attached
+const MAX_FILE_KB: u64 = 100;
+/// Most that can be attached to one message
+const MAX_TOTAL_KB: u64 = 250;
+
+/// Files taken to be images, which only some models can read
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// Most completions offered for a partly typed path
+const MAX_COMPLETIONS: usize = 50;
+
+/// Punctuation that ends a sentence rather than a path, as in "see @a.rs."
+const TRAILING: [char; 8] = ['.', ',', ';', ':', '!', '?', ')', '"'];
+
+/// The message with every mentioned file appended in a fenced block, or
+/// as it was if it mentions none. `@name` that isn't a file is left as
+/// text (it may be a handle) unless it has a `/` in it, which makes it a
+/// mistyped path.
+pub fn attach(text: &str, dir: &Path) -> Result<String> {
+    let mut files = Vec::new();
+    let mut total = 0;
+    for mention in mentions(text) {
+        let path = resolve(dir, mention);
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            if mention.contains('/') {
+                bail!("@{}: no such file", mention);
+            }
+            continue;
+        };
+        if metadata.is_dir() {
+            bail!("@{} is a folder; mention the files in it one at a time", mention);
+        }
+        let kb = metadata.len().div_ceil(1024);
+        if kb > MAX_FILE_KB {
+            bail!("@{} is {} KB; files over {} KB can't be attached", mention, kb, MAX_FILE_KB);
+        }
+        total += kb;
+        if total > MAX_TOTAL_KB {
+            bail!("The mentioned files come to over {} KB; attach fewer at a time", MAX_TOTAL_KB);
+        }
+        let bytes = std::fs::read(&path).with_context(|| format!("Failed to read @{}", mention))?;
+        let content = String::from_utf8(bytes).map_err(|_| anyhow!("@{} isn't a text file", mention))?;
+        files.push((mention, content));
+    }
+
+    let mut message = text.to_string();
+    for (mention, content) in files {
+        // A longer fence, so code blocks in the file (a README, say) don't end it
+        let fence = if content.contains("```") { "````" } else { "```" };
+        message.push_str(&format!(
+            "\n\n{}:\n{}{}\n{}\n{}",
+            mention,
+            fence,
+            language(mention),
+            content.trim_end(),
+            fence
+        ));
+    }
+    Ok(message)
+}
+
+/// A sent message split into what was typed and the files `attach`
+/// added after it
+pub fn split_attached(message: &str) -> (&str, &str) {
+    let start = mentions(message)
+        .iter()
+        .filter_map(|mention| message.find(&format!("\n\n{}:\n`", mention)))
+        .min()
+        .unwrap_or(message.len());
+    message.split_at(start)
+}
+
+/// The mentions whose files `attach` added to a sent message
+pub fn attached(message: &str) -> Vec<&str> {
+    let (text, files) = split_attached(message);
+    mentions(text)
+        .into_iter()
+        .filter(|mention| files.contains(&format!("\n\n{}:\n`", mention)))
+        .collect()
+}
+
+/// Mentioned files that are images, by their extension
+pub fn images<'a>(text: &'a str, dir: &Path) -> Vec<&'a str> {
+    mentions(text)
+        .into_iter()
+        .filter(|mention| {
+            let extension = Path::new(mention).extension().and_then(|e| e.to_str()).unwrap_or_default();
+            IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()) && resolve(dir, mention).is_file()
+        })
+        .collect()
+}
+
+/// Bytes of the files `text` mentions, as `attach` would add them;
+/// anything that isn't a file counts for nothing
+pub fn attached_size(text: &str, dir: &Path) -> u64 {
+    mentions(text)
+        .iter()
+        .filter_map(|mention| std::fs::metadata(resolve(dir, mention)).ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Paths that could finish the mention being typed at the end of `input`,
+/// sorted, with folders ending in `/`. Empty when the input doesn't end
+/// in a mention.
+pub fn completions(input: &str, dir: &Path) -> Vec<String> {
+    let Some(partial) = typed_mention(input) else {
+        return Vec::new();
+    };
+    // "src/ma" lists src/ for names starting "ma"
+    let (head, prefix) = match partial.rfind('/') {
+        Some(slash) => partial.split_at(slash + 1),
+        None => ("", partial),
+    };
+    let listed = if head.is_empty() { dir.to_path_buf() } else { resolve(dir, head) };
+    let Ok(entries) = std::fs::read_dir(&listed) else {
+        return Vec::new();
+    };
+
+    let mut found: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", head, name, slash))
+        })
+        .collect();
+    found.sort();
+    found.truncate(MAX_COMPLETIONS);
+    found
+}
+
+/// `input` with the mention at its end extended as far as every
+/// completion agrees, or None if that adds nothing
+pub fn complete(input: &str, completions: &[String]) -> Option<String> {
+    let partial = typed_mention(input)?;
+    let first = completions.first()?;
+    let common = completions.iter().fold(first.as_str(), |common, path| {
+        let shared = common
+            .char_indices()
+            .zip(path.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, c), _)| i + c.len_utf8());
+        &common[..shared]
+    });
+    if common.len() <= partial.len() {
+        return None;
+    }
+    Some(format!("{}{}", &input[..input.len() - partial.len()], common))
+}
+
+/// The path after a trailing `@`, if the input ends in one
+fn typed_mention(input: &str) -> Option<&str> {
+    let word = input.rsplit(char::is_whitespace).next()?;
+    word.strip_prefix('@')
+}
+
+/// Paths mentioned in `text`, in order and each once
+fn mentions(text: &str) -> Vec<&str> {
+    let mut found: Vec<&str> = Vec::new();
+    for word in text.split_whitespace() {
+        let Some(path) = word.strip_prefix('@').map(|path| path.trim_end_matches(TRAILING)) else {
+            continue;
+        };
+        if !path.is_empty() && !found.contains(&path) {
+            found.push(path);
+        }
+    }
+    found
+}
+
+fn resolve(dir: &Path, mention: &str) -> PathBuf {
+    let path = expand_home(mention);
+    if path.is_absolute() {
+        path
+    } else {
+        dir.join(path)
+    }
+}
+
+/// Fence label for a file, from its extension or name
+fn language(path: &str) -> &'static str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    if name == "Dockerfile" {
+        return "dockerfile";
+    }
+    if name == "Makefile" {
+        return "makefile";
+    }
+    let extension = name.rsplit_once('.').map_or("", |(_, extension)| extension);
+    match extension.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "java" => "java",
+        "kt" => "kotlin",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "php" => "php",
+        "cs" => "csharp",
+        "sh" | "bash" | "zsh" => "bash",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "xml" => "xml",
+        "md" => "markdown",
+        "lua" => "lua",
+        "rhai" => "rhai",
+        _ => "",
+    }
+}
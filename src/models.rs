@@ -0,0 +1,62 @@
+//! `animated-cli models`: what each provider will use and whether it's ready
+
+use anyhow::Result;
+
+use crate::capabilities::Capabilities;
+use crate::providers::{price_per_million, AIProvider};
+use crate::config::Config;
+
+/// Where a provider's API key comes from, if anywhere
+pub fn key_source(provider: &AIProvider, config: &Config) -> Option<&'static str> {
+    let var = provider.api_key_env();
+    if config.api_keys.get(var).is_some_and(|key| !key.is_empty()) {
+        Some("profile .env")
+    } else if std::env::var(var).is_ok_and(|key| !key.is_empty()) {
+        Some("environment")
+    } else {
+        None
+    }
+}
+
+/// Run `animated-cli models`
+pub fn run(config: &Config) -> Result<()> {
+    let default = config.provider.as_deref().and_then(AIProvider::parse).unwrap_or(AIProvider::Claude);
+    for provider in AIProvider::ALL {
+        let price = match price_per_million(provider.model()) {
+            Some((input, output)) => format!("${:.2} in / ${:.2} out per 1M tokens", input, output),
+            None => "price unknown".to_string(),
+        };
+        let key = match key_source(&provider, config) {
+            Some(source) => format!("{} from {}", provider.api_key_env(), source),
+            None => format!("{} not set", provider.api_key_env()),
+        };
+        let marker = if provider == default { "*" } else { " " };
+        println!(
+            "{} {:<7} {:<16} {:<27} {:<38} {}",
+            marker,
+            provider.db_name(),
+            provider.name(),
+            provider.model(),
+            price,
+            key
+        );
+    }
+    println!("\n* starts the chat; /set model picks another model for a conversation");
+
+    println!("\n  Model                       Images  Tools  Streaming  System  Temperature  Context");
+    let mark = |has: bool| if has { "yes" } else { "-" };
+    for (model, capabilities) in Capabilities::known() {
+        let window = capabilities.context_window.map_or("unknown".to_string(), |window| window.to_string());
+        println!(
+            "  {:<27} {:<7} {:<6} {:<10} {:<7} {:<12} {}",
+            model,
+            mark(capabilities.vision),
+            mark(capabilities.tools),
+            mark(capabilities.streaming),
+            mark(capabilities.system_prompt),
+            mark(capabilities.temperature),
+            window
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! Messages on their way to the provider: looked over for secrets, sent
+//! with as much of the conversation as fits the model's context window,
+//! and the reply handed back when it comes. The chat says what happened.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use tokio::sync::mpsc;
+
+use crate::config::RateLimitConfig;
+use crate::conversation::{context_window, fit_window};
+use crate::providers::{AIClient, AIProvider, ChatSettings, Message, Reply};
+use crate::ratelimit::{Queued, RateLimits};
+use crate::redact::{self, Redactor};
+
+/// What looking a message over for secrets decided
+#[derive(Debug, PartialEq)]
+pub enum Screened {
+    /// Send it: as it was, or with `masked` secrets blanked out
+    Send { message: String, masked: usize },
+    /// Held back for the secrets described. Sending the same message
+    /// again sends it as it is.
+    Held { secrets: String },
+}
+
+pub struct Outbox {
+    provider: AIProvider,
+    client: AIClient,
+    /// `[rate_limits]`, shared by every provider's client
+    rate_limits: RateLimits,
+    /// Looks for secrets in messages on their way out
+    redactor: Redactor,
+    /// A message held back for the secrets in it, sent as it is if it's
+    /// sent again
+    warned: Option<String>,
+    replies_rx: mpsc::UnboundedReceiver<Result<Reply>>,
+    replies_tx: mpsc::UnboundedSender<Result<Reply>>,
+    /// Where the message being sent is in its rate limit's queue
+    queue_rx: Option<mpsc::UnboundedReceiver<Option<Queued>>>,
+    /// Its place as last reported, while it waits
+    queued: Option<Queued>,
+}
+
+impl Outbox {
+    pub fn new(
+        provider: AIProvider,
+        rate_limits: &BTreeMap<String, RateLimitConfig>,
+        api_keys: &BTreeMap<String, String>,
+        profile: Option<&str>,
+    ) -> Self {
+        let rate_limits = RateLimits::new(rate_limits);
+        let client = ai_client(&provider, api_keys, profile, &rate_limits);
+        let (replies_tx, replies_rx) = mpsc::unbounded_channel();
+        Self {
+            provider,
+            client,
+            rate_limits,
+            redactor: Redactor::default(),
+            warned: None,
+            replies_rx,
+            replies_tx,
+            queue_rx: None,
+            queued: None,
+        }
+    }
+
+    /// Send to `provider` from now on. A reply still on its way from the
+    /// last one is dropped.
+    pub fn connect(&mut self, provider: AIProvider, api_keys: &BTreeMap<String, String>, profile: Option<&str>) {
+        while self.replies_rx.try_recv().is_ok() {}
+        self.queue_rx = None;
+        self.queued = None;
+        self.client = ai_client(&provider, api_keys, profile, &self.rate_limits);
+        self.provider = provider;
+    }
+
+    /// Take up edited rate limits and API keys
+    pub fn configure(
+        &mut self,
+        rate_limits: &BTreeMap<String, RateLimitConfig>,
+        api_keys: &BTreeMap<String, String>,
+        profile: Option<&str>,
+    ) {
+        self.rate_limits.configure(rate_limits);
+        self.client = ai_client(&self.provider, api_keys, profile, &self.rate_limits);
+    }
+
+    /// Take up `[redact]`
+    pub fn set_redactor(&mut self, redactor: Redactor) {
+        self.redactor = redactor;
+    }
+
+    /// The client messages go through
+    pub fn client(&self) -> AIClient {
+        self.client.clone()
+    }
+
+    /// A client for another provider, under the same rate limits
+    pub fn client_for(
+        &self,
+        provider: &AIProvider,
+        api_keys: &BTreeMap<String, String>,
+        profile: Option<&str>,
+    ) -> AIClient {
+        ai_client(provider, api_keys, profile, &self.rate_limits)
+    }
+
+    /// Look for secrets in a message about to leave, `known` being keys
+    /// and tokens in use, and mask them or, the first time it's sent,
+    /// hold it back
+    pub fn screen(&mut self, message: String, known: &[String]) -> Screened {
+        let findings = self.redactor.find(&message, known);
+        if findings.is_empty() {
+            return Screened::Send { message, masked: 0 };
+        }
+        match self.redactor.action() {
+            redact::Action::Off => Screened::Send { message, masked: 0 },
+            redact::Action::Mask => Screened::Send {
+                message: redact::mask(&message, &findings),
+                masked: findings.len(),
+            },
+            redact::Action::Warn if self.warned.as_ref() == Some(&message) => {
+                self.warned = None;
+                Screened::Send { message, masked: 0 }
+            }
+            redact::Action::Warn => {
+                let secrets = redact::describe(&message, &findings);
+                self.warned = Some(message);
+                Screened::Held { secrets }
+            }
+        }
+    }
+
+    /// The newest of `messages` that fit the model's context window
+    /// along with the system prompt in `settings`
+    pub fn fit(&self, messages: Vec<Message>, settings: &ChatSettings) -> Vec<Message> {
+        fit_window(messages, context_window(&self.provider, settings), settings.system_prompt.as_deref())
+    }
+
+    /// Send the conversation, as much as fits, for `reply` to hand back
+    /// the answer
+    pub fn send(&mut self, messages: Vec<Message>, settings: ChatSettings) {
+        let messages = self.fit(messages, &settings);
+        let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+        self.queue_rx = Some(queue_rx);
+        let client = self.client.clone().reporting_queue(queue_tx);
+        let tx = self.replies_tx.clone();
+        tokio::spawn(async move {
+            let result = client.send_message(messages, &settings).await;
+            let _ = tx.send(result);
+        });
+    }
+
+    /// Take in news of where the message being sent is in its rate
+    /// limit's queue. None if there was none; otherwise whether it's just
+    /// joined the queue.
+    pub fn follow_queue(&mut self) -> Option<bool> {
+        let mut news = None;
+        while let Some(queued) = self.queue_rx.as_mut().and_then(|updates| updates.try_recv().ok()) {
+            let joined = self.queued.is_none() && queued.is_some();
+            news = Some(news.unwrap_or(false) || joined);
+            self.queued = queued;
+        }
+        news
+    }
+
+    /// The message's place in its rate limit's queue, while it waits
+    pub fn queued(&self) -> Option<Queued> {
+        self.queued
+    }
+
+    /// The reply to the message sent, once it's in
+    pub fn reply(&mut self) -> Option<Result<Reply>> {
+        let reply = self.replies_rx.try_recv().ok()?;
+        self.queue_rx = None;
+        self.queued = None;
+        Some(reply)
+    }
+}
+
+/// A client for the provider using the profile's key, held to its rate
+/// limit and sent through the profile's daemon whenever one is running
+fn ai_client(
+    provider: &AIProvider,
+    api_keys: &BTreeMap<String, String>,
+    profile: Option<&str>,
+    rate_limits: &RateLimits,
+) -> AIClient {
+    let client = AIClient::new(provider.clone(), api_keys.get(provider.api_key_env()).cloned())
+        .with_rate_limit(rate_limits.for_provider(provider));
+    #[cfg(unix)]
+    let client = client.via_daemon(crate::daemon::socket_path(profile).ok());
+    #[cfg(not(unix))]
+    let _ = profile;
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedactConfig;
+
+    const KEY: &str = "sk-ant-REDACTED";
+
+    fn outbox(action: &str) -> Outbox {
+        let mut outbox = Outbox::new(AIProvider::Mock, &BTreeMap::new(), &BTreeMap::new(), None);
+        let config = RedactConfig { action: action.to_string(), ..RedactConfig::default() };
+        outbox.set_redactor(Redactor::new(&config).unwrap());
+        outbox
+    }
+
+    #[test]
+    fn a_warning_holds_a_message_back_once() {
+        let mut outbox = outbox("warn");
+        let message = format!("KEY={}", KEY);
+        let Screened::Held { secrets } = outbox.screen(message.clone(), &[]) else {
+            panic!("sent with a key in it");
+        };
+        assert!(secrets.starts_with("an Anthropic key"), "{}", secrets);
+        assert_eq!(outbox.screen(message.clone(), &[]), Screened::Send { message: message.clone(), masked: 0 });
+        assert!(matches!(outbox.screen(message, &[]), Screened::Held { .. }), "only the once");
+    }
+
+    #[test]
+    fn masking_blanks_out_known_keys_too() {
+        let mut outbox = outbox("mask");
+        let sent = outbox.screen("token hunter2-but-longer here".to_string(), &["hunter2-but-longer".to_string()]);
+        assert_eq!(sent, Screened::Send { message: "token [redacted] here".to_string(), masked: 1 });
+        assert_eq!(
+            outbox.screen("nothing secret".to_string(), &[]),
+            Screened::Send { message: "nothing secret".to_string(), masked: 0 }
+        );
+    }
+
+    #[test]
+    fn what_is_sent_is_fitted_to_the_mock_model() {
+        let outbox = outbox("off");
+        let messages: Vec<Message> = (0..3)
+            .map(|n| Message { role: "user".to_string(), content: format!("{}{}", n, "x".repeat(16_000)) })
+            .collect();
+        // 8,192 tokens take two of these at about 4,000 each
+        let sent = outbox.fit(messages, &ChatSettings::default());
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].content.starts_with('1'));
+    }
+}
@@ -0,0 +1,112 @@
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+use std::time::{Duration, Instant};
+
+use crate::background::{VideoBackground, VideoStats};
+
+/// How often the FPS figures are recomputed
+const SAMPLE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Toggleable performance readout for tuning background settings
+pub struct DebugOverlay {
+    visible: bool,
+    window_start: Instant,
+    renders: u32,
+    decoded_at_window_start: u64,
+    render_fps: f32,
+    decode_fps: f32,
+    stats: Option<VideoStats>,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            window_start: Instant::now(),
+            renders: 0,
+            decoded_at_window_start: 0,
+            render_fps: 0.0,
+            decode_fps: 0.0,
+            stats: None,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Call once per drawn frame
+    pub fn record_render(&mut self, video_bg: Option<&VideoBackground>) {
+        self.renders += 1;
+        self.stats = video_bg.map(|bg| bg.stats());
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed < SAMPLE_WINDOW {
+            return;
+        }
+
+        let secs = elapsed.as_secs_f32();
+        let decoded = self.stats.map(|s| s.decoded).unwrap_or(0);
+        self.render_fps = self.renders as f32 / secs;
+        self.decode_fps = decoded.saturating_sub(self.decoded_at_window_start) as f32 / secs;
+
+        self.window_start = Instant::now();
+        self.renders = 0;
+        self.decoded_at_window_start = decoded;
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        if !self.visible {
+            return;
+        }
+
+        let text = match self.stats {
+            Some(stats) => format!(
+                "Render FPS   {:>6.1}\n\
+                 Decode FPS   {:>6.1}\n\
+                 Dropped      {:>6}\n\
+                 Queued       {:>6}\n\
+                 Convert      {:>4.1}ms\n\
+                 Resolution   {:>6}\n\
+                 Source       {:>6}",
+                self.render_fps,
+                self.decode_fps,
+                stats.dropped,
+                stats.queued,
+                stats.convert_time.as_secs_f32() * 1000.0,
+                format!("1/{}", stats.scale),
+                if stats.cached { "cache" } else { "decode" },
+            ),
+            None => format!("Render FPS   {:>6.1}\nNo background video", self.render_fps),
+        };
+
+        let area = frame.area();
+        let width = 24.min(area.width);
+        let height = 9.min(area.height);
+        let popup = Rect::new(area.right().saturating_sub(width + 1), area.y + 1, width, height);
+
+        let overlay = Paragraph::new(text)
+            .style(Style::default().fg(Color::LightGreen))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Rounded)
+                    .title("Debug (F12)")
+                    .border_style(Style::default().fg(Color::LightGreen)),
+            );
+        frame.render_widget(Clear, popup);
+        frame.render_widget(overlay, popup);
+    }
+}
@@ -0,0 +1,70 @@
+//! When the UI redraws: only once something on screen has changed (a key,
+//! a resize, a video frame, a reply), at most `--fps` times a second. The
+//! loop still wakes that often to check for changes while anything is
+//! moving, and much less often once nothing has for a moment. Waiting is a
+//! poll on terminal events, so a keypress still wakes it at once.
+
+use std::time::{Duration, Instant};
+
+/// How long without input or anything moving before dropping to the idle rate
+const IDLE_AFTER: Duration = Duration::from_secs(2);
+
+/// Highest `--fps` accepted; terminals can't show more anyway
+pub const MAX_FPS: u32 = 240;
+
+pub struct Pacer {
+    frame: Duration,
+    idle_frame: Duration,
+    last_tick: Instant,
+    last_change: Instant,
+    /// Something changed since the last frame was drawn
+    dirty: bool,
+}
+
+impl Pacer {
+    /// `idle_fps` above `fps` is lowered to it
+    pub fn new(fps: u32, idle_fps: u32) -> Self {
+        let fps = fps.clamp(1, MAX_FPS);
+        let idle_fps = idle_fps.clamp(1, fps);
+        Self {
+            frame: Duration::from_secs(1) / fps,
+            idle_frame: Duration::from_secs(1) / idle_fps,
+            last_tick: Instant::now(),
+            last_change: Instant::now(),
+            dirty: true,
+        }
+    }
+
+    /// Input arrived or something on screen moved: redraw, and stay at the
+    /// full rate
+    pub fn changed(&mut self) {
+        self.dirty = true;
+        self.last_change = Instant::now();
+    }
+
+    /// Something is under way that will change the screen soon (a reply on
+    /// its way); keep checking at the full rate without redrawing yet
+    pub fn busy(&mut self) {
+        self.last_change = Instant::now();
+    }
+
+    /// Whether to draw a frame this pass; clears the change
+    pub fn take_redraw(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.last_change.elapsed() >= IDLE_AFTER
+    }
+
+    /// Call once per pass of the loop, whether or not it drew
+    pub fn tick(&mut self) {
+        self.last_tick = Instant::now();
+    }
+
+    /// How long to wait for input before checking for changes again
+    pub fn timeout(&self) -> Duration {
+        let interval = if self.is_idle() { self.idle_frame } else { self.frame };
+        (self.last_tick + interval).saturating_duration_since(Instant::now())
+    }
+}
@@ -0,0 +1,174 @@
+//! `/share`: the open conversation as Markdown, cleaned of what shouldn't
+//! leave the machine, uploaded as a secret GitHub Gist or to a paste
+//! service, with the link put on the clipboard.
+//!
+//! ```toml
+//! [paste]
+//! service = "gist"            # or a paste service's URL, such as "https://paste.rs"
+//! public = false              # list the gist publicly
+//! github_token = "ghp_..."    # or GITHUB_TOKEN in the environment
+//! ```
+//!
+//! Attached files are left out, with their names kept, and so are notes.
+//! API keys, and the home directory in paths, are blanked wherever they
+//! turn up.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::config::PasteConfig;
+use crate::crash::redact_home;
+use crate::export;
+use crate::mentions;
+use crate::providers::AIProvider;
+use crate::store::{self, Database};
+
+const GIST_API: &str = "https://api.github.com/gists";
+
+/// Where a GitHub token is looked for when the config has none
+pub const TOKEN_ENV: &str = "GITHUB_TOKEN";
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shorter strings aren't taken for secrets, so they can't blank out
+/// ordinary words
+const MIN_SECRET_LENGTH: usize = 8;
+
+#[derive(Deserialize)]
+struct Gist {
+    html_url: String,
+}
+
+/// Keys and tokens that might be pasted into a conversation: the
+/// profile's, the environment's and the GitHub token
+pub fn secrets(api_keys: &BTreeMap<String, String>, config: &PasteConfig) -> Vec<String> {
+    let mut secrets: Vec<String> = api_keys.values().cloned().collect();
+    for provider in AIProvider::ALL {
+        secrets.extend(std::env::var(provider.api_key_env()).ok());
+    }
+    secrets.extend(config.github_token.clone());
+    secrets.extend(std::env::var(TOKEN_ENV).ok());
+    secrets.retain(|secret| secret.trim().len() >= MIN_SECRET_LENGTH);
+    secrets
+}
+
+/// A message as it's shared: attached files named rather than included,
+/// and `secrets` and the home directory blanked
+pub fn sanitize(content: &str, secrets: &[String]) -> String {
+    let attached = mentions::attached(content);
+    let (text, _) = mentions::split_attached(content);
+    let mut content = text.trim_end().to_string();
+    if !attached.is_empty() {
+        let names: Vec<String> = attached.iter().map(|name| format!("`@{}`", name)).collect();
+        content.push_str(&format!("\n\n*Attached, not shared: {}*", names.join(", ")));
+    }
+    for secret in secrets {
+        content = content.replace(secret.trim(), "[redacted]");
+    }
+    redact_home(&content)
+}
+
+/// The conversation `id`'s title and the Markdown to share
+pub fn document(db: &Database, id: i64, secrets: &[String]) -> Result<(String, String)> {
+    let conversation = db
+        .get_conversation(id)?
+        .ok_or_else(|| anyhow!("No conversation with id {}", id))?;
+    let messages: Vec<store::Message> = db
+        .get_messages(id)?
+        .into_iter()
+        .map(|m| store::Message {
+            content: sanitize(&m.content, secrets),
+            note: None,
+            ..m
+        })
+        .collect();
+    let title = sanitize(&conversation.title, secrets);
+    let conversation = store::Conversation { title: title.clone(), ..conversation };
+    Ok((title, export::markdown(&[(conversation, messages)])?))
+}
+
+/// Upload `markdown` where `[paste]` says. Returns its URL.
+pub async fn upload(config: &PasteConfig, title: &str, markdown: String) -> Result<String> {
+    let http = reqwest::Client::builder()
+        .user_agent(concat!("animated-cli/", env!("CARGO_PKG_VERSION")))
+        .timeout(TIMEOUT)
+        .build()?;
+    match config.service.trim() {
+        "gist" => gist(&http, config, title, markdown).await,
+        url if url.starts_with("http://") || url.starts_with("https://") => paste(&http, url, markdown).await,
+        other => bail!("Unknown paste service: {} (expected gist or a URL)", other),
+    }
+}
+
+async fn gist(http: &reqwest::Client, config: &PasteConfig, title: &str, markdown: String) -> Result<String> {
+    let token = config
+        .github_token
+        .clone()
+        .or_else(|| std::env::var(TOKEN_ENV).ok())
+        .filter(|token| !token.trim().is_empty())
+        .ok_or_else(|| {
+            anyhow!(
+                "A gist needs a GitHub token with the gist scope: set {} or github_token under [paste], \
+                 or set service to a paste service's URL",
+                TOKEN_ENV
+            )
+        })?;
+    let body = json!({
+        "description": title,
+        "public": config.public,
+        "files": { file_name(title): { "content": markdown } },
+    });
+    let response = http
+        .post(GIST_API)
+        .bearer_auth(token.trim())
+        .header("Accept", "application/vnd.github+json")
+        .json(&body)
+        .send()
+        .await
+        .context("Couldn't reach GitHub")?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        bail!("GitHub turned the gist down ({}): {}", status, text.trim());
+    }
+    let gist: Gist = response.json().await.context("GitHub's answer had no gist in it")?;
+    Ok(gist.html_url)
+}
+
+/// Post the text as it is; the service answers with where it went
+async fn paste(http: &reqwest::Client, url: &str, markdown: String) -> Result<String> {
+    let response = http
+        .post(url)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(markdown)
+        .send()
+        .await
+        .with_context(|| format!("Couldn't reach {}", url))?;
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!("{} turned the paste down ({}): {}", url, status, text.trim());
+    }
+    let link = text.trim();
+    if !link.starts_with("http://") && !link.starts_with("https://") {
+        bail!("{} didn't answer with a link: {}", url, link.chars().take(200).collect::<String>());
+    }
+    Ok(link.to_string())
+}
+
+/// The gist's file, named after the conversation
+fn file_name(title: &str) -> String {
+    let slug: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let slug = slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+    if slug.is_empty() {
+        "conversation.md".to_string()
+    } else {
+        format!("{}.md", slug)
+    }
+}
@@ -0,0 +1,263 @@
+//! Unified diffs in messages: their lines colored in the messages panel,
+//! and `/apply` to look over the ones in a reply, unified or side by
+//! side, and hand them to `git apply` once you say yes.
+//!
+//! A diff is a fenced code block marked `diff` or `patch`, or an unmarked
+//! one that reads as a unified diff.
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+use std::ops::Range;
+
+const HINTS: &str = "y Apply  n Cancel  s Side by side  ↑↓ Scroll";
+const SIDE_BY_SIDE_HINTS: &str = "y Apply  n Cancel  s Unified  ↑↓ Scroll";
+const FAILED_HINTS: &str = "s Side by side  ↑↓ Scroll  Esc Close";
+
+/// Code block languages that mark a diff
+const LANGUAGES: [&str; 2] = ["diff", "patch"];
+
+/// The diffs in `text`, each as the range of its code block's lines
+pub fn find(text: &str) -> Vec<Range<usize>> {
+    let mut diffs = vec![];
+    // Where the open code block's lines start, and whether it's a diff
+    // by its language, or might be one without
+    let mut open: Option<(usize, Option<bool>)> = None;
+    let mut at = 0;
+    for line in text.split_inclusive('\n') {
+        let start = at;
+        at += line.len();
+        let Some(info) = line.trim_start().strip_prefix("```") else {
+            continue;
+        };
+        match open.take() {
+            None => {
+                let language = info.split_whitespace().next().map(str::to_ascii_lowercase);
+                open = Some((at, language.map(|language| LANGUAGES.contains(&language.as_str()))));
+            }
+            Some((body, marked)) => {
+                if marked.unwrap_or_else(|| reads_as_diff(&text[body..start])) {
+                    diffs.push(body..start);
+                }
+            }
+        }
+    }
+    diffs
+}
+
+/// Whether unmarked code has a diff's file headers and a hunk
+fn reads_as_diff(code: &str) -> bool {
+    let mut lines = code.lines();
+    lines.clone().any(|line| line.starts_with("@@ "))
+        && lines.any(|line| line.starts_with("--- ") || line.starts_with("diff --git "))
+}
+
+/// Every diff in `text`, one after another, ready for `git apply`
+pub fn extract(text: &str) -> Option<String> {
+    let mut patch = String::new();
+    for diff in find(text) {
+        patch.push_str(&text[diff]);
+        if !patch.ends_with('\n') {
+            patch.push('\n');
+        }
+    }
+    Some(patch).filter(|patch| !patch.trim().is_empty())
+}
+
+/// The color a diff's line is shown in, if it isn't plain context
+fn line_style(line: &str) -> Option<Style> {
+    if ["diff ", "index ", "--- ", "+++ "].iter().any(|header| line.starts_with(header)) {
+        Some(Style::default().fg(Color::White).bold())
+    } else if line.starts_with("@@") {
+        Some(Style::default().fg(Color::Cyan))
+    } else if line.starts_with('+') {
+        Some(Style::default().fg(Color::Green))
+    } else if line.starts_with('-') {
+        Some(Style::default().fg(Color::Red))
+    } else {
+        None
+    }
+}
+
+/// `text` in pieces to lay out: its diffs line by line, colored, and the
+/// rest in `style`
+pub fn highlight(text: &str, style: Style) -> Vec<(&str, Style)> {
+    let mut pieces = vec![];
+    let mut at = 0;
+    for diff in find(text) {
+        pieces.push((&text[at..diff.start], style));
+        for line in text[diff.clone()].split_inclusive('\n') {
+            pieces.push((line, line_style(line).unwrap_or(style)));
+        }
+        at = diff.end;
+    }
+    pieces.push((&text[at..], style));
+    pieces
+}
+
+/// Files touched, lines added and lines removed
+fn counts(patch: &str) -> (usize, usize, usize) {
+    let (mut files, mut added, mut removed) = (0, 0, 0);
+    for line in patch.lines() {
+        if line.starts_with("+++ ") {
+            files += 1;
+        } else if line.starts_with('+') {
+            added += 1;
+        } else if line.starts_with('-') && !line.starts_with("--- ") {
+            removed += 1;
+        }
+    }
+    (files, added, removed)
+}
+
+/// Removed and added lines side by side, as many rows as the longer takes
+fn pair<'a>(rows: &mut Vec<[Line<'a>; 2]>, removed: &mut Vec<&'a str>, added: &mut Vec<&'a str>) {
+    let red = Style::default().fg(Color::Red);
+    let green = Style::default().fg(Color::Green);
+    for i in 0..removed.len().max(added.len()) {
+        rows.push([
+            removed.get(i).map_or_else(Line::default, |line| Line::styled(&line[1..], red)),
+            added.get(i).map_or_else(Line::default, |line| Line::styled(&line[1..], green)),
+        ]);
+    }
+    removed.clear();
+    added.clear();
+}
+
+/// The patch as rows of two columns, the old lines on the left and the
+/// new on the right, a removed line beside the line that replaced it
+fn side_by_side(patch: &str) -> Vec<[Line<'_>; 2]> {
+    let mut rows = vec![];
+    let (mut removed, mut added): (Vec<&str>, Vec<&str>) = (vec![], vec![]);
+    for line in patch.lines() {
+        if line.starts_with('-') && !line.starts_with("--- ") {
+            removed.push(line);
+        } else if line.starts_with('+') && !line.starts_with("+++ ") {
+            added.push(line);
+        } else {
+            pair(&mut rows, &mut removed, &mut added);
+            match line_style(line) {
+                Some(style) => rows.push([Line::styled(line, style), Line::styled(line, style)]),
+                // Context, without the space in front
+                None => {
+                    let context = line.get(1..).unwrap_or_default();
+                    rows.push([Line::raw(context), Line::raw(context)]);
+                }
+            }
+        }
+    }
+    pair(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+/// What was said to a patch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Apply,
+    Cancel,
+}
+
+/// A reply's patch, to look over before it's applied
+pub struct PatchView {
+    patch: String,
+    /// Why git won't take it, if it won't
+    problem: Option<String>,
+    side_by_side: bool,
+    scroll: u16,
+}
+
+impl PatchView {
+    /// `check` is what `git apply --check` made of it
+    pub fn new(patch: String, check: anyhow::Result<()>) -> Self {
+        Self {
+            patch,
+            problem: check.err().map(|e| format!("{:#}", e)),
+            side_by_side: false,
+            scroll: 0,
+        }
+    }
+
+    pub fn patch(&self) -> &str {
+        &self.patch
+    }
+
+    /// e.g. "2 files, +10 −3"
+    pub fn summary(&self) -> String {
+        let (files, added, removed) = counts(&self.patch);
+        format!("{} file{}, +{} −{}", files, if files == 1 { "" } else { "s" }, added, removed)
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Decision> {
+        match key.code {
+            KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::Down => self.scroll = self.scroll.saturating_add(1),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::Char('s') => self.side_by_side = !self.side_by_side,
+            KeyCode::Char('y') if self.problem.is_none() => return Some(Decision::Apply),
+            KeyCode::Char('n') => return Some(Decision::Cancel),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4);
+        let height = area.height.saturating_sub(2);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        frame.render_widget(Clear, popup);
+
+        let hints = match (&self.problem, self.side_by_side) {
+            (Some(_), _) => FAILED_HINTS,
+            (None, true) => SIDE_BY_SIDE_HINTS,
+            (None, false) => HINTS,
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(format!("Apply patch · {}", self.summary()))
+            .title_bottom(Line::from(hints).centered())
+            .border_style(Style::default().fg(Color::Green));
+        let inner = block.inner(popup);
+        frame.render_widget(block, popup);
+
+        let [status, body] = Layout::vertical([Constraint::Length(2), Constraint::Min(0)]).areas(inner);
+        let status_line = match self.problem {
+            Some(ref problem) => Line::styled(format!("Won't apply: {}", problem), Style::default().fg(Color::Red)),
+            None => Line::styled("Applies cleanly. Apply it to the working tree?", Style::default().fg(Color::Yellow)),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+
+        let plain = Style::default().fg(Color::White);
+        if !self.side_by_side {
+            let lines: Vec<Line> = self
+                .patch
+                .lines()
+                .map(|line| Line::styled(line, line_style(line).unwrap_or(plain)))
+                .collect();
+            frame.render_widget(Paragraph::new(lines).scroll((self.scroll, 0)), body);
+            return;
+        }
+        let [old, new] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body);
+        let (left, right): (Vec<Line>, Vec<Line>) = side_by_side(&self.patch)
+            .into_iter()
+            .map(|[left, right]| (left, right))
+            .unzip();
+        for (lines, column, title) in [(left, old, "Before"), (right, new, "After")] {
+            let column_view = Paragraph::new(lines).style(plain).scroll((self.scroll, 0)).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(title)
+                    .border_style(Style::default().fg(Color::Cyan)),
+            );
+            frame.render_widget(column_view, column);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! Errors the chat can carry on after (a provider that didn't answer, a
+//! database write that failed, a background that stopped), kept for an
+//! overlay that shows the whole error and what might fix it
+
+use chrono::{DateTime, Local};
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+use std::fmt::Display;
+
+use crate::providers::AIProvider;
+
+/// Oldest problems are dropped past this many
+const MAX_PROBLEMS: usize = 20;
+
+/// What went wrong
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Provider(AIProvider),
+    Database,
+    Background,
+    Audio,
+    Recording,
+    Profile,
+    Hooks,
+    Speech,
+    Events,
+    Config,
+}
+
+impl Source {
+    fn name(&self) -> &str {
+        match self {
+            Source::Provider(provider) => provider.name(),
+            Source::Database => "Database",
+            Source::Background => "Background video",
+            Source::Audio => "Background audio",
+            Source::Recording => "Recording",
+            Source::Profile => "Profile",
+            Source::Hooks => "Hooks script",
+            Source::Speech => "Text-to-speech",
+            Source::Events => "Events",
+            Source::Config => "Config file",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub source: Source,
+    /// One line saying what didn't happen
+    pub summary: String,
+    /// The error with its whole chain of causes
+    pub detail: String,
+    pub at: DateTime<Local>,
+}
+
+impl Problem {
+    /// Pass an `anyhow::Error` as `detail` to keep its causes
+    pub fn new(source: Source, summary: impl Into<String>, detail: impl Display) -> Self {
+        Self {
+            source,
+            summary: summary.into(),
+            detail: format!("{:#}", detail),
+            at: Local::now(),
+        }
+    }
+
+    /// Likely fixes, guessed from the source and the error's text
+    pub fn fixes(&self) -> Vec<String> {
+        let detail = self.detail.to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|w| detail.contains(w));
+        let mut fixes = Vec::new();
+        match self.source {
+            Source::Provider(ref provider) => {
+                if has(&["not set in environment"]) {
+                    fixes.push(format!(
+                        "Set {} in the environment, .env or [api_keys] in config.toml",
+                        provider.api_key_env()
+                    ));
+                    fixes.push("`animated-cli config show` lists the keys that were found".to_string());
+                } else if has(&[" 401", " 403", "unauthorized", "forbidden", "invalid api key", "invalid x-api-key"]) {
+                    fixes.push(format!("Check that {} holds a current key for {}", provider.api_key_env(), provider.name()));
+                    fixes.push("`animated-cli config show` says where the key was read from".to_string());
+                } else if has(&[" 429", "rate limit", "quota"]) {
+                    fixes.push("You're being rate limited; wait a little and send again".to_string());
+                    fixes.push("Check the account's quota and billing".to_string());
+                } else if has(&[" 404", "model not found", "does not exist"]) {
+                    fixes.push("The model may have been renamed or retired; pick another with /set model".to_string());
+                } else if has(&[" 400", "invalid_request"]) {
+                    fixes.push("The provider refused a setting; /set lists them and /set name resets one".to_string());
+                } else if has(&[" 500", " 502", " 503", " 504", " 529", "overloaded"]) {
+                    fixes.push(format!("{} is having trouble; send again shortly, or press F2 for another AI", provider.name()));
+                } else if has(&["failed to parse", "no content in"]) {
+                    fixes.push("The reply wasn't in the expected shape; send again, or try another model with /set model".to_string());
+                } else if has(&["dns", "connect", "timed out", "timeout", "failed to send request"]) {
+                    fixes.push("Check the network connection and any proxy settings".to_string());
+                }
+                fixes.push("Run with --log-level debug and check the log for the full exchange".to_string());
+            }
+            Source::Database => {
+                if has(&["locked", "busy"]) {
+                    fixes.push("Another program has the database open; close it and try again".to_string());
+                } else if has(&["readonly", "read-only", "permission denied"]) {
+                    fixes.push("The database file or its folder isn't writable; check their permissions".to_string());
+                } else if has(&["disk is full", "no space"]) {
+                    fixes.push("The disk is full; free some space".to_string());
+                } else if has(&["malformed", "corrupt", "not a database"]) {
+                    fixes.push("The database is damaged; quit and run `animated-cli repair`".to_string());
+                } else if has(&["earlier messages"]) {
+                    fixes.push("Open the conversation again (F6) to read them again".to_string());
+                }
+                fixes.push("`animated-cli doctor` checks the database".to_string());
+            }
+            Source::Background => {
+                if has(&["no such file", "not found"]) {
+                    fixes.push("Check the --video path, or `video` under [background] in config.toml".to_string());
+                }
+                if has(&["yt-dlp", "youtube"]) {
+                    fixes.push("Update yt-dlp; YouTube changes often break older versions".to_string());
+                }
+                fixes.push("`--video builtin` always works".to_string());
+                fixes.push("`animated-cli doctor` checks the background".to_string());
+            }
+            Source::Audio => {
+                fixes.push("Check the system's sound output; F5 mutes the background".to_string());
+                fixes.push("Set enabled = false under [audio] (the soundtrack) or [sounds] (cues) to stop trying".to_string());
+            }
+            Source::Recording => {
+                fixes.push("Check that the --record file's folder exists and is writable, and that the disk isn't full".to_string());
+            }
+            Source::Profile => {
+                fixes.push("`animated-cli doctor --profile name` checks that profile".to_string());
+            }
+            Source::Hooks => {
+                if has(&["on_user_message"]) {
+                    fixes.push("The message wasn't sent; it's back in the input box".to_string());
+                }
+                if has(&["too many operations"]) {
+                    fixes.push("A hook ran too long; look for a loop that never ends".to_string());
+                }
+                fixes.push("Fix the script and type /hooks to load it again".to_string());
+                fixes.push("`animated-cli doctor` checks that the script compiles".to_string());
+            }
+            Source::Speech => {
+                if has(&["no text-to-speech engine"]) {
+                    fixes.push("Install espeak-ng (apt install espeak-ng, dnf install espeak-ng)".to_string());
+                }
+                if has(&["failed to start"]) {
+                    fixes.push("Check that the engine or [speech] command is installed and on PATH".to_string());
+                }
+                fixes.push("Set engine or command under [speech] in config.toml; F8 turns speech off".to_string());
+            }
+            Source::Events => {
+                if has(&["failed to open", "failed to write"]) {
+                    fixes.push("Check that the folder of `log` under [events] exists and is writable, and that the disk isn't full".to_string());
+                }
+                fixes.push("Fix [events] in config.toml; it's picked up when saved".to_string());
+            }
+            Source::Config => {
+                if has(&["permission denied", "read-only"]) {
+                    fixes.push("config.toml or its folder isn't writable; check their permissions".to_string());
+                } else if has(&["parse"]) {
+                    fixes.push("config.toml has a mistake in it; fix it and the setting is saved next time".to_string());
+                }
+                fixes.push("`animated-cli config show` says where config.toml is".to_string());
+            }
+        }
+        fixes
+    }
+}
+
+/// Every problem this session, and the one the overlay is showing
+#[derive(Default)]
+pub struct ErrorLog {
+    problems: Vec<Problem>,
+    /// Index into `problems`; None when the overlay is closed
+    open: Option<usize>,
+}
+
+impl ErrorLog {
+    /// Keep the problem and show it
+    pub fn push(&mut self, problem: Problem) {
+        tracing::warn!("{}: {}", problem.summary, problem.detail);
+        if self.problems.len() == MAX_PROBLEMS {
+            self.problems.remove(0);
+        }
+        self.problems.push(problem);
+        self.open = Some(self.problems.len() - 1);
+    }
+
+    /// Show the newest problem again. Returns false if there are none.
+    pub fn open_latest(&mut self) -> bool {
+        self.open = self.problems.len().checked_sub(1);
+        self.open.is_some()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.is_some()
+    }
+
+    /// Returns false if it wasn't open
+    pub fn close(&mut self) -> bool {
+        self.open.take().is_some()
+    }
+
+    /// ←/→ step through older and newer problems; Enter closes
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        let Some(index) = self.open else {
+            return;
+        };
+        match key.code {
+            KeyCode::Left => self.open = Some(index.saturating_sub(1)),
+            KeyCode::Right => self.open = Some((index + 1).min(self.problems.len() - 1)),
+            KeyCode::Enter => self.open = None,
+            _ => {}
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let Some(problem) = self.open.and_then(|i| self.problems.get(i)) else {
+            return;
+        };
+        let width = area.width.saturating_sub(4).min(90);
+        let height = area.height.saturating_sub(2).min(20);
+        let popup = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2,
+            width,
+            height,
+        );
+
+        let mut lines = vec![
+            Line::styled(
+                format!("{} · {}", problem.source.name(), problem.at.format("%H:%M:%S")),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Line::from(""),
+        ];
+        lines.extend(problem.detail.lines().map(|l| Line::styled(l.to_string(), Style::default().fg(Color::White))));
+        lines.push(Line::from(""));
+        lines.push(Line::styled("Might fix it:", Style::default().fg(Color::Yellow).bold()));
+        lines.extend(
+            problem
+                .fixes()
+                .into_iter()
+                .map(|fix| Line::styled(format!("  • {}", fix), Style::default().fg(Color::Yellow))),
+        );
+
+        let position = format!(" {}/{} ←/→ · Esc closes ", self.open.unwrap_or(0) + 1, self.problems.len());
+        let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(ratatui::widgets::BorderType::Rounded)
+                .title(format!(" ⚠ {} ", problem.summary))
+                .title_bottom(Line::from(position).right_aligned())
+                .border_style(Style::default().fg(Color::Red)),
+        );
+        frame.render_widget(Clear, popup);
+        frame.render_widget(paragraph, popup);
+    }
+}
@@ -0,0 +1,712 @@
+use anyhow::{anyhow, Context, Result};
+use ratatui::style::Color;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::{debug, info, trace, warn};
+
+use crate::capabilities::Capabilities;
+use crate::ratelimit::{self, Queued, RateLimiter};
+
+pub mod mock;
+
+/// The HTTP client every `AIClient::new` shares, built on the first
+/// request rather than at startup, since loading TLS roots takes a while
+static HTTP: OnceLock<Client> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AIProvider {
+    Claude,
+    Grok,
+    OpenAI,
+    Gemini,
+    /// Offline stand-in for `--demo` and the tests; not in `ALL`
+    Mock,
+}
+
+impl AIProvider {
+    /// Every provider, in F2 order
+    pub const ALL: [AIProvider; 4] = [AIProvider::Claude, AIProvider::Grok, AIProvider::OpenAI, AIProvider::Gemini];
+
+    /// Accepts `--provider` names as well as `db_name`s
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "claude" => Some(AIProvider::Claude),
+            "grok" => Some(AIProvider::Grok),
+            "gpt" | "openai" => Some(AIProvider::OpenAI),
+            "gemini" => Some(AIProvider::Gemini),
+            "mock" => Some(AIProvider::Mock),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AIProvider::Claude => "Claude Sonnet 4",
+            AIProvider::Grok => "Grok 4",
+            AIProvider::OpenAI => "GPT 5",
+            AIProvider::Gemini => "Gemini 2.5 Pro",
+            AIProvider::Mock => "Mock",
+        }
+    }
+
+    pub fn db_name(&self) -> &str {
+        match self {
+            AIProvider::Claude => "claude",
+            AIProvider::Grok => "grok",
+            AIProvider::OpenAI => "gpt",
+            AIProvider::Gemini => "gemini",
+            AIProvider::Mock => "mock",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            AIProvider::Claude => Color::Rgb(204, 143, 102), // Copper
+            AIProvider::Grok => Color::Rgb(100, 200, 255),   // Cyan
+            AIProvider::OpenAI => Color::Rgb(116, 195, 194), // Teal
+            AIProvider::Gemini => Color::Rgb(138, 180, 248), // Blue
+            AIProvider::Mock => Color::Rgb(190, 190, 190),   // Gray
+        }
+    }
+
+    /// Scheme and host of the provider's API
+    fn api_base(&self) -> &str {
+        match self {
+            AIProvider::Claude => "https://api.anthropic.com",
+            AIProvider::Grok => "https://api.x.ai",
+            AIProvider::OpenAI => "https://api.openai.com",
+            AIProvider::Gemini => "https://generativelanguage.googleapis.com",
+            AIProvider::Mock => unreachable!("the mock provider makes no requests"),
+        }
+    }
+
+    /// Gemini puts the model in the path
+    fn api_path(&self, model: &str) -> String {
+        match self {
+            AIProvider::Claude => "/v1/messages".to_string(),
+            AIProvider::Grok | AIProvider::OpenAI => "/v1/chat/completions".to_string(),
+            AIProvider::Gemini => format!("/v1beta/models/{}:generateContent", model),
+            AIProvider::Mock => unreachable!("the mock provider makes no requests"),
+        }
+    }
+
+    /// Which provider and model `name` asks for: a provider name
+    /// (`claude`), a provider and model (`claude/claude-opus-4-1`), or a
+    /// model id, whose prefix picks the provider. The model is None for a
+    /// provider name alone.
+    pub fn route(name: &str, default: &AIProvider) -> (AIProvider, Option<String>) {
+        if let Some(provider) = AIProvider::parse(name) {
+            return (provider, None);
+        }
+        if let Some((provider, model)) = name.split_once('/').and_then(|(p, m)| Some((AIProvider::parse(p)?, m))) {
+            return (provider, Some(model.to_string()));
+        }
+        let provider = match name {
+            m if m.starts_with("claude") => AIProvider::Claude,
+            m if m.starts_with("grok") => AIProvider::Grok,
+            m if m.starts_with("gpt") || m.starts_with("chatgpt") || is_reasoning_model(m) => AIProvider::OpenAI,
+            m if m.starts_with("gemini") => AIProvider::Gemini,
+            _ => default.clone(),
+        };
+        (provider, Some(name.to_string()))
+    }
+
+    /// Model used unless a conversation picks another
+    pub fn model(&self) -> &str {
+        match self {
+            AIProvider::Claude => "claude-sonnet-4-5-20250929",
+            AIProvider::Grok => "grok-4",
+            AIProvider::OpenAI => "gpt-5",
+            AIProvider::Gemini => "gemini-2.5-pro",
+            AIProvider::Mock => mock::MODEL,
+        }
+    }
+
+    pub fn api_key_env(&self) -> &str {
+        match self {
+            AIProvider::Claude => "CLAUDE_API_KEY",
+            AIProvider::Grok => "GROK_API_KEY",
+            AIProvider::OpenAI => "OPENAI_API_KEY",
+            AIProvider::Gemini => "GEMINI_API_KEY",
+            // Never read; see `needs_key`
+            AIProvider::Mock => "",
+        }
+    }
+
+    /// False for the mock, which answers without a key
+    pub fn needs_key(&self) -> bool {
+        *self != AIProvider::Mock
+    }
+
+    pub fn get_api_key(&self) -> Result<String> {
+        env::var(self.api_key_env())
+            .with_context(|| format!("{} not set in environment", self.api_key_env()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+/// OpenAI's o1, o3, o4-mini and so on
+fn is_reasoning_model(model: &str) -> bool {
+    model.strip_prefix('o').is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// How a conversation talks to its provider. None means the provider's
+/// default; they're saved with the conversation and restored with it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatSettings {
+    pub model: Option<String>,
+    pub system_prompt: Option<String>,
+    pub temperature: Option<f64>,
+    /// Name of a `[personas]` entry in the config. The chat puts its
+    /// instructions ahead of the system prompt; requests ignore this.
+    pub persona: Option<String>,
+}
+
+impl ChatSettings {
+    /// Settings as sent: the persona's instructions go ahead of the
+    /// conversation's own system prompt
+    pub fn resolve_persona(mut self, personas: &BTreeMap<String, String>) -> Self {
+        if let Some(instructions) = self.persona.as_ref().and_then(|name| personas.get(name)) {
+            self.system_prompt = Some(match self.system_prompt {
+                Some(prompt) => format!("{}\n\n{}", instructions, prompt),
+                None => instructions.clone(),
+            });
+        }
+        self
+    }
+}
+
+/// A model's answer and what it took to get it
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub content: String,
+    pub usage: Usage,
+}
+
+/// Token counts as reported by the API (None when it didn't say)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub model: String,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    /// Request round trip in milliseconds
+    pub latency_ms: i64,
+}
+
+impl Usage {
+    /// Price in US dollars at list rates, if the model and both token
+    /// counts are known
+    pub fn cost(&self) -> Option<f64> {
+        let (input, output) = price_per_million(&self.model)?;
+        let prompt = self.prompt_tokens? as f64;
+        let completion = self.completion_tokens? as f64;
+        Some((prompt * input + completion * output) / 1_000_000.0)
+    }
+}
+
+/// List price per million (input, output) tokens. Gemini's long-prompt
+/// surcharge is ignored.
+pub fn price_per_million(model: &str) -> Option<(f64, f64)> {
+    match model {
+        "claude-sonnet-4-5-20250929" => Some((3.0, 15.0)),
+        "grok-4" => Some((3.0, 15.0)),
+        "gpt-5" => Some((1.25, 10.0)),
+        "gemini-2.5-pro" => Some((1.25, 10.0)),
+        _ => None,
+    }
+}
+
+// Claude API structures
+#[derive(Debug, Serialize)]
+struct ClaudeRequest {
+    model: String,
+    messages: Vec<ClaudeMessage>,
+    max_tokens: u32,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ClaudeMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeResponse {
+    content: Vec<ClaudeContent>,
+    #[serde(default)]
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeUsage {
+    input_tokens: i64,
+    output_tokens: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeContent {
+    text: Option<String>,
+}
+
+// OpenAI/Grok API structures
+#[derive(Debug, Serialize)]
+struct OpenAIRequest {
+    model: String,
+    messages: Vec<OpenAIMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIResponse {
+    choices: Vec<OpenAIChoice>,
+    #[serde(default)]
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIUsage {
+    prompt_tokens: i64,
+    completion_tokens: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIChoice {
+    message: OpenAIMessage,
+}
+
+// Gemini API structures
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GeminiGenerationConfig>,
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiGenerationConfig {
+    temperature: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiContent {
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiPart {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiResponse {
+    candidates: Vec<GeminiCandidate>,
+    #[serde(default, rename = "usageMetadata")]
+    usage_metadata: Option<GeminiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    #[serde(default)]
+    prompt_token_count: i64,
+    #[serde(default)]
+    candidates_token_count: i64,
+    /// Thinking tokens, billed as output
+    #[serde(default)]
+    thoughts_token_count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiCandidate {
+    content: GeminiContentResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiContentResponse {
+    parts: Vec<GeminiPartResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiPartResponse {
+    text: String,
+}
+
+#[derive(Clone)]
+pub struct AIClient {
+    provider: AIProvider,
+    /// None for the shared one
+    client: Option<Client>,
+    /// Key from the profile; None reads the environment
+    api_key: Option<String>,
+    /// Scheme and host to send to instead of the provider's own
+    base_url: Option<String>,
+    /// `animated-cli daemon` socket to send through when one is listening
+    #[cfg(unix)]
+    daemon: Option<PathBuf>,
+    /// The provider's `[rate_limits]`, which requests wait their turn for
+    rate_limit: Option<Arc<RateLimiter>>,
+    /// Told where a waiting request is in the queue, and None once it's sent
+    queue_updates: Option<mpsc::UnboundedSender<Option<Queued>>>,
+}
+
+impl AIClient {
+    pub fn new(provider: AIProvider, api_key: Option<String>) -> Self {
+        Self {
+            provider,
+            client: None,
+            api_key,
+            base_url: None,
+            #[cfg(unix)]
+            daemon: None,
+            rate_limit: None,
+            queue_updates: None,
+        }
+    }
+
+    /// A client sharing another's connection pool
+    pub fn with_http(provider: AIProvider, api_key: Option<String>, client: Client) -> Self {
+        Self {
+            client: Some(client),
+            ..Self::new(provider, api_key)
+        }
+    }
+
+    fn http(&self) -> &Client {
+        self.client.as_ref().unwrap_or_else(|| HTTP.get_or_init(Client::new))
+    }
+
+    /// Send to `url` (scheme and host, e.g. `http://127.0.0.1:8080`)
+    /// instead of the provider's own API: a proxy, a gateway or a test
+    /// server. The provider's paths are kept.
+    pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    fn api_url(&self, model: &str) -> String {
+        let base = self.base_url.as_deref().unwrap_or(self.provider.api_base());
+        format!("{}{}", base, self.provider.api_path(model))
+    }
+
+    /// Send through the daemon on `socket` whenever it's running, and
+    /// directly when it isn't
+    #[cfg(unix)]
+    pub fn via_daemon(mut self, socket: Option<PathBuf>) -> Self {
+        self.daemon = socket;
+        self
+    }
+
+    /// Hold each request until `limiter` lets it go
+    pub fn with_rate_limit(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    /// Send a request's place in the rate limit's queue to `updates`
+    /// while it waits, and None when it goes
+    pub fn reporting_queue(mut self, updates: mpsc::UnboundedSender<Option<Queued>>) -> Self {
+        self.queue_updates = Some(updates);
+        self
+    }
+
+    /// Open a connection to the provider ahead of the first request. The
+    /// answer doesn't matter; the pooled connection does.
+    pub async fn warm_up(&self) {
+        if self.provider == AIProvider::Mock {
+            return;
+        }
+        let _ = self.http().head(self.api_url(self.provider.model())).send().await;
+    }
+
+    pub fn api_key(&self) -> Result<String> {
+        match self.api_key {
+            Some(ref key) => Ok(key.clone()),
+            None => self.provider.get_api_key(),
+        }
+    }
+
+    pub async fn send_message(&self, messages: Vec<Message>, settings: &ChatSettings) -> Result<Reply> {
+        let permit = match self.rate_limit {
+            Some(ref limiter) => {
+                let estimate = ratelimit::estimate_tokens(&messages, settings.system_prompt.as_deref());
+                let report = |queued| {
+                    if let Some(ref updates) = self.queue_updates {
+                        let _ = updates.send(queued);
+                    }
+                };
+                Some(limiter.wait_turn(estimate, report).await)
+            }
+            None => None,
+        };
+        let reply = self.send_now(messages, settings).await;
+        // What the provider says it took, where it says
+        if let (Some(permit), Ok(reply)) = (permit, &reply) {
+            if let (Some(prompt), Some(completion)) = (reply.usage.prompt_tokens, reply.usage.completion_tokens) {
+                permit.used((prompt + completion).max(0) as u64);
+            }
+        }
+        reply
+    }
+
+    async fn send_now(&self, messages: Vec<Message>, settings: &ChatSettings) -> Result<Reply> {
+        // The mock answers here, and the daemon only knows the providers'
+        // own APIs
+        #[cfg(unix)]
+        if let Some(connection) = self
+            .daemon
+            .as_deref()
+            .filter(|_| self.provider.needs_key() && self.base_url.is_none())
+            .and_then(crate::daemon::connect)
+        {
+            let forwarded = crate::daemon::forward(connection, &self.provider, self.api_key().ok(), messages, settings, None);
+            return forwarded.await.map(|forwarded| forwarded.reply);
+        }
+        let started = Instant::now();
+        let model = settings.model.as_deref().unwrap_or(self.provider.model());
+        let (messages, settings) = Capabilities::of(&self.provider, model).adapt(messages, settings);
+        let settings = &settings;
+        let provider = self.provider.db_name();
+        debug!(provider, model, messages = messages.len(), "Sending request");
+        let sent = match self.provider {
+            AIProvider::Claude => self.send_claude(messages, model, settings).await,
+            AIProvider::Grok => self.send_openai_compatible(messages, model, settings).await,
+            AIProvider::OpenAI => self.send_openai_compatible(messages, model, settings).await,
+            AIProvider::Gemini => self.send_gemini(messages, model, settings).await,
+            AIProvider::Mock => mock::reply(&messages, settings).await,
+        };
+        let (content, tokens) = match sent {
+            Ok(sent) => sent,
+            Err(e) => {
+                warn!(provider, model, "Request failed: {:#}", e);
+                return Err(e);
+            }
+        };
+        info!(
+            provider,
+            model,
+            latency_ms = started.elapsed().as_millis() as u64,
+            tokens = ?tokens,
+            "Reply received"
+        );
+
+        Ok(Reply {
+            content,
+            usage: Usage {
+                model: model.to_string(),
+                prompt_tokens: tokens.map(|(prompt, _)| prompt),
+                completion_tokens: tokens.map(|(_, completion)| completion),
+                latency_ms: started.elapsed().as_millis() as i64,
+            },
+        })
+    }
+
+    /// Each returns the reply text and (prompt, completion) token counts
+    async fn send_claude(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        settings: &ChatSettings,
+    ) -> Result<(String, Option<(i64, i64)>)> {
+        let api_key = self.api_key()?;
+
+        let request = ClaudeRequest {
+            model: model.to_string(),
+            messages: messages
+                .into_iter()
+                .map(|m| ClaudeMessage {
+                    role: m.role,
+                    content: m.content,
+                })
+                .collect(),
+            max_tokens: 4096,
+            stream: false,
+            system: settings.system_prompt.clone(),
+            temperature: settings.temperature,
+        };
+
+        trace!(body = %serde_json::to_string(&request).unwrap_or_default(), "Claude request");
+
+        let response = self
+            .http()
+            .post(self.api_url(model))
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .context("Failed to send request to Claude")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Claude API error {}: {}", status, error_text));
+        }
+
+        let body = response.text().await.context("Failed to read Claude response")?;
+        trace!(%body, "Claude response");
+        let claude_response: ClaudeResponse = serde_json::from_str(&body).context("Failed to parse Claude response")?;
+
+        let text = claude_response
+            .content
+            .first()
+            .and_then(|c| c.text.clone())
+            .ok_or_else(|| anyhow!("No content in Claude response"))?;
+        let tokens = claude_response.usage.map(|u| (u.input_tokens, u.output_tokens));
+        Ok((text, tokens))
+    }
+
+    async fn send_openai_compatible(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        settings: &ChatSettings,
+    ) -> Result<(String, Option<(i64, i64)>)> {
+        let api_key = self.api_key()?;
+
+        let system = settings.system_prompt.clone().map(|content| OpenAIMessage {
+            role: "system".to_string(),
+            content,
+        });
+        let request = OpenAIRequest {
+            model: model.to_string(),
+            messages: system
+                .into_iter()
+                .chain(messages.into_iter().map(|m| OpenAIMessage {
+                    role: m.role,
+                    content: m.content,
+                }))
+                .collect(),
+            stream: false,
+            temperature: settings.temperature,
+        };
+
+        trace!(body = %serde_json::to_string(&request).unwrap_or_default(), "{} request", self.provider.name());
+
+        let response = self
+            .http()
+            .post(self.api_url(model))
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .with_context(|| format!("Failed to send request to {}", self.provider.name()))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "{} API error {}: {}",
+                self.provider.name(),
+                status,
+                error_text
+            ));
+        }
+
+        let body = response
+            .text()
+            .await
+            .with_context(|| format!("Failed to read {} response", self.provider.name()))?;
+        trace!(%body, "{} response", self.provider.name());
+        let openai_response: OpenAIResponse = serde_json::from_str(&body)
+            .with_context(|| format!("Failed to parse {} response", self.provider.name()))?;
+
+        let text = openai_response
+            .choices
+            .first()
+            .map(|c| c.message.content.clone())
+            .ok_or_else(|| anyhow!("No content in {} response", self.provider.name()))?;
+        let tokens = openai_response.usage.map(|u| (u.prompt_tokens, u.completion_tokens));
+        Ok((text, tokens))
+    }
+
+    async fn send_gemini(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        settings: &ChatSettings,
+    ) -> Result<(String, Option<(i64, i64)>)> {
+        let api_key = self.api_key()?;
+
+        // Gemini expects a different format
+        let combined_text = messages
+            .into_iter()
+            .map(|m| format!("{}: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let request = GeminiRequest {
+            contents: vec![GeminiContent {
+                parts: vec![GeminiPart {
+                    text: combined_text,
+                }],
+            }],
+            system_instruction: settings.system_prompt.clone().map(|text| GeminiContent {
+                parts: vec![GeminiPart { text }],
+            }),
+            generation_config: settings.temperature.map(|temperature| GeminiGenerationConfig { temperature }),
+        };
+
+        let url = format!("{}?key={}", self.api_url(model), api_key);
+
+        trace!(body = %serde_json::to_string(&request).unwrap_or_default(), "Gemini request");
+
+        let response = self
+            .http()
+            .post(&url)
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .context("Failed to send request to Gemini")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Gemini API error {}: {}", status, error_text));
+        }
+
+        let body = response.text().await.context("Failed to read Gemini response")?;
+        trace!(%body, "Gemini response");
+        let gemini_response: GeminiResponse = serde_json::from_str(&body).context("Failed to parse Gemini response")?;
+
+        let text = gemini_response
+            .candidates
+            .first()
+            .and_then(|c| c.content.parts.first().map(|p| p.text.clone()))
+            .ok_or_else(|| anyhow!("No content in Gemini response"))?;
+        let tokens = gemini_response
+            .usage_metadata
+            .map(|u| (u.prompt_token_count, u.candidates_token_count + u.thoughts_token_count));
+        Ok((text, tokens))
+    }
+}
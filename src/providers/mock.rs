@@ -0,0 +1,133 @@
+//! The mock provider: canned or echoed replies after a short, typing-like
+//! delay, with no key and no network. It backs `--demo`, screenshots and
+//! the tests.
+
+use anyhow::{bail, Result};
+use std::time::Duration;
+
+use super::{ChatSettings, Message};
+
+/// Overrides the reply delay, in milliseconds; the tests set it to 0
+const DELAY_ENV: &str = "MEGA_CLI_MOCK_DELAY_MS";
+
+/// Delay per word of the reply, as if it were being written
+const DELAY_PER_WORD: Duration = Duration::from_millis(20);
+const MIN_DELAY: Duration = Duration::from_millis(300);
+const MAX_DELAY: Duration = Duration::from_millis(1500);
+
+/// Model reported when the conversation doesn't set one
+pub const MODEL: &str = "mock-1";
+
+/// Its pretend context window, small enough to fill up in a demo
+pub const CONTEXT_WINDOW: u64 = 8_192;
+
+const GREETING: &str = "Hello! I'm the mock provider: I answer offline, instantly and for free. \
+Ask for some code, a patch, a list, a table or a long answer, or say anything and I'll echo it back.";
+
+const CODE: &str = "Here's some Rust:
+
+```rust
+fn main() {
+    let providers = [\"claude\", \"grok\", \"gpt\", \"gemini\"];
+    for provider in providers {
+        println!(\"Hello from {}\", provider);
+    }
+}
+```
+
+It prints a greeting from each provider.";
+
+const PATCH: &str = "Here's that change as a patch; /apply looks it over before applying it:
+
+```diff
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,6 +1,6 @@
+ fn main() {
+     let providers = [\"claude\", \"grok\", \"gpt\", \"gemini\"];
+     for provider in providers {
+-        println!(\"Hello from {}\", provider);
++        println!(\"Hello from {provider}!\");
+     }
+ }
+```";
+
+const LIST: &str = "A few things to try:
+
+- **F2** switches provider
+- **F6** browses saved conversations
+- **Ctrl+F** searches every conversation
+- `/set temperature 0.3` changes a setting for this conversation";
+
+const TABLE: &str = "How the providers compare:
+
+| Provider | Default model | Context window | Images |
+|----------|---------------|---------------:|:------:|
+| Claude | claude-sonnet-4-5 | 200,000 | yes |
+| Grok | grok-4 | 256,000 | yes |
+| GPT | gpt-5 | 400,000 | yes |
+| Gemini | gemini-2.5-pro | 1,048,576 | yes |
+
+Wider than the panel? Use ← and → to scroll it.";
+
+const LONG: &str = "The background you see is decoded frame by frame and drawn as colored text \
+characters, dimmed behind the panels so the conversation stays readable. \
+Each reply is saved as it arrives, along with the model, token counts and how long it took.
+
+Conversations belong to a provider, and each provider keeps as many as you like. \
+Tags, stars and per-conversation settings travel with them, and the session manager \
+can rename, archive or delete any of them.
+
+This paragraph is only here to make the reply long enough to scroll through, \
+so that wrapping and scrolling can be seen at work on a small terminal.";
+
+/// The reply to the last user message, and (prompt, completion) word
+/// counts standing in for tokens. Asking it to "simulate an error" fails
+/// the way an overloaded API would.
+pub async fn reply(messages: &[Message], settings: &ChatSettings) -> Result<(String, Option<(i64, i64)>)> {
+    let question = messages.iter().rev().find(|m| m.role == "user").map_or("", |m| m.content.as_str());
+    let lower = question.to_lowercase();
+
+    let mut content = if lower.contains("simulate an error") {
+        tokio::time::sleep(delay(0)).await;
+        bail!("Mock API error 503 Service Unavailable: simulated failure");
+    } else if ["hello", "hi", "hey"].iter().any(|w| lower.split_whitespace().next() == Some(w)) {
+        GREETING.to_string()
+    } else if lower.contains("code") {
+        CODE.to_string()
+    } else if lower.contains("patch") {
+        PATCH.to_string()
+    } else if lower.contains("table") {
+        TABLE.to_string()
+    } else if lower.contains("list") {
+        LIST.to_string()
+    } else if lower.contains("long") {
+        LONG.to_string()
+    } else {
+        format!("You said: {}", question)
+    };
+    if let Some(ref system) = settings.system_prompt {
+        content.push_str(&format!("\n\n(System prompt: {})", system));
+    }
+    if let Some(temperature) = settings.temperature {
+        content.push_str(&format!("\n\n(Temperature: {})", temperature));
+    }
+
+    let completion = words(&content);
+    let prompt = messages.iter().map(|m| words(&m.content)).sum::<i64>()
+        + settings.system_prompt.as_deref().map_or(0, words);
+    tokio::time::sleep(delay(completion)).await;
+    Ok((content, Some((prompt, completion))))
+}
+
+/// How long a reply of `words` words takes to arrive
+fn delay(words: i64) -> Duration {
+    if let Some(ms) = std::env::var(DELAY_ENV).ok().and_then(|ms| ms.parse().ok()) {
+        return Duration::from_millis(ms);
+    }
+    (DELAY_PER_WORD * words as u32).clamp(MIN_DELAY, MAX_DELAY)
+}
+
+fn words(text: &str) -> i64 {
+    text.split_whitespace().count() as i64
+}
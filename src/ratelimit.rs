@@ -0,0 +1,210 @@
+//! Client-side rate limits (`[rate_limits.<provider>]`): requests and
+//! tokens a minute per provider, counted over the last minute. A request
+//! that would go over waits its turn, first come first served, instead
+//! of being sent only to come back as a 429, and whoever sent it is told
+//! where it is in the queue.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::time::{Duration, Instant};
+
+use crate::config::RateLimitConfig;
+use crate::providers::{AIProvider, Message};
+
+/// What the limits count over
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// How often a waiting request looks again, and its countdown moves
+const RECHECK: Duration = Duration::from_secs(1);
+
+/// Rough tokens in a prompt, before the provider says: about four
+/// characters each
+pub fn estimate_tokens(messages: &[Message], system_prompt: Option<&str>) -> u64 {
+    let chars: usize = messages.iter().map(|m| m.content.len()).sum::<usize>() + system_prompt.map_or(0, str::len);
+    (chars / 4).max(1) as u64
+}
+
+/// `estimate_tokens` for some text on its own
+pub fn estimate_text(text: &str) -> u64 {
+    (text.len() / 4) as u64
+}
+
+/// Where a waiting request stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Queued {
+    /// 1 for the next to go
+    pub position: usize,
+    /// When the next to go can, as far as the limits say
+    pub ready_in: Duration,
+}
+
+/// The limiters for every provider, shared by whatever sends to them
+#[derive(Clone, Default)]
+pub struct RateLimits {
+    limiters: Arc<Mutex<HashMap<String, Arc<RateLimiter>>>>,
+    config: Arc<Mutex<BTreeMap<String, RateLimitConfig>>>,
+}
+
+impl RateLimits {
+    pub fn new(config: &BTreeMap<String, RateLimitConfig>) -> Self {
+        let limits = Self::default();
+        limits.configure(config);
+        limits
+    }
+
+    /// Take up edited limits. What was sent in the last minute still
+    /// counts against the new ones.
+    pub fn configure(&self, config: &BTreeMap<String, RateLimitConfig>) {
+        *lock(&self.config) = config.clone();
+        for (name, limiter) in lock(&self.limiters).iter() {
+            lock(&limiter.state).limit = config.get(name).copied().unwrap_or_default();
+        }
+    }
+
+    /// The provider's limiter; one without limits lets everything straight through
+    pub fn for_provider(&self, provider: &AIProvider) -> Arc<RateLimiter> {
+        let name = provider.db_name();
+        lock(&self.limiters)
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let limit = lock(&self.config).get(name).copied().unwrap_or_default();
+                Arc::new(RateLimiter::new(limit))
+            })
+            .clone()
+    }
+}
+
+/// A poisoned lock only means a panic elsewhere; the counts are still good
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub struct RateLimiter {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    limit: RateLimitConfig,
+    /// Requests sent in the last minute: when, which, and their tokens
+    sent: VecDeque<(Instant, u64, u64)>,
+    /// Requests waiting, oldest first
+    queue: VecDeque<u64>,
+    next_id: u64,
+}
+
+impl State {
+    fn forget_old(&mut self, now: Instant) {
+        while self.sent.front().is_some_and(|(at, ..)| now.duration_since(*at) >= WINDOW) {
+            self.sent.pop_front();
+        }
+    }
+
+    /// How long until a request of `tokens` fits within the limits
+    fn wait_for(&self, now: Instant, tokens: u64) -> Duration {
+        let frees = |index: usize| self.sent[index].0 + WINDOW - now;
+        let mut wait = Duration::ZERO;
+        if let Some(requests) = self.limit.requests_per_minute.filter(|&n| n > 0) {
+            let requests = requests as usize;
+            if self.sent.len() >= requests {
+                wait = wait.max(frees(self.sent.len() - requests));
+            }
+        }
+        if let Some(limit) = self.limit.tokens_per_minute.filter(|&n| n > 0) {
+            // One request bigger than the whole limit goes once nothing
+            // else is counted, rather than never
+            let mut used: u64 = self.sent.iter().map(|(.., tokens)| tokens).sum();
+            let mut index = 0;
+            while used + tokens > limit as u64 && index < self.sent.len() {
+                used -= self.sent[index].2;
+                wait = wait.max(frees(index));
+                index += 1;
+            }
+        }
+        wait
+    }
+}
+
+impl RateLimiter {
+    fn new(limit: RateLimitConfig) -> Self {
+        Self {
+            state: Mutex::new(State { limit, ..State::default() }),
+        }
+    }
+
+    /// Wait until a request of about `tokens` can be sent, calling
+    /// `report` with its place in the queue whenever that changes, and
+    /// with None once it's on its way
+    pub async fn wait_turn(self: &Arc<Self>, tokens: u64, report: impl Fn(Option<Queued>)) -> Permit {
+        let id = {
+            let mut state = lock(&self.state);
+            state.next_id += 1;
+            let id = state.next_id;
+            state.queue.push_back(id);
+            id
+        };
+        // Gives up its place if the request is dropped while waiting
+        let mut place = Place { limiter: self, id, sent: false };
+        let mut last = None;
+        loop {
+            let wait = {
+                let mut state = lock(&self.state);
+                let now = Instant::now();
+                state.forget_old(now);
+                let position = state.queue.iter().position(|&waiting| waiting == id).unwrap_or(0) + 1;
+                let wait = state.wait_for(now, tokens);
+                if position == 1 && wait.is_zero() {
+                    state.queue.pop_front();
+                    state.sent.push_back((now, id, tokens));
+                    place.sent = true;
+                    break;
+                }
+                // Whole seconds, so the countdown doesn't report every tick
+                let ready_in = Duration::from_secs(state.wait_for(now, 0).max(wait).as_secs_f64().ceil() as u64);
+                let queued = Some(Queued { position, ready_in });
+                if queued != last {
+                    report(queued);
+                    last = queued;
+                }
+                wait
+            };
+            tokio::time::sleep(wait.clamp(Duration::from_millis(10), RECHECK)).await;
+        }
+        if last.is_some() {
+            report(None);
+        }
+        Permit { limiter: self.clone(), id }
+    }
+}
+
+/// A request's place in the queue, until it's sent or dropped
+struct Place<'a> {
+    limiter: &'a RateLimiter,
+    id: u64,
+    sent: bool,
+}
+
+impl Drop for Place<'_> {
+    fn drop(&mut self) {
+        if !self.sent {
+            lock(&self.limiter.state).queue.retain(|&waiting| waiting != self.id);
+        }
+    }
+}
+
+/// A sent request, counted against the limits at its estimate until
+/// `used` says what it really took
+pub struct Permit {
+    limiter: Arc<RateLimiter>,
+    id: u64,
+}
+
+impl Permit {
+    /// Count the request's actual prompt and reply tokens instead
+    pub fn used(self, tokens: u64) {
+        let mut state = lock(&self.limiter.state);
+        if let Some(entry) = state.sent.iter_mut().find(|(_, id, _)| *id == self.id) {
+            entry.2 = tokens;
+        }
+    }
+}
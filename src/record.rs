@@ -0,0 +1,122 @@
+//! `--record`: every message of the session appended to a transcript file
+//! as it happens, whatever becomes of the database. A `.jsonl` path gets
+//! one JSON object per message; anything else gets Markdown.
+
+use anyhow::{Context, Result};
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::providers::{AIProvider, Usage};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RecordFormat {
+    Markdown,
+    Jsonl,
+}
+
+/// One line of a `.jsonl` transcript
+#[derive(Serialize)]
+struct Entry<'a> {
+    /// Local time, RFC 3339
+    time: String,
+    provider: &'a str,
+    conversation: &'a str,
+    role: &'a str,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt_tokens: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completion_tokens: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<i64>,
+}
+
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+    format: RecordFormat,
+    /// Provider and title of the conversation the last message was in, so
+    /// Markdown gets a heading when it changes
+    last_conversation: Option<(String, String)>,
+}
+
+impl Recorder {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let format = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("jsonl") => RecordFormat::Jsonl,
+            _ => RecordFormat::Markdown,
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {} for recording", path.display()))?;
+        if format == RecordFormat::Markdown {
+            writeln!(file, "# Session {}\n", Local::now().format("%Y-%m-%d %H:%M"))
+                .with_context(|| format!("Failed to write to {}", path.display()))?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            format,
+            last_conversation: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one message. `timestamp` is Unix seconds.
+    pub fn record(
+        &mut self,
+        provider: &AIProvider,
+        conversation: &str,
+        role: &str,
+        content: &str,
+        usage: Option<&Usage>,
+        timestamp: i64,
+    ) -> Result<()> {
+        let time = Local.timestamp_opt(timestamp, 0).single().unwrap_or_else(Local::now);
+        let text = match self.format {
+            RecordFormat::Jsonl => {
+                let entry = Entry {
+                    time: time.to_rfc3339(),
+                    provider: provider.db_name(),
+                    conversation,
+                    role,
+                    content,
+                    model: usage.map(|u| u.model.as_str()),
+                    prompt_tokens: usage.and_then(|u| u.prompt_tokens),
+                    completion_tokens: usage.and_then(|u| u.completion_tokens),
+                    latency_ms: usage.map(|u| u.latency_ms),
+                };
+                format!("{}\n", serde_json::to_string(&entry)?)
+            }
+            RecordFormat::Markdown => {
+                let key = (provider.db_name().to_string(), conversation.to_string());
+                let mut text = String::new();
+                if self.last_conversation.as_ref() != Some(&key) {
+                    text.push_str(&format!("## {} ({})\n\n", conversation, provider.name()));
+                    self.last_conversation = Some(key);
+                }
+                let speaker = match (role, usage) {
+                    ("user", _) => "You".to_string(),
+                    (_, Some(usage)) => format!("{} ({})", provider.name(), usage.model),
+                    _ => provider.name().to_string(),
+                };
+                text.push_str(&format!("**{}** · {}\n\n{}\n\n", speaker, time.format("%H:%M"), content.trim_end()));
+                text
+            }
+        };
+        self.file
+            .write_all(text.as_bytes())
+            .with_context(|| format!("Failed to write to {}", self.path.display()))
+    }
+}
@@ -0,0 +1,220 @@
+//! Secrets in outgoing messages: API keys, tokens, private keys, email
+//! addresses and long random-looking strings are looked for in every
+//! message before it's sent, attached files and diffs included. Found,
+//! they either hold the message back with a warning (sending it again as
+//! it is goes ahead) or are masked on the way out.
+//!
+//! ```toml
+//! [redact]
+//! action = "warn"     # "mask" replaces them with [redacted]; "off" doesn't look
+//! emails = true       # email addresses count
+//! entropy = true      # so do long strings that look random
+//!
+//! [redact.patterns]   # more to look for, by what to call them
+//! "ticket id" = 'ACME-\d{6}'
+//! ```
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::config::RedactConfig;
+
+/// What's looked for whatever the config says, by what it's called
+const BUILT_IN: [(&str, &str); 11] = [
+    ("private key", r"-----BEGIN [A-Z ]*PRIVATE KEY-----"),
+    ("Anthropic key", r"\bsk-ant-[A-Za-z0-9_-]{20,}"),
+    ("OpenAI key", r"\bsk-(?:proj-|svcacct-)?[A-Za-z0-9_-]{20,}"),
+    ("xAI key", r"\bxai-[A-Za-z0-9]{20,}"),
+    ("Google API key", r"\bAIza[0-9A-Za-z_-]{35}"),
+    ("GitHub token", r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})"),
+    ("AWS access key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    ("Slack token", r"\bxox[abprs]-[A-Za-z0-9-]{10,}"),
+    ("Stripe key", r"\b[sr]k_live_[A-Za-z0-9]{20,}"),
+    ("JSON web token", r"\beyJ[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}"),
+    ("bearer token", r"(?i)\bbearer\s+[A-Za-z0-9._~+/-]{20,}=*"),
+];
+
+const EMAIL: &str = r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b";
+
+/// Runs of characters a token is made of, to measure for randomness
+const TOKEN: &str = r"[A-Za-z0-9+/=_-]{24,}";
+
+/// Bits per character above which a token looks random. Hex, such as a
+/// commit hash, tops out at 4 and English words stay well under.
+const MIN_ENTROPY: f64 = 4.2;
+
+/// What a found secret is replaced with when masking
+pub const MASK: &str = "[redacted]";
+
+/// What's done with a message that has secrets in it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Off,
+    Warn,
+    Mask,
+}
+
+impl Action {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(Action::Off),
+            "warn" => Some(Action::Warn),
+            "mask" => Some(Action::Mask),
+            _ => None,
+        }
+    }
+}
+
+/// Something that looks like a secret, and where
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    /// What it looks like, e.g. "OpenAI key"
+    pub kind: String,
+    pub range: Range<usize>,
+}
+
+pub struct Redactor {
+    action: Action,
+    /// Built in and from the config, each by what it finds
+    patterns: Vec<(String, Regex)>,
+    email: Option<Regex>,
+    entropy: Option<Regex>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new(&RedactConfig::default()).expect("the built-in patterns compile")
+    }
+}
+
+impl Redactor {
+    pub fn new(config: &RedactConfig) -> Result<Self> {
+        let Some(action) = Action::parse(&config.action) else {
+            bail!("Unknown redact action: {} (expected warn, mask or off)", config.action);
+        };
+        let mut patterns = vec![];
+        for (kind, pattern) in BUILT_IN {
+            patterns.push((kind.to_string(), Regex::new(pattern)?));
+        }
+        for (kind, pattern) in &config.patterns {
+            let regex = Regex::new(pattern).with_context(|| format!("The redact pattern \"{}\" isn't a valid regex", kind))?;
+            patterns.push((kind.clone(), regex));
+        }
+        Ok(Self {
+            action,
+            patterns,
+            email: config.emails.then(|| Regex::new(EMAIL)).transpose()?,
+            entropy: config.entropy.then(|| Regex::new(TOKEN)).transpose()?,
+        })
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    /// Secrets in `text`, in order and not overlapping. `known` are keys
+    /// and tokens in use, found wherever they turn up.
+    pub fn find(&self, text: &str, known: &[String]) -> Vec<Finding> {
+        if self.action == Action::Off {
+            return vec![];
+        }
+        let mut found: Vec<Finding> = vec![];
+        for secret in known {
+            let secret = secret.trim();
+            for (at, _) in text.match_indices(secret) {
+                found.push(Finding { kind: "configured API key".to_string(), range: at..at + secret.len() });
+            }
+        }
+        for (kind, regex) in &self.patterns {
+            found.extend(regex.find_iter(text).map(|m| Finding { kind: kind.clone(), range: m.range() }));
+        }
+        if let Some(ref email) = self.email {
+            found.extend(email.find_iter(text).map(|m| Finding { kind: "email address".to_string(), range: m.range() }));
+        }
+        if let Some(ref token) = self.entropy {
+            found.extend(
+                token
+                    .find_iter(text)
+                    .filter(|m| looks_random(m.as_str()))
+                    .map(|m| Finding { kind: "random-looking string".to_string(), range: m.range() }),
+            );
+        }
+        // What was found first wins where two overlap, as it's the surer
+        let mut kept: Vec<Finding> = vec![];
+        for finding in found {
+            if !kept.iter().any(|k| k.range.start < finding.range.end && finding.range.start < k.range.end) {
+                kept.push(finding);
+            }
+        }
+        kept.sort_by_key(|finding| finding.range.start);
+        kept
+    }
+}
+
+/// Whether a token has letters and digits and enough entropy per
+/// character to be a key rather than a word
+fn looks_random(token: &str) -> bool {
+    if !token.chars().any(|c| c.is_ascii_digit()) || !token.chars().any(|c| c.is_ascii_alphabetic()) {
+        return false;
+    }
+    let mut counts: BTreeMap<char, usize> = BTreeMap::new();
+    for c in token.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let length = token.chars().count() as f64;
+    let entropy: f64 = counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / length;
+            -p * p.log2()
+        })
+        .sum();
+    entropy >= MIN_ENTROPY
+}
+
+/// `text` with every finding replaced by [`MASK`]
+pub fn mask(text: &str, findings: &[Finding]) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut at = 0;
+    for finding in findings {
+        masked.push_str(&text[at..finding.range.start]);
+        masked.push_str(MASK);
+        at = finding.range.end;
+    }
+    masked.push_str(&text[at..]);
+    masked
+}
+
+/// What was found, for a warning: each kind once, with how many and the
+/// start of the first, e.g. "an OpenAI key (sk-pr…), 2 email addresses
+/// (jane…)"
+pub fn describe(text: &str, findings: &[Finding]) -> String {
+    let mut kinds: Vec<(&str, usize, &str)> = vec![];
+    for finding in findings {
+        match kinds.iter_mut().find(|(kind, _, _)| *kind == finding.kind) {
+            Some((_, count, _)) => *count += 1,
+            None => kinds.push((&finding.kind, 1, &text[finding.range.clone()])),
+        }
+    }
+    kinds
+        .into_iter()
+        .map(|(kind, count, first)| {
+            let hint: String = first.chars().take(5).collect();
+            match count {
+                1 => format!("{} {} ({}…)", article(kind), kind, hint),
+                n if kind.ends_with('s') => format!("{} {}es ({}…)", n, kind, hint),
+                n => format!("{} {}s ({}…)", n, kind, hint),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn article(word: &str) -> &'static str {
+    match word.chars().next().map(|c| c.to_ascii_lowercase()) {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => "an",
+        _ => "a",
+    }
+}
@@ -0,0 +1,131 @@
+//! Picking up edits to `config.toml` (and a profile's `.env`) while the
+//! chat runs. Settings that can change on the fly do; the rest are listed
+//! as needing a restart.
+
+use anyhow::{Context, Result};
+use crossbeam_channel::{unbounded, Receiver};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::time::{Duration, Instant};
+
+use crate::config::{self, Config};
+
+/// Editors save in several steps; wait for them to finish
+const SETTLE: Duration = Duration::from_millis(250);
+
+/// Files in the profile's directory that are watched
+const WATCHED: [&str; 2] = ["config.toml", ".env"];
+
+/// Keys applied without a restart, or whose parent table is
+const LIVE_KEYS: [&str; 18] = [
+    "background.opacity",
+    "background.panel_opacity",
+    "background.color",
+    "screensaver",
+    "audio.volume",
+    "speech",
+    "sounds",
+    "display.fps",
+    "display.idle_fps",
+    "personas",
+    "templates",
+    "macros",
+    "keys",
+    "paste",
+    "redact",
+    "events",
+    "api_keys",
+    "rate_limits",
+];
+
+pub struct ConfigWatcher {
+    /// Kept alive for as long as events are wanted
+    _watcher: RecommendedWatcher,
+    events: Receiver<()>,
+    /// When the latest unhandled change came in
+    pending: Option<Instant>,
+}
+
+impl ConfigWatcher {
+    /// Watch a profile's directory, creating it if it isn't there yet so a
+    /// config written later is seen
+    pub fn new(profile: Option<&str>) -> Result<Self> {
+        let dir = config::profile_dir(profile)?;
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let (tx, events) = unbounded();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let relevant = event.is_ok_and(|event| {
+                event
+                    .paths
+                    .iter()
+                    .filter_map(|path| path.file_name()?.to_str())
+                    .any(|name| WATCHED.contains(&name))
+            });
+            if relevant {
+                let _ = tx.send(());
+            }
+        })
+        .context("Failed to start watching the config")?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            pending: None,
+        })
+    }
+
+    /// True once a change has settled, and only once per change
+    pub fn poll(&mut self) -> bool {
+        if self.events.try_iter().count() > 0 {
+            self.pending = Some(Instant::now());
+        }
+        match self.pending {
+            Some(since) if since.elapsed() >= SETTLE => {
+                self.pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Dotted names of every setting that differs, e.g. `background.opacity`
+pub fn changed_keys(old: &Config, new: &Config) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let (Ok(old), Ok(new)) = (toml::Value::try_from(old), toml::Value::try_from(new)) {
+        diff("", &old, &new, &mut keys);
+    }
+    if old.api_keys != new.api_keys {
+        keys.push("api_keys".to_string());
+    }
+    keys
+}
+
+fn diff(prefix: &str, old: &toml::Value, new: &toml::Value, keys: &mut Vec<String>) {
+    let (toml::Value::Table(old), toml::Value::Table(new)) = (old, new) else {
+        if old != new {
+            keys.push(prefix.to_string());
+        }
+        return;
+    };
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        match (old.get(name), new.get(name)) {
+            (Some(a), Some(b)) => diff(&key, a, b, keys),
+            _ => keys.push(key),
+        }
+    }
+}
+
+/// Whether a changed key takes effect without a restart
+pub fn is_live(key: &str) -> bool {
+    LIVE_KEYS
+        .iter()
+        .any(|live| key == *live || key.strip_prefix(live).is_some_and(|rest| rest.starts_with('.')))
+}
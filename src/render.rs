@@ -0,0 +1,202 @@
+//! `render`: a saved conversation played back as `/replay` plays it, over
+//! the background, and written out as a recording to share: an asciinema
+//! cast, or with the gif and ffmpeg features a GIF or MP4 video.
+//!
+//! The replay runs against an off-screen terminal in real time, so the
+//! background moves as it would on screen and rendering takes as long as
+//! watching would; `--speed` shortens both.
+
+mod cast;
+#[cfg(feature = "gif")]
+mod gif;
+#[cfg(feature = "ffmpeg")]
+mod mp4;
+#[cfg(any(feature = "gif", feature = "ffmpeg"))]
+mod raster;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use ratatui::Terminal;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::app::App;
+#[cfg(feature = "video")]
+use crate::background::setup::{BackgroundArgs, BackgroundSetup};
+use crate::config::Config;
+use crate::pacing::Pacer;
+use crate::providers::AIProvider;
+#[cfg(feature = "video")]
+use crate::screensaver::Screensaver;
+use crate::sessions;
+use crate::store::Database;
+
+/// Highest --fps, as the chat itself draws
+const MAX_FPS: u32 = 60;
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    /// Conversation to render: its id (shown in the session manager) or words from its title
+    conversation: String,
+
+    /// File to write: .cast (asciinema), .gif or .mp4
+    #[arg(long, short)]
+    output: PathBuf,
+
+    /// Screen size in columns and rows
+    #[arg(long, default_value = "100x30", value_parser = parse_size)]
+    size: (u16, u16),
+
+    /// Frames per second
+    #[arg(long, default_value_t = 15)]
+    fps: u32,
+
+    /// Playback speed, from 0.25 to 8, as + and - set it in /replay
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+
+    /// Seconds the finished conversation stays on screen at the end
+    #[arg(long, default_value_t = 2.0)]
+    hold: f64,
+
+    #[cfg(feature = "video")]
+    #[command(flatten)]
+    background: BackgroundArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderFormat {
+    Cast,
+    Gif,
+    Mp4,
+}
+
+impl RenderFormat {
+    /// From the output file's extension
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        let format = match extension.as_str() {
+            "cast" => RenderFormat::Cast,
+            "gif" => RenderFormat::Gif,
+            "mp4" => RenderFormat::Mp4,
+            _ => bail!("Can't render to {} (expected a .cast, .gif or .mp4 file)", path.display()),
+        };
+        if format == RenderFormat::Gif && !cfg!(feature = "gif") {
+            bail!("This build can't write GIFs; rebuild with the gif feature, or render a .cast");
+        }
+        if format == RenderFormat::Mp4 && !cfg!(feature = "ffmpeg") {
+            bail!("This build can't write MP4s; rebuild with the ffmpeg feature, or render a .cast");
+        }
+        Ok(format)
+    }
+}
+
+/// Where frames go, off the drawing thread so a slow encoder doesn't hold
+/// up the replay
+trait Encoder: Send {
+    /// Add the screen as it is `at` seconds in
+    fn frame(&mut self, screen: &Buffer, at: f64) -> Result<()>;
+
+    /// Write out what's left, the last frame lasting until `end` seconds
+    fn finish(self: Box<Self>, end: f64) -> Result<()>;
+}
+
+#[cfg_attr(not(feature = "ffmpeg"), allow(unused_variables))]
+fn create(format: RenderFormat, path: &Path, size: (u16, u16), fps: u32, title: &str) -> Result<Box<dyn Encoder>> {
+    Ok(match format {
+        RenderFormat::Cast => Box::new(cast::Cast::create(path, size, title)?),
+        #[cfg(feature = "gif")]
+        RenderFormat::Gif => Box::new(gif::Gif::create(path, size)?),
+        #[cfg(feature = "ffmpeg")]
+        RenderFormat::Mp4 => Box::new(mp4::Mp4::create(path, size, fps)?),
+        // Refused by `from_path`
+        #[allow(unreachable_patterns)]
+        _ => bail!("This build can't write {:?}", format),
+    })
+}
+
+/// `100x30`, as columns and rows
+fn parse_size(value: &str) -> Result<(u16, u16), String> {
+    let (columns, rows) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("expected columns x rows, such as 100x30, not {}", value))?;
+    let parse = |n: &str| n.trim().parse::<u16>().ok().filter(|n| (10..=1000).contains(n));
+    match (parse(columns), parse(rows)) {
+        (Some(columns), Some(rows)) => Ok((columns, rows)),
+        _ => Err(format!("{} isn't a size from 10x10 to 1000x1000", value)),
+    }
+}
+
+/// Run `animated-cli render`
+pub async fn run(args: RenderArgs, db_path: &Path, config: &Config) -> Result<()> {
+    let format = RenderFormat::from_path(&args.output)?;
+    if !(1..=MAX_FPS).contains(&args.fps) {
+        bail!("--fps must be from 1 to {}", MAX_FPS);
+    }
+    let db = Database::open(db_path)?;
+    let chosen = sessions::find(&db, Some(&args.conversation), None)?;
+    let provider = AIProvider::parse(&chosen.provider).unwrap_or(AIProvider::Claude);
+
+    // A recording has no sound
+    let mut config = config.clone();
+    config.audio.enabled = false;
+    #[cfg(feature = "video")]
+    let background = {
+        let mut background = BackgroundSetup::resolve(args.background, &config);
+        background.size = Some(args.size);
+        background.screensaver = Screensaver::new(None);
+        background
+    };
+    let mut app = App::new(
+        provider,
+        Some(db),
+        #[cfg(feature = "video")]
+        background,
+        Pacer::new(args.fps, args.fps),
+        config,
+        false,
+    )?;
+    app.chat_mut().replay_saved(&chosen.id.to_string(), args.speed)?;
+
+    let mut encoder = create(format, &args.output, args.size, args.fps, &chosen.title)?;
+    let (frames, received) = crossbeam_channel::unbounded::<(Buffer, f64)>();
+    let writer = std::thread::spawn(move || -> Result<Box<dyn Encoder>> {
+        for (screen, at) in received {
+            encoder.frame(&screen, at)?;
+        }
+        Ok(encoder)
+    });
+
+    eprintln!("Rendering “{}” to {}...", chosen.title, args.output.display());
+    let mut terminal = Terminal::new(TestBackend::new(args.size.0, args.size.1))?;
+    let frame_time = Duration::from_secs_f64(1.0 / args.fps as f64);
+    let started = Instant::now();
+    let mut finished_at = None;
+    let mut end = 0.0;
+    for count in 1.. {
+        app.update()?;
+        app.draw(&mut terminal)?;
+        end = started.elapsed().as_secs_f64();
+        // Gone only if the encoder failed; joining says why
+        if frames.send((terminal.backend().buffer().clone(), end)).is_err() {
+            break;
+        }
+        if app.chat_mut().replay_finished() {
+            let finished = *finished_at.get_or_insert(end);
+            if end - finished >= args.hold {
+                break;
+            }
+        }
+        tokio::time::sleep_until((started + frame_time * count).into()).await;
+    }
+    drop(frames);
+
+    let encoder = writer.join().map_err(|_| anyhow::anyhow!("The encoder crashed"))??;
+    encoder
+        .finish(end)
+        .with_context(|| format!("Failed to finish {}", args.output.display()))?;
+    eprintln!("Wrote {:.1} seconds to {}", end, args.output.display());
+    Ok(())
+}
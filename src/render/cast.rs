@@ -0,0 +1,174 @@
+//! asciinema v2 casts: a JSON header line, then a `[seconds, "o", text]`
+//! line for each frame with the escape codes that change the screen to
+//! it. `asciinema play` and the web player show them as text, so they
+//! stay small and sharp at any size.
+
+use anyhow::{Context, Result};
+use ratatui::buffer::{Buffer, Cell};
+use ratatui::style::{Color, Modifier};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::Encoder;
+
+#[derive(Serialize)]
+struct Header<'a> {
+    version: u8,
+    width: u16,
+    height: u16,
+    /// Unix seconds
+    timestamp: i64,
+    title: &'a str,
+    env: Env,
+}
+
+#[derive(Serialize)]
+struct Env {
+    #[serde(rename = "TERM")]
+    term: &'static str,
+}
+
+pub(super) struct Cast {
+    path: PathBuf,
+    out: BufWriter<File>,
+    /// The screen as the player shows it so far
+    shown: Buffer,
+    /// When the last frame was, in seconds
+    last_at: f64,
+}
+
+impl Cast {
+    pub fn create(path: &Path, (width, height): (u16, u16), title: &str) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let header = Header {
+            version: 2,
+            width,
+            height,
+            timestamp: chrono::Local::now().timestamp(),
+            title,
+            env: Env { term: "xterm-256color" },
+        };
+        serde_json::to_writer(&mut out, &header)?;
+        out.write_all(b"\n")?;
+        let mut cast = Self {
+            path: path.to_path_buf(),
+            out,
+            shown: Buffer::empty(ratatui::layout::Rect::new(0, 0, width, height)),
+            last_at: 0.0,
+        };
+        // Hide the cursor and start from a clear screen
+        cast.event(0.0, "\x1b[?25l\x1b[0m\x1b[2J")?;
+        Ok(cast)
+    }
+
+    fn event(&mut self, at: f64, text: &str) -> Result<()> {
+        // Milliseconds are plenty, and keep the file smaller
+        let at = (at * 1000.0).round() / 1000.0;
+        serde_json::to_writer(&mut self.out, &(at, "o", text))?;
+        self.out
+            .write_all(b"\n")
+            .with_context(|| format!("Failed to write to {}", self.path.display()))?;
+        self.last_at = at;
+        Ok(())
+    }
+}
+
+impl Encoder for Cast {
+    fn frame(&mut self, screen: &Buffer, at: f64) -> Result<()> {
+        let changes = self.shown.diff(screen);
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut text = String::new();
+        let mut cursor = None;
+        let mut style = None;
+        for (x, y, cell) in changes {
+            if cursor != Some((x, y)) {
+                let _ = write!(text, "\x1b[{};{}H", y + 1, x + 1);
+            }
+            let cell_style = (cell.fg, cell.bg, cell.modifier);
+            if style != Some(cell_style) {
+                text.push_str(&sgr(cell));
+                style = Some(cell_style);
+            }
+            text.push_str(cell.symbol());
+            let width = ratatui::text::Span::raw(cell.symbol()).width().max(1) as u16;
+            cursor = Some((x + width, y));
+        }
+        text.push_str("\x1b[0m");
+        self.shown = screen.clone();
+        self.event(at, &text)
+    }
+
+    fn finish(mut self: Box<Self>, end: f64) -> Result<()> {
+        // An empty event, so the player holds the last frame until `end`
+        if end > self.last_at {
+            self.event(end, "")?;
+        }
+        self.out
+            .flush()
+            .with_context(|| format!("Failed to write to {}", self.path.display()))
+    }
+}
+
+/// The escape code that sets a cell's colors and attributes
+fn sgr(cell: &Cell) -> String {
+    let mut codes = vec!["0".to_string()];
+    for (modifier, code) in [
+        (Modifier::BOLD, "1"),
+        (Modifier::DIM, "2"),
+        (Modifier::ITALIC, "3"),
+        (Modifier::UNDERLINED, "4"),
+        (Modifier::SLOW_BLINK, "5"),
+        (Modifier::RAPID_BLINK, "6"),
+        (Modifier::REVERSED, "7"),
+        (Modifier::HIDDEN, "8"),
+        (Modifier::CROSSED_OUT, "9"),
+    ] {
+        if cell.modifier.contains(modifier) {
+            codes.push(code.to_string());
+        }
+    }
+    codes.extend(color_code(cell.fg, false));
+    codes.extend(color_code(cell.bg, true));
+    format!("\x1b[{}m", codes.join(";"))
+}
+
+/// The SGR parameter for a foreground or background color; None for the
+/// terminal's own, which the reset before it already chose
+fn color_code(color: Color, background: bool) -> Option<String> {
+    let base = if background { 40 } else { 30 };
+    let ansi = |index: u8| {
+        if index < 8 {
+            base + index as u16
+        } else {
+            base + 60 + (index - 8) as u16
+        }
+    };
+    let code = match color {
+        Color::Reset => return None,
+        Color::Rgb(r, g, b) => return Some(format!("{};2;{};{};{}", base + 8, r, g, b)),
+        Color::Indexed(index) => return Some(format!("{};5;{}", base + 8, index)),
+        Color::Black => ansi(0),
+        Color::Red => ansi(1),
+        Color::Green => ansi(2),
+        Color::Yellow => ansi(3),
+        Color::Blue => ansi(4),
+        Color::Magenta => ansi(5),
+        Color::Cyan => ansi(6),
+        Color::Gray => ansi(7),
+        Color::DarkGray => ansi(8),
+        Color::LightRed => ansi(9),
+        Color::LightGreen => ansi(10),
+        Color::LightYellow => ansi(11),
+        Color::LightBlue => ansi(12),
+        Color::LightMagenta => ansi(13),
+        Color::LightCyan => ansi(14),
+        Color::White => ansi(15),
+    };
+    Some(code.to_string())
+}
@@ -0,0 +1,85 @@
+//! Animated GIFs, through the same pure-Rust `gif` crate that decodes
+//! them for the background. Each frame is held until the next one that
+//! differs, so a still screen costs one frame however long it shows.
+
+use anyhow::{Context, Result};
+use ratatui::buffer::Buffer;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use super::raster::Raster;
+use super::Encoder;
+
+/// Quantizer speed, 1 (best) to 30 (fastest). A terminal has few colors
+/// outside the background, so fast loses little.
+const QUANTIZE_SPEED: i32 = 20;
+
+/// Shortest gap between frames, in seconds. Browsers slow down GIFs with
+/// faster frames than this.
+const MIN_DELAY: f64 = 0.02;
+
+pub(super) struct Gif {
+    path: PathBuf,
+    encoder: ::gif::Encoder<BufWriter<File>>,
+    raster: Raster,
+    size: (usize, usize),
+    /// The frame waiting for the next to know how long it shows: its
+    /// screen, its pixels and when it started
+    pending: Option<(Buffer, Vec<u8>, f64)>,
+}
+
+impl Gif {
+    pub fn create(path: &Path, screen: (u16, u16)) -> Result<Self> {
+        let raster = Raster::new()?;
+        let size = raster.image_size(screen);
+        let (width, height) = (u16::try_from(size.0), u16::try_from(size.1));
+        let (Ok(width), Ok(height)) = (width, height) else {
+            anyhow::bail!("{}×{} pixels is too big for a GIF", size.0, size.1);
+        };
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut encoder = ::gif::Encoder::new(BufWriter::new(file), width, height, &[])?;
+        encoder.set_repeat(::gif::Repeat::Infinite)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            encoder,
+            raster,
+            size,
+            pending: None,
+        })
+    }
+
+    /// Write the pending frame, shown until `until` seconds
+    fn write_pending(&mut self, until: f64) -> Result<()> {
+        let Some((_, rgb, at)) = self.pending.take() else {
+            return Ok(());
+        };
+        let (width, height) = (self.size.0 as u16, self.size.1 as u16);
+        let mut frame = ::gif::Frame::from_rgb_speed(width, height, &rgb, QUANTIZE_SPEED);
+        // In hundredths of a second, rounded from the start so the
+        // rounding doesn't add up
+        frame.delay = ((until * 100.0).round() - (at * 100.0).round()).clamp(2.0, u16::MAX as f64) as u16;
+        self.encoder
+            .write_frame(&frame)
+            .with_context(|| format!("Failed to write to {}", self.path.display()))
+    }
+}
+
+impl Encoder for Gif {
+    fn frame(&mut self, screen: &Buffer, at: f64) -> Result<()> {
+        if let Some((ref shown, _, started)) = self.pending {
+            if shown == screen || at - started < MIN_DELAY {
+                return Ok(());
+            }
+        }
+        self.write_pending(at)?;
+        let mut rgb = vec![0; self.size.0 * self.size.1 * 3];
+        self.raster.draw(screen, &mut rgb);
+        self.pending = Some((screen.clone(), rgb, at));
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, end: f64) -> Result<()> {
+        self.write_pending(end)
+    }
+}
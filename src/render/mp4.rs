@@ -0,0 +1,174 @@
+//! MP4 video through ffmpeg: H.264 where the ffmpeg build has an encoder
+//! for it, MPEG-4 Part 2 (which every build has) otherwise
+
+use anyhow::{Context, Result};
+use ffmpeg_next as ff;
+use ff::format::context::Output;
+use ff::format::Pixel;
+use ff::util::frame::video::Video;
+use ff::{codec, encoder, Packet, Rational};
+use ratatui::buffer::Buffer;
+use std::path::{Path, PathBuf};
+
+use super::raster::Raster;
+use super::Encoder;
+
+pub(super) struct Mp4 {
+    path: PathBuf,
+    output: Output,
+    encoder: encoder::video::Encoder,
+    stream: usize,
+    /// Frame times count in frames
+    time_base: Rational,
+    fps: u32,
+    raster: Raster,
+    /// Even, as 4:2:0 video needs: the last row or column of pixels is
+    /// dropped if not
+    size: (usize, usize),
+    /// The screen's pixels, `image_width` wide
+    rgb: Vec<u8>,
+    image_width: usize,
+    /// The screen last encoded and its frame number
+    last: Option<(Buffer, i64)>,
+}
+
+impl Mp4 {
+    pub fn create(path: &Path, screen: (u16, u16), fps: u32) -> Result<Self> {
+        ff::init().context("init ffmpeg")?;
+        let raster = Raster::new()?;
+        let (width, height) = raster.image_size(screen);
+        let size = (width & !1, height & !1);
+
+        let mut output = ff::format::output(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let codec = encoder::find(codec::Id::H264)
+            .or_else(|| encoder::find(codec::Id::MPEG4))
+            .context("This ffmpeg has neither an H.264 nor an MPEG-4 encoder")?;
+        let global_header = output.format().flags().contains(ff::format::Flags::GLOBAL_HEADER);
+        let mut stream = output.add_stream(codec)?;
+        let index = stream.index();
+
+        let time_base = Rational(1, fps as i32);
+        let mut video = codec::context::Context::new_with_codec(codec).encoder().video()?;
+        video.set_width(size.0 as u32);
+        video.set_height(size.1 as u32);
+        video.set_format(Pixel::YUV420P);
+        video.set_time_base(time_base);
+        video.set_frame_rate(Some(Rational(fps as i32, 1)));
+        if global_header {
+            video.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = video.open().context("Failed to start the video encoder")?;
+        stream.set_parameters(&encoder);
+        output
+            .write_header()
+            .with_context(|| format!("Failed to write to {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            output,
+            encoder,
+            stream: index,
+            time_base,
+            fps,
+            rgb: vec![0; width * height * 3],
+            image_width: width,
+            raster,
+            size,
+            last: None,
+        })
+    }
+
+    /// Encode the pixels in `rgb` as frame `number`
+    fn encode(&mut self, number: i64) -> Result<()> {
+        // A new frame each time: the encoder may still hold the last one
+        let mut frame = self.yuv_frame();
+        frame.set_pts(Some(number));
+        self.encoder.send_frame(&frame)?;
+        self.write_packets()
+    }
+
+    fn write_packets(&mut self) -> Result<()> {
+        let time_base = self.output.stream(self.stream).map_or(self.time_base, |stream| stream.time_base());
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(self.stream);
+            packet.rescale_ts(self.time_base, time_base);
+            packet
+                .write_interleaved(&mut self.output)
+                .with_context(|| format!("Failed to write to {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// `rgb` as a frame of Y, U and V planes (BT.601, video range), with a
+    /// U and V for each 2×2 block of pixels
+    fn yuv_frame(&self) -> Video {
+        let (width, height) = self.size;
+        let mut frame = Video::new(Pixel::YUV420P, width as u32, height as u32);
+        let row_bytes = self.image_width * 3;
+        let pixel = |x: usize, y: usize| {
+            let at = y * row_bytes + x * 3;
+            [self.rgb[at] as i32, self.rgb[at + 1] as i32, self.rgb[at + 2] as i32]
+        };
+
+        let stride = frame.stride(0);
+        let luma = frame.data_mut(0);
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b] = pixel(x, y);
+                luma[y * stride + x] = (16 + ((66 * r + 129 * g + 25 * b + 128) >> 8)) as u8;
+            }
+        }
+        for plane in [1, 2] {
+            let stride = frame.stride(plane);
+            let chroma = frame.data_mut(plane);
+            for y in 0..height / 2 {
+                for x in 0..width / 2 {
+                    let mut sum = [0; 3];
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let p = pixel(x * 2 + dx, y * 2 + dy);
+                        sum = [sum[0] + p[0], sum[1] + p[1], sum[2] + p[2]];
+                    }
+                    let [r, g, b] = sum.map(|channel| channel / 4);
+                    let value = if plane == 1 {
+                        128 + ((-38 * r - 74 * g + 112 * b + 128) >> 8)
+                    } else {
+                        128 + ((112 * r - 94 * g - 18 * b + 128) >> 8)
+                    };
+                    chroma[y * stride + x] = value as u8;
+                }
+            }
+        }
+        frame
+    }
+}
+
+impl Encoder for Mp4 {
+    fn frame(&mut self, screen: &Buffer, at: f64) -> Result<()> {
+        let number = (at * self.fps as f64).round() as i64;
+        if let Some((ref shown, last)) = self.last {
+            // Frames only need encoding when something changed; the
+            // player holds the one before until then
+            if number <= last || shown == screen {
+                return Ok(());
+            }
+        }
+        self.raster.draw(screen, &mut self.rgb);
+        self.encode(number)?;
+        self.last = Some((screen.clone(), number));
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, end: f64) -> Result<()> {
+        // The last frame again at the end, or the video stops when it starts
+        let number = (end * self.fps as f64).round() as i64;
+        if self.last.as_ref().is_some_and(|(_, last)| number > *last) {
+            self.encode(number)?;
+        }
+        self.encoder.send_eof()?;
+        self.write_packets()?;
+        self.output
+            .write_trailer()
+            .with_context(|| format!("Failed to write to {}", self.path.display()))
+    }
+}
@@ -0,0 +1,367 @@
+//! The screen as pixels, for the video formats: each cell's background,
+//! then its character in the Hack font (emoji from Noto Emoji). Block,
+//! braille and line-drawing characters are drawn as shapes instead, so
+//! they meet their neighbours the way they do in a terminal.
+
+use anyhow::{anyhow, Result};
+use fontdue::{Font, FontSettings, Metrics};
+use ratatui::buffer::{Buffer, Cell};
+use ratatui::style::{Color, Modifier};
+use std::collections::HashMap;
+
+/// Font size in pixels; cells come out about 10×19
+const FONT_PX: f32 = 16.0;
+
+/// Colors the terminal would use for `Color::Reset`
+const DEFAULT_FG: [u8; 3] = [0xe5, 0xe5, 0xe5];
+const DEFAULT_BG: [u8; 3] = [0x0c, 0x0c, 0x0c];
+
+/// The 16 ANSI colors, as xterm draws them
+const ANSI: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xcd, 0x00, 0x00],
+    [0x00, 0xcd, 0x00],
+    [0xcd, 0xcd, 0x00],
+    [0x00, 0x00, 0xee],
+    [0xcd, 0x00, 0xcd],
+    [0x00, 0xcd, 0xcd],
+    [0xe5, 0xe5, 0xe5],
+    [0x7f, 0x7f, 0x7f],
+    [0xff, 0x00, 0x00],
+    [0x00, 0xff, 0x00],
+    [0xff, 0xff, 0x00],
+    [0x5c, 0x5c, 0xff],
+    [0xff, 0x00, 0xff],
+    [0x00, 0xff, 0xff],
+    [0xff, 0xff, 0xff],
+];
+
+/// A rasterized character: where it sits from the pen, and how much of
+/// each pixel it covers
+struct Glyph {
+    metrics: Metrics,
+    coverage: Vec<u8>,
+}
+
+pub(super) struct Raster {
+    /// Hack, then Noto Emoji for what Hack lacks
+    fonts: [Font; 2],
+    cell_width: usize,
+    cell_height: usize,
+    /// Pixels from a cell's top to the text baseline
+    baseline: usize,
+    /// None for characters neither font has
+    glyphs: HashMap<char, Option<Glyph>>,
+}
+
+impl Raster {
+    pub fn new() -> Result<Self> {
+        let load = |bytes: &'static [u8]| Font::from_bytes(bytes, FontSettings::default()).map_err(|e| anyhow!(e));
+        let fonts = [
+            load(epaint_default_fonts::HACK_REGULAR)?,
+            load(epaint_default_fonts::NOTO_EMOJI_REGULAR)?,
+        ];
+        let line = fonts[0]
+            .horizontal_line_metrics(FONT_PX)
+            .ok_or_else(|| anyhow!("The font has no line metrics"))?;
+        Ok(Self {
+            cell_width: fonts[0].metrics('M', FONT_PX).advance_width.round() as usize,
+            cell_height: line.new_line_size.ceil() as usize,
+            baseline: line.ascent.round() as usize,
+            fonts,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    /// Width and height in pixels of a screen `cols` by `rows` cells
+    pub fn image_size(&self, (cols, rows): (u16, u16)) -> (usize, usize) {
+        (cols as usize * self.cell_width, rows as usize * self.cell_height)
+    }
+
+    /// Draw `screen` into `rgb`, three bytes a pixel and as big as
+    /// `image_size` says
+    pub fn draw(&mut self, screen: &Buffer, rgb: &mut [u8]) {
+        let width = screen.area.width as usize * self.cell_width;
+        let mut canvas = Canvas { rgb, width };
+
+        // Backgrounds first: a wide character's glyph spills into the
+        // next cell, which would otherwise paint over it
+        for (index, cell) in screen.content.iter().enumerate() {
+            let (x, y) = self.cell_origin(screen, index);
+            let (_, bg) = colors(cell);
+            canvas.fill(x, y, self.cell_width, self.cell_height, bg);
+        }
+        for (index, cell) in screen.content.iter().enumerate() {
+            if cell.skip || cell.modifier.contains(Modifier::HIDDEN) {
+                continue;
+            }
+            let Some(ch) = cell.symbol().chars().next().filter(|ch| *ch != ' ') else {
+                continue;
+            };
+            let (x, y) = self.cell_origin(screen, index);
+            let (fg, bg) = colors(cell);
+            if !self.draw_shape(&mut canvas, ch, x, y, fg, bg) {
+                self.draw_glyph(&mut canvas, ch, x, y, fg, cell.modifier.contains(Modifier::BOLD));
+            }
+            let thickness = self.line_thickness();
+            if cell.modifier.contains(Modifier::UNDERLINED) {
+                canvas.fill(x, y + self.baseline + 2, self.cell_width, thickness, fg);
+            }
+            if cell.modifier.contains(Modifier::CROSSED_OUT) {
+                canvas.fill(x, y + self.baseline * 2 / 3, self.cell_width, thickness, fg);
+            }
+        }
+    }
+
+    fn cell_origin(&self, screen: &Buffer, index: usize) -> (usize, usize) {
+        let columns = screen.area.width as usize;
+        ((index % columns) * self.cell_width, (index / columns) * self.cell_height)
+    }
+
+    fn line_thickness(&self) -> usize {
+        (self.cell_width / 8).max(1)
+    }
+
+    fn draw_glyph(&mut self, canvas: &mut Canvas, ch: char, x: usize, y: usize, fg: [u8; 3], bold: bool) {
+        let fonts = &self.fonts;
+        let glyph = self.glyphs.entry(ch).or_insert_with(|| {
+            let font = fonts.iter().find(|font| font.lookup_glyph_index(ch) != 0)?;
+            let (metrics, coverage) = font.rasterize(ch, FONT_PX);
+            Some(Glyph { metrics, coverage })
+        });
+        let Some(glyph) = glyph else {
+            return;
+        };
+        let metrics = glyph.metrics;
+        let left = x as i64 + metrics.xmin as i64;
+        let top = y as i64 + self.baseline as i64 - (metrics.ymin as i64 + metrics.height as i64);
+        // Bold is the glyph again a pixel to the right
+        for offset in 0..=i64::from(bold) {
+            for row in 0..metrics.height {
+                for column in 0..metrics.width {
+                    let coverage = glyph.coverage[row * metrics.width + column];
+                    if coverage > 0 {
+                        canvas.blend(left + column as i64 + offset, top + row as i64, fg, coverage);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draw the characters that should fill their cell exactly. Returns
+    /// false for any other character.
+    fn draw_shape(&self, canvas: &mut Canvas, ch: char, x: usize, y: usize, fg: [u8; 3], bg: [u8; 3]) -> bool {
+        let (w, h) = (self.cell_width, self.cell_height);
+        match ch {
+            // Upper half, lower eighths to full
+            '▀' => canvas.fill(x, y, w, h / 2, fg),
+            '▁'..='█' => {
+                let eighths = ch as usize - '▀' as usize;
+                let height = h * eighths / 8;
+                canvas.fill(x, y + h - height, w, height, fg);
+            }
+            // Left seven eighths down to one
+            '▉'..='▏' => {
+                let eighths = 8 - (ch as usize - '█' as usize);
+                canvas.fill(x, y, w * eighths / 8, h, fg);
+            }
+            '▐' => canvas.fill(x + w / 2, y, w - w / 2, h, fg),
+            '░' | '▒' | '▓' => {
+                let share = (ch as u32 - '░' as u32 + 1) * 64;
+                canvas.fill(x, y, w, h, mix(bg, fg, share as u8));
+            }
+            '▔' => canvas.fill(x, y, w, h / 8, fg),
+            '▕' => canvas.fill(x + w - w / 8, y, w / 8, h, fg),
+            '▖'..='▟' => {
+                // Upper left, upper right, lower left, lower right
+                let quadrants: u8 = match ch {
+                    '▖' => 0b0010,
+                    '▗' => 0b0001,
+                    '▘' => 0b1000,
+                    '▙' => 0b1011,
+                    '▚' => 0b1001,
+                    '▛' => 0b1110,
+                    '▜' => 0b1101,
+                    '▝' => 0b0100,
+                    '▞' => 0b0110,
+                    _ => 0b0111,
+                };
+                let (half_w, half_h) = (w / 2, h / 2);
+                for (bit, (qx, qy, qw, qh)) in [
+                    (0b1000, (x, y, half_w, half_h)),
+                    (0b0100, (x + half_w, y, w - half_w, half_h)),
+                    (0b0010, (x, y + half_h, half_w, h - half_h)),
+                    (0b0001, (x + half_w, y + half_h, w - half_w, h - half_h)),
+                ] {
+                    if quadrants & bit != 0 {
+                        canvas.fill(qx, qy, qw, qh, fg);
+                    }
+                }
+            }
+            '\u{2800}'..='\u{28ff}' => {
+                // Dots 1-3 down the left, 4-6 down the right, then 7 and 8
+                let bits = ch as u32 - 0x2800;
+                let dot = (w / 4).max(1);
+                for (bit, column, row) in [
+                    (0x01, 0, 0),
+                    (0x02, 0, 1),
+                    (0x04, 0, 2),
+                    (0x08, 1, 0),
+                    (0x10, 1, 1),
+                    (0x20, 1, 2),
+                    (0x40, 0, 3),
+                    (0x80, 1, 3),
+                ] {
+                    if bits & bit != 0 {
+                        let dx = x + w * (1 + 2 * column) / 4 - dot / 2;
+                        let dy = y + h * (1 + 2 * row) / 8 - dot / 2;
+                        canvas.fill(dx, dy, dot, dot, fg);
+                    }
+                }
+            }
+            _ => {
+                let Some([up, right, down, left]) = box_arms(ch) else {
+                    return false;
+                };
+                let (cx, cy) = (x + w / 2, y + h / 2);
+                for (arm, weight) in [up, right, down, left].into_iter().enumerate() {
+                    if weight == 0 {
+                        continue;
+                    }
+                    // Each arm runs from the cell's edge across the middle,
+                    // so corners close up
+                    let t = self.line_thickness() * weight as usize;
+                    let (mx, my) = (cx - t / 2, cy - t / 2);
+                    match arm {
+                        0 => canvas.fill(mx, y, t, my + t - y, fg),
+                        1 => canvas.fill(mx, my, x + w - mx, t, fg),
+                        2 => canvas.fill(mx, my, t, y + h - my, fg),
+                        _ => canvas.fill(x, my, mx + t - x, t, fg),
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+/// How heavy a line-drawing character's arms are, up, right, down and
+/// left: 0 for none, 1 light, 2 heavy. Double lines draw as heavy and
+/// rounded corners as square.
+fn box_arms(ch: char) -> Option<[u8; 4]> {
+    Some(match ch {
+        '─' => [0, 1, 0, 1],
+        '━' | '═' => [0, 2, 0, 2],
+        '│' => [1, 0, 1, 0],
+        '┃' | '║' => [2, 0, 2, 0],
+        '┌' | '╭' => [0, 1, 1, 0],
+        '┐' | '╮' => [0, 0, 1, 1],
+        '└' | '╰' => [1, 1, 0, 0],
+        '┘' | '╯' => [1, 0, 0, 1],
+        '├' => [1, 1, 1, 0],
+        '┤' => [1, 0, 1, 1],
+        '┬' => [0, 1, 1, 1],
+        '┴' => [1, 1, 0, 1],
+        '┼' => [1, 1, 1, 1],
+        '┏' | '╔' => [0, 2, 2, 0],
+        '┓' | '╗' => [0, 0, 2, 2],
+        '┗' | '╚' => [2, 2, 0, 0],
+        '┛' | '╝' => [2, 0, 0, 2],
+        '╴' => [0, 0, 0, 1],
+        '╵' => [1, 0, 0, 0],
+        '╶' => [0, 1, 0, 0],
+        '╷' => [0, 0, 1, 0],
+        _ => return None,
+    })
+}
+
+/// Foreground and background as drawn, after reversing and dimming
+fn colors(cell: &Cell) -> ([u8; 3], [u8; 3]) {
+    let (mut fg, mut bg) = (rgb(cell.fg, DEFAULT_FG), rgb(cell.bg, DEFAULT_BG));
+    if cell.modifier.contains(Modifier::REVERSED) {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if cell.modifier.contains(Modifier::DIM) {
+        fg = mix(bg, fg, 128);
+    }
+    (fg, bg)
+}
+
+fn rgb(color: Color, default: [u8; 3]) -> [u8; 3] {
+    match color {
+        Color::Reset => default,
+        Color::Rgb(r, g, b) => [r, g, b],
+        Color::Indexed(index) => indexed(index),
+        Color::Black => ANSI[0],
+        Color::Red => ANSI[1],
+        Color::Green => ANSI[2],
+        Color::Yellow => ANSI[3],
+        Color::Blue => ANSI[4],
+        Color::Magenta => ANSI[5],
+        Color::Cyan => ANSI[6],
+        Color::Gray => ANSI[7],
+        Color::DarkGray => ANSI[8],
+        Color::LightRed => ANSI[9],
+        Color::LightGreen => ANSI[10],
+        Color::LightYellow => ANSI[11],
+        Color::LightBlue => ANSI[12],
+        Color::LightMagenta => ANSI[13],
+        Color::LightCyan => ANSI[14],
+        Color::White => ANSI[15],
+    }
+}
+
+/// One of the 256 xterm colors: the 16 ANSI ones, a 6×6×6 cube, then a
+/// gray ramp
+fn indexed(index: u8) -> [u8; 3] {
+    match index {
+        0..=15 => ANSI[index as usize],
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let n = index - 16;
+            [level(n / 36), level(n / 6 % 6), level(n % 6)]
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            [gray; 3]
+        }
+    }
+}
+
+/// `from` moved `amount`/255 of the way to `to`
+fn mix(from: [u8; 3], to: [u8; 3], amount: u8) -> [u8; 3] {
+    let amount = amount as u32;
+    let channel = |a: u8, b: u8| ((a as u32 * (255 - amount) + b as u32 * amount) / 255) as u8;
+    [channel(from[0], to[0]), channel(from[1], to[1]), channel(from[2], to[2])]
+}
+
+/// An RGB image being drawn into, clipping at its edges
+struct Canvas<'a> {
+    rgb: &'a mut [u8],
+    width: usize,
+}
+
+impl Canvas<'_> {
+    fn height(&self) -> usize {
+        self.rgb.len() / 3 / self.width
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        let (right, bottom) = ((x + width).min(self.width), (y + height).min(self.height()));
+        for row in y..bottom {
+            for column in x..right {
+                let at = (row * self.width + column) * 3;
+                self.rgb[at..at + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    fn blend(&mut self, x: i64, y: i64, color: [u8; 3], coverage: u8) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height() {
+            return;
+        }
+        let at = (y as usize * self.width + x as usize) * 3;
+        let under = [self.rgb[at], self.rgb[at + 1], self.rgb[at + 2]];
+        self.rgb[at..at + 3].copy_from_slice(&mix(under, color, coverage));
+    }
+}
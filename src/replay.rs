@@ -0,0 +1,195 @@
+//! `/replay`: a conversation played back message by message, each typed
+//! out as if it were being written, for watching a session again or
+//! recording a demo of it
+
+use std::time::Instant;
+
+use crate::conversation::{ChatMessage, MessageRole};
+use crate::providers::AIProvider;
+
+/// Seconds before each message after the first starts
+const PAUSE_SECS: f64 = 0.8;
+
+/// Seconds before the first message starts
+const LEAD_IN_SECS: f64 = 0.3;
+
+/// Characters a second: typing speed for the user's messages, streaming
+/// speed for replies
+const USER_CHARS_PER_SEC: f64 = 30.0;
+const REPLY_CHARS_PER_SEC: f64 = 180.0;
+
+/// Longest any one message takes to type out, in seconds, so a pasted
+/// log doesn't take minutes
+const MAX_USER_SECS: f64 = 4.0;
+const MAX_REPLY_SECS: f64 = 10.0;
+
+/// Slowest and fastest speeds + and - reach
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 8.0;
+
+pub struct Replay {
+    provider: AIProvider,
+    title: String,
+    messages: Vec<ChatMessage>,
+    /// Replay time so far, in seconds at normal speed
+    clock: f64,
+    last_tick: Instant,
+    speed: f64,
+    paused: bool,
+    /// Show each message whole when its turn comes instead of typing it
+    /// out, for accessible mode
+    whole: bool,
+    /// The message being typed (or the last one, once finished) and how
+    /// many bytes of it show
+    index: usize,
+    shown: usize,
+}
+
+impl Replay {
+    /// None if there's nothing to replay
+    pub fn new(provider: AIProvider, title: String, messages: Vec<ChatMessage>) -> Option<Self> {
+        let messages: Vec<ChatMessage> = messages.into_iter().filter(|m| !m.is_system).collect();
+        if messages.is_empty() {
+            return None;
+        }
+        Some(Self {
+            provider,
+            title,
+            messages,
+            clock: 0.0,
+            last_tick: Instant::now(),
+            speed: 1.0,
+            paused: false,
+            whole: false,
+            index: 0,
+            shown: 0,
+        })
+    }
+
+    pub fn provider(&self) -> &AIProvider {
+        &self.provider
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The messages started so far, and how many bytes of the last one
+    /// show
+    pub fn visible(&self) -> (&[ChatMessage], usize) {
+        (&self.messages[..=self.index], self.shown)
+    }
+
+    /// Which message is under way, counting from 1, and how many there are
+    pub fn progress(&self) -> (usize, usize) {
+        (self.index + 1, self.messages.len())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.index == self.messages.len() - 1 && self.shown == self.messages[self.index].content.len()
+    }
+
+    /// Move the clock on by the time since last asked. Returns whether
+    /// more shows.
+    pub fn advance(&mut self) -> bool {
+        let now = Instant::now();
+        if !self.paused {
+            self.clock += now.duration_since(self.last_tick).as_secs_f64() * self.speed;
+        }
+        self.last_tick = now;
+        self.settle()
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.advance();
+        self.paused = !self.paused;
+    }
+
+    /// Twice as fast, or half as fast
+    pub fn faster(&mut self, faster: bool) {
+        self.advance();
+        let speed = if faster { self.speed * 2.0 } else { self.speed / 2.0 };
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// Play at `speed` times normal, within what + and - reach
+    pub fn set_speed(&mut self, speed: f64) {
+        self.advance();
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// Show messages whole rather than typed out; they still wait as long
+    /// as typing them would, to give time to read the one before
+    pub fn whole_messages(&mut self) {
+        self.whole = true;
+        self.settle();
+    }
+
+    /// Finish the message being typed, or the next one when between
+    /// messages
+    pub fn skip(&mut self) {
+        self.advance();
+        let mut end = 0.0;
+        for (index, message) in self.messages.iter().enumerate() {
+            let (pause, typing) = timing(index, message);
+            end += pause + typing;
+            if self.clock < end {
+                self.clock = end;
+                break;
+            }
+        }
+        self.settle();
+    }
+
+    /// Work out what shows at the clock's time. Returns whether it changed.
+    fn settle(&mut self) -> bool {
+        let before = (self.index, self.shown);
+        let mut start = 0.0;
+        for (index, message) in self.messages.iter().enumerate() {
+            let (pause, typing) = timing(index, message);
+            // Summed as `skip` sums them, so skipping to an end lands on it
+            let end = start + (pause + typing);
+            if self.clock < end || index == self.messages.len() - 1 {
+                let typed = self.clock - start - pause;
+                if typed < 0.0 && index > 0 {
+                    // Between messages: the one before stays whole
+                    self.index = index - 1;
+                    self.shown = self.messages[index - 1].content.len();
+                } else {
+                    let fraction = if self.clock >= end || self.whole { 1.0 } else { (typed / typing).max(0.0) };
+                    let chars = (message.content.chars().count() as f64 * fraction) as usize;
+                    self.index = index;
+                    self.shown = byte_len(&message.content, chars);
+                }
+                break;
+            }
+            start = end;
+        }
+        (self.index, self.shown) != before
+    }
+}
+
+/// The pause before a message and how long it takes to type, in seconds
+fn timing(index: usize, message: &ChatMessage) -> (f64, f64) {
+    let pause = if index == 0 { LEAD_IN_SECS } else { PAUSE_SECS };
+    let chars = message.content.chars().count() as f64;
+    let (rate, longest) = match message.role {
+        MessageRole::User => (USER_CHARS_PER_SEC, MAX_USER_SECS),
+        MessageRole::Assistant => (REPLY_CHARS_PER_SEC, MAX_REPLY_SECS),
+    };
+    // Never zero, so an empty message still takes a moment
+    (pause, (chars / rate).clamp(0.05, longest))
+}
+
+/// Bytes in the first `chars` characters of `text`
+fn byte_len(text: &str, chars: usize) -> usize {
+    text.char_indices().nth(chars).map_or(text.len(), |(at, _)| at)
+}
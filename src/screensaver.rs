@@ -0,0 +1,93 @@
+use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+use std::time::{Duration, Instant};
+
+/// How long the chat UI takes to fade to black
+const FADE_OUT: Duration = Duration::from_millis(1500);
+
+/// How long the video then takes to come up to full brightness
+const FADE_IN: Duration = Duration::from_millis(1500);
+
+/// What the screen should show right now
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Normal chat UI
+    Off,
+    /// Chat UI dimmed to this brightness (1.0 → 0.0)
+    FadingOut(f32),
+    /// Video alone at this brightness (0.0 → 1.0)
+    Showing(f32),
+}
+
+/// Takes over the screen with the background video after a period without
+/// keypresses; any key brings the chat back
+pub struct Screensaver {
+    /// None disables the screensaver
+    timeout: Option<Duration>,
+    last_input: Instant,
+}
+
+impl Screensaver {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            last_input: Instant::now(),
+        }
+    }
+
+    /// Start counting toward a new timeout (None disables)
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        self.last_input = Instant::now();
+    }
+
+    /// Record a keypress. Returns true if it woke the screensaver, in which
+    /// case the key shouldn't reach the chat.
+    pub fn wake(&mut self) -> bool {
+        let was_active = self.phase() != Phase::Off;
+        self.last_input = Instant::now();
+        was_active
+    }
+
+    pub fn phase(&self) -> Phase {
+        let Some(timeout) = self.timeout else {
+            return Phase::Off;
+        };
+        let idle = self.last_input.elapsed();
+        if idle < timeout {
+            return Phase::Off;
+        }
+
+        let since = idle - timeout;
+        if since < FADE_OUT {
+            Phase::FadingOut(1.0 - since.as_secs_f32() / FADE_OUT.as_secs_f32())
+        } else {
+            let fade_in = (since - FADE_OUT).as_secs_f32() / FADE_IN.as_secs_f32();
+            Phase::Showing(fade_in.min(1.0))
+        }
+    }
+}
+
+/// Scale every cell's colors in `area` toward black
+pub fn dim(buf: &mut Buffer, area: Rect, brightness: f32) {
+    let scale = |c: Color| match c {
+        Color::Rgb(r, g, b) => Color::Rgb(
+            (r as f32 * brightness) as u8,
+            (g as f32 * brightness) as u8,
+            (b as f32 * brightness) as u8,
+        ),
+        // Named colors have no RGB value to scale; drop them halfway through
+        other if brightness > 0.5 => other,
+        _ => Color::Black,
+    };
+
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            if let Some(cell) = buf.cell_mut((x, y)) {
+                cell.fg = scale(cell.fg);
+                if cell.bg != Color::Reset {
+                    cell.bg = scale(cell.bg);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Full-text search across every conversation, in the UI and as
+//! `animated-cli search`
+
+use anyhow::Result;
+use clap::Args;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::providers::AIProvider;
+use crate::store::{Database, SearchHit, MATCH_END, MATCH_START};
+
+/// Results shown in the search screen
+const UI_LIMIT: usize = 100;
+
+const HINTS: &str = "Type to search (tag:name, is:starred to filter) | ↑/↓ Select | Enter Open | Esc Close";
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// Words to look for; all must appear, the last may be a prefix.
+    /// `tag:name` keeps to tagged conversations, `is:starred` to starred
+    /// messages.
+    #[arg(required = true)]
+    query: Vec<String>,
+
+    /// Most results to show
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+}
+
+/// Run `animated-cli search`
+pub fn run(args: SearchArgs, db_path: &Path) -> Result<()> {
+    let db = Database::open(db_path)?;
+    let hits = db.search(&args.query.join(" "), args.limit)?;
+    if hits.is_empty() {
+        println!("No matches");
+        return Ok(());
+    }
+
+    // Bold the matches on a terminal, leave plain text for pipes
+    let (bold, reset) = if std::io::stdout().is_terminal() { ("\x1b[1m", "\x1b[0m") } else { ("", "") };
+    for hit in hits {
+        let c = &hit.conversation;
+        let snippet = hit
+            .snippet
+            .replace(['\n', '\r'], " ")
+            .replace(MATCH_START, bold)
+            .replace(MATCH_END, reset);
+        println!(
+            "#{} {} · {} ({})",
+            c.id,
+            provider_name(&c.provider),
+            if c.title.is_empty() { "Untitled" } else { &c.title },
+            c.created_on
+        );
+        println!("  {}: {}\n", speaker(&hit), snippet);
+    }
+    Ok(())
+}
+
+fn provider_name(db_name: &str) -> &str {
+    AIProvider::parse(db_name).map_or(db_name, |p| p.name())
+}
+
+/// "  #rust #work", or nothing when untagged
+pub fn tag_list(tags: &[String]) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+    let tags: Vec<String> = tags.iter().map(|tag| format!("#{}", tag)).collect();
+    format!("  {}", tags.join(" "))
+}
+
+fn speaker(hit: &SearchHit) -> &str {
+    if hit.role == "user" {
+        "You"
+    } else {
+        provider_name(&hit.conversation.provider)
+    }
+}
+
+/// Search screen opened with Ctrl+F or `/search`
+pub struct SearchView {
+    query: String,
+    hits: Vec<SearchHit>,
+    state: ListState,
+    error: Option<String>,
+}
+
+impl SearchView {
+    pub fn new(query: String, db: &Database) -> Self {
+        let mut view = Self {
+            query,
+            hits: Vec::new(),
+            state: ListState::default(),
+            error: None,
+        };
+        view.refresh(db);
+        view
+    }
+
+    fn refresh(&mut self, db: &Database) {
+        match db.search(&self.query, UI_LIMIT) {
+            Ok(hits) => {
+                self.hits = hits;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("Error: {}", e)),
+        }
+        self.state.select((!self.hits.is_empty()).then_some(0));
+    }
+
+    /// Returns the hit to open when Enter is pressed
+    pub fn handle_key(&mut self, key: KeyEvent, db: &Database) -> Option<SearchHit> {
+        match key.code {
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.refresh(db);
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.refresh(db);
+            }
+            KeyCode::Up => self.state.select_previous(),
+            KeyCode::Down => {
+                let last = self.hits.len().saturating_sub(1);
+                let next = self.state.selected().map_or(0, |i| (i + 1).min(last));
+                self.state.select((!self.hits.is_empty()).then_some(next));
+            }
+            KeyCode::Enter => return self.state.selected().and_then(|i| self.hits.get(i)).cloned(),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&mut self, frame: &mut Frame, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(3), Constraint::Length(1)])
+            .split(area);
+        frame.render_widget(Clear, area);
+
+        let input = Paragraph::new(format!("🔍 {}_", self.query))
+            .style(Style::default().fg(Color::Cyan))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Rounded)
+                    .title("Search all conversations")
+                    .border_style(Style::default().fg(Color::Cyan)),
+            );
+        frame.render_widget(input, chunks[0]);
+
+        let items: Vec<ListItem> = self
+            .hits
+            .iter()
+            .map(|hit| {
+                let c = &hit.conversation;
+                let color = AIProvider::parse(&c.provider).map_or(Color::White, |p| p.color());
+                let header = Line::from(vec![
+                    Span::styled(format!("{} ", provider_name(&c.provider)), Style::default().fg(color)),
+                    Span::styled(
+                        if c.title.is_empty() { "Untitled" } else { &c.title }.to_string(),
+                        Style::default().bold(),
+                    ),
+                    Span::styled(format!("  {}", c.created_on), Style::default().fg(Color::DarkGray)),
+                    Span::styled(tag_list(&c.tags), Style::default().fg(Color::Magenta)),
+                ]);
+
+                let mut snippet = vec![Span::styled(
+                    format!("  {}: ", speaker(hit)),
+                    Style::default().fg(Color::DarkGray),
+                )];
+                snippet.extend(highlight(&hit.snippet));
+                ListItem::new(vec![header, Line::from(snippet), Line::from("")])
+            })
+            .collect();
+
+        let title = match (&self.error, self.hits.len()) {
+            (Some(error), _) => error.clone(),
+            (None, 0) if self.query.trim().is_empty() => "Results".to_string(),
+            (None, 0) => "No matches".to_string(),
+            (None, n) if n >= UI_LIMIT => format!("Top {} results", n),
+            (None, n) => format!("{} results", n),
+        };
+        let list = List::new(items)
+            .highlight_style(Style::default().bg(Color::DarkGray))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Rounded)
+                    .title(title)
+                    .border_style(Style::default().fg(Color::White)),
+            );
+        frame.render_stateful_widget(list, chunks[1], &mut self.state);
+
+        let footer = Paragraph::new(HINTS)
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        frame.render_widget(footer, chunks[2]);
+    }
+}
+
+/// Snippet spans with the matched terms picked out
+fn highlight(snippet: &str) -> Vec<Span<'static>> {
+    let snippet = snippet.replace(['\n', '\r'], " ");
+    let mut spans = Vec::new();
+    for (i, part) in snippet.split(MATCH_START).enumerate() {
+        // Every part after the first starts with a match
+        let (matched, rest) = match part.split_once(MATCH_END) {
+            Some((matched, rest)) if i > 0 => (matched, rest),
+            _ => ("", part),
+        };
+        if !matched.is_empty() {
+            spans.push(Span::styled(matched.to_string(), Style::default().fg(Color::Yellow).bold()));
+        }
+        spans.push(Span::styled(rest.to_string(), Style::default().fg(Color::White)));
+    }
+    spans
+}
@@ -0,0 +1,387 @@
+//! `animated-cli serve`: an OpenAI-compatible `/v1/chat/completions` endpoint
+//! on localhost that answers with whichever provider the request names,
+//! using this setup's keys. Every exchange is saved like a chat.
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Local;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::providers::{AIClient, AIProvider, ChatSettings, Message, Reply};
+use crate::conversation::auto_title;
+use crate::config::Config;
+use crate::ratelimit::RateLimits;
+use crate::store::Database;
+
+/// Tag on every conversation saved by the server, so they can be found
+/// (`tag:api`) or filtered out
+const SAVED_TAG: &str = "api";
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Port to listen on
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Address to listen on; anything but localhost needs --token
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Provider for requests whose model doesn't name one [config: provider, default: claude]
+    #[arg(long)]
+    provider: Option<String>,
+
+    /// Require `Authorization: Bearer <token>` on every request
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Don't save exchanges to the conversation database
+    #[arg(long)]
+    no_save: bool,
+}
+
+struct Server {
+    config: Config,
+    default: AIProvider,
+    token: Option<String>,
+    /// `[rate_limits]`, which requests wait in line for
+    rate_limits: RateLimits,
+    /// None when saving is off or the database wouldn't open
+    db: Option<Mutex<Database>>,
+}
+
+/// Run `animated-cli serve` until interrupted
+pub async fn run(args: ServeArgs, db_path: &Path, config: &Config) -> Result<()> {
+    let name = args.provider.as_deref().or(config.provider.as_deref()).unwrap_or("claude");
+    let default = AIProvider::parse(name).ok_or_else(|| anyhow!("Unknown provider: {}", name))?;
+
+    let db = if args.no_save {
+        None
+    } else {
+        match Database::open(db_path) {
+            Ok(db) => Some(Mutex::new(db)),
+            Err(e) => {
+                eprintln!("Conversation history unavailable, so nothing will be saved: {:#}", e);
+                None
+            }
+        }
+    };
+
+    let server = Arc::new(Server {
+        config: config.clone(),
+        default,
+        token: args.token,
+        rate_limits: RateLimits::new(&config.rate_limits),
+        db,
+    });
+    let app = Router::new()
+        .route("/v1/chat/completions", post(completions))
+        .route("/v1/models", get(models))
+        .with_state(server.clone());
+
+    let listener = tokio::net::TcpListener::bind((args.host.as_str(), args.port))
+        .await
+        .with_context(|| format!("Failed to listen on {}:{}", args.host, args.port))?;
+    // Anyone who can reach it could spend this setup's keys
+    let addr = listener.local_addr()?;
+    if !addr.ip().is_loopback() && server.token.is_none() {
+        bail!("Not serving on {} without --token: anyone on the network could use your API keys", addr);
+    }
+    println!(
+        "Serving http://{}/v1 (default provider {}{}); Ctrl+C stops",
+        addr,
+        server.default.name(),
+        if server.token.is_some() { ", token required" } else { "" }
+    );
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("Server failed")?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct CompletionRequest {
+    #[serde(default)]
+    model: Option<String>,
+    messages: Vec<RequestMessage>,
+    #[serde(default)]
+    temperature: Option<f64>,
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct RequestMessage {
+    role: String,
+    #[serde(default)]
+    content: Option<Content>,
+}
+
+/// A plain string, or a list of parts of which only text is used
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Deserialize)]
+struct ContentPart {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+impl Content {
+    fn into_text(self) -> String {
+        match self {
+            Content::Text(text) => text,
+            Content::Parts(parts) => parts.into_iter().filter_map(|p| p.text).collect::<Vec<_>>().join("\n"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Completion {
+    id: String,
+    object: &'static str,
+    created: i64,
+    model: String,
+    choices: Vec<Choice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Serialize)]
+struct Choice {
+    index: u32,
+    /// `message` in a completion, `delta` in a stream chunk
+    #[serde(flatten)]
+    body: ChoiceBody,
+    finish_reason: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ChoiceBody {
+    Message(Message),
+    Delta(Message),
+}
+
+#[derive(Serialize)]
+struct CompletionUsage {
+    prompt_tokens: i64,
+    completion_tokens: i64,
+    total_tokens: i64,
+}
+
+#[derive(Serialize)]
+struct ModelList {
+    object: &'static str,
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Serialize)]
+struct ModelEntry {
+    id: String,
+    object: &'static str,
+    owned_by: &'static str,
+}
+
+/// An error in OpenAI's shape, so clients show the message
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail {
+    message: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let kind = match self.status {
+            StatusCode::UNAUTHORIZED => "authentication_error",
+            status if status.is_client_error() => "invalid_request_error",
+            _ => "api_error",
+        };
+        let body = ErrorBody {
+            error: ErrorDetail { message: self.message, kind },
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl Server {
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), ApiError> {
+        let Some(ref token) = self.token else {
+            return Ok(());
+        };
+        let given = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if given == Some(token.as_str()) {
+            Ok(())
+        } else {
+            Err(ApiError::new(StatusCode::UNAUTHORIZED, "Missing or wrong bearer token"))
+        }
+    }
+
+    /// Save the exchange as a new conversation tagged `api`. Returns its id.
+    fn save(&self, provider: &AIProvider, settings: &ChatSettings, messages: &[Message], reply: &Reply) -> Result<Option<i64>> {
+        let Some(ref db) = self.db else {
+            return Ok(None);
+        };
+        let mut db = db.lock().map_err(|_| anyhow!("Database lock poisoned"))?;
+        let first = messages.iter().find(|m| m.role == "user").map_or("", |m| m.content.as_str());
+        let id = db.create_conversation(provider.db_name(), &auto_title(first))?;
+        db.set_tags(id, &[SAVED_TAG.to_string()])?;
+        if *settings != ChatSettings::default() {
+            db.set_conversation_settings(id, settings)?;
+        }
+        for m in messages {
+            db.save_message(id, &m.role, &m.content, None)?;
+        }
+        db.save_message(id, "assistant", &reply.content, Some(&reply.usage))?;
+        Ok(Some(id))
+    }
+}
+
+async fn completions(
+    State(server): State<Arc<Server>>,
+    headers: HeaderMap,
+    Json(request): Json<CompletionRequest>,
+) -> Result<Response, ApiError> {
+    server.authorize(&headers)?;
+    if request.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "temperature must be from 0 to 2"));
+    }
+
+    // System (and developer) messages become the system prompt; tool
+    // messages have nowhere to go
+    let mut system = Vec::new();
+    let mut messages = Vec::new();
+    for m in request.messages {
+        let content = m.content.map(Content::into_text).unwrap_or_default();
+        match m.role.as_str() {
+            "system" | "developer" => system.push(content),
+            "user" | "assistant" => messages.push(Message { role: m.role, content }),
+            _ => {}
+        }
+    }
+    if !messages.iter().any(|m| m.role == "user") {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "messages needs at least one user message"));
+    }
+
+    let (provider, model) = match request.model.as_deref().filter(|m| !m.is_empty()) {
+        Some(model) => AIProvider::route(model, &server.default),
+        None => (server.default.clone(), None),
+    };
+    let settings = ChatSettings {
+        model,
+        system_prompt: Some(system.join("\n\n")).filter(|s| !s.is_empty()),
+        temperature: request.temperature,
+        persona: None,
+    };
+    let client = AIClient::new(provider.clone(), server.config.api_keys.get(provider.api_key_env()).cloned())
+        .with_rate_limit(server.rate_limits.for_provider(&provider));
+    let reply = match client.send_message(messages.clone(), &settings).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            println!("{} {} failed: {:#}", Local::now().format("%H:%M:%S"), provider.db_name(), e);
+            return Err(ApiError::new(StatusCode::BAD_GATEWAY, format!("{:#}", e)));
+        }
+    };
+
+    let saved = match server.save(&provider, &settings, &messages, &reply) {
+        Ok(Some(id)) => format!(", saved as #{}", id),
+        Ok(None) => String::new(),
+        Err(e) => format!(", not saved: {:#}", e),
+    };
+    println!(
+        "{} {} {} {}ms{}",
+        Local::now().format("%H:%M:%S"),
+        provider.db_name(),
+        reply.usage.model,
+        reply.usage.latency_ms,
+        saved
+    );
+
+    let usage = match (reply.usage.prompt_tokens, reply.usage.completion_tokens) {
+        (Some(prompt), Some(completion)) => Some(CompletionUsage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            total_tokens: prompt + completion,
+        }),
+        _ => None,
+    };
+    let message = Message {
+        role: "assistant".to_string(),
+        content: reply.content,
+    };
+    // Replies aren't streamed from the provider, so a stream is the whole
+    // reply in one chunk
+    let (object, body) = if request.stream {
+        ("chat.completion.chunk", ChoiceBody::Delta(message))
+    } else {
+        ("chat.completion", ChoiceBody::Message(message))
+    };
+    let completion = Completion {
+        id: format!("chatcmpl-{}", Local::now().timestamp_millis()),
+        object,
+        created: Local::now().timestamp(),
+        model: reply.usage.model,
+        choices: vec![Choice {
+            index: 0,
+            body,
+            finish_reason: "stop",
+        }],
+        usage,
+    };
+    if !request.stream {
+        return Ok(Json(completion).into_response());
+    }
+    let chunk = serde_json::to_string(&completion)
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let body = format!("data: {}\n\ndata: [DONE]\n\n", chunk);
+    Ok(([(header::CONTENT_TYPE, "text/event-stream")], body).into_response())
+}
+
+/// Every provider name, and each one's default model
+async fn models(State(server): State<Arc<Server>>, headers: HeaderMap) -> Result<Json<ModelList>, ApiError> {
+    server.authorize(&headers)?;
+    let data = AIProvider::ALL
+        .iter()
+        .flat_map(|provider| [provider.db_name().to_string(), provider.model().to_string()])
+        .map(|id| ModelEntry {
+            id,
+            object: "model",
+            owned_by: "mega-cli",
+        })
+        .collect();
+    Ok(Json(ModelList { object: "list", data }))
+}
@@ -0,0 +1,433 @@
+//! Full-screen browser for every saved conversation, across providers,
+//! and the trash
+
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::providers::AIProvider;
+use crate::store::{self, Database, SearchQuery};
+use crate::search::tag_list;
+
+const HINTS: &str = "↑/↓ Select | Enter Open | r Rename | t Tags | c Duplicate | a Archive | l Lock | d Delete | \
+                     / Filter | Tab Show archived | T Trash | Esc Close";
+
+const TRASH_HINTS: &str = "↑/↓ Select | u Restore | d Delete forever | E Empty trash | / Filter | T Back | Esc Close";
+
+/// A question the manager is waiting on an answer to
+enum Prompt {
+    /// New title being typed
+    Rename(String),
+    /// Space-separated tags being edited
+    Tags(String),
+    /// Filter being typed; the list narrows as it changes
+    Filter,
+    /// Deleting a trashed conversation for good
+    ConfirmPurge,
+    ConfirmEmptyTrash,
+}
+
+/// The conversation `--continue` or `--session` asks for. `which` is an id
+/// or a title (exact first, then any title containing it); None means the
+/// most recently active one that isn't archived, with `provider` if given.
+pub fn find(db: &Database, which: Option<&str>, provider: Option<&str>) -> Result<store::Conversation> {
+    let Some(which) = which else {
+        let latest = db.list_conversations(provider)?.into_iter().find(|c| !c.archived);
+        return match (latest, provider) {
+            (Some(conversation), _) => Ok(conversation),
+            (None, Some(provider)) => bail!("No {} conversation to continue", provider),
+            (None, None) => bail!("No conversation to continue"),
+        };
+    };
+
+    if let Ok(id) = which.trim_start_matches('#').parse::<i64>() {
+        return match db.get_conversation(id)? {
+            Some(c) if c.deleted_on.is_some() => bail!("Conversation #{} is in the trash; restore it first", id),
+            Some(conversation) => Ok(conversation),
+            None => bail!("No conversation #{}", id),
+        };
+    }
+
+    let all = db.list_conversations(None)?;
+    if let Some(exact) = all.iter().find(|c| c.title.eq_ignore_ascii_case(which)) {
+        return Ok(exact.clone());
+    }
+    let needle = which.to_lowercase();
+    let matches: Vec<&store::Conversation> = all.iter().filter(|c| c.title.to_lowercase().contains(&needle)).collect();
+    match matches[..] {
+        [] => bail!("No conversation titled {:?}", which),
+        [only] => Ok(only.clone()),
+        _ => {
+            let listed: Vec<String> = matches.iter().take(5).map(|c| format!("  #{} {}", c.id, c.title)).collect();
+            let more = if matches.len() > 5 { format!("\n  … and {} more", matches.len() - 5) } else { String::new() };
+            bail!("{} conversations match {:?}; pass an id:\n{}{}", matches.len(), which, listed.join("\n"), more)
+        }
+    }
+}
+
+/// What the chat needs to do after a key in the manager
+pub enum Outcome {
+    Open(store::Conversation),
+    Renamed { id: i64, title: String },
+    Tagged { id: i64, tags: Vec<String> },
+    Locked { id: i64, locked: bool },
+    Deleted(i64),
+}
+
+pub struct SessionManager {
+    conversations: Vec<store::Conversation>,
+    state: ListState,
+    show_archived: bool,
+    /// Listing the trash instead
+    show_trash: bool,
+    /// Title words, `tag:name` and `is:starred`
+    filter: String,
+    prompt: Option<Prompt>,
+    /// Result of the last action, shown in place of the key hints
+    status: Option<String>,
+}
+
+impl SessionManager {
+    pub fn new(db: &Database) -> Result<Self> {
+        let mut manager = Self {
+            conversations: Vec::new(),
+            state: ListState::default(),
+            show_archived: false,
+            show_trash: false,
+            filter: String::new(),
+            prompt: None,
+            status: None,
+        };
+        manager.reload(db, None)?;
+        Ok(manager)
+    }
+
+    /// Re-read the list, selecting `select` if given and otherwise keeping
+    /// the selection where it was
+    fn reload(&mut self, db: &Database, select: Option<i64>) -> Result<()> {
+        let select = select.or_else(|| self.selected().map(|c| c.id));
+        let index = self.state.selected().unwrap_or(0);
+
+        let filter = SearchQuery::parse(&self.filter);
+        let conversations = if self.show_trash { db.list_trash()? } else { db.list_conversations(None)? };
+        self.conversations = conversations
+            .into_iter()
+            .filter(|c| (self.show_trash || self.show_archived || !c.archived) && filter.matches(c))
+            .collect();
+
+        let index = select
+            .and_then(|id| self.conversations.iter().position(|c| c.id == id))
+            .unwrap_or(index.min(self.conversations.len().saturating_sub(1)));
+        self.state.select((!self.conversations.is_empty()).then_some(index));
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&store::Conversation> {
+        self.state.selected().and_then(|i| self.conversations.get(i))
+    }
+
+    /// Drop an unanswered prompt, or else the filter. Returns false if
+    /// there was neither, meaning Esc should close the manager.
+    pub fn cancel(&mut self, db: &Database) -> bool {
+        match self.prompt.take() {
+            Some(Prompt::Filter) | None if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.report(Ok(()), "Filter cleared", db);
+                true
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent, db: &mut Database) -> Option<Outcome> {
+        match self.prompt {
+            Some(Prompt::Rename(ref mut title)) => {
+                match key.code {
+                    KeyCode::Char(c) => title.push(c),
+                    KeyCode::Backspace => {
+                        title.pop();
+                    }
+                    KeyCode::Enter => {
+                        let title = title.trim().to_string();
+                        self.prompt = None;
+                        let id = self.selected()?.id;
+                        return self.report(db.rename_conversation(id, &title), "Renamed", db)
+                            .then_some(Outcome::Renamed { id, title });
+                    }
+                    _ => {}
+                }
+                return None;
+            }
+            Some(Prompt::Tags(ref mut tags)) => {
+                match key.code {
+                    KeyCode::Char(c) => tags.push(c),
+                    KeyCode::Backspace => {
+                        tags.pop();
+                    }
+                    KeyCode::Enter => {
+                        let tags: Vec<String> = tags.split_whitespace().map(store::normalize_tag).collect();
+                        self.prompt = None;
+                        let id = self.selected()?.id;
+                        if !self.report(db.set_tags(id, &tags), "Tags saved", db) {
+                            return None;
+                        }
+                        // Read back, normalized and sorted
+                        let tags = db.get_conversation(id).ok()??.tags;
+                        return Some(Outcome::Tagged { id, tags });
+                    }
+                    _ => {}
+                }
+                return None;
+            }
+            Some(Prompt::Filter) => {
+                match key.code {
+                    KeyCode::Char(c) => self.filter.push(c),
+                    KeyCode::Backspace => {
+                        self.filter.pop();
+                    }
+                    KeyCode::Enter => {
+                        self.prompt = None;
+                        return None;
+                    }
+                    KeyCode::Up | KeyCode::Down => self.prompt = None,
+                    _ => {}
+                }
+                if let Err(e) = self.reload(db, None) {
+                    self.status = Some(format!("Error: {}", e));
+                }
+                if self.prompt.is_some() {
+                    return None;
+                }
+            }
+            Some(Prompt::ConfirmPurge) => {
+                self.prompt = None;
+                if key.code != KeyCode::Char('y') {
+                    self.status = Some("Delete cancelled".to_string());
+                    return None;
+                }
+                let id = self.selected()?.id;
+                self.report(db.purge_conversation(id), "Deleted for good", db);
+                return None;
+            }
+            Some(Prompt::ConfirmEmptyTrash) => {
+                self.prompt = None;
+                if key.code != KeyCode::Char('y') {
+                    self.status = Some("Kept the trash".to_string());
+                    return None;
+                }
+                let emptied = db.empty_trash();
+                let done = format!("Deleted {} conversations for good", emptied.as_ref().map_or(0, |n| *n));
+                self.report(emptied.map(|_| ()), &done, db);
+                return None;
+            }
+            None => {}
+        }
+
+        if self.show_trash {
+            self.handle_trash_key(key, db);
+            return None;
+        }
+        match key.code {
+            KeyCode::Up => self.state.select_previous(),
+            KeyCode::Down => self.select_next(),
+            KeyCode::Enter => return self.selected().cloned().map(Outcome::Open),
+            KeyCode::Char('r') => {
+                let title = self.selected()?.title.clone();
+                self.prompt = Some(Prompt::Rename(title));
+            }
+            KeyCode::Char('t') => {
+                let tags = self.selected()?.tags.join(" ");
+                self.prompt = Some(Prompt::Tags(tags));
+            }
+            KeyCode::Char('/') => self.prompt = Some(Prompt::Filter),
+            // Recoverable from the trash, so no need to ask
+            KeyCode::Char('d') => {
+                let (id, locked) = self.selected().map(|c| (c.id, c.locked))?;
+                if locked {
+                    self.status = Some("Locked, so it stays; l unlocks it".to_string());
+                    return None;
+                }
+                return self.report(db.delete_conversation(id), "Moved to the trash (T to see it)", db)
+                    .then_some(Outcome::Deleted(id));
+            }
+            KeyCode::Char('a') => {
+                let (id, archived) = self.selected().map(|c| (c.id, c.archived))?;
+                let done = if archived { "Unarchived" } else { "Archived" };
+                self.report(db.set_archived(id, !archived), done, db);
+            }
+            KeyCode::Char('l') => {
+                let (id, locked) = self.selected().map(|c| (c.id, c.locked))?;
+                let done = if locked { "Unlocked" } else { "Locked: read-only until unlocked" };
+                return self.report(db.set_locked(id, !locked), done, db)
+                    .then_some(Outcome::Locked { id, locked: !locked });
+            }
+            KeyCode::Char('c') => {
+                let id = self.selected()?.id;
+                match db.duplicate_conversation(id) {
+                    Ok(copy) => {
+                        self.status = Some("Duplicated".to_string());
+                        if let Err(e) = self.reload(db, Some(copy)) {
+                            self.status = Some(format!("Error: {}", e));
+                        }
+                    }
+                    Err(e) => self.status = Some(format!("Error: {}", e)),
+                }
+            }
+            KeyCode::Char('T') => self.toggle_trash(db),
+            KeyCode::Tab => {
+                self.show_archived = !self.show_archived;
+                self.report(Ok(()), if self.show_archived { "Showing archived" } else { "Hiding archived" }, db);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn handle_trash_key(&mut self, key: KeyEvent, db: &Database) {
+        match key.code {
+            KeyCode::Up => self.state.select_previous(),
+            KeyCode::Down => self.select_next(),
+            KeyCode::Char('u') => {
+                if let Some(id) = self.selected().map(|c| c.id) {
+                    self.report(db.restore_conversation(id), "Restored", db);
+                }
+            }
+            KeyCode::Char('d') if self.selected().is_some() => self.prompt = Some(Prompt::ConfirmPurge),
+            KeyCode::Char('E') if !self.conversations.is_empty() => self.prompt = Some(Prompt::ConfirmEmptyTrash),
+            KeyCode::Char('/') => self.prompt = Some(Prompt::Filter),
+            KeyCode::Char('T') => self.toggle_trash(db),
+            _ => {}
+        }
+    }
+
+    fn toggle_trash(&mut self, db: &Database) {
+        self.show_trash = !self.show_trash;
+        self.state.select(Some(0));
+        self.report(Ok(()), if self.show_trash { "Showing the trash" } else { "Left the trash" }, db);
+    }
+
+    fn select_next(&mut self) {
+        let last = self.conversations.len().saturating_sub(1);
+        let next = self.state.selected().map_or(0, |i| (i + 1).min(last));
+        self.state.select((!self.conversations.is_empty()).then_some(next));
+    }
+
+    /// Show how an action went and refresh the list. Returns whether it
+    /// succeeded.
+    fn report(&mut self, result: Result<()>, done: &str, db: &Database) -> bool {
+        let result = result.and_then(|_| self.reload(db, None));
+        self.status = Some(match result {
+            Ok(()) => done.to_string(),
+            Err(ref e) => format!("Error: {}", e),
+        });
+        result.is_ok()
+    }
+
+    pub fn render(&mut self, frame: &mut Frame, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(3)])
+            .split(area);
+
+        let items: Vec<ListItem> = self
+            .conversations
+            .iter()
+            .map(|c| {
+                let provider = AIProvider::parse(&c.provider);
+                let color = provider.as_ref().map_or(Color::White, AIProvider::color);
+                let name = provider.as_ref().map_or(c.provider.as_str(), |p| p.name());
+                let title = if c.title.is_empty() { "Untitled" } else { &c.title };
+
+                let mut spans = vec![
+                    Span::styled(format!("#{:<5}", c.id), Style::default().fg(Color::DarkGray)),
+                    Span::styled(format!("{:<16}", name), Style::default().fg(color)),
+                    Span::styled(title.to_string(), Style::default().bold()),
+                    Span::styled(
+                        format!("  {} · {} messages", c.created_on, c.message_count),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ];
+                if c.starred_count > 0 {
+                    spans.push(Span::styled(format!("  ★{}", c.starred_count), Style::default().fg(Color::Yellow)));
+                }
+                spans.push(Span::styled(tag_list(&c.tags), Style::default().fg(Color::Magenta)));
+                if let Some(ref deleted_on) = c.deleted_on {
+                    spans.push(Span::styled(format!("  deleted {}", deleted_on), Style::default().fg(Color::Red)));
+                } else if c.archived {
+                    spans.push(Span::styled("  [archived]", Style::default().fg(Color::Yellow)));
+                }
+                if c.locked {
+                    spans.push(Span::styled("  🔒", Style::default().fg(Color::Cyan)));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+
+        let mut title = match (self.show_trash, self.show_archived) {
+            (true, _) => "Trash",
+            (false, true) => "Sessions (all)",
+            (false, false) => "Sessions",
+        }
+        .to_string();
+        if !self.filter.is_empty() {
+            title.push_str(&format!(" matching \"{}\"", self.filter));
+        }
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan));
+
+        frame.render_widget(Clear, area);
+        if items.is_empty() {
+            let empty = match (self.filter.is_empty(), self.show_trash) {
+                (false, _) => "Nothing matches the filter.",
+                (true, true) => "The trash is empty.",
+                (true, false) => "No saved conversations yet.",
+            };
+            let empty = Paragraph::new(empty)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::DarkGray))
+                .block(block);
+            frame.render_widget(empty, chunks[0]);
+        } else {
+            let list = List::new(items)
+                .style(Style::default().fg(Color::White))
+                .highlight_style(Style::default().fg(Color::Cyan).reversed())
+                .block(block);
+            frame.render_stateful_widget(list, chunks[0], &mut self.state);
+        }
+
+        let hints = if self.show_trash { TRASH_HINTS } else { HINTS };
+        let (text, color) = match (&self.prompt, &self.status) {
+            (Some(Prompt::Rename(title)), _) => (format!("New title: {}_", title), Color::Cyan),
+            (Some(Prompt::Tags(tags)), _) => (format!("Tags (space-separated): {}_", tags), Color::Cyan),
+            (Some(Prompt::Filter), _) => (
+                format!("Filter: {}_  (title words, tag:name, is:starred; Enter to keep, Esc to clear)", self.filter),
+                Color::Cyan,
+            ),
+            (Some(Prompt::ConfirmPurge), _) => {
+                ("Delete this conversation and its messages for good? (y/n)".to_string(), Color::Yellow)
+            }
+            (Some(Prompt::ConfirmEmptyTrash), _) => {
+                ("Delete everything in the trash for good? (y/n)".to_string(), Color::Yellow)
+            }
+            (None, Some(status)) => (format!("{}  —  {}", status, hints), Color::Yellow),
+            (None, None) => (hints.to_string(), Color::DarkGray),
+        };
+        let footer = Paragraph::new(text)
+            .style(Style::default().fg(color))
+            .alignment(Alignment::Center)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Rounded)
+                    .border_style(Style::default().fg(color)),
+            );
+        frame.render_widget(footer, chunks[1]);
+    }
+}
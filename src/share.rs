@@ -0,0 +1,412 @@
+//! Shared sessions: `--host` puts the open conversation on a TCP port, and
+//! other instances `--join` it to read along and send messages of their
+//! own. The host does all the talking to the provider and saving; guests
+//! only see what it sends them. Each side writes lines of JSON.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, error::TryRecvError, UnboundedReceiver, UnboundedSender};
+
+/// Longest line either side reads, so a stray client can't fill memory
+const MAX_LINE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Longest hello the host reads, before it knows the guest has the code
+const MAX_HELLO_BYTES: u64 = 4 * 1024;
+
+/// How long joining waits for the host to answer, and the host for a
+/// guest's hello
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wrong join codes after which the host stops letting anyone in, so the
+/// code can't be guessed
+pub const MAX_WRONG_CODES: usize = 5;
+
+/// What a guest sends the host
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FromGuest {
+    /// The first line: the host's join code, and who's joining
+    Hello { code: String, name: String },
+    Send { content: String },
+}
+
+/// What the host sends its guests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FromHost {
+    /// Everything shown so far: on joining, and whenever the host switches
+    /// provider or conversation
+    Conversation {
+        provider: String,
+        title: String,
+        messages: Vec<SharedMessage>,
+    },
+    Message { message: SharedMessage },
+    /// Whether a reply is on its way
+    Waiting { waiting: bool },
+    /// Someone joined or left
+    Notice { text: String },
+    /// The answer to a wrong code; the connection closes after it
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedMessage {
+    /// `user` or `assistant`
+    pub role: String,
+    /// Who wrote a user message
+    pub author: Option<String>,
+    pub content: String,
+    /// When it was sent, in Unix seconds
+    pub timestamp: i64,
+}
+
+/// What the host hears from its guests' connections
+#[derive(Debug)]
+pub enum Inbound {
+    /// A guest gave the right code. Whatever goes in `outbox` is sent to
+    /// them. `id` tells apart guests with the same name.
+    Joined {
+        id: u64,
+        name: String,
+        outbox: UnboundedSender<FromHost>,
+    },
+    Message { name: String, content: String },
+    Left { id: u64, name: String },
+    /// Too many wrong codes; nobody else can join. Guests already in stay.
+    Closed,
+}
+
+struct Connected {
+    id: u64,
+    name: String,
+    outbox: UnboundedSender<FromHost>,
+}
+
+/// The hosting side: listens for guests and relays to them
+pub struct Host {
+    addr: SocketAddr,
+    code: String,
+    /// Who the host's own messages are from
+    name: String,
+    inbound: UnboundedReceiver<Inbound>,
+    guests: Vec<Connected>,
+}
+
+impl Host {
+    /// Listen on `addr` (such as `0.0.0.0:7878`) with a new join code
+    pub async fn start(addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen on {}", addr))?;
+        let addr = listener.local_addr()?;
+        let code = new_code()?;
+        let (inbound_tx, inbound) = mpsc::unbounded_channel();
+        tokio::spawn(accept(listener, code.clone(), inbound_tx));
+        tracing::info!("Hosting a shared session on {}", addr);
+        Ok(Self {
+            addr,
+            code,
+            name: user_name(),
+            inbound,
+            guests: Vec::new(),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// What guests must give to join
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names of the guests connected now
+    pub fn guests(&self) -> Vec<&str> {
+        self.guests.iter().map(|guest| guest.name.as_str()).collect()
+    }
+
+    /// Whatever the guests' connections have sent since last asked
+    pub fn events(&mut self) -> Vec<Inbound> {
+        let mut events = Vec::new();
+        while let Ok(event) = self.inbound.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    /// Start sending to a guest, beginning with the conversation so far
+    pub fn admit(&mut self, id: u64, name: String, outbox: UnboundedSender<FromHost>, conversation: FromHost) {
+        if outbox.send(conversation).is_ok() {
+            self.guests.push(Connected { id, name, outbox });
+        }
+    }
+
+    /// Send to every guest, forgetting any whose connection has gone
+    pub fn broadcast(&mut self, event: FromHost) {
+        self.guests.retain(|guest| guest.outbox.send(event.clone()).is_ok());
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        self.guests.retain(|guest| guest.id != id);
+    }
+}
+
+async fn accept(listener: TcpListener, code: String, inbound: UnboundedSender<Inbound>) {
+    let wrong_codes = Arc::new(AtomicUsize::new(0));
+    for id in 0.. {
+        match listener.accept().await {
+            // Dropping the listener closes the port
+            Ok(_) if wrong_codes.load(Ordering::SeqCst) >= MAX_WRONG_CODES => return,
+            Ok((stream, peer)) => {
+                let (code, inbound, wrong_codes) = (code.clone(), inbound.clone(), wrong_codes.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = serve_guest(id, stream, &code, inbound, &wrong_codes).await {
+                        tracing::warn!("Shared session connection from {}: {:#}", peer, e);
+                    }
+                });
+            }
+            Err(e) => tracing::warn!("Failed to accept a shared session connection: {}", e),
+        }
+        if inbound.is_closed() {
+            return;
+        }
+    }
+}
+
+/// Check the guest's code, then relay their messages in and the host's
+/// out until either side goes
+async fn serve_guest(
+    id: u64,
+    stream: TcpStream,
+    code: &str,
+    inbound: UnboundedSender<Inbound>,
+    wrong_codes: &AtomicUsize,
+) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    let hello = tokio::time::timeout(JOIN_TIMEOUT, read_line::<FromGuest>(&mut reader, MAX_HELLO_BYTES))
+        .await
+        .context("No hello in time")??;
+    let name = match hello {
+        Some(FromGuest::Hello { code: given, name }) if is_code(&given, code, wrong_codes) => name,
+        Some(FromGuest::Hello { .. }) => {
+            if wrong_codes.fetch_add(1, Ordering::SeqCst) + 1 == MAX_WRONG_CODES {
+                let _ = inbound.send(Inbound::Closed);
+            }
+            let reason = "Wrong code for this shared session".to_string();
+            write_line(&mut write, &FromHost::Rejected { reason }).await?;
+            bail!("A guest gave the wrong code");
+        }
+        Some(FromGuest::Send { .. }) => bail!("A guest sent a message before saying hello"),
+        None => return Ok(()),
+    };
+
+    let (outbox, mut outgoing) = mpsc::unbounded_channel::<FromHost>();
+    if inbound.send(Inbound::Joined { id, name: name.clone(), outbox }).is_err() {
+        return Ok(());
+    }
+    let writer = tokio::spawn(async move {
+        while let Some(event) = outgoing.recv().await {
+            if write_line(&mut write, &event).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let read = async {
+        while let Some(FromGuest::Send { content }) = read_line(&mut reader, MAX_LINE_BYTES).await? {
+            let message = Inbound::Message { name: name.clone(), content };
+            if inbound.send(message).is_err() {
+                break;
+            }
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    let result = read.await;
+    writer.abort();
+    let _ = inbound.send(Inbound::Left { id, name });
+    result
+}
+
+/// The joining side: a connection to a host
+pub struct Guest {
+    addr: String,
+    name: String,
+    outbox: UnboundedSender<FromGuest>,
+    inbound: UnboundedReceiver<FromHost>,
+}
+
+impl Guest {
+    /// Connect to the host at `addr` and give it `code`. Returns the
+    /// connection and the conversation so far.
+    pub async fn join(addr: &str, code: &str, name: Option<String>) -> Result<(Self, FromHost)> {
+        let name = name.unwrap_or_else(user_name);
+        let stream = tokio::time::timeout(JOIN_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .with_context(|| format!("No answer from {}", addr))?
+            .with_context(|| format!("Failed to connect to {}", addr))?;
+        let (read, mut write) = stream.into_split();
+        let mut reader = BufReader::new(read);
+
+        let hello = FromGuest::Hello { code: code.to_string(), name: name.clone() };
+        write_line(&mut write, &hello).await?;
+        let first = tokio::time::timeout(JOIN_TIMEOUT, read_line::<FromHost>(&mut reader, MAX_LINE_BYTES))
+            .await
+            .with_context(|| format!("{} didn't answer; is it hosting a shared session?", addr))??;
+        let conversation = match first {
+            Some(conversation @ FromHost::Conversation { .. }) => conversation,
+            Some(FromHost::Rejected { reason }) => bail!("{} turned us away: {}", addr, reason),
+            Some(_) => bail!("{} answered with something other than the conversation", addr),
+            None => bail!("{} closed the connection without answering", addr),
+        };
+
+        let (inbound_tx, inbound) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok(Some(event)) = read_line::<FromHost>(&mut reader, MAX_LINE_BYTES).await {
+                if inbound_tx.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+        let (outbox, mut outgoing) = mpsc::unbounded_channel::<FromGuest>();
+        tokio::spawn(async move {
+            while let Some(message) = outgoing.recv().await {
+                if write_line(&mut write, &message).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok((
+            Self {
+                addr: addr.to_string(),
+                name,
+                outbox,
+                inbound,
+            },
+            conversation,
+        ))
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Who this guest's messages are from
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Send a message for the host to pass on to the provider. Returns
+    /// false if the connection has gone.
+    pub fn send(&self, content: String) -> bool {
+        self.outbox.send(FromGuest::Send { content }).is_ok()
+    }
+
+    /// What the host has sent since last asked, and whether the
+    /// connection has closed
+    pub fn events(&mut self) -> (Vec<FromHost>, bool) {
+        let mut events = Vec::new();
+        loop {
+            match self.inbound.try_recv() {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Empty) => return (events, false),
+                Err(TryRecvError::Disconnected) => return (events, true),
+            }
+        }
+    }
+}
+
+/// The next line as JSON, or None at the end of the stream. A line over
+/// `limit` bytes is an error.
+async fn read_line<T: for<'de> Deserialize<'de>>(reader: &mut (impl AsyncBufRead + Unpin), limit: u64) -> Result<Option<T>> {
+    let mut line = String::new();
+    let read = (&mut *reader).take(limit).read_line(&mut line).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if read as u64 >= limit && !line.ends_with('\n') {
+        bail!("Line over {} KB", limit / 1024);
+    }
+    Ok(Some(serde_json::from_str(&line).context("Didn't parse")?))
+}
+
+async fn write_line(writer: &mut (impl AsyncWrite + Unpin), value: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+/// 12 hex digits from the OS's random number generator
+fn new_code() -> Result<String> {
+    let mut bytes = [0u8; 6];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow!("Failed to make a join code: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Whether `given` is the join code, compared in constant time so how
+/// long a wrong guess takes says nothing. Once there have been too many
+/// wrong ones, nothing is.
+fn is_code(given: &str, code: &str, wrong_codes: &AtomicUsize) -> bool {
+    wrong_codes.load(Ordering::SeqCst) < MAX_WRONG_CODES && bool::from(given.as_bytes().ct_eq(code.as_bytes()))
+}
+
+/// The login name, to label messages with
+fn user_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| "guest".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_twelve_hex_digits_and_differ() {
+        let (first, second) = (new_code().unwrap(), new_code().unwrap());
+        assert_eq!(first.len(), 12);
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()), "{}", first);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn no_code_is_right_after_too_many_wrong_ones() {
+        let wrong_codes = AtomicUsize::new(0);
+        assert!(is_code("abc123", "abc123", &wrong_codes));
+        assert!(!is_code("abc124", "abc123", &wrong_codes));
+        assert!(!is_code("abc", "abc123", &wrong_codes));
+        wrong_codes.store(MAX_WRONG_CODES, Ordering::SeqCst);
+        assert!(!is_code("abc123", "abc123", &wrong_codes));
+    }
+
+    #[tokio::test]
+    async fn lines_over_the_limit_are_refused() {
+        let hello = |code: usize| {
+            let hello = FromGuest::Hello { code: "c".repeat(code), name: "sam".to_string() };
+            format!("{}\n", serde_json::to_string(&hello).unwrap())
+        };
+        let read = read_line::<FromGuest>(&mut hello(100).as_bytes(), 1024).await.unwrap();
+        assert!(matches!(read, Some(FromGuest::Hello { name, .. }) if name == "sam"));
+
+        let error = read_line::<FromGuest>(&mut hello(2048).as_bytes(), 1024).await.unwrap_err();
+        assert_eq!(error.to_string(), "Line over 1 KB");
+        assert!(read_line::<FromGuest>(&mut "".as_bytes(), 1024).await.unwrap().is_none());
+    }
+}
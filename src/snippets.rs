@@ -0,0 +1,245 @@
+//! The snippet library: replies kept with `/snip name`, found again with
+//! a fuzzy picker (Ctrl+S or `/snippets`) and put into the input box
+
+use chrono::{Local, TimeZone};
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::store::{Database, Snippet};
+
+const HINTS: &str = "Type to filter | ↑/↓ Select | Enter Insert | Del Delete | Esc Close";
+
+/// Name matches rank above any match in the content
+const NAME_BONUS: i64 = 1_000;
+
+/// One snippet that matches the filter
+struct Match {
+    index: usize,
+    score: i64,
+    /// Positions of the matched characters in the name
+    positions: Vec<usize>,
+}
+
+/// Snippet picker opened with Ctrl+S or `/snippets`
+pub struct SnippetPicker {
+    snippets: Vec<Snippet>,
+    query: String,
+    /// Best first
+    matches: Vec<Match>,
+    state: ListState,
+    error: Option<String>,
+}
+
+impl SnippetPicker {
+    pub fn new(query: String, db: &Database) -> Self {
+        let mut picker = Self {
+            snippets: Vec::new(),
+            query,
+            matches: Vec::new(),
+            state: ListState::default(),
+            error: None,
+        };
+        picker.reload(db);
+        picker
+    }
+
+    fn reload(&mut self, db: &Database) {
+        match db.list_snippets() {
+            Ok(snippets) => {
+                self.snippets = snippets;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("Error: {}", e)),
+        }
+        self.refilter();
+    }
+
+    fn refilter(&mut self) {
+        let query = self.query.trim();
+        self.matches = self
+            .snippets
+            .iter()
+            .enumerate()
+            .filter_map(|(index, snippet)| {
+                if let Some((score, positions)) = fuzzy_match(query, &snippet.name) {
+                    return Some(Match {
+                        index,
+                        score: score + NAME_BONUS,
+                        positions,
+                    });
+                }
+                let (score, _) = fuzzy_match(query, &snippet.content)?;
+                Some(Match {
+                    index,
+                    score,
+                    positions: Vec::new(),
+                })
+            })
+            .collect();
+        // Stable, so equal scores stay in name order
+        self.matches.sort_by_key(|found| std::cmp::Reverse(found.score));
+        self.state.select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn selected(&self) -> Option<&Snippet> {
+        let found = self.matches.get(self.state.selected()?)?;
+        self.snippets.get(found.index)
+    }
+
+    /// Returns the snippet's text when Enter is pressed
+    pub fn handle_key(&mut self, key: KeyEvent, db: &Database) -> Option<String> {
+        match key.code {
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.refilter();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.refilter();
+            }
+            KeyCode::Up => self.state.select_previous(),
+            KeyCode::Down => {
+                let last = self.matches.len().saturating_sub(1);
+                let next = self.state.selected().map_or(0, |i| (i + 1).min(last));
+                self.state.select((!self.matches.is_empty()).then_some(next));
+            }
+            KeyCode::Delete => {
+                if let Some(id) = self.selected().map(|snippet| snippet.id) {
+                    match db.delete_snippet(id) {
+                        Ok(()) => self.reload(db),
+                        Err(e) => self.error = Some(format!("Error: {}", e)),
+                    }
+                }
+            }
+            KeyCode::Enter => return self.selected().map(|snippet| snippet.content.clone()),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&mut self, frame: &mut Frame, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(3), Constraint::Length(1)])
+            .split(area);
+        frame.render_widget(Clear, area);
+
+        let input = Paragraph::new(format!("✂ {}_", self.query))
+            .style(Style::default().fg(Color::Cyan))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Rounded)
+                    .title("Snippets")
+                    .border_style(Style::default().fg(Color::Cyan)),
+            );
+        frame.render_widget(input, chunks[0]);
+
+        let preview_width = chunks[1].width.saturating_sub(6) as usize;
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|found| {
+                let snippet = &self.snippets[found.index];
+                let mut header = highlight(&snippet.name, &found.positions);
+                header.push(Span::styled(
+                    format!("  {}", saved_on(snippet.saved_at)),
+                    Style::default().fg(Color::DarkGray),
+                ));
+                let preview = Span::styled(
+                    format!("  {}", preview(&snippet.content, preview_width)),
+                    Style::default().fg(Color::White),
+                );
+                ListItem::new(vec![Line::from(header), Line::from(preview), Line::from("")])
+            })
+            .collect();
+
+        let title = match (&self.error, self.matches.len()) {
+            (Some(error), _) => error.clone(),
+            (None, 0) if self.snippets.is_empty() => "No snippets yet; keep a reply with /snip name".to_string(),
+            (None, 0) => "No matches".to_string(),
+            (None, 1) => "1 snippet".to_string(),
+            (None, n) => format!("{} snippets", n),
+        };
+        let list = List::new(items)
+            .highlight_style(Style::default().bg(Color::DarkGray))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(ratatui::widgets::BorderType::Rounded)
+                    .title(title)
+                    .border_style(Style::default().fg(Color::White)),
+            );
+        frame.render_stateful_widget(list, chunks[1], &mut self.state);
+
+        let footer = Paragraph::new(HINTS)
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        frame.render_widget(footer, chunks[2]);
+    }
+}
+
+/// Whether every character of `query` appears in `text` in order,
+/// ignoring case, and how well: runs of matched characters and matches at
+/// the start of a word score higher. Returns the score and the positions
+/// matched. An empty query matches everything equally.
+fn fuzzy_match(query: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let mut wanted = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut positions = Vec::new();
+    let mut previous: Option<char> = None;
+    for (position, c) in text.chars().enumerate() {
+        let Some(&next) = wanted.peek() else {
+            break;
+        };
+        if c.to_lowercase().eq(std::iter::once(next)) {
+            score += 1;
+            if positions.last().is_some_and(|&last| last + 1 == position) {
+                score += 5;
+            }
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 3;
+            }
+            positions.push(position);
+            wanted.next();
+        }
+        previous = Some(c);
+    }
+    wanted.peek().is_none().then_some((score, positions))
+}
+
+/// The name with its matched characters picked out
+fn highlight(name: &str, positions: &[usize]) -> Vec<Span<'static>> {
+    name.chars()
+        .enumerate()
+        .map(|(position, c)| {
+            let style = if positions.contains(&position) {
+                Style::default().fg(Color::Yellow).bold()
+            } else {
+                Style::default().bold()
+            };
+            Span::styled(c.to_string(), style)
+        })
+        .collect()
+}
+
+/// The start of the content on one line, cut to `width` characters
+fn preview(content: &str, width: usize) -> String {
+    let flat = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() <= width {
+        return flat;
+    }
+    let cut: String = flat.chars().take(width.saturating_sub(1)).collect();
+    format!("{}…", cut)
+}
+
+/// Local date, e.g. `2025-06-01`
+fn saved_on(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map_or_else(String::new, |time| time.format("%Y-%m-%d").to_string())
+}
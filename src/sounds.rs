@@ -0,0 +1,100 @@
+//! Short sound cues for chat events (a message sent, a reply in, an
+//! error), synthesized rather than loaded so there are no files to ship
+
+use anyhow::{anyhow, Context, Result};
+use crossbeam_channel::{bounded, unbounded, Sender};
+use rodio::{buffer::SamplesBuffer, OutputStream};
+use std::f32::consts::TAU;
+
+use crate::config::SoundsConfig;
+
+const SAMPLE_RATE: u32 = 44_100;
+
+/// Seconds the start of each note ramps up over, so it doesn't click
+const ATTACK: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cue {
+    Sent,
+    Reply,
+    Error,
+}
+
+impl Cue {
+    /// (frequency in Hz, seconds) for each note
+    fn notes(self) -> &'static [(f32, f32)] {
+        match self {
+            // A quick rising blip
+            Cue::Sent => &[(660.0, 0.04), (880.0, 0.05)],
+            // Two bright notes
+            Cue::Reply => &[(880.0, 0.08), (1320.0, 0.14)],
+            // Low and falling
+            Cue::Error => &[(330.0, 0.12), (220.0, 0.22)],
+        }
+    }
+
+    /// The cue as mono samples at `volume`
+    fn samples(self, volume: f32) -> Vec<f32> {
+        let mut samples = Vec::new();
+        for &(frequency, seconds) in self.notes() {
+            let count = (seconds * SAMPLE_RATE as f32) as usize;
+            samples.extend((0..count).map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let envelope = (t / ATTACK).min(1.0) * (1.0 - t / seconds).powi(2);
+                (TAU * frequency * t).sin() * envelope * volume
+            }));
+        }
+        samples
+    }
+}
+
+/// Plays cues on a thread of its own, which holds the audio output
+pub struct Sounds {
+    config: SoundsConfig,
+    cues: Sender<Vec<f32>>,
+}
+
+impl Sounds {
+    /// Open the audio output. The thread ends when this is dropped.
+    pub fn new(config: &SoundsConfig) -> Result<Self> {
+        let (cues, queued) = unbounded::<Vec<f32>>();
+        let (ready_tx, ready) = bounded(1);
+        std::thread::spawn(move || {
+            // The output stream must stay on this thread for as long as we play
+            let (_stream, handle) = match OutputStream::try_default() {
+                Ok(output) => output,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            let _ = ready_tx.send(Ok(()));
+            for samples in queued {
+                if let Err(e) = handle.play_raw(SamplesBuffer::new(1, SAMPLE_RATE, samples)) {
+                    tracing::warn!("Sound cue not played: {}", e);
+                }
+            }
+        });
+        ready
+            .recv()
+            .context("The sound thread stopped")?
+            .map_err(|e| anyhow!("Failed to open the audio output: {}", e))?;
+
+        Ok(Self {
+            config: config.clone(),
+            cues,
+        })
+    }
+
+    /// Play `cue` if it's switched on
+    pub fn play(&self, cue: Cue) {
+        let wanted = match cue {
+            Cue::Sent => self.config.sent,
+            Cue::Reply => self.config.reply,
+            Cue::Error => self.config.error,
+        };
+        if wanted {
+            let _ = self.cues.send(cue.samples(self.config.volume.clamp(0.0, 1.0)));
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Reading replies aloud with the system's text-to-speech (`say` on macOS,
+//! espeak on Linux, System.Speech on Windows) or a command of your own,
+//! such as a script that calls a speech API
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+use crate::config::SpeechConfig;
+use crate::providers::AIProvider;
+
+/// The provider's voice from `[speech.voices]`, for engines and commands
+const VOICE_ENV: &str = "MEGA_CLI_VOICE";
+/// `rate` in words per minute, when set
+const RATE_ENV: &str = "MEGA_CLI_RATE";
+
+/// Reads stdin aloud with System.Speech, taking the voice and rate from
+/// the environment so nothing needs quoting
+const POWERSHELL_SCRIPT: &str = "Add-Type -AssemblyName System.Speech; \
+$s = New-Object System.Speech.Synthesis.SpeechSynthesizer; \
+if ($env:MEGA_CLI_VOICE) { $s.SelectVoice($env:MEGA_CLI_VOICE) }; \
+if ($env:MEGA_CLI_RATE) { $s.Rate = [Math]::Max(-10, [Math]::Min(10, [int](([int]$env:MEGA_CLI_RATE - 175) / 25))) }; \
+$s.Speak([Console]::In.ReadToEnd())";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Engine {
+    Say,
+    /// espeak-ng or espeak, by executable name
+    Espeak(&'static str),
+    PowerShell,
+    /// A shell command from the config
+    Command(String),
+}
+
+impl Engine {
+    /// The configured command or engine, or the first one installed for
+    /// `auto`
+    fn resolve(config: &SpeechConfig) -> Result<Self> {
+        if let Some(command) = config.command.as_deref().filter(|c| !c.trim().is_empty()) {
+            return Ok(Engine::Command(command.to_string()));
+        }
+        match config.engine.as_str() {
+            "auto" => Self::detect().context(
+                "No text-to-speech engine found; install espeak-ng, or set `command` under [speech]",
+            ),
+            "say" => Ok(Engine::Say),
+            "espeak-ng" => Ok(Engine::Espeak("espeak-ng")),
+            "espeak" => Ok(Engine::Espeak("espeak")),
+            "powershell" => Ok(Engine::PowerShell),
+            other => bail!(
+                "Unknown speech engine {:?} (expected auto, say, espeak-ng, espeak or powershell)",
+                other
+            ),
+        }
+    }
+
+    fn detect() -> Option<Self> {
+        if cfg!(windows) {
+            return Some(Engine::PowerShell);
+        }
+        if cfg!(target_os = "macos") {
+            return Some(Engine::Say);
+        }
+        ["espeak-ng", "espeak"].into_iter().find(|name| on_path(name)).map(Engine::Espeak)
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Engine::Say => "say",
+            Engine::Espeak(name) => name,
+            Engine::PowerShell => "System.Speech",
+            Engine::Command(_) => "the [speech] command",
+        }
+    }
+
+    /// The process that reads its stdin aloud
+    fn command(&self, voice: Option<&str>, rate: Option<u32>) -> Command {
+        let mut command = match self {
+            Engine::Say => {
+                let mut command = Command::new("say");
+                if let Some(voice) = voice {
+                    command.args(["-v", voice]);
+                }
+                if let Some(rate) = rate {
+                    command.args(["-r", &rate.to_string()]);
+                }
+                command.args(["-f", "-"]);
+                command
+            }
+            Engine::Espeak(name) => {
+                let mut command = Command::new(name);
+                if let Some(voice) = voice {
+                    command.args(["-v", voice]);
+                }
+                if let Some(rate) = rate {
+                    command.args(["-s", &rate.to_string()]);
+                }
+                command.arg("--stdin");
+                command
+            }
+            Engine::PowerShell => {
+                let mut command = Command::new("powershell");
+                command.args(["-NoProfile", "-NonInteractive", "-Command", POWERSHELL_SCRIPT]);
+                command
+            }
+            Engine::Command(line) if cfg!(windows) => {
+                let mut command = Command::new("cmd");
+                command.args(["/C", line]);
+                command
+            }
+            Engine::Command(line) => {
+                let mut command = Command::new("sh");
+                command.args(["-c", line]);
+                command
+            }
+        };
+        if let Some(voice) = voice {
+            command.env(VOICE_ENV, voice);
+        }
+        if let Some(rate) = rate {
+            command.env(RATE_ENV, rate.to_string());
+        }
+        command
+    }
+}
+
+/// Speaks one reply at a time; a new one, or dropping it, cuts off the
+/// last
+pub struct Speaker {
+    engine: Engine,
+    rate: Option<u32>,
+    /// Voice per provider, by `db_name`
+    voices: BTreeMap<String, String>,
+    /// What's speaking now, or spoke last
+    current: Option<Child>,
+}
+
+impl Speaker {
+    pub fn new(config: &SpeechConfig) -> Result<Self> {
+        Ok(Self {
+            engine: Engine::resolve(config)?,
+            rate: config.rate,
+            voices: config.voices.clone(),
+            current: None,
+        })
+    }
+
+    /// What's doing the speaking, for messages
+    pub fn engine_name(&self) -> &str {
+        self.engine.name()
+    }
+
+    /// Start reading `text` in `provider`'s voice, stopping whatever was
+    /// being read
+    pub fn speak(&mut self, text: &str, provider: &AIProvider) -> Result<()> {
+        self.stop();
+        let text = speakable(text);
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let voice = self.voices.get(provider.db_name()).map(String::as_str);
+        let mut command = self.engine.command(voice, self.rate);
+        command.stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null());
+        // So a pipeline (`piper ... | aplay`) can be stopped as a whole
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.engine.name()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // A long reply can fill the pipe before the engine reads it
+            std::thread::spawn(move || {
+                let _ = stdin.write_all(text.as_bytes());
+            });
+        }
+        self.current = Some(child);
+        Ok(())
+    }
+
+    /// Stop reading. Returns false if nothing was being read.
+    pub fn stop(&mut self) -> bool {
+        let Some(mut child) = self.current.take() else {
+            return false;
+        };
+        let speaking = matches!(child.try_wait(), Ok(None));
+        if speaking {
+            #[cfg(unix)]
+            let _ = Command::new("kill")
+                .args(["-TERM", "--", &format!("-{}", child.id())])
+                .stderr(Stdio::null())
+                .status();
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+        speaking
+    }
+}
+
+impl Drop for Speaker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// A reply as it should sound: code blocks are skipped and markdown marks
+/// dropped
+fn speakable(text: &str) -> String {
+    let mut spoken = String::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            if !in_code {
+                spoken.push_str("(code block)\n");
+            }
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        let trimmed = trimmed.trim_start_matches(['#', '>']).trim_start();
+        let trimmed = trimmed.strip_prefix("- ").or_else(|| trimmed.strip_prefix("* ")).unwrap_or(trimmed);
+        spoken.push_str(&trimmed.replace(['*', '`'], ""));
+        spoken.push('\n');
+    }
+    spoken.trim().to_string()
+}
+
+fn on_path(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| Path::new(&dir).join(name).is_file()))
+}
@@ -0,0 +1,362 @@
+//! Usage dashboard: `/stats` in the chat and `animated-cli stats`
+
+use anyhow::Result;
+use chrono::{Datelike, Days, Local, NaiveDate};
+use clap::Args;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    buffer::Buffer,
+    crossterm::{
+        queue,
+        style::{Attribute, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor},
+    },
+    prelude::*,
+    widgets::{Bar, BarChart, BarGroup, Block, Borders, Clear, Paragraph},
+};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+
+use crate::providers::AIProvider;
+use crate::store::{Database, ModelLatency, Period};
+
+/// How far back the usage charts go
+const DAYS: u64 = 14;
+const WEEKS: u64 = 12;
+
+/// Tallest the dashboard is printed by `animated-cli stats`
+const INLINE_HEIGHT: u16 = 40;
+
+const HINTS: &str = "Tab Per day/week | Esc Close";
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Chart tokens and cost per week instead of per day
+    #[arg(long)]
+    weekly: bool,
+}
+
+/// Run `animated-cli stats`: charts on a terminal, a plain summary otherwise
+pub fn run(args: StatsArgs, db_path: &Path) -> Result<()> {
+    let db = Database::open(db_path)?;
+    let period = if args.weekly { Period::Week } else { Period::Day };
+    let stats = Stats::load(&db, period)?;
+
+    if !std::io::stdout().is_terminal() {
+        stats.print();
+        return Ok(());
+    }
+
+    let (columns, rows) = crossterm::terminal::size()?;
+    let mut buffer = Buffer::empty(Rect::new(0, 0, columns, rows.saturating_sub(1).min(INLINE_HEIGHT)));
+    (&stats).render(buffer.area, &mut buffer);
+    print_buffer(&buffer)?;
+    Ok(())
+}
+
+/// Write a rendered buffer to stdout as styled lines, so the charts stay
+/// in the scrollback like any other command's output
+fn print_buffer(buffer: &Buffer) -> std::io::Result<()> {
+    let mut out = std::io::stdout().lock();
+    for y in buffer.area.top()..buffer.area.bottom() {
+        let mut last = None;
+        for x in buffer.area.left()..buffer.area.right() {
+            let cell = &buffer[(x, y)];
+            if cell.skip {
+                continue;
+            }
+            let style = (cell.fg, cell.bg, cell.modifier.contains(Modifier::BOLD));
+            if last != Some(style) {
+                queue!(
+                    out,
+                    SetAttribute(Attribute::Reset),
+                    SetForegroundColor(cell.fg.into()),
+                    SetBackgroundColor(cell.bg.into())
+                )?;
+                if style.2 {
+                    queue!(out, SetAttribute(Attribute::Bold))?;
+                }
+                last = Some(style);
+            }
+            queue!(out, Print(cell.symbol()))?;
+        }
+        queue!(out, SetAttribute(Attribute::Reset), ResetColor, Print("\n"))?;
+    }
+    out.flush()
+}
+
+/// Usage over one period's worth of charts
+struct PeriodBar {
+    start: NaiveDate,
+    tokens: i64,
+    cost: f64,
+}
+
+pub struct Stats {
+    period: Period,
+    /// (provider db name, messages)
+    per_provider: Vec<(String, i64)>,
+    /// Every period in range, oldest first, including empty ones
+    usage: Vec<PeriodBar>,
+    latency: Vec<ModelLatency>,
+    per_hour: [i64; 24],
+}
+
+impl Stats {
+    pub fn load(db: &Database, period: Period) -> Result<Self> {
+        let starts = period_starts(period, Local::now().date_naive());
+        let since = starts
+            .first()
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+            .map_or(0, |midnight| midnight.timestamp());
+        let totals = db.usage_per_period(period, since)?;
+
+        let usage = starts
+            .into_iter()
+            .map(|start| {
+                let key = start.format("%Y-%m-%d").to_string();
+                let total = totals.iter().find(|t| t.start == key);
+                PeriodBar {
+                    start,
+                    tokens: total.map_or(0, |t| t.tokens),
+                    cost: total.map_or(0.0, |t| t.cost),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            period,
+            per_provider: db.messages_per_provider()?,
+            usage,
+            latency: db.latency_per_model()?,
+            per_hour: db.messages_per_hour()?,
+        })
+    }
+
+    fn period_name(&self) -> &'static str {
+        match self.period {
+            Period::Day => "day",
+            Period::Week => "week",
+        }
+    }
+
+    fn print(&self) {
+        println!("Messages per provider");
+        for (provider, count) in &self.per_provider {
+            println!("  {:<18} {}", provider_name(provider), count);
+        }
+
+        println!("\nTokens and cost per {}", self.period_name());
+        for bar in &self.usage {
+            println!("  {}  {:>10} tokens  ${:.4}", bar.start, bar.tokens, bar.cost);
+        }
+
+        println!("\nAverage reply time per model");
+        for model in &self.latency {
+            println!("  {:<28} {:>6.1}s over {} replies", model.model, model.average_ms / 1000.0, model.replies);
+        }
+
+        println!("\nMessages you sent per hour of the day");
+        for (hour, count) in self.per_hour.iter().enumerate() {
+            println!("  {:02}:00  {}", hour, count);
+        }
+    }
+}
+
+impl Widget for &Stats {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let top = (self.per_provider.len().max(self.latency.len()) as u16 + 2).clamp(3, 8);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(top), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1)])
+            .split(area);
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(rows[0]);
+
+        let providers: Vec<Bar> = self
+            .per_provider
+            .iter()
+            .map(|(provider, count)| {
+                let color = AIProvider::parse(provider).map_or(Color::White, |p| p.color());
+                Bar::default()
+                    .value(*count as u64)
+                    .label(Line::from(provider_name(provider)))
+                    .style(Style::default().fg(color))
+                    .value_style(Style::default().fg(Color::Black).bg(color))
+            })
+            .collect();
+        horizontal_chart("Messages per provider", &providers).render(columns[0], buf);
+
+        let latency: Vec<Bar> = self
+            .latency
+            .iter()
+            .map(|model| {
+                Bar::default()
+                    .value(model.average_ms as u64)
+                    .label(Line::from(model.model.clone()))
+                    .text_value(format!("{:.1}s ({})", model.average_ms / 1000.0, model.replies))
+            })
+            .collect();
+        horizontal_chart("Average reply time per model", &latency).render(columns[1], buf);
+
+        let width = bar_width(rows[1], self.usage.len());
+        let label = |start: &NaiveDate| start.format(if width >= 5 { "%m-%d" } else { "%d" }).to_string();
+        let tokens: Vec<Bar> = self
+            .usage
+            .iter()
+            .map(|bar| {
+                Bar::default()
+                    .value(bar.tokens as u64)
+                    .label(Line::from(label(&bar.start)))
+                    .text_value(compact(bar.tokens))
+            })
+            .collect();
+        let title = format!("Tokens per {}", self.period_name());
+        vertical_chart(&title, &tokens, width, Color::Cyan).render(rows[1], buf);
+
+        let cost: Vec<Bar> = self
+            .usage
+            .iter()
+            .map(|bar| {
+                Bar::default()
+                    // Hundredths of a cent, so small days still show up
+                    .value((bar.cost * 10_000.0).round() as u64)
+                    .label(Line::from(label(&bar.start)))
+                    .text_value(dollars(bar.cost))
+            })
+            .collect();
+        let title = format!("Cost per {} (list prices)", self.period_name());
+        vertical_chart(&title, &cost, width, Color::Green).render(rows[2], buf);
+
+        let hours: Vec<Bar> = self
+            .per_hour
+            .iter()
+            .enumerate()
+            .map(|(hour, count)| Bar::default().value(*count as u64).label(Line::from(format!("{:02}", hour))))
+            .collect();
+        let width = bar_width(rows[3], hours.len());
+        vertical_chart("Busiest hours (messages you sent)", &hours, width, Color::Yellow).render(rows[3], buf);
+    }
+}
+
+/// `/stats` screen, drawn over the chat
+pub struct StatsView {
+    stats: Result<Stats, String>,
+}
+
+impl StatsView {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            stats: Stats::load(db, Period::Day).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent, db: &Database) {
+        if key.code == KeyCode::Tab {
+            let period = match self.stats {
+                Ok(ref stats) if stats.period == Period::Day => Period::Week,
+                _ => Period::Day,
+            };
+            self.stats = Stats::load(db, period).map_err(|e| e.to_string());
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(1)])
+            .split(area);
+        frame.render_widget(Clear, area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title("Stats")
+            .border_style(Style::default().fg(Color::Cyan));
+        let inner = block.inner(chunks[0]);
+        frame.render_widget(block, chunks[0]);
+        match self.stats {
+            Ok(ref stats) => frame.render_widget(stats, inner),
+            Err(ref e) => frame.render_widget(
+                Paragraph::new(format!("Couldn't load stats: {}", e)).style(Style::default().fg(Color::Red)),
+                inner,
+            ),
+        }
+
+        let footer = Paragraph::new(HINTS)
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        frame.render_widget(footer, chunks[1]);
+    }
+}
+
+/// First day of every period the usage charts cover, oldest first
+fn period_starts(period: Period, today: NaiveDate) -> Vec<NaiveDate> {
+    match period {
+        Period::Day => (0..DAYS).rev().map(|i| today - Days::new(i)).collect(),
+        Period::Week => {
+            let monday = today - Days::new(today.weekday().num_days_from_monday() as u64);
+            (0..WEEKS).rev().map(|i| monday - Days::new(i * 7)).collect()
+        }
+    }
+}
+
+fn chart_block<'a>(title: impl Into<Line<'a>>) -> Block<'a> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_type(ratatui::widgets::BorderType::Rounded)
+        .title(title)
+        .border_style(Style::default().fg(Color::White))
+}
+
+/// One row per bar, labelled on the left
+fn horizontal_chart<'a>(title: &'a str, bars: &[Bar<'a>]) -> BarChart<'a> {
+    BarChart::default()
+        .block(chart_block(title))
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .bar_style(Style::default().fg(Color::Magenta))
+        .value_style(Style::default().fg(Color::White))
+        .data(BarGroup::default().bars(bars))
+}
+
+fn vertical_chart<'a>(title: &str, bars: &[Bar<'a>], width: u16, color: Color) -> BarChart<'a> {
+    BarChart::default()
+        .block(chart_block(title.to_string()))
+        .bar_width(width)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(color))
+        .value_style(Style::default().fg(Color::Black).bg(color))
+        .label_style(Style::default().fg(Color::DarkGray))
+        .data(BarGroup::default().bars(bars))
+}
+
+/// Widest bars that fit `count` of them side by side with a gap of one
+fn bar_width(area: Rect, count: usize) -> u16 {
+    let inner = area.width.saturating_sub(2);
+    (inner / count.max(1) as u16).saturating_sub(1).max(1)
+}
+
+fn provider_name(db_name: &str) -> &str {
+    AIProvider::parse(db_name).map_or(db_name, |p| p.name())
+}
+
+/// 950, 12k, 1.2M
+fn compact(n: i64) -> String {
+    match n {
+        n if n >= 1_000_000 => format!("{:.1}M", n as f64 / 1_000_000.0),
+        n if n >= 1_000 => format!("{}k", n / 1_000),
+        n => n.to_string(),
+    }
+}
+
+fn dollars(cost: f64) -> String {
+    if cost >= 10.0 {
+        format!("${:.0}", cost)
+    } else {
+        format!("${:.2}", cost)
+    }
+}
@@ -0,0 +1,1289 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::providers::{ChatSettings, Usage};
+use crate::config::{self, Config};
+
+mod backup;
+pub use backup::{backup, restore};
+mod sync;
+pub use sync::{Bundle, MergeReport};
+mod retention;
+pub use retention::prune;
+mod repair;
+pub use repair::{open_checked, repair};
+
+#[cfg(feature = "encryption")]
+mod encryption;
+#[cfg(feature = "encryption")]
+pub use encryption::encrypt;
+
+/// Providers that had their own `{provider}_messages` table before
+/// conversations existed
+const LEGACY_PROVIDERS: [&str; 4] = ["claude", "grok", "gpt", "gemini"];
+
+/// Longest a write waits for another connection to finish with the file
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bumped whenever `migrate` gains a step
+const SCHEMA_VERSION: i32 = 15;
+
+/// Bracket the matched terms in search snippets
+pub const MATCH_START: char = '\u{2}';
+pub const MATCH_END: char = '\u{3}';
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub id: i64,
+    /// Position in the conversation, from 1
+    pub seq: i64,
+    pub role: String,
+    pub content: String,
+    /// When it was sent, in Unix seconds
+    pub timestamp: i64,
+    /// What an assistant reply took; None for user and imported messages
+    pub usage: Option<MessageUsage>,
+    pub starred: bool,
+    pub rating: Option<Rating>,
+    /// A short note on a reply, such as why it was rated so
+    pub note: Option<String>,
+}
+
+/// A thumbs up or down on a reply, for telling which provider answered
+/// best
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+impl Rating {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "up" | "good" | "+" | "👍" => Some(Rating::Up),
+            "down" | "bad" | "-" | "👎" => Some(Rating::Down),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rating::Up => "up",
+            Rating::Down => "down",
+        }
+    }
+
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            Rating::Up => "👍",
+            Rating::Down => "👎",
+        }
+    }
+
+    /// As stored: 1 up, -1 down
+    pub fn score(&self) -> i64 {
+        match self {
+            Rating::Up => 1,
+            Rating::Down => -1,
+        }
+    }
+
+    pub fn from_score(score: i64) -> Option<Self> {
+        match score.signum() {
+            1 => Some(Rating::Up),
+            -1 => Some(Rating::Down),
+            _ => None,
+        }
+    }
+}
+
+/// Stored usage of one assistant reply. Cost is worked out when the reply
+/// arrives, so later price changes don't rewrite history.
+#[derive(Debug, Clone)]
+pub struct MessageUsage {
+    pub model: String,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub latency_ms: Option<i64>,
+    /// US dollars
+    pub cost: Option<f64>,
+}
+
+/// One saved chat with a provider
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    pub id: i64,
+    /// `AIProvider::db_name` of the provider it's with
+    pub provider: String,
+    pub title: String,
+    /// Local creation date, e.g. `2025-06-01`
+    pub created_on: String,
+    pub message_count: i64,
+    /// Hidden from the switcher and never reopened on launch
+    pub archived: bool,
+    /// Lowercase, sorted
+    pub tags: Vec<String>,
+    /// How many of its messages are starred
+    pub starred_count: i64,
+    /// Local date it was moved to the trash
+    pub deleted_on: Option<String>,
+    /// Read-only: nothing is sent to it, and it can't be deleted or
+    /// pruned, until it's unlocked
+    pub locked: bool,
+}
+
+/// A conversation brought in from another tool, with its messages
+#[derive(Debug, Clone)]
+pub struct NewConversation {
+    pub provider: String,
+    pub title: String,
+    pub created_at: i64,
+    /// Id in the tool it came from (e.g. `chatgpt:<uuid>`), so importing
+    /// the same file twice doesn't duplicate anything
+    pub source: Option<String>,
+    pub messages: Vec<NewMessage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewMessage {
+    pub role: String,
+    pub content: String,
+    pub timestamp: i64,
+}
+
+/// A reply kept under a name, to insert into a message later
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    pub id: i64,
+    pub name: String,
+    pub content: String,
+    /// When it was saved, in Unix seconds
+    pub saved_at: i64,
+}
+
+/// A message matching a search
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub conversation: Conversation,
+    pub message_id: i64,
+    pub role: String,
+    /// Excerpt around the match, with matched terms between `MATCH_START`
+    /// and `MATCH_END`
+    pub snippet: String,
+}
+
+pub struct Database {
+    conn: Connection,
+}
+
+impl Database {
+    pub fn open(db_path: &Path) -> Result<Self> {
+        // Create parent directory if it doesn't exist
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut conn = Connection::open(db_path)
+            .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+        if !is_readable(&conn) {
+            conn = open_encrypted(db_path)?;
+        }
+        conn.pragma_update(None, "foreign_keys", true)?;
+        // A save waits out the launch's backup and history reads on other
+        // connections rather than failing
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        let mut db = Self { conn };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    /// A database that lives only as long as the process, for
+    /// `--incognito`
+    pub fn open_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory().context("Failed to open an in-memory database")?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        let mut db = Self { conn };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    /// Whether nothing is written to disk
+    pub fn is_in_memory(&self) -> bool {
+        self.conn.path().is_none_or(str::is_empty)
+    }
+
+    /// A second, read-only connection to the same file, to read from on
+    /// another thread. None for an in-memory database, or an encrypted one
+    /// that would need its passphrase again.
+    pub fn reader(&self) -> Option<Database> {
+        let path = self.conn.path().filter(|path| !path.is_empty())?;
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok()?;
+        is_readable(&conn).then_some(Self { conn })
+    }
+
+    /// Where the database lives: `--db`, then `database.path` in the
+    /// config, then the platform data directory (e.g.
+    /// `~/.local/share/mega-cli` on Linux, with `profiles/<name>` under it
+    /// for a named profile). A database left at the old location next to
+    /// the config file is moved to the default one.
+    pub fn resolve_path(flag: Option<PathBuf>, config: &Config) -> Result<PathBuf> {
+        if let Some(path) = flag.or_else(|| config.database.path.as_deref().map(expand_home)) {
+            return Ok(path);
+        }
+
+        let dirs = directories::ProjectDirs::from("", "", "mega-cli")
+            .ok_or_else(|| anyhow!("Couldn't find a data directory for the database"))?;
+        if let Some(ref profile) = config.profile {
+            return Ok(dirs.data_dir().join("profiles").join(profile).join("conversations.db"));
+        }
+        let path = dirs.data_dir().join("conversations.db");
+
+        let legacy = config::config_dir()?.join("conversations.db");
+        if legacy.exists() && !path.exists() && legacy != path {
+            move_file(&legacy, &path)
+                .with_context(|| format!("Failed to move database from {:?} to {:?}", legacy, path))?;
+            eprintln!("Moved conversation database to {}", path.display());
+        }
+        Ok(path)
+    }
+
+    /// Bring the schema up to `SCHEMA_VERSION`, one step at a time, each in
+    /// its own transaction
+    fn migrate(&mut self) -> Result<()> {
+        let version: i32 = self.conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            bail!("Database schema v{} is newer than this build understands (v{})", version, SCHEMA_VERSION);
+        }
+
+        if version < 1 {
+            let tx = self.conn.transaction()?;
+            migrate_conversations(&tx).context("Failed to migrate to conversations")?;
+            tx.pragma_update(None, "user_version", 1)?;
+            tx.commit()?;
+        }
+        if version < 2 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch("ALTER TABLE conversations ADD COLUMN archived INTEGER NOT NULL DEFAULT 0")
+                .context("Failed to add conversation archiving")?;
+            tx.pragma_update(None, "user_version", 2)?;
+            tx.commit()?;
+        }
+        if version < 3 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(
+                "ALTER TABLE conversations ADD COLUMN source TEXT;
+                 CREATE UNIQUE INDEX conversations_by_source ON conversations (source) WHERE source IS NOT NULL;",
+            )
+            .context("Failed to add import sources")?;
+            tx.pragma_update(None, "user_version", 3)?;
+            tx.commit()?;
+        }
+        if version < 4 {
+            let tx = self.conn.transaction()?;
+            migrate_search_index(&tx).context("Failed to build the search index")?;
+            tx.pragma_update(None, "user_version", 4)?;
+            tx.commit()?;
+        }
+        if version < 5 {
+            let tx = self.conn.transaction()?;
+            migrate_message_order(&tx).context("Failed to add message ordering")?;
+            tx.pragma_update(None, "user_version", 5)?;
+            tx.commit()?;
+        }
+        if version < 6 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(
+                "ALTER TABLE messages ADD COLUMN model TEXT;
+                ALTER TABLE messages ADD COLUMN prompt_tokens INTEGER;
+                ALTER TABLE messages ADD COLUMN completion_tokens INTEGER;
+                ALTER TABLE messages ADD COLUMN latency_ms INTEGER;
+                ALTER TABLE messages ADD COLUMN cost REAL;",
+            )
+            .context("Failed to add usage columns")?;
+            tx.pragma_update(None, "user_version", 6)?;
+            tx.commit()?;
+        }
+        if version < 7 {
+            let tx = self.conn.transaction()?;
+            migrate_sync_ids(&tx).context("Failed to add sync ids")?;
+            tx.pragma_update(None, "user_version", 7)?;
+            tx.commit()?;
+        }
+        if version < 8 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(
+                "CREATE TABLE conversation_tags (
+                    conversation_id INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+                    tag TEXT NOT NULL,
+                    PRIMARY KEY (conversation_id, tag)
+                );
+                CREATE INDEX conversation_tags_by_tag ON conversation_tags (tag);
+                ALTER TABLE messages ADD COLUMN starred INTEGER NOT NULL DEFAULT 0;",
+            )
+            .context("Failed to add tags and stars")?;
+            tx.pragma_update(None, "user_version", 8)?;
+            tx.commit()?;
+        }
+        if version < 9 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch("ALTER TABLE conversations ADD COLUMN deleted_at INTEGER")
+                .context("Failed to add the trash")?;
+            tx.pragma_update(None, "user_version", 9)?;
+            tx.commit()?;
+        }
+        if version < 10 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(
+                "ALTER TABLE conversations ADD COLUMN model TEXT;
+                 ALTER TABLE conversations ADD COLUMN system_prompt TEXT;
+                 ALTER TABLE conversations ADD COLUMN temperature REAL;
+                 ALTER TABLE conversations ADD COLUMN persona TEXT;",
+            )
+            .context("Failed to add conversation settings")?;
+            tx.pragma_update(None, "user_version", 10)?;
+            tx.commit()?;
+        }
+        if version < 11 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(
+                "CREATE TABLE snippets (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE,
+                    content TEXT NOT NULL,
+                    saved_at INTEGER NOT NULL
+                );",
+            )
+            .context("Failed to add snippets")?;
+            tx.pragma_update(None, "user_version", 11)?;
+            tx.commit()?;
+        }
+        if version < 12 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(
+                "ALTER TABLE messages ADD COLUMN rating INTEGER;
+                 ALTER TABLE messages ADD COLUMN note TEXT;",
+            )
+            .context("Failed to add ratings and notes")?;
+            tx.pragma_update(None, "user_version", 12)?;
+            tx.commit()?;
+        }
+        if version < 13 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(
+                "CREATE TABLE evals (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    prompt TEXT NOT NULL,
+                    first_provider TEXT NOT NULL,
+                    first_model TEXT NOT NULL,
+                    first_answer TEXT NOT NULL,
+                    second_provider TEXT NOT NULL,
+                    second_model TEXT NOT NULL,
+                    second_answer TEXT NOT NULL,
+                    winner INTEGER,
+                    created_at INTEGER NOT NULL
+                );",
+            )
+            .context("Failed to add A/B evals")?;
+            tx.pragma_update(None, "user_version", 13)?;
+            tx.commit()?;
+        }
+        if version < 14 {
+            let tx = self.conn.transaction()?;
+            tx.execute_batch("ALTER TABLE conversations ADD COLUMN locked INTEGER NOT NULL DEFAULT 0")
+                .context("Failed to add conversation locks")?;
+            tx.pragma_update(None, "user_version", 14)?;
+            tx.commit()?;
+        }
+        if version < 15 {
+            // Tombstones come from purging alone now, not every delete, so
+            // pruning on one machine doesn't delete on the others
+            let tx = self.conn.transaction()?;
+            tx.execute_batch("DROP TRIGGER IF EXISTS conversations_tombstone")
+                .context("Failed to drop the delete trigger")?;
+            tx.pragma_update(None, "user_version", 15)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Start an empty conversation and return its id
+    pub fn create_conversation(&self, provider: &str, title: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO conversations (provider, title, created_at) VALUES (?1, ?2, ?3)",
+            params![provider, title, now()?],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn conversation_settings(&self, id: i64) -> Result<ChatSettings> {
+        Ok(self.conn.query_row(
+            "SELECT model, system_prompt, temperature, persona FROM conversations WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(ChatSettings {
+                    model: row.get(0)?,
+                    system_prompt: row.get(1)?,
+                    temperature: row.get(2)?,
+                    persona: row.get(3)?,
+                })
+            },
+        )?)
+    }
+
+    pub fn set_conversation_settings(&self, id: i64, settings: &ChatSettings) -> Result<()> {
+        self.conn.execute(
+            "UPDATE conversations SET model = ?1, system_prompt = ?2, temperature = ?3, persona = ?4 WHERE id = ?5",
+            params![settings.model, settings.system_prompt, settings.temperature, settings.persona, id],
+        )?;
+        Ok(())
+    }
+
+    /// Store an imported conversation. Returns None if one from the same
+    /// source is already stored.
+    pub fn import_conversation(&mut self, conversation: &NewConversation) -> Result<Option<i64>> {
+        let tx = self.conn.transaction()?;
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO conversations (provider, title, created_at, source) VALUES (?1, ?2, ?3, ?4)",
+            params![conversation.provider, conversation.title, conversation.created_at, conversation.source],
+        )?;
+        if inserted == 0 {
+            return Ok(None);
+        }
+
+        let id = tx.last_insert_rowid();
+        for (seq, message) in (1..).zip(&conversation.messages) {
+            tx.execute(
+                "INSERT INTO messages (conversation_id, seq, role, content, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![id, seq, message.role, message.content, message.timestamp],
+            )?;
+        }
+        tx.commit()?;
+        Ok(Some(id))
+    }
+
+    pub fn rename_conversation(&self, id: i64, title: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE conversations SET title = ?1 WHERE id = ?2",
+            params![title, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_archived(&self, id: i64, archived: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE conversations SET archived = ?1 WHERE id = ?2",
+            params![archived, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_locked(&self, id: i64, locked: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE conversations SET locked = ?1 WHERE id = ?2",
+            params![locked, id],
+        )?;
+        Ok(())
+    }
+
+    /// Replace a conversation's tags
+    pub fn set_tags(&mut self, id: i64, tags: &[String]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM conversation_tags WHERE conversation_id = ?1", params![id])?;
+        for tag in tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO conversation_tags (conversation_id, tag) VALUES (?1, ?2)",
+                params![id, normalize_tag(tag)],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn set_starred(&self, message_id: i64, starred: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE messages SET starred = ?1 WHERE id = ?2",
+            params![starred, message_id],
+        )?;
+        Ok(())
+    }
+
+    /// Rate a reply, or take its rating back with None
+    pub fn set_rating(&self, message_id: i64, rating: Option<Rating>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE messages SET rating = ?1 WHERE id = ?2",
+            params![rating.as_ref().map(Rating::score), message_id],
+        )?;
+        Ok(())
+    }
+
+    /// Note something on a reply, or remove its note with None
+    pub fn set_note(&self, message_id: i64, note: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE messages SET note = ?1 WHERE id = ?2",
+            params![note, message_id],
+        )?;
+        Ok(())
+    }
+
+    /// Keep `content` as the snippet `name`. Returns true if that replaced
+    /// a snippet of the same name.
+    pub fn save_snippet(&self, name: &str, content: &str) -> Result<bool> {
+        let existed = self
+            .conn
+            .query_row("SELECT 1 FROM snippets WHERE name = ?1", params![name], |_| Ok(()))
+            .optional()?
+            .is_some();
+        self.conn.execute(
+            "INSERT INTO snippets (name, content, saved_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (name) DO UPDATE SET content = excluded.content, saved_at = excluded.saved_at",
+            params![name, content, now()?],
+        )?;
+        Ok(existed)
+    }
+
+    /// Every snippet, by name
+    pub fn list_snippets(&self) -> Result<Vec<Snippet>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name, content, saved_at FROM snippets ORDER BY name")?;
+        let snippets = stmt.query_map([], |row| {
+            Ok(Snippet {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                content: row.get(2)?,
+                saved_at: row.get(3)?,
+            })
+        })?;
+        Ok(snippets.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn delete_snippet(&self, id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM snippets WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Move a conversation to the trash, where it's hidden everywhere but
+    /// can still be restored. A locked one stays where it is.
+    pub fn delete_conversation(&self, id: i64) -> Result<()> {
+        let moved = self.conn.execute(
+            "UPDATE conversations SET deleted_at = ?1 WHERE id = ?2 AND NOT locked",
+            params![now()?, id],
+        )?;
+        if moved == 0 && self.get_conversation(id)?.is_some_and(|c| c.locked) {
+            bail!("Conversation #{} is locked; unlock it first", id);
+        }
+        Ok(())
+    }
+
+    /// Take a conversation back out of the trash
+    pub fn restore_conversation(&self, id: i64) -> Result<()> {
+        self.conn.execute("UPDATE conversations SET deleted_at = NULL WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Delete a conversation along with its messages, for good. It leaves
+    /// a tombstone, so other machines delete it too when they next sync.
+    pub fn purge_conversation(&self, id: i64) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO sync_tombstones (uid, deleted_at) SELECT uid, ?1 FROM conversations WHERE id = ?2",
+            params![now()?, id],
+        )?;
+        tx.execute("DELETE FROM conversations WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Purge everything in the trash, leaving tombstones as
+    /// `purge_conversation` does. Returns how many conversations went.
+    pub fn empty_trash(&self) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO sync_tombstones (uid, deleted_at)
+             SELECT uid, ?1 FROM conversations WHERE deleted_at IS NOT NULL",
+            params![now()?],
+        )?;
+        let purged = tx.execute("DELETE FROM conversations WHERE deleted_at IS NOT NULL", [])?;
+        tx.commit()?;
+        Ok(purged)
+    }
+
+    /// Conversations in the trash, most recently deleted first
+    pub fn list_trash(&self) -> Result<Vec<Conversation>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE c.deleted_at IS NOT NULL
+             GROUP BY c.id
+             ORDER BY c.deleted_at DESC, c.id DESC",
+            CONVERSATION_QUERY
+        ))?;
+        let conversations = stmt.query_map([], |row| conversation_from_row(row, 0))?;
+
+        Ok(conversations.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Copy a conversation and all its messages, returning the copy's id
+    pub fn duplicate_conversation(&mut self, id: i64) -> Result<i64> {
+        self.copy_conversation(id, None, " (copy)")
+    }
+
+    /// Copy a conversation to another provider to carry on there,
+    /// returning the copy's id. Its model setting stays behind, since it
+    /// names one of the old provider's models.
+    pub fn copy_to_provider(&mut self, id: i64, provider: &str, title_suffix: &str) -> Result<i64> {
+        self.copy_conversation(id, Some(provider), title_suffix)
+    }
+
+    fn copy_conversation(&mut self, id: i64, provider: Option<&str>, title_suffix: &str) -> Result<i64> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO conversations (provider, title, created_at, model, system_prompt, temperature, persona)
+             SELECT COALESCE(?3, provider), title || ?4, ?2, CASE WHEN ?3 IS NULL THEN model END,
+                    system_prompt, temperature, persona
+             FROM conversations WHERE id = ?1",
+            params![id, now()?, provider, title_suffix],
+        )?;
+        let copy = tx.last_insert_rowid();
+        tx.execute(
+            "INSERT INTO messages
+                 (conversation_id, seq, role, content, timestamp, model, prompt_tokens, completion_tokens, latency_ms, cost, starred,
+                  rating, note)
+             SELECT ?2, seq, role, content, timestamp, model, prompt_tokens, completion_tokens, latency_ms, cost, starred,
+                    rating, note
+             FROM messages WHERE conversation_id = ?1 ORDER BY seq ASC",
+            params![id, copy],
+        )?;
+        tx.execute(
+            "INSERT INTO conversation_tags (conversation_id, tag)
+             SELECT ?2, tag FROM conversation_tags WHERE conversation_id = ?1",
+            params![id, copy],
+        )?;
+        tx.commit()?;
+        Ok(copy)
+    }
+
+    pub fn get_conversation(&self, id: i64) -> Result<Option<Conversation>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("{} WHERE c.id = ?1 GROUP BY c.id", CONVERSATION_QUERY),
+                params![id],
+                |row| conversation_from_row(row, 0),
+            )
+            .optional()?)
+    }
+
+    /// Conversations with one provider, or all of them, most recently
+    /// active first. Archived ones are included, trashed ones aren't.
+    pub fn list_conversations(&self, provider: Option<&str>) -> Result<Vec<Conversation>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR c.provider = ?1) AND c.deleted_at IS NULL
+             GROUP BY c.id
+             ORDER BY MAX(COALESCE(m.id, 0)) DESC, c.id DESC",
+            CONVERSATION_QUERY
+        ))?;
+        let conversations = stmt.query_map(params![provider], |row| conversation_from_row(row, 0))?;
+
+        Ok(conversations.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Full-text search over every message, best matches first. `tag:`
+    /// and `is:starred` terms narrow it down; with only those, the newest
+    /// matching messages come first.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let query = SearchQuery::parse(query);
+        let fts = fts_query(&query.text);
+        if fts.is_none() && query.tags.is_empty() && !query.starred {
+            return Ok(Vec::new());
+        }
+
+        let mut filters =
+            " AND fm.conversation_id IN (SELECT id FROM conversations WHERE deleted_at IS NULL)".to_string();
+        if query.starred {
+            filters.push_str(" AND fm.starred = 1");
+        }
+        let mut values: Vec<rusqlite::types::Value> = vec![
+            fts.clone().unwrap_or_default().into(),
+            MATCH_START.to_string().into(),
+            MATCH_END.to_string().into(),
+            (limit as i64).into(),
+        ];
+        for tag in &query.tags {
+            values.push(tag.clone().into());
+            filters.push_str(&format!(
+                " AND EXISTS (SELECT 1 FROM conversation_tags t WHERE t.conversation_id = fm.conversation_id AND t.tag = ?{})",
+                values.len()
+            ));
+        }
+        let hits_query = match fts {
+            Some(_) => format!(
+                "SELECT messages_fts.rowid AS message_id, messages_fts.rank AS rank,
+                        snippet(messages_fts, 0, ?2, ?3, '…', 12) AS snippet
+                 FROM messages_fts JOIN messages fm ON fm.id = messages_fts.rowid
+                 WHERE messages_fts MATCH ?1{}
+                 ORDER BY messages_fts.rank LIMIT ?4",
+                filters
+            ),
+            None => format!(
+                "SELECT fm.id AS message_id, -fm.timestamp AS rank, substr(fm.content, 1, 200) AS snippet
+                 FROM messages fm WHERE ?1 = ''{}
+                 ORDER BY fm.timestamp DESC LIMIT ?4",
+                filters
+            ),
+        };
+
+        let mut stmt = self.conn.prepare(&format!(
+            "WITH hits AS ({})
+             SELECT h.message_id, hm.role, h.snippet, sub.*
+             FROM hits h
+             JOIN messages hm ON hm.id = h.message_id
+             JOIN ({} GROUP BY c.id) sub ON sub.id = hm.conversation_id
+             ORDER BY h.rank",
+            hits_query, CONVERSATION_QUERY
+        ))?;
+        let hits = stmt.query_map(
+            rusqlite::params_from_iter(values),
+            |row| {
+                Ok(SearchHit {
+                    message_id: row.get(0)?,
+                    role: row.get(1)?,
+                    snippet: row.get(2)?,
+                    conversation: conversation_from_row(row, 3)?,
+                })
+            },
+        )?;
+
+        Ok(hits.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// The conversation to reopen for a provider on startup
+    pub fn latest_conversation(&self, provider: &str) -> Result<Option<Conversation>> {
+        Ok(self
+            .list_conversations(Some(provider))?
+            .into_iter()
+            .find(|c| !c.archived))
+    }
+
+    /// Append a message, after every other message in the conversation
+    /// whatever the clock says. Returns its id and wall-clock time.
+    pub fn save_message(
+        &self,
+        conversation_id: i64,
+        role: &str,
+        content: &str,
+        usage: Option<&Usage>,
+    ) -> Result<(i64, i64)> {
+        let timestamp = now()?;
+        self.conn.execute(
+            "INSERT INTO messages
+                 (conversation_id, seq, role, content, timestamp, model, prompt_tokens, completion_tokens, latency_ms, cost)
+             SELECT ?1, COALESCE(MAX(seq), 0) + 1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9
+             FROM messages WHERE conversation_id = ?1",
+            params![
+                conversation_id,
+                role,
+                content,
+                timestamp,
+                usage.map(|u| &u.model),
+                usage.and_then(|u| u.prompt_tokens),
+                usage.and_then(|u| u.completion_tokens),
+                usage.map(|u| u.latency_ms),
+                usage.and_then(Usage::cost),
+            ],
+        )?;
+        Ok((self.conn.last_insert_rowid(), timestamp))
+    }
+
+    pub fn get_messages(&self, conversation_id: i64) -> Result<Vec<Message>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE conversation_id = ?1 ORDER BY seq ASC, id ASC",
+            MESSAGE_QUERY
+        ))?;
+        let messages: Result<Vec<Message>, _> = stmt.query_map(params![conversation_id], message_from_row)?.collect();
+        Ok(messages?)
+    }
+
+    /// The last `limit` messages before `before`, a message's `(seq, id)`,
+    /// or the conversation's last `limit` when it's None; in order, as
+    /// `get_messages` has them. No limit reads all of them.
+    pub fn get_messages_before(
+        &self,
+        conversation_id: i64,
+        before: Option<(i64, i64)>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Message>> {
+        let (seq, id) = before.unzip();
+        let limit = limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE conversation_id = ?1 AND (?2 IS NULL OR seq < ?2 OR (seq = ?2 AND id < ?3))
+             ORDER BY seq DESC, id DESC LIMIT ?4",
+            MESSAGE_QUERY
+        ))?;
+        let messages: Result<Vec<Message>, _> =
+            stmt.query_map(params![conversation_id, seq, id, limit], message_from_row)?.collect();
+        let mut messages = messages?;
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// How many messages come before `before`, a message's `(seq, id)`
+    pub fn count_messages_before(&self, conversation_id: i64, before: (i64, i64)) -> Result<usize> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages
+             WHERE conversation_id = ?1 AND (seq < ?2 OR (seq = ?2 AND id < ?3))",
+            params![conversation_id, before.0, before.1],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Messages (sent and received) with each provider, busiest first
+    pub fn messages_per_provider(&self) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.provider, COUNT(*) FROM messages m JOIN conversations c ON c.id = m.conversation_id
+             GROUP BY c.provider ORDER BY COUNT(*) DESC",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Tokens and cost of replies since `since` (Unix seconds), per local
+    /// day or week, oldest first. Periods without replies are left out.
+    pub fn usage_per_period(&self, period: Period, since: i64) -> Result<Vec<PeriodUsage>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} AS start, SUM(COALESCE(prompt_tokens, 0) + COALESCE(completion_tokens, 0)),
+                    SUM(COALESCE(cost, 0))
+             FROM messages WHERE model IS NOT NULL AND timestamp >= ?1
+             GROUP BY start ORDER BY start ASC",
+            period.bucket_sql()
+        ))?;
+        let rows = stmt.query_map(params![since], |row| {
+            Ok(PeriodUsage {
+                start: row.get(0)?,
+                tokens: row.get(1)?,
+                cost: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Mean reply time of every model that has answered, slowest first
+    pub fn latency_per_model(&self) -> Result<Vec<ModelLatency>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT model, AVG(latency_ms), COUNT(*) FROM messages
+             WHERE model IS NOT NULL AND latency_ms IS NOT NULL
+             GROUP BY model ORDER BY AVG(latency_ms) DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ModelLatency {
+                model: row.get(0)?,
+                average_ms: row.get(1)?,
+                replies: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Keep an A/B round's answers and which side won: 0 for the first,
+    /// 1 for the second, None for a tie
+    pub fn record_eval(&self, prompt: &str, sides: &[EvalSide; 2], winner: Option<usize>) -> Result<()> {
+        let [first, second] = sides;
+        self.conn.execute(
+            "INSERT INTO evals (prompt, first_provider, first_model, first_answer,
+                                second_provider, second_model, second_answer, winner, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                prompt,
+                first.provider,
+                first.model,
+                first.answer,
+                second.provider,
+                second.model,
+                second.answer,
+                winner.map(|w| w as i64),
+                now()?
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Every provider's A/B record, best win rate first
+    pub fn eval_standings(&self) -> Result<Vec<EvalStanding>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT provider, COUNT(*), SUM(won), SUM(tied) FROM (
+                 SELECT first_provider AS provider, IFNULL(winner = 0, 0) AS won, winner IS NULL AS tied FROM evals
+                 UNION ALL
+                 SELECT second_provider, IFNULL(winner = 1, 0), winner IS NULL FROM evals
+             )
+             GROUP BY provider ORDER BY (SUM(won) + SUM(tied) / 2.0) / COUNT(*) DESC, COUNT(*) DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(EvalStanding {
+                provider: row.get(0)?,
+                rounds: row.get(1)?,
+                wins: row.get(2)?,
+                ties: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Messages sent by the user in each local hour of the day
+    pub fn messages_per_hour(&self) -> Result<[i64; 24]> {
+        let mut stmt = self.conn.prepare(
+            "SELECT CAST(strftime('%H', timestamp, 'unixepoch', 'localtime') AS INTEGER), COUNT(*)
+             FROM messages WHERE role = 'user' GROUP BY 1",
+        )?;
+        let mut hours = [0; 24];
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, usize>(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (hour, count) = row?;
+            if let Some(slot) = hours.get_mut(hour) {
+                *slot = count;
+            }
+        }
+        Ok(hours)
+    }
+}
+
+/// How usage is bucketed over time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Day,
+    Week,
+}
+
+impl Period {
+    /// Local date the bucket starts on; weeks start on Monday
+    fn bucket_sql(self) -> &'static str {
+        match self {
+            Period::Day => "date(timestamp, 'unixepoch', 'localtime')",
+            Period::Week => "date(timestamp, 'unixepoch', 'localtime', 'weekday 0', '-6 days')",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PeriodUsage {
+    /// First day of the period, YYYY-MM-DD
+    pub start: String,
+    pub tokens: i64,
+    /// US dollars
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelLatency {
+    pub model: String,
+    pub average_ms: f64,
+    pub replies: i64,
+}
+
+/// One model's answer in an A/B round
+#[derive(Debug, Clone)]
+pub struct EvalSide {
+    pub provider: String,
+    pub model: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalStanding {
+    pub provider: String,
+    /// Rounds it was in; twice for a round against itself
+    pub rounds: i64,
+    pub wins: i64,
+    pub ties: i64,
+}
+
+impl EvalStanding {
+    /// Share of rounds won, a tie counting as half a win
+    pub fn win_rate(&self) -> f64 {
+        (self.wins as f64 + self.ties as f64 / 2.0) / self.rounds.max(1) as f64
+    }
+}
+
+/// Selects `Conversation` columns; callers add WHERE/GROUP BY c.id
+const CONVERSATION_QUERY: &str = "SELECT c.id, c.provider, c.title, date(c.created_at, 'unixepoch', 'localtime'),
+        COUNT(m.id), c.archived,
+        (SELECT group_concat(tag, ' ') FROM (SELECT tag FROM conversation_tags WHERE conversation_id = c.id ORDER BY tag)),
+        COALESCE(SUM(m.starred), 0), date(c.deleted_at, 'unixepoch', 'localtime'), c.locked
+ FROM conversations c LEFT JOIN messages m ON m.conversation_id = c.id";
+
+/// Columns `CONVERSATION_QUERY` selects
+const CONVERSATION_COLUMNS: usize = 10;
+
+fn conversation_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Conversation> {
+    let tags: Option<String> = row.get(first + 6)?;
+    Ok(Conversation {
+        id: row.get(first)?,
+        provider: row.get(first + 1)?,
+        title: row.get(first + 2)?,
+        created_on: row.get(first + 3)?,
+        message_count: row.get(first + 4)?,
+        archived: row.get(first + 5)?,
+        tags: tags.map_or_else(Vec::new, |tags| tags.split(' ').map(str::to_string).collect()),
+        starred_count: row.get(first + 7)?,
+        deleted_on: row.get(first + 8)?,
+        locked: row.get(first + 9)?,
+    })
+}
+
+/// Selects `Message` columns; callers add WHERE/ORDER BY
+const MESSAGE_QUERY: &str = "SELECT id, seq, role, content, timestamp, model, prompt_tokens, completion_tokens, latency_ms,
+        cost, starred, rating, note
+ FROM messages";
+
+fn message_from_row(row: &rusqlite::Row) -> rusqlite::Result<Message> {
+    let model: Option<String> = row.get(5)?;
+    Ok(Message {
+        id: row.get(0)?,
+        seq: row.get(1)?,
+        role: row.get(2)?,
+        content: row.get(3)?,
+        timestamp: row.get(4)?,
+        usage: match model {
+            Some(model) => Some(MessageUsage {
+                model,
+                prompt_tokens: row.get(6)?,
+                completion_tokens: row.get(7)?,
+                latency_ms: row.get(8)?,
+                cost: row.get(9)?,
+            }),
+            None => None,
+        },
+        starred: row.get(10)?,
+        rating: row.get::<_, Option<i64>>(11)?.and_then(Rating::from_score),
+        note: row.get(12)?,
+    })
+}
+
+/// Search or filter text split into its parts: `tag:rust` and
+/// `is:starred` terms, and the words left over
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    pub tags: Vec<String>,
+    pub starred: bool,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut query = SearchQuery::default();
+        let mut words = Vec::new();
+        for word in input.split_whitespace() {
+            match word.split_once(':') {
+                Some(("tag", tag)) if !tag.is_empty() => query.tags.push(normalize_tag(tag)),
+                Some(("is", "starred")) => query.starred = true,
+                _ => words.push(word),
+            }
+        }
+        query.text = words.join(" ");
+        query
+    }
+
+    /// Whether a conversation passes the filters, with the remaining
+    /// words matched against its title
+    pub fn matches(&self, conversation: &Conversation) -> bool {
+        let title = conversation.title.to_lowercase();
+        self.tags.iter().all(|tag| conversation.tags.contains(tag))
+            && (!self.starred || conversation.starred_count > 0)
+            && self.text.split_whitespace().all(|word| title.contains(&word.to_lowercase()))
+    }
+}
+
+/// Tags are lowercase words; `#Rust` and `rust` are the same tag
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().trim_start_matches('#').to_lowercase().replace(char::is_whitespace, "-")
+}
+
+/// Turn what the user typed into an FTS5 query: every word must appear,
+/// the last one as a prefix so results show up while typing. Words are
+/// quoted so punctuation can't be read as query syntax.
+fn fts_query(input: &str) -> Option<String> {
+    let words: Vec<String> = input
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    let last = words.last()?;
+    let mut query = words[..words.len() - 1].join(" ");
+    query.push_str(&format!(" {}*", last));
+    Some(query.trim_start().to_string())
+}
+
+/// False for an encrypted (or corrupt) file
+fn is_readable(conn: &Connection) -> bool {
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(())).is_ok()
+}
+
+#[cfg(feature = "encryption")]
+use encryption::open_encrypted;
+
+#[cfg(not(feature = "encryption"))]
+fn open_encrypted(db_path: &Path) -> Result<Connection> {
+    bail!(
+        "{} is encrypted or not a database (this build has no encryption support; enable the `encryption` feature)",
+        db_path.display()
+    )
+}
+
+/// `~/...` paths from the config file, and `~\...` on Windows, where the
+/// home directory comes from the user profile when HOME isn't set
+pub fn expand_home(path: &str) -> PathBuf {
+    let rest = path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\").filter(|_| cfg!(windows)));
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .or_else(|| directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf()));
+    match (rest, home) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Rename, falling back to copy-and-delete across filesystems
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+fn now() -> Result<i64> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64)
+}
+
+/// v1: one `messages` table keyed by conversation. Each provider's old
+/// rolling history becomes a single conversation.
+fn migrate_conversations(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE conversations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            provider TEXT NOT NULL,
+            title TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        CREATE TABLE messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            conversation_id INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+        CREATE INDEX messages_by_conversation ON messages (conversation_id, id);
+        CREATE INDEX conversations_by_provider ON conversations (provider);",
+    )?;
+
+    for provider in LEGACY_PROVIDERS {
+        let table_name = format!("{}_messages", provider);
+        let exists = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table_name],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if !exists {
+            continue;
+        }
+
+        let first: Option<i64> = conn.query_row(
+            &format!("SELECT MIN(timestamp) FROM {}", table_name),
+            [],
+            |row| row.get(0),
+        )?;
+        if let Some(created_at) = first {
+            conn.execute(
+                "INSERT INTO conversations (provider, title, created_at) VALUES (?1, 'Earlier chats', ?2)",
+                params![provider, created_at],
+            )?;
+            let id = conn.last_insert_rowid();
+            conn.execute(
+                &format!(
+                    "INSERT INTO messages (conversation_id, role, content, timestamp)
+                     SELECT ?1, role, content, timestamp FROM {} ORDER BY id ASC",
+                    table_name
+                ),
+                params![id],
+            )?;
+        }
+        conn.execute(&format!("DROP TABLE {}", table_name), [])?;
+    }
+
+    Ok(())
+}
+
+/// v4: FTS5 index over message text, kept in sync by triggers
+fn migrate_search_index(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE messages_fts USING fts5(content, content = 'messages', content_rowid = 'id');
+        CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');",
+    )?;
+    Ok(())
+}
+
+/// v5: explicit per-conversation ordering, numbered from the existing
+/// insertion order
+fn migrate_message_order(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE messages ADD COLUMN seq INTEGER NOT NULL DEFAULT 0;
+        UPDATE messages SET seq = (
+            SELECT COUNT(*) FROM messages earlier
+            WHERE earlier.conversation_id = messages.conversation_id AND earlier.id <= messages.id
+        );
+        CREATE INDEX messages_by_seq ON messages (conversation_id, seq);",
+    )?;
+    Ok(())
+}
+
+/// v7: ids that are the same on every machine, for sync. New rows get
+/// theirs from triggers so no insert has to know about them. Deleted
+/// conversations leave a tombstone so sync doesn't bring them back.
+fn migrate_sync_ids(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE conversations ADD COLUMN uid TEXT;
+        ALTER TABLE messages ADD COLUMN uid TEXT;
+        UPDATE conversations SET uid = lower(hex(randomblob(16)));
+        UPDATE messages SET uid = lower(hex(randomblob(16)));
+        CREATE UNIQUE INDEX conversations_by_uid ON conversations (uid);
+        CREATE UNIQUE INDEX messages_by_uid ON messages (uid);
+        CREATE TRIGGER conversations_uid AFTER INSERT ON conversations WHEN new.uid IS NULL BEGIN
+            UPDATE conversations SET uid = lower(hex(randomblob(16))) WHERE id = new.id;
+        END;
+        CREATE TRIGGER messages_uid AFTER INSERT ON messages WHEN new.uid IS NULL BEGIN
+            UPDATE messages SET uid = lower(hex(randomblob(16))) WHERE id = new.id;
+        END;
+        CREATE TABLE sync_tombstones (
+            uid TEXT PRIMARY KEY,
+            deleted_at INTEGER NOT NULL
+        );
+        CREATE TRIGGER conversations_tombstone AFTER DELETE ON conversations BEGIN
+            INSERT OR IGNORE INTO sync_tombstones (uid, deleted_at) VALUES (old.uid, CAST(strftime('%s', 'now') AS INTEGER));
+        END;
+        CREATE TABLE meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        INSERT INTO meta (key, value) VALUES ('device_id', lower(hex(randomblob(8))));",
+    )?;
+    Ok(())
+}
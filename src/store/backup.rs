@@ -0,0 +1,172 @@
+//! Consistent snapshots of the database: `animated-cli backup`/`restore` and
+//! rotating automatic backups
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{expand_home, is_readable, move_file, Database, LEGACY_PROVIDERS};
+use crate::config::BackupConfig;
+
+/// Automatic backups are named `conversations-<timestamp>.db`
+const AUTO_PREFIX: &str = "conversations-";
+const AUTO_SUFFIX: &str = ".db";
+
+impl Database {
+    /// Write a compacted copy of the whole database to `path`, consistent
+    /// even while the chat is writing
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            bail!("{} already exists", path.display());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let target = path.to_str().with_context(|| format!("{:?} is not valid UTF-8", path))?;
+        self.conn
+            .execute("VACUUM INTO ?1", [target])
+            .with_context(|| format!("Failed to back up to {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Take an automatic backup if the newest one is older than the
+    /// configured interval, then drop all but the newest `keep`. Returns
+    /// the new backup's path, if one was taken.
+    pub fn auto_backup(&self, db_path: &Path, config: &BackupConfig) -> Result<Option<PathBuf>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let dir = backup_dir(db_path, config);
+        let mut backups = auto_backups(&dir)?;
+
+        let interval = Duration::from_secs(config.every_hours * 3600);
+        let newest_age = backups
+            .last()
+            .and_then(|newest| newest.metadata().ok()?.modified().ok()?.elapsed().ok());
+        if newest_age.is_some_and(|age| age < interval) {
+            return Ok(None);
+        }
+
+        let path = dir.join(format!("{}{}{}", AUTO_PREFIX, Local::now().format("%Y%m%d-%H%M%S"), AUTO_SUFFIX));
+        self.backup_to(&path)?;
+        backups.push(path.clone());
+
+        let excess = backups.len().saturating_sub(config.keep.max(1));
+        for old in &backups[..excess] {
+            std::fs::remove_file(old).with_context(|| format!("Failed to remove old backup {}", old.display()))?;
+        }
+        Ok(Some(path))
+    }
+}
+
+/// Where automatic backups go: the configured directory, or `backups`
+/// next to the database
+fn backup_dir(db_path: &Path, config: &BackupConfig) -> PathBuf {
+    match config.dir {
+        Some(ref dir) => expand_home(dir),
+        None => db_path.parent().unwrap_or(Path::new(".")).join("backups"),
+    }
+}
+
+/// Existing automatic backups, oldest first (their names sort by time)
+fn auto_backups(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(AUTO_PREFIX) && name.ends_with(AUTO_SUFFIX))
+        })
+        .collect();
+    backups.sort();
+    Ok(backups)
+}
+
+/// The newest automatic backup that passes a quick check. Encrypted
+/// backups can't be looked into here, so they count; `restore` checks
+/// them once it has the passphrase.
+pub(super) fn newest_healthy_backup(db_path: &Path, config: &BackupConfig) -> Option<PathBuf> {
+    let backups = auto_backups(&backup_dir(db_path, config)).ok()?;
+    backups.into_iter().rev().find(|path| {
+        let Ok(conn) = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY) else {
+            return false;
+        };
+        !is_readable(&conn)
+            || conn
+                .query_row("PRAGMA quick_check", [], |row| row.get::<_, String>(0))
+                .is_ok_and(|check| check == "ok")
+    })
+}
+
+/// Run `animated-cli backup`
+pub fn backup(db_path: &Path, to: &Path) -> Result<()> {
+    let db = Database::open(db_path)?;
+    db.backup_to(to)?;
+    println!("Backed up {} to {}", db_path.display(), to.display());
+    Ok(())
+}
+
+/// Run `animated-cli restore`: check the backup opens and is a schema this
+/// build understands (upgrading it if it's older), then swap it in. The
+/// replaced database is kept alongside as `.before-restore`. The restored
+/// copy gets its own sync device id, since it may be another machine's.
+pub fn restore(db_path: &Path, from: &Path) -> Result<()> {
+    if !from.exists() {
+        bail!("{} does not exist", from.display());
+    }
+    check_is_history(from)?;
+
+    let staging = db_path.with_extension("db.restoring");
+    std::fs::copy(from, &staging).with_context(|| format!("Failed to copy {}", from.display()))?;
+    let verified = Database::open(&staging).and_then(|db| {
+        let check: String = db.conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+        if check != "ok" {
+            bail!("{} is damaged: {}", from.display(), check);
+        }
+        db.conn.execute("UPDATE meta SET value = lower(hex(randomblob(8))) WHERE key = 'device_id'", [])?;
+        Ok(())
+    });
+    if let Err(e) = verified {
+        let _ = std::fs::remove_file(&staging);
+        return Err(e.context(format!("Not restoring {}", from.display())));
+    }
+
+    let previous = db_path.with_extension("db.before-restore");
+    if db_path.exists() {
+        move_file(db_path, &previous)?;
+    }
+    move_file(&staging, db_path)?;
+
+    println!("Restored {} from {}", db_path.display(), from.display());
+    if previous.exists() {
+        println!("The database it replaced is at {}", previous.display());
+    }
+    Ok(())
+}
+
+/// Refuse SQLite files that aren't conversation history. Encrypted files
+/// can't be looked into yet and are checked when opened.
+fn check_is_history(path: &Path) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if !is_readable(&conn) {
+        return Ok(());
+    }
+
+    let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let legacy = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter_map(|name| name.ok())
+        .any(|name| LEGACY_PROVIDERS.iter().any(|p| name == format!("{}_messages", p)));
+    if version == 0 && !legacy {
+        bail!("{} is not a conversation database", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! SQLCipher at-rest encryption. The passphrase comes from
+//! `MEGA_CLI_DB_KEY`, then the OS keychain, then a terminal prompt.
+
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, Connection, DatabaseName};
+use std::path::Path;
+
+/// Environment variable holding the passphrase, for scripts
+const KEY_ENV: &str = "MEGA_CLI_DB_KEY";
+
+/// Keychain entry the passphrase is saved under
+const KEYCHAIN_SERVICE: &str = "mega-cli";
+const KEYCHAIN_USER: &str = "database";
+
+/// Open an encrypted database, asking for the passphrase if it isn't
+/// stored anywhere
+pub(super) fn open_encrypted(path: &Path) -> Result<Connection> {
+    let passphrase = match stored_passphrase() {
+        Some(passphrase) => passphrase,
+        None => rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))
+            .context("Failed to read passphrase")?,
+    };
+
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "key", &passphrase)?;
+    if !super::is_readable(&conn) {
+        bail!("Wrong passphrase for {}", path.display());
+    }
+    Ok(conn)
+}
+
+fn stored_passphrase() -> Option<String> {
+    std::env::var(KEY_ENV).ok().or_else(|| {
+        keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_USER)
+            .and_then(|entry| entry.get_password())
+            .ok()
+    })
+}
+
+/// Rewrite a plaintext database encrypted under a new passphrase,
+/// optionally saving the passphrase to the OS keychain
+pub fn encrypt(path: &Path, keychain: bool) -> Result<()> {
+    let plain = Connection::open(path).with_context(|| format!("Failed to open database at {:?}", path))?;
+    if !super::is_readable(&plain) {
+        bail!("{} is already encrypted", path.display());
+    }
+
+    let passphrase = rpassword::prompt_password("New passphrase: ").context("Failed to read passphrase")?;
+    if passphrase.is_empty() {
+        bail!("The passphrase can't be empty");
+    }
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        bail!("Passphrases don't match");
+    }
+
+    let staging = path.with_extension("db.encrypting");
+    let _ = std::fs::remove_file(&staging);
+    let version: i32 = plain.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    plain.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        params![staging.to_string_lossy(), passphrase],
+    )?;
+    plain.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+    // sqlcipher_export copies tables but not the schema version
+    plain.pragma_update(Some(DatabaseName::Attached("encrypted")), "user_version", version)?;
+    plain.execute("DETACH DATABASE encrypted", [])?;
+    drop(plain);
+
+    std::fs::rename(&staging, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+
+    if keychain {
+        keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_USER)
+            .and_then(|entry| entry.set_password(&passphrase))
+            .context("Failed to save the passphrase to the keychain")?;
+        println!("Passphrase saved to the keychain");
+    }
+    println!("Encrypted {}", path.display());
+    Ok(())
+}
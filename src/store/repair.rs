@@ -0,0 +1,360 @@
+//! Catching a damaged database on launch, and `animated-cli repair`: put back
+//! the newest healthy backup, or salvage every row that can still be read
+//! into a fresh database
+
+use anyhow::{bail, Context, Result};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, ErrorCode, OpenFlags, Statement};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use super::backup::newest_healthy_backup;
+use super::{is_readable, move_file, restore, Database};
+use crate::config::BackupConfig;
+
+/// Tables copied by a salvage, parents before children
+const SALVAGED_TABLES: [&str; 7] = [
+    "conversations",
+    "conversation_tags",
+    "messages",
+    "sync_tombstones",
+    "meta",
+    "snippets",
+    "evals",
+];
+
+/// Most problems an integrity check lists before stopping
+const MAX_PROBLEMS: i32 = 20;
+
+/// What a salvage brought across
+#[derive(Debug, Default)]
+pub struct SalvageReport {
+    pub conversations: usize,
+    pub messages: usize,
+    /// Messages whose conversation couldn't be read
+    pub orphaned: usize,
+    /// Tables that couldn't be read at all
+    pub unreadable: Vec<String>,
+}
+
+impl Database {
+    /// What `PRAGMA integrity_check` finds wrong; empty when healthy.
+    /// `quick` runs `quick_check` instead, which skips matching indexes
+    /// against their tables and so takes a fraction of the time on a big
+    /// database.
+    pub fn integrity_problems(&self, quick: bool) -> Result<Vec<String>> {
+        let pragma = if quick { "quick_check" } else { "integrity_check" };
+        let mut stmt = self.conn.prepare(&format!("PRAGMA {}({})", pragma, MAX_PROBLEMS))?;
+        let problems = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(problems.into_iter().filter(|p| p != "ok").collect())
+    }
+}
+
+/// Whether opening or checking failed because the file is damaged, rather
+/// than missing, locked or encrypted
+fn is_corruption(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<rusqlite::Error>())
+        .any(|e| e.sqlite_error_code() == Some(ErrorCode::DatabaseCorrupt))
+}
+
+enum Health {
+    Intact(Database),
+    /// What's wrong with it
+    Damaged(String),
+}
+
+/// Open the database and check it's intact, `quick`ly or not. Damage
+/// isn't an Err; anything else that stops it opening is.
+fn check(path: &Path, quick: bool) -> Result<Health> {
+    let db = match Database::open(path) {
+        Ok(db) => db,
+        Err(e) if is_corruption(&e) => return Ok(Health::Damaged(format!("{:#}", e))),
+        Err(e) => return Err(e),
+    };
+    match db.integrity_problems(quick) {
+        Ok(problems) if problems.is_empty() => Ok(Health::Intact(db)),
+        Ok(problems) => Ok(Health::Damaged(problems.join("; "))),
+        Err(e) if is_corruption(&e) => Ok(Health::Damaged(format!("{:#}", e))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Open the database for the chat, after a quick check so a big one
+/// doesn't hold up the launch. If it's damaged, ask whether to restore the
+/// newest healthy backup or salvage what can be read; declining (or having
+/// no terminal to ask on) is an error, so the chat runs without history
+/// rather than on a broken file.
+pub fn open_checked(path: &Path, backups: &BackupConfig) -> Result<Database> {
+    let damage = match check(path, true)? {
+        Health::Intact(db) => return Ok(db),
+        Health::Damaged(damage) => damage,
+    };
+
+    let backup = newest_healthy_backup(path, backups);
+    if !std::io::stdin().is_terminal() {
+        bail!("{} is damaged ({}); run `animated-cli repair` to fix it", path.display(), damage);
+    }
+
+    eprintln!("The conversation database at {} is damaged:\n  {}\n", path.display(), damage);
+    if let Some(ref backup) = backup {
+        eprintln!("  [b] Restore the newest healthy backup, {}", backup.display());
+    }
+    eprintln!("  [s] Salvage every conversation that can still be read into a fresh database");
+    eprintln!("  [c] Carry on without history this time");
+    eprint!("\nChoice: ");
+    std::io::stderr().flush()?;
+
+    let mut choice = String::new();
+    std::io::stdin().lock().read_line(&mut choice)?;
+    match (choice.trim().to_lowercase().as_str(), backup) {
+        ("b", Some(backup)) => restore(path, &backup)?,
+        ("s", _) => print_salvage(path, &salvage(path)?),
+        _ => bail!("{} is damaged ({}); run `animated-cli repair` to fix it", path.display(), damage),
+    }
+    Database::open(path)
+}
+
+/// Run `animated-cli repair`: check the database, and if it's damaged restore
+/// the newest healthy backup, or salvage it when asked to or when there's
+/// no backup to go back to
+pub fn repair(path: &Path, backups: &BackupConfig, force_salvage: bool) -> Result<()> {
+    if !path.exists() {
+        bail!("{} does not exist", path.display());
+    }
+    let damage = match check(path, false)? {
+        Health::Intact(_) => {
+            println!("No problems found in {}", path.display());
+            return Ok(());
+        }
+        Health::Damaged(damage) => damage,
+    };
+    println!("{} is damaged: {}", path.display(), damage);
+
+    match newest_healthy_backup(path, backups).filter(|_| !force_salvage) {
+        Some(backup) => restore(path, &backup),
+        None => {
+            print_salvage(path, &salvage(path)?);
+            Ok(())
+        }
+    }
+}
+
+fn print_salvage(path: &Path, report: &SalvageReport) {
+    println!(
+        "Salvaged {} conversations and {} messages into a fresh {}",
+        report.conversations,
+        report.messages,
+        path.display()
+    );
+    if report.orphaned > 0 {
+        println!("{} messages were left out because their conversation couldn't be read", report.orphaned);
+    }
+    if !report.unreadable.is_empty() {
+        println!("Couldn't read: {}", report.unreadable.join(", "));
+    }
+    println!("The damaged database is kept at {}", path.with_extension("db.damaged").display());
+}
+
+/// Copy every readable row into a fresh database and swap it in, keeping
+/// the damaged file alongside as `.damaged`
+pub fn salvage(path: &Path) -> Result<SalvageReport> {
+    let damaged = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if !is_readable(&damaged) {
+        bail!("{} is encrypted or too damaged to read; restore a backup instead", path.display());
+    }
+
+    let staging = path.with_extension("db.salvaging");
+    let _ = std::fs::remove_file(&staging);
+    let fresh = Database::open(&staging)?;
+    let salvaged = copy_readable(&damaged, &fresh.conn);
+    drop(fresh);
+    let report = match salvaged {
+        Ok(report) => report,
+        Err(e) => {
+            let _ = std::fs::remove_file(&staging);
+            return Err(e.context(format!("Failed to salvage {}", path.display())));
+        }
+    };
+
+    drop(damaged);
+    move_file(path, &path.with_extension("db.damaged"))?;
+    move_file(&staging, path)?;
+    Ok(report)
+}
+
+fn copy_readable(damaged: &Connection, fresh: &Connection) -> Result<SalvageReport> {
+    let mut report = SalvageReport::default();
+    // Children can outlive their parent's row; they're tidied up below
+    fresh.pragma_update(None, "foreign_keys", false)?;
+    fresh.execute_batch("BEGIN")?;
+
+    for table in SALVAGED_TABLES {
+        let columns = shared_columns(damaged, fresh, table);
+        if columns.is_empty() {
+            report.unreadable.push(table.to_string());
+            continue;
+        }
+        let copied = copy_table(damaged, fresh, table, &columns)?;
+        match table {
+            "conversations" => report.conversations = copied,
+            "messages" => report.messages = copied,
+            _ => {}
+        }
+    }
+
+    report.orphaned = fresh.execute(
+        "DELETE FROM messages WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+        [],
+    )?;
+    report.messages -= report.orphaned;
+    fresh.execute(
+        "DELETE FROM conversation_tags WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+        [],
+    )?;
+
+    fresh.execute_batch("COMMIT")?;
+    fresh.pragma_update(None, "foreign_keys", true)?;
+    Ok(report)
+}
+
+/// Columns the table has in both databases; the damaged one may be an
+/// older schema, or its schema may not be readable at all
+fn shared_columns(damaged: &Connection, fresh: &Connection, table: &str) -> Vec<String> {
+    let columns = |conn: &Connection| -> Vec<String> {
+        conn.prepare(&format!("PRAGMA table_info({})", table))
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get::<_, String>(1))?.collect())
+            .unwrap_or_default()
+    };
+    let old = columns(damaged);
+    columns(fresh).into_iter().filter(|c| old.contains(c)).collect()
+}
+
+/// Read the table front to back until a damaged page stops the scan, then
+/// back to front from the end until it stops again, so the rows on either
+/// side of the damage both come across. Returns how many were copied.
+fn copy_table(damaged: &Connection, fresh: &Connection, table: &str, columns: &[String]) -> Result<usize> {
+    let list = columns.join(", ");
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let mut insert = fresh.prepare(&format!("INSERT OR REPLACE INTO {} ({}) VALUES ({})", table, list, placeholders))?;
+    let scan = |order: &str| format!("SELECT rowid, {} FROM {} WHERE rowid > ?1 ORDER BY rowid {}", list, table, order);
+
+    let (forward, last) = copy_rows(damaged, &scan("ASC"), i64::MIN, &mut insert, columns.len())?;
+    let (backward, _) = copy_rows(damaged, &scan("DESC"), last, &mut insert, columns.len())?;
+    Ok(forward + backward)
+}
+
+/// Copy rows from the scan until it ends or hits damage. Returns how many
+/// were copied and the rowid of the last one.
+fn copy_rows(
+    damaged: &Connection,
+    scan: &str,
+    after: i64,
+    insert: &mut Statement,
+    width: usize,
+) -> Result<(usize, i64)> {
+    let (mut copied, mut last) = (0, after);
+    let Ok(mut stmt) = damaged.prepare(scan) else {
+        return Ok((copied, last));
+    };
+    let Ok(mut rows) = stmt.query([after]) else {
+        return Ok((copied, last));
+    };
+    while let Ok(Some(row)) = rows.next() {
+        let read = row
+            .get::<_, i64>(0)
+            .and_then(|rowid| Ok((rowid, (1..=width).map(|i| row.get(i)).collect::<Result<Vec<Value>, _>>()?)));
+        let Ok((rowid, values)) = read else {
+            break;
+        };
+        copied += insert.execute(params_from_iter(values))?;
+        last = rowid;
+    }
+    Ok((copied, last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh database file under the temp dir, named for the test
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("mega-cli-repair-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("conversations.db")
+    }
+
+    #[test]
+    fn a_healthy_database_checks_clean() {
+        let path = scratch("healthy");
+        let db = Database::open(&path).unwrap();
+        db.create_conversation("claude", "Fine").unwrap();
+        assert_eq!(db.integrity_problems(false).unwrap(), Vec::<String>::new());
+        drop(db);
+        assert!(matches!(check(&path, true).unwrap(), Health::Intact(_)));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn salvage_drops_messages_whose_conversation_is_gone() {
+        let path = scratch("orphans");
+        let db = Database::open(&path).unwrap();
+        let kept = db.create_conversation("claude", "Kept").unwrap();
+        let lost = db.create_conversation("gpt", "Lost").unwrap();
+        for id in [kept, kept, lost] {
+            db.save_message(id, "user", "hello", None).unwrap();
+        }
+        db.conn.pragma_update(None, "foreign_keys", false).unwrap();
+        db.conn.execute("DELETE FROM conversations WHERE id = ?1", [lost]).unwrap();
+        drop(db);
+
+        let report = salvage(&path).unwrap();
+        assert_eq!((report.conversations, report.messages, report.orphaned), (1, 2, 1));
+        assert!(report.unreadable.is_empty());
+        assert!(path.with_extension("db.damaged").exists());
+
+        let db = Database::open(&path).unwrap();
+        let conversations = db.list_conversations(None).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].title, "Kept");
+        assert_eq!(db.get_messages(kept).unwrap().len(), 2);
+        drop(db);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn a_damaged_page_is_caught_and_the_rows_around_it_salvaged() {
+        let path = scratch("damaged");
+        let db = Database::open(&path).unwrap();
+        let id = db.create_conversation("claude", "Long").unwrap();
+        for i in 0..200 {
+            db.save_message(id, "user", &format!("{:04} {}", i, "x".repeat(1000)), None).unwrap();
+        }
+        drop(db);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let middle = bytes.len() / 4096 / 2 * 4096;
+        bytes[middle..middle + 4096].fill(0xff);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let Health::Damaged(damage) = check(&path, false).unwrap() else {
+            panic!("damage went unnoticed");
+        };
+        assert!(!damage.is_empty());
+
+        let report = salvage(&path).unwrap();
+        // One page holds three of these messages; everything either side of it comes across
+        assert_eq!((report.conversations, report.messages), (1, 197));
+        let Health::Intact(db) = check(&path, false).unwrap() else {
+            panic!("the salvaged database is damaged too");
+        };
+        let messages = db.get_messages(id).unwrap();
+        assert!(messages.first().unwrap().content.starts_with("0000 "));
+        assert!(messages.last().unwrap().content.starts_with("0199 "));
+        drop(db);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+//! Age and size limits on history, applied on startup and by
+//! `animated-cli prune`
+
+use anyhow::{bail, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+use super::{conversation_from_row, now, Conversation, Database, CONVERSATION_COLUMNS, CONVERSATION_QUERY};
+use crate::config::RetentionConfig;
+
+/// Rough per-row overhead (keys, timestamps, index entries) used when
+/// estimating how much space a conversation takes
+const ROW_OVERHEAD: i64 = 64;
+
+/// What applying the policy did, or would do
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub archived: Vec<Conversation>,
+    /// Moved to the trash for being too old, where they can be restored
+    pub trashed: Vec<Conversation>,
+    /// Purged to fit the size cap. Only from this machine: sync doesn't
+    /// pass these on.
+    pub deleted: Vec<Conversation>,
+    /// Database size in bytes before
+    pub size_before: u64,
+    /// Database size in bytes after; estimated for a dry run
+    pub size_after: u64,
+}
+
+impl PruneReport {
+    pub fn is_empty(&self) -> bool {
+        self.archived.is_empty() && self.trashed.is_empty() && self.deleted.is_empty()
+    }
+
+    /// One line for the footer
+    pub fn summary(&self) -> String {
+        let parts: Vec<String> = [("archived", &self.archived), ("trashed", &self.trashed), ("deleted", &self.deleted)]
+            .into_iter()
+            .filter(|(_, conversations)| !conversations.is_empty())
+            .map(|(verb, conversations)| format!("{} {}", verb, conversations.len()))
+            .collect();
+        format!("Retention: {} old conversations", parts.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Archive,
+    Delete,
+}
+
+/// A conversation with what the policy needs to know about it
+struct Candidate {
+    conversation: Conversation,
+    /// Newest message, or creation time for an empty conversation
+    last_active: i64,
+    /// Estimated bytes it takes up
+    weight: i64,
+}
+
+impl Database {
+    /// Archive or trash conversations the policy says are too old, then
+    /// purge the oldest, those in the trash first, until the database fits
+    /// the size cap. Locked conversations are left alone. A dry run rolls
+    /// everything back and only reports.
+    pub fn apply_retention(&mut self, policy: &RetentionConfig, dry_run: bool) -> Result<PruneReport> {
+        let action = match policy.action.to_lowercase().as_str() {
+            "archive" => Action::Archive,
+            "delete" => Action::Delete,
+            other => bail!("Unknown retention action: {} (expected archive or delete)", other),
+        };
+        let mut report = PruneReport::default();
+        if policy.max_age_days.is_none() && policy.max_size_mb.is_none() {
+            return Ok(report);
+        }
+
+        let tx = self.conn.transaction()?;
+        let size = database_size(&tx)?;
+        report.size_before = size;
+        let candidates = candidates(&tx)?;
+
+        let mut in_trash: Vec<bool> = candidates.iter().map(|c| c.conversation.deleted_on.is_some()).collect();
+        if let Some(days) = policy.max_age_days {
+            let cutoff = now()? - days as i64 * 86_400;
+            for (i, candidate) in candidates.iter().enumerate() {
+                let c = &candidate.conversation;
+                if candidate.last_active >= cutoff || c.locked || in_trash[i] {
+                    continue;
+                }
+                match action {
+                    Action::Archive if !c.archived => {
+                        tx.execute("UPDATE conversations SET archived = 1 WHERE id = ?1", params![c.id])?;
+                        report.archived.push(c.clone());
+                    }
+                    Action::Archive => {}
+                    Action::Delete => {
+                        tx.execute("UPDATE conversations SET deleted_at = ?1 WHERE id = ?2", params![now()?, c.id])?;
+                        report.trashed.push(c.clone());
+                        in_trash[i] = true;
+                    }
+                }
+            }
+        }
+
+        // Freed space isn't known until VACUUM, so share the file out
+        // between conversations by how much text each holds
+        let total_weight: i64 = candidates.iter().map(|c| c.weight).sum::<i64>().max(1);
+        let share = |weight: i64| (size as f64 * weight as f64 / total_weight as f64) as u64;
+        let mut estimate = size;
+        if let Some(mb) = policy.max_size_mb {
+            let cap = mb * 1024 * 1024;
+            let unlocked = || candidates.iter().zip(&in_trash).filter(|(c, _)| !c.conversation.locked);
+            let trash_first = unlocked().filter(|(_, &trash)| trash).chain(unlocked().filter(|(_, &trash)| !trash));
+            for (candidate, _) in trash_first {
+                if estimate <= cap {
+                    break;
+                }
+                // No tombstone, so other machines keep their copies
+                let c = &candidate.conversation;
+                tx.execute("DELETE FROM conversations WHERE id = ?1", params![c.id])?;
+                report.archived.retain(|a| a.id != c.id);
+                report.trashed.retain(|t| t.id != c.id);
+                report.deleted.push(c.clone());
+                estimate = estimate.saturating_sub(share(candidate.weight));
+            }
+        }
+
+        if dry_run {
+            tx.rollback()?;
+            report.size_after = estimate;
+            return Ok(report);
+        }
+        tx.commit()?;
+        if !report.deleted.is_empty() {
+            self.conn.execute_batch("VACUUM")?;
+        }
+        report.size_after = database_size(&self.conn)?;
+        Ok(report)
+    }
+}
+
+/// Every conversation, least recently active first
+fn candidates(conn: &Connection) -> Result<Vec<Candidate>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT sub.*,
+                COALESCE((SELECT MAX(timestamp) FROM messages WHERE conversation_id = sub.id),
+                         (SELECT created_at FROM conversations WHERE id = sub.id)) AS last_active,
+                (SELECT COALESCE(SUM(length(content)), 0) + COUNT(*) * ?1 FROM messages
+                 WHERE conversation_id = sub.id) + ?1 AS weight
+         FROM ({} GROUP BY c.id) sub
+         ORDER BY last_active ASC, sub.id ASC",
+        CONVERSATION_QUERY
+    ))?;
+    let rows = stmt.query_map(params![ROW_OVERHEAD], |row| {
+        Ok(Candidate {
+            conversation: conversation_from_row(row, 0)?,
+            last_active: row.get(CONVERSATION_COLUMNS)?,
+            weight: row.get(CONVERSATION_COLUMNS + 1)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+fn database_size(conn: &Connection) -> Result<u64> {
+    let pages: u64 = conn.pragma_query_value(None, "page_count", |row| row.get(0))?;
+    let page_size: u64 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+    Ok(pages * page_size)
+}
+
+/// Run `animated-cli prune`
+pub fn prune(path: &Path, policy: &RetentionConfig, dry_run: bool) -> Result<()> {
+    if policy.max_age_days.is_none() && policy.max_size_mb.is_none() {
+        println!("No retention policy set; add max_age_days or max_size_mb under [retention] in config.toml");
+        return Ok(());
+    }
+
+    let mut db = Database::open(path)?;
+    let report = db.apply_retention(policy, dry_run)?;
+    let (archive, trash, delete) = if dry_run {
+        ("Would archive", "Would move to the trash", "Would delete")
+    } else {
+        ("Archived", "Moved to the trash", "Deleted")
+    };
+
+    for (verb, conversations) in [(archive, &report.archived), (trash, &report.trashed), (delete, &report.deleted)] {
+        if conversations.is_empty() {
+            continue;
+        }
+        println!("{} {} conversations:", verb, conversations.len());
+        for c in conversations {
+            let title = if c.title.is_empty() { "Untitled" } else { &c.title };
+            println!("  #{} {} · {} ({}, {} messages)", c.id, c.provider, title, c.created_on, c.message_count);
+        }
+    }
+    if report.is_empty() {
+        println!("Nothing to prune");
+    }
+
+    let approx = if dry_run { "about " } else { "" };
+    println!("Database size: {} → {}{}", human_size(report.size_before), approx, human_size(report.size_after));
+    Ok(())
+}
+
+fn human_size(bytes: u64) -> String {
+    if bytes < 1024 * 1024 {
+        format!("{} KB", bytes / 1024)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400;
+
+    /// A conversation whose one message is `days` old
+    fn conversation(db: &Database, title: &str, days: i64) -> i64 {
+        let id = db.create_conversation("claude", title).unwrap();
+        db.save_message(id, "user", &"x".repeat(1000), None).unwrap();
+        let then = now().unwrap() - days * DAY;
+        db.conn
+            .execute("UPDATE conversations SET created_at = ?1 WHERE id = ?2", params![then, id])
+            .unwrap();
+        db.conn
+            .execute("UPDATE messages SET timestamp = ?1 WHERE conversation_id = ?2", params![then, id])
+            .unwrap();
+        id
+    }
+
+    fn policy(max_age_days: Option<u64>, action: &str, max_size_mb: Option<u64>) -> RetentionConfig {
+        RetentionConfig { max_age_days, action: action.to_string(), max_size_mb }
+    }
+
+    fn ids(conversations: &[Conversation]) -> Vec<i64> {
+        conversations.iter().map(|c| c.id).collect()
+    }
+
+    #[test]
+    fn dry_run_reports_but_changes_nothing() {
+        let mut db = Database::open_in_memory().unwrap();
+        let old = conversation(&db, "old", 100);
+        conversation(&db, "new", 1);
+
+        let report = db.apply_retention(&policy(Some(30), "delete", None), true).unwrap();
+        assert_eq!(ids(&report.trashed), vec![old]);
+        assert_eq!(db.list_conversations(None).unwrap().len(), 2);
+        assert!(db.list_trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn old_conversations_go_to_the_trash_and_locked_ones_stay() {
+        let mut db = Database::open_in_memory().unwrap();
+        let old = conversation(&db, "old", 100);
+        let locked = conversation(&db, "kept", 100);
+        db.set_locked(locked, true).unwrap();
+        let new = conversation(&db, "new", 1);
+
+        let report = db.apply_retention(&policy(Some(30), "delete", None), false).unwrap();
+        assert_eq!(ids(&report.trashed), vec![old]);
+        assert!(report.deleted.is_empty());
+        assert_eq!(ids(&db.list_trash().unwrap()), vec![old]);
+        let mut left = ids(&db.list_conversations(None).unwrap());
+        left.sort();
+        assert_eq!(left, vec![locked, new]);
+
+        // Still there to restore
+        db.restore_conversation(old).unwrap();
+        assert_eq!(db.get_messages(old).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn archive_leaves_recent_and_locked_conversations() {
+        let mut db = Database::open_in_memory().unwrap();
+        let old = conversation(&db, "old", 100);
+        let locked = conversation(&db, "kept", 100);
+        db.set_locked(locked, true).unwrap();
+        conversation(&db, "new", 1);
+
+        let report = db.apply_retention(&policy(Some(30), "archive", None), false).unwrap();
+        assert_eq!(ids(&report.archived), vec![old]);
+        assert!(db.get_conversation(old).unwrap().unwrap().archived);
+        assert!(!db.get_conversation(locked).unwrap().unwrap().archived);
+        assert_eq!(report.summary(), "Retention: archived 1 old conversations");
+    }
+
+    #[test]
+    fn size_cap_purges_the_trash_first_and_spares_locked() {
+        let mut db = Database::open_in_memory().unwrap();
+        let locked = conversation(&db, "kept", 300);
+        db.set_locked(locked, true).unwrap();
+        let oldest = conversation(&db, "oldest", 200);
+        let trashed = conversation(&db, "trashed", 10);
+        db.delete_conversation(trashed).unwrap();
+
+        // A zero cap can't be met, so everything unlocked goes, the trash
+        // first even though it's newer
+        let report = db.apply_retention(&policy(None, "archive", Some(0)), false).unwrap();
+        assert_eq!(ids(&report.deleted), vec![trashed, oldest]);
+        assert_eq!(ids(&db.list_conversations(None).unwrap()), vec![locked]);
+        assert!(db.list_trash().unwrap().is_empty());
+        // Purged by the cap, not by the user, so not passed on by sync
+        let tombstones: i64 = db.conn.query_row("SELECT COUNT(*) FROM sync_tombstones", [], |row| row.get(0)).unwrap();
+        assert_eq!(tombstones, 0);
+    }
+
+    #[test]
+    fn unknown_action_is_an_error() {
+        let mut db = Database::open_in_memory().unwrap();
+        assert!(db.apply_retention(&policy(Some(30), "shred", None), false).is_err());
+    }
+}
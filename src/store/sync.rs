@@ -0,0 +1,409 @@
+//! Sync bundles: every conversation and deletion, keyed by ids that are
+//! the same on every machine. Merging is a union by message id, so two
+//! machines that both added to a conversation end up with both sets of
+//! messages.
+
+use anyhow::{bail, Result};
+use rusqlite::{params, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+
+use super::{now, Database};
+
+/// Identifies the file as a bundle, and which layout it has
+const BUNDLE_FORMAT: &str = "mega-cli-sync";
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    format: String,
+    version: u32,
+    /// Machine that wrote it
+    pub device: String,
+    /// Unix seconds
+    exported_at: i64,
+    conversations: Vec<BundleConversation>,
+    /// Conversations deleted on the writing machine
+    deleted: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleConversation {
+    uid: String,
+    provider: String,
+    title: String,
+    created_at: i64,
+    archived: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    locked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    persona: Option<String>,
+    messages: Vec<BundleMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleMessage {
+    uid: String,
+    seq: i64,
+    role: String,
+    content: String,
+    timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prompt_tokens: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    completion_tokens: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    starred: bool,
+    /// 1 up, -1 down
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rating: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+}
+
+impl Bundle {
+    /// Reject files that aren't bundles, or are from a newer version
+    pub fn check(&self) -> Result<()> {
+        if self.format != BUNDLE_FORMAT {
+            bail!("Not a sync bundle");
+        }
+        if self.version > BUNDLE_VERSION {
+            bail!("Sync bundle v{} is newer than this build understands (v{})", self.version, BUNDLE_VERSION);
+        }
+        Ok(())
+    }
+}
+
+/// What merging a bundle changed
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    pub conversations_added: usize,
+    pub messages_added: usize,
+    pub deleted: usize,
+    /// Conversations the bundle deleted that are kept, being locked here
+    pub locked: Vec<String>,
+}
+
+impl MergeReport {
+    pub fn add(&mut self, other: MergeReport) {
+        self.conversations_added += other.conversations_added;
+        self.messages_added += other.messages_added;
+        self.deleted += other.deleted;
+        self.locked.extend(other.locked);
+    }
+}
+
+impl Database {
+    /// This machine's id, written into the bundles it exports
+    pub fn device_id(&self) -> Result<String> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'device_id'", [], |row| row.get(0))?)
+    }
+
+    /// Everything but the trash, for another machine to merge. Trashed
+    /// conversations reach other machines as deletions once purged.
+    pub fn export_bundle(&self) -> Result<Bundle> {
+        let mut conversations = Vec::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT id, uid, provider, title, created_at, archived, source, model, system_prompt, temperature, persona,
+                    locked
+             FROM conversations WHERE deleted_at IS NULL ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                BundleConversation {
+                    uid: row.get(1)?,
+                    provider: row.get(2)?,
+                    title: row.get(3)?,
+                    created_at: row.get(4)?,
+                    archived: row.get(5)?,
+                    locked: row.get(11)?,
+                    source: row.get(6)?,
+                    tags: Vec::new(),
+                    model: row.get(7)?,
+                    system_prompt: row.get(8)?,
+                    temperature: row.get(9)?,
+                    persona: row.get(10)?,
+                    messages: Vec::new(),
+                },
+            ))
+        })?;
+
+        let mut messages = self.conn.prepare(
+            "SELECT uid, seq, role, content, timestamp, model, prompt_tokens, completion_tokens, latency_ms, cost, starred,
+                    rating, note
+             FROM messages WHERE conversation_id = ?1 ORDER BY seq, id",
+        )?;
+        let mut tags = self
+            .conn
+            .prepare("SELECT tag FROM conversation_tags WHERE conversation_id = ?1 ORDER BY tag")?;
+        for row in rows {
+            let (id, mut conversation) = row?;
+            conversation.tags = tags.query_map(params![id], |row| row.get(0))?.collect::<Result<_, _>>()?;
+            conversation.messages = messages
+                .query_map(params![id], |row| {
+                    Ok(BundleMessage {
+                        uid: row.get(0)?,
+                        seq: row.get(1)?,
+                        role: row.get(2)?,
+                        content: row.get(3)?,
+                        timestamp: row.get(4)?,
+                        model: row.get(5)?,
+                        prompt_tokens: row.get(6)?,
+                        completion_tokens: row.get(7)?,
+                        latency_ms: row.get(8)?,
+                        cost: row.get(9)?,
+                        starred: row.get(10)?,
+                        rating: row.get(11)?,
+                        note: row.get(12)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+            conversations.push(conversation);
+        }
+
+        let deleted = self
+            .conn
+            .prepare("SELECT uid FROM sync_tombstones ORDER BY uid")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(Bundle {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            device: self.device_id()?,
+            exported_at: now()?,
+            conversations,
+            deleted,
+        })
+    }
+
+    /// Bring in whatever the bundle has that this database doesn't:
+    /// deletions first, then new conversations and new messages. Nothing
+    /// here is overwritten; titles only fill in untitled conversations,
+    /// tags are added to the ones already there, and stars and locks only
+    /// ever go on. A conversation locked here outlives its deletion
+    /// elsewhere, and is listed in the report.
+    pub fn merge_bundle(&mut self, bundle: &Bundle) -> Result<MergeReport> {
+        bundle.check()?;
+        let mut report = MergeReport::default();
+        let tx = self.conn.transaction()?;
+
+        for uid in &bundle.deleted {
+            let locked = tx
+                .query_row("SELECT locked FROM conversations WHERE uid = ?1", params![uid], |row| row.get(0))
+                .optional()?
+                .unwrap_or(false);
+            if locked {
+                report.locked.push(uid.clone());
+                continue;
+            }
+            report.deleted += tx.execute("DELETE FROM conversations WHERE uid = ?1 AND NOT locked", params![uid])?;
+            tx.execute(
+                "INSERT OR IGNORE INTO sync_tombstones (uid, deleted_at) VALUES (?1, ?2)",
+                params![uid, now()?],
+            )?;
+        }
+
+        for conversation in &bundle.conversations {
+            let tombstoned = tx
+                .query_row("SELECT 1 FROM sync_tombstones WHERE uid = ?1", params![conversation.uid], |_| Ok(()))
+                .optional()?
+                .is_some();
+            if tombstoned {
+                continue;
+            }
+            merge_conversation(&tx, conversation, &mut report)?;
+        }
+
+        tx.commit()?;
+        Ok(report)
+    }
+}
+
+fn merge_conversation(tx: &Transaction, conversation: &BundleConversation, report: &mut MergeReport) -> Result<()> {
+    let existing: Option<i64> = tx
+        .query_row("SELECT id FROM conversations WHERE uid = ?1", params![conversation.uid], |row| row.get(0))
+        .optional()?;
+    let id = match existing {
+        Some(id) => {
+            tx.execute(
+                "UPDATE conversations SET title = ?1 WHERE id = ?2 AND title = ''",
+                params![conversation.title, id],
+            )?;
+            if conversation.locked {
+                tx.execute("UPDATE conversations SET locked = 1 WHERE id = ?1", params![id])?;
+            }
+            id
+        }
+        None => {
+            // The same import done on both machines gives different uids
+            // but the same source; keep the local copy
+            let inserted = tx.execute(
+                "INSERT OR IGNORE INTO conversations
+                     (uid, provider, title, created_at, archived, source, model, system_prompt, temperature, persona,
+                      locked)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    conversation.uid,
+                    conversation.provider,
+                    conversation.title,
+                    conversation.created_at,
+                    conversation.archived,
+                    conversation.source,
+                    conversation.model,
+                    conversation.system_prompt,
+                    conversation.temperature,
+                    conversation.persona,
+                    conversation.locked
+                ],
+            )?;
+            if inserted == 0 {
+                return Ok(());
+            }
+            report.conversations_added += 1;
+            tx.last_insert_rowid()
+        }
+    };
+
+    for tag in &conversation.tags {
+        tx.execute(
+            "INSERT OR IGNORE INTO conversation_tags (conversation_id, tag) VALUES (?1, ?2)",
+            params![id, tag],
+        )?;
+    }
+
+    let mut added = 0;
+    for m in &conversation.messages {
+        if m.starred {
+            tx.execute("UPDATE messages SET starred = 1 WHERE uid = ?1", params![m.uid])?;
+        }
+        // A rating or note from the other side wins over none here
+        if m.rating.is_some() || m.note.is_some() {
+            tx.execute(
+                "UPDATE messages SET rating = COALESCE(?2, rating), note = COALESCE(?3, note) WHERE uid = ?1",
+                params![m.uid, m.rating, m.note],
+            )?;
+        }
+        added += tx.execute(
+            "INSERT OR IGNORE INTO messages
+                 (conversation_id, uid, seq, role, content, timestamp, model, prompt_tokens, completion_tokens, latency_ms, cost, starred,
+                  rating, note)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                id,
+                m.uid,
+                m.seq,
+                m.role,
+                m.content,
+                m.timestamp,
+                m.model,
+                m.prompt_tokens,
+                m.completion_tokens,
+                m.latency_ms,
+                m.cost,
+                m.starred,
+                m.rating,
+                m.note
+            ],
+        )?;
+    }
+
+    // Both sides may have added messages after the last sync; interleave
+    // them by when they were sent
+    if added > 0 && existing.is_some() {
+        tx.execute(
+            "UPDATE messages SET seq = (
+                SELECT ordered.position FROM (
+                    SELECT id, ROW_NUMBER() OVER (ORDER BY timestamp, seq, id) AS position
+                    FROM messages WHERE conversation_id = ?1
+                ) ordered WHERE ordered.id = messages.id
+             ) WHERE conversation_id = ?1",
+            params![id],
+        )?;
+    }
+    report.messages_added += added;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two machines, the second holding a copy of a conversation started
+    /// on the first. Returns the conversation's id on each.
+    fn synced_pair() -> (Database, Database, i64, i64) {
+        let first = Database::open_in_memory().unwrap();
+        let id = first.create_conversation("claude", "Plans").unwrap();
+        first.save_message(id, "user", "hello", None).unwrap();
+        let mut second = Database::open_in_memory().unwrap();
+        second.merge_bundle(&first.export_bundle().unwrap()).unwrap();
+        let copy = second.list_conversations(None).unwrap()[0].id;
+        (first, second, id, copy)
+    }
+
+    #[test]
+    fn merge_adds_conversations_and_messages_once() {
+        let (first, mut second, id, copy) = synced_pair();
+        assert_eq!(second.get_messages(copy).unwrap()[0].content, "hello");
+
+        first.save_message(id, "assistant", "hi", None).unwrap();
+        let report = second.merge_bundle(&first.export_bundle().unwrap()).unwrap();
+        assert_eq!((report.conversations_added, report.messages_added), (0, 1));
+        let report = second.merge_bundle(&first.export_bundle().unwrap()).unwrap();
+        assert_eq!((report.conversations_added, report.messages_added), (0, 0));
+        assert_eq!(second.get_messages(copy).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn purge_deletes_on_the_other_machine() {
+        let (first, mut second, id, copy) = synced_pair();
+        first.delete_conversation(id).unwrap();
+        first.purge_conversation(id).unwrap();
+
+        let report = second.merge_bundle(&first.export_bundle().unwrap()).unwrap();
+        assert_eq!(report.deleted, 1);
+        assert!(report.locked.is_empty());
+        assert!(second.get_conversation(copy).unwrap().is_none());
+    }
+
+    #[test]
+    fn tombstone_keeps_a_locked_conversation() {
+        let (first, mut second, id, copy) = synced_pair();
+        second.set_locked(copy, true).unwrap();
+        first.delete_conversation(id).unwrap();
+        first.empty_trash().unwrap();
+
+        let bundle = first.export_bundle().unwrap();
+        let report = second.merge_bundle(&bundle).unwrap();
+        assert_eq!(report.deleted, 0);
+        assert_eq!(report.locked, bundle.deleted);
+        assert_eq!(second.get_messages(copy).unwrap().len(), 1);
+        // Nor is the deletion passed on from here
+        assert!(second.export_bundle().unwrap().deleted.is_empty());
+    }
+
+    #[test]
+    fn hard_delete_without_purge_leaves_no_tombstone() {
+        let (first, _, id, _) = synced_pair();
+        first.conn.execute("DELETE FROM conversations WHERE id = ?1", params![id]).unwrap();
+        assert!(first.export_bundle().unwrap().deleted.is_empty());
+    }
+}
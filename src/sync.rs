@@ -0,0 +1,152 @@
+//! `animated-cli sync`: share history between machines through a folder they
+//! both see (Dropbox, Syncthing, a network drive or a git repository), or
+//! by hand with bundle files
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Subcommand};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::SyncConfig;
+use crate::store::{self, Bundle, Database, MergeReport};
+
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    #[command(subcommand)]
+    action: Option<SyncAction>,
+
+    /// Sync folder; overrides `dir` under [sync] in the config
+    #[arg(long)]
+    dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum SyncAction {
+    /// Write all history to a bundle file to carry to another machine
+    Export {
+        path: PathBuf,
+    },
+    /// Merge a bundle written by `sync export` on another machine
+    Import {
+        path: PathBuf,
+    },
+}
+
+/// Run `animated-cli sync`
+pub fn run(args: SyncArgs, db_path: &Path, config: &SyncConfig) -> Result<()> {
+    let mut db = Database::open(db_path)?;
+    match args.action {
+        Some(SyncAction::Export { path }) => {
+            write_bundle(&db.export_bundle()?, &path)?;
+            println!("Wrote {}", path.display());
+        }
+        Some(SyncAction::Import { path }) => {
+            let bundle = read_bundle(&path)?;
+            let report = db.merge_bundle(&bundle)?;
+            print_report(&bundle.device, &report);
+        }
+        None => {
+            let dir = args
+                .dir
+                .or_else(|| config.dir.as_deref().map(store::expand_home))
+                .ok_or_else(|| anyhow!("No sync folder; pass --dir or set `dir` under [sync] in config.toml"))?;
+            sync_folder(&mut db, &dir)?;
+        }
+    }
+    Ok(())
+}
+
+/// Every machine writes its own `<device>.json` to the folder and merges
+/// everyone else's, so no two machines ever write the same file. In a git
+/// repository the folder is pulled first and the bundle pushed after.
+fn sync_folder(db: &mut Database, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let use_git = dir.join(".git").exists();
+    let has_remote = use_git && !git(dir, &["remote"])?.trim().is_empty();
+    if has_remote {
+        git(dir, &["fetch", "--quiet"])?;
+        // Nothing to pull until some machine has pushed
+        if git(dir, &["rev-parse", "--verify", "--quiet", "@{upstream}"]).is_ok() {
+            git(dir, &["pull", "--rebase", "--quiet"])?;
+        }
+    }
+
+    let device = db.device_id()?;
+    let own = format!("{}.json", device);
+    let mut total = MergeReport::default();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let is_bundle = path.extension().is_some_and(|ext| ext == "json");
+        if !is_bundle || path.file_name().is_some_and(|name| name == own.as_str()) {
+            continue;
+        }
+        let bundle = read_bundle(&path)?;
+        let report = db.merge_bundle(&bundle)?;
+        print_report(&bundle.device, &report);
+        total.add(report);
+    }
+
+    let own_path = dir.join(&own);
+    write_bundle(&db.export_bundle()?, &own_path)?;
+    println!("Wrote this machine's history to {}", own_path.display());
+
+    if use_git {
+        git(dir, &["add", &own])?;
+        let staged = !git(dir, &["diff", "--cached", "--name-only"])?.trim().is_empty();
+        if staged {
+            git(dir, &["commit", "--quiet", "-m", &format!("Sync from {}", device)])?;
+        }
+        if has_remote {
+            git(dir, &["push", "--quiet"])?;
+        }
+    }
+
+    if total.conversations_added + total.messages_added + total.deleted == 0 {
+        println!("Already up to date");
+    }
+    Ok(())
+}
+
+fn read_bundle(path: &Path) -> Result<Bundle> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let bundle: Bundle = serde_json::from_reader(std::io::BufReader::new(file))
+        .with_context(|| format!("{} is not a sync bundle", path.display()))?;
+    bundle.check().with_context(|| format!("Can't merge {}", path.display()))?;
+    Ok(bundle)
+}
+
+/// Written beside the target and renamed over it, so a machine reading
+/// the folder mid-write never sees half a bundle
+fn write_bundle(bundle: &Bundle, path: &Path) -> Result<()> {
+    let staging = path.with_extension("json.partial");
+    let file = std::fs::File::create(&staging).with_context(|| format!("Failed to create {}", staging.display()))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), bundle)?;
+    std::fs::rename(&staging, path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn print_report(device: &str, report: &MergeReport) {
+    println!(
+        "From {}: {} new conversations, {} new messages, {} deleted",
+        device, report.conversations_added, report.messages_added, report.deleted
+    );
+    if !report.locked.is_empty() {
+        println!(
+            "  Kept {} locked conversations it deleted; unlock and delete them here to match",
+            report.locked.len()
+        );
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
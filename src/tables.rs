@@ -0,0 +1,217 @@
+//! Markdown tables in replies, drawn as tables: columns padded to their
+//! widest cell and aligned as the delimiter row says, with borders between
+//! them. A table wider than the messages panel is cut to fit, and ←/→
+//! scroll every such table sideways.
+
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// A message's text, split into the tables in it and the rest
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block<'a> {
+    Text(String),
+    Table(Table<'a>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table<'a> {
+    pub header: Vec<&'a str>,
+    pub alignments: Vec<Align>,
+    /// Each as many cells as the header
+    pub rows: Vec<Vec<&'a str>>,
+}
+
+/// Split `text` into tables and the text between them. Tables in fenced
+/// code blocks are left as they are. Text blocks don't include the line
+/// breaks either side of a table.
+pub fn split(text: &str) -> Vec<Block<'_>> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut blocks = vec![];
+    let mut plain: Vec<&str> = vec![];
+    let mut fenced = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+        }
+        let alignments = lines.get(i + 1).and_then(|next| delimiter(next));
+        match alignments {
+            Some(alignments) if !fenced && is_row(line) && cells(line).len() == alignments.len() => {
+                let header = cells(line);
+                let mut rows = vec![];
+                i += 2;
+                while let Some(row) = lines.get(i).filter(|row| is_row(row)) {
+                    let mut cells = cells(row);
+                    cells.resize(header.len(), "");
+                    rows.push(cells);
+                    i += 1;
+                }
+                if !plain.is_empty() {
+                    blocks.push(Block::Text(plain.join("\n")));
+                    plain.clear();
+                }
+                blocks.push(Block::Table(Table { header, alignments, rows }));
+            }
+            _ => {
+                plain.push(line);
+                i += 1;
+            }
+        }
+    }
+    if !plain.is_empty() || blocks.is_empty() {
+        blocks.push(Block::Text(plain.join("\n")));
+    }
+    blocks
+}
+
+fn is_row(line: &str) -> bool {
+    line.contains('|') && !line.trim().is_empty()
+}
+
+/// A row's cells, trimmed, without the pipes at either end
+fn cells(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').filter(|rest| !rest.ends_with('\\')).unwrap_or(line);
+    let mut cells = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    for (at, c) in line.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '|' if !escaped => {
+                cells.push(line[start..at].trim());
+                start = at + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    cells.push(line[start..].trim());
+    cells
+}
+
+/// The alignments a delimiter row (`|:---|---:|`) gives its columns, if
+/// `line` is one
+fn delimiter(line: &str) -> Option<Vec<Align>> {
+    if !line.contains('-') {
+        return None;
+    }
+    cells(line)
+        .into_iter()
+        .map(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Align::Center,
+                (false, true) => Align::Right,
+                _ => Align::Left,
+            })
+        })
+        .collect()
+}
+
+/// A cell's text as shown: `\|` is a pipe
+fn shown(cell: &str) -> String {
+    cell.replace("\\|", "|")
+}
+
+impl Table<'_> {
+    /// Each column's width, its widest cell
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = self.header.iter().map(|cell| shown(cell).width()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(shown(cell).width());
+            }
+        }
+        widths
+    }
+
+    /// Columns the table takes up, borders and all
+    pub fn width(&self) -> usize {
+        self.widths().iter().map(|w| w + 3).sum::<usize>() + 1
+    }
+
+    /// The table as rows, `offset` columns in and at most `width` wide,
+    /// with a line under it saying how to see the rest if it doesn't fit.
+    /// Cell text is in `style`, the header bold.
+    pub fn lines(&self, offset: usize, width: usize, style: Style) -> Vec<Line<'static>> {
+        let widths = self.widths();
+        let border = Style::default().fg(Color::DarkGray);
+        let rule = |left: &str, middle: &str, right: &str| -> Vec<(String, Style)> {
+            let bars: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            vec![(format!("{}{}{}", left, bars.join(middle), right), border)]
+        };
+        let row = |cells: &[&str], style: Style| -> Vec<(String, Style)> {
+            let mut pieces = vec![("│".to_string(), border)];
+            for (i, width) in widths.iter().enumerate() {
+                let text = shown(cells.get(i).copied().unwrap_or_default());
+                let gap = width - text.width();
+                let (before, after) = match self.alignments.get(i).copied().unwrap_or(Align::Left) {
+                    Align::Left => (0, gap),
+                    Align::Right => (gap, 0),
+                    Align::Center => (gap / 2, gap - gap / 2),
+                };
+                pieces.push((format!(" {}{}{} ", " ".repeat(before), text, " ".repeat(after)), style));
+                pieces.push(("│".to_string(), border));
+            }
+            pieces
+        };
+
+        let mut rows = vec![rule("┌", "┬", "┐"), row(&self.header, style.bold()), rule("├", "┼", "┤")];
+        for cells in &self.rows {
+            rows.push(row(cells, style));
+        }
+        rows.push(rule("└", "┴", "┘"));
+
+        let full = self.width();
+        let offset = offset.min(full.saturating_sub(width));
+        let mut lines: Vec<Line<'static>> = rows.iter().map(|pieces| clip(pieces, offset, width)).collect();
+        if full > width {
+            let shown_to = (offset + width).min(full);
+            lines.push(Line::styled(
+                format!("{}columns {}–{} of {}, ←/→ scroll", if offset > 0 { "◀ " } else { "" }, offset + 1, shown_to, full),
+                border.italic(),
+            ));
+        }
+        lines
+    }
+}
+
+/// Styled pieces cut to the `width` columns from `offset`. A wide
+/// character cut in half is a space.
+fn clip(pieces: &[(String, Style)], offset: usize, width: usize) -> Line<'static> {
+    let mut spans = vec![];
+    let mut column = 0;
+    for (text, style) in pieces {
+        let mut kept = String::new();
+        for c in text.chars() {
+            let columns = c.width().unwrap_or(0);
+            let (start, end) = (column, column + columns);
+            column = end;
+            if end <= offset || start >= offset + width {
+                continue;
+            }
+            if start < offset || end > offset + width {
+                kept.push_str(&" ".repeat(end.min(offset + width) - start.max(offset)));
+            } else {
+                kept.push(c);
+            }
+        }
+        if !kept.is_empty() {
+            spans.push(Span::styled(kept, *style));
+        }
+    }
+    Line::from(spans)
+}
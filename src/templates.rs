@@ -0,0 +1,109 @@
+//! Prompt templates from `[templates]` in the config, sent with `/t name`.
+//! A template's `{placeholders}` are asked for one at a time before it's
+//! sent:
+//!
+//! ```toml
+//! [templates]
+//! review = "Review this {language} code for bugs:\n\n{input}"
+//! ```
+//!
+//! Only a name of letters, digits, `_` or `-` between braces is a
+//! placeholder, so JSON and code in a template are left alone.
+
+use std::collections::BTreeMap;
+
+/// A template being filled in, placeholder by placeholder
+#[derive(Debug, Clone)]
+pub struct Filling {
+    name: String,
+    template: String,
+    placeholders: Vec<String>,
+    values: Vec<String>,
+}
+
+impl Filling {
+    pub fn new(name: &str, template: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            template: template.to_string(),
+            placeholders: placeholders(template),
+            values: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The placeholder to ask for next, or None once they're all filled
+    pub fn next(&self) -> Option<&str> {
+        self.placeholders.get(self.values.len()).map(String::as_str)
+    }
+
+    /// Which placeholder is next, counting from 1, and how many there are
+    pub fn progress(&self) -> (usize, usize) {
+        (self.values.len() + 1, self.placeholders.len())
+    }
+
+    /// Fill the next placeholder with `value`
+    pub fn answer(&mut self, value: String) {
+        if self.next().is_some() {
+            self.values.push(value);
+        }
+    }
+
+    /// The template with the answers so far in place of their placeholders
+    pub fn message(&self) -> String {
+        let values: BTreeMap<&str, &str> = self
+            .placeholders
+            .iter()
+            .map(String::as_str)
+            .zip(self.values.iter().map(String::as_str))
+            .collect();
+        fill(&self.template, &values)
+    }
+}
+
+/// Placeholder names in `template`, in order and each once
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    for (start, end) in spans(template) {
+        let name = &template[start + 1..end - 1];
+        if !found.iter().any(|f| f == name) {
+            found.push(name.to_string());
+        }
+    }
+    found
+}
+
+/// `template` with each placeholder in `values` replaced; others are left
+/// as they are
+pub fn fill(template: &str, values: &BTreeMap<&str, &str>) -> String {
+    let mut filled = String::new();
+    let mut copied = 0;
+    for (start, end) in spans(template) {
+        if let Some(value) = values.get(&template[start + 1..end - 1]) {
+            filled.push_str(&template[copied..start]);
+            filled.push_str(value);
+            copied = end;
+        }
+    }
+    filled.push_str(&template[copied..]);
+    filled
+}
+
+/// Byte ranges of the `{name}` placeholders in `template`, braces included
+fn spans(template: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    for (start, _) in template.match_indices('{') {
+        let rest = &template[start + 1..];
+        let Some(close) = rest.find('}') else {
+            break;
+        };
+        let name = &rest[..close];
+        if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            spans.push((start, start + close + 2));
+        }
+    }
+    spans
+}
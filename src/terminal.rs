@@ -0,0 +1,213 @@
+//! Putting the terminal back: raw mode and the alternate screen are undone
+//! when the chat ends, when an error returns early, and when the main
+//! thread panics, so the shell works again and the panic message can be
+//! read. The same happens around a Ctrl+Z stop, and the chat takes the
+//! terminal back over once it's continued. Also what the old Windows
+//! console needs to show the chat at all.
+
+use anyhow::Result;
+use crossterm::cursor::Show;
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::Color;
+use std::io::Stdout;
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::sync::Arc;
+use std::sync::Once;
+
+type Backend = CrosstermBackend<Stdout>;
+
+/// The terminal in raw mode on the alternate screen, restored when dropped
+pub struct Tui {
+    terminal: ratatui::Terminal<Backend>,
+}
+
+impl Tui {
+    /// Switch the terminal over for the chat. The first call also installs
+    /// the panic hook.
+    pub fn enter() -> Result<Self> {
+        static HOOK: Once = Once::new();
+        HOOK.call_once(install_panic_hook);
+
+        enable_raw_mode()?;
+        // From here on anything that fails must undo what's been done
+        let entered = execute!(std::io::stdout(), EnterAlternateScreen)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(ratatui::Terminal::new(CrosstermBackend::new(std::io::stdout()))?));
+        match entered {
+            Ok(terminal) => Ok(Self { terminal }),
+            Err(e) => {
+                restore();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Deref for Tui {
+    type Target = ratatui::Terminal<Backend>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl DerefMut for Tui {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Leave raw mode and the alternate screen and show the cursor. Harmless
+/// when they're already undone.
+pub fn restore() {
+    let _ = disable_raw_mode();
+    let _ = execute!(std::io::stdout(), LeaveAlternateScreen, Show);
+}
+
+/// Stop and continue signals from outside, such as `kill -TSTP` or the
+/// shell's `fg`. In raw mode Ctrl+Z comes in as a key rather than a
+/// signal, and the chat suspends itself for it.
+#[cfg(unix)]
+pub struct StopSignals {
+    stop: Arc<AtomicBool>,
+    cont: Arc<AtomicBool>,
+}
+
+#[cfg(unix)]
+impl StopSignals {
+    /// Catch SIGTSTP, which would otherwise stop us with the terminal
+    /// still in raw mode, and watch for SIGCONT
+    pub fn watch() -> Result<Self> {
+        use signal_hook::consts::{SIGCONT, SIGTSTP};
+        let stop = Arc::new(AtomicBool::new(false));
+        let cont = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(SIGTSTP, stop.clone())?;
+        signal_hook::flag::register(SIGCONT, cont.clone())?;
+        Ok(Self { stop, cont })
+    }
+
+    /// Whether a SIGTSTP came in since the last call
+    pub fn stop_requested(&self) -> bool {
+        self.stop.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether we were continued since the last call, after a stop of
+    /// our own or a SIGSTOP that couldn't be caught
+    pub fn continued(&self) -> bool {
+        self.cont.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Put the terminal back and stop, as Ctrl+Z does anywhere else. Returns
+/// once continued, with the terminal taken over again.
+#[cfg(unix)]
+pub fn suspend() -> Result<()> {
+    restore();
+    signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)?;
+    take_over()
+}
+
+/// Raw mode and the alternate screen again, after something else had the
+/// terminal. The screen needs drawing from scratch afterwards.
+#[cfg(unix)]
+pub fn take_over() -> Result<()> {
+    enable_raw_mode()?;
+    execute!(std::io::stdout(), EnterAlternateScreen)?;
+    Ok(())
+}
+
+/// Restore the terminal before the panic message is printed. Only for the
+/// main thread: a panicking decode thread or request task is logged and the
+/// chat carries on, so the screen has to stay as it is.
+fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            restore();
+        }
+        previous(info);
+    }));
+}
+
+/// The 16 colors every console has, as the Windows console draws them
+const SIXTEEN: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (128, 0, 0)),
+    (Color::Green, (0, 128, 0)),
+    (Color::Yellow, (128, 128, 0)),
+    (Color::Blue, (0, 0, 128)),
+    (Color::Magenta, (128, 0, 128)),
+    (Color::Cyan, (0, 128, 128)),
+    (Color::Gray, (192, 192, 192)),
+    (Color::DarkGray, (128, 128, 128)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (0, 0, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Whether the terminal only has its 16 named colors. The old Windows
+/// console does without escape sequences, and draws 24-bit and 256-color
+/// cells black; Windows Terminal and everything else don't.
+pub fn sixteen_colors() -> bool {
+    #[cfg(windows)]
+    {
+        !crossterm::ansi_support::supports_ansi()
+    }
+    #[cfg(not(windows))]
+    {
+        false
+    }
+}
+
+/// Swap the 24-bit and 256-color colors in `area` for the nearest of
+/// the 16 named ones
+pub fn limit_to_sixteen_colors(buf: &mut Buffer, area: Rect) {
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            if let Some(cell) = buf.cell_mut((x, y)) {
+                cell.fg = nearest_named(cell.fg);
+                cell.bg = nearest_named(cell.bg);
+            }
+        }
+    }
+}
+
+fn nearest_named(color: Color) -> Color {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(index) if index < 16 => return SIXTEEN[index as usize].0,
+        Color::Indexed(index) if index < 232 => {
+            // The 6×6×6 cube
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let n = index - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        Color::Indexed(index) => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+        named => return named,
+    };
+    let distance = |(nr, ng, nb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, nr) + d(g, ng) + d(b, nb)
+    };
+    SIXTEEN.iter().min_by_key(|(_, rgb)| distance(*rgb)).map_or(Color::White, |(named, _)| *named)
+}
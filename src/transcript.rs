@@ -0,0 +1,306 @@
+//! The messages panel's text. Each message is laid out once, as rows
+//! wrapped to the panel's width, and kept until it changes or the width,
+//! the day, whose messages are showing or how far wide tables are
+//! scrolled does; a frame then only copies the rows in view onto the
+//! screen. Only a message still being typed out by a replay is laid out
+//! every frame.
+
+use chrono::{Local, NaiveDate, TimeZone};
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use unicode_width::UnicodeWidthChar;
+
+use crate::conversation::{ChatMessage, MessageRole};
+use crate::patch;
+use crate::providers::AIProvider;
+use crate::store::Rating;
+use crate::tables::{self, Block};
+
+/// Columns a tab takes up
+const TAB_WIDTH: usize = 4;
+
+/// What every layout depends on besides the message: the width, the day
+/// (other days' times show their date), whose messages, as the provider's
+/// name and whether they're a replay, and the columns tables are scrolled
+type Basis = (u16, NaiveDate, &'static str, bool, usize);
+
+#[derive(Default)]
+pub struct Transcript {
+    basis: Option<Basis>,
+    /// By the message's place in the conversation
+    messages: Vec<Option<Laid>>,
+}
+
+struct Laid {
+    stamp: Stamp,
+    rows: Vec<Line<'static>>,
+    /// Columns its widest table doesn't fit by
+    overflow: usize,
+}
+
+/// Tells a message from whatever was at its place before: its text (by
+/// address and length, without comparing it), its time, its star, its
+/// rating and its note
+#[derive(PartialEq)]
+struct Stamp {
+    content: (usize, usize),
+    timestamp: i64,
+    starred: bool,
+    rating: Option<Rating>,
+    note: Option<String>,
+}
+
+impl Stamp {
+    fn of(message: &ChatMessage) -> Self {
+        Self {
+            content: (message.content.as_ptr() as usize, message.content.len()),
+            timestamp: message.timestamp,
+            starred: message.starred,
+            rating: message.rating,
+            note: message.note.clone(),
+        }
+    }
+}
+
+/// Who a message is from, as its prefix names them, and their color
+fn speaker<'a>(message: &'a ChatMessage, provider: &AIProvider) -> (&'a str, Color) {
+    match message.role {
+        MessageRole::User => match message.author {
+            Some(ref author) => (author, Color::LightBlue),
+            None => ("You", Color::Green),
+        },
+        MessageRole::Assistant => (provider.name(), provider.color()),
+    }
+}
+
+impl Transcript {
+    /// Drop every layout unless they were for this `basis`
+    pub fn prepare(&mut self, basis: Basis) {
+        if self.basis != Some(basis) {
+            self.basis = Some(basis);
+            self.messages.clear();
+        }
+    }
+
+    /// Columns the widest table laid out so far doesn't fit by, as far as
+    /// tables can usefully scroll
+    pub fn table_overflow(&self) -> usize {
+        self.messages.iter().flatten().map(|laid| laid.overflow).max().unwrap_or(0)
+    }
+
+    /// Draw the messages from `first` into `area`, a blank row between
+    /// each, as far as the bottom; `provider` answered them, and a replay
+    /// has typed `typed` bytes of the last
+    pub fn render(
+        &mut self,
+        buf: &mut Buffer,
+        area: Rect,
+        messages: &[ChatMessage],
+        first: usize,
+        provider: &AIProvider,
+        typed: Option<usize>,
+    ) {
+        let Some((width, today, _, _, offset)) = self.basis else {
+            return;
+        };
+        let mut y = area.top();
+        for (index, message) in messages.iter().enumerate().skip(first) {
+            if y >= area.bottom() {
+                break;
+            }
+            let typing;
+            let rows = match typed {
+                // Changes every frame until it's done, so never kept
+                Some(shown) if index == messages.len() - 1 && shown < message.content.len() => {
+                    typing = lay_out(message, speaker(message, provider), Some(shown), width, today, offset).0;
+                    &typing
+                }
+                _ => self.rows(index, message, provider, width, today, offset),
+            };
+            for row in rows {
+                if y >= area.bottom() {
+                    break;
+                }
+                buf.set_line(area.x, y, row, area.width);
+                y += 1;
+            }
+            y += 1;
+        }
+    }
+
+    fn rows(
+        &mut self,
+        index: usize,
+        message: &ChatMessage,
+        provider: &AIProvider,
+        width: u16,
+        today: NaiveDate,
+        offset: usize,
+    ) -> &[Line<'static>] {
+        if self.messages.len() <= index {
+            self.messages.resize_with(index + 1, || None);
+        }
+        let stamp = Stamp::of(message);
+        let slot = &mut self.messages[index];
+        if slot.as_ref().is_none_or(|laid| laid.stamp != stamp) {
+            let (rows, overflow) = lay_out(message, speaker(message, provider), None, width, today, offset);
+            *slot = Some(Laid { stamp, rows, overflow });
+        }
+        slot.as_ref().map_or(&[], |laid| &laid.rows)
+    }
+}
+
+/// A message as rows `width` wide: its time, star, rating and who it's
+/// from, then its text, with its tables drawn `offset` columns in and its
+/// diffs colored, or the first `typed` bytes of it with a cursor after
+/// them, and its note. Also
+/// returns how many columns its widest table doesn't fit by.
+fn lay_out(
+    message: &ChatMessage,
+    (name, color): (&str, Color),
+    typed: Option<usize>,
+    width: u16,
+    today: NaiveDate,
+    offset: usize,
+) -> (Vec<Line<'static>>, usize) {
+    // A table being typed out stays as it's written until it's done
+    let blocks = match typed {
+        Some(_) => vec![],
+        None => tables::split(&message.content),
+    };
+    let time = sent_at(message.timestamp, today);
+    let prefix = format!("{}: ", name);
+    let color = Style::default().fg(color);
+    let mut pieces = vec![(time.as_str(), Style::default().fg(Color::DarkGray))];
+    if message.starred {
+        pieces.push(("★ ", Style::default().fg(Color::Yellow)));
+    }
+    let rating = message.rating.map(|rating| format!("{} ", rating.emoji()));
+    if let Some(ref rating) = rating {
+        pieces.push((rating, Style::default()));
+    }
+    pieces.push((&prefix, color.bold()));
+    let (mut rows, mut overflow) = (vec![], 0);
+    if let Some(shown) = typed {
+        pieces.push((&message.content[..shown], color));
+        pieces.push(("▌", color));
+    }
+    for block in &blocks {
+        match block {
+            Block::Text(text) => pieces.extend(patch::highlight(text, color)),
+            Block::Table(table) => {
+                // What's before it, the name at least, goes on rows of its own
+                rows.extend(wrap(&pieces, width as usize));
+                pieces.clear();
+                overflow = overflow.max(table.width().saturating_sub(width as usize));
+                rows.extend(table.lines(offset, width as usize, color));
+            }
+        }
+    }
+    if let (Some(ref note), None) = (&message.note, typed) {
+        let style = Style::default().fg(Color::DarkGray).italic();
+        pieces.push((if pieces.is_empty() { "✎ " } else { "\n✎ " }, style));
+        pieces.push((note, style));
+    }
+    if !pieces.is_empty() {
+        rows.extend(wrap(&pieces, width as usize));
+    }
+    (rows, overflow)
+}
+
+/// Local time a message was sent, with the date if it wasn't today
+fn sent_at(timestamp: i64, today: NaiveDate) -> String {
+    match Local.timestamp_opt(timestamp, 0).single() {
+        Some(time) if time.date_naive() == today => time.format("%H:%M ").to_string(),
+        Some(time) => time.format("%Y-%m-%d %H:%M ").to_string(),
+        None => String::new(),
+    }
+}
+
+/// Styled text as rows no wider than `width`: a new row at each line
+/// break, and otherwise between words, or within a word too long for a
+/// row of its own. A space where a row breaks is left out.
+fn wrap(pieces: &[(&str, Style)], width: usize) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    let mut row = Row::default();
+    // The word being read, held until it's known whether it fits
+    let mut word = Row::default();
+
+    for &(text, style) in pieces {
+        for c in text.chars() {
+            match c {
+                '\n' => {
+                    row.place(&mut word, &mut rows, width);
+                    rows.push(row.finish());
+                }
+                ' ' | '\t' => {
+                    row.place(&mut word, &mut rows, width);
+                    let spaces = if c == '\t' { TAB_WIDTH } else { 1 };
+                    for _ in 0..spaces {
+                        if row.width < width {
+                            row.push(' ', 1, style);
+                        } else {
+                            rows.push(row.finish());
+                        }
+                    }
+                }
+                c if c.is_control() => {}
+                c => word.push(c, c.width().unwrap_or(0), style),
+            }
+        }
+    }
+    row.place(&mut word, &mut rows, width);
+    rows.push(row.finish());
+    rows
+}
+
+/// Styled text with its width in columns
+#[derive(Default)]
+struct Row {
+    spans: Vec<(String, Style)>,
+    width: usize,
+}
+
+impl Row {
+    fn push(&mut self, c: char, width: usize, style: Style) {
+        match self.spans.last_mut() {
+            Some((text, last)) if *last == style => text.push(c),
+            _ => self.spans.push((c.to_string(), style)),
+        }
+        self.width += width;
+    }
+
+    /// Move `word` onto the end of this row, or onto the next if it
+    /// doesn't fit, breaking it up if it doesn't fit a row at all
+    fn place(&mut self, word: &mut Row, rows: &mut Vec<Line<'static>>, width: usize) {
+        if word.width == 0 && word.spans.is_empty() {
+            return;
+        }
+        if self.width + word.width > width && self.width > 0 {
+            rows.push(self.finish());
+        }
+        for (text, style) in std::mem::take(&mut word.spans) {
+            for c in text.chars() {
+                let columns = c.width().unwrap_or(0);
+                if self.width + columns > width && self.width > 0 {
+                    rows.push(self.finish());
+                }
+                self.push(c, columns, style);
+            }
+        }
+        word.width = 0;
+    }
+
+    fn finish(&mut self) -> Line<'static> {
+        self.width = 0;
+        Line::from(
+            std::mem::take(&mut self.spans)
+                .into_iter()
+                .map(|(text, style)| Span::styled(text, style))
+                .collect::<Vec<_>>(),
+        )
+    }
+}
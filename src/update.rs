@@ -0,0 +1,279 @@
+//! `animated-cli update`: download the newest GitHub release built for this
+//! platform and swap it in for the running executable. The chat also
+//! looks for a newer release on launch, at most once a day, and mentions
+//! it in the footer.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where releases and issues are
+pub const REPOSITORY: &str = "https://github.com/lalomorales22/animated-cli-chatgpt-grok-gemini-claude";
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/lalomorales22/animated-cli-chatgpt-grok-gemini-claude/releases/latest";
+
+/// Release asset listing each build's SHA-256, as `sha256sum` writes them
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// How long the launch check trusts its last answer
+const CHECK_INTERVAL_SECS: i64 = 24 * 60 * 60;
+
+/// The launch check gives up rather than hold anything up
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(clap::Args, Debug)]
+pub struct UpdateArgs {
+    /// Only say whether there's a newer release
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// A release's `major.minor.patch`, from a tag like `v0.2.1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(u64, u64, u64);
+
+impl Version {
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.trim().trim_start_matches('v');
+        // Pre-release and build suffixes don't count
+        let core = tag.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|part| part.parse().ok());
+        let version = Self(parts.next()??, parts.next().unwrap_or(Some(0))?, parts.next().unwrap_or(Some(0))?);
+        parts.next().is_none().then_some(version)
+    }
+
+    /// This build's
+    pub fn current() -> Self {
+        Self::parse(env!("CARGO_PKG_VERSION")).unwrap_or(Self(0, 0, 0))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// The release asset built for this platform, e.g. `animated-cli-x86_64-linux`
+/// or `animated-cli-aarch64-macos`
+pub fn asset_name() -> String {
+    format!(
+        "animated-cli-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+fn client(timeout: Option<Duration>) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().user_agent(concat!("animated-cli/", env!("CARGO_PKG_VERSION")));
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder.build().context("Failed to set up the HTTP client")
+}
+
+async fn latest_release(http: &reqwest::Client) -> Result<(Release, Version)> {
+    let release: Release = http
+        .get(LATEST_RELEASE_URL)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context("Couldn't reach GitHub for the latest release")?
+        .json()
+        .await
+        .context("GitHub's answer about the latest release wasn't understood")?;
+    let version = Version::parse(&release.tag_name)
+        .ok_or_else(|| anyhow!("The latest release's tag, {}, isn't a version", release.tag_name))?;
+    Ok((release, version))
+}
+
+pub async fn run(args: UpdateArgs) -> Result<()> {
+    let http = client(None)?;
+    let (release, latest) = latest_release(&http).await?;
+    remember(latest);
+    let current = Version::current();
+    if latest <= current {
+        println!("{} is the newest release", current);
+        return Ok(());
+    }
+    if args.check {
+        println!("{} is out (this is {}): {}", latest, current, release.html_url);
+        println!("`animated-cli update` installs it");
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let Some(asset) = release.assets.iter().find(|asset| asset.name == name) else {
+        bail!(
+            "{} has no {} build; install it from source instead:\n  cargo install --git {} --force",
+            latest,
+            name,
+            REPOSITORY
+        );
+    };
+    // Nothing is swapped in that can't be checked
+    let Some(sums) = release.assets.iter().find(|asset| asset.name == CHECKSUMS_ASSET) else {
+        bail!("{} has no {} to check the download against, so it isn't installed", latest, CHECKSUMS_ASSET);
+    };
+    let sums = String::from_utf8_lossy(&download(&http, sums).await?).into_owned();
+    let expected = checksum(&sums, &name)
+        .ok_or_else(|| anyhow!("{} in {} has no checksum for {}, so it isn't installed", CHECKSUMS_ASSET, latest, name))?;
+
+    println!("Downloading {} {}...", name, latest);
+    let binary = download(&http, asset).await?;
+    if binary.is_empty() {
+        bail!("{} downloaded empty", name);
+    }
+    let actual = hex(&Sha256::digest(&binary));
+    if actual != expected {
+        bail!("{} doesn't match its checksum ({} instead of {}), so it isn't installed", name, actual, expected);
+    }
+    let exe = install(&binary)?;
+    println!("Updated {} from {} to {}", exe.display(), current, latest);
+    Ok(())
+}
+
+async fn download(http: &reqwest::Client, asset: &Asset) -> Result<Vec<u8>> {
+    let bytes = http
+        .get(&asset.browser_download_url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("Couldn't download {}", asset.browser_download_url))?
+        .bytes()
+        .await
+        .with_context(|| format!("Download of {} broke off", asset.name))?;
+    Ok(bytes.to_vec())
+}
+
+/// `name`'s SHA-256 in a `sha256sum` listing (`<hex>  <name>`, or
+/// `<hex> *<name>` for one taken in binary mode), lowercase
+fn checksum(sums: &str, name: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (hash, file) = line.trim().split_once(char::is_whitespace)?;
+        let file = file.trim_start();
+        (file.strip_prefix('*').unwrap_or(file) == name).then(|| hash.to_ascii_lowercase())
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Put `binary` in place of the running executable. Written beside it
+/// first, so the swap is a rename and a failed download leaves the old
+/// one as it was.
+fn install(binary: &[u8]) -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Couldn't find the running executable")?;
+    // Replace what a symlink points at, not the link
+    let exe = exe.canonicalize().unwrap_or(exe);
+    let dir = exe.parent().ok_or_else(|| anyhow!("{} has no directory", exe.display()))?;
+    let staged = dir.join(format!(".{}.update", file_name(&exe)));
+    std::fs::write(&staged, binary).with_context(|| {
+        format!("Couldn't write to {}; run `update` as a user that can replace {}", dir.display(), exe.display())
+    })?;
+    let swapped = swap(&staged, &exe);
+    if swapped.is_err() {
+        let _ = std::fs::remove_file(&staged);
+    }
+    swapped.map(|_| exe)
+}
+
+#[cfg(unix)]
+fn swap(staged: &Path, exe: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(staged, std::fs::Permissions::from_mode(0o755))
+        .with_context(|| format!("Couldn't make {} executable", staged.display()))?;
+    // The running process keeps the old file open; the name moves over
+    std::fs::rename(staged, exe).with_context(|| format!("Couldn't replace {}", exe.display()))
+}
+
+/// Windows won't overwrite a running executable, but lets it be renamed
+/// out of the way. The old one is removed on the next update.
+#[cfg(not(unix))]
+fn swap(staged: &Path, exe: &Path) -> Result<()> {
+    let old = exe.with_extension("old.exe");
+    let _ = std::fs::remove_file(&old);
+    std::fs::rename(exe, &old).with_context(|| format!("Couldn't move {} aside", exe.display()))?;
+    std::fs::rename(staged, exe).map_err(|e| {
+        let _ = std::fs::rename(&old, exe);
+        anyhow!(e).context(format!("Couldn't replace {}", exe.display()))
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// The launch check's last answer, so it asks GitHub at most once a day
+#[derive(Serialize, Deserialize)]
+struct LastCheck {
+    /// Unix seconds
+    checked_at: i64,
+    latest: String,
+}
+
+fn last_check_path() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "mega-cli").map(|dirs| dirs.data_dir().join("update-check.json"))
+}
+
+fn remember(latest: Version) {
+    let Some(path) = last_check_path() else {
+        return;
+    };
+    let check = LastCheck { checked_at: Local::now().timestamp(), latest: latest.to_string() };
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| Ok(std::fs::write(&path, serde_json::to_vec(&check)?)?));
+    if let Err(e) = written {
+        tracing::debug!("Couldn't note the update check in {}: {:#}", path.display(), e);
+    }
+}
+
+/// A release newer than this build, if there is one. Asks GitHub only if
+/// it wasn't asked in the last day; any failure is as good as no news.
+pub async fn newer_release() -> Option<Version> {
+    let recent = last_check_path()
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|data| serde_json::from_slice::<LastCheck>(&data).ok())
+        .filter(|check| Local::now().timestamp() - check.checked_at < CHECK_INTERVAL_SECS);
+    let latest = match recent {
+        Some(check) => Version::parse(&check.latest)?,
+        None => {
+            let found = async { latest_release(&client(Some(CHECK_TIMEOUT))?).await };
+            match found.await {
+                Ok((_, latest)) => {
+                    remember(latest);
+                    latest
+                }
+                Err(e) => {
+                    tracing::info!("Update check failed: {:#}", e);
+                    return None;
+                }
+            }
+        }
+    };
+    (latest > Version::current()).then_some(latest)
+}
@@ -0,0 +1,167 @@
+//! Variations: `/vary [n]` asks again for the last reply, n times at
+//! once, each at a temperature from low to high. The answers come back
+//! in a picker, one beside each temperature, and the one picked is added
+//! to the conversation as the next reply.
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+};
+
+use crate::providers::Reply;
+
+const HINTS: &str = "↑/↓ Choose | PgUp/PgDn Scroll | Enter Add to the chat | Esc Cancel";
+
+/// Variations asked for when `/vary` doesn't say
+pub const DEFAULT_COUNT: usize = 3;
+
+/// Most variations `/vary` asks for at once
+pub const MAX_COUNT: usize = 5;
+
+/// The temperatures variations are spread across; every provider takes
+/// these
+const COOLEST: f64 = 0.2;
+const WARMEST: f64 = 1.0;
+
+/// `count` temperatures, evenly spread from cool to warm
+pub fn temperatures(count: usize) -> Vec<f64> {
+    match count {
+        0 => vec![],
+        1 => vec![(COOLEST + WARMEST) / 2.0],
+        _ => (0..count)
+            .map(|i| {
+                let t = COOLEST + (WARMEST - COOLEST) * i as f64 / (count - 1) as f64;
+                (t * 100.0).round() / 100.0
+            })
+            .collect(),
+    }
+}
+
+struct Candidate {
+    temperature: f64,
+    /// The reply, or what went wrong, once it's in
+    answer: Option<Result<Reply, String>>,
+}
+
+/// The answers to one `/vary`, to pick from
+pub struct VariationsView {
+    id: u64,
+    prompt: String,
+    candidates: Vec<Candidate>,
+    state: ListState,
+    scroll: u16,
+}
+
+impl VariationsView {
+    pub fn new(id: u64, prompt: String, temperatures: &[f64]) -> Self {
+        Self {
+            id,
+            prompt,
+            candidates: temperatures
+                .iter()
+                .map(|&temperature| Candidate { temperature, answer: None })
+                .collect(),
+            state: ListState::default().with_selected(Some(0)),
+            scroll: 0,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Take in the answer at the `index`th temperature
+    pub fn answer(&mut self, index: usize, answer: Result<Reply, String>) {
+        if let Some(candidate) = self.candidates.get_mut(index) {
+            candidate.answer = Some(answer);
+        }
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.candidates.iter().any(|c| c.answer.is_none())
+    }
+
+    /// Move the choice, or scroll the answer chosen. Enter returns the
+    /// reply picked, and its temperature, if it came back.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<(f64, Reply)> {
+        match key.code {
+            KeyCode::Up => {
+                self.state.select_previous();
+                self.scroll = 0;
+            }
+            KeyCode::Down => {
+                let next = self.state.selected().map_or(0, |i| (i + 1).min(self.candidates.len() - 1));
+                self.state.select(Some(next));
+                self.scroll = 0;
+            }
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::Enter => {
+                let candidate = self.candidates.get(self.state.selected()?)?;
+                if let Some(Ok(ref reply)) = candidate.answer {
+                    return Some((candidate.temperature, reply.clone()));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&mut self, frame: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4);
+        let height = area.height.saturating_sub(2);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        frame.render_widget(Clear, popup);
+
+        let prompt: String = self.prompt.lines().next().unwrap_or_default().chars().take(60).collect();
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(format!("Variations · {}", prompt))
+            .title_bottom(Line::from(HINTS).centered())
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(popup);
+        frame.render_widget(block, popup);
+
+        let [list_area, answer_area] =
+            Layout::horizontal([Constraint::Length(22), Constraint::Min(0)]).areas(inner);
+        let items: Vec<ListItem> = self
+            .candidates
+            .iter()
+            .map(|c| {
+                let (status, color) = match c.answer {
+                    None => ("waiting…".to_string(), Color::DarkGray),
+                    Some(Ok(ref reply)) => (format!("{} words", reply.content.split_whitespace().count()), Color::Green),
+                    Some(Err(_)) => ("failed".to_string(), Color::Red),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("🌡 {:.2}  ", c.temperature), Style::default().fg(Color::White).bold()),
+                    Span::styled(status, Style::default().fg(color)),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .highlight_style(Style::default().bg(Color::DarkGray))
+            .block(Block::default().borders(Borders::RIGHT).border_style(Style::default().fg(Color::DarkGray)));
+        frame.render_stateful_widget(list, list_area, &mut self.state);
+
+        let chosen = self.state.selected().and_then(|i| self.candidates.get(i));
+        let (text, style) = match chosen.and_then(|c| c.answer.as_ref()) {
+            None => ("Waiting…".to_string(), Style::default().fg(Color::DarkGray)),
+            Some(Ok(reply)) => (reply.content.clone(), Style::default().fg(Color::White)),
+            Some(Err(e)) => (e.clone(), Style::default().fg(Color::Red)),
+        };
+        let answer = Paragraph::new(text)
+            .style(style)
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0))
+            .block(Block::default().padding(ratatui::widgets::Padding::horizontal(1)));
+        frame.render_widget(answer, answer_area);
+    }
+}
@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Context, Result};
+use std::process::Command;
+
+/// yt-dlp format selector: the background is tiny ASCII, so a low
+/// resolution video-only stream is plenty and saves bandwidth
+const FORMAT: &str = "bestvideo[height<=480]/best[height<=480]/best";
+
+pub fn is_youtube_url(source: &str) -> bool {
+    let rest = source
+        .strip_prefix("https://")
+        .or_else(|| source.strip_prefix("http://"))
+        .unwrap_or("");
+    let host = rest.split('/').next().unwrap_or("");
+
+    matches!(
+        host.trim_start_matches("www.").trim_start_matches("m."),
+        "youtube.com" | "youtu.be" | "music.youtube.com"
+    )
+}
+
+/// Shell out to yt-dlp to turn a YouTube page URL into a direct stream URL
+/// that ffmpeg can open
+pub fn resolve_stream_url(ytdlp: &str, url: &str) -> Result<String> {
+    let output = Command::new(ytdlp)
+        .args(["--no-playlist", "--get-url", "--format", FORMAT, url])
+        .output()
+        .with_context(|| format!("Failed to run {} (is yt-dlp installed?)", ytdlp))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("{} failed: {}", ytdlp, stderr.trim()));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("{} returned no stream URL for {}", ytdlp, url))
+}
@@ -0,0 +1,423 @@
+//! Each provider's client against a stand-in of its API: what the request
+//! looks like, and what comes back for a good answer, a rate limit, a body
+//! that isn't JSON and a streamed body. Then the client-side rate limit,
+//! on a paused clock, and `/share` uploading to a paste service.
+
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use wiremock::matchers::{body_partial_json, body_string_contains, header, method, path, query_param};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+
+use mega_cli::config::{PasteConfig, RateLimitConfig};
+use mega_cli::paste;
+use mega_cli::providers::{AIClient, AIProvider, ChatSettings, Message};
+use mega_cli::ratelimit::{Queued, RateLimits};
+use mega_cli::store::Database;
+
+const KEY: &str = "test-key";
+
+fn question() -> Vec<Message> {
+    vec![
+        Message { role: "user".to_string(), content: "Hi".to_string() },
+        Message { role: "assistant".to_string(), content: "Hello".to_string() },
+        Message { role: "user".to_string(), content: "What's 2+2?".to_string() },
+    ]
+}
+
+fn settings() -> ChatSettings {
+    ChatSettings {
+        system_prompt: Some("Answer in digits".to_string()),
+        temperature: Some(0.5),
+        ..ChatSettings::default()
+    }
+}
+
+/// A request to the provider's endpoint for `model`, carrying the key the
+/// way the provider expects it
+fn request_to(provider: &AIProvider, model: &str) -> MockBuilder {
+    let builder = Mock::given(method("POST"));
+    match provider {
+        AIProvider::Claude => builder
+            .and(path("/v1/messages"))
+            .and(header("x-api-key", KEY))
+            .and(header("anthropic-version", "2023-06-01")),
+        AIProvider::Grok | AIProvider::OpenAI => builder
+            .and(path("/v1/chat/completions"))
+            .and(header("authorization", format!("Bearer {}", KEY).as_str())),
+        AIProvider::Gemini => builder
+            .and(path(format!("/v1beta/models/{}:generateContent", model)))
+            .and(query_param("key", KEY)),
+        AIProvider::Mock => unreachable!(),
+    }
+}
+
+/// A successful answer of "4", with 12 tokens in and 1 out
+fn answer(provider: &AIProvider) -> serde_json::Value {
+    match provider {
+        AIProvider::Claude => json!({
+            "content": [{ "type": "text", "text": "4" }],
+            "usage": { "input_tokens": 12, "output_tokens": 1 }
+        }),
+        AIProvider::Grok | AIProvider::OpenAI => json!({
+            "choices": [{ "message": { "role": "assistant", "content": "4" } }],
+            "usage": { "prompt_tokens": 12, "completion_tokens": 1 }
+        }),
+        AIProvider::Gemini => json!({
+            "candidates": [{ "content": { "parts": [{ "text": "4" }] } }],
+            "usageMetadata": { "promptTokenCount": 12, "candidatesTokenCount": 1 }
+        }),
+        AIProvider::Mock => unreachable!(),
+    }
+}
+
+/// An answer with nothing in it
+fn empty_answer(provider: &AIProvider) -> serde_json::Value {
+    match provider {
+        AIProvider::Claude => json!({ "content": [] }),
+        AIProvider::Grok | AIProvider::OpenAI => json!({ "choices": [] }),
+        AIProvider::Gemini => json!({ "candidates": [] }),
+        AIProvider::Mock => unreachable!(),
+    }
+}
+
+/// The question and settings as the provider's request body should carry them
+fn expected_body(provider: &AIProvider, model: &str) -> serde_json::Value {
+    match provider {
+        AIProvider::Claude => json!({
+            "model": model,
+            "stream": false,
+            "system": "Answer in digits",
+            "temperature": 0.5,
+            "messages": [
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello" },
+                { "role": "user", "content": "What's 2+2?" }
+            ]
+        }),
+        AIProvider::Grok => json!({
+            "model": model,
+            "stream": false,
+            "temperature": 0.5,
+            "messages": [
+                { "role": "system", "content": "Answer in digits" },
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello" },
+                { "role": "user", "content": "What's 2+2?" }
+            ]
+        }),
+        // GPT-5 answers at its own temperature; see below
+        AIProvider::OpenAI => json!({
+            "model": model,
+            "stream": false,
+            "messages": [
+                { "role": "system", "content": "Answer in digits" },
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello" },
+                { "role": "user", "content": "What's 2+2?" }
+            ]
+        }),
+        AIProvider::Gemini => json!({
+            "contents": [{ "parts": [{ "text": "user: Hi\n\nassistant: Hello\n\nuser: What's 2+2?" }] }],
+            "systemInstruction": { "parts": [{ "text": "Answer in digits" }] },
+            "generationConfig": { "temperature": 0.5 }
+        }),
+        AIProvider::Mock => unreachable!(),
+    }
+}
+
+fn client_for(provider: &AIProvider, server: &MockServer) -> AIClient {
+    AIClient::new(provider.clone(), Some(KEY.to_string())).with_base_url(server.uri())
+}
+
+/// Error text, causes included
+async fn failure(provider: &AIProvider, response: ResponseTemplate) -> String {
+    let server = MockServer::start().await;
+    request_to(provider, provider.model()).respond_with(response).expect(1).mount(&server).await;
+    let client = client_for(provider, &server);
+    let error = client
+        .send_message(question(), &ChatSettings::default())
+        .await
+        .expect_err("the request should fail");
+    format!("{:#}", error)
+}
+
+#[tokio::test]
+async fn requests_carry_the_conversation_and_settings() {
+    for provider in AIProvider::ALL {
+        let server = MockServer::start().await;
+        request_to(&provider, provider.model())
+            .and(body_partial_json(expected_body(&provider, provider.model())))
+            .respond_with(ResponseTemplate::new(200).set_body_json(answer(&provider)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client_for(&provider, &server);
+        let reply = client.send_message(question(), &settings()).await.unwrap_or_else(|e| {
+            panic!("{} failed: {:#}", provider.name(), e);
+        });
+        assert_eq!(reply.content, "4", "{}", provider.name());
+        assert_eq!(reply.usage.model, provider.model(), "{}", provider.name());
+        assert_eq!(reply.usage.prompt_tokens, Some(12), "{}", provider.name());
+        assert_eq!(reply.usage.completion_tokens, Some(1), "{}", provider.name());
+    }
+}
+
+#[tokio::test]
+async fn a_conversation_model_replaces_the_default() {
+    for (provider, model) in [
+        (AIProvider::Claude, "claude-opus-4-1"),
+        (AIProvider::OpenAI, "gpt-5-mini"),
+        (AIProvider::Gemini, "gemini-2.5-flash"),
+    ] {
+        let server = MockServer::start().await;
+        let mut matched = request_to(&provider, model);
+        if provider != AIProvider::Gemini {
+            matched = matched.and(body_partial_json(json!({ "model": model })));
+        }
+        matched
+            .respond_with(ResponseTemplate::new(200).set_body_json(answer(&provider)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client_for(&provider, &server);
+        let settings = ChatSettings { model: Some(model.to_string()), ..ChatSettings::default() };
+        let reply = client.send_message(question(), &settings).await.unwrap();
+        assert_eq!(reply.usage.model, model);
+    }
+}
+
+#[tokio::test]
+async fn unset_settings_are_left_out() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(answer(&AIProvider::Claude)))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = client_for(&AIProvider::Claude, &server);
+    client.send_message(question(), &ChatSettings::default()).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert!(body.get("system").is_none(), "{}", body);
+    assert!(body.get("temperature").is_none(), "{}", body);
+}
+
+/// The body of the one request a model was sent, with `settings()`
+async fn request_body(provider: &AIProvider, model: &str) -> serde_json::Value {
+    let server = MockServer::start().await;
+    request_to(provider, model)
+        .respond_with(ResponseTemplate::new(200).set_body_json(answer(provider)))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = client_for(provider, &server);
+    let settings = ChatSettings { model: Some(model.to_string()), ..settings() };
+    client.send_message(question(), &settings).await.unwrap();
+    let requests = server.received_requests().await.unwrap();
+    serde_json::from_slice(&requests[0].body).unwrap()
+}
+
+#[tokio::test]
+async fn requests_leave_out_what_the_model_does_not_take() {
+    // Reasoning models refuse a temperature
+    for model in ["gpt-5", "o3"] {
+        let body = request_body(&AIProvider::OpenAI, model).await;
+        assert!(body.get("temperature").is_none(), "{}", body);
+    }
+    let body = request_body(&AIProvider::OpenAI, "gpt-4.1").await;
+    assert_eq!(body["temperature"], 0.5);
+
+    // Gemma has no system instruction; it goes ahead of the conversation
+    let body = request_body(&AIProvider::Gemini, "gemma-3-27b-it").await;
+    assert!(body.get("systemInstruction").is_none(), "{}", body);
+    assert_eq!(
+        body["contents"][0]["parts"][0]["text"],
+        "user: Answer in digits\n\nHi\n\nassistant: Hello\n\nuser: What's 2+2?"
+    );
+    assert_eq!(body["generationConfig"]["temperature"], 0.5);
+}
+
+#[tokio::test]
+async fn rate_limits_are_reported_with_the_status_and_body() {
+    for provider in AIProvider::ALL {
+        let response = ResponseTemplate::new(429).set_body_string("slow down");
+        let error = failure(&provider, response).await;
+        assert!(error.contains("API error 429 Too Many Requests"), "{}", error);
+        assert!(error.contains("slow down"), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn server_errors_are_reported_with_the_status() {
+    for provider in AIProvider::ALL {
+        let error = failure(&provider, ResponseTemplate::new(503)).await;
+        assert!(error.contains("503 Service Unavailable"), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn malformed_json_is_a_parse_error() {
+    for provider in AIProvider::ALL {
+        let response = ResponseTemplate::new(200).set_body_string("{\"content\": [");
+        let error = failure(&provider, response).await;
+        assert!(error.contains("Failed to parse"), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn an_answer_without_content_is_an_error() {
+    for provider in AIProvider::ALL {
+        let response = ResponseTemplate::new(200).set_body_json(empty_answer(&provider));
+        let error = failure(&provider, response).await;
+        assert!(error.contains("No content in"), "{}", error);
+    }
+}
+
+/// Replies are asked for whole (`"stream": false` above); a server that
+/// streams anyway gets a parse error rather than a garbled answer
+#[tokio::test]
+async fn a_streamed_answer_is_a_parse_error() {
+    for provider in AIProvider::ALL {
+        let chunk = serde_json::to_string(&answer(&provider)).unwrap();
+        let response = ResponseTemplate::new(200)
+            .insert_header("content-type", "text/event-stream")
+            .set_body_string(format!("data: {}\n\ndata: [DONE]\n\n", chunk));
+        let error = failure(&provider, response).await;
+        assert!(error.contains("Failed to parse"), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn an_unreachable_server_is_a_send_error() {
+    // Bound and let go, so nothing is listening there
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    for provider in AIProvider::ALL {
+        let client = AIClient::new(provider.clone(), Some(KEY.to_string()))
+            .with_base_url(format!("http://127.0.0.1:{}", port));
+        let error = client.send_message(question(), &ChatSettings::default()).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("Failed to send request to"), "{:#}", error);
+    }
+}
+
+/// The mock provider's client, held to `limit`, and where it reports
+/// waiting requests' places
+fn limited(limit: RateLimitConfig) -> (AIClient, mpsc::UnboundedReceiver<Option<Queued>>) {
+    let limits = RateLimits::new(&BTreeMap::from([("mock".to_string(), limit)]));
+    let (updates, queued) = mpsc::unbounded_channel();
+    let client = AIClient::new(AIProvider::Mock, None)
+        .with_rate_limit(limits.for_provider(&AIProvider::Mock))
+        .reporting_queue(updates);
+    (client, queued)
+}
+
+fn places(queued: &mut mpsc::UnboundedReceiver<Option<Queued>>) -> Vec<Option<Queued>> {
+    std::iter::from_fn(|| queued.try_recv().ok()).collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn requests_over_the_limit_wait_in_line() {
+    let (client, mut queued) = limited(RateLimitConfig { requests_per_minute: Some(1), ..Default::default() });
+    let started = Instant::now();
+    client.send_message(question(), &settings()).await.unwrap();
+    assert!(places(&mut queued).is_empty(), "the first request shouldn't wait");
+
+    let settings = settings();
+    let (second, third) = tokio::join!(
+        client.send_message(question(), &settings),
+        client.send_message(question(), &settings),
+    );
+    second.unwrap();
+    third.unwrap();
+    // A minute after the first for the second, and after that for the third
+    assert!(started.elapsed() >= Duration::from_secs(120), "{:?}", started.elapsed());
+    assert!(started.elapsed() < Duration::from_secs(125), "{:?}", started.elapsed());
+
+    let places = places(&mut queued);
+    let first = places.iter().flatten().next().unwrap();
+    assert_eq!(first.position, 1);
+    assert!(first.ready_in <= Duration::from_secs(60) && first.ready_in >= Duration::from_secs(55), "{:?}", first);
+    assert!(places.iter().flatten().any(|place| place.position == 2), "{:?}", places);
+    assert_eq!(places.last(), Some(&None), "the last to go should report it's on its way");
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_token_limit_counts_what_the_provider_said_it_took() {
+    // Each exchange is estimated at 8 tokens and the mock says it took 16,
+    // so a second fits the estimate but not what the first really took
+    let (client, mut queued) = limited(RateLimitConfig { tokens_per_minute: Some(20), ..Default::default() });
+    let started = Instant::now();
+    client.send_message(question(), &settings()).await.unwrap();
+    client.send_message(question(), &settings()).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(60), "{:?}", started.elapsed());
+    assert!(!places(&mut queued).is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_request_bigger_than_the_token_limit_still_goes() {
+    let (client, _queued) = limited(RateLimitConfig { tokens_per_minute: Some(1), ..Default::default() });
+    let started = Instant::now();
+    client.send_message(question(), &settings()).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(60), "{:?}", started.elapsed());
+    // Once nothing else counts
+    client.send_message(question(), &settings()).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(60), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn a_shared_conversation_leaves_out_attached_files_and_keys() {
+    let db = Database::open_in_memory().unwrap();
+    let id = db.create_conversation("claude", "Config trouble").unwrap();
+    let (message_id, _) = db
+        .save_message(
+            id,
+            "user",
+            "Why does sk-ant-secret-1234 fail with @config.toml?\n\nconfig.toml:\n```toml\npassword = \"hunter22\"\n```",
+            None,
+        )
+        .unwrap();
+    db.set_note(message_id, Some("private aside")).unwrap();
+    db.save_message(id, "assistant", "That key has expired.", None).unwrap();
+
+    let mut api_keys = BTreeMap::new();
+    api_keys.insert("CLAUDE_API_KEY".to_string(), "sk-ant-secret-1234".to_string());
+    let secrets = paste::secrets(&api_keys, &PasteConfig::default());
+    let (title, markdown) = paste::document(&db, id, &secrets).unwrap();
+    assert_eq!(title, "Config trouble");
+    assert!(markdown.starts_with("# Config trouble"), "{}", markdown);
+    assert!(markdown.contains("Why does [redacted] fail with @config.toml?"), "{}", markdown);
+    assert!(markdown.contains("*Attached, not shared: `@config.toml`*"), "{}", markdown);
+    assert!(markdown.contains("That key has expired."), "{}", markdown);
+    for left_out in ["sk-ant-secret-1234", "hunter22", "private aside"] {
+        assert!(!markdown.contains(left_out), "{}", markdown);
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/"))
+        .and(body_string_contains("That key has expired."))
+        .respond_with(ResponseTemplate::new(201).set_body_string("https://paste.example/abc\n"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let config = PasteConfig { service: server.uri(), ..PasteConfig::default() };
+    let url = paste::upload(&config, &title, markdown).await.unwrap();
+    assert_eq!(url, "https://paste.example/abc");
+}
+
+#[tokio::test]
+async fn a_paste_service_that_answers_without_a_link_is_an_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<html>welcome</html>"))
+        .mount(&server)
+        .await;
+    let config = PasteConfig { service: server.uri(), ..PasteConfig::default() };
+    let error = paste::upload(&config, "t", "text".to_string()).await.unwrap_err().to_string();
+    assert!(error.contains("didn't answer with a link"), "{}", error);
+}
@@ -0,0 +1,1179 @@
+//! Drive the chat with scripted keys against the mock provider and check
+//! what ends up on screen
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{backend::TestBackend, style::Color, Terminal};
+use std::time::Duration;
+
+use mega_cli::app::App;
+use mega_cli::chat::ChatInterface;
+use mega_cli::config::{Config, MacroConfig, WebhookConfig};
+#[cfg(unix)]
+use mega_cli::control::{self, Control, Request, Response};
+use mega_cli::export::{self, ExportFormat};
+use mega_cli::pacing::Pacer;
+use mega_cli::providers::AIProvider;
+use mega_cli::render::{self, RenderArgs};
+use mega_cli::share::{Guest, Host};
+use mega_cli::store::Database;
+
+const WIDTH: u16 = 100;
+const HEIGHT: u16 = 30;
+
+fn key(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+fn terminal() -> Terminal<TestBackend> {
+    Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap()
+}
+
+/// A chat with the mock provider, answering at once, over an empty
+/// in-memory database
+fn mock_chat() -> ChatInterface {
+    std::env::set_var("MEGA_CLI_MOCK_DELAY_MS", "0");
+    ChatInterface::new(AIProvider::Mock, Some(Database::open_in_memory().unwrap()), &Config::default())
+}
+
+fn type_text(chat: &mut ChatInterface, text: &str) {
+    for c in text.chars() {
+        chat.handle_key(key(KeyCode::Char(c))).unwrap();
+    }
+}
+
+/// Type `text` and press Enter
+fn send(chat: &mut ChatInterface, text: &str) {
+    type_text(chat, text);
+    chat.handle_key(key(KeyCode::Enter)).unwrap();
+}
+
+/// Keep updating until the reply is in
+async fn wait_for_reply(chat: &mut ChatInterface) {
+    for _ in 0..500 {
+        chat.update().unwrap();
+        if !chat.is_waiting() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("no reply from the mock provider");
+}
+
+fn draw(terminal: &mut Terminal<TestBackend>, chat: &mut ChatInterface) -> String {
+    terminal.draw(|f| chat.render(f, f.area()).unwrap()).unwrap();
+    screen(terminal)
+}
+
+/// The rendered buffer as text, one line per row
+fn screen(terminal: &Terminal<TestBackend>) -> String {
+    let buffer = terminal.backend().buffer();
+    let mut text = String::new();
+    for y in 0..buffer.area.height {
+        for x in 0..buffer.area.width {
+            text.push_str(buffer[(x, y)].symbol());
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[test]
+fn welcome_names_the_provider() {
+    let mut chat = mock_chat();
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("Welcome to MEGA-CLI!"), "{}", screen);
+    assert!(screen.contains("Connected to: Mock"), "{}", screen);
+    assert!(screen.contains("MEGA-CLI // Mock"), "{}", screen);
+}
+
+#[test]
+fn typing_shows_in_the_input_box() {
+    let mut chat = mock_chat();
+    type_text(&mut chat, "half a thought");
+    chat.handle_key(key(KeyCode::Backspace)).unwrap();
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("> half a though_"), "{}", screen);
+}
+
+#[tokio::test]
+async fn a_sent_message_gets_a_reply() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "ping");
+    assert!(chat.is_waiting());
+    let waiting = draw(&mut terminal, &mut chat);
+    assert!(waiting.contains("Waiting for response"), "{}", waiting);
+    assert!(waiting.contains("You: ping"), "{}", waiting);
+
+    // The reply scrolls to the bottom
+    wait_for_reply(&mut chat).await;
+    let answered = draw(&mut terminal, &mut chat);
+    assert!(answered.contains("Mock: You said: ping"), "{}", answered);
+    assert!(answered.contains("> _"), "{}", answered);
+    chat.handle_key(key(KeyCode::Up)).unwrap();
+    let scrolled = draw(&mut terminal, &mut chat);
+    assert!(scrolled.contains("You: ping"), "{}", scrolled);
+}
+
+/// Keep updating and drawing until `text` is on screen
+async fn wait_for_screen(terminal: &mut Terminal<TestBackend>, chat: &mut ChatInterface, text: &str) -> String {
+    for _ in 0..200 {
+        chat.update().unwrap();
+        let screen = draw(terminal, chat);
+        if screen.contains(text) {
+            return screen;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{:?} never showed:\n{}", text, draw(terminal, chat));
+}
+
+#[tokio::test]
+async fn each_providers_last_conversation_is_read_in_the_background_once_shown() {
+    let dir = std::env::temp_dir().join(format!("mega-cli-history-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = Database::open(&dir.join("history.db")).unwrap();
+    for (provider, text) in [("mock", "asked the mock"), ("claude", "asked claude")] {
+        let id = db.create_conversation(provider, "").unwrap();
+        db.save_message(id, "user", text, None).unwrap();
+    }
+
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(db), &Config::default());
+    let mut terminal = terminal();
+    wait_for_screen(&mut terminal, &mut chat, "asked the mock").await;
+    chat.handle_key(key(KeyCode::F(2))).unwrap();
+    let screen = wait_for_screen(&mut terminal, &mut chat, "asked claude").await;
+    assert!(screen.contains("Switched to Claude"), "{}", screen);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn a_long_conversation_reads_earlier_messages_as_they_are_scrolled_to() {
+    let db = Database::open_in_memory().unwrap();
+    let id = db.create_conversation("mock", "long one").unwrap();
+    for n in 1..=450 {
+        db.save_message(id, if n % 2 == 1 { "user" } else { "assistant" }, &format!("message {:03}", n), None).unwrap();
+    }
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(db), &Config::default());
+    let mut terminal = terminal();
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("message 251"), "{}", screen);
+    assert!(screen.contains("↑ earlier ones"), "{}", screen);
+
+    chat.handle_key(key(KeyCode::Up)).unwrap();
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("message 250"), "{}", screen);
+    assert!(!screen.contains("message 001"), "{}", screen);
+
+    for _ in 0..25 {
+        chat.handle_key(key(KeyCode::PageUp)).unwrap();
+    }
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("message 001"), "{}", screen);
+    assert!(!screen.contains("↑ earlier ones"), "{}", screen);
+}
+
+#[tokio::test]
+async fn a_long_conversation_sends_what_fits_the_window_beyond_the_page_shown() {
+    let db = Database::open_in_memory().unwrap();
+    let id = db.create_conversation("mock", "long one").unwrap();
+    // 25 tokens each, 11,250 in all against the mock's 8,192
+    for n in 1..=450 {
+        let role = if n % 2 == 1 { "user" } else { "assistant" };
+        db.save_message(id, role, &format!("message {:03} {}", n, "x".repeat(88)), None).unwrap();
+    }
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(db), &Config::default());
+    let mut terminal = terminal();
+    draw(&mut terminal, &mut chat);
+
+    chat.handle_key(KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL)).unwrap();
+    let gauge = draw(&mut terminal, &mut chat);
+    let line = gauge.lines().find(|line| line.contains("Conversation")).unwrap_or_default();
+    // The newest 326, from a question on: more than the 200 shown
+    assert!(line.contains(" 8,150 tokens"), "{}", gauge);
+}
+
+#[tokio::test]
+async fn a_long_conversation_replays_from_its_first_message() {
+    let db = Database::open_in_memory().unwrap();
+    let id = db.create_conversation("mock", "long one").unwrap();
+    for n in 1..=450 {
+        db.save_message(id, if n % 2 == 1 { "user" } else { "assistant" }, &format!("message {:03}", n), None).unwrap();
+    }
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(db), &Config::default());
+    let mut terminal = terminal();
+    draw(&mut terminal, &mut chat);
+
+    send(&mut chat, "/replay");
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("Replay · long one · 1/450"), "{}", screen);
+    assert!(chat.close_popup());
+
+    send(&mut chat, &format!("/replay {}", id));
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("Replay · long one · 1/450"), "{}", screen);
+}
+
+#[tokio::test]
+async fn the_conversation_is_titled_after_the_first_message() {
+    let mut chat = mock_chat();
+    send(&mut chat, "hello there");
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("MEGA-CLI // Mock // hello there"), "{}", screen);
+    assert!(screen.contains("I'm the mock provider"), "{}", screen);
+}
+
+#[tokio::test]
+async fn settings_reach_the_header_and_the_provider() {
+    let mut chat = mock_chat();
+    send(&mut chat, "/set temperature 0.5");
+    send(&mut chat, "/set system Be brief");
+    send(&mut chat, "anything");
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("temperature 0.5"), "{}", screen);
+    assert!(screen.contains("(System prompt: Be brief)"), "{}", screen);
+}
+
+/// Tests run in the crate's directory, so Cargo.toml is there to mention
+#[tokio::test]
+async fn mentioned_files_are_completed_and_attached() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    type_text(&mut chat, "what's in @Cargo.t");
+    let typing = draw(&mut terminal, &mut chat);
+    assert!(typing.contains("Tab ⇥ Cargo.toml"), "{}", typing);
+
+    chat.handle_key(key(KeyCode::Tab)).unwrap();
+    let completed = draw(&mut terminal, &mut chat);
+    assert!(completed.contains("> what's in @Cargo.toml_"), "{}", completed);
+
+    chat.handle_key(key(KeyCode::Enter)).unwrap();
+    wait_for_reply(&mut chat).await;
+    chat.handle_key(key(KeyCode::Up)).unwrap();
+    let sent = draw(&mut terminal, &mut chat);
+    assert!(sent.contains("```toml"), "{}", sent);
+    assert!(sent.contains("[package]"), "{}", sent);
+}
+
+#[tokio::test]
+async fn the_context_gauge_splits_the_next_request_into_its_parts() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "/set system Answer very briefly.");
+    send(&mut chat, "see @Cargo.toml");
+    wait_for_reply(&mut chat).await;
+    type_text(&mut chat, &"x".repeat(400));
+    chat.handle_key(KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL)).unwrap();
+
+    let gauge = draw(&mut terminal, &mut chat);
+    let line = |label: &str| gauge.lines().find(|line| line.contains(label)).unwrap_or_default().to_string();
+    assert!(gauge.contains("Context · mock-1 · 8,192 token window"), "{}", gauge);
+    assert!(line("System prompt").contains(" 5 tokens"), "{}", gauge);
+    assert!(line("Being typed").contains(" 100 tokens"), "{}", gauge);
+    assert!(!line("Attached files").contains(" 0 tokens"), "{}", gauge);
+    assert!(line("Conversation").contains("tokens"), "{}", gauge);
+
+    assert!(chat.close_popup());
+    assert!(!draw(&mut terminal, &mut chat).contains("token window"));
+}
+
+#[test]
+fn a_missing_mentioned_file_is_not_sent() {
+    let mut chat = mock_chat();
+    send(&mut chat, "look at @src/nope.rs");
+    assert!(!chat.is_waiting());
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("Not sent: @src/nope.rs: no such file"), "{}", screen);
+    assert!(screen.contains("> look at @src/nope.rs_"), "{}", screen);
+}
+
+#[test]
+fn what_the_model_cannot_take_is_turned_away_before_sending() {
+    let mut chat = mock_chat();
+    let image = std::env::temp_dir().join(format!("mega-cli-test-{}.png", std::process::id()));
+    std::fs::write(&image, b"\x89PNG").unwrap();
+    let mention = format!("what's this? @{}", image.display());
+    send(&mut chat, &mention);
+    std::fs::remove_file(&image).unwrap();
+    assert!(!chat.is_waiting());
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("Not sent: mock-1 can't read images"), "{}", screen);
+    assert!(screen.contains("> what's this? @"), "{}", screen);
+
+    let mut chat = mock_chat();
+    send(&mut chat, "/set model gpt-5");
+    send(&mut chat, "/set temperature 0.5");
+    send(&mut chat, "/set");
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("gpt-5 only answers at its own temperature"), "{}", screen);
+    assert!(screen.contains("the model takes no temperature"), "{}", screen);
+}
+
+#[tokio::test]
+async fn templates_ask_for_their_placeholders() {
+    std::env::set_var("MEGA_CLI_MOCK_DELAY_MS", "0");
+    let mut config = Config::default();
+    config
+        .templates
+        .insert("explain".to_string(), "Explain {topic} to a {level}".to_string());
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(Database::open_in_memory().unwrap()), &config);
+    let mut terminal = terminal();
+
+    send(&mut chat, "/t explain");
+    let asking = draw(&mut terminal, &mut chat);
+    assert!(asking.contains("Template explain · topic (1/2)"), "{}", asking);
+    send(&mut chat, "/dev/null");
+    let next = draw(&mut terminal, &mut chat);
+    assert!(next.contains("Template explain · level (2/2)"), "{}", next);
+    send(&mut chat, "beginner");
+
+    assert!(chat.is_waiting());
+    wait_for_reply(&mut chat).await;
+    let sent = draw(&mut terminal, &mut chat);
+    assert!(sent.contains("You said: Explain /dev/null to a beginner"), "{}", sent);
+}
+
+#[tokio::test]
+async fn messages_and_replies_reach_the_event_log_and_webhooks() {
+    let server = wiremock::MockServer::start().await;
+    wiremock::Mock::given(wiremock::matchers::method("POST"))
+        .and(wiremock::matchers::body_string_contains("Mock replied"))
+        .respond_with(wiremock::ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let log = std::env::temp_dir().join(format!("mega-cli-events-test-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+
+    std::env::set_var("MEGA_CLI_MOCK_DELAY_MS", "0");
+    let mut config = Config::default();
+    config.events.log = Some(log.display().to_string());
+    config.events.webhooks.push(WebhookConfig {
+        url: server.uri(),
+        on: vec!["response".to_string()],
+        format: "slack".to_string(),
+    });
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(Database::open_in_memory().unwrap()), &config);
+    send(&mut chat, "ping");
+    wait_for_reply(&mut chat).await;
+
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2, "{:?}", lines);
+    assert_eq!(lines[0]["event"], "user_message");
+    assert_eq!(lines[0]["content"], "ping");
+    assert_eq!(lines[1]["event"], "response");
+    assert_eq!(lines[1]["conversation"], "ping");
+    assert!(lines[1]["conversation_id"].is_i64(), "{:?}", lines[1]);
+
+    // Sent in the background
+    for _ in 0..100 {
+        if !server.received_requests().await.unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    server.verify().await;
+    let _ = std::fs::remove_file(&log);
+}
+
+#[tokio::test]
+async fn replay_types_the_conversation_out_again() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "ping");
+    wait_for_reply(&mut chat).await;
+
+    send(&mut chat, "/replay");
+    assert!(chat.is_replaying());
+    let started = draw(&mut terminal, &mut chat);
+    assert!(started.contains("Replay · ping · 1/2"), "{}", started);
+    assert!(!started.contains("You said"), "{}", started);
+
+    // Each skip finishes a message
+    chat.handle_key(key(KeyCode::Right)).unwrap();
+    chat.handle_key(key(KeyCode::Right)).unwrap();
+    assert!(!chat.is_replaying());
+    let done = draw(&mut terminal, &mut chat);
+    assert!(done.contains("2/2 · done · Esc closes"), "{}", done);
+    assert!(done.contains("You: ping"), "{}", done);
+    assert!(done.contains("Mock: You said: ping"), "{}", done);
+
+    assert!(chat.close_popup());
+    let closed = draw(&mut terminal, &mut chat);
+    assert!(!closed.contains("Replay"), "{}", closed);
+}
+
+#[tokio::test]
+async fn replies_keep_their_line_breaks_and_wrap_to_the_panel() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "list");
+    wait_for_reply(&mut chat).await;
+
+    // Each item of the list starts a row of its own
+    let wide = draw(&mut terminal, &mut chat);
+    let rows: Vec<&str> = wide.lines().map(|row| row.trim_start_matches('│')).collect();
+    assert!(rows.iter().any(|row| row.contains("Mock: A few things to try:  ")), "{}", wide);
+    assert!(rows.iter().any(|row| row.starts_with("- **F2** switches provider")), "{}", wide);
+    assert!(rows.iter().any(|row| row.starts_with("- **Ctrl+F** searches every conversation")), "{}", wide);
+
+    // Narrower, the last item wraps between words
+    terminal.backend_mut().resize(40, HEIGHT);
+    let narrow = draw(&mut terminal, &mut chat);
+    let rows: Vec<&str> = narrow.lines().map(|row| row.trim_start_matches('│')).collect();
+    assert!(rows.iter().any(|row| row.starts_with("- `/set temperature 0.3` changes a  ")), "{}", narrow);
+    assert!(rows.iter().any(|row| row.starts_with("setting for this conversation  ")), "{}", narrow);
+}
+
+#[tokio::test]
+async fn accessible_mode_says_what_happened_in_high_contrast() {
+    std::env::set_var("MEGA_CLI_MOCK_DELAY_MS", "0");
+    let mut config = Config::default();
+    config.display.accessible = true;
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(Database::open_in_memory().unwrap()), &config);
+    let mut terminal = terminal();
+
+    send(&mut chat, "ping");
+    let sent = draw(&mut terminal, &mut chat);
+    assert!(sent.contains("Message sent to Mock, waiting for the response"), "{}", sent);
+    wait_for_reply(&mut chat).await;
+    let answered = draw(&mut terminal, &mut chat);
+    assert!(answered.contains("Response received from Mock, 1 line"), "{}", answered);
+
+    // Bright text on black, or black on white. Checked as drawn, since
+    // the test terminal skips the cell after a wide emoji.
+    let mut drawn = None;
+    terminal
+        .draw(|f| {
+            chat.render(f, f.area()).unwrap();
+            drawn = Some(f.buffer_mut().clone());
+        })
+        .unwrap();
+    for cell in drawn.unwrap().content() {
+        match cell.bg {
+            Color::Black => assert!(!matches!(cell.fg, Color::Rgb(..) | Color::DarkGray | Color::Blue), "{:?}", cell),
+            Color::White => assert_eq!(cell.fg, Color::Black),
+            other => panic!("background {:?}", other),
+        }
+    }
+
+    // A replay shows messages whole, one at a time
+    send(&mut chat, "/replay");
+    let started = draw(&mut terminal, &mut chat);
+    assert!(started.contains("You: ping"), "{}", started);
+    assert!(started.contains("Replaying ping, 2 messages"), "{}", started);
+    chat.handle_key(key(KeyCode::Right)).unwrap();
+    chat.handle_key(key(KeyCode::Right)).unwrap();
+    chat.update().unwrap();
+    let done = draw(&mut terminal, &mut chat);
+    assert!(done.contains("Replay finished"), "{}", done);
+}
+
+/// The screen a cast's events draw, reading only the cursor moves and
+/// skipping the colors
+fn play_cast(events: &[(f64, String, String)], width: usize, height: usize) -> String {
+    let mut grid = vec![vec![' '; width]; height];
+    let (mut row, mut column) = (0, 0);
+    for (_, _, text) in events {
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\x1b' {
+                if row < height && column < width {
+                    grid[row][column] = c;
+                }
+                column += 1;
+                continue;
+            }
+            let mut code = String::new();
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    if c == 'H' {
+                        let (r, c) = code.trim_start_matches('[').split_once(';').unwrap();
+                        (row, column) = (r.parse::<usize>().unwrap() - 1, c.parse::<usize>().unwrap() - 1);
+                    }
+                    break;
+                }
+                code.push(c);
+            }
+        }
+    }
+    grid.into_iter().map(|line| line.into_iter().collect::<String>() + "\n").collect()
+}
+
+#[tokio::test]
+async fn a_saved_conversation_renders_to_a_cast_and_a_gif() {
+    let dir = std::env::temp_dir().join(format!("mega-cli-render-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db_path = dir.join("history.db");
+    let db = Database::open(&db_path).unwrap();
+    let id = db.create_conversation("claude", "Render me").unwrap();
+    db.save_message(id, "user", "ping", None).unwrap();
+    db.save_message(id, "assistant", "pong", None).unwrap();
+    drop(db);
+
+    let args = |output: &std::path::Path| {
+        use clap::Parser;
+        #[derive(Parser)]
+        struct Flags {
+            #[command(flatten)]
+            render: RenderArgs,
+        }
+        let id = id.to_string();
+        let output = output.to_str().unwrap();
+        #[allow(unused_mut)]
+        let mut flags = vec!["test", &id, "-o", output, "--size", "60x16", "--speed", "8", "--hold", "0.2"];
+        #[cfg(feature = "video")]
+        flags.extend(["--video", "builtin"]);
+        Flags::parse_from(flags).render
+    };
+
+    let cast = dir.join("demo.cast");
+    render::run(args(&cast), &db_path, &Config::default()).await.unwrap();
+    let written = std::fs::read_to_string(&cast).unwrap();
+    let mut lines = written.lines();
+    let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert_eq!((header["version"].as_u64(), header["width"].as_u64()), (Some(2), Some(60)));
+    assert_eq!(header["title"], "Render me");
+    let events: Vec<(f64, String, String)> = lines.map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert!(events.len() > 2, "{:?}", events);
+    assert!(events.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    let last = play_cast(&events, 60, 16);
+    assert!(last.contains("You: ping"), "{}", last);
+    assert!(last.contains(": pong"), "{}", last);
+    assert!(last.contains("2/2 · done"), "{}", last);
+
+    #[cfg(feature = "gif")]
+    {
+        let gif = dir.join("demo.gif");
+        render::run(args(&gif), &db_path, &Config::default()).await.unwrap();
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(std::fs::File::open(&gif).unwrap()).unwrap();
+        assert!(decoder.width() >= 60 * 6 && decoder.height() >= 16 * 12);
+        let mut frames = 0;
+        while decoder.read_next_frame().unwrap().is_some() {
+            frames += 1;
+        }
+        assert!(frames > 2, "{} frames", frames);
+    }
+
+    assert!(render::run(args(&dir.join("demo.txt")), &db_path, &Config::default()).await.is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(feature = "pdf")]
+#[test]
+fn saved_conversations_export_to_a_pdf_a_page_or_more_each() {
+    let dir = std::env::temp_dir().join(format!("mega-cli-pdf-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = Database::open(&dir.join("history.db")).unwrap();
+    let first = db.create_conversation("claude", "Reverse a string").unwrap();
+    db.save_message(first, "user", "How do I reverse a string? 🙂", None).unwrap();
+    let reply = format!("Like this:\n\n```rust\ns.chars().rev().collect()\n```\n\n{}", "More detail. ".repeat(400));
+    db.save_message(first, "assistant", &reply, None).unwrap();
+    let second = db.create_conversation("grok", "Short").unwrap();
+    db.save_message(second, "user", "ping", None).unwrap();
+
+    let path = dir.join("chats.pdf");
+    let messages = export::to_file(&db, &[first, second], ExportFormat::Pdf, &path).unwrap();
+    assert_eq!(messages, 3);
+    let pdf = std::fs::read(&path).unwrap();
+    assert!(pdf.starts_with(b"%PDF-"));
+    let text = String::from_utf8_lossy(&pdf);
+    // The long reply runs onto a second page, and the next conversation starts a third
+    let pages = text.matches("/Type/Page").count() - text.matches("/Type/Pages").count();
+    assert!(pages >= 3, "{} pages", pages);
+    assert!(text.contains("/Title(Reverse a string)"), "the first conversation should be bookmarked");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn rated_replies_show_and_export_with_what_they_answered() {
+    std::env::set_var("MEGA_CLI_MOCK_DELAY_MS", "0");
+    let dir = std::env::temp_dir().join(format!("mega-cli-rated-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db_path = dir.join("history.db");
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(Database::open(&db_path).unwrap()), &Config::default());
+    send(&mut chat, "first question");
+    wait_for_reply(&mut chat).await;
+    send(&mut chat, "/rate down");
+    send(&mut chat, "second question");
+    wait_for_reply(&mut chat).await;
+    send(&mut chat, "/rate up");
+    send(&mut chat, "/note concise and right");
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("👍") && screen.contains("Mock: You said: second question"), "{}", screen);
+    assert!(screen.contains("✎ concise and right"), "{}", screen);
+
+    let path = dir.join("rated.jsonl");
+    let db = Database::open(&db_path).unwrap();
+    assert_eq!(export::to_file(&db, &[], ExportFormat::Rated, &path).unwrap(), 2);
+    let pairs: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(pairs[0]["prompt"], "first question");
+    assert_eq!(pairs[0]["rating"], "down");
+    assert!(pairs[0].get("note").is_none(), "{}", pairs[0]);
+    assert_eq!(pairs[1]["prompt"], "second question");
+    assert_eq!(pairs[1]["response"], "You said: second question");
+    assert_eq!(pairs[1]["rating"], "up");
+    assert_eq!(pairs[1]["note"], "concise and right");
+    assert_eq!(pairs[1]["provider"], "mock");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn ab_evals_answer_blinded_and_keep_the_vote() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "/ab mock mock/mock-2");
+    send(&mut chat, "which is better");
+    wait_for_reply(&mut chat).await;
+    let blinded = draw(&mut terminal, &mut chat);
+    assert!(blinded.contains("Model A") && blinded.contains("Model B"), "{}", blinded);
+    assert_eq!(blinded.matches("You said: which is better").count(), 2, "{}", blinded);
+    assert!(!blinded.contains("mock-2"), "{}", blinded);
+
+    chat.handle_key(key(KeyCode::Char('a'))).unwrap();
+    send(&mut chat, "/ab");
+    let revealed = draw(&mut terminal, &mut chat);
+    assert!(revealed.contains("mock  ") && revealed.contains("50%  1 won, 0 tied of 2"), "{}", revealed);
+
+    // Back to the conversation
+    send(&mut chat, "/ab off");
+    send(&mut chat, "just me");
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("Mock: You said: just me"), "{}", screen);
+}
+
+#[tokio::test]
+async fn tables_in_replies_are_drawn_aligned_and_scroll_sideways() {
+    let mut chat = mock_chat();
+    let mut terminal = Terminal::new(TestBackend::new(40, 24)).unwrap();
+    send(&mut chat, "show me a table");
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("┌──────────┬"), "{}", screen);
+    assert!(screen.contains("│ Provider │ Default model     │"), "{}", screen);
+    assert!(screen.contains("│ Gemini   │ gemini-2.5-pro    │"), "{}", screen);
+    assert!(!screen.contains("|---"), "{}", screen);
+    assert!(screen.contains("columns 1–38 of 58, ←/→ scroll"), "{}", screen);
+
+    // Right-aligned numbers and centred text come into view
+    for _ in 0..3 {
+        chat.handle_key(key(KeyCode::Right)).unwrap();
+    }
+    let scrolled = draw(&mut terminal, &mut chat);
+    assert!(scrolled.contains("1,048,576 │  yes   │"), "{}", scrolled);
+    assert!(scrolled.contains("◀ columns 21–58 of 58"), "{}", scrolled);
+    for _ in 0..3 {
+        chat.handle_key(key(KeyCode::Left)).unwrap();
+    }
+    assert_eq!(draw(&mut terminal, &mut chat), screen);
+}
+
+#[tokio::test]
+async fn diffs_in_replies_are_colored_and_offered_to_git_apply() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "show me a patch");
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal, &mut chat);
+    let buffer = terminal.backend().buffer().clone();
+    let color_of = |text: &str| {
+        let (y, line) = screen.lines().enumerate().find(|(_, line)| line.contains(text)).unwrap();
+        let x = line[..line.find(text).unwrap()].chars().count() as u16;
+        buffer[(x, y as u16)].fg
+    };
+    assert_eq!(color_of("+        println!(\"Hello from {provider}!\");"), Color::Green);
+    assert_eq!(color_of("-        println!(\"Hello from {}\", provider);"), Color::Red);
+    assert_eq!(color_of("@@ -1,6 +1,6 @@"), Color::Cyan);
+
+    // It doesn't fit this tree's src/main.rs, so it can't be applied
+    send(&mut chat, "/apply");
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("Apply patch · 1 file, +1 −1"), "{}", screen);
+    assert!(screen.contains("Won't apply"), "{}", screen);
+    chat.handle_key(key(KeyCode::Char('s'))).unwrap();
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("Before"), "{}", screen);
+    assert!(screen.contains("After"), "{}", screen);
+    chat.handle_key(key(KeyCode::Char('y'))).unwrap();
+    assert!(draw(&mut terminal, &mut chat).contains("Won't apply"));
+    chat.handle_key(key(KeyCode::Char('n'))).unwrap();
+    assert!(!draw(&mut terminal, &mut chat).contains("Apply patch"));
+
+    send(&mut chat, "hello");
+    wait_for_reply(&mut chat).await;
+    send(&mut chat, "/apply");
+    assert!(draw(&mut terminal, &mut chat).contains("No diff in the last reply"));
+}
+
+#[tokio::test]
+async fn variations_are_asked_for_at_several_temperatures_and_one_is_kept() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "/vary");
+    assert!(draw(&mut terminal, &mut chat).contains("No reply to vary yet"));
+
+    send(&mut chat, "how warm is it");
+    wait_for_reply(&mut chat).await;
+    send(&mut chat, "/vary 3");
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("Variations · how warm is it"), "{}", screen);
+    for temperature in ["0.20", "0.60", "1.00"] {
+        assert!(screen.contains(&format!("🌡 {}", temperature)), "{}", screen);
+    }
+    assert!(screen.contains("(Temperature: 0.2)"), "{}", screen);
+
+    chat.handle_key(key(KeyCode::Down)).unwrap();
+    chat.handle_key(key(KeyCode::Down)).unwrap();
+    assert!(draw(&mut terminal, &mut chat).contains("(Temperature: 1)"));
+    chat.handle_key(key(KeyCode::Enter)).unwrap();
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(!screen.contains("Variations ·"), "{}", screen);
+    // Added after the first answer, which stays
+    let first = screen.find("You said: how warm is it").unwrap();
+    assert!(screen[first..].contains("(Temperature: 1)"), "{}", screen);
+
+    send(&mut chat, "/vary 9");
+    assert!(draw(&mut terminal, &mut chat).contains("Usage: /vary [n]"));
+}
+
+#[tokio::test]
+async fn secrets_in_a_message_are_warned_about_or_masked() {
+    let secret = "sk-proj-Abc123Def456Ghi789Jkl012";
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, &format!("why is {} refused? mail jo@example.com", secret));
+    assert!(!chat.is_waiting());
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(
+        screen.contains("Not sent: it has an OpenAI key (sk-pr…), an email address (jo@ex…) in it"),
+        "{}",
+        screen
+    );
+    // Back in the input box, and sent as it is the second time
+    chat.handle_key(key(KeyCode::Enter)).unwrap();
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains(&format!("You said: why is {} refused?", secret)), "{}", screen);
+
+    let mut config = Config::default();
+    config.redact.action = "mask".to_string();
+    config.redact.patterns.insert("ticket id".to_string(), r"ACME-\d{6}".to_string());
+    let mut chat = ChatInterface::new(AIProvider::Mock, Some(Database::open_in_memory().unwrap()), &config);
+    send(&mut chat, &format!("ACME-123456 leaks {}", secret));
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("You said: [redacted] leaks [redacted]"), "{}", screen);
+    assert!(!screen.contains("sk-proj"), "{}", screen);
+}
+
+#[tokio::test]
+async fn a_locked_conversation_takes_no_messages_and_stays_out_of_the_trash() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "keep this");
+    wait_for_reply(&mut chat).await;
+    send(&mut chat, "/lock");
+    assert!(draw(&mut terminal, &mut chat).contains("keep this 🔒"));
+
+    send(&mut chat, "one more");
+    assert!(!chat.is_waiting());
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("This conversation is locked: /unlock it"), "{}", screen);
+    assert!(screen.contains("> one more_"), "{}", screen);
+    assert!(!screen.contains("You said: one more"), "{}", screen);
+
+    chat.handle_key(key(KeyCode::F(7))).unwrap();
+    chat.handle_key(key(KeyCode::Char('d'))).unwrap();
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("keep this  2"), "{}", screen);
+    chat.handle_key(key(KeyCode::Char('l'))).unwrap();
+    chat.handle_key(key(KeyCode::F(7))).unwrap();
+
+    // Unlocked from the session manager, so it sends
+    chat.handle_key(key(KeyCode::Enter)).unwrap();
+    wait_for_reply(&mut chat).await;
+    let screen = draw(&mut terminal, &mut chat);
+    assert!(screen.contains("You said: one more"), "{}", screen);
+    assert!(!screen.contains("🔒"), "{}", screen);
+}
+
+#[tokio::test]
+async fn a_kept_reply_is_picked_into_the_input() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "ping");
+    wait_for_reply(&mut chat).await;
+    send(&mut chat, "/snip pong");
+
+    chat.handle_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL)).unwrap();
+    type_text(&mut chat, "pn");
+    let picking = draw(&mut terminal, &mut chat);
+    assert!(picking.contains("1 snippet"), "{}", picking);
+    assert!(picking.contains("You said: ping"), "{}", picking);
+
+    chat.handle_key(key(KeyCode::Enter)).unwrap();
+    let picked = draw(&mut terminal, &mut chat);
+    assert!(picked.contains("> You said: ping_"), "{}", picked);
+}
+
+#[tokio::test]
+async fn guests_of_a_shared_session_see_and_send_messages() {
+    let mut host_chat = mock_chat();
+    let host = Host::start("127.0.0.1:0").await.unwrap();
+    let (addr, code) = (host.addr().to_string(), host.code().to_string());
+    host_chat.start_hosting(host);
+    send(&mut host_chat, "ping");
+    wait_for_reply(&mut host_chat).await;
+
+    let wrong = Guest::join(&addr, "nope", None).await.err().unwrap();
+    assert!(wrong.to_string().contains("Wrong code"), "{}", wrong);
+
+    // The host answers a join from its update loop
+    let joining = tokio::spawn(async move { Guest::join(&addr, &code, Some("sam".to_string())).await });
+    while !joining.is_finished() {
+        host_chat.update().unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let (guest, conversation) = joining.await.unwrap().unwrap();
+    let mut guest_chat = mock_chat();
+    guest_chat.join(guest, conversation);
+    let mut terminal = terminal();
+    let joined = draw(&mut terminal, &mut guest_chat);
+    assert!(joined.contains("Mock: You said: ping"), "{}", joined);
+
+    send(&mut guest_chat, "pong");
+    assert!(guest_chat.is_waiting());
+    for _ in 0..500 {
+        host_chat.update().unwrap();
+        guest_chat.update().unwrap();
+        if !guest_chat.is_waiting() && !host_chat.is_waiting() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    guest_chat.handle_key(key(KeyCode::Up)).unwrap();
+    let guest_screen = draw(&mut terminal, &mut guest_chat);
+    assert!(guest_screen.contains("You: pong"), "{}", guest_screen);
+    assert!(guest_screen.contains("Mock: You said: pong"), "{}", guest_screen);
+    host_chat.handle_key(key(KeyCode::Up)).unwrap();
+    let host_screen = draw(&mut terminal, &mut host_chat);
+    assert!(host_screen.contains("sam: pong"), "{}", host_screen);
+    assert!(host_screen.contains("Input · shared with sam"), "{}", host_screen);
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_close_a_shared_session() {
+    let mut host_chat = mock_chat();
+    let host = Host::start("127.0.0.1:0").await.unwrap();
+    let (addr, code) = (host.addr().to_string(), host.code().to_string());
+    host_chat.start_hosting(host);
+
+    for _ in 0..5 {
+        let wrong = Guest::join(&addr, "nope", None).await.err().unwrap();
+        assert!(wrong.to_string().contains("Wrong code"), "{}", wrong);
+    }
+    // Not even the right code gets in now
+    assert!(Guest::join(&addr, &code, None).await.is_err());
+
+    host_chat.update().unwrap();
+    let screen = draw(&mut terminal(), &mut host_chat);
+    assert!(screen.contains("5 wrong join codes: nobody else can join"), "{}", screen);
+}
+
+/// Send `request` to the chat's control socket, running the chat until
+/// it answers
+#[cfg(unix)]
+async fn control_request(chat: &mut ChatInterface, socket: &std::path::Path, request: Request) -> Response {
+    let socket = socket.to_path_buf();
+    let asking = tokio::spawn(async move { control::request(&socket, &request).await });
+    for _ in 0..500 {
+        chat.update().unwrap();
+        if asking.is_finished() {
+            return asking.await.unwrap().unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("no answer on the control socket");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn the_control_socket_drives_a_running_chat() {
+    let dir = std::env::temp_dir().join(format!("mega-cli-control-test-{}", std::process::id()));
+    let socket = dir.join("control.sock");
+    let mut chat = mock_chat();
+    chat.start_control(Control::start(socket.clone()).unwrap());
+    assert!(Control::start(socket.clone()).is_err(), "a second chat took the socket");
+
+    let text = "ping".to_string();
+    match control_request(&mut chat, &socket, Request::Send { text, wait: true }).await {
+        Response::Reply { content } => assert!(content.contains("You said: ping"), "{}", content),
+        other => panic!("{:?}", other),
+    }
+    chat.handle_key(key(KeyCode::Up)).unwrap();
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("You: ping"), "{}", screen);
+
+    match control_request(&mut chat, &socket, Request::Status).await {
+        Response::Status(status) => {
+            assert_eq!(status.messages, 2);
+            assert!(status.conversation_id.is_some());
+            assert!(!status.waiting);
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let path = dir.join("export.jsonl");
+    let export = Request::Export { path: path.clone(), format: "jsonl".to_string() };
+    match control_request(&mut chat, &socket, export).await {
+        Response::Exported { messages, .. } => assert_eq!(messages, 2),
+        other => panic!("{:?}", other),
+    }
+    assert!(std::fs::read_to_string(&path).unwrap().contains("\"content\":\"ping\""));
+
+    let unknown = Request::Provider { name: "hal".to_string() };
+    match control_request(&mut chat, &socket, unknown).await {
+        Response::Error { message } => assert!(message.contains("Unknown provider: hal"), "{}", message),
+        other => panic!("{:?}", other),
+    }
+
+    drop(chat);
+    assert!(!socket.exists());
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn unknown_commands_are_explained() {
+    let mut chat = mock_chat();
+    send(&mut chat, "/frobnicate");
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("Unknown command: /frobnicate"), "{}", screen);
+}
+
+#[tokio::test]
+async fn a_doubled_slash_sends_the_message_as_typed() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "//usr/bin/ld fails");
+    let waiting = draw(&mut terminal, &mut chat);
+    assert!(waiting.contains("You: /usr/bin/ld fails"), "{}", waiting);
+    wait_for_reply(&mut chat).await;
+    let answered = draw(&mut terminal, &mut chat);
+    assert!(answered.contains("Mock: You said: /usr/bin/ld fails"), "{}", answered);
+}
+
+#[tokio::test]
+async fn a_failed_reply_opens_the_error_overlay() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    send(&mut chat, "please simulate an error");
+    wait_for_reply(&mut chat).await;
+    let failed = draw(&mut terminal, &mut chat);
+    assert!(failed.contains("Mock didn't answer"), "{}", failed);
+    assert!(failed.contains("503 Service Unavailable"), "{}", failed);
+    assert!(failed.contains("Might fix it:"), "{}", failed);
+
+    // Keys go to the overlay while it's open
+    type_text(&mut chat, "x");
+    assert!(chat.close_popup());
+    let closed = draw(&mut terminal, &mut chat);
+    assert!(!closed.contains("Might fix it:"), "{}", closed);
+    assert!(closed.contains("> _"), "{}", closed);
+
+    send(&mut chat, "/errors");
+    let reopened = draw(&mut terminal, &mut chat);
+    assert!(reopened.contains("Mock didn't answer"), "{}", reopened);
+}
+
+#[test]
+fn errors_without_any_says_so() {
+    let mut chat = mock_chat();
+    send(&mut chat, "/errors");
+    let screen = draw(&mut terminal(), &mut chat);
+    assert!(screen.contains("No errors this session"), "{}", screen);
+}
+
+#[test]
+fn f1_toggles_help() {
+    let mut chat = mock_chat();
+    let mut terminal = terminal();
+    chat.handle_key(key(KeyCode::F(1))).unwrap();
+    let help = draw(&mut terminal, &mut chat);
+    assert!(help.contains("Keyboard Shortcuts"), "{}", help);
+    chat.handle_key(key(KeyCode::F(1))).unwrap();
+    let chat_screen = draw(&mut terminal, &mut chat);
+    assert!(!chat_screen.contains("Keyboard Shortcuts"), "{}", chat_screen);
+}
+
+/// The whole chat screen with the mock provider, on the built-in
+/// animation where there's video
+fn mock_app(config: Config) -> App {
+    std::env::set_var("MEGA_CLI_MOCK_DELAY_MS", "0");
+    #[cfg(feature = "video")]
+    let background = {
+        use clap::Parser;
+        #[derive(Parser)]
+        struct Flags {
+            #[command(flatten)]
+            background: mega_cli::background::setup::BackgroundArgs,
+        }
+        let flags = Flags::parse_from(["test", "--video", "builtin", "--screensaver", "0"]);
+        mega_cli::background::setup::BackgroundSetup::resolve(flags.background, &config)
+    };
+    App::new(
+        AIProvider::Mock,
+        Some(Database::open_in_memory().unwrap()),
+        #[cfg(feature = "video")]
+        background,
+        Pacer::new(30, 2),
+        config,
+        false,
+    )
+    .unwrap()
+}
+
+/// Keep updating the app until the event log has `count` lines
+async fn wait_for_events(app: &mut App, log: &std::path::Path, count: usize) -> Vec<(String, String)> {
+    let read = || -> Vec<(String, String)> {
+        std::fs::read_to_string(log)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|event| (event["event"].as_str().unwrap().to_string(), event["content"].as_str().unwrap().to_string()))
+            .collect()
+    };
+    for _ in 0..500 {
+        app.update().unwrap();
+        if read().len() >= count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    read()
+}
+
+#[tokio::test]
+async fn the_app_runs_a_conversation_and_quits_on_esc() {
+    let mut app = mock_app(Config::default());
+    let mut terminal = terminal();
+
+    // As Windows reports keys, let go of as well as pressed
+    for code in "hi".chars().map(KeyCode::Char).chain([KeyCode::Enter]) {
+        app.handle_event(Event::Key(key(code))).unwrap();
+        let released = KeyEvent::new_with_kind(code, KeyModifiers::NONE, KeyEventKind::Release);
+        app.handle_event(Event::Key(released)).unwrap();
+    }
+    app.draw(&mut terminal).unwrap();
+    let sent = screen(&terminal);
+    assert!(sent.contains("You: hi ") && !sent.contains("hih"), "{}", sent);
+
+    for _ in 0..500 {
+        app.update().unwrap();
+        app.draw(&mut terminal).unwrap();
+        if screen(&terminal).contains("Mock: Hello!") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let answered = screen(&terminal);
+    assert!(answered.contains("Mock: Hello!"), "{}", answered);
+
+    assert!(!app.should_quit());
+    app.handle_event(Event::Key(key(KeyCode::Esc))).unwrap();
+    assert!(app.should_quit());
+}
+
+#[tokio::test]
+async fn macros_record_play_back_and_wait_for_each_reply() {
+    let log = std::env::temp_dir().join(format!("mega-cli-macros-test-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let mut config = Config::default();
+    config.events.log = Some(log.display().to_string());
+    config.macros.insert(
+        "twice".to_string(),
+        MacroConfig {
+            key: Some("alt+m".to_string()),
+            keys: "one<enter>two<enter><esc>".to_string(),
+        },
+    );
+    let mut app = mock_app(config);
+    let mut terminal = terminal();
+    let ctrl = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL));
+
+    app.handle_event(ctrl('r')).unwrap();
+    for code in "again".chars().map(KeyCode::Char).chain([KeyCode::Enter]) {
+        app.handle_event(Event::Key(key(code))).unwrap();
+    }
+    app.handle_event(ctrl('r')).unwrap();
+    app.draw(&mut terminal).unwrap();
+    assert!(screen(&terminal).contains("Recorded 6 keys"), "{}", screen(&terminal));
+    wait_for_events(&mut app, &log, 2).await;
+
+    app.handle_event(ctrl('p')).unwrap();
+    wait_for_events(&mut app, &log, 4).await;
+
+    // The second message waits for the first's reply, and the macro's Esc
+    // doesn't quit
+    app.handle_event(Event::Key(KeyEvent::new(KeyCode::Char('m'), KeyModifiers::ALT))).unwrap();
+    let events = wait_for_events(&mut app, &log, 8).await;
+    let sent: Vec<&str> = events.iter().filter(|(event, _)| event == "user_message").map(|(_, content)| content.as_str()).collect();
+    assert_eq!(sent, ["again", "again", "one", "two"], "{:?}", events);
+    assert_eq!(events[5], ("response".to_string(), "You said: one".to_string()), "{:?}", events);
+    assert!(!app.should_quit());
+    let _ = std::fs::remove_file(&log);
+}
+
+#[test]
+fn the_leader_key_shows_what_follows_and_does_it() {
+    let mut app = mock_app(Config::default());
+    let mut terminal = terminal();
+    let press = |app: &mut App, code| app.handle_event(Event::Key(key(code))).unwrap();
+    let mut shown = |app: &mut App| {
+        app.draw(&mut terminal).unwrap();
+        screen(&terminal)
+    };
+
+    press(&mut app, KeyCode::Char(' '));
+    let root = shown(&mut app);
+    assert!(root.contains("n New conversation") && root.contains("c +Conversation"), "{}", root);
+    press(&mut app, KeyCode::Char('c'));
+    let group = shown(&mut app);
+    assert!(group.contains("space › Conversation") && group.contains("s Star the last reply"), "{}", group);
+    assert!(!group.contains("New conversation"), "{}", group);
+    press(&mut app, KeyCode::Backspace);
+    assert!(shown(&mut app).contains("n New conversation"));
+    press(&mut app, KeyCode::Char('x'));
+    let unbound = shown(&mut app);
+    assert!(unbound.contains("space x does nothing") && !unbound.contains("New conversation"), "{}", unbound);
+
+    // It stands for F1
+    press(&mut app, KeyCode::Char(' '));
+    press(&mut app, KeyCode::Char('?'));
+    assert!(shown(&mut app).contains("Keyboard Shortcuts"));
+    press(&mut app, KeyCode::Char(' '));
+    press(&mut app, KeyCode::Char('?'));
+
+    // Esc closes the popup before it quits, and a space after text is typed
+    press(&mut app, KeyCode::Char(' '));
+    press(&mut app, KeyCode::Esc);
+    assert!(!app.should_quit());
+    for c in "a b".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    let typed = shown(&mut app);
+    assert!(typed.contains("> a b_") && !typed.contains("New conversation"), "{}", typed);
+}